@@ -0,0 +1,180 @@
+//! Access log of the requests handled by miniserve, in the format chosen with `--log-format`.
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::web;
+use futures::future::Either;
+use serde::Serialize;
+use std::future::Future;
+use std::time::Instant;
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+use crate::auth::CurrentUser;
+use crate::log_file::LogFile;
+use crate::proxy;
+use crate::system_log::SystemLog;
+
+/// Format of the access log
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable lines, printed along with the other messages when running with --verbose
+    Text,
+
+    /// One JSON object per request, printed on the standard output
+    Json,
+}
+
+/// Destinations of the access log, besides the console
+pub struct AccessLog {
+    /// File given with `--log-file`
+    pub file: Option<LogFile>,
+
+    /// Syslog or journald, given with `--log-target`
+    pub system: Option<SystemLog>,
+}
+
+/// Access log entry of a request
+#[derive(Serialize)]
+struct AccessLogEntry {
+    /// Time at which the request was received, in RFC 3339 format
+    timestamp: String,
+
+    method: String,
+
+    /// Path of the request, without the query string which may contain share tokens
+    path: String,
+
+    status: u16,
+
+    /// Size of the response body, if known
+    bytes: Option<u64>,
+
+    /// Time spent handling the request, in milliseconds
+    duration_ms: f64,
+
+    client_ip: Option<String>,
+    user: Option<String>,
+}
+
+impl AccessLogEntry {
+    /// Format the entry as a human-readable line
+    fn to_text(&self) -> String {
+        format!(
+            "{} {} \"{} {}\" {} {} {:.3}ms {}",
+            self.timestamp,
+            self.client_ip.as_deref().unwrap_or("-"),
+            self.method,
+            self.path,
+            self.status,
+            self.bytes
+                .map_or_else(|| "-".to_string(), |bytes| bytes.to_string()),
+            self.duration_ms,
+            self.user.as_deref().unwrap_or("-")
+        )
+    }
+
+    /// Format the entry as a JSON object
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Write the entry to the system logger, with its details as structured fields
+    fn send_to(&self, system: &SystemLog, message: &str) {
+        let status = self.status.to_string();
+        let mut fields = vec![
+            ("HTTP_METHOD", self.method.as_str()),
+            ("HTTP_PATH", self.path.as_str()),
+            ("HTTP_STATUS", status.as_str()),
+        ];
+        if let Some(client_ip) = &self.client_ip {
+            fields.push(("CLIENT_IP", client_ip));
+        }
+        if let Some(user) = &self.user {
+            fields.push(("REMOTE_USER", user));
+        }
+        system.send(log::Level::Info, message, &fields);
+    }
+}
+
+/// Log every request once it was handled, as JSON on the standard output if the JSON access log
+/// is enabled, to the system logger instead if one is configured, and to the log file if one is
+/// configured
+pub fn log_request<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let log_format = conf.log_format;
+    let verbose = conf.verbose;
+    let access_log = req.app_data::<web::Data<AccessLog>>().unwrap().clone();
+    if log_format != LogFormat::Json && access_log.file.is_none() && access_log.system.is_none() {
+        return Either::Left(srv.call(req));
+    }
+
+    let (http_req, payload) = req.into_parts();
+    let client_ip = proxy::client_ip(&http_req).map(|ip| ip.to_string());
+    let req = ServiceRequest::from_parts(http_req, payload);
+
+    let start = Instant::now();
+    let mut entry = AccessLogEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        method: req.method().to_string(),
+        path: req.path().to_string(),
+        status: 0,
+        bytes: None,
+        duration_ms: 0.0,
+        client_ip,
+        user: None,
+    };
+    let fut = srv.call(req);
+
+    Either::Right(async move {
+        let result = fut.await;
+        match &result {
+            Ok(res) => {
+                entry.status = res.status().as_u16();
+                entry.bytes = match res.response().body().size() {
+                    BodySize::Sized(size) => Some(size),
+                    BodySize::Empty => Some(0),
+                    _ => None,
+                };
+                entry.user = res
+                    .request()
+                    .extensions()
+                    .get::<CurrentUser>()
+                    .map(|user| user.name.clone());
+            }
+            Err(e) => entry.status = e.as_response_error().status_code().as_u16(),
+        }
+        entry.duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        match &access_log.system {
+            // Like the text access log on the console, which requires --verbose
+            Some(system) if log_format == LogFormat::Json || verbose => {
+                let message = match log_format {
+                    LogFormat::Text => entry.to_text(),
+                    LogFormat::Json => entry.to_json(),
+                };
+                entry.send_to(system, &message);
+            }
+            Some(_) => (),
+            None if log_format == LogFormat::Json => println!("{}", entry.to_json()),
+            None => (),
+        }
+        if let Some(file) = &access_log.file {
+            let line = match log_format {
+                LogFormat::Text => entry.to_text(),
+                LogFormat::Json => entry.to_json(),
+            };
+            if let Err(e) = file.write_line(&line) {
+                log::error!("Failed to write to the access log file: {}", e);
+            }
+        }
+
+        result
+    })
+}
@@ -0,0 +1,550 @@
+//! TLS certificates obtained from an ACME server such as Let's Encrypt with `--acme-domain`,
+//! using the HTTP-01 challenge (RFC 8555).
+//!
+//! Certificates are written to the cache directory, from which they are served and reloaded
+//! like the ones given with `--tls-cert`.
+use actix_web::http::header;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::errors::ContextualError;
+use crate::tls;
+
+/// Certificates older than this are renewed, i.e. 30 days before Let's Encrypt certificates expire
+const RENEWAL_AGE: Duration = Duration::from_secs(60 * 24 * 60 * 60);
+
+/// How often the age of the certificate is checked
+const RENEWAL_CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Delay between two checks of a pending authorization or order
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Number of checks of a pending authorization or order before giving up
+const MAX_POLLS: usize = 30;
+
+/// Settings of the ACME client
+#[derive(Clone)]
+pub struct AcmeConfig {
+    /// Domains the certificate is valid for
+    pub domains: Vec<String>,
+
+    /// Directory where the account key and the certificates are kept
+    pub cache_dir: PathBuf,
+
+    /// Directory URL of the ACME server
+    pub directory_url: String,
+
+    /// Contact email address of the account
+    pub email: Option<String>,
+
+    /// Port on which the HTTP-01 challenges are answered
+    pub http_port: u16,
+}
+
+impl AcmeConfig {
+    /// Path of the certificate chain
+    pub fn cert_path(&self) -> PathBuf {
+        self.cache_dir.join(format!("{}.crt", self.domains[0]))
+    }
+
+    /// Path of the private key of the certificate
+    pub fn key_path(&self) -> PathBuf {
+        self.cache_dir.join(format!("{}.key", self.domains[0]))
+    }
+
+    /// Path of the private key of the ACME account
+    fn account_key_path(&self) -> PathBuf {
+        self.cache_dir.join("account.pk8")
+    }
+
+    /// Return `true` if the certificate is missing, or old enough to be renewed
+    fn needs_certificate(&self) -> bool {
+        std::fs::metadata(self.cert_path())
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_none_or(|age| age > RENEWAL_AGE)
+    }
+}
+
+/// Key authorizations of the pending HTTP-01 challenges, by token
+#[derive(Default)]
+pub struct Challenges(RwLock<HashMap<String, String>>);
+
+impl Challenges {
+    fn insert(&self, token: String, key_authorization: String) {
+        let mut challenges = self.0.write().unwrap_or_else(|e| e.into_inner());
+        challenges.insert(token, key_authorization);
+    }
+
+    fn clear(&self) {
+        self.0.write().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    fn get(&self, token: &str) -> Option<String> {
+        self.0
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(token)
+            .cloned()
+    }
+}
+
+/// Answer an HTTP-01 challenge
+async fn challenge_response(req: HttpRequest) -> HttpResponse {
+    let challenges = req.app_data::<web::Data<Challenges>>().unwrap();
+    match challenges.get(req.match_info().query("token")) {
+        Some(key_authorization) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(key_authorization),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Start the HTTP server answering the challenges on the HTTP-01 port of `interfaces`
+fn start_challenge_server(
+    conf: &AcmeConfig,
+    challenges: web::Data<Challenges>,
+    interfaces: &[IpAddr],
+) -> Result<(), ContextualError> {
+    let interfaces = if interfaces.is_empty() {
+        vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)]
+    } else {
+        interfaces.to_vec()
+    };
+    let addresses = interfaces
+        .into_iter()
+        .map(|interface| SocketAddr::new(interface, conf.http_port))
+        .collect::<Vec<_>>();
+
+    let srv = HttpServer::new(move || {
+        App::new().app_data(challenges.clone()).route(
+            "/.well-known/acme-challenge/{token}",
+            web::get().to(challenge_response),
+        )
+    })
+    .workers(1)
+    .disable_signals()
+    .bind(addresses.as_slice())
+    .map_err(|e| {
+        ContextualError::IoError(
+            format!("Failed to bind the ACME challenge port {}", conf.http_port),
+            e,
+        )
+    })?
+    .run();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = srv.await {
+            log::error!("The ACME challenge server stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+/// Encode `data` in unpadded base64url, as used by JWS
+fn base64url(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+fn acme_error(context: &str, e: impl std::fmt::Display) -> ContextualError {
+    ContextualError::AcmeError(format!("{}: {}", context, e))
+}
+
+/// Write `contents` to the file at `path`, only readable by the current user
+fn write_private_file(path: &Path, contents: &[u8]) -> Result<(), ContextualError> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .map_err(|e| ContextualError::IoError(format!("Failed to write {}", path.display()), e))
+}
+
+/// URLs of the ACME server
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Deserialize)]
+struct Order {
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Authorization {
+    status: String,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    token: String,
+}
+
+/// Client of an ACME server, signing its requests with the account key
+struct AcmeClient {
+    client: awc::Client,
+    key: EcdsaKeyPair,
+    rng: SystemRandom,
+    directory: Directory,
+
+    /// Nonce returned by the last response, to be used by the next request
+    nonce: Option<String>,
+
+    /// URL of the account, once it is registered
+    account_url: Option<String>,
+}
+
+impl AcmeClient {
+    /// Fetch the directory of the ACME server, with the account key kept in the cache directory
+    async fn new(conf: &AcmeConfig) -> Result<Self, ContextualError> {
+        let rng = SystemRandom::new();
+        let key_path = conf.account_key_path();
+        let pkcs8 = match std::fs::read(&key_path) {
+            Ok(pkcs8) => pkcs8,
+            Err(_) => {
+                let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                    .map_err(|e| acme_error("Failed to generate the account key", e))?;
+                write_private_file(&key_path, pkcs8.as_ref())?;
+                pkcs8.as_ref().to_vec()
+            }
+        };
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8)
+            .map_err(|e| acme_error(&format!("Invalid account key {}", key_path.display()), e))?;
+
+        let client = awc::Client::new();
+        let directory = client
+            .get(&conf.directory_url)
+            .send()
+            .await
+            .map_err(|e| acme_error(&conf.directory_url, e))?
+            .json::<Directory>()
+            .await
+            .map_err(|e| acme_error(&conf.directory_url, e))?;
+
+        Ok(AcmeClient {
+            client,
+            key,
+            rng,
+            directory,
+            nonce: None,
+            account_url: None,
+        })
+    }
+
+    /// Public account key as a JSON web key
+    fn jwk(&self) -> Value {
+        // Uncompressed point: 0x04, then the x and y coordinates
+        let public_key = self.key.public_key().as_ref();
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": base64url(&public_key[1..33]),
+            "y": base64url(&public_key[33..65]),
+        })
+    }
+
+    /// Thumbprint of the account key, as used in key authorizations (RFC 7638)
+    fn thumbprint(&self) -> String {
+        let public_key = self.key.public_key().as_ref();
+        // Required members in lexicographic order, without whitespace
+        let jwk = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            base64url(&public_key[1..33]),
+            base64url(&public_key[33..65])
+        );
+        base64url(ring::digest::digest(&ring::digest::SHA256, jwk.as_bytes()).as_ref())
+    }
+
+    /// Nonce for the next request
+    async fn nonce(&mut self) -> Result<String, ContextualError> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let res = self
+            .client
+            .head(&self.directory.new_nonce)
+            .send()
+            .await
+            .map_err(|e| acme_error(&self.directory.new_nonce, e))?;
+        replay_nonce(res.headers())
+            .ok_or_else(|| acme_error(&self.directory.new_nonce, "missing nonce"))
+    }
+
+    /// Send `payload` to `url`, signed with the account key, and return the response body and
+    /// location. Without payload, this is a "POST-as-GET" request.
+    async fn post(
+        &mut self,
+        url: &str,
+        payload: Option<&Value>,
+    ) -> Result<(web::Bytes, Option<String>), ContextualError> {
+        let mut protected = json!({
+            "alg": "ES256",
+            "nonce": self.nonce().await?,
+            "url": url,
+        });
+        match &self.account_url {
+            Some(account_url) => protected["kid"] = json!(account_url),
+            None => protected["jwk"] = self.jwk(),
+        }
+        let protected = base64url(protected.to_string().as_bytes());
+        let payload = payload.map_or_else(String::new, |payload| {
+            base64url(payload.to_string().as_bytes())
+        });
+        let signature = self
+            .key
+            .sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+            .map_err(|e| acme_error("Failed to sign request", e))?;
+        let body = json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": base64url(signature.as_ref()),
+        });
+
+        let mut res = self
+            .client
+            .post(url)
+            .content_type("application/jose+json")
+            .send_body(body.to_string())
+            .await
+            .map_err(|e| acme_error(url, e))?;
+        self.nonce = replay_nonce(res.headers());
+        let location = res
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .map(str::to_string);
+        let body = res
+            .body()
+            .limit(1024 * 1024)
+            .await
+            .map_err(|e| acme_error(url, e))?;
+        if !res.status().is_success() {
+            return Err(acme_error(
+                url,
+                format!("{} {}", res.status(), String::from_utf8_lossy(&body)),
+            ));
+        }
+        Ok((body, location))
+    }
+
+    /// Fetch the resource at `url`
+    async fn get<T: DeserializeOwned>(&mut self, url: &str) -> Result<T, ContextualError> {
+        let (body, _) = self.post(url, None).await?;
+        serde_json::from_slice(&body).map_err(|e| acme_error(url, e))
+    }
+
+    /// Wait until the status of the resource at `url` is `expected`, and return the resource
+    async fn wait_for_status(
+        &mut self,
+        url: &str,
+        expected: &str,
+    ) -> Result<Value, ContextualError> {
+        for _ in 0..MAX_POLLS {
+            let resource = self.get::<Value>(url).await?;
+            match resource["status"].as_str() {
+                Some(status) if status == expected => return Ok(resource),
+                Some("pending") | Some("processing") | Some("ready") => {
+                    actix_web::rt::time::sleep(POLL_INTERVAL).await
+                }
+                _ => return Err(acme_error(url, resource)),
+            }
+        }
+        Err(acme_error(url, format!("not {} in time", expected)))
+    }
+}
+
+/// Nonce given by the ACME server in `headers`
+fn replay_nonce(headers: &header::HeaderMap) -> Option<String> {
+    headers
+        .get("replay-nonce")
+        .and_then(|nonce| nonce.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Obtain a certificate for the domains of `conf`, and write it to the cache directory
+async fn obtain_certificate(
+    conf: &AcmeConfig,
+    challenges: &Challenges,
+) -> Result<(), ContextualError> {
+    std::fs::create_dir_all(&conf.cache_dir).map_err(|e| {
+        ContextualError::IoError(format!("Failed to create {}", conf.cache_dir.display()), e)
+    })?;
+    let mut client = AcmeClient::new(conf).await?;
+
+    // An existing account is returned if there is one for this key
+    let mut account = json!({ "termsOfServiceAgreed": true });
+    if let Some(email) = &conf.email {
+        account["contact"] = json!([format!("mailto:{}", email)]);
+    }
+    let new_account = client.directory.new_account.clone();
+    let (_, account_url) = client.post(&new_account, Some(&account)).await?;
+    client.account_url =
+        Some(account_url.ok_or_else(|| acme_error(&new_account, "missing account URL"))?);
+
+    let identifiers = conf
+        .domains
+        .iter()
+        .map(|domain| json!({ "type": "dns", "value": domain }))
+        .collect::<Vec<_>>();
+    let new_order = client.directory.new_order.clone();
+    let (body, order_url) = client
+        .post(&new_order, Some(&json!({ "identifiers": identifiers })))
+        .await?;
+    let order_url = order_url.ok_or_else(|| acme_error(&new_order, "missing order URL"))?;
+    let order = serde_json::from_slice::<Order>(&body).map_err(|e| acme_error(&new_order, e))?;
+
+    let thumbprint = client.thumbprint();
+    for authorization_url in &order.authorizations {
+        let authorization = client.get::<Authorization>(authorization_url).await?;
+        if authorization.status == "valid" {
+            continue;
+        }
+        let challenge = authorization
+            .challenges
+            .iter()
+            .find(|challenge| challenge.kind == "http-01")
+            .ok_or_else(|| acme_error(authorization_url, "no HTTP-01 challenge"))?;
+        challenges.insert(
+            challenge.token.clone(),
+            format!("{}.{}", challenge.token, thumbprint),
+        );
+        client.post(&challenge.url, Some(&json!({}))).await?;
+        client.wait_for_status(authorization_url, "valid").await?;
+    }
+    challenges.clear();
+
+    let mut params = rcgen::CertificateParams::new(conf.domains.clone());
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, conf.domains[0].clone());
+    let certificate = rcgen::Certificate::from_params(params)
+        .map_err(|e| acme_error("Failed to generate the certificate key", e))?;
+    let csr = certificate
+        .serialize_request_der()
+        .map_err(|e| acme_error("Failed to create the certificate request", e))?;
+
+    client.wait_for_status(&order_url, "ready").await?;
+    client
+        .post(&order.finalize, Some(&json!({ "csr": base64url(&csr) })))
+        .await?;
+    let order = serde_json::from_value::<Order>(client.wait_for_status(&order_url, "valid").await?)
+        .map_err(|e| acme_error(&order_url, e))?;
+    let certificate_url = order
+        .certificate
+        .ok_or_else(|| acme_error(&order_url, "missing certificate URL"))?;
+    let (chain, _) = client.post(&certificate_url, None).await?;
+
+    write_private_file(
+        &conf.key_path(),
+        certificate.serialize_private_key_pem().as_bytes(),
+    )?;
+    write_private_file(&conf.cert_path(), &chain)
+}
+
+/// Renew the certificate when it gets old. The new files are picked up by
+/// `tls::watch_certificate`.
+async fn renew_certificate(conf: AcmeConfig, challenges: web::Data<Challenges>) {
+    loop {
+        actix_web::rt::time::sleep(RENEWAL_CHECK_INTERVAL).await;
+        if conf.needs_certificate() {
+            match obtain_certificate(&conf, &challenges).await {
+                Ok(()) => log::warn!("Renewed the certificate of {}", conf.domains.join(", ")),
+                Err(e) => log::error!("{}", e),
+            }
+        }
+    }
+}
+
+/// Serve the certificate obtained from the ACME server if `--acme-domain` is given, obtaining
+/// it first if needed
+pub async fn configure_tls(conf: &mut crate::MiniserveConfig) -> Result<(), ContextualError> {
+    let acme = match &conf.acme {
+        Some(acme) => acme.clone(),
+        None => return Ok(()),
+    };
+
+    let challenges = web::Data::new(Challenges::default());
+    start_challenge_server(&acme, challenges.clone(), &conf.interfaces)?;
+    if acme.needs_certificate() {
+        log::warn!(
+            "Obtaining a certificate for {} from {}...",
+            acme.domains.join(", "),
+            acme.directory_url
+        );
+        obtain_certificate(&acme, &challenges).await?;
+    }
+
+    let (server_config, certificate) =
+        tls::server_config(acme.cert_path(), acme.key_path(), None, &conf.tls_protocols)
+            .map_err(|e| ContextualError::AcmeError(format!("{:#}", e)))?;
+    conf.tls_rustls_config = Some(server_config);
+    conf.tls_certificate = Some(certificate);
+
+    actix_web::rt::spawn(renew_certificate(acme, challenges));
+    Ok(())
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    fn acme_config(cache_dir: &Path) -> AcmeConfig {
+        AcmeConfig {
+            domains: vec!["example.com".to_string(), "www.example.com".to_string()],
+            cache_dir: cache_dir.to_path_buf(),
+            directory_url: "https://acme.invalid/directory".to_string(),
+            email: None,
+            http_port: 80,
+        }
+    }
+
+    #[rstest]
+    fn certificate_is_needed_when_missing() {
+        let dir = TempDir::new().unwrap();
+        let conf = acme_config(dir.path());
+        assert_eq!(conf.cert_path(), dir.path().join("example.com.crt"));
+        assert!(conf.needs_certificate());
+
+        std::fs::write(conf.cert_path(), "certificate").unwrap();
+        assert!(!conf.needs_certificate());
+    }
+
+    #[rstest]
+    fn challenges_are_answered() {
+        let challenges = Challenges::default();
+        challenges.insert("token".to_string(), "token.thumbprint".to_string());
+        assert_eq!(challenges.get("token").as_deref(), Some("token.thumbprint"));
+        assert_eq!(challenges.get("other"), None);
+
+        challenges.clear();
+        assert_eq!(challenges.get("token"), None);
+    }
+}
@@ -0,0 +1,422 @@
+//! Status page of a running instance at `/__admin`, enabled with `--enable-admin`.
+//!
+//! It shows the uptime, the transfers in progress, the recent requests and the most downloaded
+//! files, and lets the users with write permission switch the server to read-only mode.
+use actix_web::body::{AnyBody, BodySize, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::web::{self, Bytes};
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::{DateTime, Local};
+use futures::future::{ready, Either};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Instant, SystemTime};
+
+use crate::auth::{self, AuthPermission, CurrentUser};
+use crate::download_stats::{DownloadStats, FileStatsEntry};
+use crate::errors::ContextualError;
+use crate::proxy;
+use crate::renderer;
+use crate::session;
+use crate::MiniserveConfig;
+
+/// Route of the status page
+pub const ADMIN_ROUTE: &str = "/__admin";
+
+/// Route of the status in JSON
+pub const ADMIN_STATUS_ROUTE: &str = "/__admin/status";
+
+/// Route switching the read-only mode on or off
+pub const ADMIN_READ_ONLY_ROUTE: &str = "/__admin/read-only";
+
+/// Number of recent requests which are kept
+const RECENT_REQUESTS: usize = 50;
+
+/// Request which was recently answered
+#[derive(Clone, Serialize)]
+pub struct RecentRequest {
+    pub time: String,
+    pub client: Option<String>,
+    pub user: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+}
+
+/// Response body being sent
+struct Transfer {
+    path: String,
+    client: Option<String>,
+    user: Option<String>,
+    started: SystemTime,
+    size: Option<u64>,
+    sent: AtomicU64,
+}
+
+/// Progress of a response body being sent
+#[derive(Serialize)]
+pub struct TransferStatus {
+    pub path: String,
+    pub client: Option<String>,
+    pub user: Option<String>,
+    pub started: String,
+    pub size: Option<u64>,
+    pub sent: u64,
+}
+
+/// Everything shown on the status page
+#[derive(Serialize)]
+pub struct AdminStatus {
+    pub version: &'static str,
+    pub uptime_secs: u64,
+    pub read_only: bool,
+    pub transfers: Vec<TransferStatus>,
+    pub recent_requests: Vec<RecentRequest>,
+
+    /// Most downloaded files, with `--download-stats`
+    pub downloads: Option<Vec<FileStatsEntry>>,
+}
+
+/// Transfers and requests recorded while miniserve is running
+pub struct AdminState {
+    started: Instant,
+    next_transfer_id: AtomicU64,
+    transfers: Mutex<BTreeMap<u64, Arc<Transfer>>>,
+    recent_requests: Mutex<VecDeque<RecentRequest>>,
+}
+
+impl AdminState {
+    pub fn new() -> Self {
+        AdminState {
+            started: Instant::now(),
+            next_transfer_id: AtomicU64::new(0),
+            transfers: Mutex::new(BTreeMap::new()),
+            recent_requests: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn record_request(&self, request: RecentRequest) {
+        let mut recent_requests = self
+            .recent_requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if recent_requests.len() == RECENT_REQUESTS {
+            recent_requests.pop_back();
+        }
+        recent_requests.push_front(request);
+    }
+
+    fn start_transfer(&self, transfer: Transfer) -> (u64, Arc<Transfer>) {
+        let id = self.next_transfer_id.fetch_add(1, Ordering::Relaxed);
+        let transfer = Arc::new(transfer);
+        let mut transfers = self.transfers.lock().unwrap_or_else(|e| e.into_inner());
+        transfers.insert(id, transfer.clone());
+        (id, transfer)
+    }
+
+    fn finish_transfer(&self, id: u64) {
+        let mut transfers = self.transfers.lock().unwrap_or_else(|e| e.into_inner());
+        transfers.remove(&id);
+    }
+
+    /// Current status, with `read_only` as the state of the read-only mode
+    pub fn status(&self, read_only: bool) -> AdminStatus {
+        let transfers = self.transfers.lock().unwrap_or_else(|e| e.into_inner());
+        let recent_requests = self
+            .recent_requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        AdminStatus {
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: self.started.elapsed().as_secs(),
+            read_only,
+            transfers: transfers
+                .values()
+                .map(|transfer| TransferStatus {
+                    path: transfer.path.clone(),
+                    client: transfer.client.clone(),
+                    user: transfer.user.clone(),
+                    started: format_time(transfer.started),
+                    size: transfer.size,
+                    sent: transfer.sent.load(Ordering::Relaxed),
+                })
+                .collect(),
+            recent_requests: recent_requests.iter().cloned().collect(),
+            downloads: None,
+        }
+    }
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).to_rfc3339()
+}
+
+/// Response body counting the bytes sent, listed as a transfer until it is dropped
+struct TrackedBody<B> {
+    body: B,
+    id: u64,
+    transfer: Arc<Transfer>,
+    state: web::Data<AdminState>,
+}
+
+impl<B> Drop for TrackedBody<B> {
+    fn drop(&mut self) {
+        self.state.finish_transfer(self.id);
+    }
+}
+
+impl<B: MessageBody + Unpin> MessageBody for TrackedBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let chunk = futures::ready!(Pin::new(&mut this.body).poll_next(cx));
+        if let Some(Ok(bytes)) = &chunk {
+            this.transfer
+                .sent
+                .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        }
+        Poll::Ready(chunk)
+    }
+}
+
+/// Record every request and the progress of its response, if the status page is enabled
+pub fn record_request<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    if !conf.admin_enabled {
+        return Either::Left(srv.call(req));
+    }
+
+    let state = req.app_data::<web::Data<AdminState>>().unwrap().clone();
+    let (http_req, payload) = req.into_parts();
+    let client = proxy::client_ip(&http_req).map(|ip| ip.to_string());
+    let req = ServiceRequest::from_parts(http_req, payload);
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let fut = srv.call(req);
+
+    Either::Right(async move {
+        let res = fut.await?;
+        let user = res
+            .request()
+            .extensions()
+            .get::<CurrentUser>()
+            .map(|user| user.name.clone());
+        state.record_request(RecentRequest {
+            time: format_time(SystemTime::now()),
+            client: client.clone(),
+            user: user.clone(),
+            method,
+            path: path.clone(),
+            status: res.status().as_u16(),
+        });
+
+        let size = match res.response().body().size() {
+            BodySize::None | BodySize::Empty | BodySize::Sized(0) => return Ok(res),
+            BodySize::Sized(size) => Some(size),
+            BodySize::Stream => None,
+        };
+        Ok(res.map_body(|_, body| {
+            let (id, transfer) = state.start_transfer(Transfer {
+                path,
+                client,
+                user,
+                started: SystemTime::now(),
+                size,
+                sent: AtomicU64::new(0),
+            });
+            AnyBody::from_message(TrackedBody {
+                body,
+                id,
+                transfer,
+                state,
+            })
+        }))
+    })
+}
+
+/// Refuse the requests which would modify the served directory while read-only mode is enabled
+pub fn enforce_read_only<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let (req, payload) = req.into_parts();
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let refused = *conf.read_only.get()
+        && auth::is_write_request(&req)
+        && !req.path().starts_with(ADMIN_ROUTE)
+        && req.path() != session::LOGIN_ROUTE;
+
+    if refused {
+        let res = HttpResponse::Forbidden().body(ContextualError::ReadOnlyModeError.to_string());
+        Either::Left(ready(Ok(ServiceResponse::new(req, res))))
+    } else {
+        Either::Right(srv.call(ServiceRequest::from_parts(req, payload)))
+    }
+}
+
+/// Return `true` if the user of `req` may use the status page
+fn is_admin(req: &HttpRequest) -> bool {
+    req.extensions()
+        .get::<CurrentUser>()
+        .is_some_and(|user| user.permission == AuthPermission::ReadWrite)
+}
+
+/// Current status of the server answering `req`
+fn current_status(req: &HttpRequest, conf: &MiniserveConfig) -> AdminStatus {
+    let state = req.app_data::<web::Data<AdminState>>().unwrap();
+    let mut status = state.status(*conf.read_only.get());
+    status.downloads = req
+        .app_data::<web::Data<DownloadStats>>()
+        .filter(|stats| stats.is_enabled())
+        .map(|stats| stats.top_files(&conf.path));
+    status
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().body(ContextualError::InsufficientAuthPermissionsError.to_string())
+}
+
+/// Handle the status page
+pub async fn admin_page(req: HttpRequest) -> HttpResponse {
+    if !is_admin(&req) {
+        return forbidden();
+    }
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let status = current_status(&req, conf);
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .append_header((header::CACHE_CONTROL, "no-store"))
+        .body(
+            renderer::admin_page(
+                &status,
+                &conf.favicon_route,
+                &conf.css_route,
+                &conf.default_color_scheme,
+                &conf.default_color_scheme_dark,
+                conf.hide_version_footer,
+            )
+            .into_string(),
+        )
+}
+
+/// Handle the status in JSON
+pub async fn admin_status(req: HttpRequest) -> HttpResponse {
+    if !is_admin(&req) {
+        return forbidden();
+    }
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+
+    HttpResponse::Ok()
+        .append_header((header::CACHE_CONTROL, "no-store"))
+        .json(current_status(&req, conf))
+}
+
+#[derive(Deserialize)]
+pub struct ReadOnlyForm {
+    read_only: bool,
+}
+
+/// Switch the read-only mode on or off
+pub async fn set_read_only(req: HttpRequest, form: web::Form<ReadOnlyForm>) -> HttpResponse {
+    if !is_admin(&req) {
+        return forbidden();
+    }
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    if *conf.read_only.get() != form.read_only {
+        let user = req
+            .extensions()
+            .get::<CurrentUser>()
+            .map(|user| user.name.clone())
+            .unwrap_or_default();
+        log::warn!(
+            "Read-only mode {} by {}",
+            if form.read_only {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            user
+        );
+        conf.read_only.set(form.read_only);
+    }
+
+    HttpResponse::SeeOther()
+        .append_header((header::LOCATION, ADMIN_ROUTE))
+        .finish()
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    fn request(path: &str) -> RecentRequest {
+        RecentRequest {
+            time: format_time(SystemTime::now()),
+            client: None,
+            user: None,
+            method: "GET".to_string(),
+            path: path.to_string(),
+            status: 200,
+        }
+    }
+
+    #[rstest]
+    fn recent_requests_are_capped() {
+        let state = AdminState::new();
+        for i in 0..RECENT_REQUESTS + 10 {
+            state.record_request(request(&format!("/{}", i)));
+        }
+
+        let status = state.status(false);
+        assert_eq!(status.recent_requests.len(), RECENT_REQUESTS);
+        assert_eq!(status.recent_requests[0].path, format!("/{}", RECENT_REQUESTS + 9));
+    }
+
+    #[rstest]
+    fn finished_transfers_are_removed() {
+        let state = AdminState::new();
+        let (id, transfer) = state.start_transfer(Transfer {
+            path: "/file".to_string(),
+            client: None,
+            user: None,
+            started: SystemTime::now(),
+            size: Some(10),
+            sent: AtomicU64::new(0),
+        });
+        transfer.sent.store(4, Ordering::Relaxed);
+        assert_eq!(state.status(false).transfers[0].sent, 4);
+
+        state.finish_transfer(id);
+        assert!(state.status(false).transfers.is_empty());
+    }
+}
@@ -1,15 +1,18 @@
 use actix_web::http::ContentEncoding;
-use libflate::gzip::Encoder;
+use libflate::gzip::{Decoder, Encoder};
 use serde::Deserialize;
-use std::fs::File;
-use std::io::{Cursor, Read, Write};
-use std::path::Path;
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, Read, Seek, Write};
 use std::path::PathBuf;
+use std::path::{Component, Path};
 use strum_macros::{Display, EnumIter, EnumString};
-use tar::Builder;
-use zip::{write, ZipWriter};
+use tar::{Archive, Builder, EntryType};
+use zip::{write, ZipArchive, ZipWriter};
 
+use crate::dir_config::DIR_CONFIG_FILE;
 use crate::errors::ContextualError;
+use crate::file_upload::{self, DuplicateFilePolicy};
+use crate::ignore::IgnoredEntries;
 
 /// Available archive methods
 #[derive(Deserialize, Clone, Copy, EnumIter, EnumString, Display)]
@@ -66,10 +69,12 @@ impl ArchiveMethod {
     /// Recursively includes all files and subdirectories.
     ///
     /// If `skip_symlinks` is `true`, symlinks fill not be followed and will just be ignored.
+    /// The entries matching `ignored` are left out.
     pub fn create_archive<T, W>(
         self,
         dir: T,
         skip_symlinks: bool,
+        ignored: Option<IgnoredEntries>,
         out: W,
     ) -> Result<(), ContextualError>
     where
@@ -77,22 +82,28 @@ impl ArchiveMethod {
         W: std::io::Write,
     {
         let dir = dir.as_ref();
+        let ignored = ignored.as_ref();
         match self {
-            ArchiveMethod::TarGz => tar_gz(dir, skip_symlinks, out),
-            ArchiveMethod::Tar => tar_dir(dir, skip_symlinks, out),
-            ArchiveMethod::Zip => zip_dir(dir, skip_symlinks, out),
+            ArchiveMethod::TarGz => tar_gz(dir, skip_symlinks, ignored, out),
+            ArchiveMethod::Tar => tar_dir(dir, skip_symlinks, ignored, out),
+            ArchiveMethod::Zip => zip_dir(dir, skip_symlinks, ignored, out),
         }
     }
 }
 
 /// Write a gzipped tarball of `dir` in `out`.
-fn tar_gz<W>(dir: &Path, skip_symlinks: bool, out: W) -> Result<(), ContextualError>
+fn tar_gz<W>(
+    dir: &Path,
+    skip_symlinks: bool,
+    ignored: Option<&IgnoredEntries>,
+    out: W,
+) -> Result<(), ContextualError>
 where
     W: std::io::Write,
 {
     let mut out = Encoder::new(out).map_err(|e| ContextualError::IoError("GZIP".to_string(), e))?;
 
-    tar_dir(dir, skip_symlinks, &mut out)?;
+    tar_dir(dir, skip_symlinks, ignored, &mut out)?;
 
     out.finish()
         .into_result()
@@ -124,7 +135,12 @@ where
 /// ├── f
 /// └── g
 /// ```
-fn tar_dir<W>(dir: &Path, skip_symlinks: bool, out: W) -> Result<(), ContextualError>
+fn tar_dir<W>(
+    dir: &Path,
+    skip_symlinks: bool,
+    ignored: Option<&IgnoredEntries>,
+    out: W,
+) -> Result<(), ContextualError>
 where
     W: std::io::Write,
 {
@@ -132,13 +148,10 @@ where
         ContextualError::InvalidPathError("Directory name terminates in \"..\"".to_string())
     })?;
 
-    let directory = inner_folder.to_str().ok_or_else(|| {
-        ContextualError::InvalidPathError(
-            "Directory name contains invalid UTF-8 characters".to_string(),
-        )
-    })?;
+    // Names which aren't valid UTF-8 get replacement characters
+    let directory = inner_folder.to_string_lossy();
 
-    tar(dir, directory.to_string(), skip_symlinks, out)
+    tar(dir, directory.to_string(), skip_symlinks, ignored, out)
         .map_err(|e| ContextualError::ArchiveCreationError("tarball".to_string(), Box::new(e)))
 }
 
@@ -149,6 +162,7 @@ fn tar<W>(
     src_dir: &Path,
     inner_folder: String,
     skip_symlinks: bool,
+    ignored: Option<&IgnoredEntries>,
     out: W,
 ) -> Result<(), ContextualError>
 where
@@ -159,17 +173,25 @@ where
     tar_builder.follow_symlinks(!skip_symlinks);
 
     // Recursively adds the content of src_dir into the archive stream
-    tar_builder
-        .append_dir_all(inner_folder, src_dir)
-        .map_err(|e| {
-            ContextualError::IoError(
-                format!(
-                    "Failed to append the content of {} to the TAR archive",
-                    src_dir.to_str().unwrap_or("file")
-                ),
-                e,
-            )
-        })?;
+    match ignored {
+        Some(ignored) => append_dir_except(
+            &mut tar_builder,
+            src_dir,
+            Path::new(&inner_folder),
+            skip_symlinks,
+            ignored,
+        ),
+        None => tar_builder.append_dir_all(inner_folder, src_dir),
+    }
+    .map_err(|e| {
+        ContextualError::IoError(
+            format!(
+                "Failed to append the content of {} to the TAR archive",
+                src_dir.to_str().unwrap_or("file")
+            ),
+            e,
+        )
+    })?;
 
     // Finish the archive
     tar_builder.into_inner().map_err(|e| {
@@ -179,6 +201,46 @@ where
     Ok(())
 }
 
+/// Add the content of `src_dir` to `tar_builder` in the folder `name`, like
+/// `Builder::append_dir_all` does, except for the `ignored` entries
+fn append_dir_except<W>(
+    tar_builder: &mut Builder<W>,
+    src_dir: &Path,
+    name: &Path,
+    skip_symlinks: bool,
+    ignored: &IgnoredEntries,
+) -> std::io::Result<()>
+where
+    W: std::io::Write,
+{
+    tar_builder.append_dir(name, src_dir)?;
+    for entry in std::fs::read_dir(src_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let entry_name = file_name.to_string_lossy();
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        let is_dir = if file_type.is_symlink() {
+            !skip_symlinks && path.is_dir()
+        } else {
+            file_type.is_dir()
+        };
+        if ignored.is_ignored(&entry_name, is_dir) {
+            continue;
+        }
+
+        if is_dir {
+            if let Some(ignored) = ignored.subdir(&entry_name) {
+                let name = name.join(&file_name);
+                append_dir_except(tar_builder, &path, &name, skip_symlinks, &ignored)?;
+            }
+        } else {
+            tar_builder.append_path_with_name(&path, name.join(&file_name))?;
+        }
+    }
+    Ok(())
+}
+
 /// Write a zip of `dir` in `out`.
 ///
 /// The target directory will be saved as a top-level directory in the archive.
@@ -206,12 +268,14 @@ fn create_zip_from_directory<W>(
     out: W,
     directory: &Path,
     skip_symlinks: bool,
+    ignored: Option<&IgnoredEntries>,
 ) -> Result<(), ContextualError>
 where
     W: std::io::Write + std::io::Seek,
 {
     let options = write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
-    let mut paths_queue: Vec<PathBuf> = vec![directory.to_path_buf()];
+    let mut paths_queue: Vec<(PathBuf, Option<IgnoredEntries>)> =
+        vec![(directory.to_path_buf(), ignored.cloned())];
     let zip_root_folder_name = directory.file_name().ok_or_else(|| {
         ContextualError::InvalidPathError("Directory name terminates in \"..\"".to_string())
     })?;
@@ -219,7 +283,7 @@ where
     let mut zip_writer = ZipWriter::new(out);
     let mut buffer = Vec::new();
     while !paths_queue.is_empty() {
-        let next = paths_queue.pop().ok_or_else(|| {
+        let (next, ignored) = paths_queue.pop().ok_or_else(|| {
             ContextualError::ArchiveCreationDetailError("Could not get path from queue".to_string())
         })?;
         let current_dir = next.as_path();
@@ -252,6 +316,12 @@ where
             let current_entry_name = entry_path.file_name().ok_or_else(|| {
                 ContextualError::InvalidPathError("Invalid file or direcotory name".to_string())
             })?;
+            let entry_name = current_entry_name.to_string_lossy();
+            if let Some(ignored) = &ignored {
+                if ignored.is_ignored(&entry_name, entry_metadata.is_dir()) {
+                    continue;
+                }
+            }
             if entry_metadata.is_file() {
                 let mut f = File::open(&entry_path)
                     .map_err(|e| ContextualError::IoError("Could not open file".to_string(), e))?;
@@ -281,7 +351,10 @@ where
                             "Could not add directory path to ZIP".to_string(),
                         )
                     })?;
-                paths_queue.push(entry_path.clone());
+                let ignored = ignored
+                    .as_ref()
+                    .and_then(|ignored| ignored.subdir(&entry_name));
+                paths_queue.push((entry_path.clone(), ignored));
             }
         }
     }
@@ -297,13 +370,18 @@ where
 /// Writes a zip of `dir` in `out`.
 ///
 /// The content of `src_dir` will be saved in the archive as the  folder named .
-fn zip_data<W>(src_dir: &Path, skip_symlinks: bool, mut out: W) -> Result<(), ContextualError>
+fn zip_data<W>(
+    src_dir: &Path,
+    skip_symlinks: bool,
+    ignored: Option<&IgnoredEntries>,
+    mut out: W,
+) -> Result<(), ContextualError>
 where
     W: std::io::Write,
 {
     let mut data = Vec::new();
     let memory_file = Cursor::new(&mut data);
-    create_zip_from_directory(memory_file, &src_dir.to_path_buf(), skip_symlinks).map_err(|e| {
+    create_zip_from_directory(memory_file, src_dir, skip_symlinks, ignored).map_err(|e| {
         ContextualError::ArchiveCreationError(
             "Failed to create the ZIP archive".to_string(),
             Box::new(e),
@@ -316,20 +394,362 @@ where
     Ok(())
 }
 
-fn zip_dir<W>(dir: &Path, skip_symlinks: bool, out: W) -> Result<(), ContextualError>
+fn zip_dir<W>(
+    dir: &Path,
+    skip_symlinks: bool,
+    ignored: Option<&IgnoredEntries>,
+    out: W,
+) -> Result<(), ContextualError>
 where
     W: std::io::Write,
 {
-    let inner_folder = dir.file_name().ok_or_else(|| {
+    dir.file_name().ok_or_else(|| {
         ContextualError::InvalidPathError("Directory name terminates in \"..\"".to_string())
     })?;
 
-    inner_folder.to_str().ok_or_else(|| {
-        ContextualError::InvalidPathError(
-            "Directory name contains invalid UTF-8 characters".to_string(),
-        )
+    zip_data(dir, skip_symlinks, ignored, out)
+        .map_err(|e| ContextualError::ArchiveCreationError("zip".to_string(), Box::new(e)))
+}
+
+/// Archive formats which uploaded files can be extracted from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExtractMethod {
+    /// Gzipped tarball
+    TarGz,
+
+    /// Regular tarball
+    Tar,
+
+    /// Regular zip
+    Zip,
+}
+
+impl ExtractMethod {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ExtractMethod::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ExtractMethod::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ExtractMethod::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Return `true` if `path` has the extension of an archive which can be extracted
+pub fn is_extractable(path: &Path) -> bool {
+    ExtractMethod::from_path(path).is_some()
+}
+
+/// Extract the archive at `archive_path` into `target_dir`, which must be canonical.
+///
+/// Entries which would end up outside of `target_dir`, which `check_access` refuses, or which are
+/// `.miniserve.toml` files are rejected, and extraction fails once more than `max_size` bytes
+/// were extracted. Only files and directories are extracted, and the
+/// files created by the extraction are removed again if it fails. The files it overwrites are
+/// only replaced once it succeeded, so that they are kept intact otherwise.
+///
+/// Return the extracted bytes and files.
+pub fn extract_archive(
+    archive_path: &Path,
+    target_dir: &Path,
+    max_size: u64,
+    on_duplicate_files: DuplicateFilePolicy,
+    check_access: &dyn Fn(&Path) -> Result<(), ContextualError>,
+) -> Result<Extraction, ContextualError> {
+    let method = ExtractMethod::from_path(archive_path).ok_or_else(|| {
+        ContextualError::ArchiveExtractionError("Unsupported archive format".to_string())
     })?;
+    let file = File::open(archive_path)
+        .map_err(|e| ContextualError::IoError("Failed to open the archive".to_string(), e))?;
+    let mut extractor = Extractor {
+        target_dir,
+        remaining: max_size,
+        on_duplicate_files,
+        check_access,
+        created_files: Vec::new(),
+        replacements: Vec::new(),
+    };
+
+    let result = match method {
+        ExtractMethod::TarGz => Decoder::new(file)
+            .map_err(|e| ContextualError::IoError("GZIP".to_string(), e))
+            .and_then(|decoder| extractor.extract_tar(decoder)),
+        ExtractMethod::Tar => extractor.extract_tar(file),
+        ExtractMethod::Zip => extractor.extract_zip(file),
+    };
+
+    let mut files = extractor.created_files.clone();
+    files.extend(extractor.replacements.iter().map(|(_, path)| path.clone()));
+    let result = result.and_then(|()| extractor.replace_files());
+    if let Err(e) = result {
+        for path in extractor.created_files {
+            let _ = std::fs::remove_file(path);
+        }
+        for (staged_path, _) in extractor.replacements {
+            let _ = std::fs::remove_file(staged_path);
+        }
+        return Err(e);
+    }
 
-    zip_data(dir, skip_symlinks, out)
-        .map_err(|e| ContextualError::ArchiveCreationError("zip".to_string(), Box::new(e)))
+    Ok(Extraction {
+        size: max_size - extractor.remaining,
+        files,
+    })
+}
+
+/// Result of a successful extraction
+pub struct Extraction {
+    /// Number of extracted bytes
+    pub size: u64,
+
+    /// Files written by the extraction, which it created or replaced
+    pub files: Vec<PathBuf>,
+}
+
+/// State of an archive extraction
+struct Extractor<'a> {
+    target_dir: &'a Path,
+    remaining: u64,
+    on_duplicate_files: DuplicateFilePolicy,
+
+    /// Check of the right to write each entry, following the rules of its own directory
+    check_access: &'a dyn Fn(&Path) -> Result<(), ContextualError>,
+
+    /// Files which didn't exist before the extraction, removed if it fails
+    created_files: Vec<PathBuf>,
+
+    /// Files written next to the existing files they replace, with the paths of the latter
+    replacements: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Path of the file an archive entry is written to until it replaces the file at `path`
+fn staged_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.extract", name))
+}
+
+impl Extractor<'_> {
+    fn extract_tar<R: Read>(&mut self, reader: R) -> Result<(), ContextualError> {
+        let mut archive = Archive::new(reader);
+        let entries = archive.entries().map_err(|e| {
+            ContextualError::IoError("Failed to read the TAR archive".to_string(), e)
+        })?;
+
+        for entry in entries {
+            let mut entry = entry.map_err(|e| {
+                ContextualError::IoError("Failed to read the TAR archive".to_string(), e)
+            })?;
+            let entry_path = entry
+                .path()
+                .map_err(|e| {
+                    ContextualError::IoError("Failed to read the TAR archive".to_string(), e)
+                })?
+                .into_owned();
+
+            match entry.header().entry_type() {
+                EntryType::Directory => self.create_dir(&entry_path)?,
+                EntryType::Regular | EntryType::Continuous => {
+                    self.create_file(&entry_path, &mut entry)?
+                }
+                // Links and special files are never extracted
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn extract_zip<R: Read + Seek>(&mut self, reader: R) -> Result<(), ContextualError> {
+        let mut archive = ZipArchive::new(reader)
+            .map_err(|e| ContextualError::ArchiveExtractionError(e.to_string()))?;
+
+        for i in 0..archive.len() {
+            let mut entry = archive
+                .by_index(i)
+                .map_err(|e| ContextualError::ArchiveExtractionError(e.to_string()))?;
+            let entry_path = PathBuf::from(entry.name());
+
+            if entry.is_dir() {
+                self.create_dir(&entry_path)?;
+            } else {
+                self.create_file(&entry_path, &mut entry)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the path an archive entry has to be extracted to, creating its parent directories
+    fn resolve(&self, entry_path: &Path) -> Result<PathBuf, ContextualError> {
+        let outside_error = || {
+            ContextualError::ArchiveExtractionError(format!(
+                "{} is outside of the target directory",
+                entry_path.display()
+            ))
+        };
+
+        let mut path = self.target_dir.to_path_buf();
+        for component in entry_path.components() {
+            match component {
+                Component::Normal(name) => path.push(name),
+                Component::CurDir => (),
+                _ => return Err(outside_error()),
+            }
+        }
+        // The restrictions of the directories can't be replaced by extracting an archive
+        if path.file_name().is_some_and(|name| name == DIR_CONFIG_FILE) {
+            return Err(ContextualError::ProtectedFileError(
+                DIR_CONFIG_FILE.to_string(),
+            ));
+        }
+        (self.check_access)(&path)?;
+
+        // Existing symlinks could lead outside of the target directory as well
+        file_upload::create_parent_dirs(self.target_dir, &path).map_err(|err| match err {
+            ContextualError::InvalidPathError(_) => outside_error(),
+            err => err,
+        })?;
+        if path
+            .symlink_metadata()
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or(false)
+        {
+            return Err(outside_error());
+        }
+
+        Ok(path)
+    }
+
+    fn create_dir(&self, entry_path: &Path) -> Result<(), ContextualError> {
+        let path = self.resolve(entry_path)?;
+        std::fs::create_dir_all(&path).map_err(|e| {
+            ContextualError::IoError(format!("Failed to create {}", path.display()), e)
+        })
+    }
+
+    fn create_file<R: Read>(
+        &mut self,
+        entry_path: &Path,
+        reader: &mut R,
+    ) -> Result<(), ContextualError> {
+        let path = match self.on_duplicate_files.resolve(self.resolve(entry_path)?)? {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let create_error =
+            |e| ContextualError::IoError(format!("Failed to create {}", path.display()), e);
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => {
+                self.created_files.push(path.clone());
+                file
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                // Leftovers of previous extractions, possibly symlinks, are never written through
+                let staged_path = staged_path(&path);
+                let _ = std::fs::remove_file(&staged_path);
+                let file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&staged_path)
+                    .map_err(create_error)?;
+                if !self
+                    .replacements
+                    .iter()
+                    .any(|(staged, _)| *staged == staged_path)
+                {
+                    self.replacements.push((staged_path, path.clone()));
+                }
+                file
+            }
+            Err(e) => return Err(create_error(e)),
+        };
+        let written = std::io::copy(&mut reader.take(self.remaining + 1), &mut file)
+            .map_err(|e| ContextualError::IoError("Failed to write to file".to_string(), e))?;
+
+        if written > self.remaining {
+            return Err(ContextualError::ArchiveExtractionError(
+                "The extracted files exceed the maximum size".to_string(),
+            ));
+        }
+        self.remaining -= written;
+
+        Ok(())
+    }
+
+    /// Replace the existing files with the entries written next to them
+    fn replace_files(&mut self) -> Result<(), ContextualError> {
+        while let Some((staged_path, path)) = self.replacements.pop() {
+            std::fs::rename(&staged_path, &path).map_err(|e| {
+                let _ = std::fs::remove_file(&staged_path);
+                ContextualError::IoError(format!("Failed to replace {}", path.display()), e)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+
+    /// Write a TAR archive of `files` to `path`
+    fn create_tar(path: &Path, files: &[(&str, &[u8])]) {
+        let mut builder = Builder::new(File::create(path).unwrap());
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[rstest]
+    fn failed_extractions_only_remove_the_created_files() {
+        let dir = TempDir::new().unwrap();
+        let target_dir = dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        let target_dir = target_dir.canonicalize().unwrap();
+        std::fs::write(target_dir.join("existing.txt"), "existing").unwrap();
+        let archive = dir.path().join("archive.tar");
+        create_tar(&archive, &[
+            ("new.txt", b"new"),
+            ("existing.txt", b"overwritten"),
+            ("large.bin", &[0; 100]),
+        ]);
+
+        let result = extract_archive(&archive, &target_dir, 50, DuplicateFilePolicy::Overwrite, &|_| Ok(()));
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(target_dir.join("existing.txt")).unwrap(), "existing");
+        assert!(!target_dir.join("new.txt").exists());
+        assert!(!target_dir.join("large.bin").exists());
+        assert_eq!(std::fs::read_dir(&target_dir).unwrap().count(), 1);
+    }
+
+    #[rstest]
+    fn successful_extractions_replace_the_existing_files() {
+        let dir = TempDir::new().unwrap();
+        let target_dir = dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        let target_dir = target_dir.canonicalize().unwrap();
+        std::fs::write(target_dir.join("existing.txt"), "existing").unwrap();
+        let archive = dir.path().join("archive.tar");
+        create_tar(&archive, &[("existing.txt", b"overwritten")]);
+
+        let result = extract_archive(&archive, &target_dir, 50, DuplicateFilePolicy::Overwrite, &|_| Ok(()));
+        let extraction = result.unwrap();
+        assert_eq!(extraction.size, 11);
+        assert_eq!(extraction.files, vec![target_dir.join("existing.txt")]);
+        assert_eq!(std::fs::read_to_string(target_dir.join("existing.txt")).unwrap(), "overwritten");
+        assert_eq!(std::fs::read_dir(&target_dir).unwrap().count(), 1);
+    }
 }
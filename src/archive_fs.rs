@@ -0,0 +1,328 @@
+//! Archive served as a read-only directory tree with `--from-archive`, without extracting it.
+//!
+//! The entries are indexed when miniserve starts, and the content of a file is read from the
+//! archive whenever it is downloaded.
+use actix_web::web::Bytes;
+use chrono::{NaiveDate, TimeZone, Utc};
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use futures::stream::StreamExt;
+use libflate::gzip::Decoder;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tar::{Archive, EntryType};
+use zip::ZipArchive;
+
+use crate::archive::ExtractMethod;
+use crate::canonical_path;
+use crate::errors::ContextualError;
+use crate::storage::{normalize, ByteStream, Storage, StorageEntry};
+
+/// Where the content of a file is stored in the archive
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Location {
+    /// Index of the entry in a zip archive
+    Zip(usize),
+
+    /// Position of the content in a regular tarball
+    Tar(u64),
+
+    /// Index of the entry in a gzipped tarball, which has to be read from the start
+    TarGz(usize),
+}
+
+/// File or directory of the archive
+#[derive(Debug, PartialEq)]
+struct ArchiveEntry {
+    /// Size in bytes, 0 for directories
+    size: u64,
+    modified: Option<SystemTime>,
+
+    /// Content of the file, `None` for directories
+    location: Option<Location>,
+}
+
+/// Files and directories of an archive
+#[derive(Debug)]
+pub struct ArchiveFs {
+    path: PathBuf,
+    method: ExtractMethod,
+
+    /// Entries by their path in the archive, without leading or trailing slash. The root
+    /// directory is the empty path.
+    entries: BTreeMap<PathBuf, ArchiveEntry>,
+}
+
+fn read_error<E: ToString>(e: E) -> ContextualError {
+    ContextualError::ArchiveReadError(e.to_string())
+}
+
+impl ArchiveFs {
+    /// Index the entries of the .tar, .tar.gz or .zip archive at `path`
+    pub fn open(path: PathBuf) -> Result<Self, ContextualError> {
+        let method = ExtractMethod::from_path(&path).ok_or_else(|| {
+            read_error(format!(
+                "{} is not a .tar, .tar.gz or .zip archive",
+                path.display()
+            ))
+        })?;
+        let file = File::open(&path).map_err(|e| {
+            ContextualError::IoError(format!("Failed to open {}", path.display()), e)
+        })?;
+
+        let mut archive_fs = ArchiveFs {
+            path,
+            method,
+            entries: BTreeMap::new(),
+        };
+        archive_fs.insert(PathBuf::new(), 0, None, None);
+        match method {
+            ExtractMethod::TarGz => {
+                archive_fs.index_tar(Decoder::new(file).map_err(read_error)?)?
+            }
+            ExtractMethod::Tar => archive_fs.index_tar(file)?,
+            ExtractMethod::Zip => archive_fs.index_zip(file)?,
+        }
+        Ok(archive_fs)
+    }
+
+    /// Add an entry along with its parent directories, unless they are already known
+    fn insert(
+        &mut self,
+        path: PathBuf,
+        size: u64,
+        modified: Option<SystemTime>,
+        location: Option<Location>,
+    ) {
+        for parent in path.ancestors().skip(1) {
+            self.entries
+                .entry(parent.to_path_buf())
+                .or_insert(ArchiveEntry {
+                    size: 0,
+                    modified: None,
+                    location: None,
+                });
+        }
+        self.entries.insert(
+            path,
+            ArchiveEntry {
+                size,
+                modified,
+                location,
+            },
+        );
+    }
+
+    fn index_tar<R: Read>(&mut self, reader: R) -> Result<(), ContextualError> {
+        let mut archive = Archive::new(reader);
+        for (i, entry) in archive.entries().map_err(read_error)?.enumerate() {
+            let entry = entry.map_err(read_error)?;
+            let path = match normalize(&entry.path().map_err(read_error)?) {
+                Some(path) => path,
+                None => continue,
+            };
+            let modified = entry
+                .header()
+                .mtime()
+                .ok()
+                .map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime));
+
+            match entry.header().entry_type() {
+                EntryType::Directory => self.insert(path, 0, modified, None),
+                EntryType::Regular | EntryType::Continuous => {
+                    let location = match self.method {
+                        ExtractMethod::TarGz => Location::TarGz(i),
+                        _ => Location::Tar(entry.raw_file_position()),
+                    };
+                    self.insert(path, entry.size(), modified, Some(location))
+                }
+                // Links and special files are left out, like when extracting archives
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn index_zip<R: Read + Seek>(&mut self, reader: R) -> Result<(), ContextualError> {
+        let mut archive = ZipArchive::new(reader).map_err(read_error)?;
+        for i in 0..archive.len() {
+            let entry = archive.by_index(i).map_err(read_error)?;
+            let path = match normalize(Path::new(entry.name())) {
+                Some(path) => path,
+                None => continue,
+            };
+            // Zip archives store the local time without time zone, which is taken as UTC
+            let time = entry.last_modified();
+            let modified =
+                NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())
+                    .and_then(|date| {
+                        date.and_hms_opt(
+                            time.hour().into(),
+                            time.minute().into(),
+                            time.second().into(),
+                        )
+                    })
+                    .map(|time| Utc.from_utc_datetime(&time).into());
+
+            if entry.is_dir() {
+                self.insert(path, 0, modified, None);
+            } else {
+                self.insert(path, entry.size(), modified, Some(Location::Zip(i)));
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, path: &Path) -> Option<&ArchiveEntry> {
+        self.entries.get(path)
+    }
+
+    /// Entries directly in the directory `dir`, along with their names
+    fn children<'a>(
+        &'a self,
+        dir: &'a Path,
+    ) -> impl Iterator<Item = (String, &'a ArchiveEntry)> + 'a {
+        self.entries
+            .range(dir.to_path_buf()..)
+            .skip_while(move |(path, _)| *path == dir)
+            .take_while(move |(path, _)| path.starts_with(dir))
+            .filter(move |(path, _)| path.parent() == Some(dir))
+            .map(|(path, entry)| {
+                let name = path.file_name().unwrap_or_default();
+                (name.to_string_lossy().to_string(), entry)
+            })
+    }
+
+    /// Write the content of the file at `path` to `out`
+    fn copy_file<W: Write>(&self, path: &Path, mut out: W) -> Result<u64, ContextualError> {
+        let location = self
+            .get(path)
+            .and_then(|entry| entry.location)
+            .ok_or_else(|| read_error(format!("{} is not a file", path.display())))?;
+        let mut file = File::open(&self.path).map_err(read_error)?;
+
+        match location {
+            Location::Zip(index) => {
+                let mut archive = ZipArchive::new(file).map_err(read_error)?;
+                let mut entry = archive.by_index(index).map_err(read_error)?;
+                io::copy(&mut entry, &mut out).map_err(read_error)
+            }
+            Location::Tar(position) => {
+                let size = self.get(path).map_or(0, |entry| entry.size);
+                file.seek(SeekFrom::Start(position)).map_err(read_error)?;
+                io::copy(&mut file.take(size), &mut out).map_err(read_error)
+            }
+            Location::TarGz(index) => {
+                let mut archive = Archive::new(Decoder::new(file).map_err(read_error)?);
+                let mut entry = archive
+                    .entries()
+                    .map_err(read_error)?
+                    .nth(index)
+                    .ok_or_else(|| read_error(format!("{} is missing", path.display())))?
+                    .map_err(read_error)?;
+                io::copy(&mut entry, &mut out).map_err(read_error)
+            }
+        }
+    }
+}
+
+impl Storage for ArchiveFs {
+    fn location(&self) -> String {
+        let path = canonical_path::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+        path.display().to_string()
+    }
+
+    fn entry<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<Option<StorageEntry>, ContextualError>> {
+        let entry = self.get(path).map(|entry| {
+            let name = path.file_name().unwrap_or_default();
+            storage_entry(name.to_string_lossy().to_string(), entry)
+        });
+        future::ready(Ok(entry)).boxed_local()
+    }
+
+    fn list<'a>(
+        &'a self,
+        dir: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<Vec<StorageEntry>, ContextualError>> {
+        let entries = self
+            .children(dir)
+            .map(|(name, entry)| storage_entry(name, entry))
+            .collect();
+        future::ready(Ok(entries)).boxed_local()
+    }
+
+    fn read(
+        self: Arc<Self>,
+        path: PathBuf,
+    ) -> LocalBoxFuture<'static, Result<ByteStream, ContextualError>> {
+        // The file is read in a separate thread, like archives are created
+        let (tx, rx) = futures::channel::mpsc::channel::<io::Result<Bytes>>(10);
+        let pipe = crate::pipe::Pipe::new(tx);
+        std::thread::spawn(move || {
+            if let Err(err) = self.copy_file(&path, pipe) {
+                log::error!("Error while reading {}: {}", path.display(), err);
+            }
+        });
+        future::ready(Ok(rx.boxed_local())).boxed_local()
+    }
+}
+
+fn storage_entry(name: String, entry: &ArchiveEntry) -> StorageEntry {
+    StorageEntry {
+        name,
+        size: entry.location.map(|_| entry.size),
+        modified: entry.modified,
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+    use zip::write::{FileOptions, ZipWriter};
+
+    fn zip_archive(name: &str) -> ArchiveFs {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("docs/readme.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"read me").unwrap();
+        writer.start_file("top.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"top").unwrap();
+        writer.start_file("../outside.txt", FileOptions::default()).unwrap();
+        let content = writer.finish().unwrap().into_inner();
+
+        let dir = std::env::temp_dir().join(format!("miniserve-archive-fs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        ArchiveFs::open(path).unwrap()
+    }
+
+    #[rstest]
+    fn zip_entries_are_indexed() {
+        let archive = zip_archive("indexed.zip");
+        let root = archive.children(Path::new("")).map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(root, vec!["docs", "top.txt"]);
+        let docs = archive.children(Path::new("docs")).map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(docs, vec!["readme.txt"]);
+        assert!(archive.get(Path::new("docs")).unwrap().location.is_none());
+        assert!(archive.get(Path::new("outside.txt")).is_none());
+    }
+
+    #[rstest]
+    fn zip_files_are_read() {
+        let archive = zip_archive("read.zip");
+        let mut content = Vec::new();
+        archive.copy_file(Path::new("docs/readme.txt"), &mut content).unwrap();
+        assert_eq!(content, b"read me");
+        assert!(archive.copy_file(Path::new("docs"), &mut content).is_err());
+    }
+}
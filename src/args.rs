@@ -1,12 +1,31 @@
 use bytes::Bytes;
 use http::header::{HeaderMap, HeaderName, HeaderValue};
+use http::StatusCode;
 use std::net::IpAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 use structopt::StructOpt;
+use strum::VariantNames;
 
+use crate::access_log::LogFormat;
 use crate::auth;
+use crate::cache_control::{parse_cache_control, CacheControlRule};
+use crate::canonical_url::{parse_redirect_status, DirectorySlash};
+use crate::compress::Compression;
+use crate::error_pages::parse_error_page;
 use crate::errors::ContextualError;
+use crate::file_upload::DuplicateFilePolicy;
+use crate::glob::Globs;
+use crate::hooks::{parse_hook, Hook};
+use crate::ip_filter::{DenyAction, IpNet};
+use crate::log_file::RotationInterval;
+use crate::mime_types::parse_mime_type;
+use crate::mount::{Mount, VirtualHost};
 use crate::renderer;
+use crate::service::ServiceManager;
+use crate::system_log::LogTarget;
+#[cfg(feature = "tls")]
+use crate::tls::TlsVersion;
 
 #[derive(StructOpt)]
 #[structopt(
@@ -16,14 +35,136 @@ use crate::renderer;
     global_settings = &[structopt::clap::AppSettings::ColoredHelp],
 )]
 pub struct CliArgs {
+    /// Read arguments from this TOML file, overridden by the ones given on the command line
+    ///
+    /// Keys are the long names of the arguments (e.g. port = 8080, enable-tar = true,
+    /// auth = ["joe:123"]), and "path" for the path to serve. Defaults to
+    /// $XDG_CONFIG_HOME/miniserve/config.toml if it exists.
+    #[structopt(long = "config", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
     /// Be verbose, includes emitting access logs
     #[structopt(short = "v", long = "verbose")]
     pub verbose: bool,
 
+    /// Format of the access log
+    ///
+    /// "json" prints one JSON object per request on the standard output, even without --verbose.
+    #[structopt(
+        long = "log-format",
+        default_value = "text",
+        possible_values = &LogFormat::VARIANTS
+    )]
+    pub log_format: LogFormat,
+
+    /// Where the messages and the access log are written
+    ///
+    /// "syslog" and "journald" send them to the system logger, with the details of the requests,
+    /// like the client IP, as fields of their own with journald.
+    #[structopt(
+        long = "log-target",
+        default_value = "stdout",
+        possible_values = &LogTarget::VARIANTS
+    )]
+    pub log_target: LogTarget,
+
+    /// Also write the access log to this file, regardless of --verbose
+    #[structopt(long = "log-file", parse(from_os_str))]
+    pub log_file: Option<PathBuf>,
+
+    /// Rotate the log file once it reaches this size in bytes
+    #[structopt(long = "log-rotate-size", requires = "log-file")]
+    pub log_rotate_size: Option<u64>,
+
+    /// Rotate the log file every hour or every day
+    #[structopt(
+        long = "log-rotate-interval",
+        requires = "log-file",
+        possible_values = &RotationInterval::VARIANTS
+    )]
+    pub log_rotate_interval: Option<RotationInterval>,
+
+    /// Number of rotated log files which are kept
+    #[structopt(long = "log-rotate-keep", default_value = "7")]
+    pub log_rotate_keep: usize,
+
+    /// Compress rotated log files with gzip
+    #[structopt(long = "log-compress", requires = "log-file")]
+    pub log_compress: bool,
+
     /// Which path to serve
     #[structopt(name = "PATH", parse(from_os_str))]
     pub path: Option<PathBuf>,
 
+    /// Serve the contents of the .tar, .tar.gz or .zip archive given as PATH, without extracting it
+    ///
+    /// The files of the archive can be listed and downloaded, but not modified.
+    #[structopt(
+        long = "from-archive",
+        requires = "PATH",
+        conflicts_with_all = &["file-upload", "rename-enabled", "edit-enabled"]
+    )]
+    pub from_archive: bool,
+
+    /// Serve this directory merged with PATH, like a union mount (can be repeated)
+    ///
+    /// The entries of PATH take precedence, then the ones of the overlays in the order they are
+    /// given. The merged tree can be listed and downloaded, but not modified.
+    #[structopt(
+        long = "overlay",
+        requires = "PATH",
+        number_of_values = 1,
+        parse(from_os_str),
+        conflicts_with_all = &["from-archive", "file-upload", "rename-enabled", "edit-enabled"]
+    )]
+    pub overlay: Vec<PathBuf>,
+
+    /// Serve the standard input as a single file, e.g. `command | miniserve --from-stdin`
+    ///
+    /// The standard input is read until its end before the server starts.
+    #[structopt(
+        long = "from-stdin",
+        conflicts_with_all = &["PATH", "from-archive", "s3-bucket"]
+    )]
+    pub from_stdin: bool,
+
+    /// Name of the file served with --from-stdin [default: stdin.txt]
+    #[structopt(long = "filename", requires = "from-stdin")]
+    pub filename: Option<String>,
+
+    /// Serve the objects of this bucket of an S3 compatible object storage instead of a path
+    ///
+    /// The credentials are taken from the AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and
+    /// AWS_SESSION_TOKEN environment variables, without them the bucket has to be public.
+    /// The objects can be listed and downloaded, but not modified.
+    #[structopt(
+        long = "s3-bucket",
+        conflicts_with_all = &["PATH", "from-archive", "file-upload", "rename-enabled", "edit-enabled"]
+    )]
+    pub s3_bucket: Option<String>,
+
+    /// URL of the S3 compatible object storage, like http://localhost:9000 for MinIO
+    ///
+    /// Defaults to Amazon S3 in the region given with --s3-region.
+    #[structopt(long = "s3-endpoint", requires = "s3-bucket")]
+    pub s3_endpoint: Option<String>,
+
+    /// Region of the bucket given with --s3-bucket
+    #[structopt(long = "s3-region", default_value = "us-east-1")]
+    pub s3_region: String,
+
+    /// Fetch the files missing from PATH from this HTTP server, and store them in PATH
+    ///
+    /// The requests are forwarded to the same path on the server, like
+    /// http://example.com/debian/pool/main/a.deb for /pool/main/a.deb with
+    /// http://example.com/debian. Only the directories which exist locally can be listed.
+    #[structopt(
+        long = "mirror-upstream",
+        requires = "PATH",
+        conflicts_with_all = &["from-archive", "overlay"]
+    )]
+    pub mirror_upstream: Option<http::Uri>,
+
     /// The name of a directory index file to serve, like "index.html"
     ///
     /// Normally, when miniserve serves a directory, it creates a listing for that directory.
@@ -31,6 +172,48 @@ pub struct CliArgs {
     #[structopt(long, parse(from_os_str), name = "index_file")]
     pub index: Option<PathBuf>,
 
+    /// Update the listings open in the browser when their files are created, modified or deleted
+    ///
+    /// The changes are pushed to the browser with Server-Sent Events.
+    #[structopt(long = "live-updates")]
+    pub live_updates: bool,
+
+    /// Keep the contents of the listed directories in memory, until they change
+    ///
+    /// Changes are seen by watching the directories, and the contents are read again after a
+    /// minute in case some changes were missed.
+    #[structopt(long = "listing-cache")]
+    pub listing_cache: bool,
+
+    /// Keep small files (up to 256 KiB) in memory, within this total size in bytes
+    ///
+    /// The least recently used files are evicted first. Changed files are read again.
+    #[structopt(long = "memory-cache")]
+    pub memory_cache: Option<u64>,
+
+    /// Serve the .br or .gz copy next to a requested file instead, if the client accepts it
+    ///
+    /// For instance, app.js.br is sent with "Content-Encoding: br" for requests of app.js.
+    #[structopt(long = "precompressed")]
+    pub precompressed: bool,
+
+    /// Compress the responses on the fly with these encodings, if the client accepts them
+    ///
+    /// Only formats which benefit from it are compressed, like listings, text files and JSON
+    /// (e.g. --compress gzip,br).
+    #[structopt(
+        long = "compress",
+        use_delimiter = true,
+        possible_values = &Compression::VARIANTS
+    )]
+    pub compress: Vec<Compression>,
+
+    /// Never compress the files with these extensions (e.g. --compress-exclude log,csv)
+    ///
+    /// Already compressed formats like .gz, .zip or .woff2 are never compressed again.
+    #[structopt(long = "compress-exclude", use_delimiter = true, requires = "compress")]
+    pub compress_exclude: Vec<String>,
+
     /// Port to use
     #[structopt(short = "p", long = "port", default_value = "8080")]
     pub port: u16,
@@ -44,9 +227,48 @@ pub struct CliArgs {
     )]
     pub interfaces: Vec<IpAddr>,
 
+    /// Listen on all the addresses of the network interface with this name, e.g. eth0 (can be
+    /// repeated)
+    ///
+    /// The printed URLs follow the changes of its addresses, e.g. after a DHCP renewal. On Linux,
+    /// the new addresses are also listened on without restarting.
+    #[structopt(
+        long = "interface",
+        number_of_values = 1,
+        conflicts_with = "interfaces"
+    )]
+    pub network_interfaces: Vec<String>,
+
+    /// Ask the local router to forward the port to miniserve with UPnP, and print the URL it can
+    /// be reached at from the internet
+    ///
+    /// The same port of the router is forwarded, until miniserve stops.
+    #[structopt(long = "upnp-forward")]
+    pub upnp_forward: bool,
+
+    /// Listen on this Unix domain socket, e.g. behind a reverse proxy
+    ///
+    /// TCP connections are then only accepted on the interfaces given explicitly with --interfaces.
+    /// Connections on the socket always use plain HTTP.
+    #[cfg(unix)]
+    #[structopt(long = "listen-unix", parse(from_os_str))]
+    pub listen_unix: Option<PathBuf>,
+
+    /// Permissions of the Unix domain socket, in octal (e.g. 660)
+    #[cfg(unix)]
+    #[structopt(
+        long = "listen-unix-mode",
+        requires = "listen-unix",
+        parse(try_from_str = parse_file_mode)
+    )]
+    pub listen_unix_mode: Option<u32>,
+
     /// Set authentication. Currently supported formats:
     /// username:password, username:sha256:hash, username:sha512:hash
     /// (e.g. joe:123, joe:sha256:a665a45920422f9d417e4867efdc4fb8a04a1f3fff1fa07e998e86f7f7a27ae3)
+    ///
+    /// These credentials allow modifications such as uploads, unless their user is given with
+    /// --auth-read-only.
     #[structopt(
         short = "a",
         long = "auth",
@@ -55,6 +277,140 @@ pub struct CliArgs {
     )]
     pub auth: Vec<auth::RequiredAuth>,
 
+    /// Only allow this --auth user to read, without modifications such as uploads (can be
+    /// repeated)
+    #[structopt(long = "auth-read-only", number_of_values = 1)]
+    pub auth_read_only: Vec<String>,
+
+    /// Accept this token in the "Authorization: Bearer <token>" header (can be repeated)
+    ///
+    /// Meant for scripts which should not embed a username and password. Append @ro to only
+    /// allow reading with the token, or @rw to also allow modifications (e.g. 8f4e2a@ro), which
+    /// is the default. Tokens can't end with @ro or @rw otherwise.
+    #[structopt(
+        long = "api-token",
+        parse(try_from_str = parse_api_token),
+        number_of_values = 1
+    )]
+    pub api_tokens: Vec<auth::ApiToken>,
+
+    /// Use a login page with session cookies instead of HTTP basic authentication popups
+    ///
+    /// The credentials set with --auth are used to log in. Basic authentication is still accepted
+    /// for scripted access.
+    #[structopt(long = "login-form")]
+    pub login_form: bool,
+
+    /// Lifetime of login sessions in seconds
+    #[structopt(long = "session-lifetime", default_value = "86400")]
+    pub session_lifetime: u64,
+
+    /// Number of consecutive failed authentication attempts after which a client is locked out
+    ///
+    /// Set to 0 to disable lockouts.
+    #[structopt(long = "auth-max-failures", default_value = "5")]
+    pub auth_max_failures: u32,
+
+    /// Duration in seconds of the first lockout, which doubles with every further failure
+    #[structopt(long = "auth-lockout", default_value = "60")]
+    pub auth_lockout: u64,
+
+    /// Apply the restrictions of the .miniserve.toml files of the served directories
+    ///
+    /// These files can require authentication, only allow some users, hide their directory from
+    /// listings or disable uploads, e.g. `users = ["joe"]` or `uploads = false`. Their
+    /// restrictions also apply to the subdirectories.
+    #[structopt(long = "dir-config")]
+    pub dir_config: bool,
+
+    /// Let users without credentials read the directories which don't require authentication
+    ///
+    /// Credentials are then only asked for in the directories whose .miniserve.toml requires
+    /// authentication, and to modify files.
+    #[structopt(long = "allow-anonymous", requires = "dir-config")]
+    pub allow_anonymous: bool,
+
+    /// Let users without credentials download the files and browse the directories
+    ///
+    /// Credentials are still required to upload or modify files, and for the admin page, the
+    /// metrics, the editor and the creation of share links.
+    #[structopt(long = "public-read")]
+    pub public_read: bool,
+
+    /// Serve each authenticated user their own directory, named after them in the served
+    /// directory
+    ///
+    /// The directories are created at startup, and their users can't see the rest of the served
+    /// directory.
+    #[structopt(
+        long = "user-dirs",
+        requires = "auth",
+        conflicts_with_all = &["public-read", "allow-anonymous", "share-links", "mounts", "vhosts", "from-archive", "s3-bucket"]
+    )]
+    pub user_dirs: bool,
+
+    /// Log every failed authentication attempt with the client IP to this file
+    ///
+    /// The format is suitable for fail2ban.
+    #[structopt(long = "auth-fail2ban-log", parse(from_os_str))]
+    pub auth_fail2ban_log: Option<PathBuf>,
+
+    /// Serve another directory or file under a route, e.g. /docs=/srv/docs (can be repeated)
+    ///
+    /// The settings of the main directory are used, unless others are appended after @ among
+    /// upload, tar, tar-gz, zip, hidden and dirs-first (e.g. /builds=/var/builds@upload,zip).
+    #[structopt(long = "mount", number_of_values = 1)]
+    pub mounts: Vec<Mount>,
+
+    /// Serve another directory or file to the requests for a host name, e.g.
+    /// docs.example.com=/srv/docs (can be repeated)
+    ///
+    /// Requests for other host names are served the main directory and the mounts.
+    #[structopt(long = "vhost", number_of_values = 1)]
+    pub vhosts: Vec<VirtualHost>,
+
+    /// Only allow clients from this IP address or CIDR range, e.g. 192.168.1.0/24 (can be
+    /// repeated)
+    #[structopt(long = "allow-ip", number_of_values = 1)]
+    pub allow_ip: Vec<IpNet>,
+
+    /// Deny clients from this IP address or CIDR range, even if allowed by --allow-ip (can be
+    /// repeated)
+    #[structopt(long = "deny-ip", number_of_values = 1)]
+    pub deny_ip: Vec<IpNet>,
+
+    /// Trust the Forwarded and X-Forwarded-* headers of the requests from this reverse proxy, given
+    /// as an IP address or CIDR range (can be repeated)
+    ///
+    /// The client address and the URL they give are then used for the access log, --allow-ip,
+    /// --deny-ip, the authentication lockout and the generated links.
+    #[structopt(long = "trusted-proxy", number_of_values = 1)]
+    pub trusted_proxies: Vec<IpNet>,
+
+    /// Expect the PROXY protocol header of HAProxy and other TCP load balancers on every
+    /// connection, and identify the clients with the address it gives
+    ///
+    /// Versions 1 and 2 are accepted. Connections without the header are closed.
+    #[structopt(long = "proxy-protocol")]
+    pub proxy_protocol: bool,
+
+    /// What to do with clients which aren't allowed
+    ///
+    /// "drop" closes their connections without answering.
+    #[structopt(
+        long = "ip-deny-action",
+        default_value = "forbidden",
+        possible_values = &DenyAction::VARIANTS
+    )]
+    pub ip_deny_action: DenyAction,
+
+    /// Enable share links granting temporary access to a file or directory without credentials
+    ///
+    /// Authenticated users can generate them from the listing or from /__share?path=<path>.
+    /// Share links are invalidated when miniserve restarts.
+    #[structopt(long = "enable-share-links")]
+    pub share_links: bool,
+
     /// Generate a random 6-hexdigit route
     #[structopt(long = "random-route")]
     pub random_route: bool,
@@ -67,6 +423,56 @@ pub struct CliArgs {
     #[structopt(short = "H", long = "hidden")]
     pub hidden: bool,
 
+    /// Redirect the paths which don't exist to the entries whose paths only differ by case
+    ///
+    /// E.g. /ReadMe.TXT is redirected to /readme.txt. When several entries match, the one with
+    /// the exact case is chosen, then the first one in byte order.
+    #[structopt(long = "case-insensitive-paths")]
+    pub case_insensitive_paths: bool,
+
+    /// What to do with the requests of directories without a trailing slash
+    ///
+    /// "redirect" sends /dir to /dir/, "serve" lists the directory at /dir as well.
+    #[structopt(
+        long = "directory-slash",
+        default_value = "redirect",
+        possible_values = &DirectorySlash::VARIANTS
+    )]
+    pub directory_slash: DirectorySlash,
+
+    /// Redirect the paths with consecutive slashes, like /a//b, to the path with single slashes
+    #[structopt(long = "merge-slashes")]
+    pub merge_slashes: bool,
+
+    /// Status of the redirects to the canonical URLs: 301, 302, 307 or 308
+    ///
+    /// 301 and 308 are permanent, and kept by the clients, 302 and 307 are temporary. 307 and 308
+    /// keep the method of the requests.
+    #[structopt(
+        long = "redirect-status",
+        default_value = "302",
+        parse(try_from_str = parse_redirect_status)
+    )]
+    pub redirect_status: StatusCode,
+
+    /// Hide the entries matching the gitignore-style patterns of this file
+    ///
+    /// The patterns are relative to the served directory. Ignored entries are left out of the
+    /// listings, archives, feeds and manifests, and can't be requested unless --serve-ignored is
+    /// given.
+    #[structopt(long = "ignore-file", parse(from_os_str))]
+    pub ignore_file: Option<PathBuf>,
+
+    /// Hide the entries matching the gitignore-style patterns of the .miniserveignore files
+    ///
+    /// The patterns of a .miniserveignore file apply to its directory and subdirectories.
+    #[structopt(long = "enable-ignore-files")]
+    pub enable_ignore_files: bool,
+
+    /// Still serve the entries hidden by --ignore-file or --enable-ignore-files by direct URL
+    #[structopt(long = "serve-ignored")]
+    pub serve_ignored: bool,
+
     /// Default color scheme
     #[structopt(
         short = "c",
@@ -87,7 +493,7 @@ pub struct CliArgs {
     )]
     pub color_scheme_dark: String,
 
-    /// Enable QR code display
+    /// Enable QR code display, for the server and for each entry of the listing
     #[structopt(short = "q", long = "qrcode")]
     pub qrcode: bool,
 
@@ -96,9 +502,286 @@ pub struct CliArgs {
     pub file_upload: bool,
 
     /// Enable overriding existing files during file upload
+    ///
+    /// Same as --on-duplicate-files=overwrite.
     #[structopt(short = "o", long = "overwrite-files")]
     pub overwrite_files: bool,
 
+    /// What to do when an uploaded file already exists
+    ///
+    /// "rename" stores the upload as "file (1).txt", "skip" keeps the existing file. Uploads of
+    /// existing files are refused with "error", the default.
+    #[structopt(
+        long = "on-duplicate-files",
+        possible_values = &DuplicateFilePolicy::VARIANTS,
+        conflicts_with = "overwrite-files"
+    )]
+    pub on_duplicate_files: Option<DuplicateFilePolicy>,
+
+    /// Only show the upload form, without listing or serving the uploaded files
+    ///
+    /// Uploads of existing files are renamed, and the archives and manifests of the served
+    /// directory are refused, so that nothing can be learnt about them.
+    #[structopt(
+        long = "upload-only",
+        requires = "file-upload",
+        conflicts_with_all = &[
+            "overwrite-files", "live-updates", "share-links", "feed-enabled", "opds-enabled",
+            "rename-enabled",
+            "edit-enabled", "extract-enabled", "enable-torrent", "enable-hls",
+            "enable-resize", "enable-content-search", "enable-duplicates", "enable-disk-usage",
+            "case-insensitive-paths"
+        ]
+    )]
+    pub upload_only: bool,
+
+    /// Delete the uploaded files once they are older than this, e.g. 30m, 24h or 7d
+    ///
+    /// Only the files uploaded, created or extracted through miniserve are deleted. They are
+    /// tagged in the .miniserve-uploads.json file of the served directory, so that they are still
+    /// deleted after a restart. Resumable uploads which weren't resumed for as long are deleted
+    /// as well.
+    #[structopt(
+        long = "upload-ttl",
+        requires = "file-upload",
+        parse(try_from_str = parse_duration)
+    )]
+    pub upload_ttl: Option<Duration>,
+
+    /// Limit the rate at which each download is sent, e.g. 10MBps or 500KiBps
+    ///
+    /// This applies to files, archives and listings, for every connection on its own.
+    #[structopt(long = "throttle", parse(try_from_str = parse_rate))]
+    pub throttle: Option<u64>,
+
+    /// Limit the rate at which each upload is received, e.g. 1MBps
+    #[structopt(long = "throttle-upload", parse(try_from_str = parse_rate))]
+    pub throttle_upload: Option<u64>,
+
+    /// Limit the rate of all the downloads and uploads together, e.g. 50MBps
+    #[structopt(long = "throttle-total", parse(try_from_str = parse_rate))]
+    pub throttle_total: Option<u64>,
+
+    /// Maximum number of simultaneous connections
+    ///
+    /// Further connections are closed right away until others are closed.
+    #[structopt(long = "max-connections")]
+    pub max_connections: Option<usize>,
+
+    /// Maximum size in bytes of each uploaded file
+    #[structopt(long = "max-upload-size", requires = "file-upload")]
+    pub max_upload_size: Option<u64>,
+
+    /// Maximum number of bytes which can be uploaded in total while miniserve is running
+    #[structopt(long = "upload-quota", requires = "file-upload")]
+    pub upload_quota: Option<u64>,
+
+    /// Maximum number of bytes an --auth user can store, e.g. alice=5GB (can be repeated)
+    ///
+    /// With --user-dirs, the files already in their directory count towards it, otherwise the
+    /// files they uploaded since miniserve started. Exceeding uploads are refused with "507
+    /// Insufficient Storage".
+    #[structopt(
+        long = "quota",
+        parse(try_from_str = parse_quota),
+        number_of_values = 1,
+        requires_all = &["auth", "file-upload"]
+    )]
+    pub quotas: Vec<(String, u64)>,
+
+    /// Extract uploaded .zip, .tar and .tar.gz archives into the upload directory
+    ///
+    /// The archive itself is not kept.
+    #[structopt(long = "upload-auto-extract", requires = "file-upload")]
+    pub upload_auto_extract: bool,
+
+    /// Maximum number of bytes which can be extracted from a single uploaded archive
+    #[structopt(long = "max-extracted-size", default_value = "1073741824")]
+    pub max_extracted_size: u64,
+
+    /// Keep up to this number of previous versions of the files overwritten by uploads
+    ///
+    /// The previous content of file.txt is kept as file.txt.~1~, file.txt.~2~ and so on, the
+    /// oldest versions are deleted. Files are only overwritten with --on-duplicate-files=overwrite.
+    #[structopt(long = "keep-versions", requires = "file-upload")]
+    pub keep_versions: Option<usize>,
+
+    /// Drop uploads whose content is already stored under their name, instead of storing it again
+    ///
+    /// Such uploads succeed with the "duplicate" Upload-Result header, even with
+    /// --on-duplicate-files=error.
+    #[structopt(long = "upload-dedup", requires = "file-upload")]
+    pub upload_dedup: bool,
+
+    /// Store uploads identical to another file of their directory as hard links to it
+    #[structopt(long = "dedup-hard-links", requires = "upload-dedup")]
+    pub dedup_hard_links: bool,
+
+    /// POST a JSON event to this URL whenever a file is uploaded
+    ///
+    /// The event contains the action, the path and size of the file, the client IP and the user.
+    #[structopt(long = "on-change-webhook")]
+    pub on_change_webhook: Option<http::Uri>,
+
+    /// POST a JSON event to this URL whenever a file was entirely downloaded
+    ///
+    /// The event contains the path of the file, the bytes sent, the duration of the download in
+    /// milliseconds, the client IP and the user.
+    #[structopt(long = "on-download-webhook")]
+    pub on_download_webhook: Option<http::Uri>,
+
+    /// Run this command on an event (e.g. --hook 'upload=notify-send "New upload"')
+    /// (can be repeated)
+    ///
+    /// The events are startup, download, upload and auth-failure. The command is run through the
+    /// shell in the background, with the event in the MINISERVE_EVENT environment variable, and
+    /// its details in MINISERVE_PATH, MINISERVE_SIZE, MINISERVE_CLIENT_IP, MINISERVE_USER and
+    /// MINISERVE_ADDRESSES when they are known.
+    #[structopt(long = "hook", parse(try_from_str = parse_hook), number_of_values = 1)]
+    pub hooks: Vec<Hook>,
+
+    /// Count the downloads and the bytes sent of each file, and keep the counts in this file
+    ///
+    /// The most downloaded files are listed on the status page of --enable-admin.
+    #[structopt(long = "download-stats", parse(from_os_str))]
+    pub download_stats: Option<PathBuf>,
+
+    /// Show the number of downloads of each file in the listings
+    #[structopt(long = "show-download-counts", requires = "download-stats")]
+    pub show_download_counts: bool,
+
+    /// Read the EXIF metadata of the photos in the listings
+    ///
+    /// The listings can then be sorted and grouped by the date the photos were taken, and show
+    /// their camera and resolution when hovering them.
+    #[structopt(long = "photo-metadata")]
+    pub photo_metadata: bool,
+
+    /// Seconds to let running transfers finish when shutting down on SIGINT or SIGTERM
+    ///
+    /// No new connections are accepted in the meantime. A second signal stops at once.
+    #[structopt(long = "shutdown-grace-period", default_value = "30")]
+    pub shutdown_grace_period: u64,
+
+    /// Stop once no client was connected for this long, e.g. 30m or 2h
+    #[structopt(long = "shutdown-after-idle", parse(try_from_str = parse_duration))]
+    pub shutdown_after_idle: Option<Duration>,
+
+    /// Stop once files were downloaded this many times in total, e.g. 1 to share a file once
+    ///
+    /// Only downloads of whole files count, and running transfers can finish.
+    #[structopt(long = "max-downloads")]
+    pub max_downloads: Option<u64>,
+
+    /// Seconds given to clients to send the headers of their request, 0 to wait forever
+    ///
+    /// Clients which are too slow are disconnected, so that they can't hold connections forever.
+    #[structopt(long = "client-timeout", default_value = "5")]
+    pub client_timeout: u64,
+
+    /// Seconds idle connections are kept open for more requests, 0 to close them after each
+    /// request
+    #[structopt(long = "keep-alive", default_value = "5")]
+    pub keep_alive: u64,
+
+    /// Answer with 408 Request Timeout to the requests which take longer than this to be
+    /// received and handled, e.g. 30s or 2h
+    ///
+    /// This includes the time taken to receive uploads, but not to send downloads. Requests are
+    /// never timed out by default.
+    #[structopt(long = "request-timeout", parse(try_from_str = parse_duration))]
+    pub request_timeout: Option<Duration>,
+
+    /// Export tracing spans of the requests, archives and uploads to this OTLP/gRPC endpoint
+    ///
+    /// For instance http://localhost:4317 for a local OpenTelemetry collector or Jaeger.
+    #[cfg(feature = "otlp")]
+    #[structopt(long = "otlp-endpoint")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Expose Prometheus metrics on --metrics-route
+    #[structopt(long = "enable-metrics")]
+    pub metrics_enabled: bool,
+
+    /// Route of the Prometheus metrics
+    #[structopt(long = "metrics-route", default_value = "/__metrics")]
+    pub metrics_route: String,
+
+    /// Let anonymous users read the metrics when authentication is enabled
+    #[structopt(long = "metrics-public")]
+    pub metrics_public: bool,
+
+    /// Enable the admin status page at /__admin
+    ///
+    /// It shows the uptime, the running transfers and the recent requests, and lets users with
+    /// read-write permission switch miniserve to read-only mode. It requires --auth or
+    /// --api-token.
+    #[structopt(long = "enable-admin")]
+    pub admin_enabled: bool,
+
+    /// Run this command on every uploaded file before it is moved into place
+    ///
+    /// The command is run through the shell, with the path of the temporary file as last argument
+    /// and the name of the uploaded file in the MINISERVE_UPLOAD_NAME environment variable.
+    /// A non-zero exit status rejects the upload (e.g. "clamscan --no-summary").
+    #[structopt(long = "upload-hook", requires = "file-upload")]
+    pub upload_hook: Option<String>,
+
+    /// Error message shown when --upload-hook rejects an upload
+    #[structopt(long = "upload-hook-error", default_value = "The upload was rejected")]
+    pub upload_hook_error: String,
+
+    /// Expose an Atom feed of the newest files of the served directory at /feed.xml
+    ///
+    /// The feed of a subdirectory is at /feed.xml?path=/subdirectory.
+    #[structopt(long = "enable-feed")]
+    pub feed_enabled: bool,
+
+    /// Expose an OPDS catalog of the ebooks of the served directory at /opds, for ebook readers
+    ///
+    /// The catalog lists the subdirectories and the EPUB, PDF, MOBI, AZW3, FB2, DjVu, CBZ and
+    /// CBR files of a directory, e.g. /opds?path=/subdirectory.
+    #[structopt(long = "enable-opds")]
+    pub opds_enabled: bool,
+
+    /// Show the HTML fragments of the directories above and below their listing
+    ///
+    /// The content of the --header-name and --footer-name files of a directory is inserted as is,
+    /// so only enable this when the users who can upload files are trusted.
+    #[structopt(long = "enable-fragments")]
+    pub fragments_enabled: bool,
+
+    /// Name of the files shown above the listing of their directory with --enable-fragments
+    #[structopt(long = "header-name", default_value = "HEADER.html")]
+    pub header_name: String,
+
+    /// Name of the files shown below the listing of their directory with --enable-fragments
+    #[structopt(long = "footer-name", default_value = "FOOTER.html")]
+    pub footer_name: String,
+
+    /// Enable renaming and moving files and directories
+    ///
+    /// Users with write access can rename entries from the listing or with POST /__rename.
+    #[structopt(long = "enable-rename")]
+    pub rename_enabled: bool,
+
+    /// Enable editing small text files in the browser
+    ///
+    /// Users with write access get an edit link next to text files smaller than --max-edit-size.
+    #[structopt(long = "enable-edit")]
+    pub edit_enabled: bool,
+
+    /// Enable extracting the .zip, .tar and .tar.gz archives of the served directory next to them
+    ///
+    /// Users with write access get an extract link next to the archives, or can POST /__extract.
+    /// The archives are kept.
+    #[structopt(long = "enable-extract", requires = "file-upload")]
+    pub extract_enabled: bool,
+
+    /// Maximum size in bytes of files which can be edited in the browser
+    #[structopt(long = "max-edit-size", default_value = "1048576")]
+    pub max_edit_size: u64,
+
     /// Enable uncompressed tar archive generation
     #[structopt(short = "r", long = "enable-tar")]
     pub enable_tar: bool,
@@ -114,6 +797,80 @@ pub struct CliArgs {
     #[structopt(short = "z", long = "enable-zip")]
     pub enable_zip: bool,
 
+    /// Enable torrent generation for the files, with this server as web seed
+    ///
+    /// The torrents are downloaded with ?download=torrent. Every file is read once to hash its
+    /// pieces.
+    #[structopt(long = "enable-torrent")]
+    pub enable_torrent: bool,
+
+    /// Enable manifests of the files of the directories, in the format of sha256sum
+    ///
+    /// The manifest of a directory and its subdirectories is downloaded with ?manifest=sha256.
+    /// The hashes are kept in memory until the files change.
+    #[structopt(long = "enable-manifest")]
+    pub enable_manifest: bool,
+
+    /// Enable the report of the duplicate files of a directory with ?view=duplicates
+    ///
+    /// The files of the directory and its subdirectories are grouped by size, then by SHA-256
+    /// hash. The hashes are kept in memory until the files change, like with --enable-manifest.
+    #[structopt(long = "enable-duplicates")]
+    pub enable_duplicates: bool,
+
+    /// Enable the disk usage of the directories with ?view=usage
+    ///
+    /// The sizes of the entries of a directory are shown as a treemap. They are kept in memory for
+    /// two minutes, for the directory and all its subdirectories.
+    #[structopt(long = "enable-disk-usage")]
+    pub enable_disk_usage: bool,
+
+    /// Enable the last lines of the files with ?tail=100, followed with ?tail=100&follow=1
+    ///
+    /// Followed files are sent as they grow, like with `tail -f`, until the client disconnects.
+    #[structopt(long = "enable-tail")]
+    pub enable_tail: bool,
+
+    /// Enable HLS streams of the videos with ?hls=index.m3u8, transcoded with ffmpeg
+    ///
+    /// This lets browsers and players which can't play the container or the codecs of a video
+    /// stream it anyway. Each video is transcoded once, into the --hls-cache directory.
+    #[structopt(long = "enable-hls")]
+    pub enable_hls: bool,
+
+    /// Command transcoding the videos to HLS instead of ffmpeg, run through the shell
+    ///
+    /// The video is in $MINISERVE_INPUT. The command has to write index.m3u8 and the segments
+    /// it lists into $MINISERVE_OUTPUT_DIR.
+    #[structopt(long = "transcode-cmd", requires = "enable-hls")]
+    pub transcode_cmd: Option<String>,
+
+    /// Directory caching the HLS streams of the videos [default: miniserve-hls in the temporary
+    /// directory]
+    #[structopt(long = "hls-cache", requires = "enable-hls", parse(from_os_str))]
+    pub hls_cache: Option<PathBuf>,
+
+    /// Enable downscaled copies of the images with ?width=800, for quick viewing
+    ///
+    /// The width is rounded up to a multiple of 100 pixels, up to 4000. JPEG, PNG, GIF, WebP,
+    /// BMP and TIFF images can be resized, and the copies are kept in the --resize-cache
+    /// directory.
+    #[structopt(long = "enable-resize")]
+    pub enable_resize: bool,
+
+    /// Directory caching the downscaled copies of the images [default: miniserve-resized in the
+    /// temporary directory]
+    #[structopt(long = "resize-cache", requires = "enable-resize", parse(from_os_str))]
+    pub resize_cache: Option<PathBuf>,
+
+    /// Enable searching the content of the text files with ?q=, e.g. /docs/?q=invoice
+    ///
+    /// The text files of up to 4 MB are indexed in the background when miniserve starts, and
+    /// again every 10 minutes. Results are limited to the files of the searched directory. The
+    /// file names can be searched fuzzily instead with &mode=fuzzy, e.g. ?q=invc&mode=fuzzy.
+    #[structopt(long = "enable-content-search")]
+    pub enable_content_search: bool,
+
     /// List directories first
     #[structopt(short = "D", long = "dirs-first")]
     pub dirs_first: bool,
@@ -122,10 +879,67 @@ pub struct CliArgs {
     #[structopt(short = "t", long = "title")]
     pub title: Option<String>,
 
+    /// Send this MIME type for the files with this extension (e.g. --mime-type wasm=application/wasm)
+    /// (can be repeated)
+    #[structopt(
+        long = "mime-type",
+        parse(try_from_str = parse_mime_type),
+        number_of_values = 1
+    )]
+    pub mime_type: Vec<(String, mime::Mime)>,
+
+    /// Read MIME types of file extensions from this file, in the format of /etc/mime.types
+    ///
+    /// Each line holds a MIME type followed by its extensions, e.g. "text/plain gcode nc".
+    /// Those given with --mime-type take precedence.
+    #[structopt(long = "mime-types-file", parse(from_os_str))]
+    pub mime_types_file: Option<PathBuf>,
+
+    /// Send this page for the responses with this error status (e.g. --error-page 404=404.html)
+    /// (can be repeated)
+    ///
+    /// The pages are read on startup, and replace the built-in error pages.
+    #[structopt(
+        long = "error-page",
+        parse(try_from_str = parse_error_page),
+        number_of_values = 1
+    )]
+    pub error_page: Vec<(StatusCode, PathBuf)>,
+
+    /// Send the files matching these glob patterns as downloads instead of displaying them
+    /// (e.g. --force-download '*.html,*.svg') (can be repeated)
+    ///
+    /// This keeps untrusted HTML or SVG files from running scripts under the origin of miniserve.
+    #[structopt(long = "force-download", number_of_values = 1)]
+    pub force_download: Vec<Globs>,
+
+    /// Set the Cache-Control header of the files matching glob patterns (can be repeated)
+    ///
+    /// For instance --cache-control '*.css,*.js=max-age=86400' --cache-control '*.html=no-cache'.
+    /// Patterns with a slash are matched against the path of the file, the others against its name.
+    /// The first matching rule applies.
+    #[structopt(
+        long = "cache-control",
+        parse(try_from_str = parse_cache_control),
+        number_of_values = 1
+    )]
+    pub cache_control: Vec<CacheControlRule>,
+
     /// Set custom header for responses
     #[structopt(long = "header", parse(try_from_str = parse_header), number_of_values = 1)]
     pub header: Vec<HeaderMap>,
 
+    /// Send security headers: X-Content-Type-Options, X-Frame-Options, Referrer-Policy and a
+    /// Content-Security-Policy allowing the inline scripts of the listing
+    ///
+    /// Headers given with --header take precedence. Combine with --hsts when TLS is enabled.
+    #[structopt(long = "security-headers")]
+    pub security_headers: bool,
+
+    /// Send this Content-Security-Policy header instead of the one of --security-headers
+    #[structopt(long = "csp", parse(try_from_str = HeaderValue::from_str))]
+    pub csp: Option<HeaderValue>,
+
     /// Hide version footer
     #[structopt(short = "F", long = "hide-version-footer")]
     pub hide_version_footer: bool,
@@ -134,6 +948,17 @@ pub struct CliArgs {
     #[structopt(long = "print-completions", value_name = "shell", possible_values = &structopt::clap::Shell::variants())]
     pub print_completions: Option<structopt::clap::Shell>,
 
+    /// Print a service definition running miniserve with the other arguments, then exit
+    ///
+    /// "systemd" prints a unit running as a dynamic user in a sandbox, "launchd" the property
+    /// list of a macOS agent.
+    #[structopt(
+        long = "print-service",
+        value_name = "manager",
+        possible_values = &ServiceManager::VARIANTS
+    )]
+    pub print_service: Option<ServiceManager>,
+
     /// TLS certificate to use
     #[cfg(feature = "tls")]
     #[structopt(long = "tls-cert", requires = "tls-key")]
@@ -143,6 +968,94 @@ pub struct CliArgs {
     #[cfg(feature = "tls")]
     #[structopt(long = "tls-key", requires = "tls-cert")]
     pub tls_key: Option<PathBuf>,
+
+    /// Require clients to present a TLS certificate signed by this CA
+    #[cfg(feature = "tls")]
+    #[structopt(long = "tls-client-ca", requires = "tls-cert")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// Oldest TLS version accepted, e.g. 1.3 to refuse TLS 1.2
+    #[cfg(feature = "tls")]
+    #[structopt(
+        long = "tls-min-version",
+        default_value = "1.2",
+        possible_values = &TlsVersion::VARIANTS
+    )]
+    pub tls_min_version: TlsVersion,
+
+    /// Only accept these TLS cipher suites, instead of all the secure ones
+    ///
+    /// e.g. --tls-ciphers TLS13_AES_256_GCM_SHA384,TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384
+    #[cfg(feature = "tls")]
+    #[structopt(long = "tls-ciphers", use_delimiter = true)]
+    pub tls_ciphers: Vec<String>,
+
+    /// Generate a self-signed TLS certificate at startup, and print its fingerprint
+    ///
+    /// With --tls-cert and --tls-key, the certificate is kept in these files and only generated
+    /// if they don't exist, so that it stays the same across restarts.
+    #[cfg(feature = "tls")]
+    #[structopt(long = "tls-self-signed", conflicts_with = "acme-domains")]
+    pub tls_self_signed: bool,
+
+    /// Obtain and renew the TLS certificate for this domain from Let's Encrypt (can be repeated)
+    ///
+    /// The HTTP-01 challenge is answered on --acme-http-port, which must be reachable from the
+    /// internet. Using this option means agreeing to the terms of service of the ACME server.
+    #[cfg(feature = "tls")]
+    #[structopt(
+        long = "acme-domain",
+        number_of_values = 1,
+        requires = "acme-cache-dir",
+        conflicts_with = "tls-cert"
+    )]
+    pub acme_domains: Vec<String>,
+
+    /// Directory where the ACME account key and the certificates are kept
+    #[cfg(feature = "tls")]
+    #[structopt(long = "acme-cache-dir", parse(from_os_str))]
+    pub acme_cache_dir: Option<PathBuf>,
+
+    /// Contact email address given to the ACME server, e.g. for expiration notices
+    #[cfg(feature = "tls")]
+    #[structopt(long = "acme-email")]
+    pub acme_email: Option<String>,
+
+    /// Directory URL of the ACME server
+    #[cfg(feature = "tls")]
+    #[structopt(
+        long = "acme-directory",
+        default_value = "https://acme-v02.api.letsencrypt.org/directory"
+    )]
+    pub acme_directory: String,
+
+    /// Port on which the ACME HTTP-01 challenges are answered
+    #[cfg(feature = "tls")]
+    #[structopt(long = "acme-http-port", default_value = "80")]
+    pub acme_http_port: u16,
+
+    /// Also serve plain HTTP on this port when TLS is enabled
+    #[cfg(feature = "tls")]
+    #[structopt(long = "http-port")]
+    pub http_port: Option<u16>,
+
+    /// Redirect plain HTTP requests to HTTPS with "301 Moved Permanently"
+    #[cfg(feature = "tls")]
+    #[structopt(long = "redirect-to-https", requires = "http-port")]
+    pub redirect_to_https: bool,
+
+    /// Send the Strict-Transport-Security header with this max-age in seconds over HTTPS
+    ///
+    /// Browsers then use HTTPS for this host until max-age expires, so only enable this once
+    /// HTTPS works reliably.
+    #[cfg(feature = "tls")]
+    #[structopt(long = "hsts")]
+    pub hsts_max_age: Option<u64>,
+
+    /// Authenticate clients as the --auth user named like the common name (CN) of their certificate
+    #[cfg(feature = "tls")]
+    #[structopt(long = "tls-client-cn-auth", requires = "tls-client-ca")]
+    pub tls_client_cn_auth: bool,
 }
 
 /// Checks wether an interface is valid, i.e. it can be parsed into an IP address
@@ -150,6 +1063,87 @@ fn parse_interface(src: &str) -> Result<IpAddr, std::net::AddrParseError> {
     src.parse::<IpAddr>()
 }
 
+/// Parse a positive number of bytes, with an optional unit like 500KB, 10MB or 1MiB
+fn parse_byte_count(src: &str) -> Option<u64> {
+    let split = src.find(|c: char| !c.is_ascii_digit()).unwrap_or(src.len());
+    let (number, unit) = src.split_at(split);
+    let multiplier = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => return None,
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .filter(|&count| count > 0)
+}
+
+/// Parse a transfer rate in bytes per second, with an optional unit like 500KBps, 10MBps or 1MiBps
+fn parse_rate(src: &str) -> Result<u64, ContextualError> {
+    let rate = src.trim_end_matches("ps").trim_end_matches("/s");
+    parse_byte_count(rate)
+        .ok_or_else(|| ContextualError::ParseError("transfer rate".to_string(), src.to_string()))
+}
+
+/// Parse the storage quota of a user, like alice=5GB
+fn parse_quota(src: &str) -> Result<(String, u64), ContextualError> {
+    let parse_error = || ContextualError::ParseError("quota".to_string(), src.to_string());
+
+    let mut split = src.rsplitn(2, '=');
+    match (split.next(), split.next()) {
+        (Some(size), Some(user)) if !user.is_empty() => {
+            let size = parse_byte_count(size).ok_or_else(parse_error)?;
+            Ok((user.to_string(), size))
+        }
+        _ => Err(parse_error()),
+    }
+}
+
+/// Parse a duration in seconds, with an optional unit like 30s, 15m, 24h or 7d
+fn parse_duration(src: &str) -> Result<Duration, ContextualError> {
+    let parse_error = || ContextualError::ParseError("duration".to_string(), src.to_string());
+
+    let split = src.find(|c: char| !c.is_ascii_digit()).unwrap_or(src.len());
+    let (number, unit) = src.split_at(split);
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(parse_error()),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .filter(|&seconds| seconds > 0)
+        .map(Duration::from_secs)
+        .ok_or_else(parse_error)
+}
+
+/// Parse file permissions given in octal
+#[cfg(unix)]
+fn parse_file_mode(src: &str) -> Result<u32, std::num::ParseIntError> {
+    u32::from_str_radix(src, 8)
+}
+
+/// Split the optional permission suffix from an API token
+fn split_permission(src: &str) -> (&str, auth::AuthPermission) {
+    if let Some(credentials) = src.strip_suffix("@ro") {
+        (credentials, auth::AuthPermission::ReadOnly)
+    } else if let Some(credentials) = src.strip_suffix("@rw") {
+        (credentials, auth::AuthPermission::ReadWrite)
+    } else {
+        (src, auth::AuthPermission::ReadWrite)
+    }
+}
+
 /// Parse authentication requirement
 fn parse_auth(src: &str) -> Result<auth::RequiredAuth, ContextualError> {
     let mut split = src.splitn(3, ':');
@@ -193,6 +1187,21 @@ fn parse_auth(src: &str) -> Result<auth::RequiredAuth, ContextualError> {
     Ok(auth::RequiredAuth {
         username: username.to_owned(),
         password,
+        // Users are only restricted by --auth-read-only
+        permission: auth::AuthPermission::ReadWrite,
+    })
+}
+
+/// Parse API token
+fn parse_api_token(src: &str) -> Result<auth::ApiToken, ContextualError> {
+    let (token, permission) = split_permission(src);
+    if token.is_empty() {
+        return Err(ContextualError::InvalidApiToken);
+    }
+
+    Ok(auth::ApiToken {
+        token: token.to_owned(),
+        permission,
     })
 }
 
@@ -230,14 +1239,15 @@ mod tests {
 
         let password = match encrypt {
             "plain" => Plain(password.to_owned()),
-            "sha256" => Sha256(hex::decode(password.to_owned()).unwrap()),
-            "sha512" => Sha512(hex::decode(password.to_owned()).unwrap()),
+            "sha256" => Sha256(hex::decode(password).unwrap()),
+            "sha512" => Sha512(hex::decode(password).unwrap()),
             _ => panic!("Unknown encryption type"),
         };
 
         auth::RequiredAuth {
             username: username.to_owned(),
             password,
+            permission: AuthPermission::ReadWrite,
         }
     }
 
@@ -245,7 +1255,9 @@ mod tests {
         auth_string, username, password, encrypt,
         case("username:password", "username", "password", "plain"),
         case("username:sha256:abcd", "username", "abcd", "sha256"),
-        case("username:sha512:abcd", "username", "abcd", "sha512")
+        case("username:sha512:abcd", "username", "abcd", "sha512"),
+        case("username:password@ro", "username", "password@ro", "plain"),
+        case("username:pass@word", "username", "pass@word", "plain")
     )]
     fn parse_auth_valid(auth_string: &str, username: &str, password: &str, encrypt: &str) {
         assert_eq!(
@@ -277,4 +1289,85 @@ mod tests {
         let err = parse_auth(auth_string).unwrap_err();
         assert_eq!(format!("{}", err), err_msg.to_owned());
     }
+
+    #[rstest(
+        token_string, token, permission,
+        case("8f4e2a", "8f4e2a", auth::AuthPermission::ReadWrite),
+        case("8f4e2a@ro", "8f4e2a", auth::AuthPermission::ReadOnly),
+        case("8f4e2a@rw", "8f4e2a", auth::AuthPermission::ReadWrite)
+    )]
+    fn parse_api_token_valid(token_string: &str, token: &str, permission: auth::AuthPermission) {
+        assert_eq!(
+            parse_api_token(token_string).unwrap(),
+            auth::ApiToken { token: token.to_owned(), permission },
+        );
+    }
+
+    #[rstest(token_string, case(""), case("@ro"))]
+    fn parse_api_token_invalid(token_string: &str) {
+        let err = parse_api_token(token_string).unwrap_err();
+        assert_eq!(format!("{}", err), "API tokens must not be empty");
+    }
+
+    #[rstest(rate, expected,
+        case("1000", 1000),
+        case("500KBps", 500_000),
+        case("10MBps", 10_000_000),
+        case("10mb/s", 10_000_000),
+        case("1MiBps", 1_048_576),
+        case("2GB", 2_000_000_000)
+    )]
+    fn parse_rate_valid(rate: &str, expected: u64) {
+        assert_eq!(parse_rate(rate).unwrap(), expected);
+    }
+
+    #[rstest(rate,
+        case("0"),
+        case("MBps"),
+        case("10XBps"),
+        case("-1MBps")
+    )]
+    fn parse_rate_invalid(rate: &str) {
+        assert!(parse_rate(rate).is_err());
+    }
+
+    #[rstest(quota, user, expected,
+        case("alice=5GB", "alice", 5_000_000_000),
+        case("bob=100MiB", "bob", 104_857_600),
+        case("a=b=1000", "a=b", 1000)
+    )]
+    fn parse_quota_valid(quota: &str, user: &str, expected: u64) {
+        assert_eq!(parse_quota(quota).unwrap(), (user.to_string(), expected));
+    }
+
+    #[rstest(quota,
+        case("alice"),
+        case("=5GB"),
+        case("alice=0"),
+        case("alice=5XB")
+    )]
+    fn parse_quota_invalid(quota: &str) {
+        assert!(parse_quota(quota).is_err());
+    }
+
+    #[rstest(duration, expected,
+        case("90", 90),
+        case("30s", 30),
+        case("15m", 900),
+        case("24h", 86_400),
+        case("7d", 604_800)
+    )]
+    fn parse_duration_valid(duration: &str, expected: u64) {
+        assert_eq!(parse_duration(duration).unwrap(), Duration::from_secs(expected));
+    }
+
+    #[rstest(duration,
+        case("0"),
+        case("h"),
+        case("1w"),
+        case("-1h")
+    )]
+    fn parse_duration_invalid(duration: &str) {
+        assert!(parse_duration(duration).is_err());
+    }
 }
@@ -1,12 +1,24 @@
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
-use actix_web::http::{header, StatusCode};
-use actix_web::{HttpRequest, HttpResponse};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
 use futures::future::Either;
 use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::fs::File;
 use std::future::{ready, Future};
+use std::io::Write;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use crate::admin;
 use crate::errors::{self, ContextualError};
+use crate::file_op;
+use crate::hooks::{self, EventDetails, HookEvent};
+use crate::proxy;
 use crate::renderer;
+use crate::session;
+use crate::share;
 
 #[derive(Clone, Debug)]
 /// HTTP Basic authentication parameters
@@ -15,6 +27,23 @@ pub struct BasicAuthParams {
     pub password: String,
 }
 
+/// Parameters of the HTTP Bearer authentication scheme
+struct BearerAuthParams {
+    token: String,
+}
+
+impl BearerAuthParams {
+    fn try_from_request(req: &HttpRequest) -> actix_web::Result<Self> {
+        use actix_web::http::header::Header;
+        use actix_web_httpauth::headers::authorization::{Authorization, Bearer};
+
+        let auth = Authorization::<Bearer>::parse(req)?.into_scheme();
+        Ok(Self {
+            token: auth.token().to_string(),
+        })
+    }
+}
+
 impl BasicAuthParams {
     fn try_from_request(req: &HttpRequest) -> actix_web::Result<Self> {
         use actix_web::http::header::Header;
@@ -36,20 +65,267 @@ pub enum RequiredAuthPassword {
     Sha512(Vec<u8>),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Access level granted by a set of credentials
+pub enum AuthPermission {
+    /// Credentials may only be used to list and download files
+    ReadOnly,
+
+    /// Credentials may also be used to modify the served directory (upload, etc.)
+    ReadWrite,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 /// Authentication structure to match `BasicAuthParams` against
 pub struct RequiredAuth {
     pub username: String,
     pub password: RequiredAuthPassword,
+    pub permission: AuthPermission,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// API token accepted in the `Authorization: Bearer` header
+pub struct ApiToken {
+    pub token: String,
+    pub permission: AuthPermission,
+}
+
+#[derive(Clone, Debug)]
+/// Identity of the user who successfully authenticated the current request.
+/// It is stored in the request extensions by the auth middleware.
+pub struct CurrentUser {
+    pub name: String,
+    pub permission: AuthPermission,
+}
+
+impl From<&RequiredAuth> for CurrentUser {
+    fn from(auth: &RequiredAuth) -> Self {
+        CurrentUser {
+            name: auth.username.clone(),
+            permission: auth.permission,
+        }
+    }
+}
+
+/// Name given to users who authenticate with an API token
+const API_TOKEN_USER: &str = "api-token";
+
+#[cfg(feature = "tls")]
+#[derive(Clone, Debug)]
+/// Common name (CN) of the verified certificate presented by a TLS client.
+/// It is stored in the connection data by `extract_client_certificate_name`, which is given to
+/// the extensions of its requests.
+pub struct ClientCertificateName(pub String);
+
+#[cfg(feature = "tls")]
+/// Store the common name of the client certificate of a new TLS connection, if there is one
+pub fn extract_client_certificate_name(
+    conn: &dyn std::any::Any,
+    data: &mut actix_web::dev::Extensions,
+) {
+    use actix_tls::accept::rustls::TlsStream;
+    use actix_web::rt::net::TcpStream;
+    use rustls::Session;
+
+    let name = conn
+        .downcast_ref::<TlsStream<TcpStream>>()
+        .and_then(|stream| stream.get_ref().1.get_peer_certificates())
+        .and_then(|certs| {
+            let cert = certs.first()?;
+            let (_, cert) = x509_parser::parse_x509_certificate(&cert.0).ok()?;
+            let name = cert.subject().iter_common_name().next()?.as_str().ok()?;
+            Some(name.to_string())
+        });
+
+    if let Some(name) = name {
+        data.insert(ClientCertificateName(name));
+    }
+}
+
+/// Return the account of `required_auth` named like the common name of the client certificate, if
+/// any
+#[cfg(feature = "tls")]
+fn get_client_certificate_auth<'a>(
+    req: &HttpRequest,
+    conf: &crate::MiniserveConfig,
+    required_auth: &'a [RequiredAuth],
+) -> Option<&'a RequiredAuth> {
+    if !conf.tls_client_cn_auth {
+        return None;
+    }
+
+    let name = req
+        .extensions()
+        .get::<ClientCertificateName>()
+        .map(|ClientCertificateName(name)| name.clone())?;
+    required_auth.iter().find(|auth| auth.username == name)
+}
+
+#[cfg(not(feature = "tls"))]
+fn get_client_certificate_auth<'a>(
+    _req: &HttpRequest,
+    _conf: &crate::MiniserveConfig,
+    _required_auth: &'a [RequiredAuth],
+) -> Option<&'a RequiredAuth> {
+    None
+}
+
+/// Lockouts never last longer than this, however many attempts failed
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Above this number of tracked clients, the ones which are not locked out are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Failed authentication attempts of a single client
+#[derive(Default)]
+struct AuthFailures {
+    /// Number of consecutive failed attempts
+    count: u32,
+
+    /// The client may not try again before this instant
+    locked_until: Option<Instant>,
+}
+
+/// Tracks failed authentication attempts per client IP to slow down password guessing
+pub struct AuthFailureTracker {
+    /// Number of consecutive failures after which a client is locked out, 0 disables lockouts
+    max_failures: u32,
+
+    /// Duration of the first lockout, which doubles with every further failure
+    lockout: Duration,
+
+    /// File in which every failure is logged, in a format suitable for fail2ban
+    fail2ban_log: Option<Mutex<File>>,
+
+    failures: Mutex<HashMap<IpAddr, AuthFailures>>,
+}
+
+impl AuthFailureTracker {
+    pub fn new(max_failures: u32, lockout: Duration, fail2ban_log: Option<File>) -> Self {
+        Self {
+            max_failures,
+            lockout,
+            fail2ban_log: fail2ban_log.map(Mutex::new),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return how long `ip` is still locked out for, if it is
+    pub fn lockout_remaining(&self, ip: IpAddr) -> Option<Duration> {
+        let failures = self.failures.lock().unwrap();
+        let locked_until = failures.get(&ip)?.locked_until?;
+        locked_until.checked_duration_since(Instant::now())
+    }
+
+    /// Record a failed authentication attempt of `ip` as `username`
+    pub fn record_failure(&self, ip: IpAddr, username: &str) {
+        if let Some(log) = &self.fail2ban_log {
+            let line = format!(
+                "{} miniserve: authentication failure for {:?} from {}",
+                chrono::Utc::now().to_rfc3339(),
+                username,
+                ip
+            );
+            if let Err(e) = writeln!(log.lock().unwrap(), "{}", line) {
+                log::error!("Failed to write to the authentication failure log: {}", e);
+            }
+        }
+
+        if self.max_failures == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap();
+        if failures.len() >= MAX_TRACKED_CLIENTS {
+            failures.retain(|_, f| f.locked_until.is_some_and(|t| t > now));
+        }
+
+        let client = failures.entry(ip).or_default();
+        client.count += 1;
+        if client.count >= self.max_failures {
+            let exponent = (client.count - self.max_failures).min(16);
+            let lockout = (self.lockout * 2u32.pow(exponent)).min(MAX_LOCKOUT);
+            client.locked_until = Some(now + lockout);
+            log::warn!(
+                "Locking out {} for {} seconds after {} failed authentication attempts",
+                ip,
+                lockout.as_secs(),
+                client.count
+            );
+        }
+    }
+
+    /// Forget the failed attempts of `ip` after it authenticated successfully
+    pub fn record_success(&self, ip: IpAddr) {
+        self.failures.lock().unwrap().remove(&ip);
+    }
+}
+
+/// Run the hooks of the authentication failures, when `ip` sent wrong credentials for `username`
+pub fn run_auth_failure_hooks(conf: &crate::MiniserveConfig, ip: IpAddr, username: &str) {
+    let details = EventDetails {
+        client_ip: Some(ip.to_string()),
+        user: Some(username.to_string()),
+        ..EventDetails::default()
+    };
+    hooks::run_hooks(&conf.hooks, HookEvent::AuthFailure, &details);
+}
+
+/// Return the first entry of `required_auth` that `basic_auth` matches, if any
+pub fn get_matching_auth<'a>(
+    basic_auth: &BasicAuthParams,
+    required_auth: &'a [RequiredAuth],
+) -> Option<&'a RequiredAuth> {
+    required_auth.iter().find(|auth| {
+        basic_auth.username == auth.username
+            && compare_password(&basic_auth.password, &auth.password)
+    })
+}
+
+/// Return `true` if `req` targets the favicon or the stylesheet
+fn is_asset_route(req: &HttpRequest, conf: &crate::MiniserveConfig) -> bool {
+    let path = req.path().trim_start_matches('/');
+    path == conf.favicon_route || path == conf.css_route
 }
 
-/// Return `true` if `basic_auth` is matches any of `required_auth`
-pub fn match_auth(basic_auth: BasicAuthParams, required_auth: &[RequiredAuth]) -> bool {
-    required_auth
+/// Return the entry of `api_tokens` matching `bearer_auth`, if any
+fn get_matching_api_token<'a>(
+    bearer_auth: &BearerAuthParams,
+    api_tokens: &'a [ApiToken],
+) -> Option<&'a ApiToken> {
+    api_tokens
         .iter()
-        .any(|RequiredAuth { username, password }| {
-            basic_auth.username == *username && compare_password(&basic_auth.password, password)
-        })
+        .find(|api_token| api_token.token == bearer_auth.token)
+}
+
+/// Return `true` if the user of `req` is allowed to modify the served directory
+pub fn has_write_permission(req: &HttpRequest, conf: &crate::MiniserveConfig) -> bool {
+    if *conf.read_only.get() {
+        return false;
+    }
+    match req.extensions().get::<CurrentUser>() {
+        Some(user) => user.permission == AuthPermission::ReadWrite,
+        None => conf.auth.get().is_empty() && conf.api_tokens.get().is_empty(),
+    }
+}
+
+/// Return `true` if the request would modify the served directory
+pub fn is_write_request(req: &HttpRequest) -> bool {
+    !matches!(*req.method(), Method::GET | Method::HEAD)
+}
+
+/// Return `true` if `req` only reads the served files, which users without credentials can do
+/// with --public-read. The admin page, the metrics, the editor and the creation of share links
+/// still require credentials.
+fn is_public_read_request(req: &HttpRequest, conf: &crate::MiniserveConfig) -> bool {
+    let path = req.path();
+    let restricted = [admin::ADMIN_ROUTE, file_op::EDIT_ROUTE, share::SHARE_ROUTE];
+    !(is_write_request(req)
+        || restricted
+            .iter()
+            .any(|route| path == *route || path.starts_with(&format!("{}/", route)))
+        || (conf.metrics_enabled && path == conf.metrics_route))
 }
 
 /// Return `true` if `basic_auth_pwd` meets `required_auth_pwd`'s requirement
@@ -81,31 +357,150 @@ pub fn get_hash<T: Digest>(text: &str) -> Vec<u8> {
 /// Otherwise, return an error response
 fn handle_auth(req: ServiceRequest) -> Result<ServiceRequest, ServiceResponse> {
     let (req, pl) = req.into_parts();
-    let required_auth = &req.app_data::<crate::MiniserveConfig>().unwrap().auth;
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let required_auth = conf.auth.get();
+    let api_tokens = conf.api_tokens.get();
 
-    if required_auth.is_empty() {
+    if required_auth.is_empty() && api_tokens.is_empty() {
         // auth is disabled by configuration
         return Ok(ServiceRequest::from_parts(req, pl));
-    } else if let Ok(cred) = BasicAuthParams::try_from_request(&req) {
-        if match_auth(cred, required_auth) {
-            return Ok(ServiceRequest::from_parts(req, pl));
+    } else if is_asset_route(&req, conf) || (conf.login_form && session::is_public_route(&req)) {
+        // the login page and its assets have to be reachable by anonymous users
+        return Ok(ServiceRequest::from_parts(req, pl));
+    } else if conf.metrics_enabled && conf.metrics_public && req.path() == conf.metrics_route {
+        return Ok(ServiceRequest::from_parts(req, pl));
+    } else if !is_write_request(&req) {
+        match share::get_shared_access(&req, conf) {
+            Ok(Some(access)) => {
+                req.extensions_mut().insert(access);
+                return Ok(ServiceRequest::from_parts(req, pl));
+            }
+            Ok(None) => {}
+            Err(err) => {
+                let resp = HttpResponse::Gone().body(build_unauthorized_response(
+                    &req,
+                    err,
+                    false,
+                    StatusCode::GONE,
+                ));
+                return Err(ServiceResponse::new(req, resp));
+            }
+        }
+    }
+
+    let failure_tracker = req.app_data::<web::Data<AuthFailureTracker>>().unwrap();
+    let client_ip = proxy::client_ip(&req);
+
+    if let Some(remaining) = client_ip.and_then(|ip| failure_tracker.lockout_remaining(ip)) {
+        let resp = HttpResponse::TooManyRequests()
+            .append_header((header::RETRY_AFTER, remaining.as_secs() + 1))
+            .body(build_unauthorized_response(
+                &req,
+                ContextualError::TooManyAuthFailuresError(remaining.as_secs() + 1),
+                false,
+                StatusCode::TOO_MANY_REQUESTS,
+            ));
+        return Err(ServiceResponse::new(req, resp));
+    }
+
+    let user = if let Ok(cred) = BasicAuthParams::try_from_request(&req) {
+        let auth = get_matching_auth(&cred, &required_auth);
+        if let (None, Some(ip)) = (auth, client_ip) {
+            failure_tracker.record_failure(ip, &cred.username);
+            run_auth_failure_hooks(conf, ip, &cred.username);
+        }
+        auth.map(CurrentUser::from)
+    } else if let Ok(bearer) = BearerAuthParams::try_from_request(&req) {
+        let api_token = get_matching_api_token(&bearer, &api_tokens);
+        if let (None, Some(ip)) = (api_token, client_ip) {
+            failure_tracker.record_failure(ip, API_TOKEN_USER);
+            run_auth_failure_hooks(conf, ip, API_TOKEN_USER);
         }
+        api_token.map(|api_token| CurrentUser {
+            name: API_TOKEN_USER.to_string(),
+            permission: api_token.permission,
+        })
+    } else {
+        get_client_certificate_auth(&req, conf, &required_auth)
+            .or_else(|| session::get_session_auth(&req, conf, &required_auth))
+            .map(CurrentUser::from)
+    };
+
+    if let Some(user) = user {
+        if let Some(ip) = client_ip {
+            failure_tracker.record_success(ip);
+        }
+
+        if user.permission == AuthPermission::ReadOnly && is_write_request(&req) {
+            let resp = HttpResponse::Forbidden().body(build_unauthorized_response(
+                &req,
+                ContextualError::InsufficientAuthPermissionsError,
+                true,
+                StatusCode::FORBIDDEN,
+            ));
+            return Err(ServiceResponse::new(req, resp));
+        }
+
+        req.extensions_mut().insert(user);
+        return Ok(ServiceRequest::from_parts(req, pl));
+    }
+
+    // Anonymous users may read what the .miniserve.toml files leave public, which is checked by
+    // `dir_config::enforce_dir_config` afterwards
+    let has_credentials = BasicAuthParams::try_from_request(&req).is_ok()
+        || BearerAuthParams::try_from_request(&req).is_ok();
+    if conf.allow_anonymous && !has_credentials && !is_write_request(&req) {
+        return Ok(ServiceRequest::from_parts(req, pl));
+    }
+    if conf.public_read && !has_credentials && is_public_read_request(&req, conf) {
+        return Ok(ServiceRequest::from_parts(req, pl));
+    }
+
+    let resp = unauthorized_response(&req);
+    Err(ServiceResponse::new(req, resp))
+}
+
+/// Response asking the client of `req` to authenticate, or sending users to the login page
+pub fn unauthorized_response(req: &HttpRequest) -> HttpResponse {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let required_auth = conf.auth.get();
+
+    if conf.login_form && !required_auth.is_empty() && !is_write_request(req) {
+        // send users who aren't logged in yet to the login page
+        return HttpResponse::SeeOther()
+            .append_header((header::LOCATION, session::login_url(req)))
+            .finish();
     }
 
     // auth failed; render and return the error response
-    let resp = HttpResponse::Unauthorized()
-        .append_header((
+    let mut resp = HttpResponse::Unauthorized();
+    if required_auth.is_empty() {
+        resp.append_header((
+            header::WWW_AUTHENTICATE,
+            header::HeaderValue::from_static("Bearer realm=\"miniserve\""),
+        ));
+    } else if !conf.login_form {
+        resp.append_header((
             header::WWW_AUTHENTICATE,
             header::HeaderValue::from_static("Basic realm=\"miniserve\""),
-        ))
-        .body(build_unauthorized_response(
-            &req,
-            ContextualError::InvalidHttpCredentials,
-            true,
-            StatusCode::UNAUTHORIZED,
         ));
+    }
+    resp.body(build_unauthorized_response(
+        req,
+        ContextualError::InvalidHttpCredentials,
+        true,
+        StatusCode::UNAUTHORIZED,
+    ))
+}
 
-    Err(ServiceResponse::new(req, resp))
+/// Response refusing the access of the user of `req` because of `error`
+pub fn forbidden_response(req: &HttpRequest, error: ContextualError) -> HttpResponse {
+    HttpResponse::Forbidden().body(build_unauthorized_response(
+        req,
+        error,
+        true,
+        StatusCode::FORBIDDEN,
+    ))
 }
 
 pub fn auth_middleware<S>(
@@ -183,7 +578,7 @@ mod tests {
     fn test_get_hash(password: &str, hash_method: &str, hash: &str) {
         let hash_func = get_hash_func(hash_method);
         let expected = hex::decode(hash).expect("Provided hash is not a valid hex code");
-        let received = hash_func(&password.to_owned());
+        let received = hash_func(password);
         assert_eq!(received, expected);
     }
 
@@ -193,17 +588,39 @@ mod tests {
 
         let password = match encrypt {
             "plain" => Plain(password.to_owned()),
-            "sha256" => Sha256(get_hash::<sha2::Sha256>(&password.to_owned())),
-            "sha512" => Sha512(get_hash::<sha2::Sha512>(&password.to_owned())),
+            "sha256" => Sha256(get_hash::<sha2::Sha256>(password)),
+            "sha512" => Sha512(get_hash::<sha2::Sha512>(password)),
             _ => panic!("Unknown encryption type"),
         };
 
         RequiredAuth {
             username: username.to_owned(),
             password,
+            permission: AuthPermission::ReadWrite,
         }
     }
 
+    /// Helper function that returns `true` if `basic_auth` matches any of `required_auth`
+    fn match_auth(basic_auth: BasicAuthParams, required_auth: &[RequiredAuth]) -> bool {
+        get_matching_auth(&basic_auth, required_auth).is_some()
+    }
+
+    #[rstest(
+        token, expected,
+        case("first-token", true),
+        case("second-token", true),
+        case("first", false),
+        case("", false)
+    )]
+    fn test_api_tokens(token: &str, expected: bool) {
+        let api_tokens = [
+            ApiToken { token: "first-token".to_owned(), permission: AuthPermission::ReadWrite },
+            ApiToken { token: "second-token".to_owned(), permission: AuthPermission::ReadOnly },
+        ];
+        let bearer_auth = BearerAuthParams { token: token.to_owned() };
+        assert_eq!(get_matching_api_token(&bearer_auth, &api_tokens).is_some(), expected);
+    }
+
     #[rstest(
         should_pass, param_username, param_password, required_username, required_password, encrypt,
         case(true, "obi", "hello there", "obi", "hello there", "plain"),
@@ -305,4 +722,54 @@ mod tests {
             &account_sample,
         ), false);
     }
+
+    #[rstest]
+    fn failure_tracker_locks_out_after_max_failures() {
+        let tracker = AuthFailureTracker::new(3, Duration::from_secs(60), None);
+        let ip = IpAddr::from([10, 0, 0, 1]);
+
+        tracker.record_failure(ip, "joe");
+        tracker.record_failure(ip, "joe");
+        assert!(tracker.lockout_remaining(ip).is_none());
+
+        tracker.record_failure(ip, "joe");
+        assert!(tracker.lockout_remaining(ip).is_some());
+
+        // Other clients are not affected
+        assert!(tracker.lockout_remaining(IpAddr::from([10, 0, 0, 2])).is_none());
+    }
+
+    #[rstest]
+    fn failure_tracker_lockout_doubles() {
+        let tracker = AuthFailureTracker::new(1, Duration::from_secs(60), None);
+        let ip = IpAddr::from([10, 0, 0, 1]);
+
+        tracker.record_failure(ip, "joe");
+        assert!(tracker.lockout_remaining(ip).unwrap() <= Duration::from_secs(60));
+
+        tracker.record_failure(ip, "joe");
+        assert!(tracker.lockout_remaining(ip).unwrap() > Duration::from_secs(60));
+    }
+
+    #[rstest]
+    fn failure_tracker_resets_on_success() {
+        let tracker = AuthFailureTracker::new(2, Duration::from_secs(60), None);
+        let ip = IpAddr::from([10, 0, 0, 1]);
+
+        tracker.record_failure(ip, "joe");
+        tracker.record_success(ip);
+        tracker.record_failure(ip, "joe");
+        assert!(tracker.lockout_remaining(ip).is_none());
+    }
+
+    #[rstest]
+    fn failure_tracker_can_be_disabled() {
+        let tracker = AuthFailureTracker::new(0, Duration::from_secs(60), None);
+        let ip = IpAddr::from([10, 0, 0, 1]);
+
+        for _ in 0..100 {
+            tracker.record_failure(ip, "joe");
+        }
+        assert!(tracker.lockout_remaining(ip).is_none());
+    }
 }
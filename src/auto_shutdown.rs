@@ -0,0 +1,84 @@
+//! Server stopping itself once it isn't needed anymore, for shares which shouldn't linger: after
+//! no client was connected for `--shutdown-after-idle`, or once files were downloaded
+//! `--max-downloads` times.
+//!
+//! The server is stopped like on SIGINT, so that running transfers can finish within
+//! `--shutdown-grace-period`.
+use actix_web::web;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::throttle::TransferLimits;
+
+/// How often the server is checked for idleness
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Conditions to stop the server
+pub struct AutoShutdown {
+    max_downloads: Option<u64>,
+    downloads: AtomicU64,
+
+    /// Server to stop, once it is running. It is taken when it is stopped.
+    server: Mutex<Option<actix_web::dev::Server>>,
+}
+
+impl AutoShutdown {
+    pub fn new(max_downloads: Option<u64>) -> Self {
+        AutoShutdown {
+            max_downloads,
+            downloads: AtomicU64::new(0),
+            server: Mutex::new(None),
+        }
+    }
+
+    /// Whether the downloads are counted, to stop after `--max-downloads`
+    pub fn counts_downloads(&self) -> bool {
+        self.max_downloads.is_some()
+    }
+
+    /// Set the running server to stop
+    pub fn set_server(&self, server: actix_web::dev::Server) {
+        *self.server.lock().unwrap_or_else(|e| e.into_inner()) = Some(server);
+    }
+
+    /// Stop the server, letting the running transfers finish
+    fn stop(&self, reason: &str) {
+        let server = self.server.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(server) = server {
+            log::warn!("Shutting down, {}", reason);
+            actix_web::rt::spawn(async move { server.stop(true).await });
+        }
+    }
+
+    /// Count a download of a whole file, and stop the server after the last one allowed
+    pub fn record_download(&self) {
+        let downloads = self.downloads.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(max_downloads) = self.max_downloads {
+            if downloads >= max_downloads {
+                self.stop(&format!("the files were downloaded {} times", downloads));
+            }
+        }
+    }
+
+    /// Stop the server once no client was connected for `idle_timeout`
+    pub async fn stop_when_idle(
+        shutdown: web::Data<AutoShutdown>,
+        limits: web::Data<TransferLimits>,
+        idle_timeout: Duration,
+    ) {
+        loop {
+            actix_web::rt::time::sleep(CHECK_INTERVAL).await;
+            if limits
+                .idle_time()
+                .is_some_and(|idle_time| idle_time >= idle_timeout)
+            {
+                shutdown.stop(&format!(
+                    "no client was connected for {} seconds",
+                    idle_timeout.as_secs()
+                ));
+                return;
+            }
+        }
+    }
+}
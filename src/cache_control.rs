@@ -0,0 +1,100 @@
+//! Cache-Control headers set with `--cache-control` for the files matching glob patterns, like
+//! `*.css,*.js=max-age=86400`, so that static sites can be served with proper caching.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use futures::future::TryFutureExt;
+use std::future::Future;
+
+use crate::errors::ContextualError;
+use crate::glob::{self, Globs};
+use crate::MiniserveConfig;
+
+/// Cache-Control header value sent for the files matching any of the patterns
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheControlRule {
+    patterns: Globs,
+
+    value: HeaderValue,
+}
+
+/// Parse a rule like `*.css,*.js=max-age=86400`
+pub fn parse_cache_control(src: &str) -> Result<CacheControlRule, ContextualError> {
+    let parse_error = |cause: &str| {
+        ContextualError::ParseError(format!("cache control rule {}", src), cause.to_string())
+    };
+
+    let (patterns, value) = src
+        .split_once('=')
+        .ok_or_else(|| parse_error("expected <patterns>=<value>"))?;
+    let patterns = patterns.parse::<Globs>()?;
+    let value = HeaderValue::from_str(value.trim())
+        .ok()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| parse_error("invalid header value"))?;
+
+    Ok(CacheControlRule { patterns, value })
+}
+
+/// Set the Cache-Control header of the files matching the rules of `--cache-control`, unless the
+/// handler already set one
+pub fn set_cache_control<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let mut path = glob::request_path(&req, conf);
+    // Directories are served as their index file, if any
+    if path.is_empty() || path.ends_with('/') {
+        if let Some(index) = &conf.index {
+            path.push_str(&index.to_string_lossy());
+        }
+    }
+    let value = conf
+        .cache_control
+        .get()
+        .iter()
+        .find(|rule| rule.patterns.matches(&path))
+        .map(|rule| rule.value.clone());
+
+    srv.call(req).map_ok(move |mut res| {
+        let served = matches!(
+            res.status(),
+            StatusCode::OK | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+        );
+        if let Some(value) = value {
+            if served && !res.headers().contains_key(header::CACHE_CONTROL) {
+                res.headers_mut().insert(header::CACHE_CONTROL, value);
+            }
+        }
+        res
+    })
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn rule_is_parsed() {
+        let rule = parse_cache_control("*.css, *.js=max-age=86400").unwrap();
+        assert_eq!(rule.patterns, "*.css,*.js".parse().unwrap());
+        assert_eq!(rule.value, "max-age=86400");
+    }
+
+    #[rstest(src,
+        case("no-cache"),
+        case("*.css="),
+        case("*.css,=no-cache")
+    )]
+    fn invalid_rules_are_rejected(src: &str) {
+        assert!(parse_cache_control(src).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! Canonical paths of the served entries, which work with the long paths and network shares of
+//! Windows.
+//!
+//! On Windows, `fs::canonicalize` returns verbatim paths like `\\?\C:\dir` or
+//! `\\?\UNC\server\share\dir`, in which `/` isn't a separator, so that joining them with the
+//! paths of URLs fails. They are turned back into `C:\dir` and `\\server\share\dir` when that
+//! doesn't change their meaning, and joined component by component otherwise.
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Longest path which can be used without the verbatim prefix
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// Canonical form of `path`, like `fs::canonicalize`, without the verbatim prefix of Windows
+/// unless it is needed
+pub fn canonicalize<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
+    let path = path.as_ref().canonicalize()?;
+    #[cfg(windows)]
+    let path = simplify(path);
+    Ok(path)
+}
+
+/// Path of `relative_path` in `base`, where the `/` of the relative path are separators even if
+/// `base` is a verbatim path
+pub fn join(base: &Path, relative_path: &Path) -> PathBuf {
+    let mut path = base.to_path_buf();
+    for component in relative_path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => (),
+            component => path.push(component.as_os_str()),
+        }
+    }
+    path
+}
+
+/// `path` without its verbatim prefix, if it is short enough, and if none of its components
+/// would be changed by Windows without the prefix, like names ending with a dot
+#[cfg(windows)]
+fn simplify(path: PathBuf) -> PathBuf {
+    let simplified = match path.to_str() {
+        Some(verbatim) => match verbatim.strip_prefix(r"\\?\") {
+            Some(unc) if unc.starts_with(r"UNC\") => format!(r"\\{}", &unc[4..]),
+            Some(disk) if disk.get(1..3) == Some(r":\") => disk.to_string(),
+            _ => return path,
+        },
+        None => return path,
+    };
+    let is_ambiguous = simplified
+        .split('\\')
+        .skip(1)
+        .any(|name| name.ends_with('.') || name.ends_with(' '));
+    if simplified.len() >= MAX_PATH || is_ambiguous {
+        return path;
+    }
+    PathBuf::from(simplified)
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn relative_paths_are_joined_by_component() {
+        let base = Path::new("served");
+        assert_eq!(join(base, Path::new("/a/b/c.txt")), base.join("a").join("b").join("c.txt"));
+        assert_eq!(join(base, Path::new("a/../b")), base.join("a").join("..").join("b"));
+    }
+
+    #[cfg(windows)]
+    #[rstest(verbatim, expected,
+        case(r"\\?\C:\share\dir", r"C:\share\dir"),
+        case(r"\\?\UNC\server\share\dir", r"\\server\share\dir"),
+        case(r"\\?\C:\share\dir.", r"\\?\C:\share\dir."),
+        case(r"\\?\Volume{0123}\dir", r"\\?\Volume{0123}\dir")
+    )]
+    fn verbatim_prefixes_are_removed(verbatim: &str, expected: &str) {
+        assert_eq!(simplify(PathBuf::from(verbatim)), PathBuf::from(expected));
+    }
+
+    #[cfg(windows)]
+    #[rstest]
+    fn long_paths_keep_their_verbatim_prefix() {
+        let long = format!(r"\\?\C:\{}", "a".repeat(300));
+        assert_eq!(simplify(PathBuf::from(&long)), PathBuf::from(&long));
+    }
+}
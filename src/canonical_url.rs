@@ -0,0 +1,126 @@
+//! Redirects to the canonical URLs of the entries: the directories with a trailing slash, unless
+//! `--directory-slash serve` is given, and the paths without duplicate slashes with
+//! `--merge-slashes`. Their status is chosen with `--redirect-status`, as mirroring tools and
+//! strict clients treat permanent and temporary redirects differently.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::HttpResponse;
+use futures::future::{ready, Either};
+use std::future::Future;
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+use crate::dir_config::requested_entry;
+use crate::errors::ContextualError;
+use crate::MiniserveConfig;
+
+/// What to do with the requests of directories without a trailing slash
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum DirectorySlash {
+    /// Redirect `/dir` to `/dir/`
+    Redirect,
+
+    /// Serve the listing at `/dir` as well
+    Serve,
+}
+
+/// Redirect the requests to the canonical URL of the requested entry
+pub fn redirect_to_canonical_url<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let is_read = matches!(*req.method(), Method::GET | Method::HEAD);
+    let path = req.path();
+    let canonical_path = canonical_path(conf, path);
+
+    if is_read && canonical_path != path {
+        let location = match req.query_string() {
+            "" => canonical_path,
+            query => format!("{}?{}", canonical_path, query),
+        };
+        let resp = redirect(conf, &location);
+        let (req, _) = req.into_parts();
+        Either::Right(ready(Ok(ServiceResponse::new(req, resp))))
+    } else {
+        Either::Left(srv.call(req))
+    }
+}
+
+/// Canonical form of the URL path `path`
+fn canonical_path(conf: &MiniserveConfig, path: &str) -> String {
+    let mut canonical_path = if conf.merge_slashes {
+        merge_slashes(path)
+    } else {
+        path.to_string()
+    };
+    let is_dir = conf.storage.is_none()
+        && requested_entry(conf, &canonical_path).is_some_and(|entry| entry.is_dir());
+    if conf.directory_slash == DirectorySlash::Redirect && is_dir && !canonical_path.ends_with('/')
+    {
+        canonical_path.push('/');
+    }
+    canonical_path
+}
+
+/// `path` with its consecutive slashes replaced by one
+fn merge_slashes(path: &str) -> String {
+    let mut merged = String::with_capacity(path.len());
+    for c in path.chars() {
+        if !(c == '/' && merged.ends_with('/')) {
+            merged.push(c);
+        }
+    }
+    merged
+}
+
+/// Redirect to `location`, with the status of `--redirect-status`
+pub fn redirect(conf: &MiniserveConfig, location: &str) -> HttpResponse {
+    HttpResponse::build(conf.redirect_status)
+        .append_header((header::LOCATION, location))
+        .finish()
+}
+
+/// Parse the status of the redirects, which are either permanent or temporary
+pub fn parse_redirect_status(src: &str) -> Result<StatusCode, ContextualError> {
+    match src {
+        "301" => Ok(StatusCode::MOVED_PERMANENTLY),
+        "302" => Ok(StatusCode::FOUND),
+        "307" => Ok(StatusCode::TEMPORARY_REDIRECT),
+        "308" => Ok(StatusCode::PERMANENT_REDIRECT),
+        _ => Err(ContextualError::ParseError(
+            "redirect status".to_string(),
+            format!("{} isn't one of 301, 302, 307 or 308", src),
+        )),
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(path, expected,
+        case("/a//b///c.txt", "/a/b/c.txt"),
+        case("//", "/"),
+        case("/dir/", "/dir/")
+    )]
+    fn consecutive_slashes_are_merged(path: &str, expected: &str) {
+        assert_eq!(merge_slashes(path), expected);
+    }
+
+    #[rstest(status, expected,
+        case("301", Some(StatusCode::MOVED_PERMANENTLY)),
+        case("308", Some(StatusCode::PERMANENT_REDIRECT)),
+        case("200", None)
+    )]
+    fn redirect_statuses_are_parsed(status: &str, expected: Option<StatusCode>) {
+        assert_eq!(parse_redirect_status(status).ok(), expected);
+    }
+}
@@ -0,0 +1,151 @@
+//! Case-insensitive paths with `--case-insensitive-paths`, so that the links written for the
+//! case-insensitive filesystems of Windows and macOS, like `/ReadMe.TXT`, still lead to
+//! `readme.txt`.
+//!
+//! The requests of paths which don't exist are redirected to the entry whose path only differs by
+//! case. When several entries of a directory match, the name with the exact case wins, then the
+//! first name in byte order, so that a path always leads to the same entry.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::HttpResponse;
+use futures::future::{ready, Either};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use std::future::Future;
+use std::path::Path;
+
+use crate::listing::percent_encode_sets::PATH_SEGMENT;
+use crate::storage::served_path;
+use crate::MiniserveConfig;
+
+/// Redirect the requests of the entries which don't exist to the entries whose path only differs
+/// by case
+pub fn redirect_case_insensitive<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let is_read = matches!(*req.method(), Method::GET | Method::HEAD);
+    let location = if conf.case_insensitive_paths && conf.storage.is_none() && is_read {
+        case_insensitive_location(conf, req.path(), req.query_string())
+    } else {
+        None
+    };
+
+    match location {
+        Some(location) => {
+            let (req, _) = req.into_parts();
+            let resp = HttpResponse::MovedPermanently()
+                .append_header((header::LOCATION, location))
+                .finish();
+            Either::Right(ready(Ok(ServiceResponse::new(req, resp))))
+        }
+        None => Either::Left(srv.call(req)),
+    }
+}
+
+/// URL of the entry requested at `uri_path` with another case, if no entry has this exact path
+fn case_insensitive_location(
+    conf: &MiniserveConfig,
+    uri_path: &str,
+    query: &str,
+) -> Option<String> {
+    let route = format!("/{}", conf.random_route.clone().unwrap_or_default());
+    let encoded_path = uri_path.strip_prefix(&route)?;
+    let decoded_path = percent_decode_str(encoded_path).decode_utf8().ok()?;
+    if decoded_path.trim_matches('/').is_empty()
+        || served_path(conf, &decoded_path).is_some_and(|path| path.exists())
+    {
+        return None;
+    }
+
+    let names = resolve(&conf.path, &decoded_path, conf.show_hidden)?;
+    let mut location = route.trim_end_matches('/').to_string();
+    for name in names {
+        location.push('/');
+        location.push_str(&utf8_percent_encode(&name, PATH_SEGMENT).to_string());
+    }
+    if decoded_path.ends_with('/') {
+        location.push('/');
+    }
+    if !query.is_empty() {
+        location.push('?');
+        location.push_str(query);
+    }
+    Some(location)
+}
+
+/// Names of the entries along `relative_path` in `root`, compared without case. Hidden entries are
+/// only found with `show_hidden`.
+fn resolve(root: &Path, relative_path: &str, show_hidden: bool) -> Option<Vec<String>> {
+    let mut dir = root.to_path_buf();
+    let mut names = vec![];
+    for segment in relative_path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+    {
+        if segment == "." || segment == ".." || (!show_hidden && segment.starts_with('.')) {
+            return None;
+        }
+        let name = if dir.join(segment).exists() {
+            segment.to_string()
+        } else {
+            find_name(&dir, segment)?
+        };
+        dir.push(&name);
+        names.push(name);
+    }
+    Some(names)
+}
+
+/// Name of the entry of `dir` which is `name` without case, the first one in byte order if there
+/// are several
+fn find_name(dir: &Path, name: &str) -> Option<String> {
+    let lowercase_name = name.to_lowercase();
+    dir.read_dir()
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|entry_name| entry_name.to_lowercase() == lowercase_name)
+        .min()
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn paths_are_resolved_without_case() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("Docs/Guides")).unwrap();
+        std::fs::write(dir.path().join("Docs/Guides/readme.txt"), "").unwrap();
+        std::fs::write(dir.path().join(".secret"), "").unwrap();
+
+        assert_eq!(
+            resolve(dir.path(), "/docs/GUIDES/ReadMe.TXT", false),
+            Some(vec!["Docs".to_string(), "Guides".to_string(), "readme.txt".to_string()])
+        );
+        assert_eq!(resolve(dir.path(), "/docs/missing.txt", false), None);
+        assert_eq!(resolve(dir.path(), "/docs/../.SECRET", true), None);
+        assert_eq!(resolve(dir.path(), "/.SECRET", false), None);
+        assert_eq!(resolve(dir.path(), "/.SECRET", true), Some(vec![".secret".to_string()]));
+    }
+
+    #[cfg(not(any(windows, target_os = "macos")))]
+    #[rstest]
+    fn ties_are_broken_in_byte_order() {
+        let dir = TempDir::new().unwrap();
+        for name in &["readme.txt", "README.txt", "ReadMe.txt"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        assert_eq!(find_name(dir.path(), "README.TXT"), Some("README.txt".to_string()));
+        assert_eq!(resolve(dir.path(), "/readme.txt", false), Some(vec!["readme.txt".to_string()]));
+    }
+}
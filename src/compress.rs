@@ -0,0 +1,186 @@
+//! Compression of the responses on the fly with `--compress`, for the formats which benefit from
+//! it, like listings, text files and JSON.
+//!
+//! The compression itself is done by actix-web's `Compress` middleware, which picks an encoding
+//! among the ones accepted by the client. `negotiate_encoding` restricts these to the enabled
+//! encodings, and `skip_uncompressible` keeps the other responses as they are.
+use actix_web::body::{AnyBody, BodySize, MessageBody};
+use actix_web::dev::{BodyEncoding, Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::{ContentEncoding, StatusCode};
+use actix_web::HttpMessage;
+use futures::future::TryFutureExt;
+use std::future::Future;
+use std::path::Path;
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+use crate::MiniserveConfig;
+
+/// Encoding which responses can be compressed with
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Br,
+    Zstd,
+}
+
+/// Extensions of already compressed formats, which may still be sent with a compressible MIME type
+const EXCLUDED_EXTENSIONS: &[&str] = &[
+    "7z", "br", "bz2", "gz", "rar", "svgz", "tgz", "woff", "woff2", "xz", "zip", "zst",
+];
+
+/// Smallest body worth compressing, in bytes
+const MIN_COMPRESSED_SIZE: u64 = 1024;
+
+/// Accept-Encoding header sent by the client, before `negotiate_encoding` restricted it
+#[derive(Clone, Debug)]
+pub struct ClientAcceptEncoding(pub String);
+
+/// The `Accept-Encoding` header value `accept`, without the encodings which aren't `enabled`
+fn restrict_accept_encoding(accept: &str, enabled: &[Compression]) -> String {
+    accept
+        .split(',')
+        .filter(|coding| {
+            let name = coding.split(';').next().unwrap_or_default().trim();
+            enabled
+                .iter()
+                .any(|encoding| name.eq_ignore_ascii_case(&encoding.to_string()))
+        })
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Whether responses of the MIME type `content_type` are worth compressing
+fn is_compressible(content_type: &str) -> bool {
+    let mime = match content_type.parse::<mime::Mime>() {
+        Ok(mime) => mime,
+        Err(_) => return false,
+    };
+    let subtype = mime.subtype().as_str();
+    match mime.type_() {
+        mime::TEXT => true,
+        mime::IMAGE => subtype == "svg",
+        mime::APPLICATION => {
+            matches!(
+                subtype,
+                "json" | "javascript" | "x-javascript" | "xml" | "wasm" | "toml" | "x-sh"
+            ) || matches!(
+                mime.suffix().map(|suffix| suffix.as_str()),
+                Some("json") | Some("xml")
+            )
+        }
+        _ => false,
+    }
+}
+
+/// Restrict the encodings accepted by the client to the ones enabled with `--compress`, so that
+/// the `Compress` middleware only picks one of these
+pub fn negotiate_encoding<S, B>(
+    mut req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+    B::Error: Into<Box<dyn std::error::Error>>,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let accept = req
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|accept| accept.to_str().ok())
+        .map(str::to_string);
+
+    if let Some(accept) = accept {
+        let restricted = restrict_accept_encoding(&accept, &conf.compress);
+        req.extensions_mut().insert(ClientAcceptEncoding(accept));
+        match HeaderValue::from_str(&restricted) {
+            Ok(restricted) if !restricted.is_empty() => {
+                req.headers_mut()
+                    .insert(header::ACCEPT_ENCODING, restricted);
+            }
+            _ => {
+                req.headers_mut().remove(header::ACCEPT_ENCODING);
+            }
+        }
+    }
+
+    srv.call(req)
+        .map_ok(|res| res.map_body(|_, body| AnyBody::from_message(body)))
+}
+
+/// Leave the responses which don't benefit from compression uncompressed: small ones, partial
+/// ones, and the ones of formats which aren't compressible or are already compressed
+pub fn skip_uncompressible<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let extension = Path::new(req.path())
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    let excluded = extension.is_some_and(|extension| {
+        EXCLUDED_EXTENSIONS.contains(&extension.as_str())
+            || conf.compress_exclude.contains(&extension)
+    });
+
+    srv.call(req).map_ok(move |mut res| {
+        let content_type = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .unwrap_or_default();
+        let too_small = match res.response().body().size() {
+            BodySize::Sized(size) => size < MIN_COMPRESSED_SIZE,
+            BodySize::Stream => false,
+            BodySize::None | BodySize::Empty => true,
+        };
+
+        if excluded
+            || too_small
+            || res.status() == StatusCode::PARTIAL_CONTENT
+            || !is_compressible(content_type)
+        {
+            res.response_mut().encoding(ContentEncoding::Identity);
+        }
+        res
+    })
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(accept, enabled, expected,
+        case("gzip, deflate, br", vec![Compression::Gzip, Compression::Br], "gzip, br"),
+        case("br;q=0.5, zstd", vec![Compression::Br], "br;q=0.5"),
+        case("deflate, *", vec![Compression::Gzip], ""),
+        case("gzip", vec![], "")
+    )]
+    fn accept_encoding_is_restricted(accept: &str, enabled: Vec<Compression>, expected: &str) {
+        assert_eq!(restrict_accept_encoding(accept, &enabled), expected);
+    }
+
+    #[rstest(content_type, expected,
+        case("text/html; charset=utf-8", true),
+        case("application/json", true),
+        case("application/ld+json", true),
+        case("image/svg+xml", true),
+        case("image/png", false),
+        case("application/zip", false),
+        case("video/mp4", false)
+    )]
+    fn compressible_types(content_type: &str, expected: bool) {
+        assert_eq!(is_compressible(content_type), expected);
+    }
+}
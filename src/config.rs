@@ -1,29 +1,125 @@
 use std::{
-    fs::File,
-    io::BufReader,
+    collections::HashMap,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
 };
 
-use anyhow::{anyhow, Context, Result};
-use http::HeaderMap;
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue, StatusCode};
 
 #[cfg(feature = "tls")]
-use rustls::internal::pemfile::{certs, pkcs8_private_keys};
+use crate::{
+    acme::AcmeConfig,
+    tls::{self, ReloadableCertificate, TlsProtocols},
+};
 
-use crate::{args::CliArgs, auth::RequiredAuth};
+use crate::{
+    access_log::LogFormat,
+    archive_fs::ArchiveFs,
+    args::CliArgs,
+    auth::{ApiToken, AuthPermission, RequiredAuth},
+    cache_control::CacheControlRule,
+    canonical_url::DirectorySlash,
+    compress::Compression,
+    file_upload::DuplicateFilePolicy,
+    glob::Globs,
+    hooks::Hook,
+    ignore::{self, Ignore},
+    ip_filter::{IpFilter, IpNet},
+    log_file::RotationPolicy,
+    memory_cache::MemoryCache,
+    mime_types,
+    mount::{Mount, VirtualHost},
+    network_interface,
+    overlay_fs::OverlayFs,
+    s3::{Credentials, S3Bucket},
+    stdin,
+    storage::Storage,
+    system_log::LogTarget,
+};
 
 /// Possible characters for random routes
 const ROUTE_ALPHABET: [char; 16] = [
     '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', 'a', 'b', 'c', 'd', 'e', 'f',
 ];
 
+/// Setting which can be replaced while miniserve is running, see `MiniserveConfig::reload`.
+/// All the clones of the configuration share the same value.
+#[derive(Clone)]
+pub struct Reloadable<T>(Arc<RwLock<Arc<T>>>);
+
+impl<T> Reloadable<T> {
+    pub fn new(value: T) -> Self {
+        Reloadable(Arc::new(RwLock::new(Arc::new(value))))
+    }
+
+    /// Current value, which stays valid for the caller if it is replaced in the meantime
+    pub fn get(&self) -> Arc<T> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn set(&self, value: T) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(value);
+    }
+}
+
 #[derive(Clone)]
 /// Configuration of the Miniserve application
 pub struct MiniserveConfig {
     /// Enable verbose mode
     pub verbose: bool,
 
+    /// Format of the access log
+    pub log_format: LogFormat,
+
+    /// Where the messages and the access log are written
+    pub log_target: LogTarget,
+
+    /// File the access log is written to
+    pub log_file: Option<PathBuf>,
+
+    /// Rotation of the access log file
+    pub log_rotation: RotationPolicy,
+
+    /// Seconds given to running transfers to finish when shutting down
+    pub shutdown_grace_period: u64,
+
+    /// Time without connections after which miniserve stops
+    pub shutdown_after_idle: Option<std::time::Duration>,
+
+    /// Number of downloads after which miniserve stops
+    pub max_downloads: Option<u64>,
+
+    /// Seconds given to clients to send the headers of their request, 0 for no limit
+    pub client_timeout: u64,
+
+    /// Seconds idle connections are kept open, 0 to disable keep-alive
+    pub keep_alive: u64,
+
+    /// Maximum time taken to receive and handle a request
+    pub request_timeout: Option<std::time::Duration>,
+
+    /// Enable the admin status page
+    pub admin_enabled: bool,
+
+    /// Refuse any modification of the served directory, switched on the admin status page
+    pub read_only: Reloadable<bool>,
+
+    /// OTLP endpoint the tracing spans are exported to
+    #[cfg(feature = "otlp")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Expose Prometheus metrics
+    pub metrics_enabled: bool,
+
+    /// Route of the Prometheus metrics, starting with a slash
+    pub metrics_route: String,
+
+    /// Let anonymous users read the metrics
+    pub metrics_public: bool,
+
     /// Path to be served by miniserve
     pub path: std::path::PathBuf,
 
@@ -33,18 +129,112 @@ pub struct MiniserveConfig {
     /// IP address(es) on which miniserve will be available
     pub interfaces: Vec<IpAddr>,
 
+    /// Names of the network interfaces whose addresses are the interfaces
+    pub network_interfaces: Vec<String>,
+
+    /// Enable the forwarding of the port by the local router with UPnP
+    pub upnp_forward: bool,
+
+    /// Unix domain socket on which miniserve will be available
+    pub unix_socket: Option<PathBuf>,
+
+    /// Permissions of the Unix domain socket
+    pub unix_socket_mode: Option<u32>,
+
     /// Enable HTTP basic authentication
-    pub auth: Vec<RequiredAuth>,
+    pub auth: Reloadable<Vec<RequiredAuth>>,
+
+    /// Tokens accepted with HTTP bearer authentication
+    pub api_tokens: Reloadable<Vec<ApiToken>>,
+
+    /// Enable the login page and session cookies
+    pub login_form: bool,
+
+    /// If enabled, the restrictions of the .miniserve.toml files of the directories are applied
+    pub dir_config: bool,
+
+    /// If enabled, users without credentials can read the directories not requiring auth
+    pub allow_anonymous: bool,
+
+    /// Let users without credentials read the served files
+    pub public_read: bool,
+
+    /// If enabled, each authenticated user is served their own directory of the served one
+    pub user_dirs: bool,
+
+    /// Lifetime of login sessions in seconds
+    pub session_lifetime: u64,
+
+    /// Randomly generated key used to sign session cookies and share links
+    pub signing_key: Vec<u8>,
+
+    /// Number of consecutive failed authentication attempts after which a client is locked out
+    pub auth_max_failures: u32,
+
+    /// Duration of the first authentication lockout
+    pub auth_lockout: std::time::Duration,
+
+    /// Log failed authentication attempts to this file
+    pub auth_fail2ban_log: Option<PathBuf>,
+
+    /// Clients allowed to connect
+    pub ip_filter: IpFilter,
+
+    /// Reverse proxies whose forwarding headers are trusted, see `--trusted-proxy`
+    pub trusted_proxies: Vec<IpNet>,
+
+    /// Read the PROXY protocol header at the start of the connections
+    pub proxy_protocol: bool,
+
+    /// Enable share links
+    pub share_links: bool,
+
+    /// Archive or object storage whose contents are served instead of the path, see
+    /// `--from-archive` and `--s3-bucket`
+    pub storage: Option<Arc<dyn Storage>>,
+
+    /// HTTP server the files missing from the path are fetched from
+    pub mirror_upstream: Option<http::Uri>,
+
+    /// Copy of the standard input served with `--from-stdin`, removed when miniserve exits
+    pub stdin_file: Option<PathBuf>,
 
     /// If false, miniserve will serve the current working directory
     pub path_explicitly_chosen: bool,
 
+    /// Other directories served under their own route
+    pub mounts: Vec<Mount>,
+
+    /// Other directories served for their own host name
+    pub vhosts: Vec<VirtualHost>,
+
+    /// Route of the mount served with this configuration, see `Mount::config`
+    pub mount_route: Option<String>,
+
     /// Enable symlink resolution
     pub no_symlinks: bool,
 
     /// Show hidden files
     pub show_hidden: bool,
 
+    /// Redirect the paths which don't exist to the entries whose paths only differ by case
+    pub case_insensitive_paths: bool,
+
+    /// What to do with the requests of directories without a trailing slash
+    pub directory_slash: DirectorySlash,
+
+    /// Redirect the paths with consecutive slashes to the path with single slashes
+    pub merge_slashes: bool,
+
+    /// Status of the redirects to the canonical URLs
+    pub redirect_status: StatusCode,
+
+    /// Patterns of the entries hidden by --ignore-file or --enable-ignore-files, if any
+    pub ignore: Option<Ignore>,
+
+    /// If enabled, ignored entries are still served by direct URL
+    pub serve_ignored: bool,
+
     /// Enable random route generation
     pub random_route: Option<String>,
 
@@ -66,14 +256,125 @@ pub struct MiniserveConfig {
     /// However, if a directory contains this file, miniserve will serve that file instead.
     pub index: Option<std::path::PathBuf>,
 
+    /// Update the listings open in the browser when their directory changes
+    pub live_updates: bool,
+
+    /// Keep the contents of the listed directories in memory, see `--listing-cache`
+    pub listing_cache: bool,
+
+    /// Small files kept in memory, see `--memory-cache`
+    pub memory_cache: Option<Arc<MemoryCache>>,
+
+    /// Serve the .br and .gz copies of the requested files, see `--precompressed`
+    pub precompressed: bool,
+
+    /// Encodings which responses are compressed with, none if compression is disabled
+    pub compress: Vec<Compression>,
+
+    /// Extensions of the files which are never compressed, in lowercase and without dot
+    pub compress_exclude: Vec<String>,
+
     /// Enable QR code display
     pub show_qrcode: bool,
 
     /// Enable file upload
     pub file_upload: bool,
 
-    /// Enable upload to override existing files
-    pub overwrite_files: bool,
+    /// What to do when an uploaded file already exists
+    pub on_duplicate_files: DuplicateFilePolicy,
+
+    /// Only show the upload form, without listing or serving the files
+    pub upload_only: bool,
+
+    /// Age after which the files uploaded through miniserve are deleted
+    pub upload_ttl: Option<std::time::Duration>,
+
+    /// Maximum size in bytes of each uploaded file
+    pub max_upload_size: Option<u64>,
+
+    /// Maximum rate of each download in bytes per second
+    pub throttle: Option<u64>,
+
+    /// Maximum rate of each upload in bytes per second
+    pub throttle_upload: Option<u64>,
+
+    /// Maximum rate of all the transfers together in bytes per second
+    pub throttle_total: Option<u64>,
+
+    /// Maximum number of simultaneous connections
+    pub max_connections: Option<usize>,
+
+    /// Maximum number of bytes which can be uploaded in total
+    pub upload_quota: Option<u64>,
+
+    /// Maximum number of bytes each user can store
+    pub quotas: HashMap<String, u64>,
+
+    /// Enable extraction of uploaded archives
+    pub upload_auto_extract: bool,
+
+    /// Maximum number of bytes which can be extracted from a single uploaded archive
+    pub max_extracted_size: u64,
+
+    /// Number of previous versions kept of the files overwritten by uploads
+    pub keep_versions: Option<usize>,
+
+    /// Drop uploads whose content is already stored under their name
+    pub upload_dedup: bool,
+
+    /// Store uploads identical to another file of their directory as hard links to it
+    pub dedup_hard_links: bool,
+
+    /// URL notified of changes to the served directory
+    pub on_change_webhook: Option<http::Uri>,
+
+    /// URL notified of the downloaded files
+    pub on_download_webhook: Option<http::Uri>,
+
+    /// Commands run on events
+    pub hooks: Vec<Hook>,
+
+    /// File keeping the download counts of each file
+    pub download_stats: Option<PathBuf>,
+
+    /// Show the download counts in the listings
+    pub show_download_counts: bool,
+
+    /// Read the EXIF metadata of the photos in the listings
+    pub photo_metadata: bool,
+
+    /// Command validating uploaded files
+    pub upload_hook: Option<String>,
+
+    /// Error message shown when the upload hook rejects a file
+    pub upload_hook_error: String,
+
+    /// Expose an Atom feed of the newest files
+    pub feed_enabled: bool,
+
+    /// Expose an OPDS catalog of the ebooks
+    pub opds_enabled: bool,
+
+    /// Show the HTML fragments of the directories around their listing
+    pub fragments_enabled: bool,
+
+    /// Name of the fragments shown above the listings
+    pub header_name: String,
+
+    /// Name of the fragments shown below the listings
+    pub footer_name: String,
+
+    /// Enable renaming and moving entries
+    pub rename_enabled: bool,
+
+    /// Enable editing text files in the browser
+    pub edit_enabled: bool,
+
+    /// Enable extracting the archives of the served directory
+    pub extract_enabled: bool,
+
+    /// Maximum size in bytes of files which can be edited in the browser
+    pub max_edit_size: u64,
 
     /// If false, creation of uncompressed tar archives is disabled
     pub tar_enabled: bool,
@@ -84,14 +385,65 @@ pub struct MiniserveConfig {
     /// If false, creation of zip archives is disabled
     pub zip_enabled: bool,
 
+    /// If false, creation of torrents is disabled
+    pub torrent_enabled: bool,
+
+    /// If false, creation of manifests is disabled
+    pub manifest_enabled: bool,
+
+    /// If false, the duplicate files of the directories can't be listed
+    pub duplicates_enabled: bool,
+
+    /// If false, the disk usage of the directories can't be shown
+    pub disk_usage_enabled: bool,
+
+    /// Stream the videos as HLS
+    pub hls_enabled: bool,
+
+    /// Command transcoding the videos to HLS, instead of ffmpeg
+    pub transcode_cmd: Option<String>,
+
+    /// Directory caching the HLS streams
+    pub hls_cache: PathBuf,
+
+    /// Send downscaled copies of the images
+    pub resize_enabled: bool,
+
+    /// Directory caching the downscaled copies of the images
+    pub resize_cache: PathBuf,
+
+    /// Search the content of the text files
+    pub content_search_enabled: bool,
+
+    /// If false, the last lines of the files can't be requested
+    pub tail_enabled: bool,
+
     /// If enabled, directories are listed first
     pub dirs_first: bool,
 
     /// Shown instead of host in page title and heading
     pub title: Option<String>,
 
+    /// MIME types sent instead of the guessed ones, by lowercase file extension
+    pub mime_types: HashMap<String, mime::Mime>,
+
+    /// Pages sent instead of the built-in error pages, by status
+    pub error_pages: HashMap<StatusCode, Bytes>,
+
+    /// Files sent as downloads, see `--force-download`
+    pub force_download: Reloadable<Vec<Globs>>,
+
+    /// Cache-Control headers of the files matching the rules, see `--cache-control`
+    pub cache_control: Reloadable<Vec<CacheControlRule>>,
+
     /// If specified, header will be added
-    pub header: Vec<HeaderMap>,
+    pub header: Reloadable<Vec<HeaderMap>>,
+
+    /// If enabled, security headers are added to responses
+    pub security_headers: bool,
+
+    /// If set, this Content-Security-Policy header is added to responses
+    pub csp: Option<HeaderValue>,
 
     /// If enabled, version footer is hidden
     pub hide_version_footer: bool,
@@ -102,13 +454,55 @@ pub struct MiniserveConfig {
 
     #[cfg(not(feature = "tls"))]
     pub tls_rustls_config: Option<()>,
+
+    /// Certificate used by `tls_rustls_config`, reloaded when its files change
+    #[cfg(feature = "tls")]
+    pub tls_certificate: Option<Arc<ReloadableCertificate>>,
+
+    /// TLS versions and cipher suites accepted by `tls_rustls_config`
+    #[cfg(feature = "tls")]
+    pub tls_protocols: TlsProtocols,
+
+    /// SHA-256 fingerprint of the self-signed certificate generated with --tls-self-signed
+    pub tls_self_signed_fingerprint: Option<String>,
+
+    /// Obtain the TLS certificate from an ACME server
+    #[cfg(feature = "tls")]
+    pub acme: Option<AcmeConfig>,
+
+    /// Port on which plain HTTP is served alongside HTTPS
+    pub http_port: Option<u16>,
+
+    /// If enabled, plain HTTP requests are redirected to HTTPS
+    pub redirect_to_https: bool,
+
+    /// If set, the Strict-Transport-Security header is sent over HTTPS with this max-age
+    pub hsts_max_age: Option<u64>,
+
+    /// If enabled, the common name of verified client certificates is used as the username
+    pub tls_client_cn_auth: bool,
 }
 
 impl MiniserveConfig {
     /// Parses the command line arguments
     pub fn try_from_args(args: CliArgs) -> Result<Self> {
+        #[cfg(unix)]
+        let (unix_socket, unix_socket_mode) = (args.listen_unix, args.listen_unix_mode);
+        #[cfg(not(unix))]
+        let (unix_socket, unix_socket_mode) = (None, None);
+
         let interfaces = if !args.interfaces.is_empty() {
             args.interfaces
+        } else if !args.network_interfaces.is_empty() {
+            let addresses = network_interface::interface_addresses(&args.network_interfaces)
+                .context("Failed to look up the addresses of the --interface interfaces")?;
+            if addresses.is_empty() {
+                bail!("{} has no address", args.network_interfaces.join(", "));
+            }
+            addresses
+        } else if unix_socket.is_some() {
+            // Only listen on the socket unless interfaces are given explicitly
+            vec![]
         } else {
             vec![
                 IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
@@ -127,10 +521,41 @@ impl MiniserveConfig {
         let favicon_route = nanoid::nanoid!(10, &ROUTE_ALPHABET);
         let css_route = nanoid::nanoid!(10, &ROUTE_ALPHABET);
 
+        // Sessions and share links don't need to survive restarts, so a fresh key is generated on
+        // every start.
+        let signing_key = nanoid::nanoid!(64).into_bytes();
+
+        let metrics_route = format!("/{}", args.metrics_route.trim_start_matches('/'));
+
         let default_color_scheme = args.color_scheme;
         let default_color_scheme_dark = args.color_scheme_dark;
 
-        let path_explicitly_chosen = args.path.is_some() || args.index.is_some();
+        let path_explicitly_chosen = args.path.is_some()
+            || args.index.is_some()
+            || args.s3_bucket.is_some()
+            || args.from_stdin;
+
+        let storage: Option<Arc<dyn Storage>> = match (&args.path, args.from_archive) {
+            (Some(path), true) => Some(Arc::new(
+                ArchiveFs::open(path.clone())
+                    .with_context(|| format!("Failed to serve {}", path.display()))?,
+            )),
+            (Some(path), false) if !args.overlay.is_empty() => {
+                let roots = std::iter::once(path.clone())
+                    .chain(args.overlay.iter().cloned())
+                    .collect();
+                Some(Arc::new(OverlayFs::new(roots, args.no_symlinks)?))
+            }
+            _ => match &args.s3_bucket {
+                Some(bucket) => Some(Arc::new(S3Bucket::new(
+                    bucket.clone(),
+                    args.s3_endpoint.clone(),
+                    args.s3_region.clone(),
+                    Credentials::from_env(),
+                )?)),
+                None => None,
+            },
+        };
 
         let port = match args.port {
             0 => port_check::free_local_port().context("No free ports available")?,
@@ -138,55 +563,453 @@ impl MiniserveConfig {
         };
 
         #[cfg(feature = "tls")]
-        let tls_rustls_server_config = if let (Some(tls_cert), Some(tls_key)) =
-            (args.tls_cert, args.tls_key)
-        {
-            let mut server_config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
-            let cert_file = &mut BufReader::new(
-                File::open(&tls_cert)
-                    .context(format!("Couldn't access TLS certificate {:?}", tls_cert))?,
+        let tls_protocols = TlsProtocols::new(args.tls_min_version, &args.tls_ciphers)?;
+
+        #[cfg(feature = "tls")]
+        let (tls_rustls_server_config, tls_certificate, tls_self_signed_fingerprint) =
+            if args.tls_self_signed {
+                let (server_config, certificate, fingerprint) = tls::self_signed_server_config(
+                    args.tls_cert,
+                    args.tls_key,
+                    args.tls_client_ca.as_deref(),
+                    &tls_protocols,
+                )?;
+                (Some(server_config), certificate, Some(fingerprint))
+            } else if let (Some(tls_cert), Some(tls_key)) = (args.tls_cert, args.tls_key) {
+                let (server_config, certificate) = tls::server_config(
+                    tls_cert,
+                    tls_key,
+                    args.tls_client_ca.as_deref(),
+                    &tls_protocols,
+                )?;
+                (Some(server_config), Some(certificate), None)
+            } else {
+                (None, None, None)
+            };
+
+        #[cfg(feature = "tls")]
+        let acme = if args.acme_domains.is_empty() {
+            None
+        } else {
+            Some(AcmeConfig {
+                domains: args.acme_domains,
+                cache_dir: args.acme_cache_dir.unwrap_or_default(),
+                directory_url: args.acme_directory,
+                email: args.acme_email,
+                http_port: args.acme_http_port,
+            })
+        };
+
+        #[cfg(feature = "tls")]
+        if args.proxy_protocol && (tls_rustls_server_config.is_some() || acme.is_some()) {
+            bail!(
+                "--proxy-protocol can't be used with TLS, which has to be terminated by the proxy"
             );
-            let key_file = &mut BufReader::new(
-                File::open(&tls_key).context(format!("Couldn't access TLS key {:?}", tls_key))?,
+        }
+
+        #[cfg(not(feature = "tls"))]
+        let tls_rustls_server_config = None;
+
+        #[cfg(not(feature = "tls"))]
+        let tls_self_signed_fingerprint = None;
+
+        for (i, mount) in args.mounts.iter().enumerate() {
+            if args.mounts[..i]
+                .iter()
+                .any(|other| other.route == mount.route)
+            {
+                bail!("Several directories are mounted at {}", mount.route);
+            }
+            if !mount.path.exists() {
+                bail!(
+                    "The path {} mounted at {} doesn't exist",
+                    mount.path.display(),
+                    mount.route
+                );
+            }
+        }
+
+        for (i, vhost) in args.vhosts.iter().enumerate() {
+            if args.vhosts[..i]
+                .iter()
+                .any(|other| other.host == vhost.host)
+            {
+                bail!("Several directories are served for {}", vhost.host);
+            }
+            if !vhost.path.exists() {
+                bail!(
+                    "The path {} served for {} doesn't exist",
+                    vhost.path.display(),
+                    vhost.host
+                );
+            }
+        }
+
+        if args.user_dirs && args.path.as_ref().is_some_and(|path| path.is_file()) {
+            bail!("--user-dirs requires a directory to serve");
+        }
+
+        let auth = credentials(&args.auth, &args.auth_read_only);
+        if let Some((user, _)) = args
+            .quotas
+            .iter()
+            .find(|(user, _)| !auth.iter().any(|auth| &auth.username == user))
+        {
+            bail!("--quota is given for {}, who isn't an --auth user", user);
+        }
+        if let Some(user) = args
+            .auth_read_only
+            .iter()
+            .find(|user| !auth.iter().any(|auth| &auth.username == *user))
+        {
+            bail!(
+                "--auth-read-only is given for {}, who isn't an --auth user",
+                user
             );
-            let cert_chain = certs(cert_file).map_err(|_| anyhow!("Couldn't load certificates"))?;
-            let mut keys =
-                pkcs8_private_keys(key_file).map_err(|_| anyhow!("Couldn't load private key"))?;
-            server_config.set_single_cert(cert_chain, keys.remove(0))?;
-            Some(server_config)
+        }
+
+        if args.max_downloads == Some(0) {
+            bail!("--max-downloads must be at least 1");
+        }
+
+        if args.admin_enabled && args.auth.is_empty() && args.api_tokens.is_empty() {
+            bail!("--enable-admin requires --auth or --api-token");
+        }
+
+        let on_duplicate_files = if args.overwrite_files {
+            DuplicateFilePolicy::Overwrite
+        } else if args.upload_only {
+            // Errors about existing files would tell what others uploaded
+            DuplicateFilePolicy::Rename
         } else {
-            None
+            args.on_duplicate_files
+                .unwrap_or(DuplicateFilePolicy::Error)
+        };
+
+        #[cfg(feature = "tls")]
+        let (http_port, redirect_to_https, hsts_max_age) = {
+            let tls_enabled = tls_rustls_server_config.is_some() || acme.is_some();
+            if (args.http_port.is_some() || args.hsts_max_age.is_some()) && !tls_enabled {
+                bail!(
+                    "--http-port and --hsts require --tls-cert, --tls-self-signed or --acme-domain"
+                );
+            }
+            if args.http_port.is_some()
+                && args.http_port == acme.as_ref().map(|acme| acme.http_port)
+            {
+                bail!("--http-port must be different from --acme-http-port");
+            }
+            (args.http_port, args.redirect_to_https, args.hsts_max_age)
         };
 
         #[cfg(not(feature = "tls"))]
-        let tls_rustls_server_config = None;
+        let (http_port, redirect_to_https, hsts_max_age) = (None, false, None);
+
+        #[cfg(feature = "tls")]
+        let tls_client_cn_auth = args.tls_client_cn_auth;
+
+        #[cfg(not(feature = "tls"))]
+        let tls_client_cn_auth = false;
+
+        let mut mime_types = match &args.mime_types_file {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                mime_types::parse_mime_types_file(&content)
+                    .with_context(|| format!("Invalid MIME types file {}", path.display()))?
+            }
+            None => HashMap::new(),
+        };
+        mime_types.extend(args.mime_type);
+
+        let ignore_patterns = match &args.ignore_file {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                ignore::parse_ignore_file(&content, Path::new(""))
+            }
+            None => vec![],
+        };
+        let ignore = if args.ignore_file.is_some() || args.enable_ignore_files {
+            Some(Ignore {
+                patterns: ignore_patterns,
+                ignore_files: args.enable_ignore_files,
+            })
+        } else {
+            None
+        };
+
+        let error_pages = args
+            .error_page
+            .iter()
+            .map(|(status, path)| {
+                let page = std::fs::read(path)
+                    .with_context(|| format!("Failed to read the error page {}", path.display()))?;
+                Ok((*status, Bytes::from(page)))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        // Read last, as nothing can fail afterwards
+        let stdin_file = if args.from_stdin {
+            let filename = args.filename.as_deref().unwrap_or(stdin::DEFAULT_FILENAME);
+            if filename.is_empty()
+                || filename.contains(['/', '\\'])
+                || matches!(filename, "." | "..")
+            {
+                bail!("--filename must be a file name without directory");
+            }
+            if atty::is(atty::Stream::Stdin) {
+                bail!("--from-stdin expects the output of a command, e.g. command | miniserve --from-stdin");
+            }
+            Some(stdin::spool_stdin(filename).context("Failed to read the standard input")?)
+        } else {
+            None
+        };
 
         Ok(MiniserveConfig {
             verbose: args.verbose,
-            path: args.path.unwrap_or_else(|| PathBuf::from(".")),
+            log_format: args.log_format,
+            log_target: args.log_target,
+            log_file: args.log_file,
+            shutdown_grace_period: args.shutdown_grace_period,
+            shutdown_after_idle: args.shutdown_after_idle,
+            max_downloads: args.max_downloads,
+            client_timeout: args.client_timeout,
+            keep_alive: args.keep_alive,
+            request_timeout: args.request_timeout,
+            admin_enabled: args.admin_enabled,
+            read_only: Reloadable::new(false),
+            #[cfg(feature = "otlp")]
+            otlp_endpoint: args.otlp_endpoint,
+            metrics_enabled: args.metrics_enabled,
+            metrics_route,
+            metrics_public: args.metrics_public,
+            log_rotation: RotationPolicy {
+                max_size: args.log_rotate_size,
+                interval: args.log_rotate_interval,
+                keep: args.log_rotate_keep,
+                compress: args.log_compress,
+            },
+            path: stdin_file
+                .clone()
+                .or(args.path)
+                .unwrap_or_else(|| PathBuf::from(".")),
             port,
             interfaces,
-            auth: args.auth,
+            network_interfaces: args.network_interfaces,
+            upnp_forward: args.upnp_forward,
+            unix_socket,
+            unix_socket_mode,
+            auth: Reloadable::new(auth),
+            api_tokens: Reloadable::new(args.api_tokens),
+            login_form: args.login_form,
+            dir_config: args.dir_config,
+            allow_anonymous: args.allow_anonymous,
+            public_read: args.public_read,
+            user_dirs: args.user_dirs,
+            session_lifetime: args.session_lifetime,
+            signing_key,
+            auth_max_failures: args.auth_max_failures,
+            auth_lockout: std::time::Duration::from_secs(args.auth_lockout),
+            auth_fail2ban_log: args.auth_fail2ban_log,
+            trusted_proxies: args.trusted_proxies,
+            proxy_protocol: args.proxy_protocol,
+            ip_filter: IpFilter {
+                allow: args.allow_ip,
+                deny: args.deny_ip,
+                action: args.ip_deny_action,
+            },
+            share_links: args.share_links,
+            storage,
+            mirror_upstream: args.mirror_upstream,
+            stdin_file,
             path_explicitly_chosen,
+            mounts: args.mounts,
+            vhosts: args.vhosts,
+            mount_route: None,
             no_symlinks: args.no_symlinks,
             show_hidden: args.hidden,
+            case_insensitive_paths: args.case_insensitive_paths,
+            directory_slash: args.directory_slash,
+            merge_slashes: args.merge_slashes,
+            redirect_status: args.redirect_status,
+            ignore,
+            serve_ignored: args.serve_ignored,
             random_route,
             favicon_route,
             css_route,
             default_color_scheme,
             default_color_scheme_dark,
             index: args.index,
-            overwrite_files: args.overwrite_files,
+            live_updates: args.live_updates,
+            listing_cache: args.listing_cache,
+            memory_cache: args
+                .memory_cache
+                .map(|capacity| Arc::new(MemoryCache::new(capacity))),
+            precompressed: args.precompressed,
+            compress: args.compress,
+            compress_exclude: args
+                .compress_exclude
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_lowercase())
+                .collect(),
+            on_duplicate_files,
+            upload_only: args.upload_only,
+            upload_ttl: args.upload_ttl,
+            max_upload_size: args.max_upload_size,
+            throttle: args.throttle,
+            throttle_upload: args.throttle_upload,
+            throttle_total: args.throttle_total,
+            max_connections: args.max_connections,
+            upload_quota: args.upload_quota,
+            quotas: args.quotas.into_iter().collect(),
+            upload_auto_extract: args.upload_auto_extract,
+            max_extracted_size: args.max_extracted_size,
+            keep_versions: args.keep_versions,
+            upload_dedup: args.upload_dedup,
+            dedup_hard_links: args.dedup_hard_links,
+            on_change_webhook: args.on_change_webhook,
+            on_download_webhook: args.on_download_webhook,
+            hooks: args.hooks,
+            download_stats: args.download_stats,
+            show_download_counts: args.show_download_counts,
+            photo_metadata: args.photo_metadata,
+            upload_hook: args.upload_hook,
+            upload_hook_error: args.upload_hook_error,
             show_qrcode: args.qrcode,
             file_upload: args.file_upload,
+            feed_enabled: args.feed_enabled,
+            opds_enabled: args.opds_enabled,
+            fragments_enabled: args.fragments_enabled,
+            header_name: args.header_name,
+            footer_name: args.footer_name,
+            rename_enabled: args.rename_enabled,
+            edit_enabled: args.edit_enabled,
+            extract_enabled: args.extract_enabled,
+            max_edit_size: args.max_edit_size,
             tar_enabled: args.enable_tar,
             tar_gz_enabled: args.enable_tar_gz,
             zip_enabled: args.enable_zip,
+            torrent_enabled: args.enable_torrent,
+            hls_enabled: args.enable_hls,
+            transcode_cmd: args.transcode_cmd,
+            hls_cache: args
+                .hls_cache
+                .unwrap_or_else(|| std::env::temp_dir().join("miniserve-hls")),
+            resize_enabled: args.enable_resize,
+            resize_cache: args
+                .resize_cache
+                .unwrap_or_else(|| std::env::temp_dir().join("miniserve-resized")),
+            content_search_enabled: args.enable_content_search,
+            manifest_enabled: args.enable_manifest,
+            duplicates_enabled: args.enable_duplicates,
+            disk_usage_enabled: args.enable_disk_usage,
+            tail_enabled: args.enable_tail,
             dirs_first: args.dirs_first,
             title: args.title,
-            header: args.header,
+            mime_types,
+            error_pages,
+            force_download: Reloadable::new(args.force_download),
+            cache_control: Reloadable::new(args.cache_control),
+            header: Reloadable::new(args.header),
+            security_headers: args.security_headers,
+            csp: args.csp,
             hide_version_footer: args.hide_version_footer,
             tls_rustls_config: tls_rustls_server_config,
+            #[cfg(feature = "tls")]
+            tls_certificate,
+            #[cfg(feature = "tls")]
+            tls_protocols,
+            tls_self_signed_fingerprint,
+            #[cfg(feature = "tls")]
+            acme,
+            http_port,
+            redirect_to_https,
+            hsts_max_age,
+            tls_client_cn_auth,
         })
     }
+
+    /// Apply the credentials, headers and rules of `args` which apply to routes, which can be
+    /// changed without restarting, and log what changed. The sessions of the users whose
+    /// credentials changed end, and the users added with --user-dirs only get a directory once
+    /// miniserve restarts.
+    pub fn reload(&self, args: CliArgs) {
+        let auth = self.auth.get();
+        let new_auth = credentials(&args.auth, &args.auth_read_only);
+        let mut changes = vec![];
+        for new in &new_auth {
+            match auth.iter().find(|old| old.username == new.username) {
+                None if self.user_dirs => changes.push(format!(
+                    "added user {}, without a directory until restarting",
+                    new.username
+                )),
+                None => changes.push(format!("added user {}", new.username)),
+                Some(old) if old != new => changes.push(format!("changed user {}", new.username)),
+                Some(_) => (),
+            }
+        }
+        for old in auth.iter() {
+            if !new_auth.iter().any(|new| new.username == old.username) {
+                changes.push(format!("removed user {}", old.username));
+            }
+        }
+        if *self.api_tokens.get() != args.api_tokens {
+            changes.push("changed API tokens".to_string());
+        }
+        if *self.header.get() != args.header {
+            changes.push("changed headers".to_string());
+        }
+        if *self.force_download.get() != args.force_download {
+            changes.push("changed forced downloads".to_string());
+        }
+        if *self.cache_control.get() != args.cache_control {
+            changes.push("changed Cache-Control rules".to_string());
+        }
+
+        if changes.is_empty() {
+            log::warn!("Configuration reloaded without changes");
+        } else {
+            log::warn!("Configuration reloaded: {}", changes.join(", "));
+        }
+
+        self.auth.set(new_auth);
+        self.api_tokens.set(args.api_tokens);
+        self.header.set(args.header);
+        self.force_download.set(args.force_download);
+        self.cache_control.set(args.cache_control);
+    }
+
+    /// Hosts of the interfaces the server listens on, as they are bound and shown in URLs
+    pub fn interface_hosts(&self) -> Vec<String> {
+        self.interfaces
+            .iter()
+            .map(|&interface| {
+                if interface == IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)) {
+                    // If the interface is 0.0.0.0, we'll change it to 127.0.0.1 so that clicking the link will
+                    // also work on Windows. Why can't Windows interpret 0.0.0.0?
+                    "127.0.0.1".to_string()
+                } else if interface.is_ipv6() {
+                    // If the interface is IPv6 then we'll print it with brackets so that it is clickable.
+                    format!("[{}]", interface)
+                } else {
+                    format!("{}", interface)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Credentials of the --auth users, who are only allowed to read with --auth-read-only
+fn credentials(auth: &[RequiredAuth], read_only: &[String]) -> Vec<RequiredAuth> {
+    auth.iter()
+        .map(|auth| RequiredAuth {
+            permission: if read_only.contains(&auth.username) {
+                AuthPermission::ReadOnly
+            } else {
+                AuthPermission::ReadWrite
+            },
+            ..auth.clone()
+        })
+        .collect()
 }
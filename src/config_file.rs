@@ -0,0 +1,177 @@
+//! TOML configuration file, whose keys are the long names of the command line arguments.
+//!
+//! For instance `port = 8080`, `enable-tar = true` or `auth = ["joe:123", "ann:456"]`, and
+//! `path` for the directory to serve. Arguments given on the command line take precedence.
+use std::ffi::OsString;
+use std::path::PathBuf;
+use structopt::clap::ErrorKind;
+use structopt::StructOpt;
+use toml::Value;
+
+use crate::args::CliArgs;
+use crate::errors::ContextualError;
+
+/// Configuration file used when --config is not given, if it exists
+pub fn default_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(config_dir.join("miniserve").join("config.toml"))
+}
+
+/// Format a scalar TOML value as a command line value
+fn to_arg_value(key: &str, value: &Value) -> Result<OsString, ContextualError> {
+    match value {
+        Value::String(s) => Ok(s.into()),
+        Value::Integer(i) => Ok(i.to_string().into()),
+        Value::Float(f) => Ok(f.to_string().into()),
+        Value::Boolean(b) => Ok(b.to_string().into()),
+        Value::Datetime(d) => Ok(d.to_string().into()),
+        Value::Array(_) | Value::Table(_) => Err(ContextualError::ConfigFileError(format!(
+            "{} must be a string, a number or a boolean",
+            key
+        ))),
+    }
+}
+
+/// Command line arguments equivalent to a key of the configuration file
+fn to_args(key: &str, value: &Value) -> Result<Vec<OsString>, ContextualError> {
+    let flag = OsString::from(format!("--{}", key));
+    match value {
+        Value::Boolean(true) => Ok(vec![flag]),
+        Value::Boolean(false) => Ok(vec![]),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| Ok(vec![flag.clone(), to_arg_value(key, value)?]))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|args| args.concat()),
+        value => Ok(vec![flag, to_arg_value(key, value)?]),
+    }
+}
+
+/// Parse the configuration file `content`, and merge it with the command line arguments
+/// `cli_args` (including the program name).
+///
+/// Single values given on the command line replace those of the file, while lists (e.g.
+/// `auth`) are added to those of the file.
+pub fn merge_args(content: &str, cli_args: &[OsString]) -> Result<Vec<OsString>, ContextualError> {
+    let table = toml::from_str::<toml::value::Table>(content)
+        .map_err(|e| ContextualError::ConfigFileError(e.to_string()))?;
+    let (program, cli_args) = cli_args
+        .split_first()
+        .ok_or_else(|| ContextualError::ConfigFileError("missing program name".to_string()))?;
+    let cli_path = CliArgs::from_iter_safe(std::iter::once(program).chain(cli_args))
+        .map(|args| args.path)
+        .map_err(|e| ContextualError::ConfigFileError(e.message))?;
+
+    let mut file_args = vec![];
+    for (key, value) in &table {
+        if key == "config" {
+            return Err(ContextualError::ConfigFileError(
+                "config cannot be set in the configuration file".to_string(),
+            ));
+        }
+
+        let key_args = if key == "path" {
+            if cli_path.is_some() {
+                continue;
+            }
+            vec![to_arg_value(key, value)?]
+        } else {
+            to_args(key, value)?
+        };
+        if key_args.is_empty() {
+            continue;
+        }
+
+        // Arguments given on the command line can't be given again
+        let probe = std::iter::once(program)
+            .chain(&key_args)
+            .chain(cli_args)
+            .collect::<Vec<_>>();
+        match CliArgs::clap().get_matches_from_safe(probe) {
+            // The path goes first, so that it's not taken as the value of an option
+            Ok(_) if key == "path" => {
+                file_args.splice(0..0, key_args);
+            }
+            Ok(_) => file_args.extend(key_args),
+            Err(e) if e.kind == ErrorKind::UnexpectedMultipleUsage => (),
+            Err(e) => return Err(ContextualError::ConfigFileError(e.message)),
+        }
+    }
+
+    Ok(std::iter::once(program.clone())
+        .chain(file_args)
+        .chain(cli_args.iter().cloned())
+        .collect())
+}
+
+/// Configuration file miniserve was started with, along with the command line arguments
+pub struct ConfigSource {
+    pub path: PathBuf,
+
+    /// Command line arguments, including the program name
+    pub cli_args: Vec<OsString>,
+}
+
+impl ConfigSource {
+    /// Read the configuration file, and merge it with the command line arguments
+    pub fn merged_args(&self) -> Result<Vec<OsString>, ContextualError> {
+        let content = std::fs::read_to_string(&self.path).map_err(|e| {
+            ContextualError::IoError(format!("Failed to read {}", self.path.display()), e)
+        })?;
+        merge_args(&content, &self.cli_args)
+    }
+
+    /// Read the configuration file again, e.g. when it was changed
+    pub fn reload(&self) -> Result<CliArgs, ContextualError> {
+        CliArgs::from_iter_safe(self.merged_args()?)
+            .map_err(|e| ContextualError::ConfigFileError(e.message))
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    fn parse(content: &str, cli_args: &[&str]) -> CliArgs {
+        let cli_args = std::iter::once("miniserve").chain(cli_args.iter().copied()).map(OsString::from).collect::<Vec<_>>();
+        CliArgs::from_iter(merge_args(content, &cli_args).unwrap())
+    }
+
+    #[rstest]
+    fn file_values_are_used() {
+        let args = parse("path = \"/srv\"\nport = 9000\nenable-tar = true\nhidden = false\nauth = [\"joe:123\", \"ann:456\"]\nauth-read-only = [\"ann\"]", &[]);
+        assert_eq!(args.path, Some(PathBuf::from("/srv")));
+        assert_eq!(args.port, 9000);
+        assert!(args.enable_tar);
+        assert!(!args.hidden);
+        assert_eq!(args.auth.len(), 2);
+        assert_eq!(args.auth_read_only, vec!["ann".to_string()]);
+    }
+
+    #[rstest]
+    fn command_line_takes_precedence() {
+        let args = parse("path = \"/srv\"\nport = 9000\ntitle = \"file\"", &["-p", "8000", "--title=cli", "/tmp"]);
+        assert_eq!(args.path, Some(PathBuf::from("/tmp")));
+        assert_eq!(args.port, 8000);
+        assert_eq!(args.title.as_deref(), Some("cli"));
+    }
+
+    #[rstest(content,
+        case("port = \"not a port\""),
+        case("unknown-key = true"),
+        case("config = \"other.toml\""),
+        case("auth = [[\"nested\"]]"),
+        case("not toml")
+    )]
+    fn invalid_files_are_refused(content: &str) {
+        let cli_args = vec![OsString::from("miniserve")];
+        assert!(merge_args(content, &cli_args).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+//! Restrictions of the served directories declared in their `.miniserve.toml` files, applied with
+//! `--dir-config`.
+//!
+//! For instance:
+//!
+//! ```toml
+//! require-auth = true    # anonymous users can't read this directory
+//! users = ["joe", "ann"] # only these --auth users can read or modify it
+//! hidden = true          # it isn't shown in the listing of its parent
+//! uploads = false        # nothing can be uploaded into it
+//! ```
+//!
+//! The restrictions of a directory apply to its subdirectories as well, which can only add
+//! their own. Directories requiring authentication are left out of the feeds and manifests of
+//! their parents, and their parents can't be downloaded as archives.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::{HttpRequest, HttpResponse};
+use futures::future::{ready, Either};
+use serde::Deserialize;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+use crate::auth::{self, CurrentUser};
+use crate::canonical_path;
+use crate::errors::{self, ContextualError};
+use crate::share::SharedAccess;
+use crate::storage::requested_file;
+use crate::MiniserveConfig;
+
+/// Name of the files declaring the restrictions of their directory
+pub const DIR_CONFIG_FILE: &str = ".miniserve.toml";
+
+/// Content of a `.miniserve.toml` file
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct DirConfig {
+    #[serde(default)]
+    require_auth: bool,
+
+    /// Users allowed in the directory, which implies `require_auth`
+    users: Option<Vec<String>>,
+
+    #[serde(default)]
+    hidden: bool,
+
+    uploads: Option<bool>,
+}
+
+impl DirConfig {
+    /// Restrictions declared in `dir`, if any. Invalid files restrict everything, rather than
+    /// leaving a directory unprotected.
+    fn read(dir: &Path) -> Option<Self> {
+        let path = dir.join(DIR_CONFIG_FILE);
+        let content = std::fs::read_to_string(&path).ok()?;
+        match toml::from_str(&content) {
+            Ok(dir_config) => Some(dir_config),
+            Err(e) => {
+                errors::log_error_chain(
+                    ContextualError::ParseError(path.display().to_string(), e.to_string())
+                        .to_string(),
+                );
+                Some(DirConfig {
+                    require_auth: true,
+                    users: Some(vec![]),
+                    hidden: true,
+                    uploads: Some(false),
+                })
+            }
+        }
+    }
+
+    fn requires_auth(&self) -> bool {
+        self.require_auth || self.users.is_some()
+    }
+}
+
+/// Restrictions applying to a directory, merged from its `.miniserve.toml` and those of its
+/// parents
+#[derive(Debug, Default, PartialEq)]
+pub struct DirRules {
+    pub require_auth: bool,
+
+    /// Users allowed in the directory, if restricted
+    pub users: Option<Vec<String>>,
+
+    pub uploads_disabled: bool,
+}
+
+impl DirRules {
+    fn merge(&mut self, dir_config: DirConfig) {
+        self.require_auth |= dir_config.requires_auth();
+        self.users = match (self.users.take(), dir_config.users) {
+            (Some(users), Some(allowed)) => Some(
+                users
+                    .into_iter()
+                    .filter(|user| allowed.contains(user))
+                    .collect(),
+            ),
+            (users, allowed) => users.or(allowed),
+        };
+        self.uploads_disabled |= dir_config.uploads == Some(false);
+    }
+}
+
+/// Restrictions of the directory at `path`, or of the directory of the file at `path`, inside of
+/// the served directory
+pub fn dir_rules(conf: &MiniserveConfig, path: &Path) -> DirRules {
+    let mut rules = DirRules::default();
+    let root = match canonical_path::canonicalize(&conf.path) {
+        Ok(root) => root,
+        Err(_) => return rules,
+    };
+    // Entries which don't exist yet, e.g. uploaded files, are in their closest existing parent
+    let (existing, missing) = match path
+        .ancestors()
+        .find_map(|ancestor| Some((ancestor, canonical_path::canonicalize(ancestor).ok()?)))
+    {
+        Some((ancestor, canonical)) => (canonical, path.strip_prefix(ancestor).ok()),
+        None => return rules,
+    };
+    let dir = if existing.is_dir() {
+        existing
+    } else {
+        match (existing.parent(), missing) {
+            (Some(parent), Some(missing)) if missing.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => return rules,
+        }
+    };
+    let relative_dir = match dir.strip_prefix(&root) {
+        Ok(relative_dir) => relative_dir,
+        Err(_) => return rules,
+    };
+
+    let mut current = root.clone();
+    rules.merge(DirConfig::read(&current).unwrap_or_default());
+    for component in relative_dir.components() {
+        current.push(component);
+        rules.merge(DirConfig::read(&current).unwrap_or_default());
+    }
+    rules
+}
+
+/// Whether the directory at `dir` has to be left out of the listing of its parent
+pub fn is_hidden(dir: &Path) -> bool {
+    DirConfig::read(dir).is_some_and(|dir_config| dir_config.hidden)
+}
+
+/// Whether the directory at `dir` requires authentication by itself
+pub fn is_restricted(dir: &Path) -> bool {
+    DirConfig::read(dir).is_some_and(|dir_config| dir_config.requires_auth())
+}
+
+/// Whether a subdirectory of `dir`, at any depth, requires authentication
+pub fn has_restricted_subdir(dir: &Path) -> bool {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .any(|path| is_restricted(&path) || has_restricted_subdir(&path))
+}
+
+/// Check that the user of `req` may read, or modify with `write`, the entry at `path`
+pub fn check_access(
+    req: &HttpRequest,
+    conf: &MiniserveConfig,
+    path: &Path,
+    write: bool,
+) -> Result<(), ContextualError> {
+    let extensions = req.extensions();
+    // Share links can only be created by users who can read the shared entry
+    if !write && extensions.get::<SharedAccess>().is_some() {
+        return Ok(());
+    }
+
+    check_user_access(conf, extensions.get::<CurrentUser>(), path, write)
+}
+
+/// Check that `user` may read, or modify with `write`, the entry at `path`
+fn check_user_access(
+    conf: &MiniserveConfig,
+    user: Option<&CurrentUser>,
+    path: &Path,
+    write: bool,
+) -> Result<(), ContextualError> {
+    if !conf.dir_config {
+        return Ok(());
+    }
+    if write && path.file_name().is_some_and(|name| name == DIR_CONFIG_FILE) {
+        return Err(ContextualError::ProtectedFileError(
+            DIR_CONFIG_FILE.to_string(),
+        ));
+    }
+
+    let rules = dir_rules(conf, path);
+    if rules.require_auth && user.is_none() {
+        return Err(ContextualError::InvalidHttpCredentials);
+    }
+    if let (Some(users), Some(user)) = (&rules.users, user) {
+        if !users.contains(&user.name) {
+            return Err(ContextualError::DirectoryAccessDeniedError(
+                user.name.clone(),
+            ));
+        }
+    }
+    if write && rules.uploads_disabled {
+        return Err(ContextualError::UploadsDisabledError);
+    }
+    Ok(())
+}
+
+/// Right of the user of a request to modify the entries of the served directory, which can be
+/// checked away from the request, e.g. for each entry of an extracted archive
+#[derive(Clone)]
+pub struct WriteAccess {
+    conf: MiniserveConfig,
+    user: Option<CurrentUser>,
+}
+
+impl WriteAccess {
+    pub fn new(req: &HttpRequest, conf: &MiniserveConfig) -> Self {
+        WriteAccess {
+            conf: conf.clone(),
+            user: req.extensions().get::<CurrentUser>().cloned(),
+        }
+    }
+
+    /// Check that the user may create or overwrite the entry at `path`
+    pub fn check(&self, path: &Path) -> Result<(), ContextualError> {
+        check_user_access(&self.conf, self.user.as_ref(), path, true)
+    }
+}
+
+/// Response refusing the access of the user of `req` because of `err`, returned by `check_access`
+pub fn access_denied_response(req: &HttpRequest, err: ContextualError) -> HttpResponse {
+    match err {
+        ContextualError::InvalidHttpCredentials => auth::unauthorized_response(req),
+        err => auth::forbidden_response(req, err),
+    }
+}
+
+/// Entry of the served directory requested at the URL path `uri_path`, including the served
+/// directory itself
+pub fn requested_entry(conf: &MiniserveConfig, uri_path: &str) -> Option<PathBuf> {
+    let route = format!("/{}", conf.random_route.clone().unwrap_or_default());
+    if uri_path.trim_end_matches('/') == route.trim_end_matches('/') {
+        return Some(conf.path.clone());
+    }
+    requested_file(conf, uri_path).filter(|path| path.exists())
+}
+
+/// Apply the restrictions of the directory of the requested entry, and never serve the
+/// `.miniserve.toml` files
+pub fn enforce_dir_config<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let (req, payload) = req.into_parts();
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let path = match requested_entry(conf, req.path()) {
+        Some(path) if conf.dir_config && conf.storage.is_none() => path,
+        _ => return Either::Left(srv.call(ServiceRequest::from_parts(req, payload))),
+    };
+
+    if path.file_name().is_some_and(|name| name == DIR_CONFIG_FILE) {
+        let resp = HttpResponse::NotFound().finish();
+        return Either::Right(ready(Ok(ServiceResponse::new(req, resp))));
+    }
+    // Modifications are checked by their handlers, for the entries given in their parameters
+    let result = check_access(&req, conf, &path, false);
+    match result {
+        Ok(()) => Either::Left(srv.call(ServiceRequest::from_parts(req, payload))),
+        Err(err) => {
+            let resp = access_denied_response(&req, err);
+            Either::Right(ready(Ok(ServiceResponse::new(req, resp))))
+        }
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    fn users(names: &[&str]) -> Option<Vec<String>> {
+        Some(names.iter().map(|name| name.to_string()).collect())
+    }
+
+    #[rstest]
+    fn dir_config_is_parsed() {
+        let dir_config: DirConfig = toml::from_str("users = [\"joe\"]\nhidden = true\nuploads = false").unwrap();
+        assert_eq!(
+            dir_config,
+            DirConfig { require_auth: false, users: users(&["joe"]), hidden: true, uploads: Some(false) }
+        );
+        assert!(dir_config.requires_auth());
+        assert!(toml::from_str::<DirConfig>("public = true").is_err());
+    }
+
+    #[rstest]
+    fn restrictions_add_up() {
+        let mut rules = DirRules::default();
+        rules.merge(DirConfig { users: users(&["joe", "ann"]), ..DirConfig::default() });
+        rules.merge(DirConfig { users: users(&["ann", "bob"]), uploads: Some(true), ..DirConfig::default() });
+        rules.merge(DirConfig { uploads: Some(false), ..DirConfig::default() });
+        rules.merge(DirConfig { uploads: Some(true), ..DirConfig::default() });
+        assert_eq!(rules, DirRules { require_auth: true, users: users(&["ann"]), uploads_disabled: true });
+    }
+}
@@ -0,0 +1,320 @@
+//! Disk usage of the directories, shown as a treemap with `?view=usage` when
+//! `--enable-disk-usage` is given, so that users can see what takes space in the served directory
+//! without a shell.
+//!
+//! The sizes of a directory and of all its subdirectories are computed in one walk on the
+//! blocking thread pool, and kept for `USAGE_TTL`, so that browsing down the subdirectories
+//! doesn't walk them again.
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::canonical_path;
+use crate::dir_config::{self, DIR_CONFIG_FILE};
+use crate::ignore::IgnoredEntries;
+use crate::listing_cache;
+
+/// Time during which the computed sizes are shown, before the directories are walked again
+const USAGE_TTL: Duration = Duration::from_secs(2 * 60);
+
+/// Number of directories whose sizes are kept in memory, after which the cache is cleared
+const MAX_CACHED_DIRS: usize = 10_000;
+
+/// Depth of the subdirectories which are walked, which also stops symlink loops
+const MAX_DEPTH: usize = 64;
+
+/// Entry of a directory, with its size
+pub struct UsageEntry {
+    pub name: String,
+    pub is_dir: bool,
+
+    /// Size of the file, or of all the files of the subdirectory, in bytes
+    pub size: u64,
+
+    /// Number of files, 1 for files
+    pub files: u64,
+}
+
+/// Disk usage of a directory
+pub struct DirUsage {
+    /// Entries of the directory, the largest first
+    pub entries: Vec<UsageEntry>,
+
+    /// Size of all the files of the directory and its subdirectories, in bytes
+    pub size: u64,
+
+    /// Number of files of the directory and its subdirectories
+    pub files: u64,
+
+    /// Time the sizes were computed at
+    pub computed: Instant,
+}
+
+/// Tile of the treemap, in percents of the width and height of the map
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub left: f64,
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Entries of the directories which are counted
+struct WalkOptions {
+    skip_symlinks: bool,
+    show_hidden: bool,
+
+    /// Leave out the subdirectories restricted by a .miniserve.toml, and these files
+    dir_config: bool,
+}
+
+/// Disk usage of the directories which were shown recently
+#[derive(Default)]
+pub struct DiskUsageCache {
+    dirs: Mutex<HashMap<PathBuf, Arc<DirUsage>>>,
+}
+
+impl DiskUsageCache {
+    /// Disk usage of `dir`, computed again if it is older than `USAGE_TTL`. The subdirectories
+    /// restricted by a .miniserve.toml are left out with `dir_config`, and the entries matching
+    /// `ignored`.
+    pub fn usage(
+        &self,
+        dir: &Path,
+        skip_symlinks: bool,
+        show_hidden: bool,
+        dir_config: bool,
+        ignored: Option<IgnoredEntries>,
+    ) -> io::Result<Arc<DirUsage>> {
+        let dir = canonical_path::canonicalize(dir)?;
+        {
+            let dirs = self.dirs.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(usage) = dirs.get(&dir) {
+                if usage.computed.elapsed() < USAGE_TTL {
+                    return Ok(usage.clone());
+                }
+            }
+        }
+
+        let options = WalkOptions {
+            skip_symlinks,
+            show_hidden,
+            dir_config,
+        };
+        let mut usages = vec![];
+        walk(
+            &dir,
+            &options,
+            ignored.as_ref(),
+            0,
+            &mut HashSet::new(),
+            &mut usages,
+        )?;
+        let mut dirs = self.dirs.lock().unwrap_or_else(|e| e.into_inner());
+        if dirs.len() + usages.len() > MAX_CACHED_DIRS {
+            dirs.clear();
+        }
+        let mut usage = None;
+        for (path, dir_usage) in usages {
+            let dir_usage = Arc::new(dir_usage);
+            if path == dir {
+                usage = Some(dir_usage.clone());
+            }
+            dirs.insert(path, dir_usage);
+        }
+        usage.ok_or_else(|| io::Error::other("Directory walked already"))
+    }
+}
+
+/// Compute the disk usage of `dir` and of its subdirectories into `usages`. Returns the size and
+/// number of files of `dir`.
+fn walk(
+    dir: &Path,
+    options: &WalkOptions,
+    ignored: Option<&IgnoredEntries>,
+    depth: usize,
+    visited: &mut HashSet<PathBuf>,
+    usages: &mut Vec<(PathBuf, DirUsage)>,
+) -> io::Result<(u64, u64)> {
+    // Symlinks may lead back to a parent directory
+    let canonical_dir = canonical_path::canonicalize(dir)?;
+    if !visited.insert(canonical_dir.clone()) || depth > MAX_DEPTH {
+        return Ok((0, 0));
+    }
+    let mut entries = vec![];
+    for entry in listing_cache::read_entries(dir)? {
+        if (!options.show_hidden && entry.name.starts_with('.'))
+            || (options.skip_symlinks && entry.is_symlink)
+        {
+            continue;
+        }
+        let path = dir.join(&entry.name);
+        if options.dir_config
+            && (entry.name == DIR_CONFIG_FILE || (entry.is_dir && dir_config::is_restricted(&path)))
+        {
+            continue;
+        }
+        if ignored.is_some_and(|ignored| ignored.is_ignored(&entry.name, entry.is_dir)) {
+            continue;
+        }
+        let (size, files) = if entry.is_dir {
+            let ignored = ignored.and_then(|ignored| ignored.subdir(&entry.name));
+            // Unreadable subdirectories count as empty
+            walk(&path, options, ignored.as_ref(), depth + 1, visited, usages).unwrap_or((0, 0))
+        } else {
+            (entry.size, 1)
+        };
+        entries.push(UsageEntry {
+            name: entry.name.clone(),
+            is_dir: entry.is_dir,
+            size,
+            files,
+        });
+    }
+    entries.sort_by(|entry1, entry2| {
+        entry2
+            .size
+            .cmp(&entry1.size)
+            .then_with(|| entry1.name.cmp(&entry2.name))
+    });
+
+    let size = entries.iter().map(|entry| entry.size).sum();
+    let files = entries.iter().map(|entry| entry.files).sum();
+    usages.push((
+        canonical_dir,
+        DirUsage {
+            entries,
+            size,
+            files,
+            computed: Instant::now(),
+        },
+    ));
+    Ok((size, files))
+}
+
+/// Highest ratio between the longer and the shorter side of the tiles of `row`, the areas of
+/// tiles laid out along a side of length `side`
+fn worst_ratio(row: &[f64], side: f64) -> f64 {
+    let sum = row.iter().sum::<f64>();
+    let max = row.iter().cloned().fold(f64::MIN, f64::max);
+    let min = row.iter().cloned().fold(f64::MAX, f64::min);
+    f64::max(
+        side * side * max / (sum * sum),
+        sum * sum / (side * side * min),
+    )
+}
+
+/// Squarified treemap of `sizes`, sorted from the largest, in a map `aspect_ratio` times wider
+/// than high. The tiles are as close to squares as possible, and the empty entries get no tile.
+pub fn treemap(sizes: &[u64], aspect_ratio: f64) -> Vec<Option<Tile>> {
+    let total = sizes.iter().sum::<u64>() as f64;
+    let mut tiles = vec![None; sizes.len()];
+    if total == 0.0 {
+        return tiles;
+    }
+    // The areas are laid out in a rectangle of the aspect ratio of the map, with an area of 1
+    let areas = sizes
+        .iter()
+        .map(|size| *size as f64 / total)
+        .take_while(|area| *area > 0.0)
+        .collect::<Vec<_>>();
+    let (mut left, mut top) = (0.0, 0.0);
+    let (mut width, mut height) = (aspect_ratio.sqrt(), 1.0 / aspect_ratio.sqrt());
+    let mut start = 0;
+    while start < areas.len() {
+        let side = f64::min(width, height);
+        let mut end = start + 1;
+        while end < areas.len()
+            && worst_ratio(&areas[start..=end], side) <= worst_ratio(&areas[start..end], side)
+        {
+            end += 1;
+        }
+
+        // The row fills the shorter side of the remaining space
+        let row_area = areas[start..end].iter().sum::<f64>();
+        let thickness = row_area / side;
+        let mut offset = 0.0;
+        for (i, area) in areas[start..end].iter().enumerate() {
+            let length = area / thickness;
+            let (tile_left, tile_top, tile_width, tile_height) = if width >= height {
+                (left, top + offset, thickness, length)
+            } else {
+                (left + offset, top, length, thickness)
+            };
+            tiles[start + i] = Some(Tile {
+                left: tile_left / aspect_ratio.sqrt() * 100.0,
+                top: tile_top * aspect_ratio.sqrt() * 100.0,
+                width: tile_width / aspect_ratio.sqrt() * 100.0,
+                height: tile_height * aspect_ratio.sqrt() * 100.0,
+            });
+            offset += length;
+        }
+        if width >= height {
+            left += thickness;
+            width -= thickness;
+        } else {
+            top += thickness;
+            height -= thickness;
+        }
+        start = end;
+    }
+    tiles
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn sizes_include_subdirectories() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub/deep")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "hello").unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), "hello, world").unwrap();
+        std::fs::write(dir.path().join("sub/deep/c.txt"), "hello, world").unwrap();
+        std::fs::write(dir.path().join(".hidden"), "hello").unwrap();
+
+        let cache = DiskUsageCache::default();
+        let usage = cache.usage(dir.path(), false, false, false, None).unwrap();
+        assert_eq!((usage.size, usage.files), (29, 3));
+        assert_eq!(
+            usage.entries.iter().map(|entry| (entry.name.as_str(), entry.size)).collect::<Vec<_>>(),
+            vec![("sub", 24), ("a.txt", 5)]
+        );
+
+        // The subdirectories were computed along
+        std::fs::write(dir.path().join("sub/deep/d.txt"), "hello").unwrap();
+        let usage = cache.usage(&dir.path().join("sub/deep"), false, false, false, None).unwrap();
+        assert_eq!((usage.size, usage.files), (12, 1));
+    }
+
+    #[rstest]
+    fn treemap_fills_the_map() {
+        let sizes = [6, 6, 4, 3, 2, 2, 1, 0];
+        let tiles = treemap(&sizes, 1.5);
+        assert_eq!(tiles[7], None);
+
+        let tiles = tiles.iter().flatten().collect::<Vec<_>>();
+        let area = tiles.iter().map(|tile| tile.width * tile.height).sum::<f64>();
+        assert!((area - 100.0 * 100.0).abs() < 1e-6);
+        for tile in &tiles {
+            assert!(tile.left >= -1e-9 && tile.left + tile.width <= 100.0 + 1e-9);
+            assert!(tile.top >= -1e-9 && tile.top + tile.height <= 100.0 + 1e-9);
+        }
+        for (size, tile) in sizes.iter().zip(&tiles) {
+            let share = *size as f64 / 24.0 * 100.0 * 100.0;
+            assert!((tile.width * tile.height - share).abs() < 1e-6);
+        }
+    }
+
+    #[rstest]
+    fn empty_directories_have_no_tiles() {
+        assert_eq!(treemap(&[0, 0], 2.0), vec![None, None]);
+    }
+}
@@ -0,0 +1,277 @@
+//! Number of downloads and of bytes sent of each file, counted with `--download-stats <file>` and
+//! kept in this file across restarts.
+//!
+//! The most downloaded files are listed on the status page, and the counts can be shown in the
+//! listings with `--show-download-counts`.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::web;
+use futures::future::TryFutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::auto_shutdown::AutoShutdown;
+use crate::canonical_path;
+use crate::storage::requested_file;
+use crate::MiniserveConfig;
+
+/// How often the counters are written to the state file, when they changed
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Number of files listed on the status page
+const TOP_FILES: usize = 20;
+
+/// Counters of a file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct FileStats {
+    /// Number of times the whole file was sent
+    pub downloads: u64,
+
+    /// Bytes sent, including the parts of the file requested with a Range header
+    pub bytes: u64,
+}
+
+/// Counters of a file, as listed on the status page
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FileStatsEntry {
+    pub path: String,
+
+    #[serde(flatten)]
+    pub stats: FileStats,
+}
+
+/// Counters of the downloaded files, by canonical path
+pub struct DownloadStats {
+    /// State file, without which nothing is counted
+    path: Option<PathBuf>,
+
+    files: Mutex<BTreeMap<String, FileStats>>,
+
+    /// Whether the counters changed since they were saved
+    changed: AtomicBool,
+}
+
+impl DownloadStats {
+    /// Counters saved in the state file at `path`, if it exists
+    pub fn load(path: Option<PathBuf>) -> io::Result<Self> {
+        let files = match &path {
+            Some(path) if path.exists() => serde_json::from_slice(&std::fs::read(path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            _ => BTreeMap::new(),
+        };
+        Ok(DownloadStats {
+            path,
+            files: Mutex::new(files),
+            changed: AtomicBool::new(false),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Count `bytes` sent of `file`, and a download if the whole file was sent
+    fn record(&self, file: &Path, whole_file: bool, bytes: u64) {
+        let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = files.entry(file.to_string_lossy().to_string()).or_default();
+        if whole_file {
+            stats.downloads += 1;
+        }
+        stats.bytes += bytes;
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Counters of `file`, which must be canonical
+    pub fn get(&self, file: &Path) -> FileStats {
+        let files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        files
+            .get(&*file.to_string_lossy())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Most downloaded files, with their paths relative to `root` when they are inside of it
+    pub fn top_files(&self, root: &Path) -> Vec<FileStatsEntry> {
+        let root = canonical_path::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        let files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries = files
+            .iter()
+            .map(|(path, stats)| FileStatsEntry {
+                path: match Path::new(path).strip_prefix(&root) {
+                    Ok(relative) => {
+                        let components = relative
+                            .components()
+                            .map(|c| c.as_os_str().to_string_lossy())
+                            .collect::<Vec<_>>();
+                        format!("/{}", components.join("/"))
+                    }
+                    Err(_) => path.clone(),
+                },
+                stats: *stats,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|e1, e2| {
+            (e2.stats.downloads, e2.stats.bytes).cmp(&(e1.stats.downloads, e1.stats.bytes))
+        });
+        entries.truncate(TOP_FILES);
+        entries
+    }
+
+    /// Write the counters to the state file, if they changed since they were saved
+    pub fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) if self.changed.swap(false, Ordering::Relaxed) => path,
+            _ => return Ok(()),
+        };
+        let content = {
+            let files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+            serde_json::to_vec_pretty(&*files)?
+        };
+
+        // Readers never see a partially written file
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, content)?;
+        std::fs::rename(&temp_path, path).inspect_err(|_| {
+            self.changed.store(true, Ordering::Relaxed);
+        })
+    }
+
+    /// Save the counters regularly while the server is running
+    pub async fn save_regularly(stats: web::Data<DownloadStats>) {
+        loop {
+            actix_web::rt::time::sleep(SAVE_INTERVAL).await;
+            if let Err(e) = stats.save() {
+                log::error!("Failed to save the download statistics: {}", e);
+            }
+        }
+    }
+}
+
+/// Length of the range of a Content-Range header like `bytes 0-99/1000`
+fn range_length(content_range: &str) -> Option<u64> {
+    let range = content_range.strip_prefix("bytes ")?.split('/').next()?;
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (
+        start.trim().parse::<u64>().ok()?,
+        end.trim().parse::<u64>().ok()?,
+    );
+    end.checked_sub(start).map(|length| length + 1)
+}
+
+/// Count the files of the served directory which are sent, if the counters are enabled
+pub fn count_downloads<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    srv.call(req).map_ok(|res| {
+        let req = res.request();
+        let whole_file = match res.status() {
+            StatusCode::OK => true,
+            StatusCode::PARTIAL_CONTENT => false,
+            _ => return res,
+        };
+        if req.method() != Method::GET {
+            return res;
+        }
+        let stats = req
+            .app_data::<web::Data<DownloadStats>>()
+            .filter(|stats| stats.is_enabled());
+        let auto_shutdown = req
+            .app_data::<web::Data<AutoShutdown>>()
+            .filter(|auto_shutdown| auto_shutdown.counts_downloads());
+        if stats.is_none() && auto_shutdown.is_none() {
+            return res;
+        }
+        let file = match req
+            .app_data::<MiniserveConfig>()
+            .and_then(|conf| requested_file(conf, req.path()))
+            .and_then(|file| canonical_path::canonicalize(&file).ok())
+        {
+            Some(file) if file.is_file() => file,
+            _ => return res,
+        };
+
+        if whole_file {
+            if let Some(auto_shutdown) = auto_shutdown {
+                auto_shutdown.record_download();
+            }
+        }
+        let stats = match stats {
+            Some(stats) => stats,
+            None => return res,
+        };
+
+        // The body may be compressed, so the size of the file or of the range is counted instead
+        let bytes = if whole_file {
+            file.metadata().map(|metadata| metadata.len()).ok()
+        } else {
+            res.headers()
+                .get(header::CONTENT_RANGE)
+                .and_then(|range| range.to_str().ok())
+                .and_then(range_length)
+        };
+        stats.record(&file, whole_file, bytes.unwrap_or(0));
+        res
+    })
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn downloads_are_counted() {
+        let stats = DownloadStats::load(Some(PathBuf::from("stats.json"))).unwrap();
+        stats.record(Path::new("/srv/a.txt"), true, 10);
+        stats.record(Path::new("/srv/a.txt"), false, 4);
+        stats.record(Path::new("/srv/b.txt"), true, 3);
+        stats.record(Path::new("/srv/b.txt"), true, 3);
+
+        assert_eq!(stats.get(Path::new("/srv/a.txt")), FileStats { downloads: 1, bytes: 14 });
+        assert_eq!(stats.get(Path::new("/srv/c.txt")), FileStats::default());
+        assert_eq!(
+            stats.top_files(Path::new("/srv")),
+            vec![
+                FileStatsEntry { path: "/b.txt".to_string(), stats: FileStats { downloads: 2, bytes: 6 } },
+                FileStatsEntry { path: "/a.txt".to_string(), stats: FileStats { downloads: 1, bytes: 14 } },
+            ]
+        );
+    }
+
+    #[rstest(content_range, length,
+        case("bytes 0-99/1000", Some(100)),
+        case("bytes 10-10/*", Some(1)),
+        case("bytes */1000", None),
+        case("bytes 99-0/1000", None)
+    )]
+    fn range_length_is_parsed(content_range: &str, length: Option<u64>) {
+        assert_eq!(range_length(content_range), length);
+    }
+
+    #[rstest]
+    fn counters_are_saved_and_loaded() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("stats.json");
+        let stats = DownloadStats::load(Some(path.clone())).unwrap();
+        stats.record(Path::new("/srv/a.txt"), true, 10);
+        stats.save().unwrap();
+
+        let stats = DownloadStats::load(Some(path)).unwrap();
+        assert_eq!(stats.get(Path::new("/srv/a.txt")), FileStats { downloads: 1, bytes: 10 });
+    }
+}
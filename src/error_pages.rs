@@ -0,0 +1,91 @@
+//! Custom error pages given with `--error-page`, e.g. the branded 404 page of a static site, sent
+//! instead of the built-in ones.
+use actix_web::body::AnyBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use futures::future::TryFutureExt;
+use std::future::Future;
+use std::path::PathBuf;
+
+use crate::errors::ContextualError;
+use crate::MiniserveConfig;
+
+/// Parse a page like `404=/srv/site/404.html`, into the error status and the path of the page
+pub fn parse_error_page(src: &str) -> Result<(StatusCode, PathBuf), ContextualError> {
+    let parse_error =
+        |cause: &str| ContextualError::ParseError(format!("error page {}", src), cause.to_string());
+
+    let (status, path) = src
+        .split_once('=')
+        .ok_or_else(|| parse_error("expected <status>=<path>"))?;
+    let status = status
+        .trim()
+        .parse::<u16>()
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .filter(|status| status.is_client_error() || status.is_server_error())
+        .ok_or_else(|| parse_error("expected an error status between 400 and 599"))?;
+    let path = path.trim();
+    if path.is_empty() {
+        return Err(parse_error("empty path"));
+    }
+    Ok((status, PathBuf::from(path)))
+}
+
+/// Send the pages of `--error-page` instead of the bodies of the responses with their status
+pub fn replace_error_page<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    srv.call(req).map_ok(|mut res| {
+        let page = res
+            .request()
+            .app_data::<MiniserveConfig>()
+            .and_then(|conf| conf.error_pages.get(&res.status()))
+            .cloned();
+        let page = match page {
+            Some(page) => page,
+            None => return res,
+        };
+        let headers = res.headers_mut();
+        // The original body may have been compressed
+        headers.remove(header::CONTENT_ENCODING);
+        headers.remove(header::CONTENT_LENGTH);
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        res.map_body(|_, _| AnyBody::from(page))
+    })
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(src, status, path,
+        case("404=/srv/site/404.html", StatusCode::NOT_FOUND, "/srv/site/404.html"),
+        case("500 = errors/500.html", StatusCode::INTERNAL_SERVER_ERROR, "errors/500.html")
+    )]
+    fn error_page_is_parsed(src: &str, status: StatusCode, path: &str) {
+        assert_eq!(parse_error_page(src).unwrap(), (status, PathBuf::from(path)));
+    }
+
+    #[rstest(src,
+        case("404"),
+        case("200=ok.html"),
+        case("abc=404.html"),
+        case("404=")
+    )]
+    fn invalid_error_pages_are_rejected(src: &str) {
+        assert!(parse_error_page(src).is_err());
+    }
+}
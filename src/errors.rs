@@ -14,6 +14,46 @@ pub enum ContextualError {
     #[error("File already exists, and the overwrite_files option has not been set")]
     DuplicateFileError,
 
+    /// Might occur when renaming or moving an entry
+    #[error("{0} already exists")]
+    DestinationExistsError(String),
+
+    /// Might occur when saving a file which was modified since it was opened in the editor
+    #[error("{0} was changed by someone else since it was opened, save again to overwrite these changes")]
+    EditConflictError(String),
+
+    /// Might occur during file upload, when a file is larger than allowed by --max-upload-size
+    #[error("File exceeds the maximum upload size of {0} bytes")]
+    UploadTooLargeError(u64),
+
+    /// Might occur during file upload, when the total size allowed by --upload-quota is exhausted
+    #[error("Upload quota exceeded")]
+    UploadQuotaExceededError,
+
+    /// Might occur during file upload, when the user exhausted their storage quota given with
+    /// --quota
+    #[error("Storage quota of {0} bytes exceeded")]
+    UserQuotaExceededError(u64),
+
+    /// Might occur during file upload, when the --upload-hook command rejects the file
+    #[error("{0}")]
+    UploadRejectedError(String),
+
+    /// Might occur during file upload, when the checksum of the received file doesn't match the
+    /// one sent by the client
+    #[error(
+        "The checksum of the uploaded file does not match, it was corrupted during the transfer"
+    )]
+    UploadChecksumMismatchError,
+
+    /// Might occur during resumable uploads, when a chunk doesn't start where the previous one ended
+    #[error("Upload offset does not match the {0} bytes received so far")]
+    UploadOffsetMismatchError(u64),
+
+    /// Might occur during resumable uploads, when a chunk is sent while another one is received
+    #[error("Another chunk of this upload is being received")]
+    UploadInProgressError,
+
     /// Any error related to an invalid path (failed to retrieve entry name, unexpected entry type, etc)
     #[error("Invalid path\ncaused by: {0}")]
     InvalidPathError(String),
@@ -22,6 +62,10 @@ pub enum ContextualError {
     #[error("Invalid format for credentials string. Expected username:password, username:sha256:hash or username:sha512:hash")]
     InvalidAuthFormat,
 
+    /// Might occur if an API token is empty
+    #[error("API tokens must not be empty")]
+    InvalidApiToken,
+
     /// Might occure if the hash method is neither sha256 nor sha512
     #[error("{0} is not a valid hashing method. Expected sha256 or sha512")]
     InvalidHashMethod(String),
@@ -50,6 +94,22 @@ pub enum ContextualError {
     #[error("{0}")]
     ArchiveCreationDetailError(String),
 
+    /// Might occur when the archive served with --from-archive can't be read
+    #[error("Failed to read the archive\ncaused by: {0}")]
+    ArchiveReadError(String),
+
+    /// Might occur when the object storage served with --s3-bucket can't be reached or read
+    #[error("Failed to access the object storage\ncaused by: {0}")]
+    StorageError(String),
+
+    /// Might occur when the upstream server of --mirror-upstream can't be reached or read
+    #[error("Failed to mirror the upstream server\ncaused by: {0}")]
+    MirrorError(String),
+
+    /// Might occur when an uploaded archive can't be extracted
+    #[error("Failed to extract the archive\ncaused by: {0}")]
+    ArchiveExtractionError(String),
+
     /// Might occur when the HTTP authentication fails
     #[error("An error occured during HTTP authentication\ncaused by: {0}")]
     HttpAuthenticationError(Box<ContextualError>),
@@ -58,6 +118,44 @@ pub enum ContextualError {
     #[error("Invalid credentials for HTTP authentication")]
     InvalidHttpCredentials,
 
+    /// Might occur when a client failed to authenticate too many times in a row
+    #[error("Too many failed authentication attempts, try again in {0} seconds")]
+    TooManyAuthFailuresError(u64),
+
+    /// Might occur when read-only credentials are used to modify the served directory
+    #[error("The provided credentials do not allow modifying files")]
+    InsufficientAuthPermissionsError,
+
+    /// Might occur when a user isn't among the users allowed by the .miniserve.toml of a directory
+    #[error("{0} is not allowed to access this directory")]
+    DirectoryAccessDeniedError(String),
+
+    /// Might occur with --user-dirs, when a user has no directory, e.g. an API token or a user
+    /// added after miniserve started
+    #[error("{0} has no directory of their own")]
+    NoUserDirectoryError(String),
+
+    /// Might occur when a single-use share link is opened after the file was downloaded with it,
+    /// or while it is being downloaded
+    #[error("This share link was already used")]
+    ShareLinkUsedError,
+
+    /// Might occur with --upnp-forward, when the router can't be found or refuses the mapping
+    #[error("Failed to forward the port with UPnP\ncaused by: {0}")]
+    UpnpError(String),
+
+    /// Might occur when uploading to a directory whose .miniserve.toml disables uploads
+    #[error("Uploads are disabled in this directory")]
+    UploadsDisabledError,
+
+    /// Might occur when trying to modify the .miniserve.toml file of a directory
+    #[error("{0} can't be modified")]
+    ProtectedFileError(String),
+
+    /// Might occur when modifying the served directory while read-only mode is enabled
+    #[error("miniserve is in read-only mode, files can't be modified")]
+    ReadOnlyModeError,
+
     /// Might occur when an HTTP request is invalid
     #[error("Invalid HTTP request\ncaused by: {0}")]
     InvalidHttpRequestError(String),
@@ -66,6 +164,19 @@ pub enum ContextualError {
     #[error("Route {0} could not be found")]
     RouteNotFoundError(String),
 
+    /// Might occur when a certificate can't be obtained from the ACME server
+    #[error("Failed to obtain a certificate\ncaused by: {0}")]
+    AcmeError(String),
+
+    /// Might occur when the export of the traces to --otlp-endpoint can't be set up
+    #[cfg(feature = "otlp")]
+    #[error("Failed to set up the export of traces\ncaused by: {0}")]
+    TelemetryError(String),
+
+    /// Might occur when the configuration file can't be parsed or contains invalid values
+    #[error("Invalid configuration file\ncaused by: {0}")]
+    ConfigFileError(String),
+
     /// In case miniserve was invoked without an interactive terminal and without an explicit path
     #[error("Refusing to start as no explicit serve path was set and no interactive terminal was attached
 Please set an explicit serve path like: `miniserve /my/path`")]
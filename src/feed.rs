@@ -0,0 +1,292 @@
+//! Atom feed of the newest files of the served directory with `--enable-feed`, so that a
+//! directory of build artifacts or podcast episodes can be subscribed to.
+//!
+//! The feed of the whole directory is at `/feed.xml`, and the one of a subdirectory at
+//! `/feed.xml?path=/subdirectory`.
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::dir_config;
+use crate::ignore::IgnoredEntries;
+use crate::listing::percent_encode_sets::PATH_SEGMENT;
+use crate::proxy;
+use crate::storage::served_path;
+use crate::MiniserveConfig;
+
+/// Route of the feed, relative to the route of the served directory
+pub const FEED_ROUTE: &str = "/feed.xml";
+
+/// Number of files in the feed
+const MAX_FEED_ENTRIES: usize = 50;
+
+/// Depth of the subdirectories searched for files, which also stops symlink loops
+const MAX_DEPTH: usize = 16;
+
+/// Query parameters of the feed
+#[derive(Deserialize)]
+pub struct FeedQuery {
+    /// Directory whose files are in the feed, relative to the served directory
+    path: Option<String>,
+}
+
+/// File of the feed
+struct FeedEntry {
+    /// Path relative to the directory of the feed
+    path: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Add the files of `dir` and of its subdirectories to `entries`, keeping the newest ones
+fn find_newest_files(
+    conf: &MiniserveConfig,
+    dir: &Path,
+    prefix: &str,
+    depth: usize,
+    ignored: Option<&IgnoredEntries>,
+    entries: &mut Vec<FeedEntry>,
+) -> io::Result<()> {
+    for entry in dir.read_dir()? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') && !conf.show_hidden {
+            continue;
+        }
+        let is_symlink = entry.file_type()?.is_symlink();
+        if is_symlink && conf.no_symlinks {
+            continue;
+        }
+        // for symlinks, get the metadata of the original file
+        let metadata = match std::fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+
+        if ignored.is_some_and(|ignored| ignored.is_ignored(&name, metadata.is_dir())) {
+            continue;
+        }
+
+        let path = format!("{}{}", prefix, name);
+        if metadata.is_dir() {
+            // Restricted subdirectories are only in their own feed
+            if depth < MAX_DEPTH && !(conf.dir_config && dir_config::is_restricted(&entry.path())) {
+                // Unreadable subdirectories are left out of the feed
+                let ignored = ignored.and_then(|ignored| ignored.subdir(&name));
+                let _ = find_newest_files(
+                    conf,
+                    &entry.path(),
+                    &(path + "/"),
+                    depth + 1,
+                    ignored.as_ref(),
+                    entries,
+                );
+            }
+        } else if let (true, Ok(modified)) = (metadata.is_file(), metadata.modified()) {
+            entries.push(FeedEntry {
+                path,
+                size: metadata.len(),
+                modified,
+            });
+            // Keep the memory bounded in huge directories
+            if entries.len() >= 2 * MAX_FEED_ENTRIES {
+                keep_newest(entries);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sort `entries` from the newest one, and drop those which don't fit in the feed
+fn keep_newest(entries: &mut Vec<FeedEntry>) {
+    entries.sort_by_key(|entry| Reverse(entry.modified));
+    entries.truncate(MAX_FEED_ENTRIES);
+}
+
+/// Escape `text` for XML content and attributes
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Format `time` for the dates of the feed
+pub fn rfc3339(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Percent-encode the segments of the relative `path` for URLs
+pub fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// URL of the served directory, with a trailing slash
+pub fn root_url(req: &HttpRequest, conf: &MiniserveConfig) -> String {
+    format!(
+        "{}://{}/{}",
+        proxy::scheme(req),
+        proxy::host(req),
+        conf.random_route
+            .as_ref()
+            .map(|route| format!("{}/", route))
+            .unwrap_or_default()
+    )
+}
+
+/// Render the feed of `entries`, the newest files of the directory at the URL `dir_url`
+fn render_feed(title: &str, feed_url: &str, dir_url: &str, entries: &[FeedEntry]) -> String {
+    let updated = entries
+        .first()
+        .map_or_else(SystemTime::now, |entry| entry.modified);
+
+    let mut feed = String::new();
+    feed += "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n";
+    feed += "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n";
+    feed += &format!("  <title>{}</title>\n", escape(title));
+    feed += &format!("  <id>{}</id>\n", escape(feed_url));
+    feed += &format!("  <link rel=\"self\" href=\"{}\"/>\n", escape(feed_url));
+    feed += &format!("  <link href=\"{}\"/>\n", escape(dir_url));
+    feed += &format!("  <updated>{}</updated>\n", rfc3339(updated));
+    feed += &format!("  <author><name>{}</name></author>\n", escape(title));
+    feed += &format!(
+        "  <generator uri=\"https://github.com/svenstaro/miniserve\" version=\"{}\">miniserve</generator>\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    for entry in entries {
+        let url = format!("{}{}", dir_url, encode_path(&entry.path));
+        let mime = mime_guess::from_path(&entry.path).first_or_octet_stream();
+        feed += "  <entry>\n";
+        feed += &format!("    <title>{}</title>\n", escape(&entry.path));
+        feed += &format!("    <id>{}</id>\n", escape(&url));
+        feed += &format!("    <link href=\"{}\"/>\n", escape(&url));
+        // Lets podcast clients download the files
+        feed += &format!(
+            "    <link rel=\"enclosure\" href=\"{}\" type=\"{}\" length=\"{}\"/>\n",
+            escape(&url),
+            escape(mime.as_ref()),
+            entry.size
+        );
+        feed += &format!("    <updated>{}</updated>\n", rfc3339(entry.modified));
+        feed += "  </entry>\n";
+    }
+    feed += "</feed>\n";
+    feed
+}
+
+/// Serve the feed of the newest files of the directory at `query.path`
+pub async fn feed(req: HttpRequest, query: web::Query<FeedQuery>) -> HttpResponse {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let relative_path = query.path.clone().unwrap_or_default();
+    let is_ignored = |dir: &Path| {
+        conf.ignore
+            .as_ref()
+            .is_some_and(|ignore| !conf.serve_ignored && ignore.is_ignored(&conf.path, dir))
+    };
+    let dir = match served_path(conf, &relative_path) {
+        Some(dir) if dir.is_dir() && !is_ignored(&dir) => dir,
+        _ => return crate::error_404(req).await,
+    };
+    if let Err(err) = dir_config::check_access(&req, conf, &dir, false) {
+        return dir_config::access_denied_response(&req, err);
+    }
+
+    let walk_conf = conf.clone();
+    let entries = web::block(move || {
+        let mut entries = vec![];
+        let ignored = walk_conf
+            .ignore
+            .as_ref()
+            .and_then(|ignore| ignore.dir(&walk_conf.path, &dir));
+        find_newest_files(&walk_conf, &dir, "", 0, ignored.as_ref(), &mut entries)?;
+        keep_newest(&mut entries);
+        Ok::<_, io::Error>(entries)
+    })
+    .await
+    .unwrap_or_else(|e| Err(io::Error::other(e.to_string())));
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::error!("Failed to list the files of the feed: {}", e);
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body("Failed to list the files of the feed");
+        }
+    };
+
+    let host = proxy::host(&req);
+    let root_url = root_url(&req, conf);
+    let relative_dir = relative_path.trim_matches('/');
+    let dir_url = if relative_dir.is_empty() {
+        root_url.clone()
+    } else {
+        format!("{}{}/", root_url, encode_path(relative_dir))
+    };
+    let feed_url = format!(
+        "{}{}{}",
+        root_url.trim_end_matches('/'),
+        FEED_ROUTE,
+        query
+            .path
+            .as_ref()
+            .map(|path| format!("?path={}", utf8_percent_encode(path, NON_ALPHANUMERIC)))
+            .unwrap_or_default()
+    );
+    let title = match (&conf.title, relative_dir) {
+        (Some(title), "") => title.clone(),
+        (Some(title), dir) => format!("{} - {}", title, dir),
+        (None, "") => host,
+        (None, dir) => format!("{} - {}", host, dir),
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .body(render_feed(&title, &feed_url, &dir_url, &entries))
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[rstest]
+    fn newest_files_are_kept() {
+        let mut entries = (0..3 * MAX_FEED_ENTRIES as u64)
+            .map(|i| FeedEntry {
+                path: i.to_string(),
+                size: 0,
+                modified: SystemTime::UNIX_EPOCH + Duration::from_secs(i),
+            })
+            .collect::<Vec<_>>();
+        keep_newest(&mut entries);
+        assert_eq!(entries.len(), MAX_FEED_ENTRIES);
+        assert_eq!(entries[0].path, (3 * MAX_FEED_ENTRIES - 1).to_string());
+    }
+
+    #[rstest]
+    fn feed_is_escaped() {
+        let entries = vec![FeedEntry {
+            path: "a <b> & c.mp3".to_string(),
+            size: 3,
+            modified: SystemTime::UNIX_EPOCH,
+        }];
+        let feed = render_feed("Podcast", "http://localhost/feed.xml", "http://localhost/", &entries);
+        assert!(feed.contains("<title>a &lt;b&gt; &amp; c.mp3</title>"));
+        assert!(feed.contains("href=\"http://localhost/a%20%3Cb%3E%20&amp;%20c.mp3\""));
+        assert!(feed.contains("type=\"audio/mpeg\" length=\"3\""));
+        assert!(feed.contains("<updated>1970-01-01T00:00:00Z</updated>"));
+    }
+}
@@ -0,0 +1,498 @@
+//! Operations modifying existing entries of the served directory.
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::archive;
+use crate::auth;
+use crate::canonical_path;
+use crate::dir_config;
+use crate::errors::{self, ContextualError};
+use crate::file_upload::{partial_upload_path, sanitize_upload_name};
+use crate::listing::percent_encode_sets::PATH;
+use crate::renderer;
+use crate::webhook::{self, Change, ChangeAction};
+
+/// Route renaming or moving an entry
+pub const RENAME_ROUTE: &str = "/__rename";
+
+/// Route of the text editor
+pub const EDIT_ROUTE: &str = "/__edit";
+
+/// Route extracting an archive of the served directory next to it
+pub const EXTRACT_ROUTE: &str = "/__extract";
+
+/// Query parameters of the text editor
+#[derive(Deserialize)]
+pub struct EditQuery {
+    /// Path of the edited file, relative to the served directory
+    path: String,
+
+    /// Set after the file was saved
+    saved: Option<bool>,
+}
+
+/// Fields submitted by the text editor
+#[derive(Deserialize)]
+pub struct EditForm {
+    content: String,
+
+    /// Modification time of the file when it was opened, to detect concurrent changes
+    modified: String,
+}
+
+/// Fields of a rename request
+#[derive(Deserialize)]
+pub struct RenameForm {
+    /// Current path of the entry, relative to the served directory
+    from: String,
+
+    /// New path of the entry, relative to the served directory
+    to: String,
+}
+
+/// Fields of an extraction request
+#[derive(Deserialize)]
+pub struct ExtractForm {
+    /// Path of the archive, relative to the served directory
+    path: String,
+}
+
+/// Resolve `path`, relative to the canonical served directory `root`.
+///
+/// The parent directory of the entry has to exist inside of `root`. The entry itself is not
+/// resolved, so that a symlink is renamed rather than its target.
+fn resolve_entry(root: &Path, path: &str, no_symlinks: bool) -> Result<PathBuf, ContextualError> {
+    let relative_path = sanitize_upload_name(path.trim_start_matches('/'))?;
+    let parent = root.join(relative_path.parent().unwrap_or_else(|| Path::new("")));
+    let file_name = relative_path.file_name().unwrap_or_default();
+
+    let canonical_parent = canonical_path::canonicalize(&parent).map_err(|_| {
+        ContextualError::InvalidPathError(format!("the directory of {} does not exist", path))
+    })?;
+    if !canonical_parent.starts_with(root) {
+        return Err(ContextualError::InvalidPathError(format!(
+            "{} is outside of the served directory",
+            path
+        )));
+    }
+    if no_symlinks && canonical_parent != parent {
+        return Err(ContextualError::InvalidPathError(format!(
+            "{} is inside of a symlink",
+            path
+        )));
+    }
+
+    Ok(canonical_parent.join(file_name))
+}
+
+/// Rename or move the entry at `form.from` to `form.to`, on behalf of the user of `req`
+fn rename_entry(req: &HttpRequest, form: &RenameForm) -> Result<Change, ContextualError> {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let root = canonical_path::canonicalize(&conf.path).map_err(|e| {
+        ContextualError::IoError("Failed to resolve path served by miniserve".to_string(), e)
+    })?;
+    let from = resolve_entry(&root, &form.from, conf.no_symlinks)?;
+    let to = resolve_entry(&root, &form.to, conf.no_symlinks)?;
+    dir_config::check_access(req, conf, &from, true)?;
+    dir_config::check_access(req, conf, &to, true)?;
+
+    let metadata = from
+        .symlink_metadata()
+        .map_err(|_| ContextualError::RouteNotFoundError(form.from.clone()))?;
+    if conf.no_symlinks && metadata.file_type().is_symlink() {
+        return Err(ContextualError::RouteNotFoundError(form.from.clone()));
+    }
+    if to.symlink_metadata().is_ok() {
+        return Err(ContextualError::DestinationExistsError(form.to.clone()));
+    }
+    if to.starts_with(&from) {
+        return Err(ContextualError::InvalidPathError(format!(
+            "cannot move {} into itself",
+            form.from
+        )));
+    }
+
+    std::fs::rename(&from, &to)
+        .map_err(|e| ContextualError::IoError(format!("Failed to rename {}", form.from), e))?;
+
+    Ok(Change {
+        action: ChangeAction::Rename,
+        path: to,
+        previous_path: Some(from),
+        size: if metadata.is_file() {
+            metadata.len()
+        } else {
+            0
+        },
+        extracted_files: Vec::new(),
+    })
+}
+
+/// Convenience method for creating plain text responses, if an operation fails
+fn operation_error(err: ContextualError) -> HttpResponse {
+    errors::log_error_chain(err.to_string());
+    let mut resp = match &err {
+        ContextualError::InvalidPathError(_) => HttpResponse::BadRequest(),
+        ContextualError::RouteNotFoundError(_) => HttpResponse::NotFound(),
+        ContextualError::DestinationExistsError(_) | ContextualError::DuplicateFileError => {
+            HttpResponse::Conflict()
+        }
+        ContextualError::ArchiveExtractionError(_) => HttpResponse::BadRequest(),
+        ContextualError::InsufficientAuthPermissionsError
+        | ContextualError::DirectoryAccessDeniedError(_)
+        | ContextualError::UploadsDisabledError
+        | ContextualError::ProtectedFileError(_) => HttpResponse::Forbidden(),
+        ContextualError::InvalidHttpCredentials => HttpResponse::Unauthorized(),
+        _ => HttpResponse::InternalServerError(),
+    };
+    resp.content_type("text/plain; charset=utf-8")
+        .body(err.to_string())
+}
+
+/// Handle requests renaming or moving an entry
+pub async fn rename(req: HttpRequest, form: web::Form<RenameForm>) -> HttpResponse {
+    match rename_entry(&req, &form) {
+        Ok(change) => {
+            webhook::notify_changes(&req, vec![change]);
+            HttpResponse::NoContent().finish()
+        }
+        Err(err) => operation_error(err),
+    }
+}
+
+/// Extract the archive at `form.path` into its directory, on behalf of the user of `req`. The
+/// archive itself is kept.
+async fn extract_entry(req: &HttpRequest, form: &ExtractForm) -> Result<Change, ContextualError> {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let root = canonical_path::canonicalize(&conf.path).map_err(|e| {
+        ContextualError::IoError("Failed to resolve path served by miniserve".to_string(), e)
+    })?;
+    let entry = resolve_entry(&root, &form.path, conf.no_symlinks)?;
+    let metadata = entry
+        .symlink_metadata()
+        .map_err(|_| ContextualError::RouteNotFoundError(form.path.clone()))?;
+    if !metadata.is_file() || !archive::is_extractable(&entry) {
+        return Err(ContextualError::InvalidPathError(format!(
+            "{} is not an archive which can be extracted",
+            form.path
+        )));
+    }
+    dir_config::check_access(req, conf, &entry, false)?;
+    let target_dir = entry.parent().unwrap_or(&root).to_path_buf();
+    dir_config::check_access(req, conf, &target_dir, true)?;
+
+    let archive_path = entry.clone();
+    let (max_size, on_duplicate_files) = (conf.max_extracted_size, conf.on_duplicate_files);
+    let access = dir_config::WriteAccess::new(req, conf);
+    let extraction = web::block(move || {
+        archive::extract_archive(
+            &archive_path,
+            &target_dir,
+            max_size,
+            on_duplicate_files,
+            &|path| access.check(path),
+        )
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(ContextualError::IoError(
+            "Failed to extract the archive".to_string(),
+            std::io::Error::other(e.to_string()),
+        ))
+    })?;
+
+    Ok(Change {
+        action: ChangeAction::Extract,
+        path: entry,
+        previous_path: None,
+        size: extraction.size,
+        extracted_files: extraction.files,
+    })
+}
+
+/// Handle requests extracting an archive
+pub async fn extract(req: HttpRequest, form: web::Form<ExtractForm>) -> HttpResponse {
+    match extract_entry(&req, &form).await {
+        Ok(change) => {
+            webhook::notify_changes(&req, vec![change]);
+            HttpResponse::NoContent().finish()
+        }
+        Err(err) => operation_error(err),
+    }
+}
+
+/// Return `true` if the file called `name` looks like a text file which can be edited.
+/// Files of unknown type are considered editable, as configuration files often lack an extension.
+pub fn is_editable(name: &str) -> bool {
+    match mime_guess::from_path(name).first() {
+        Some(mime) => {
+            mime.type_() == mime::TEXT
+                || matches!(
+                    mime.subtype().as_str(),
+                    "json" | "xml" | "javascript" | "toml" | "x-yaml" | "x-sh"
+                )
+                || matches!(
+                    mime.suffix().map(|s| s.as_str()),
+                    Some("json") | Some("xml")
+                )
+        }
+        None => true,
+    }
+}
+
+/// Modification time of the file with the given metadata, as sent to the text editor
+fn modification_stamp(metadata: &std::fs::Metadata) -> String {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Resolve the file edited at `path` by the user of `req`, and return its canonical path and
+/// metadata
+fn resolve_edited_file(
+    req: &HttpRequest,
+    path: &str,
+) -> Result<(PathBuf, std::fs::Metadata), ContextualError> {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let root = canonical_path::canonicalize(&conf.path).map_err(|e| {
+        ContextualError::IoError("Failed to resolve path served by miniserve".to_string(), e)
+    })?;
+    let entry = resolve_entry(&root, path, conf.no_symlinks)?;
+    let metadata = entry
+        .symlink_metadata()
+        .map_err(|_| ContextualError::RouteNotFoundError(path.to_string()))?;
+    if conf.no_symlinks && metadata.file_type().is_symlink() {
+        return Err(ContextualError::RouteNotFoundError(path.to_string()));
+    }
+
+    // Symlinks are followed, their target has to be inside of the served directory as well
+    let file_path = canonical_path::canonicalize(&entry)
+        .map_err(|_| ContextualError::RouteNotFoundError(path.to_string()))?;
+    if !file_path.starts_with(&root) {
+        return Err(ContextualError::InvalidPathError(format!(
+            "{} is outside of the served directory",
+            path
+        )));
+    }
+    let metadata = file_path
+        .metadata()
+        .map_err(|e| ContextualError::IoError(format!("Failed to read {}", path), e))?;
+    if !metadata.is_file() {
+        return Err(ContextualError::InvalidPathError(format!(
+            "{} is not a file",
+            path
+        )));
+    }
+    if metadata.len() > conf.max_edit_size {
+        return Err(ContextualError::InvalidPathError(format!(
+            "{} is too large to be edited",
+            path
+        )));
+    }
+    dir_config::check_access(req, conf, &file_path, true)?;
+
+    Ok((file_path, metadata))
+}
+
+/// Render the text editor of the file at `path`
+fn editor_response(
+    req: &HttpRequest,
+    path: &str,
+    content: &str,
+    modified: &str,
+    error: Option<&str>,
+    saved: bool,
+    status: StatusCode,
+) -> HttpResponse {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let random_route = conf
+        .random_route
+        .as_ref()
+        .map(|r| format!("/{}", r))
+        .unwrap_or_default();
+    let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+    let return_path = format!(
+        "{}/{}",
+        random_route,
+        utf8_percent_encode(dir.trim_start_matches('/'), PATH)
+    );
+    let action = format!(
+        "{}?path={}",
+        EDIT_ROUTE,
+        utf8_percent_encode(path, NON_ALPHANUMERIC)
+    );
+
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .append_header((header::CACHE_CONTROL, "no-store"))
+        .body(
+            renderer::edit_page(
+                path,
+                &action,
+                &return_path,
+                content,
+                modified,
+                error,
+                saved,
+                &conf.favicon_route,
+                &conf.css_route,
+                &conf.default_color_scheme,
+                &conf.default_color_scheme_dark,
+                conf.hide_version_footer,
+            )
+            .into_string(),
+        )
+}
+
+/// Handle requests to the text editor
+pub async fn edit_page(req: HttpRequest, query: web::Query<EditQuery>) -> HttpResponse {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    if !auth::has_write_permission(&req, conf) {
+        return operation_error(ContextualError::InsufficientAuthPermissionsError);
+    }
+
+    let result = resolve_edited_file(&req, &query.path).and_then(|(file_path, metadata)| {
+        let content = std::fs::read(&file_path)
+            .map_err(|e| ContextualError::IoError(format!("Failed to read {}", query.path), e))?;
+        let content = String::from_utf8(content).map_err(|_| {
+            ContextualError::InvalidPathError(format!("{} is not a text file", query.path))
+        })?;
+        Ok((content, modification_stamp(&metadata)))
+    });
+
+    match result {
+        Ok((content, modified)) => editor_response(
+            &req,
+            &query.path,
+            &content,
+            &modified,
+            None,
+            query.saved.unwrap_or(false),
+            StatusCode::OK,
+        ),
+        Err(err) => operation_error(err),
+    }
+}
+
+/// Handle files saved by the text editor.
+///
+/// The file is not saved if it was modified after it was opened, the editor is shown again
+/// instead so that the user can decide to overwrite these changes.
+pub async fn save_edit(
+    req: HttpRequest,
+    query: web::Query<EditQuery>,
+    form: web::Form<EditForm>,
+) -> HttpResponse {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let (file_path, metadata) = match resolve_edited_file(&req, &query.path) {
+        Ok(file) => file,
+        Err(err) => return operation_error(err),
+    };
+
+    let modified = modification_stamp(&metadata);
+    if form.modified != modified {
+        let err = ContextualError::EditConflictError(query.path.clone());
+        errors::log_error_chain(err.to_string());
+        return editor_response(
+            &req,
+            &query.path,
+            &form.content,
+            &modified,
+            Some(&err.to_string()),
+            false,
+            StatusCode::CONFLICT,
+        );
+    }
+
+    // Browsers send the lines of text areas with CRLF line endings
+    let original = std::fs::read(&file_path).unwrap_or_default();
+    let content = if original.windows(2).any(|w| w == b"\r\n") {
+        form.content.clone()
+    } else {
+        form.content.replace("\r\n", "\n")
+    };
+    if content.len() as u64 > conf.max_edit_size {
+        return operation_error(ContextualError::InvalidPathError(format!(
+            "{} is too large to be edited",
+            query.path
+        )));
+    }
+
+    // The file is replaced at once, so that it is never left half written
+    let temp_path = partial_upload_path(&file_path);
+    let result = std::fs::write(&temp_path, &content)
+        .and_then(|_| std::fs::set_permissions(&temp_path, metadata.permissions()))
+        .and_then(|_| std::fs::rename(&temp_path, &file_path));
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return operation_error(ContextualError::IoError(
+            format!("Failed to save {}", query.path),
+            e,
+        ));
+    }
+
+    webhook::notify_changes(
+        &req,
+        vec![Change {
+            action: ChangeAction::Edit,
+            path: file_path,
+            previous_path: None,
+            size: content.len() as u64,
+            extracted_files: Vec::new(),
+        }],
+    );
+
+    HttpResponse::SeeOther()
+        .append_header((
+            header::LOCATION,
+            format!(
+                "{}?path={}&saved=true",
+                EDIT_ROUTE,
+                utf8_percent_encode(&query.path, NON_ALPHANUMERIC)
+            ),
+        ))
+        .finish()
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(
+        path, expected,
+        case("/file.txt", Some("file.txt")),
+        case("file.txt", Some("file.txt")),
+        case("/./file.txt", Some("file.txt")),
+        case("/../file.txt", None),
+        case("/miniserve-missing-dir/file.txt", None),
+        case("/", None)
+    )]
+    fn resolved_entries(path: &str, expected: Option<&str>) {
+        let root = canonical_path::canonicalize(std::env::temp_dir()).unwrap();
+        assert_eq!(resolve_entry(&root, path, false).ok(), expected.map(|name| root.join(name)));
+    }
+
+    #[rstest(
+        name, expected,
+        case("notes.txt", true),
+        case("config.toml", true),
+        case("data.json", true),
+        case("Makefile", true),
+        case("photo.jpg", false),
+        case("archive.zip", false)
+    )]
+    fn editable_files(name: &str, expected: bool) {
+        assert_eq!(is_editable(name), expected);
+    }
+}
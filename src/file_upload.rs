@@ -1,49 +1,1341 @@
 use actix_web::{
     http::{header, StatusCode},
-    HttpRequest, HttpResponse,
+    web, HttpRequest, HttpResponse,
 };
-use futures::TryStreamExt;
+use futures::{Stream, StreamExt, TryStreamExt};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::{
-    io::Write,
-    path::{Component, PathBuf},
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Mutex,
 };
+use strum_macros::{Display, EnumString, EnumVariantNames};
 
+use crate::archive;
+use crate::auth;
+use crate::canonical_path;
+use crate::dir_config;
 use crate::errors::{self, ContextualError};
 use crate::listing::{self, SortingMethod, SortingOrder};
 use crate::renderer;
+use crate::webhook::{self, Change, ChangeAction};
 
-/// Create future to save file.
-async fn save_file(
-    field: actix_multipart::Field,
+/// Size limits of uploads, and number of bytes uploaded since miniserve started
+pub struct UploadLimits {
+    max_upload_size: Option<u64>,
+    quota: Option<u64>,
+    uploaded: AtomicU64,
+
+    /// Maximum number of bytes each user can store, given with --quota
+    user_quotas: HashMap<String, u64>,
+
+    /// Number of bytes stored by each user with a quota
+    user_usage: Mutex<HashMap<String, u64>>,
+}
+
+impl UploadLimits {
+    /// Limits of the uploads. With `user_dirs`, the files already in the directories of the
+    /// users count towards their quota.
+    pub fn new(
+        max_upload_size: Option<u64>,
+        quota: Option<u64>,
+        user_quotas: HashMap<String, u64>,
+        user_dirs: Option<&Path>,
+    ) -> Self {
+        let user_usage = user_quotas
+            .keys()
+            .map(|name| {
+                let usage = match user_dirs {
+                    Some(root) => directory_size(&root.join(name)).unwrap_or_else(|e| {
+                        if e.kind() != io::ErrorKind::NotFound {
+                            log::warn!("Failed to measure the directory of {}: {}", name, e);
+                        }
+                        0
+                    }),
+                    None => 0,
+                };
+                (name.clone(), usage)
+            })
+            .collect();
+        UploadLimits {
+            max_upload_size,
+            quota,
+            uploaded: AtomicU64::new(0),
+            user_quotas,
+            user_usage: Mutex::new(user_usage),
+        }
+    }
+
+    /// Fail if a file of `len` bytes exceeds the maximum upload size
+    fn check_file_size(&self, len: u64) -> Result<(), ContextualError> {
+        match self.max_upload_size {
+            Some(max_upload_size) if len > max_upload_size => {
+                Err(ContextualError::UploadTooLargeError(max_upload_size))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Number of bytes which can still be uploaded, if there is a quota
+    fn remaining(&self) -> Option<u64> {
+        self.quota
+            .map(|quota| quota.saturating_sub(self.uploaded.load(Ordering::SeqCst)))
+    }
+
+    /// Account for `len` more uploaded bytes, unless they exceed the upload quota
+    fn add(&self, len: u64) -> Result<(), ContextualError> {
+        self.uploaded
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |uploaded| {
+                Some(uploaded + len).filter(|total| self.quota.is_none_or(|quota| *total <= quota))
+            })
+            .map(|_| ())
+            .map_err(|_| ContextualError::UploadQuotaExceededError)
+    }
+
+    /// Give back `len` bytes of the upload quota, after their file was deleted
+    fn release(&self, len: u64) {
+        self.uploaded.fetch_sub(len, Ordering::SeqCst);
+    }
+
+    /// Number of bytes stored by `user` and their quota, if they have one
+    fn user_usage(&self, user: &str) -> Option<(u64, u64)> {
+        let quota = *self.user_quotas.get(user)?;
+        let usage = self.user_usage.lock().unwrap_or_else(|e| e.into_inner());
+        Some((usage.get(user).copied().unwrap_or(0), quota))
+    }
+
+    /// Account for `len` more bytes stored by `user`, unless they exceed their quota or
+    /// `check` is false
+    fn add_user_usage(&self, user: &str, len: u64, check: bool) -> Result<(), ContextualError> {
+        let quota = match self.user_quotas.get(user) {
+            Some(quota) => *quota,
+            None => return Ok(()),
+        };
+        let mut usage = self.user_usage.lock().unwrap_or_else(|e| e.into_inner());
+        let used = usage.entry(user.to_string()).or_insert(0);
+        if check && *used + len > quota {
+            return Err(ContextualError::UserQuotaExceededError(quota));
+        }
+        *used += len;
+        Ok(())
+    }
+
+    /// Give back `len` bytes of the quota of `user`, after their file was deleted
+    fn release_user_usage(&self, user: &str, len: u64) {
+        let mut usage = self.user_usage.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(used) = usage.get_mut(user) {
+            *used = used.saturating_sub(len);
+        }
+    }
+}
+
+/// Upload limits applying to the user who made a request, whose uploads also count towards
+/// their own quota if they have one
+#[derive(Clone)]
+pub struct UserUploadLimits {
+    limits: web::Data<UploadLimits>,
+
+    /// Authenticated user who made the request
+    user: Option<String>,
+}
+
+impl UserUploadLimits {
+    /// Upload limits of the user who made `req`
+    pub fn of(req: &HttpRequest) -> Self {
+        UserUploadLimits {
+            limits: req.app_data::<web::Data<UploadLimits>>().unwrap().clone(),
+            user: req
+                .extensions()
+                .get::<auth::CurrentUser>()
+                .map(|user| user.name.clone()),
+        }
+    }
+
+    /// Number of bytes stored by the user and their quota, if they have one
+    pub fn usage(&self) -> Option<(u64, u64)> {
+        self.limits.user_usage(self.user.as_deref()?)
+    }
+
+    /// Fail if a file of `len` bytes exceeds the maximum upload size
+    fn check_file_size(&self, len: u64) -> Result<(), ContextualError> {
+        self.limits.check_file_size(len)
+    }
+
+    /// Fail early if the body of `req` can't fit in the remaining upload quotas
+    fn check_content_length(&self, req: &HttpRequest) -> Result<(), ContextualError> {
+        let content_length = match req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+        {
+            Some(content_length) => content_length,
+            None => return Ok(()),
+        };
+
+        match (self.limits.remaining(), self.usage()) {
+            (Some(remaining), _) if content_length > remaining => {
+                Err(ContextualError::UploadQuotaExceededError)
+            }
+            (_, Some((used, quota))) if used + content_length > quota => {
+                Err(ContextualError::UserQuotaExceededError(quota))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Number of bytes which can still be uploaded, if there is a quota
+    fn remaining(&self) -> Option<u64> {
+        let user_remaining = self.usage().map(|(used, quota)| quota.saturating_sub(used));
+        match (self.limits.remaining(), user_remaining) {
+            (Some(remaining), Some(user_remaining)) => Some(remaining.min(user_remaining)),
+            (remaining, user_remaining) => remaining.or(user_remaining),
+        }
+    }
+
+    /// Account for `len` more uploaded bytes, unless they exceed one of the quotas
+    fn add(&self, len: u64) -> Result<(), ContextualError> {
+        self.limits.add(len)?;
+        if let Some(user) = &self.user {
+            if let Err(e) = self.limits.add_user_usage(user, len, true) {
+                self.limits.release(len);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Account for `len` bytes which were extracted from an archive, whose size was checked
+    /// during the extraction
+    fn add_extracted(&self, len: u64) {
+        self.limits.uploaded.fetch_add(len, Ordering::SeqCst);
+        if let Some(user) = &self.user {
+            let _ = self.limits.add_user_usage(user, len, false);
+        }
+    }
+
+    /// Give back `len` bytes of the quotas, after their file was deleted
+    fn release(&self, len: u64) {
+        self.limits.release(len);
+        if let Some(user) = &self.user {
+            self.limits.release_user_usage(user, len);
+        }
+    }
+
+    /// Give back the `len` bytes of a file which was replaced by an upload to the quota of the
+    /// user, who would otherwise be charged for both versions
+    fn release_replaced(&self, len: u64) {
+        if let Some(user) = &self.user {
+            self.limits.release_user_usage(user, len);
+        }
+    }
+}
+
+/// Total size of the files in the directory at `path` and its subdirectories. Symbolic links
+/// aren't followed.
+fn directory_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            size += directory_size(&entry.path())?;
+        } else if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Write the content of `stream` to `file`, counting the written bytes in `written_len`
+async fn write_stream<S>(
+    mut stream: S,
+    mut file: File,
+    limits: &UserUploadLimits,
+    written_len: &mut u64,
+) -> Result<(), ContextualError>
+where
+    S: Stream<Item = Result<web::Bytes, ContextualError>> + Unpin,
+{
+    while let Some(bytes) = stream.try_next().await? {
+        let len = bytes.len() as u64;
+        limits.check_file_size(*written_len + len)?;
+        limits.add(len)?;
+        *written_len += len;
+        file.write_all(bytes.as_ref())
+            .map_err(|e| ContextualError::IoError("Failed to write to file".to_string(), e))?;
+    }
+
+    Ok(())
+}
+
+/// What to do when an uploaded file already exists
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum DuplicateFilePolicy {
+    /// Refuse the upload
+    Error,
+
+    /// Replace the existing file
+    Overwrite,
+
+    /// Store the upload as `file (1).txt`, `file (2).txt`, etc.
+    Rename,
+
+    /// Keep the existing file and silently drop the upload
+    Skip,
+}
+
+impl DuplicateFilePolicy {
+    /// Return the path a new file meant to be stored at `file_path` has to be written to,
+    /// or `None` if it has to be skipped
+    pub fn resolve(self, file_path: PathBuf) -> Result<Option<PathBuf>, ContextualError> {
+        if !file_path.exists() {
+            return Ok(Some(file_path));
+        }
+
+        match self {
+            DuplicateFilePolicy::Error => Err(ContextualError::DuplicateFileError),
+            DuplicateFilePolicy::Overwrite => Ok(Some(file_path)),
+            DuplicateFilePolicy::Rename => Ok(Some(renamed_path(&file_path))),
+            DuplicateFilePolicy::Skip => Ok(None),
+        }
+    }
+}
+
+/// Return the first path named like `file (1).txt`, `file (2).txt`, etc. which doesn't exist
+fn renamed_path(file_path: &Path) -> PathBuf {
+    let stem = file_path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = file_path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|i| file_path.with_file_name(format!("{} ({}){}", stem, i, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Result of a completely received upload
+pub enum UploadOutcome {
+    /// The upload was stored, possibly under another name
+    Stored(Change),
+
+    /// The upload was dropped, as the file already exists
+    Skipped,
+
+    /// The upload was dropped, as the file already exists with the same content
+    Duplicate,
+
+    /// The upload was stored as a hard link to an identical file of its directory
+    Linked(Change),
+}
+
+/// Settings of uploads, taken from the configuration
+#[derive(Clone)]
+struct UploadOptions {
+    on_duplicate_files: DuplicateFilePolicy,
+    auto_extract: bool,
+    max_extracted_size: u64,
+    keep_versions: Option<usize>,
+    dedup: bool,
+    dedup_hard_links: bool,
+    hook: Option<String>,
+    hook_error: String,
+
+    /// Right of the uploader to write the entries of uploaded archives
+    write_access: dir_config::WriteAccess,
+
+    /// SHA-256 checksum the uploaded file must have, sent by the client
+    checksum: Option<Vec<u8>>,
+}
+
+impl UploadOptions {
+    fn new(req: &HttpRequest, conf: &crate::MiniserveConfig) -> Self {
+        UploadOptions {
+            on_duplicate_files: conf.on_duplicate_files,
+            auto_extract: conf.upload_auto_extract,
+            max_extracted_size: conf.max_extracted_size,
+            keep_versions: conf.keep_versions,
+            dedup: conf.upload_dedup,
+            dedup_hard_links: conf.dedup_hard_links,
+            hook: conf.upload_hook.clone(),
+            hook_error: conf.upload_hook_error.clone(),
+            write_access: dir_config::WriteAccess::new(req, conf),
+            checksum: None,
+        }
+    }
+}
+
+/// Header carrying the hex-encoded SHA-256 checksum of a raw or resumable upload
+const CHECKSUM_HEADER: &str = "x-checksum-sha256";
+
+/// Standard header carrying the digests of the request body, e.g. `sha-256=:<base64>:`
+const CONTENT_DIGEST_HEADER: &str = "content-digest";
+
+/// Multipart field carrying the hex-encoded SHA-256 checksum of the file field following it
+const CHECKSUM_FIELD: &str = "checksum_sha256";
+
+/// Decode a SHA-256 checksum, which has to be 32 bytes long
+fn decode_checksum(
+    value: &str,
+    decode: impl Fn(&str) -> Option<Vec<u8>>,
+) -> Result<Vec<u8>, ContextualError> {
+    decode(value.trim())
+        .filter(|checksum| checksum.len() == 32)
+        .ok_or_else(|| {
+            ContextualError::InvalidHttpRequestError(format!("Invalid SHA-256 checksum {}", value))
+        })
+}
+
+/// SHA-256 checksum of the uploaded file, if the client sent one in the `X-Checksum-SHA256` or
+/// the `Content-Digest` header
+fn checksum_header(req: &HttpRequest) -> Result<Option<Vec<u8>>, ContextualError> {
+    let header_value = |name: &str| {
+        req.headers()
+            .get(name)
+            .map(|value| {
+                value.to_str().map_err(|_| {
+                    ContextualError::InvalidHttpRequestError(format!("Invalid {} header", name))
+                })
+            })
+            .transpose()
+    };
+
+    if let Some(value) = header_value(CHECKSUM_HEADER)? {
+        return decode_checksum(value, |value| hex::decode(value).ok()).map(Some);
+    }
+    // Digests with other algorithms are ignored
+    header_value(CONTENT_DIGEST_HEADER)?
+        .and_then(|digests| {
+            digests
+                .split(',')
+                .filter_map(|digest| digest.split_once('='))
+                .find(|(algorithm, _)| algorithm.trim().eq_ignore_ascii_case("sha-256"))
+        })
+        .map(|(_, value)| {
+            decode_checksum(value, |value| {
+                let value = value.strip_prefix(':')?.strip_suffix(':')?;
+                base64::decode(value).ok()
+            })
+        })
+        .transpose()
+}
+
+/// SHA-256 checksum of the file at `path`
+fn file_checksum(path: &Path) -> io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Fail if the SHA-256 checksum of the file at `path` isn't `checksum`
+async fn verify_file_checksum(path: &Path, checksum: &[u8]) -> Result<(), ContextualError> {
+    let file_path = path.to_path_buf();
+    let hash = web::block(move || file_checksum(&file_path))
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e.to_string())))
+        .map_err(|e| ContextualError::IoError(format!("Failed to read {}", path.display()), e))?;
+
+    if hash != checksum {
+        return Err(ContextualError::UploadChecksumMismatchError);
+    }
+    Ok(())
+}
+
+/// Path of the file an upload is written to until it is complete
+pub fn partial_upload_path(file_path: &Path) -> PathBuf {
+    let name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    file_path.with_file_name(format!(".{}.part", name))
+}
+
+/// Whether `path` is the file an upload is written to until it is complete
+pub fn is_partial_upload(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name.starts_with('.') && name.ends_with(".part")
+}
+
+/// File with the same content as the upload received at `temp_path`: the file at `file_path`
+/// it is meant to be stored as, or any other file of the same directory with `other_files`.
+/// Only the files of the same size are hashed.
+fn identical_file(
+    temp_path: &Path,
+    file_path: &Path,
+    other_files: bool,
+) -> io::Result<Option<PathBuf>> {
+    let size = std::fs::metadata(temp_path)?.len();
+    let mut candidates = vec![file_path.to_path_buf()];
+    if let (true, Some(dir)) = (other_files, file_path.parent()) {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path != file_path && !is_partial_upload(&path) {
+                candidates.push(path);
+            }
+        }
+    }
+
+    let mut hash = None;
+    for candidate in candidates {
+        // Symlinks are never followed, they could lead out of the directory
+        match std::fs::symlink_metadata(&candidate) {
+            Ok(metadata) if metadata.is_file() && metadata.len() == size => (),
+            _ => continue,
+        }
+        if hash.is_none() {
+            hash = Some(file_checksum(temp_path)?);
+        }
+        if file_checksum(&candidate).ok() == hash {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// Store the upload received at `temp_path` as a hard link to the identical file at
+/// `identical` instead, replacing `file_path` if it exists. Return whether it worked.
+fn link_identical_file(identical: &Path, temp_path: &Path, file_path: &Path) -> bool {
+    if file_path.exists() {
+        if let Err(e) = std::fs::remove_file(file_path) {
+            log::warn!("Failed to replace {}: {}", file_path.display(), e);
+            return false;
+        }
+    }
+    match std::fs::hard_link(identical, file_path) {
+        Ok(()) => {
+            let _ = std::fs::remove_file(temp_path);
+            true
+        }
+        Err(e) => {
+            log::warn!(
+                "Failed to link {} to {}: {}",
+                file_path.display(),
+                identical.display(),
+                e
+            );
+            false
+        }
+    }
+}
+
+/// Build the command running `hook` through the shell, with `path` as last argument
+fn upload_hook_command(hook: &str, path: &Path) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(hook).arg(path);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(format!("{} \"$0\"", hook)).arg(path);
+        command
+    }
+}
+
+/// Run the upload hook on the received file at `temp_path`, before it is moved to `file_path`.
+/// The upload is rejected if the hook exits with a non-zero status.
+async fn run_upload_hook(
+    options: &UploadOptions,
+    temp_path: &Path,
+    file_path: &Path,
+) -> Result<(), ContextualError> {
+    let hook = match &options.hook {
+        Some(hook) => hook.clone(),
+        None => return Ok(()),
+    };
+
+    let mut command = upload_hook_command(&hook, temp_path);
+    command.env(
+        "MINISERVE_UPLOAD_NAME",
+        file_path.file_name().unwrap_or_default(),
+    );
+    let status = web::block(move || command.status())
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e.to_string())))
+        .map_err(|e| ContextualError::IoError("Failed to run the upload hook".to_string(), e))?;
+
+    if !status.success() {
+        return Err(ContextualError::UploadRejectedError(
+            options.hook_error.clone(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Numbers of the versions of `file_path` kept next to it, like `file.txt.~1~`
+fn version_numbers(file_path: &Path) -> io::Result<Vec<u64>> {
+    let name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    let prefix = format!("{}.~", name);
+    let dir = file_path.parent().unwrap_or_else(|| Path::new("."));
+    let mut numbers = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let entry_name = entry.file_name().to_string_lossy().to_string();
+            entry_name
+                .strip_prefix(&prefix)?
+                .strip_suffix('~')?
+                .parse::<u64>()
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+/// Keep the content of the file at `file_path`, which is about to be overwritten, as its newest
+/// version, and delete the oldest versions beyond `keep_versions`
+fn keep_version(file_path: &Path, keep_versions: usize) -> io::Result<()> {
+    if !file_path.is_file() {
+        return Ok(());
+    }
+    let name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    let version_path = |number: u64| file_path.with_file_name(format!("{}.~{}~", name, number));
+
+    let numbers = version_numbers(file_path)?;
+    let next = numbers.last().map_or(1, |last| last + 1);
+    if keep_versions > 0 {
+        std::fs::rename(file_path, version_path(next))?;
+    }
+    let kept = numbers.len() + usize::from(keep_versions > 0);
+    for &number in numbers.iter().take(kept.saturating_sub(keep_versions)) {
+        std::fs::remove_file(version_path(number))?;
+    }
+    Ok(())
+}
+
+/// Replace the uploaded archive at `archive_path` with its content, extracted into `target_dir`.
+/// Return the extracted bytes and files.
+fn extract_upload(
+    archive_path: &Path,
+    target_dir: &Path,
+    archive_size: u64,
+    on_duplicate_files: DuplicateFilePolicy,
+    max_extracted_size: u64,
+    write_access: &dir_config::WriteAccess,
+    limits: &UserUploadLimits,
+) -> Result<archive::Extraction, ContextualError> {
+    // The extracted files take the place of the archive in the upload quota
+    let max_size = limits.remaining().map_or(max_extracted_size, |remaining| {
+        max_extracted_size.min(remaining + archive_size)
+    });
+    let result = archive::extract_archive(
+        archive_path,
+        target_dir,
+        max_size,
+        on_duplicate_files,
+        &|path| write_access.check(path),
+    );
+
+    let _ = std::fs::remove_file(archive_path);
+    limits.release(archive_size);
+    let extraction = result?;
+    limits.add_extracted(extraction.size);
+
+    Ok(extraction)
+}
+
+/// Move the completely received file at `temp_path` to `file_path` if the upload hook accepts
+/// it, and extract it if it is an archive. The file is deleted if it is rejected or skipped, or
+/// if it is a duplicate. With `dedup_hard_links`, it is linked to an identical file instead.
+#[tracing::instrument(skip_all, fields(path = %file_path.display(), size = size))]
+async fn complete_upload(
+    options: &UploadOptions,
+    limits: &UserUploadLimits,
+    temp_path: &Path,
     file_path: PathBuf,
-    overwrite_files: bool,
-) -> Result<u64, ContextualError> {
-    if !overwrite_files && file_path.exists() {
-        return Err(ContextualError::DuplicateFileError);
+    size: u64,
+) -> Result<UploadOutcome, ContextualError> {
+    let identical = if options.dedup {
+        let (temp, file, other_files) = (
+            temp_path.to_path_buf(),
+            file_path.clone(),
+            options.dedup_hard_links,
+        );
+        web::block(move || identical_file(&temp, &file, other_files))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e.to_string())))
+            .unwrap_or_else(|e| {
+                log::warn!(
+                    "Failed to look for a duplicate of {}: {}",
+                    file_path.display(),
+                    e
+                );
+                None
+            })
+    } else {
+        None
+    };
+    if identical.as_ref() == Some(&file_path) {
+        let _ = std::fs::remove_file(temp_path);
+        limits.release(size);
+        return Ok(UploadOutcome::Duplicate);
     }
 
-    let file = std::fs::File::create(&file_path).map_err(|e| {
+    let result = match run_upload_hook(options, temp_path, &file_path).await {
+        Ok(()) => options.on_duplicate_files.resolve(file_path),
+        Err(e) => Err(e),
+    };
+    let file_path = match result {
+        Ok(Some(file_path)) => file_path,
+        Ok(None) => {
+            let _ = std::fs::remove_file(temp_path);
+            limits.release(size);
+            return Ok(UploadOutcome::Skipped);
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(temp_path);
+            limits.release(size);
+            return Err(e);
+        }
+    };
+    if let Some(keep_versions) = options.keep_versions {
+        if let Err(e) = keep_version(&file_path, keep_versions) {
+            let _ = std::fs::remove_file(temp_path);
+            limits.release(size);
+            return Err(ContextualError::IoError(
+                format!(
+                    "Failed to keep the previous version of {}",
+                    file_path.display()
+                ),
+                e,
+            ));
+        }
+    }
+    if let Some(identical) = identical {
+        if link_identical_file(&identical, temp_path, &file_path) {
+            limits.release(size);
+            return Ok(UploadOutcome::Linked(Change {
+                action: ChangeAction::Upload,
+                path: file_path,
+                previous_path: None,
+                size,
+                extracted_files: Vec::new(),
+            }));
+        }
+    }
+    // The previous version of an overwritten file no longer takes space, unless it was kept
+    let replaced_len = std::fs::symlink_metadata(&file_path)
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len());
+    std::fs::rename(temp_path, &file_path).map_err(|e| {
         ContextualError::IoError(format!("Failed to create {}", file_path.display()), e)
     })?;
+    if let Some(replaced_len) = replaced_len {
+        limits.release_replaced(replaced_len);
+    }
+
+    if options.auto_extract && archive::is_extractable(&file_path) {
+        if let Some(target_dir) = file_path.parent() {
+            let extraction = extract_upload(
+                &file_path,
+                target_dir,
+                size,
+                options.on_duplicate_files,
+                options.max_extracted_size,
+                &options.write_access,
+                limits,
+            )?;
+            return Ok(UploadOutcome::Stored(Change {
+                action: ChangeAction::Extract,
+                path: file_path,
+                previous_path: None,
+                size: extraction.size,
+                extracted_files: extraction.files,
+            }));
+        }
+    }
+
+    Ok(UploadOutcome::Stored(Change {
+        action: ChangeAction::Upload,
+        path: file_path,
+        previous_path: None,
+        size,
+        extracted_files: Vec::new(),
+    }))
+}
+
+/// Consume the content of `stream` without storing it
+async fn skip_stream<S>(mut stream: S) -> Result<(), ContextualError>
+where
+    S: Stream<Item = Result<web::Bytes, ContextualError>> + Unpin,
+{
+    while stream.try_next().await?.is_some() {}
 
-    let (_, written_len) = field
-        .map_err(ContextualError::MultipartError)
-        .try_fold((file, 0u64), |(mut file, written_len), bytes| async move {
-            file.write_all(bytes.as_ref())
-                .map_err(|e| ContextualError::IoError("Failed to write to file".to_string(), e))?;
-            Ok((file, written_len + bytes.len() as u64))
+    Ok(())
+}
+
+/// Create future to save file.
+#[tracing::instrument(name = "upload", skip_all, fields(path = %file_path.display()))]
+async fn save_file<S>(
+    stream: S,
+    file_path: PathBuf,
+    options: &UploadOptions,
+    limits: UserUploadLimits,
+) -> Result<UploadOutcome, ContextualError>
+where
+    S: Stream<Item = Result<web::Bytes, ContextualError>> + Unpin,
+{
+    // Don't receive files which would be refused anyway, unless they could be duplicates
+    if file_path.exists() {
+        match options.on_duplicate_files {
+            DuplicateFilePolicy::Error if !options.dedup => {
+                return Err(ContextualError::DuplicateFileError)
+            }
+            DuplicateFilePolicy::Skip => {
+                skip_stream(stream).await?;
+                return Ok(UploadOutcome::Skipped);
+            }
+            DuplicateFilePolicy::Error
+            | DuplicateFilePolicy::Overwrite
+            | DuplicateFilePolicy::Rename => (),
+        }
+    }
+
+    let temp_path = partial_upload_path(&file_path);
+    let file = File::create(&temp_path).map_err(|e| {
+        ContextualError::IoError(format!("Failed to create {}", temp_path.display()), e)
+    })?;
+
+    // The checksum is computed while the file is written, instead of reading it again
+    let mut hasher = options.checksum.as_ref().map(|_| Sha256::new());
+    let stream = stream.inspect_ok(|bytes| {
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(bytes);
+        }
+    });
+    let mut written_len = 0;
+    let written = write_stream(stream, file, &limits, &mut written_len).await;
+    let result = written.and_then(|()| {
+        let hash = hasher.map(|hasher| hasher.finalize().to_vec());
+        match (hash, &options.checksum) {
+            (Some(hash), Some(checksum)) if hash != *checksum => {
+                Err(ContextualError::UploadChecksumMismatchError)
+            }
+            _ => Ok(()),
+        }
+    });
+    if let Err(e) = result {
+        // Don't leave incomplete or corrupted files behind
+        let _ = std::fs::remove_file(&temp_path);
+        limits.release(written_len);
+        return Err(e);
+    }
+
+    complete_upload(options, &limits, &temp_path, file_path, written_len).await
+}
+
+/// Header carrying the number of bytes of a resumable upload which were received so far
+const UPLOAD_OFFSET_HEADER: &str = "upload-offset";
+
+/// Header carrying the total size of a resumable upload
+const UPLOAD_LENGTH_HEADER: &str = "upload-length";
+
+/// Header telling whether a completed resumable upload was stored or skipped
+const UPLOAD_RESULT_HEADER: &str = "upload-result";
+
+/// Header carrying the percent-encoded name a completed resumable upload was stored as
+const UPLOAD_NAME_HEADER: &str = "upload-name";
+
+/// Query parameters of the resumable upload route
+#[derive(Deserialize)]
+pub struct ResumableUploadQuery {
+    /// Directory to upload to, relative to the served directory
+    path: PathBuf,
+
+    /// Name of the uploaded file
+    name: String,
+}
+
+/// Resolve `upload_path` inside of the served directory `root`.
+/// Any path which would go outside of this directory is considered invalid.
+fn resolve_upload_dir(root: &Path, upload_path: &Path) -> Result<PathBuf, ContextualError> {
+    let app_root_dir = canonical_path::canonicalize(root).map_err(|e| {
+        ContextualError::IoError("Failed to resolve path served by miniserve".to_string(), e)
+    })?;
+    let upload_path = upload_path
+        .strip_prefix(Component::RootDir)
+        .unwrap_or(upload_path);
+
+    match canonical_path::canonicalize(canonical_path::join(&app_root_dir, upload_path)) {
+        Ok(path) if path.starts_with(&app_root_dir) => Ok(path),
+        _ => Err(ContextualError::InvalidHttpRequestError(
+            "Invalid value for 'path' parameter".to_string(),
+        )),
+    }
+}
+
+/// Turn the name of an uploaded file into a path relative to the upload directory.
+/// Names of files uploaded along with their folder contain directories, e.g. `folder/file.txt`.
+/// Any name which would go outside of the upload directory is considered invalid.
+pub fn sanitize_upload_name(name: &str) -> Result<PathBuf, ContextualError> {
+    let invalid_name = || ContextualError::InvalidPathError(format!("invalid file name {}", name));
+
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => (),
+            _ => return Err(invalid_name()),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        return Err(invalid_name());
+    }
+
+    Ok(path)
+}
+
+/// Create the missing parent directories of `path`, which must stay inside of the canonical
+/// directory `root`, even if existing directories are symlinks.
+pub fn create_parent_dirs(root: &Path, path: &Path) -> Result<(), ContextualError> {
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => return Ok(()),
+    };
+
+    std::fs::create_dir_all(parent).map_err(|e| {
+        ContextualError::IoError(format!("Failed to create {}", parent.display()), e)
+    })?;
+    let canonical_parent = canonical_path::canonicalize(parent).map_err(|e| {
+        ContextualError::IoError(format!("Failed to resolve {}", parent.display()), e)
+    })?;
+    if !canonical_parent.starts_with(root) {
+        return Err(ContextualError::InvalidPathError(format!(
+            "{} is outside of the upload directory",
+            path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Return the upload directory and the path of the final file of a resumable upload
+fn resolve_resumable_upload(
+    req: &HttpRequest,
+    query: &ResumableUploadQuery,
+) -> Result<(PathBuf, PathBuf), ContextualError> {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let upload_dir = resolve_upload_dir(&conf.path, &query.path)?;
+    let file_path = upload_dir.join(sanitize_upload_name(&query.name)?);
+    dir_config::check_access(req, conf, &file_path, true)?;
+    Ok((upload_dir, file_path))
+}
+
+/// Parse the value of a numeric header of a resumable upload request
+fn parse_size_header(req: &HttpRequest, name: &str) -> Result<u64, ContextualError> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .ok_or_else(|| {
+            ContextualError::InvalidHttpRequestError(format!("Missing or invalid {} header", name))
         })
-        .await?;
+}
+
+/// Partial files of the resumable uploads which a chunk is being written to
+#[derive(Default)]
+pub struct ResumableUploads {
+    busy: Mutex<HashSet<PathBuf>>,
+}
+
+impl ResumableUploads {
+    /// Reserve the partial file at `path` until the returned guard is dropped, or return `None`
+    /// if another chunk is being written to it
+    fn reserve(&self, path: &Path) -> Option<ReservedUpload<'_>> {
+        let mut busy = self.busy.lock().unwrap_or_else(|e| e.into_inner());
+        if !busy.insert(path.to_path_buf()) {
+            return None;
+        }
+        Some(ReservedUpload {
+            uploads: self,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+/// Reservation of the partial file of a resumable upload, released when dropped
+struct ReservedUpload<'a> {
+    uploads: &'a ResumableUploads,
+    path: PathBuf,
+}
+
+impl Drop for ReservedUpload<'_> {
+    fn drop(&mut self) {
+        let mut busy = self.uploads.busy.lock().unwrap_or_else(|e| e.into_inner());
+        busy.remove(&self.path);
+    }
+}
+
+/// Size of the partial file of a resumable upload, which is 0 before the upload started
+fn partial_upload_size(partial_path: &Path) -> u64 {
+    std::fs::metadata(partial_path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0)
+}
+
+/// Append the request body to the partial file of a resumable upload, and move it into
+/// place once it is complete. Return the new upload offset.
+#[tracing::instrument(name = "resumable_upload_chunk", skip_all, fields(name = %query.name))]
+async fn append_upload_chunk(
+    req: &HttpRequest,
+    query: &ResumableUploadQuery,
+    mut payload: web::Payload,
+) -> Result<(u64, Option<UploadOutcome>), ContextualError> {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let limits = &UserUploadLimits::of(req);
+    let (upload_dir, file_path) = resolve_resumable_upload(req, query)?;
+    let partial_path = partial_upload_path(&file_path);
+    let offset = parse_size_header(req, UPLOAD_OFFSET_HEADER)?;
+    let length = parse_size_header(req, UPLOAD_LENGTH_HEADER)?;
+    let checksum = checksum_header(req)?;
+    limits.check_file_size(length)?;
+    limits.check_content_length(req)?;
+
+    // The offset is only checked and extended by one chunk at a time
+    let _reserved = match req
+        .app_data::<web::Data<ResumableUploads>>()
+        .map(|uploads| uploads.reserve(&partial_path))
+    {
+        Some(None) => return Err(ContextualError::UploadInProgressError),
+        reserved => reserved,
+    };
+
+    // Chunks have to be sent in order, the client has to ask for the offset after a failure
+    let current_offset = partial_upload_size(&partial_path);
+    if offset != current_offset {
+        return Err(ContextualError::UploadOffsetMismatchError(current_offset));
+    }
+    if offset > length {
+        return Err(ContextualError::InvalidHttpRequestError(
+            "Upload exceeds the announced length".to_string(),
+        ));
+    }
+    if offset == 0 && file_path.exists() {
+        match conf.on_duplicate_files {
+            DuplicateFilePolicy::Error if !conf.upload_dedup => {
+                return Err(ContextualError::DuplicateFileError)
+            }
+            // Tell the client the upload is complete, so that it doesn't send anything
+            DuplicateFilePolicy::Skip => return Ok((length, Some(UploadOutcome::Skipped))),
+            DuplicateFilePolicy::Error
+            | DuplicateFilePolicy::Overwrite
+            | DuplicateFilePolicy::Rename => (),
+        }
+    }
+
+    create_parent_dirs(&upload_dir, &file_path)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&partial_path)
+        .map_err(|e| {
+            ContextualError::IoError(format!("Failed to create {}", partial_path.display()), e)
+        })?;
+
+    let mut offset = offset;
+    while let Some(bytes) = payload.next().await {
+        let bytes = bytes.map_err(|e| {
+            ContextualError::InvalidHttpRequestError(format!("Failed to read upload: {}", e))
+        })?;
+        offset += bytes.len() as u64;
+        if offset > length {
+            return Err(ContextualError::InvalidHttpRequestError(
+                "Upload exceeds the announced length".to_string(),
+            ));
+        }
+        limits.add(bytes.len() as u64)?;
+        file.write_all(bytes.as_ref())
+            .map_err(|e| ContextualError::IoError("Failed to write to file".to_string(), e))?;
+    }
+
+    if offset == length {
+        // The checksum of the whole file is sent along with the last chunk
+        if let Some(checksum) = &checksum {
+            if let Err(e) = verify_file_checksum(&partial_path, checksum).await {
+                let _ = std::fs::remove_file(&partial_path);
+                limits.release(length);
+                return Err(e);
+            }
+        }
+        let options = UploadOptions::new(req, conf);
+        let outcome = complete_upload(&options, limits, &partial_path, file_path, length).await?;
+        if let UploadOutcome::Stored(change) | UploadOutcome::Linked(change) = &outcome {
+            webhook::notify_changes(req, vec![change.clone()]);
+        }
+        return Ok((offset, Some(outcome)));
+    }
+
+    Ok((offset, None))
+}
+
+/// Convenience method for creating plain text responses, if a resumable or raw upload fails
+fn plain_upload_error(err: ContextualError) -> HttpResponse {
+    errors::log_error_chain(err.to_string());
+    let mut resp = match &err {
+        ContextualError::InvalidHttpRequestError(_) | ContextualError::InvalidPathError(_) => {
+            HttpResponse::BadRequest()
+        }
+        ContextualError::DuplicateFileError => HttpResponse::Conflict(),
+        ContextualError::ArchiveExtractionError(_)
+        | ContextualError::UploadChecksumMismatchError => HttpResponse::BadRequest(),
+        ContextualError::UploadRejectedError(_) => HttpResponse::UnprocessableEntity(),
+        ContextualError::UploadTooLargeError(_) | ContextualError::UploadQuotaExceededError => {
+            HttpResponse::PayloadTooLarge()
+        }
+        ContextualError::UserQuotaExceededError(_) => HttpResponse::InsufficientStorage(),
+        ContextualError::UploadOffsetMismatchError(offset) => {
+            let mut resp = HttpResponse::Conflict();
+            resp.append_header((UPLOAD_OFFSET_HEADER, *offset));
+            resp
+        }
+        ContextualError::UploadInProgressError => HttpResponse::build(StatusCode::LOCKED),
+        ContextualError::InvalidHttpCredentials => HttpResponse::Unauthorized(),
+        ContextualError::DirectoryAccessDeniedError(_)
+        | ContextualError::UploadsDisabledError
+        | ContextualError::ProtectedFileError(_) => HttpResponse::Forbidden(),
+        _ => HttpResponse::InternalServerError(),
+    };
+    resp.content_type("text/plain; charset=utf-8")
+        .body(err.to_string())
+}
+
+/// Report how many bytes of a resumable upload were received so far
+pub async fn resumable_upload_offset(
+    req: HttpRequest,
+    query: web::Query<ResumableUploadQuery>,
+) -> HttpResponse {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    if !auth::has_write_permission(&req, conf) {
+        return HttpResponse::Forbidden()
+            .body(ContextualError::InsufficientAuthPermissionsError.to_string());
+    }
+
+    match resolve_resumable_upload(&req, &query) {
+        Ok((_, file_path)) => HttpResponse::Ok()
+            .append_header((
+                UPLOAD_OFFSET_HEADER,
+                partial_upload_size(&partial_upload_path(&file_path)),
+            ))
+            .append_header((header::CACHE_CONTROL, "no-store"))
+            .finish(),
+        Err(err) => plain_upload_error(err),
+    }
+}
+
+/// Handle a chunk of a resumable upload.
+/// The chunk is sent as the request body, starting at the offset given in the `Upload-Offset`
+/// header. The total size of the file is given in the `Upload-Length` header.
+pub async fn resumable_upload_chunk(
+    req: HttpRequest,
+    query: web::Query<ResumableUploadQuery>,
+    payload: web::Payload,
+) -> HttpResponse {
+    match append_upload_chunk(&req, &query, payload).await {
+        Ok((offset, outcome)) => {
+            let mut resp = HttpResponse::NoContent();
+            resp.append_header((UPLOAD_OFFSET_HEADER, offset));
+            match outcome {
+                Some(UploadOutcome::Stored(change)) | Some(UploadOutcome::Linked(change)) => {
+                    let name = change.path.file_name().unwrap_or_default();
+                    resp.append_header((UPLOAD_RESULT_HEADER, "stored"));
+                    resp.append_header((
+                        UPLOAD_NAME_HEADER,
+                        utf8_percent_encode(&name.to_string_lossy(), NON_ALPHANUMERIC).to_string(),
+                    ));
+                }
+                Some(UploadOutcome::Skipped) => {
+                    resp.append_header((UPLOAD_RESULT_HEADER, "skipped"));
+                }
+                Some(UploadOutcome::Duplicate) => {
+                    resp.append_header((UPLOAD_RESULT_HEADER, "duplicate"));
+                }
+                None => (),
+            }
+            resp.finish()
+        }
+        Err(err) => plain_upload_error(err),
+    }
+}
+
+/// Store the body of a raw upload at the path of the request, below the served directory.
+/// Only the file is created, its parent directory has to exist.
+#[tracing::instrument(name = "raw_upload", skip_all, fields(path = %req.path()))]
+async fn store_raw_upload(
+    req: &HttpRequest,
+    payload: web::Payload,
+) -> Result<UploadOutcome, ContextualError> {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let limits = UserUploadLimits::of(req);
+    limits.check_content_length(req)?;
+
+    let path = req.match_info().query("path");
+    if path.is_empty() || path.ends_with('/') {
+        return Err(ContextualError::InvalidHttpRequestError(
+            "The URL has to end with the name of the uploaded file".to_string(),
+        ));
+    }
+    let relative_path = sanitize_upload_name(path)?;
+    let (parent, file_name) = match (relative_path.parent(), relative_path.file_name()) {
+        (Some(parent), Some(file_name)) => (parent, file_name),
+        _ => {
+            return Err(ContextualError::InvalidPathError(format!(
+                "invalid file name {}",
+                path
+            )))
+        }
+    };
+    let upload_dir = resolve_upload_dir(&conf.path, parent).map_err(|err| match err {
+        ContextualError::InvalidHttpRequestError(_) => {
+            ContextualError::InvalidPathError(format!("the directory of {} does not exist", path))
+        }
+        err => err,
+    })?;
+    let file_path = upload_dir.join(file_name);
+    if file_path.is_dir() {
+        return Err(ContextualError::InvalidPathError(format!(
+            "cannot upload to {}, since it's a directory",
+            path
+        )));
+    }
+    dir_config::check_access(req, conf, &file_path, true)?;
+
+    let options = UploadOptions {
+        checksum: checksum_header(req)?,
+        ..UploadOptions::new(req, conf)
+    };
+    let stream = payload.map_err(|e| {
+        ContextualError::InvalidHttpRequestError(format!("Failed to read upload: {}", e))
+    });
+    save_file(stream, file_path, &options, limits).await
+}
+
+/// Handle raw uploads, which send the content of the file as the request body of a `PUT`
+/// request to its URL, e.g. with `curl -T file.txt http://localhost:8080/dir/`.
+pub async fn upload_raw_file(req: HttpRequest, payload: web::Payload) -> HttpResponse {
+    match store_raw_upload(&req, payload).await {
+        Ok(UploadOutcome::Stored(change)) | Ok(UploadOutcome::Linked(change)) => {
+            webhook::notify_changes(&req, vec![change]);
+            HttpResponse::Created().finish()
+        }
+        Ok(UploadOutcome::Skipped) => HttpResponse::NoContent().finish(),
+        Ok(UploadOutcome::Duplicate) => HttpResponse::Ok()
+            .append_header((UPLOAD_RESULT_HEADER, "duplicate"))
+            .finish(),
+        Err(err) => plain_upload_error(err),
+    }
+}
+
+/// Fields of the form creating a new file
+#[derive(Deserialize)]
+pub struct NewFileForm {
+    /// Name of the file, relative to the target directory
+    name: String,
+
+    /// Initial content of the file, the file is empty if it is missing
+    #[serde(default)]
+    content: String,
+}
+
+/// Create the file described by `form` in the directory given by the `path` query parameter.
+/// Existing files are never replaced.
+fn create_new_file(req: &HttpRequest, form: &NewFileForm) -> Result<Change, ContextualError> {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let limits = &UserUploadLimits::of(req);
+    let upload_path = listing::extract_query_parameters(req).path.ok_or_else(|| {
+        ContextualError::InvalidHttpRequestError("Missing query parameter 'path'".to_string())
+    })?;
+    let target_dir = resolve_upload_dir(&conf.path, &upload_path)?;
+    let file_path = target_dir.join(sanitize_upload_name(&form.name)?);
+    dir_config::check_access(req, conf, &file_path, true)?;
+
+    let len = form.content.len() as u64;
+    limits.check_file_size(len)?;
+    create_parent_dirs(&target_dir, &file_path)?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&file_path)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => {
+                ContextualError::DestinationExistsError(form.name.clone())
+            }
+            _ => ContextualError::IoError(format!("Failed to create {}", file_path.display()), e),
+        })?;
 
-    Ok(written_len)
+    limits.add(len)?;
+    if let Err(e) = file.write_all(form.content.as_bytes()) {
+        let _ = std::fs::remove_file(&file_path);
+        limits.release(len);
+        return Err(ContextualError::IoError(
+            "Failed to write to file".to_string(),
+            e,
+        ));
+    }
+
+    Ok(Change {
+        action: ChangeAction::Create,
+        path: file_path,
+        previous_path: None,
+        size: len,
+        extracted_files: Vec::new(),
+    })
+}
+
+/// Handle requests creating a new file, which is empty unless its content is given
+pub async fn create_file(req: HttpRequest, form: web::Form<NewFileForm>) -> HttpResponse {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let return_path = return_path(&req);
+
+    match create_new_file(&req, &form) {
+        Ok(change) => {
+            webhook::notify_changes(&req, vec![change]);
+            HttpResponse::SeeOther()
+                .append_header((header::LOCATION, return_path))
+                .finish()
+        }
+        Err(e) => {
+            let query_params = listing::extract_query_parameters(&req);
+            create_error_response(
+                &e.to_string(),
+                upload_error_code(&e),
+                &return_path,
+                query_params.sort,
+                query_params.order,
+                conf.random_route.is_some(),
+                &conf.favicon_route,
+                &conf.css_route,
+                &conf.default_color_scheme,
+                &conf.default_color_scheme_dark,
+                conf.hide_version_footer,
+            )
+        }
+    }
+}
+
+/// Page the user is sent back to after an upload, i.e. the page the upload was made from
+fn return_path(req: &HttpRequest) -> String {
+    if let Some(header) = req.headers().get(header::REFERER) {
+        header.to_str().unwrap_or("/").to_owned()
+    } else {
+        "/".to_string()
+    }
 }
 
 /// Create new future to handle file as multipart data.
 async fn handle_multipart(
+    req: HttpRequest,
     field: actix_multipart::Field,
     file_path: PathBuf,
-    overwrite_files: bool,
-) -> Result<u64, ContextualError> {
+    options: UploadOptions,
+    limits: UserUploadLimits,
+) -> Result<UploadOutcome, ContextualError> {
     let filename = field
         .content_disposition()
         .and_then(|cd| cd.get_filename().map(String::from))
@@ -68,7 +1360,14 @@ async fn handle_multipart(
         Ok(_) => Ok(()),
     }?;
 
-    save_file(field, file_path.join(filename), overwrite_files).await
+    // Files uploaded along with their folder are stored in the same folder structure
+    let uploaded_path = file_path.join(sanitize_upload_name(&filename)?);
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    dir_config::check_access(&req, conf, &uploaded_path, true)?;
+    create_parent_dirs(&file_path, &uploaded_path)?;
+
+    let stream = field.map_err(ContextualError::MultipartError);
+    save_file(stream, uploaded_path, &options, limits).await
 }
 
 /// Handle incoming request to upload file.
@@ -88,18 +1387,29 @@ pub async fn upload_file(
     hide_version_footer: bool,
 ) -> Result<HttpResponse, actix_web::Error> {
     let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
-    let return_path = if let Some(header) = req.headers().get(header::REFERER) {
-        header.to_str().unwrap_or("/").to_owned()
-    } else {
-        "/".to_string()
-    };
+    let return_path = return_path(&req);
 
     let query_params = listing::extract_query_parameters(&req);
+
+    let limits = UserUploadLimits::of(&req);
+    if let Err(err) = limits.check_content_length(&req) {
+        return Ok(create_error_response(
+            &err.to_string(),
+            upload_error_code(&err),
+            &return_path,
+            query_params.sort,
+            query_params.order,
+            uses_random_route,
+            &favicon_route,
+            &css_route,
+            &default_color_scheme,
+            &default_color_scheme_dark,
+            hide_version_footer,
+        ));
+    }
+
     let upload_path = match query_params.path.clone() {
-        Some(path) => match path.strip_prefix(Component::RootDir) {
-            Ok(stripped_path) => stripped_path.to_owned(),
-            Err(_) => path.clone(),
-        },
+        Some(path) => path,
         None => {
             let err = ContextualError::InvalidHttpRequestError(
                 "Missing query parameter 'path'".to_string(),
@@ -120,16 +1430,16 @@ pub async fn upload_file(
         }
     };
 
-    let app_root_dir = match conf.path.canonicalize() {
+    let target_dir = match resolve_upload_dir(&conf.path, &upload_path) {
         Ok(dir) => dir,
-        Err(e) => {
-            let err = ContextualError::IoError(
-                "Failed to resolve path served by miniserve".to_string(),
-                e,
-            );
+        Err(err) => {
+            let error_code = match err {
+                ContextualError::IoError(..) => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
             return Ok(create_error_response(
                 &err.to_string(),
-                StatusCode::INTERNAL_SERVER_ERROR,
+                error_code,
                 &return_path,
                 query_params.sort,
                 query_params.order,
@@ -142,45 +1452,66 @@ pub async fn upload_file(
             ));
         }
     };
-
-    // If the target path is under the app root directory, save the file.
-    let target_dir = match &app_root_dir.join(upload_path).canonicalize() {
-        Ok(path) if path.starts_with(&app_root_dir) => path.clone(),
-        _ => {
-            let err = ContextualError::InvalidHttpRequestError(
-                "Invalid value for 'path' parameter".to_string(),
+    let options = UploadOptions::new(&req, conf);
+    let default_color_scheme = conf.default_color_scheme.clone();
+    let default_color_scheme_dark = conf.default_color_scheme_dark.clone();
+    let mut fields = actix_multipart::Multipart::new(req.headers(), payload)
+        .map_err(ContextualError::MultipartError);
+    let uploads = async {
+        let mut outcomes = Vec::new();
+        // A checksum field applies to the file field right after it
+        let mut checksum = None;
+        while let Some(field) = fields.try_next().await? {
+            let field_name = field
+                .content_disposition()
+                .and_then(|cd| cd.get_name().map(String::from));
+            if field_name.as_deref() == Some(CHECKSUM_FIELD) {
+                let value = field
+                    .map_err(ContextualError::MultipartError)
+                    .try_fold(Vec::new(), |mut value, bytes| async move {
+                        value.extend_from_slice(&bytes);
+                        Ok(value)
+                    })
+                    .await?;
+                let value = String::from_utf8_lossy(&value);
+                checksum = Some(decode_checksum(&value, |value| hex::decode(value).ok())?);
+                continue;
+            }
+            let options = UploadOptions {
+                checksum: checksum.take(),
+                ..options.clone()
+            };
+            outcomes.push(
+                handle_multipart(
+                    req.clone(),
+                    field,
+                    target_dir.clone(),
+                    options,
+                    limits.clone(),
+                )
+                .await?,
             );
-            return Ok(create_error_response(
-                &err.to_string(),
-                StatusCode::BAD_REQUEST,
-                &return_path,
-                query_params.sort,
-                query_params.order,
-                uses_random_route,
-                &favicon_route,
-                &css_route,
-                &default_color_scheme,
-                &default_color_scheme_dark,
-                hide_version_footer,
-            ));
         }
+        Ok::<_, ContextualError>(outcomes)
     };
-    let overwrite_files = conf.overwrite_files;
-    let default_color_scheme = conf.default_color_scheme.clone();
-    let default_color_scheme_dark = conf.default_color_scheme_dark.clone();
 
-    match actix_multipart::Multipart::new(req.headers(), payload)
-        .map_err(ContextualError::MultipartError)
-        .and_then(move |field| handle_multipart(field, target_dir.clone(), overwrite_files))
-        .try_collect::<Vec<u64>>()
-        .await
-    {
-        Ok(_) => Ok(HttpResponse::SeeOther()
-            .append_header((header::LOCATION, return_path))
-            .finish()),
+    match uploads.await {
+        Ok(outcomes) => {
+            let changes = outcomes
+                .into_iter()
+                .filter_map(|outcome| match outcome {
+                    UploadOutcome::Stored(change) | UploadOutcome::Linked(change) => Some(change),
+                    UploadOutcome::Skipped | UploadOutcome::Duplicate => None,
+                })
+                .collect();
+            webhook::notify_changes(&req, changes);
+            Ok(HttpResponse::SeeOther()
+                .append_header((header::LOCATION, return_path))
+                .finish())
+        }
         Err(e) => Ok(create_error_response(
             &e.to_string(),
-            StatusCode::INTERNAL_SERVER_ERROR,
+            upload_error_code(&e),
             &return_path,
             query_params.sort,
             query_params.order,
@@ -194,6 +1525,29 @@ pub async fn upload_file(
     }
 }
 
+/// Status code of the response to a failed upload
+fn upload_error_code(err: &ContextualError) -> StatusCode {
+    match err {
+        ContextualError::DuplicateFileError | ContextualError::DestinationExistsError(_) => {
+            StatusCode::CONFLICT
+        }
+        ContextualError::InvalidPathError(_)
+        | ContextualError::InvalidHttpRequestError(_)
+        | ContextualError::ArchiveExtractionError(_)
+        | ContextualError::UploadChecksumMismatchError => StatusCode::BAD_REQUEST,
+        ContextualError::UploadRejectedError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        ContextualError::UploadTooLargeError(_) | ContextualError::UploadQuotaExceededError => {
+            StatusCode::PAYLOAD_TOO_LARGE
+        }
+        ContextualError::UserQuotaExceededError(_) => StatusCode::INSUFFICIENT_STORAGE,
+        ContextualError::InvalidHttpCredentials => StatusCode::UNAUTHORIZED,
+        ContextualError::DirectoryAccessDeniedError(_)
+        | ContextualError::UploadsDisabledError
+        | ContextualError::ProtectedFileError(_) => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
 /// Convenience method for creating response errors, if file upload fails.
 #[allow(clippy::too_many_arguments)]
 fn create_error_response(
@@ -210,7 +1564,7 @@ fn create_error_response(
     hide_version_footer: bool,
 ) -> HttpResponse {
     errors::log_error_chain(description.to_string());
-    HttpResponse::BadRequest()
+    HttpResponse::build(error_code)
         .content_type("text/html; charset=utf-8")
         .body(
             renderer::render_error(
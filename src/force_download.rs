@@ -0,0 +1,51 @@
+//! Downloads forced with `--force-download` for the files matching glob patterns, so that shared
+//! HTML or SVG files aren't displayed, and their scripts run, under the origin of miniserve.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use futures::future::TryFutureExt;
+use std::future::Future;
+
+use crate::glob;
+use crate::MiniserveConfig;
+
+/// Send the files matching the patterns of `--force-download` as attachments
+pub fn force_download<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let path = glob::request_path(&req, conf);
+    // Directories, and the index files served for them, are displayed as usual
+    let forced = !path.is_empty()
+        && !path.ends_with('/')
+        && conf
+            .force_download
+            .get()
+            .iter()
+            .any(|patterns| patterns.matches(&path));
+
+    srv.call(req).map_ok(move |mut res| {
+        if forced && matches!(res.status(), StatusCode::OK | StatusCode::PARTIAL_CONTENT) {
+            let disposition = res
+                .headers()
+                .get(header::CONTENT_DISPOSITION)
+                .and_then(|disposition| disposition.to_str().ok())
+                .map(|disposition| {
+                    let parameters = disposition.split_once(';').map(|x| x.1);
+                    parameters.map_or("attachment".to_string(), |parameters| {
+                        format!("attachment;{}", parameters)
+                    })
+                })
+                .and_then(|disposition| HeaderValue::from_str(&disposition).ok())
+                .unwrap_or_else(|| HeaderValue::from_static("attachment"));
+            res.headers_mut()
+                .insert(header::CONTENT_DISPOSITION, disposition);
+        }
+        res
+    })
+}
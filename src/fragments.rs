@@ -0,0 +1,72 @@
+//! HTML fragments shown above and below the listing of a directory with `--enable-fragments`,
+//! like the `HEADER.html` and `FOOTER.html` files of the Apache autoindex.
+//!
+//! They are inserted as they are, so they can contain links, images or instructions about the
+//! directory.
+use std::path::Path;
+
+use crate::MiniserveConfig;
+
+/// Fragments larger than this are left out, rather than slowing down every listing
+const MAX_FRAGMENT_SIZE: u64 = 64 * 1024;
+
+/// Fragments of a directory, when it has them
+#[derive(Debug, Default, PartialEq)]
+pub struct Fragments {
+    pub header: Option<String>,
+    pub footer: Option<String>,
+}
+
+impl Fragments {
+    /// Fragments of the directory at `dir`, read when fragments are enabled
+    pub fn read(conf: &MiniserveConfig, dir: &Path) -> Self {
+        if !conf.fragments_enabled {
+            return Fragments::default();
+        }
+        Fragments {
+            header: read_fragment(&dir.join(&conf.header_name)),
+            footer: read_fragment(&dir.join(&conf.footer_name)),
+        }
+    }
+}
+
+/// Content of the fragment at `path`, if it is a small enough text file
+fn read_fragment(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    if metadata.len() > MAX_FRAGMENT_SIZE {
+        log::warn!(
+            "{} is larger than {} bytes, it isn't shown in the listing",
+            path.display(),
+            MAX_FRAGMENT_SIZE
+        );
+        return None;
+    }
+    std::fs::read_to_string(path).ok()
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn only_small_text_files_are_fragments() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("HEADER.html"), "<p>Hello</p>").unwrap();
+        std::fs::write(dir.path().join("big.html"), vec![b'a'; MAX_FRAGMENT_SIZE as usize + 1]).unwrap();
+        std::fs::write(dir.path().join("binary.html"), [0xff, 0xfe]).unwrap();
+        std::fs::create_dir(dir.path().join("dir.html")).unwrap();
+
+        assert_eq!(read_fragment(&dir.path().join("HEADER.html")).as_deref(), Some("<p>Hello</p>"));
+        assert_eq!(read_fragment(&dir.path().join("big.html")), None);
+        assert_eq!(read_fragment(&dir.path().join("binary.html")), None);
+        assert_eq!(read_fragment(&dir.path().join("dir.html")), None);
+        assert_eq!(read_fragment(&dir.path().join("FOOTER.html")), None);
+    }
+}
@@ -0,0 +1,131 @@
+//! Glob patterns selecting the served files, like `*.css,*.js` or `assets/*.js`.
+use actix_web::dev::ServiceRequest;
+use percent_encoding::percent_decode_str;
+use std::str::FromStr;
+
+use crate::errors::ContextualError;
+use crate::MiniserveConfig;
+
+/// Comma separated glob patterns, matched against the file name, or against the path relative to
+/// the served directory if they contain a slash
+#[derive(Clone, Debug, PartialEq)]
+pub struct Globs {
+    patterns: Vec<String>,
+}
+
+impl Globs {
+    /// Whether any pattern matches the file at `path`, relative to the served directory
+    pub fn matches(&self, path: &str) -> bool {
+        let name = path.rsplit('/').next().unwrap_or_default();
+        self.patterns.iter().any(|pattern| {
+            if pattern.contains('/') {
+                glob_match(pattern.trim_start_matches('/'), path)
+            } else {
+                glob_match(pattern, name)
+            }
+        })
+    }
+}
+
+impl FromStr for Globs {
+    type Err = ContextualError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let patterns = src
+            .split(',')
+            .map(|pattern| pattern.trim().to_string())
+            .collect::<Vec<_>>();
+        if patterns.iter().any(String::is_empty) {
+            return Err(ContextualError::ParseError(
+                format!("glob patterns {}", src),
+                "empty pattern".to_string(),
+            ));
+        }
+        Ok(Globs { patterns })
+    }
+}
+
+/// Whether `text` matches the glob `pattern`, where `*` stands for any sequence of characters
+/// except `/`, and `?` for any single character except `/`
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // Position in the pattern after the last `*`, and in the text where it started to match
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+                continue;
+            }
+            Some('?') if text[t] != '/' => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            Some(&c) if c != '?' && c == text[t] => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            _ => (),
+        }
+        match backtrack {
+            Some((star_p, star_t)) if text[star_t] != '/' => {
+                p = star_p;
+                t = star_t + 1;
+                backtrack = Some((star_p, t));
+            }
+            _ => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Decoded path of the file requested by `req`, relative to the served directory, which ends
+/// with a slash for directories
+pub fn request_path(req: &ServiceRequest, conf: &MiniserveConfig) -> String {
+    let route = format!("/{}", conf.random_route.clone().unwrap_or_default());
+    let path = req
+        .path()
+        .strip_prefix(&route)
+        .unwrap_or_else(|| req.path());
+    percent_decode_str(path.trim_start_matches('/'))
+        .decode_utf8_lossy()
+        .to_string()
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(pattern, text, expected,
+        case("*.css", "style.css", true),
+        case("*.css", "style.css.map", false),
+        case("*", "", true),
+        case("app.?s", "app.js", true),
+        case("assets/*.js", "assets/app.js", true),
+        case("assets/*.js", "assets/vendor/app.js", false),
+        case("*/*.js", "assets/app.js", true),
+        case("a*b*c", "aXbYbZc", true),
+        case("a*b*c", "aXbYbZ", false)
+    )]
+    fn glob_matches(pattern: &str, text: &str, expected: bool) {
+        assert_eq!(glob_match(pattern, text), expected);
+    }
+
+    #[rstest]
+    fn globs_are_parsed() {
+        let globs = "*.css, *.js".parse::<Globs>().unwrap();
+        assert_eq!(globs.patterns, vec!["*.css", "*.js"]);
+        assert!(globs.matches("assets/app.js"));
+        assert!(!globs.matches("index.html"));
+        assert!("*.css,".parse::<Globs>().is_err());
+    }
+}
@@ -0,0 +1,371 @@
+//! HLS streams of the videos with `--enable-hls`, for the browsers and players which can't play
+//! their container or codecs. The playlist of `video.mkv` is at `video.mkv?hls=index.m3u8`, and
+//! its segments are requested the same way, e.g. `video.mkv?hls=segment00000.ts`.
+//!
+//! Each video is transcoded once, by ffmpeg or by the `--transcode-cmd` command, into its own
+//! directory of `--hls-cache`, named after the path, size and modification time of the video. The
+//! playlist is served while the transcoding goes on, so that the video can be played right away.
+use actix_files::NamedFile;
+use actix_web::dev::RequestHead;
+use actix_web::http::{header, Method};
+use actix_web::web::{self, Query};
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::canonical_path;
+use crate::hooks::shell_command;
+use crate::listing::percent_encode_sets::PATH_SEGMENT;
+use crate::storage::requested_file;
+use crate::MiniserveConfig;
+
+/// Name of the playlist written by the transcoding command
+pub const PLAYLIST: &str = "index.m3u8";
+
+/// File written next to the segments once the transcoding succeeded
+const COMPLETE_MARKER: &str = ".complete";
+
+/// Extensions of the videos which can be streamed
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "mkv", "avi", "wmv", "flv", "mov", "mp4", "m4v", "webm", "mpg", "mpeg", "ts", "3gp",
+];
+
+/// How long a client waits for the first segments, before giving up
+const PLAYLIST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the playlist is checked for while waiting for it
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Query parameter of the HLS requests
+#[derive(Deserialize)]
+struct HlsQuery {
+    /// Playlist or segment requested
+    hls: String,
+}
+
+/// Transcoding commands which are running, by output directory
+#[derive(Default)]
+pub struct Transcodes {
+    running: Mutex<HashSet<PathBuf>>,
+}
+
+impl Transcodes {
+    fn is_running(&self, output_dir: &Path) -> bool {
+        let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        running.contains(output_dir)
+    }
+
+    /// Start transcoding `input` into `output_dir`, unless it is already done or running. What is
+    /// left of an interrupted transcoding is removed first.
+    fn start(
+        transcodes: web::Data<Transcodes>,
+        conf: &MiniserveConfig,
+        input: &Path,
+        output_dir: &Path,
+    ) -> io::Result<()> {
+        let mut running = transcodes.running.lock().unwrap_or_else(|e| e.into_inner());
+        if output_dir.join(COMPLETE_MARKER).exists() || running.contains(output_dir) {
+            return Ok(());
+        }
+
+        if output_dir.exists() {
+            std::fs::remove_dir_all(output_dir)?;
+        }
+        std::fs::create_dir_all(output_dir)?;
+        let mut child = transcode_command(conf, input, output_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        running.insert(output_dir.to_path_buf());
+        drop(running);
+
+        let output_dir = output_dir.to_path_buf();
+        let input = input.to_path_buf();
+        actix_web::rt::spawn(async move {
+            let status = web::block(move || child.wait())
+                .await
+                .unwrap_or_else(|e| Err(io::Error::other(e.to_string())));
+            let succeeded = match status {
+                Ok(status) if status.success() => true,
+                Ok(status) => {
+                    log::error!(
+                        "Transcoding of {} to HLS exited with {}",
+                        input.display(),
+                        status
+                    );
+                    false
+                }
+                Err(e) => {
+                    log::error!("Failed to transcode {} to HLS: {}", input.display(), e);
+                    false
+                }
+            };
+            if succeeded {
+                if let Err(e) = std::fs::write(output_dir.join(COMPLETE_MARKER), "") {
+                    log::error!("Failed to mark the HLS stream as complete: {}", e);
+                }
+            } else {
+                // Retried on the next request of the playlist
+                let _ = std::fs::remove_dir_all(&output_dir);
+            }
+            let mut running = transcodes.running.lock().unwrap_or_else(|e| e.into_inner());
+            running.remove(&output_dir);
+        });
+        Ok(())
+    }
+}
+
+/// Whether the file `name` is a video which can be streamed
+pub fn is_video(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| VIDEO_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Whether `head` requests the HLS playlist or a segment of a video of the served directory
+pub fn is_hls_request(conf: &MiniserveConfig, head: &RequestHead) -> bool {
+    if head.method != Method::GET {
+        return false;
+    }
+    if Query::<HlsQuery>::from_query(head.uri.query().unwrap_or_default()).is_err() {
+        return false;
+    }
+    requested_file(conf, head.uri.path())
+        .is_some_and(|file| file.is_file() && is_video(&file.to_string_lossy()))
+}
+
+/// Directory of the cache holding the stream of `video`, which changes along with the video
+fn cache_dir(conf: &MiniserveConfig, video: &Path) -> io::Result<PathBuf> {
+    let video = canonical_path::canonicalize(video)?;
+    let metadata = video.metadata()?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos());
+
+    let mut hasher = Sha256::new();
+    hasher.update(video.to_string_lossy().as_bytes());
+    hasher.update(format!("|{}|{}", metadata.len(), modified).as_bytes());
+    Ok(conf.hls_cache.join(hex::encode(&hasher.finalize()[..16])))
+}
+
+/// Command transcoding `input` into the playlist and segments of `output_dir`
+fn transcode_command(conf: &MiniserveConfig, input: &Path, output_dir: &Path) -> Command {
+    match &conf.transcode_cmd {
+        Some(transcode_cmd) => {
+            let mut command = shell_command(transcode_cmd);
+            command
+                .env("MINISERVE_INPUT", input)
+                .env("MINISERVE_OUTPUT_DIR", output_dir);
+            command
+        }
+        None => {
+            let mut command = Command::new("ffmpeg");
+            command
+                .args(["-nostdin", "-loglevel", "error", "-i"])
+                .arg(input)
+                .args([
+                    "-c:v",
+                    "libx264",
+                    "-preset",
+                    "veryfast",
+                    "-c:a",
+                    "aac",
+                    "-f",
+                    "hls",
+                    "-hls_time",
+                    "6",
+                    "-hls_playlist_type",
+                    "event",
+                    "-hls_segment_filename",
+                ])
+                .arg(output_dir.join("segment%05d.ts"))
+                .arg(output_dir.join(PLAYLIST));
+            command
+        }
+    }
+}
+
+/// Point the URIs of `playlist` to the segments of the video called `name`
+fn rewrite_playlist(playlist: &str, name: &str) -> String {
+    let name = utf8_percent_encode(name, PATH_SEGMENT).to_string();
+    let segment_url = |uri: &str| {
+        format!(
+            "{}?hls={}",
+            name,
+            utf8_percent_encode(uri, NON_ALPHANUMERIC)
+        )
+    };
+
+    playlist
+        .lines()
+        .map(|line| {
+            if line.is_empty() {
+                line.to_string()
+            } else if !line.starts_with('#') {
+                segment_url(line.trim())
+            } else if let Some((start, rest)) = line.split_once("URI=\"") {
+                // e.g. the initialization section of fragmented MP4 streams
+                match rest.split_once('"') {
+                    Some((uri, end)) => format!("{}URI=\"{}\"{}", start, segment_url(uri), end),
+                    None => line.to_string(),
+                }
+            } else {
+                line.to_string()
+            }
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Whether `name` can be a file written by the transcoding command
+fn is_segment_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(&['/', '\\'][..])
+}
+
+/// Media type of the segment `name`
+fn segment_type(name: &str) -> mime::Mime {
+    match Path::new(name).extension().and_then(|e| e.to_str()) {
+        Some("ts") => "video/mp2t".parse().unwrap(),
+        Some("m4s") => "video/iso.segment".parse().unwrap(),
+        _ => mime_guess::from_path(name).first_or_octet_stream(),
+    }
+}
+
+/// Send the playlist of the video requested by `req`, once the transcoding wrote it
+async fn playlist(
+    req: &HttpRequest,
+    conf: &MiniserveConfig,
+    video: &Path,
+    output_dir: &Path,
+) -> HttpResponse {
+    let transcodes = req.app_data::<web::Data<Transcodes>>().unwrap().clone();
+    let failed = |message: &str| {
+        HttpResponse::InternalServerError()
+            .content_type("text/plain; charset=utf-8")
+            .body(message.to_string())
+    };
+
+    if let Err(e) = Transcodes::start(transcodes.clone(), conf, video, output_dir) {
+        log::error!("Failed to transcode {} to HLS: {}", video.display(), e);
+        return failed("Failed to start transcoding the video");
+    }
+
+    let path = output_dir.join(PLAYLIST);
+    let started = Instant::now();
+    let playlist = loop {
+        match std::fs::read_to_string(&path) {
+            // The playlist is only complete enough to be played once a segment is listed
+            Ok(playlist)
+                if playlist
+                    .lines()
+                    .any(|line| !line.is_empty() && !line.starts_with('#')) =>
+            {
+                break playlist
+            }
+            _ if !transcodes.is_running(output_dir)
+                && !output_dir.join(COMPLETE_MARKER).exists() =>
+            {
+                return failed("Failed to transcode the video");
+            }
+            _ if started.elapsed() > PLAYLIST_TIMEOUT => {
+                return failed("The transcoding of the video is too slow");
+            }
+            _ => actix_web::rt::time::sleep(POLL_INTERVAL).await,
+        }
+    };
+
+    let name = video
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    HttpResponse::Ok()
+        .content_type("application/vnd.apple.mpegurl")
+        // The playlist grows while the video is transcoded
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .body(rewrite_playlist(&playlist, &name))
+}
+
+/// Send the HLS playlist or segment of the video requested by `req`
+pub async fn stream(req: HttpRequest) -> HttpResponse {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let query = match Query::<HlsQuery>::from_query(req.query_string()) {
+        Ok(query) => query.into_inner(),
+        Err(_) => return crate::error_404(req).await,
+    };
+    let video = match requested_file(conf, req.path()) {
+        Some(video) if video.is_file() => video,
+        _ => return crate::error_404(req).await,
+    };
+    let output_dir = match cache_dir(conf, &video) {
+        Ok(output_dir) => output_dir,
+        Err(e) => {
+            log::error!("Failed to read {}: {}", video.display(), e);
+            return crate::error_404(req).await;
+        }
+    };
+
+    if query.hls == PLAYLIST {
+        return playlist(&req, conf, &video, &output_dir).await;
+    }
+    if !is_segment_name(&query.hls) {
+        return crate::error_404(req).await;
+    }
+    match NamedFile::open(output_dir.join(&query.hls)) {
+        Ok(segment) => segment
+            .set_content_type(segment_type(&query.hls))
+            .respond_to(&req),
+        Err(_) => crate::error_404(req).await,
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(name, expected,
+        case("movie.MKV", true),
+        case("clip.webm", true),
+        case("song.mp3", false),
+        case("mkv", false)
+    )]
+    fn videos_are_recognized(name: &str, expected: bool) {
+        assert_eq!(is_video(name), expected);
+    }
+
+    #[rstest(name, expected,
+        case("segment00001.ts", true),
+        case("../secret.txt", false),
+        case("sub/segment.ts", false),
+        case(".complete", false),
+        case("", false)
+    )]
+    fn segment_names(name: &str, expected: bool) {
+        assert_eq!(is_segment_name(name), expected);
+    }
+
+    #[rstest]
+    fn playlist_points_to_segments() {
+        let playlist = "#EXTM3U\n#EXT-X-MAP:URI=\"init.mp4\"\n#EXTINF:6.0,\nsegment00000.ts\n";
+        assert_eq!(
+            rewrite_playlist(playlist, "my movie.mkv"),
+            "#EXTM3U\n\
+             #EXT-X-MAP:URI=\"my%20movie.mkv?hls=init%2Emp4\"\n\
+             #EXTINF:6.0,\n\
+             my%20movie.mkv?hls=segment00000%2Ets\n"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Commands run on the events given with `--hook <event>=<command>`, e.g. to send notifications,
+//! with the details of the event in `MINISERVE_*` environment variables.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{web, HttpRequest};
+use futures::future::TryFutureExt;
+use std::future::Future;
+use std::io;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use strum::VariantNames;
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+use crate::auth::CurrentUser;
+use crate::errors::ContextualError;
+use crate::proxy;
+use crate::storage::requested_file;
+use crate::MiniserveConfig;
+
+/// Event which hooks can be run on
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum HookEvent {
+    /// The server started accepting connections
+    Startup,
+
+    /// A file was sent to a client
+    Download,
+
+    /// A file was uploaded
+    Upload,
+
+    /// A client sent wrong credentials
+    AuthFailure,
+}
+
+/// Command run on an event
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hook {
+    pub event: HookEvent,
+
+    /// Command run through the shell
+    pub command: String,
+}
+
+/// Parse a hook like `upload=notify-send "New upload"`
+pub fn parse_hook(src: &str) -> Result<Hook, ContextualError> {
+    let parse_error =
+        |cause: &str| ContextualError::ParseError(format!("hook {}", src), cause.to_string());
+
+    let (event, command) = src
+        .split_once('=')
+        .ok_or_else(|| parse_error("expected <event>=<command>"))?;
+    let event = HookEvent::from_str(event.trim()).map_err(|_| {
+        parse_error(&format!(
+            "expected one of the events {}",
+            HookEvent::VARIANTS.join(", ")
+        ))
+    })?;
+    let command = command.trim();
+    if command.is_empty() {
+        return Err(parse_error("empty command"));
+    }
+    Ok(Hook {
+        event,
+        command: command.to_string(),
+    })
+}
+
+/// Details of an event, given to the hooks in environment variables
+#[derive(Default)]
+pub struct EventDetails {
+    /// Path of the file, relative to the served directory, or the served directory on startup
+    pub path: Option<String>,
+
+    pub size: Option<u64>,
+    pub client_ip: Option<String>,
+    pub user: Option<String>,
+
+    /// Addresses the server listens on, on startup
+    pub addresses: Option<String>,
+}
+
+impl EventDetails {
+    /// Details of an event caused by `req`
+    pub fn from_request(req: &HttpRequest) -> Self {
+        EventDetails {
+            client_ip: proxy::client_ip(req).map(|ip| ip.to_string()),
+            user: req
+                .extensions()
+                .get::<CurrentUser>()
+                .map(|user| user.name.clone()),
+            ..EventDetails::default()
+        }
+    }
+
+    /// Environment variables of the details which are known
+    fn env_vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("MINISERVE_PATH", self.path.clone()),
+            ("MINISERVE_SIZE", self.size.map(|size| size.to_string())),
+            ("MINISERVE_CLIENT_IP", self.client_ip.clone()),
+            ("MINISERVE_USER", self.user.clone()),
+            ("MINISERVE_ADDRESSES", self.addresses.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .collect()
+    }
+}
+
+/// Build the command running `command` through the shell
+pub fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+/// Run the hooks of `event` in the background. Failures are only logged.
+pub fn run_hooks(hooks: &[Hook], event: HookEvent, details: &EventDetails) {
+    for hook in hooks.iter().filter(|hook| hook.event == event) {
+        let mut command = shell_command(&hook.command);
+        command
+            .stdin(Stdio::null())
+            .env("MINISERVE_EVENT", event.to_string())
+            .envs(details.env_vars());
+
+        let hook_command = hook.command.clone();
+        actix_web::rt::spawn(async move {
+            let status = web::block(move || command.status())
+                .await
+                .unwrap_or_else(|e| Err(io::Error::other(e.to_string())));
+            match status {
+                Ok(status) if !status.success() => {
+                    log::warn!("Hook {:?} exited with {}", hook_command, status)
+                }
+                Ok(_) => (),
+                Err(e) => log::error!("Failed to run hook {:?}: {}", hook_command, e),
+            }
+        });
+    }
+}
+
+/// Run the download hooks when a file of the served directory is sent
+pub fn hook_downloads<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    srv.call(req).map_ok(|res| {
+        let req = res.request();
+        // Parts of a file requested with a Range header aren't complete downloads
+        if req.method() != Method::GET || res.status() != StatusCode::OK {
+            return res;
+        }
+        let conf = match req.app_data::<MiniserveConfig>() {
+            Some(conf)
+                if conf
+                    .hooks
+                    .iter()
+                    .any(|hook| hook.event == HookEvent::Download) =>
+            {
+                conf
+            }
+            _ => return res,
+        };
+        let file = match requested_file(conf, req.path()) {
+            Some(file) if file.is_file() => file,
+            _ => return res,
+        };
+
+        let details = EventDetails {
+            path: file.strip_prefix(&conf.path).ok().map(|path| {
+                let components = path
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>();
+                format!("/{}", components.join("/"))
+            }),
+            size: file.metadata().ok().map(|metadata| metadata.len()),
+            ..EventDetails::from_request(req)
+        };
+        run_hooks(&conf.hooks, HookEvent::Download, &details);
+        res
+    })
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(src, event, command,
+        case("upload=notify-send \"New upload\"", HookEvent::Upload, "notify-send \"New upload\""),
+        case("auth-failure = ./alert.sh", HookEvent::AuthFailure, "./alert.sh"),
+        case("startup=curl -d up=1 https://example.com", HookEvent::Startup, "curl -d up=1 https://example.com")
+    )]
+    fn hook_is_parsed(src: &str, event: HookEvent, command: &str) {
+        assert_eq!(parse_hook(src).unwrap(), Hook { event, command: command.to_string() });
+    }
+
+    #[rstest(src,
+        case("upload"),
+        case("upload="),
+        case("shutdown=./stop.sh")
+    )]
+    fn invalid_hooks_are_rejected(src: &str) {
+        assert!(parse_hook(src).is_err());
+    }
+
+    #[rstest]
+    fn unknown_details_are_left_out() {
+        let details = EventDetails {
+            path: Some("/dir/file.txt".to_string()),
+            size: Some(3),
+            ..EventDetails::default()
+        };
+        assert_eq!(
+            details.env_vars(),
+            vec![("MINISERVE_PATH", "/dir/file.txt".to_string()), ("MINISERVE_SIZE", "3".to_string())]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Plain HTTP served alongside HTTPS with `--http-port`, optionally redirected to HTTPS with
+//! `--redirect-to-https`, and the Strict-Transport-Security header enabled with `--hsts`.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::HttpResponse;
+use futures::future::{ready, Either};
+use std::future::Future;
+
+use crate::proxy;
+use crate::MiniserveConfig;
+
+/// URL of `path` on the HTTPS listener at `port`, for a request made to `host`
+fn https_url(host: &str, port: u16, path: &str) -> String {
+    // Remove the port of the plain HTTP listener, without cutting IPv6 addresses like [::1]
+    let hostname = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    };
+    match port {
+        443 => format!("https://{}{}", hostname, path),
+        port => format!("https://{}:{}{}", hostname, port, path),
+    }
+}
+
+/// Redirect plain HTTP requests to HTTPS if `--redirect-to-https` is set, and add the
+/// Strict-Transport-Security header to HTTPS responses if `--hsts` is set
+pub fn enforce_https<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let (req, payload) = req.into_parts();
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let secure = req.app_config().secure();
+
+    if !secure && conf.redirect_to_https {
+        let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+        let location = https_url(&proxy::host(&req), conf.port, path);
+        let res = HttpResponse::MovedPermanently()
+            .insert_header((header::LOCATION, location))
+            .finish();
+        return Either::Left(ready(Ok(ServiceResponse::new(req, res))));
+    }
+
+    let hsts_max_age = conf.hsts_max_age.filter(|_| secure);
+    let fut = srv.call(ServiceRequest::from_parts(req, payload));
+
+    Either::Right(async move {
+        let mut res = fut.await?;
+        if let Some(max_age) = hsts_max_age {
+            res.headers_mut().insert(
+                header::STRICT_TRANSPORT_SECURITY,
+                HeaderValue::from_str(&format!("max-age={}", max_age)).unwrap(),
+            );
+        }
+        Ok(res)
+    })
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(host, port, path, expected,
+        case("example.com", 443, "/", "https://example.com/"),
+        case("example.com:80", 443, "/dir/?q=1", "https://example.com/dir/?q=1"),
+        case("localhost:8081", 8080, "/file", "https://localhost:8080/file"),
+        case("[::1]:8081", 8080, "/", "https://[::1]:8080/"),
+        case("[::1]", 443, "/", "https://[::1]/")
+    )]
+    fn https_url_replaces_port(host: &str, port: u16, path: &str, expected: &str) {
+        assert_eq!(https_url(host, port, path), expected);
+    }
+}
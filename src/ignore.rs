@@ -0,0 +1,290 @@
+//! Entries hidden from the listings, archives, feeds and manifests by gitignore-style patterns,
+//! read from the `.miniserveignore` files of the served directories with `--enable-ignore-files`,
+//! or from a global `--ignore-file`.
+//!
+//! Patterns support `*`, `?` and `**`, negation with `!`, and match directories only when they
+//! end with `/`. Like with git, the content of an ignored directory can't be included again.
+//! Ignored entries can't be requested either, unless `--serve-ignored` is given.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::HttpResponse;
+use futures::future::{ready, Either};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+use crate::glob::glob_match;
+use crate::storage::requested_file;
+use crate::MiniserveConfig;
+
+/// Name of the files listing the patterns of the entries ignored in their directory
+pub const IGNORE_FILE: &str = ".miniserveignore";
+
+/// Pattern of a line of an ignore file
+#[derive(Clone, Debug, PartialEq)]
+pub struct IgnorePattern {
+    /// Directory of the ignore file, relative to the served directory, where the pattern applies
+    base: PathBuf,
+
+    /// Components of the pattern, which is matched against the name of the entries if it has a
+    /// single one and isn't anchored by a slash
+    segments: Vec<String>,
+    anchored: bool,
+    negated: bool,
+    dirs_only: bool,
+}
+
+impl IgnorePattern {
+    /// Parse a line of an ignore file, which is skipped if it is empty or a comment
+    fn parse(line: &str, base: &Path) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dirs_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        if line.is_empty() {
+            return None;
+        }
+        Some(IgnorePattern {
+            base: base.to_path_buf(),
+            segments: line
+                .trim_start_matches('/')
+                .split('/')
+                .map(String::from)
+                .collect(),
+            anchored: line.contains('/'),
+            negated,
+            dirs_only,
+        })
+    }
+
+    /// Whether the pattern matches the entry at `path`, relative to the served directory
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dirs_only && !is_dir {
+            return false;
+        }
+        let path = match path.strip_prefix(&self.base) {
+            Ok(path) => path,
+            Err(_) => return false,
+        };
+        let components = path
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect::<Vec<_>>();
+        let components = components.iter().map(|c| c.as_ref()).collect::<Vec<_>>();
+        if self.anchored {
+            let segments = self.segments.iter().map(String::as_str).collect::<Vec<_>>();
+            match_segments(&segments, &components)
+        } else {
+            components
+                .last()
+                .is_some_and(|name| glob_match(&self.segments[0], name))
+        }
+    }
+}
+
+/// Whether the `components` of a path match the `segments` of a pattern, where `**` stands for
+/// any number of components
+fn match_segments(segments: &[&str], components: &[&str]) -> bool {
+    match segments {
+        [] => components.is_empty(),
+        // `dir/**` matches what is inside of the directory, not the directory itself
+        ["**"] => !components.is_empty(),
+        ["**", rest @ ..] => (0..=components.len()).any(|i| match_segments(rest, &components[i..])),
+        [segment, rest @ ..] => match components {
+            [component, components @ ..] => {
+                glob_match(segment, component) && match_segments(rest, components)
+            }
+            [] => false,
+        },
+    }
+}
+
+/// Parse the content of an ignore file of the directory at `base`, relative to the served
+/// directory
+pub fn parse_ignore_file(content: &str, base: &Path) -> Vec<IgnorePattern> {
+    content
+        .lines()
+        .filter_map(|line| IgnorePattern::parse(line, base))
+        .collect()
+}
+
+/// Entries ignored by the served directory
+#[derive(Clone, Debug, Default)]
+pub struct Ignore {
+    /// Patterns of the global --ignore-file, relative to the served directory
+    pub patterns: Vec<IgnorePattern>,
+
+    /// Whether the .miniserveignore files of the directories are read
+    pub ignore_files: bool,
+}
+
+impl Ignore {
+    /// Patterns applying to the entries of the directory at `root`, the served directory
+    pub fn root(&self, root: &Path) -> IgnoredEntries {
+        let mut entries = IgnoredEntries {
+            dir: root.to_path_buf(),
+            relative_dir: PathBuf::new(),
+            patterns: self.patterns.clone(),
+            ignore_files: self.ignore_files,
+        };
+        entries.read_ignore_file();
+        entries
+    }
+
+    /// Patterns applying to the entries of `dir`, inside of the served directory at `root`, or
+    /// `None` if `dir` is ignored itself
+    pub fn dir(&self, root: &Path, dir: &Path) -> Option<IgnoredEntries> {
+        let relative_dir = dir.strip_prefix(root).unwrap_or_else(|_| Path::new(""));
+        relative_dir
+            .iter()
+            .try_fold(self.root(root), |entries, name| {
+                entries.subdir(&name.to_string_lossy())
+            })
+    }
+
+    /// Whether the entry at `path`, inside of the served directory at `root`, is ignored
+    pub fn is_ignored(&self, root: &Path, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None => return false,
+        };
+        match path.parent().map(|parent| self.dir(root, parent)) {
+            Some(Some(entries)) => entries.is_ignored(&name, path.is_dir()),
+            Some(None) => true,
+            None => false,
+        }
+    }
+}
+
+/// Patterns applying to the entries of a directory, from the ignore files of its parents
+#[derive(Clone, Debug)]
+pub struct IgnoredEntries {
+    dir: PathBuf,
+    relative_dir: PathBuf,
+    patterns: Vec<IgnorePattern>,
+    ignore_files: bool,
+}
+
+impl IgnoredEntries {
+    fn read_ignore_file(&mut self) {
+        if !self.ignore_files {
+            return;
+        }
+        if let Ok(content) = std::fs::read_to_string(self.dir.join(IGNORE_FILE)) {
+            self.patterns
+                .extend(parse_ignore_file(&content, &self.relative_dir));
+        }
+    }
+
+    /// Whether the entry `name` of the directory is ignored, the last matching pattern wins
+    pub fn is_ignored(&self, name: &str, is_dir: bool) -> bool {
+        let path = self.relative_dir.join(name);
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(&path, is_dir))
+            .is_some_and(|pattern| !pattern.negated)
+    }
+
+    /// Patterns applying to the entries of the subdirectory `name`, or `None` if it is ignored
+    pub fn subdir(&self, name: &str) -> Option<IgnoredEntries> {
+        if self.is_ignored(name, true) {
+            return None;
+        }
+        let mut entries = IgnoredEntries {
+            dir: self.dir.join(name),
+            relative_dir: self.relative_dir.join(name),
+            patterns: self.patterns.clone(),
+            ignore_files: self.ignore_files,
+        };
+        entries.read_ignore_file();
+        Some(entries)
+    }
+}
+
+/// Answer the requests for ignored entries with 404, unless they are served anyway
+pub fn hide_ignored<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let is_ignored = match (&conf.ignore, requested_file(conf, req.path())) {
+        (Some(ignore), Some(path)) if !conf.serve_ignored && path.exists() => {
+            ignore.is_ignored(&conf.path, &path)
+        }
+        _ => false,
+    };
+
+    if is_ignored {
+        let (req, _) = req.into_parts();
+        let resp = HttpResponse::NotFound().finish();
+        Either::Right(ready(Ok(ServiceResponse::new(req, resp))))
+    } else {
+        Either::Left(srv.call(req))
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(pattern, path, is_dir, expected,
+        case("*.log", "a.log", false, true),
+        case("*.log", "logs/a.log", false, true),
+        case("/*.log", "logs/a.log", false, false),
+        case("build/", "build", true, true),
+        case("build/", "build", false, false),
+        case("docs/*.md", "docs/a.md", false, true),
+        case("docs/*.md", "src/docs/a.md", false, false),
+        case("**/cache", "a/b/cache", true, true),
+        case("a/**/b", "a/b", true, true),
+        case("a/**/b", "a/x/y/b", true, true),
+        case("a/**", "a", true, false),
+        case("a/**", "a/b.txt", false, true)
+    )]
+    fn patterns_match(pattern: &str, path: &str, is_dir: bool, expected: bool) {
+        let pattern = IgnorePattern::parse(pattern, Path::new("")).unwrap();
+        assert_eq!(pattern.matches(Path::new(path), is_dir), expected);
+    }
+
+    #[rstest]
+    fn comments_and_blank_lines_are_skipped() {
+        let patterns = parse_ignore_file("# build output\n\ntarget/\n!keep.log\n", Path::new("sub"));
+        assert_eq!(patterns.len(), 2);
+        assert!(patterns[0].dirs_only);
+        assert!(patterns[1].negated);
+        assert!(!patterns[0].matches(Path::new("target"), true));
+        assert!(patterns[0].matches(Path::new("sub/target"), true));
+    }
+
+    #[rstest]
+    fn ignore_files_of_directories_are_merged() {
+        let root = TempDir::new().unwrap();
+        std::fs::create_dir_all(root.path().join("a/b")).unwrap();
+        std::fs::create_dir_all(root.path().join("secret")).unwrap();
+        std::fs::write(root.path().join(IGNORE_FILE), "*.log\nsecret/").unwrap();
+        std::fs::write(root.path().join("a").join(IGNORE_FILE), "!keep.log").unwrap();
+        let ignore = Ignore { patterns: parse_ignore_file("*.tmp", Path::new("")), ignore_files: true };
+
+        let entries = ignore.dir(root.path(), &root.path().join("a/b")).unwrap();
+        assert!(entries.is_ignored("debug.log", false));
+        assert!(!entries.is_ignored("keep.log", false));
+        assert!(entries.is_ignored("x.tmp", false));
+        assert!(ignore.dir(root.path(), &root.path().join("secret")).is_none());
+        assert!(ignore.is_ignored(root.path(), &root.path().join("secret")));
+    }
+}
@@ -0,0 +1,244 @@
+//! Restriction of the clients by IP address with `--allow-ip` and `--deny-ip`.
+use actix_web::dev::{Extensions, Service, ServiceRequest, ServiceResponse};
+use actix_web::rt::net::TcpStream;
+use actix_web::HttpResponse;
+use futures::future::{ready, Either};
+use std::any::Any;
+use std::future::Future;
+use std::net::{IpAddr, Shutdown};
+use std::str::FromStr;
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+use crate::errors::ContextualError;
+use crate::proxy;
+use crate::proxy_protocol::ProxiedClient;
+use crate::MiniserveConfig;
+
+/// Range of IP addresses in CIDR notation, e.g. `192.168.1.0/24`, or a single address
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNet {
+    /// Return `true` if `ip` is in this range
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = ContextualError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let parse_error = |reason: &str| {
+            ContextualError::ParseError(format!("IP range {}", src), reason.to_string())
+        };
+
+        let (addr, prefix_len) = match src.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (src, None),
+        };
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|e| parse_error(&e.to_string()))?;
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|&prefix_len| prefix_len <= max_prefix_len)
+                .ok_or_else(|| parse_error("invalid prefix length"))?,
+            None => max_prefix_len,
+        };
+
+        Ok(IpNet { addr, prefix_len })
+    }
+}
+
+/// `ip` in its usual form: clients connecting over IPv4 to a dual-stack socket show up as
+/// ::ffff:a.b.c.d
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) if v6.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => {
+            v6.to_ipv4().map_or(ip, IpAddr::V4)
+        }
+        ip => ip,
+    }
+}
+
+/// What to do with the requests of clients which aren't allowed
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum DenyAction {
+    /// Answer with "403 Forbidden"
+    Forbidden,
+
+    /// Close the connection without answering
+    Drop,
+}
+
+/// Client IP addresses which are allowed or denied
+#[derive(Clone, Debug)]
+pub struct IpFilter {
+    /// If not empty, only these addresses are allowed
+    pub allow: Vec<IpNet>,
+
+    /// These addresses are denied, even if they are allowed by `allow`
+    pub deny: Vec<IpNet>,
+
+    /// What to do with the requests of denied clients
+    pub action: DenyAction,
+}
+
+impl IpFilter {
+    /// Return `true` if requests from `ip` are allowed
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        let ip = canonical_ip(ip);
+        !self.deny.iter().any(|net| net.contains(ip))
+            && (self.allow.is_empty() || self.allow.iter().any(|net| net.contains(ip)))
+    }
+
+    /// Close the new connection `conn` right away if its client isn't allowed and the action is
+    /// `DenyAction::Drop`. The clients of the `trusted_proxies` are only known from their requests.
+    pub fn drop_denied_connection(
+        &self,
+        conn: &dyn Any,
+        data: &Extensions,
+        trusted_proxies: &[IpNet],
+    ) {
+        if self.action != DenyAction::Drop {
+            return;
+        }
+
+        let addr = match data.get::<ProxiedClient>() {
+            Some(ProxiedClient(client)) => Some(*client),
+            None => tcp_stream(conn).and_then(|stream| stream.peer_addr().ok()),
+        };
+        if let Some(addr) = addr {
+            let ip = canonical_ip(addr.ip());
+            let is_proxy = trusted_proxies.iter().any(|net| net.contains(ip));
+            if !is_proxy && !self.is_allowed(ip) {
+                close_connection(conn);
+            }
+        }
+    }
+}
+
+/// TCP stream of the new connection `conn`, which may be wrapped in TLS
+fn tcp_stream(conn: &dyn Any) -> Option<&TcpStream> {
+    let stream = conn.downcast_ref::<TcpStream>();
+    #[cfg(feature = "tls")]
+    let stream = stream.or_else(|| {
+        conn.downcast_ref::<actix_tls::accept::rustls::TlsStream<TcpStream>>()
+            .map(|stream| stream.get_ref().0)
+    });
+    stream
+}
+
+/// Close the new connection `conn` without answering
+pub fn close_connection(conn: &dyn Any) {
+    let result = match tcp_stream(conn) {
+        Some(stream) => socket2::SockRef::from(stream).shutdown(Shutdown::Both),
+        #[cfg(unix)]
+        None => match conn.downcast_ref::<actix_web::rt::net::UnixStream>() {
+            Some(stream) => socket2::SockRef::from(stream).shutdown(Shutdown::Both),
+            None => return,
+        },
+        #[cfg(not(unix))]
+        None => return,
+    };
+    if let Err(e) = result {
+        log::error!("Failed to close a connection: {}", e);
+    }
+}
+
+/// Refuse the requests of clients which aren't allowed, identified through the trusted proxies.
+/// Connections without IP address, like the ones on a Unix domain socket, are allowed.
+pub fn filter_ip<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let (req, payload) = req.into_parts();
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let allowed = proxy::client_ip(&req).is_none_or(|ip| conf.ip_filter.is_allowed(ip));
+
+    if allowed {
+        Either::Left(srv.call(ServiceRequest::from_parts(req, payload)))
+    } else {
+        let res = HttpResponse::Forbidden().finish();
+        Either::Right(ready(Ok(ServiceResponse::new(req, res))))
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    fn filter(allow: &[&str], deny: &[&str]) -> IpFilter {
+        IpFilter {
+            allow: allow.iter().map(|net| net.parse().unwrap()).collect(),
+            deny: deny.iter().map(|net| net.parse().unwrap()).collect(),
+            action: DenyAction::Forbidden,
+        }
+    }
+
+    #[rstest(net, ip, expected,
+        case("192.168.1.0/24", "192.168.1.42", true),
+        case("192.168.1.0/24", "192.168.2.1", false),
+        case("10.0.0.1", "10.0.0.1", true),
+        case("10.0.0.1", "10.0.0.2", false),
+        case("0.0.0.0/0", "8.8.8.8", true),
+        case("fd00::/8", "fd12::1", true),
+        case("fd00::/8", "fe80::1", false),
+        case("::/0", "10.0.0.1", false)
+    )]
+    fn ip_net_contains(net: &str, ip: &str, expected: bool) {
+        let net = net.parse::<IpNet>().unwrap();
+        assert_eq!(net.contains(ip.parse().unwrap()), expected);
+    }
+
+    #[rstest(net,
+        case("192.168.1.0/33"),
+        case("192.168.1.0/"),
+        case("192.168.1/24"),
+        case("example.com")
+    )]
+    fn ip_net_invalid(net: &str) {
+        assert!(net.parse::<IpNet>().is_err());
+    }
+
+    #[rstest(allow, deny, ip, expected,
+        case(&[], &[], "8.8.8.8", true),
+        case(&["10.0.0.0/8"], &[], "10.1.2.3", true),
+        case(&["10.0.0.0/8"], &[], "8.8.8.8", false),
+        case(&["10.0.0.0/8"], &["10.0.0.13"], "10.0.0.13", false),
+        case(&[], &["10.0.0.13"], "10.0.0.14", true),
+        case(&["10.0.0.0/8"], &[], "::ffff:10.1.2.3", true)
+    )]
+    fn ip_filter_is_allowed(allow: &[&str], deny: &[&str], ip: &str, expected: bool) {
+        assert_eq!(filter(allow, deny).is_allowed(ip.parse().unwrap()), expected);
+    }
+}
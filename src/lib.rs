@@ -0,0 +1,578 @@
+//! Serve a directory over HTTP, with the features of the `miniserve` binary, from another Rust
+//! program. See [`Server`] to embed a server.
+use std::future::Future;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::web;
+use actix_web::{guard, HttpRequest, HttpResponse};
+use actix_web::{
+    http::{
+        header::{self, ContentType, HeaderValue},
+        StatusCode,
+    },
+    Responder,
+};
+
+mod access_log;
+#[cfg(feature = "tls")]
+mod acme;
+mod admin;
+mod archive;
+mod archive_fs;
+pub mod args;
+mod auth;
+mod auto_shutdown;
+mod cache_control;
+pub mod canonical_path;
+mod canonical_url;
+mod case_insensitive;
+mod compress;
+mod config;
+pub mod config_file;
+mod dir_config;
+mod disk_usage;
+mod download_stats;
+mod error_pages;
+pub mod errors;
+mod feed;
+mod file_op;
+mod file_upload;
+mod force_download;
+mod fragments;
+mod glob;
+mod hls;
+mod hooks;
+mod https;
+mod ignore;
+mod ip_filter;
+mod listing;
+mod listing_cache;
+mod live_updates;
+mod log_file;
+mod manifest;
+mod memory_cache;
+mod metrics;
+mod middlewares;
+mod mime_types;
+mod mirror;
+mod mount;
+mod network_interface;
+mod opds;
+mod overlay_fs;
+mod photo;
+mod pipe;
+mod playlist;
+mod precompressed;
+mod proxy;
+mod proxy_protocol;
+mod raw_names;
+mod renderer;
+mod resize;
+mod s3;
+mod search;
+mod server;
+pub mod service;
+mod session;
+mod share;
+mod stdin;
+mod storage;
+pub mod system_log;
+mod tail;
+pub mod telemetry;
+mod throttle;
+mod timeout;
+#[cfg(feature = "tls")]
+mod tls;
+mod torrent;
+mod upload_only;
+mod upload_ttl;
+mod upnp;
+mod user_dirs;
+mod webhook;
+
+pub use crate::access_log::LogFormat;
+pub use crate::config::MiniserveConfig;
+pub use crate::errors::ContextualError;
+pub use crate::server::{RunningServer, Server, ServerBuilder, ServerHandle};
+pub use crate::system_log::LogTarget;
+
+/// Headers sent with --security-headers, unless they are set with --header
+const SECURITY_HEADERS: [(&str, &str); 3] = [
+    ("x-content-type-options", "nosniff"),
+    ("x-frame-options", "DENY"),
+    ("referrer-policy", "no-referrer"),
+];
+
+/// Content-Security-Policy sent with --security-headers, allowing the inline scripts and event
+/// handlers of the listing
+const DEFAULT_CSP: &str = "default-src 'self'; script-src 'self' 'unsafe-inline'; \
+    style-src 'self' 'unsafe-inline'; frame-ancestors 'none'; base-uri 'none'; form-action 'self'";
+
+/// Add the headers given with --header to every response, unless they are already set
+fn add_custom_headers<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    // Read the headers now, as they may be reloaded while the request is handled
+    let headers = conf.header.get();
+    let security_headers = conf.security_headers;
+    let csp = conf.csp.clone();
+    let fut = srv.call(req);
+
+    async move {
+        let mut res = fut.await?;
+        for header in headers.iter() {
+            for (header_name, header_value) in header {
+                if !res.headers().contains_key(header_name) {
+                    res.headers_mut()
+                        .insert(header_name.clone(), header_value.clone());
+                }
+            }
+        }
+
+        let csp = match csp {
+            Some(csp) => Some(csp),
+            None if security_headers => Some(HeaderValue::from_static(DEFAULT_CSP)),
+            None => None,
+        };
+        if let Some(csp) = csp {
+            if !res.headers().contains_key(header::CONTENT_SECURITY_POLICY) {
+                res.headers_mut()
+                    .insert(header::CONTENT_SECURITY_POLICY, csp);
+            }
+        }
+        if security_headers {
+            for (header_name, header_value) in SECURITY_HEADERS.iter() {
+                if !res.headers().contains_key(*header_name) {
+                    res.headers_mut().insert(
+                        header::HeaderName::from_static(header_name),
+                        HeaderValue::from_static(header_value),
+                    );
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Configures the route of the Prometheus metrics
+fn configure_metrics(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
+    if conf.metrics_enabled {
+        app.route(&conf.metrics_route, web::get().to(metrics::metrics));
+    }
+}
+
+/// Configures the routes of the admin status page
+fn configure_admin(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
+    if conf.admin_enabled {
+        app.route(admin::ADMIN_ROUTE, web::get().to(admin::admin_page))
+            .route(
+                admin::ADMIN_STATUS_ROUTE,
+                web::get().to(admin::admin_status),
+            )
+            .route(
+                admin::ADMIN_READ_ONLY_ROUTE,
+                web::post().to(admin::set_read_only),
+            );
+    }
+}
+
+/// Configures the routes of the login page
+fn configure_login(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
+    if conf.login_form && !conf.auth.get().is_empty() {
+        app.service(
+            web::resource(session::LOGIN_ROUTE)
+                .route(web::get().to(session::login_page))
+                .route(web::post().to(session::login)),
+        )
+        .route(session::LOGOUT_ROUTE, web::get().to(session::logout));
+    }
+}
+
+/// Configures the route generating share links
+fn configure_share_links(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
+    if conf.share_links {
+        app.route(share::SHARE_ROUTE, web::get().to(share::create_share_link));
+    }
+}
+
+/// Configures the routes modifying existing entries
+fn configure_file_ops(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
+    if conf.rename_enabled {
+        app.route(file_op::RENAME_ROUTE, web::post().to(file_op::rename));
+    }
+    if conf.edit_enabled {
+        // Form fields are percent-encoded, which can triple the size of the content
+        let form_limit = conf.max_edit_size.saturating_mul(3).saturating_add(4096) as usize;
+        app.service(
+            web::resource(file_op::EDIT_ROUTE)
+                .app_data(web::FormConfig::default().limit(form_limit))
+                .route(web::get().to(file_op::edit_page))
+                .route(web::post().to(file_op::save_edit)),
+        );
+    }
+    if conf.extract_enabled {
+        app.route(file_op::EXTRACT_ROUTE, web::post().to(file_op::extract));
+    }
+}
+
+/// Configures the directories served for their own host name, before the mounts and the main
+/// directory which would take precedence
+fn configure_vhosts(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
+    for vhost in &conf.vhosts {
+        let vhost_conf = vhost.config(conf);
+        app.service(
+            web::scope("")
+                .guard(guard::Host(&vhost.host))
+                .app_data(vhost_conf.clone())
+                .configure(|c| configure_app(c, &vhost_conf)),
+        );
+    }
+}
+
+/// Configures the directories of the users, before the main directory whose routes would take
+/// precedence
+fn configure_user_dirs(app: &mut web::ServiceConfig, user_dirs: &user_dirs::UserDirs) {
+    for (name, user_conf) in user_dirs.configs() {
+        let name = name.clone();
+        app.service(
+            web::scope("")
+                .guard(guard::fn_guard(move |head| user_dirs::is_user(head, &name)))
+                .app_data(user_conf.clone())
+                .configure(|c| configure_app(c, user_conf)),
+        );
+    }
+}
+
+/// Configures the directories mounted under their own route, before the main directory whose
+/// routes would take precedence
+fn configure_mounts(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
+    for mount in &conf.mounts {
+        let mount_conf = mount.config(conf);
+        app.service(
+            web::scope(mount_conf.mount_route.as_deref().unwrap_or_default())
+                .app_data(mount_conf.clone())
+                .configure(|c| configure_app(c, &mount_conf)),
+        );
+    }
+}
+
+/// Configures the Actix application
+fn configure_app(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
+    let random_route = conf.random_route.clone().unwrap_or_default();
+    let uses_random_route = conf.random_route.clone().is_some();
+    let full_route = format!("/{}", random_route);
+
+    let upload_route = if let Some(random_route) = conf.random_route.clone() {
+        format!("/{}/upload", random_route)
+    } else {
+        "/upload".to_string()
+    };
+    let resumable_upload_route = format!("{}/resumable", upload_route);
+    let new_file_route = format!("{}/new-file", upload_route);
+    let raw_upload_route = format!("{}/{{path:.*}}", full_route.trim_end_matches('/'));
+
+    // Mounts are registered in a scope at their route, which their routes are relative to
+    let scope = conf.mount_route.as_deref().unwrap_or_default();
+    let scoped = |route: &str| route[scope.len()..].to_string();
+
+    // Lists the directories served by `Files`, and the ones whose names aren't valid UTF-8
+    let render_listing = {
+        let no_symlinks = conf.no_symlinks;
+        let show_hidden = conf.show_hidden;
+        let random_route = conf.random_route.clone();
+        let favicon_route = conf.favicon_route.clone();
+        let css_route = conf.css_route.clone();
+        let default_color_scheme = conf.default_color_scheme.clone();
+        let default_color_scheme_dark = conf.default_color_scheme_dark.clone();
+        let show_qrcode = conf.show_qrcode;
+        let file_upload = conf.file_upload;
+        let tar_enabled = conf.tar_enabled;
+        let tar_gz_enabled = conf.tar_gz_enabled;
+        let zip_enabled = conf.zip_enabled;
+        let dirs_first = conf.dirs_first;
+        let hide_version_footer = conf.hide_version_footer;
+        let title = conf.title.clone();
+        let upload_route = upload_route.clone();
+
+        move |dir: &actix_files::Directory, req: &HttpRequest| {
+            listing::directory_listing(
+                dir,
+                req,
+                no_symlinks,
+                show_hidden,
+                file_upload,
+                random_route.clone(),
+                favicon_route.clone(),
+                css_route.clone(),
+                &default_color_scheme,
+                &default_color_scheme_dark,
+                show_qrcode,
+                upload_route.clone(),
+                tar_enabled,
+                tar_gz_enabled,
+                zip_enabled,
+                dirs_first,
+                hide_version_footer,
+                title.clone(),
+            )
+        }
+    };
+
+    let serve_path = {
+        let path = &conf.path;
+        let show_hidden = conf.show_hidden;
+
+        if path.is_file() || conf.storage.is_some() {
+            None
+        } else {
+            // build `Files` service using configuraion parameters
+            let files = actix_files::Files::new(&scoped(&full_route), path);
+            let files = match &conf.index {
+                Some(index_file) => files.index_file(index_file.to_string_lossy()),
+                None => files,
+            };
+            let files = match show_hidden {
+                true => files.use_hidden_files(),
+                false => files,
+            };
+            let files = files
+                .show_files_listing()
+                .files_listing_renderer(render_listing.clone())
+                // The directories are redirected to their trailing slash by `canonical_url`
+                .prefer_utf8(true)
+                .default_handler(web::to(error_404));
+            Some(files)
+        }
+    };
+
+    let favicon_route = conf.favicon_route.clone();
+    let css_route = conf.css_route.clone();
+
+    let default_color_scheme = conf.default_color_scheme.clone();
+    let default_color_scheme_dark = conf.default_color_scheme_dark.clone();
+    let hide_version_footer = conf.hide_version_footer;
+
+    if let Some(serve_path) = serve_path {
+        if conf.live_updates {
+            let events_route = format!(
+                "{}{}",
+                full_route.trim_end_matches('/'),
+                live_updates::EVENTS_ROUTE
+            );
+            app.route(&scoped(&events_route), web::get().to(live_updates::events));
+        }
+        if conf.feed_enabled {
+            let feed_route = format!("{}{}", full_route.trim_end_matches('/'), feed::FEED_ROUTE);
+            app.route(&scoped(&feed_route), web::get().to(feed::feed));
+        }
+        if conf.opds_enabled {
+            let opds_route = format!("{}{}", full_route.trim_end_matches('/'), opds::OPDS_ROUTE);
+            app.route(&scoped(&opds_route), web::get().to(opds::catalog));
+        }
+        if conf.torrent_enabled {
+            // Handle the requests of torrents, before `Files` serves the files themselves
+            let torrent_conf = conf.clone();
+            app.service(
+                web::resource(scoped(&raw_upload_route))
+                    .guard(guard::fn_guard(move |head| {
+                        torrent::is_torrent_request(&torrent_conf, head)
+                    }))
+                    .route(web::get().to(torrent::torrent_file)),
+            );
+        }
+        if conf.tail_enabled {
+            // Handle the requests of the last lines of files, before `Files` serves the files
+            let tail_conf = conf.clone();
+            app.service(
+                web::resource(scoped(&raw_upload_route))
+                    .guard(guard::fn_guard(move |head| {
+                        tail::is_tail_request(&tail_conf, head)
+                    }))
+                    .route(web::get().to(tail::tail_file)),
+            );
+        }
+        if conf.hls_enabled {
+            // Handle the requests of HLS streams, before `Files` serves the videos themselves
+            let hls_conf = conf.clone();
+            app.service(
+                web::resource(scoped(&raw_upload_route))
+                    .guard(guard::fn_guard(move |head| {
+                        hls::is_hls_request(&hls_conf, head)
+                    }))
+                    .route(web::get().to(hls::stream)),
+            );
+        }
+        if conf.resize_enabled {
+            // Handle the requests of downscaled images, before `Files` serves the images themselves
+            let resize_conf = conf.clone();
+            app.service(
+                web::resource(scoped(&raw_upload_route))
+                    .guard(guard::fn_guard(move |head| {
+                        resize::is_resize_request(&resize_conf, head)
+                    }))
+                    .route(web::get().to(resize::resized_image)),
+            );
+        }
+        if conf.content_search_enabled {
+            // Handle the searches, before `Files` lists the directories themselves
+            let search_conf = conf.clone();
+            app.service(
+                web::resource(scoped(&raw_upload_route))
+                    .guard(guard::fn_guard(move |head| {
+                        search::is_search_request(&search_conf, head)
+                    }))
+                    .route(web::get().to(search::search)),
+            );
+        }
+        if conf.precompressed {
+            // Handle the requests of files which have a compressed copy, before `Files` does
+            let precompressed_conf = conf.clone();
+            app.service(
+                web::resource(scoped(&raw_upload_route))
+                    .guard(guard::fn_guard(move |head| {
+                        precompressed::find_precompressed(&precompressed_conf, head).is_some()
+                    }))
+                    .route(web::get().to(precompressed::precompressed_file))
+                    .route(web::head().to(precompressed::precompressed_file)),
+            );
+        }
+        if conf.memory_cache.is_some() {
+            // Handle the requests of small files from memory, before `Files` reads them
+            let cache_conf = conf.clone();
+            app.service(
+                web::resource(scoped(&raw_upload_route))
+                    .guard(guard::fn_guard(move |head| {
+                        memory_cache::find_cached(&cache_conf, head)
+                    }))
+                    .route(web::get().to(memory_cache::cached_file))
+                    .route(web::head().to(memory_cache::cached_file)),
+            );
+        }
+        if conf.mirror_upstream.is_some() {
+            // Fetch the files missing from the served directory, which `Files` can't find
+            let mirror_conf = conf.clone();
+            app.service(
+                web::resource(scoped(&raw_upload_route))
+                    .guard(guard::fn_guard(move |head| {
+                        mirror::is_mirror_request(&mirror_conf, head)
+                    }))
+                    .route(web::get().to(mirror::mirror_file))
+                    .route(web::head().to(mirror::mirror_file)),
+            );
+        }
+        {
+            // Handle the requests of the entries whose names aren't valid UTF-8, which `Files`
+            // can't decode
+            let raw_names_conf = conf.clone();
+            let (get_listing, head_listing) = (render_listing.clone(), render_listing);
+            app.service(
+                web::resource(scoped(&raw_upload_route))
+                    .guard(guard::fn_guard(move |head| {
+                        raw_names::is_raw_name_request(&raw_names_conf, head)
+                    }))
+                    .route(web::get().to(move |req: HttpRequest| {
+                        raw_names::serve_raw_name(req, get_listing.clone())
+                    }))
+                    .route(web::head().to(move |req: HttpRequest| {
+                        raw_names::serve_raw_name(req, head_listing.clone())
+                    })),
+            );
+        }
+        if conf.file_upload {
+            // Allow file upload
+            app.service(web::resource(scoped(&upload_route)).route(web::post().to(
+                move |req, payload| {
+                    file_upload::upload_file(
+                        req,
+                        payload,
+                        uses_random_route,
+                        favicon_route.clone(),
+                        css_route.clone(),
+                        default_color_scheme.clone(),
+                        default_color_scheme_dark.clone(),
+                        hide_version_footer,
+                    )
+                },
+            )))
+            .service(
+                web::resource(scoped(&resumable_upload_route))
+                    .route(web::get().to(file_upload::resumable_upload_offset))
+                    .route(web::patch().to(file_upload::resumable_upload_chunk)),
+            )
+            .route(
+                &scoped(&new_file_route),
+                web::post().to(file_upload::create_file),
+            )
+            // Allow raw uploads to the URL of the file
+            .service(
+                web::resource(scoped(&raw_upload_route))
+                    .guard(guard::Put())
+                    .to(file_upload::upload_raw_file),
+            )
+            // Handle directories
+            .service(serve_path);
+        } else {
+            // Handle directories
+            app.service(serve_path);
+        }
+    } else if conf.storage.is_some() {
+        // Handle the contents of the storage, at the same URLs as raw uploads of files
+        app.service(
+            web::resource(scoped(&raw_upload_route))
+                .route(web::get().to(storage::storage_handler))
+                .route(web::head().to(storage::storage_handler)),
+        );
+    } else {
+        // Handle single files
+        app.service(web::resource(scoped(&full_route)).route(web::to(listing::file_handler)));
+    }
+}
+
+async fn error_404(req: HttpRequest) -> HttpResponse {
+    let err_404 = ContextualError::RouteNotFoundError(req.path().to_string());
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let uses_random_route = conf.random_route.is_some();
+    let favicon_route = conf.favicon_route.clone();
+    let css_route = conf.css_route.clone();
+    let query_params = listing::extract_query_parameters(&req);
+
+    errors::log_error_chain(err_404.to_string());
+
+    actix_web::HttpResponse::NotFound().body(
+        renderer::render_error(
+            &err_404.to_string(),
+            StatusCode::NOT_FOUND,
+            "/",
+            query_params.sort,
+            query_params.order,
+            false,
+            !uses_random_route,
+            &favicon_route,
+            &css_route,
+            &conf.default_color_scheme,
+            &conf.default_color_scheme_dark,
+            conf.hide_version_footer,
+        )
+        .into_string(),
+    )
+}
+
+async fn favicon() -> impl Responder {
+    let logo = include_str!("../data/logo.svg");
+    HttpResponse::Ok()
+        .insert_header(ContentType(mime::IMAGE_SVG))
+        .message_body(logo.into())
+}
+
+async fn css() -> impl Responder {
+    let css = include_str!(concat!(env!("OUT_DIR"), "/style.css"));
+    HttpResponse::Ok()
+        .insert_header(ContentType(mime::TEXT_CSS))
+        .message_body(css.into())
+}
@@ -1,25 +1,44 @@
 use actix_web::body::Body;
 use actix_web::dev::ServiceResponse;
-use actix_web::http::StatusCode;
-use actix_web::web::Query;
+use actix_web::http::{header, StatusCode};
+use actix_web::web::{self, Query};
 use actix_web::{HttpRequest, HttpResponse};
 use bytesize::ByteSize;
 use percent_encoding::{percent_decode_str, utf8_percent_encode};
 use qrcodegen::{QrCode, QrCodeEcc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
 use std::io;
 use std::path::{Component, Path, PathBuf};
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use strum_macros::{Display, EnumString};
 
 use crate::archive::ArchiveMethod;
+use crate::auth::{self, CurrentUser};
+use crate::canonical_path;
+use crate::dir_config::{self, DIR_CONFIG_FILE};
+use crate::disk_usage::DiskUsageCache;
+use crate::download_stats::DownloadStats;
 use crate::errors::{self, ContextualError};
+use crate::file_upload::UserUploadLimits;
+use crate::fragments::Fragments;
+use crate::listing_cache::{self, ListingCache};
+use crate::live_updates;
+use crate::manifest::{ManifestAlgorithm, ManifestCache};
+use crate::metrics::Metrics;
+use crate::photo::{self, PhotoMetadata, PhotoMetadataCache};
+use crate::playlist::{self, PlaylistFormat};
+use crate::proxy;
+use crate::raw_names;
 use crate::renderer;
+use crate::share::SharedAccess;
+use crate::upload_ttl;
 use percent_encode_sets::PATH_SEGMENT;
 
 /// "percent-encode sets" as defined by WHATWG specs:
 /// https://url.spec.whatwg.org/#percent-encoded-bytes
-mod percent_encode_sets {
+pub mod percent_encode_sets {
     use percent_encoding::{AsciiSet, CONTROLS};
     const BASE: &AsciiSet = &CONTROLS.add(b'%');
     pub const QUERY: &AsciiSet = &BASE.add(b' ').add(b'"').add(b'#').add(b'<').add(b'>');
@@ -34,7 +53,21 @@ pub struct QueryParameters {
     pub sort: Option<SortingMethod>,
     pub order: Option<SortingOrder>,
     qrcode: Option<String>,
-    download: Option<ArchiveMethod>,
+    download: Option<DirectoryDownload>,
+    manifest: Option<ManifestAlgorithm>,
+    view: Option<ListingView>,
+    pub format: Option<ListingFormat>,
+}
+
+/// Downloads of a directory, with `?download=`
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub enum DirectoryDownload {
+    /// Archive of the directory
+    Archive(ArchiveMethod),
+
+    /// Playlist of the audio files of the directory
+    Playlist(PlaylistFormat),
 }
 
 /// Available sorting methods
@@ -42,14 +75,19 @@ pub struct QueryParameters {
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
 pub enum SortingMethod {
-    /// Sort by name
+    /// Sort by name, ignoring the case (natural sort: numbers are compared by value, so that
+    /// `file2` comes before `file10`)
     Name,
 
     /// Sort by size
     Size,
 
-    /// Sort by last modification date (natural sort: follows alphanumerical order)
+    /// Sort by last modification date
     Date,
+
+    /// Sort by the date photos were taken, with --photo-metadata. Other entries are sorted by
+    /// last modification date.
+    Taken,
 }
 
 /// Available sorting orders
@@ -66,6 +104,109 @@ pub enum SortingOrder {
     Descending,
 }
 
+/// Other views of a directory, with `?view=`
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingView {
+    /// Files of the directory and its subdirectories which have the same content
+    Duplicates,
+
+    /// Size of the entries of the directory, shown as a treemap
+    Usage,
+}
+
+/// Available formats of the listings
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingFormat {
+    /// HTML page
+    Html,
+
+    /// One entry per line, with a trailing slash for directories
+    Txt,
+
+    /// Array of the entries with their metadata
+    Json,
+}
+
+impl ListingFormat {
+    /// Format of the listing requested by `req`, with the `format` query parameter, or else with
+    /// its Accept header
+    fn negotiate(req: &HttpRequest, format: Option<ListingFormat>) -> Self {
+        if let Some(format) = format {
+            return format;
+        }
+        let accept = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+        // Browsers accept anything, but ask for HTML first
+        let media_types = accept
+            .split(',')
+            .map(|media_type| media_type.split(';').next().unwrap_or_default().trim())
+            .collect::<Vec<_>>();
+        if media_types.contains(&"text/html") {
+            ListingFormat::Html
+        } else if media_types.contains(&"application/json") {
+            ListingFormat::Json
+        } else if media_types.contains(&"text/plain") {
+            ListingFormat::Txt
+        } else {
+            ListingFormat::Html
+        }
+    }
+}
+
+/// Entry of a JSON listing
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    name: &'a str,
+
+    #[serde(rename = "type")]
+    entry_type: &'static str,
+    symlink: bool,
+
+    /// URL of the entry, relative to the server
+    url: &'a str,
+
+    /// Size in bytes, for files
+    size: Option<u64>,
+
+    /// Last modification date, in RFC 3339 format
+    modified: Option<String>,
+
+    /// Number of downloads, with `--show-download-counts`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    downloads: Option<u64>,
+
+    /// Date the photo was taken, with `--photo-metadata`, without time zone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    taken: Option<String>,
+}
+
+impl<'a> From<&'a Entry> for JsonEntry<'a> {
+    fn from(entry: &'a Entry) -> Self {
+        JsonEntry {
+            name: &entry.name,
+            entry_type: if entry.is_dir() { "directory" } else { "file" },
+            symlink: entry.is_symlink,
+            url: &entry.link,
+            size: entry.size.map(|size| size.as_u64()),
+            modified: entry.last_modification_date.map(|date| {
+                chrono::DateTime::<chrono::Utc>::from(date)
+                    .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+            }),
+            downloads: entry.downloads,
+            taken: entry
+                .photo
+                .as_ref()
+                .and_then(|photo| photo.taken)
+                .map(|taken| taken.format("%Y-%m-%dT%H:%M:%S").to_string()),
+        }
+    }
+}
+
 #[derive(PartialEq)]
 /// Possible entry types
 pub enum EntryType {
@@ -95,10 +236,19 @@ pub struct Entry {
 
     /// Last modification date
     pub last_modification_date: Option<SystemTime>,
+
+    /// Number of downloads, for files when the counts are shown
+    pub downloads: Option<u64>,
+
+    /// EXIF metadata, for photos when it is read
+    pub photo: Option<PhotoMetadata>,
+
+    /// Whether the name isn't valid UTF-8, and is shown with replacement characters
+    pub invalid_name: bool,
 }
 
 impl Entry {
-    fn new(
+    pub fn new(
         name: String,
         entry_type: EntryType,
         is_symlink: bool,
@@ -113,6 +263,9 @@ impl Entry {
             link,
             size,
             last_modification_date,
+            downloads: None,
+            photo: None,
+            invalid_name: false,
         }
     }
 
@@ -125,6 +278,17 @@ impl Entry {
     pub fn is_file(&self) -> bool {
         self.entry_type == EntryType::File
     }
+
+    /// Date the photo was taken, or the last modification date in local time for other entries
+    pub fn date_taken(&self) -> Option<chrono::NaiveDateTime> {
+        self.photo
+            .as_ref()
+            .and_then(|photo| photo.taken)
+            .or_else(|| {
+                self.last_modification_date
+                    .map(|date| chrono::DateTime::<chrono::Local>::from(date).naive_local())
+            })
+    }
 }
 
 /// One entry in the path to the listed directory
@@ -172,12 +336,405 @@ pub fn directory_listing(
 ) -> io::Result<ServiceResponse> {
     use actix_web::dev::BodyEncoding;
     let serve_path = req.path();
+    let base = Path::new(serve_path);
+    let query_params = extract_query_parameters(req);
+    let apply_dir_config = req
+        .app_data::<crate::MiniserveConfig>()
+        .is_some_and(|conf| conf.dir_config);
+    // Ignored directories are only listed when they are served anyway, then entirely
+    let ignored_entries = req
+        .app_data::<crate::MiniserveConfig>()
+        .and_then(|conf| conf.ignore.as_ref()?.dir(&conf.path, &dir.path));
+
+    let forbidden = |error_description: &str| {
+        ServiceResponse::new(
+            req.clone(),
+            HttpResponse::Forbidden()
+                .content_type("text/html; charset=utf-8")
+                .body(
+                    renderer::render_error(
+                        error_description,
+                        StatusCode::FORBIDDEN,
+                        "/",
+                        None,
+                        None,
+                        false,
+                        false,
+                        &favicon_route,
+                        &css_route,
+                        default_color_scheme,
+                        default_color_scheme_dark,
+                        hide_version_footer,
+                    )
+                    .into_string(),
+                ),
+        )
+    };
+
+    if let Some(DirectoryDownload::Archive(archive_method)) = query_params.download {
+        if !archive_method.is_enabled(tar_enabled, tar_gz_enabled, zip_enabled) {
+            return Ok(forbidden("Archive creation is disabled."));
+        }
+        if apply_dir_config && dir_config::has_restricted_subdir(&dir.path) {
+            return Ok(forbidden(
+                "This directory contains restricted directories and can't be downloaded.",
+            ));
+        }
+        log::info!(
+            "Creating an archive ({extension}) of {path}...",
+            extension = archive_method.extension(),
+            path = &dir.path.display().to_string()
+        );
+
+        let file_name = format!(
+            "{}.{}",
+            dir.path.file_name().unwrap().to_string_lossy(),
+            archive_method.extension()
+        );
+
+        // We will create the archive in a separate thread, and stream the content using a pipe.
+        // The pipe is made of a futures channel, and an adapter to implement the `Write` trait.
+        // Include 10 messages of buffer for erratic connection speeds.
+        let (tx, rx) = futures::channel::mpsc::channel::<io::Result<actix_web::web::Bytes>>(10);
+        let pipe = crate::pipe::Pipe::new(tx);
+
+        // Start the actual archive creation in a separate thread.
+        let dir = dir.path.to_path_buf();
+        let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+        let span = tracing::info_span!(
+            "archive",
+            method = %archive_method.extension(),
+            path = %dir.display()
+        );
+        std::thread::spawn(move || {
+            let _entered = span.enter();
+            let start = Instant::now();
+            if let Err(err) =
+                archive_method.create_archive(dir, skip_symlinks, ignored_entries, pipe)
+            {
+                log::error!("Error during archive creation: {:?}", err);
+            } else if let Some(metrics) = metrics {
+                metrics.record_archive(archive_method, start.elapsed());
+            }
+        });
+
+        return Ok(ServiceResponse::new(
+            req.clone(),
+            HttpResponse::Ok()
+                .content_type(archive_method.content_type())
+                .encoding(archive_method.content_encoding())
+                .append_header(("Content-Transfer-Encoding", "binary"))
+                .append_header((
+                    "Content-Disposition",
+                    format!("attachment; filename={:?}", file_name),
+                ))
+                .body(actix_web::body::BodyStream::new(rx)),
+        ));
+    }
+
+    if let Some(algorithm) = query_params.manifest {
+        let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+        if !conf.manifest_enabled {
+            return Ok(forbidden("Manifest creation is disabled."));
+        }
+        log::info!(
+            "Creating a manifest ({algorithm}) of {path}...",
+            algorithm = algorithm,
+            path = &dir.path.display().to_string()
+        );
+
+        // The files are hashed on the blocking thread pool, and the manifest sent once complete
+        let cache = req.app_data::<web::Data<ManifestCache>>().unwrap().clone();
+        let dir = dir.path.to_path_buf();
+        let manifest = async move {
+            web::block(move || {
+                cache.manifest(
+                    &dir,
+                    skip_symlinks,
+                    show_hidden,
+                    apply_dir_config,
+                    ignored_entries,
+                )
+            })
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e.to_string())))
+            .map(web::Bytes::from)
+            .map_err(|e| {
+                log::error!("Error during manifest creation: {}", e);
+                e
+            })
+        };
+
+        return Ok(ServiceResponse::new(
+            req.clone(),
+            HttpResponse::Ok()
+                .content_type("text/plain; charset=utf-8")
+                .append_header((
+                    "Content-Disposition",
+                    format!("inline; filename={:?}", algorithm.file_name()),
+                ))
+                .streaming(futures::stream::once(Box::pin(manifest))),
+        ));
+    }
+
+    if let Some(ListingView::Duplicates) = query_params.view {
+        let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+        if !conf.duplicates_enabled {
+            return Ok(forbidden("Duplicate file reports are disabled."));
+        }
+        log::info!(
+            "Looking for duplicate files in {path}...",
+            path = &dir.path.display().to_string()
+        );
+
+        // The files are hashed on the blocking thread pool, like for the manifests
+        let cache = req.app_data::<web::Data<ManifestCache>>().unwrap().clone();
+        let dir = dir.path.to_path_buf();
+        let dir_link = format!("{}/", serve_path.trim_end_matches('/'));
+        let (default_color_scheme, default_color_scheme_dark) = (
+            default_color_scheme.to_string(),
+            default_color_scheme_dark.to_string(),
+        );
+        let report = async move {
+            web::block(move || {
+                cache.duplicates(
+                    &dir,
+                    skip_symlinks,
+                    show_hidden,
+                    apply_dir_config,
+                    ignored_entries,
+                )
+            })
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e.to_string())))
+            .map(|duplicates| {
+                let page = renderer::duplicates_page(
+                    &duplicates,
+                    &dir_link,
+                    &favicon_route,
+                    &css_route,
+                    &default_color_scheme,
+                    &default_color_scheme_dark,
+                    hide_version_footer,
+                );
+                web::Bytes::from(page.into_string())
+            })
+            .map_err(|e| {
+                log::error!("Error while looking for duplicate files: {}", e);
+                e
+            })
+        };
+
+        return Ok(ServiceResponse::new(
+            req.clone(),
+            HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .streaming(futures::stream::once(Box::pin(report))),
+        ));
+    }
+
+    if let Some(ListingView::Usage) = query_params.view {
+        let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+        if !conf.disk_usage_enabled {
+            return Ok(forbidden("Disk usage reports are disabled."));
+        }
+        log::info!(
+            "Computing the disk usage of {path}...",
+            path = &dir.path.display().to_string()
+        );
+
+        // The directories are walked on the blocking thread pool, like for the duplicates
+        let cache = req.app_data::<web::Data<DiskUsageCache>>().unwrap().clone();
+        let dir = dir.path.to_path_buf();
+        let dir_link = format!("{}/", serve_path.trim_end_matches('/'));
+        let (default_color_scheme, default_color_scheme_dark) = (
+            default_color_scheme.to_string(),
+            default_color_scheme_dark.to_string(),
+        );
+        let report = async move {
+            web::block(move || {
+                cache.usage(
+                    &dir,
+                    skip_symlinks,
+                    show_hidden,
+                    apply_dir_config,
+                    ignored_entries,
+                )
+            })
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e.to_string())))
+            .map(|usage| {
+                let page = renderer::usage_page(
+                    &usage,
+                    &dir_link,
+                    &favicon_route,
+                    &css_route,
+                    &default_color_scheme,
+                    &default_color_scheme_dark,
+                    hide_version_footer,
+                );
+                web::Bytes::from(page.into_string())
+            })
+            .map_err(|e| {
+                log::error!("Error while computing the disk usage: {}", e);
+                e
+            })
+        };
+
+        return Ok(ServiceResponse::new(
+            req.clone(),
+            HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .streaming(futures::stream::once(Box::pin(report))),
+        ));
+    }
+
+    let download_stats = req
+        .app_data::<web::Data<DownloadStats>>()
+        .filter(|stats| stats.is_enabled())
+        .filter(|_| {
+            req.app_data::<crate::MiniserveConfig>()
+                .is_some_and(|conf| conf.show_download_counts)
+        });
+    let photo_metadata = req.app_data::<web::Data<PhotoMetadataCache>>().filter(|_| {
+        req.app_data::<crate::MiniserveConfig>()
+            .is_some_and(|conf| conf.photo_metadata)
+    });
+    let upload_only = req
+        .app_data::<crate::MiniserveConfig>()
+        .is_some_and(|conf| conf.upload_only);
+    let expiring_uploads = req
+        .app_data::<crate::MiniserveConfig>()
+        .is_some_and(|conf| conf.upload_ttl.is_some());
+    let listed_entries = match req.app_data::<web::Data<ListingCache>>() {
+        // Nothing is listed in drop box mode, only the upload form is shown
+        _ if upload_only => std::sync::Arc::new(vec![]),
+        Some(cache) => cache.read_dir(&dir.path)?,
+        None => listing_cache::read_entries(&dir.path).map(std::sync::Arc::new)?,
+    };
+    let entries = listed_entries
+        .iter()
+        .filter(|entry| show_hidden || !entry.name.starts_with('.'))
+        .filter(|entry| !(skip_symlinks && entry.is_symlink))
+        .filter(|entry| {
+            ignored_entries
+                .as_ref()
+                .is_none_or(|ignored| !ignored.is_ignored(&entry.name, entry.is_dir))
+        })
+        .filter(|entry| !(expiring_uploads && entry.name == upload_ttl::EXPIRY_FILE))
+        .filter(|entry| {
+            !apply_dir_config
+                || (entry.name != DIR_CONFIG_FILE
+                    && !(entry.is_dir && dir_config::is_hidden(&dir.path.join(&entry.name))))
+        })
+        .map(|entry| {
+            // show file url as relative to static path
+            let encoded_name = match &entry.raw_name {
+                Some(raw_name) => raw_names::encode_name(raw_name),
+                None => utf8_percent_encode(&entry.name, PATH_SEGMENT).to_string(),
+            };
+            let file_url = base.join(&encoded_name).to_string_lossy().to_string();
+            let (entry_type, size) = if entry.is_dir {
+                (EntryType::Directory, None)
+            } else {
+                (EntryType::File, Some(ByteSize::b(entry.size)))
+            };
+            let mut listing_entry = Entry::new(
+                entry.name.clone(),
+                entry_type,
+                entry.is_symlink,
+                file_url,
+                size,
+                entry.modified,
+            );
+            listing_entry.invalid_name = entry.raw_name.is_some();
+            if let (Some(stats), false) = (download_stats, entry.is_dir) {
+                let file = dir.path.join(&entry.name);
+                let file = canonical_path::canonicalize(&file).unwrap_or(file);
+                listing_entry.downloads = Some(stats.get(&file).downloads);
+            }
+            if let (Some(cache), false) = (photo_metadata, entry.is_dir) {
+                if photo::is_photo(&entry.name) {
+                    listing_entry.photo = cache.get(&dir.path.join(&entry.name), entry.modified);
+                }
+            }
+            listing_entry
+        })
+        .collect();
+
+    let fragments = req
+        .app_data::<crate::MiniserveConfig>()
+        .map(|conf| Fragments::read(conf, &dir.path))
+        .unwrap_or_default();
+
+    Ok(ServiceResponse::new(
+        req.clone(),
+        render_listing(
+            req,
+            entries,
+            dir.path
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+            file_upload,
+            random_route,
+            favicon_route,
+            css_route,
+            default_color_scheme,
+            default_color_scheme_dark,
+            show_qrcode,
+            upload_route,
+            tar_enabled,
+            tar_gz_enabled,
+            zip_enabled,
+            dirs_first,
+            hide_version_footer,
+            title,
+            fragments,
+        ),
+    ))
+}
+
+/// Render the listing of `entries`, the content of the directory at the path of `req`, which was
+/// last modified at `modified`, between its `fragments`
+#[allow(clippy::too_many_arguments)]
+pub fn render_listing(
+    req: &HttpRequest,
+    mut entries: Vec<Entry>,
+    modified: Option<SystemTime>,
+    file_upload: bool,
+    random_route: Option<String>,
+    favicon_route: String,
+    css_route: String,
+    default_color_scheme: &str,
+    default_color_scheme_dark: &str,
+    show_qrcode: bool,
+    upload_route: String,
+    tar_enabled: bool,
+    tar_gz_enabled: bool,
+    zip_enabled: bool,
+    dirs_first: bool,
+    hide_version_footer: bool,
+    title: Option<String>,
+    fragments: Fragments,
+) -> HttpResponse {
+    let serve_path = req.path();
+
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let show_logout = conf.login_form && req.extensions().get::<CurrentUser>().is_some();
+
+    // Only offer the upload form to users who are allowed to upload
+    let file_upload = file_upload && auth::has_write_permission(req, conf);
+    let share_cookie = req
+        .extensions()
+        .get::<SharedAccess>()
+        .and_then(|access| access.cookie.clone());
 
     let base = Path::new(serve_path);
     let random_route_abs = format!("/{}", random_route.clone().unwrap_or_default());
     let is_root = base.parent().is_none() || Path::new(&req.path()) == Path::new(&random_route_abs);
 
-    let encoded_dir = match base.strip_prefix(random_route_abs) {
+    let encoded_dir = match base.strip_prefix(&random_route_abs) {
         Ok(c_d) => Path::new("/").join(c_d),
         Err(_) => base.to_path_buf(),
     }
@@ -185,15 +742,13 @@ pub fn directory_listing(
     .to_string();
 
     let breadcrumbs = {
-        let title = title.unwrap_or_else(|| req.connection_info().host().into());
-
-        let decoded = percent_decode_str(&encoded_dir).decode_utf8_lossy();
+        let title = title.unwrap_or_else(|| proxy::host(req));
 
         let mut res: Vec<Breadcrumb> = Vec::new();
         let mut link_accumulator =
             format!("/{}", random_route.map(|r| r + "/").unwrap_or_default());
 
-        let mut components = Path::new(&*decoded).components().peekable();
+        let mut components = Path::new(&encoded_dir).components().peekable();
 
         while let Some(c) = components.next() {
             let name;
@@ -203,9 +758,15 @@ pub fn directory_listing(
                     name = title.clone();
                 }
                 Component::Normal(s) => {
-                    name = s.to_string_lossy().to_string();
-                    link_accumulator
-                        .push_str(&(utf8_percent_encode(&name, PATH_SEGMENT).to_string() + "/"));
+                    let segment = s.to_string_lossy();
+                    let decoded = percent_decode_str(&segment);
+                    name = decoded.clone().decode_utf8_lossy().to_string();
+                    // The names which aren't valid UTF-8 keep the encoding of their raw bytes
+                    let encoded = match decoded.decode_utf8() {
+                        Ok(name) => utf8_percent_encode(&name, PATH_SEGMENT).to_string(),
+                        Err(_) => segment.to_string(),
+                    };
+                    link_accumulator.push_str(&(encoded + "/"));
                 }
                 _ => name = "".to_string(),
             };
@@ -235,61 +796,7 @@ pub fn directory_listing(
                 HttpResponse::UriTooLong().body(Body::Empty)
             }
         };
-        return Ok(ServiceResponse::new(req.clone(), res));
-    }
-
-    let mut entries: Vec<Entry> = Vec::new();
-
-    for entry in dir.path.read_dir()? {
-        if dir.is_visible(&entry) || show_hidden {
-            let entry = entry?;
-            // show file url as relative to static path
-            let file_name = entry.file_name().to_string_lossy().to_string();
-            let (is_symlink, metadata) = match entry.metadata() {
-                Ok(metadata) if metadata.file_type().is_symlink() => {
-                    // for symlinks, get the metadata of the original file
-                    (true, std::fs::metadata(entry.path()))
-                }
-                res => (false, res),
-            };
-            let file_url = base
-                .join(&utf8_percent_encode(&file_name, PATH_SEGMENT).to_string())
-                .to_string_lossy()
-                .to_string();
-
-            // if file is a directory, add '/' to the end of the name
-            if let Ok(metadata) = metadata {
-                if skip_symlinks && is_symlink {
-                    continue;
-                }
-                let last_modification_date = match metadata.modified() {
-                    Ok(date) => Some(date),
-                    Err(_) => None,
-                };
-
-                if metadata.is_dir() {
-                    entries.push(Entry::new(
-                        file_name,
-                        EntryType::Directory,
-                        is_symlink,
-                        file_url,
-                        None,
-                        last_modification_date,
-                    ));
-                } else if metadata.is_file() {
-                    entries.push(Entry::new(
-                        file_name,
-                        EntryType::File,
-                        is_symlink,
-                        file_url,
-                        Some(ByteSize::b(metadata.len())),
-                        last_modification_date,
-                    ));
-                }
-            } else {
-                continue;
-            }
-        }
+        return res;
     }
 
     match query_params.sort.unwrap_or(SortingMethod::Name) {
@@ -310,6 +817,7 @@ pub fn directory_listing(
                 .unwrap_or(SystemTime::UNIX_EPOCH)
                 .cmp(&e1.last_modification_date.unwrap_or(SystemTime::UNIX_EPOCH))
         }),
+        SortingMethod::Taken => entries.sort_by_key(|e| Reverse(e.date_taken())),
     };
 
     if let Some(SortingOrder::Descending) = query_params.order {
@@ -321,97 +829,244 @@ pub fn directory_listing(
         entries.sort_by_key(|e| !e.is_dir());
     }
 
-    if let Some(archive_method) = query_params.download {
-        if !archive_method.is_enabled(tar_enabled, tar_gz_enabled, zip_enabled) {
-            return Ok(ServiceResponse::new(
-                req.clone(),
-                HttpResponse::Forbidden()
-                    .content_type("text/html; charset=utf-8")
-                    .body(
-                        renderer::render_error(
-                            "Archive creation is disabled.",
-                            StatusCode::FORBIDDEN,
-                            "/",
-                            None,
-                            None,
-                            false,
-                            false,
-                            &favicon_route,
-                            &css_route,
-                            default_color_scheme,
-                            default_color_scheme_dark,
-                            hide_version_footer,
-                        )
-                        .into_string(),
-                    ),
-            ));
-        }
-        log::info!(
-            "Creating an archive ({extension}) of {path}...",
-            extension = archive_method.extension(),
-            path = &dir.path.display().to_string()
-        );
+    let events_route = if conf.live_updates && conf.storage.is_none() {
+        Some(format!(
+            "{}{}",
+            random_route_abs.trim_end_matches('/'),
+            live_updates::EVENTS_ROUTE
+        ))
+    } else {
+        None
+    };
+    let rename_enabled = conf.rename_enabled && auth::has_write_permission(req, conf);
+    let extract_enabled = conf.extract_enabled && auth::has_write_permission(req, conf);
+    let max_edit_size = if conf.edit_enabled && auth::has_write_permission(req, conf) {
+        Some(conf.max_edit_size)
+    } else {
+        None
+    };
+
+    let storage_usage = if file_upload {
+        UserUploadLimits::of(req).usage()
+    } else {
+        None
+    };
+
+    // Torrents are only generated for the files of the served directory
+    let torrent_enabled = conf.torrent_enabled && conf.storage.is_none();
+    let hls_enabled = conf.hls_enabled && conf.storage.is_none();
+    let resize_enabled = conf.resize_enabled && conf.storage.is_none();
+    let search_enabled = conf.content_search_enabled && conf.storage.is_none();
+    let duplicates_enabled = conf.duplicates_enabled && conf.storage.is_none();
+    let usage_enabled = conf.disk_usage_enabled && conf.storage.is_none();
+
+    let format = ListingFormat::negotiate(req, query_params.format);
+
+    // Anything changing the page changes its ETag: the entries, the query and the options
+    let options = format!(
+        "{}|{:?}|{}|{:?}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{:?}|{}|{}|{:?}|{:?}|{}|{:?}|{}|{}|{}|{}|{}|{}",
+        env!("CARGO_PKG_VERSION"),
+        format,
+        req.query_string(),
+        breadcrumbs
+            .iter()
+            .map(|breadcrumb| &breadcrumb.name)
+            .collect::<Vec<_>>(),
+        show_qrcode,
+        file_upload,
+        upload_route,
+        favicon_route,
+        css_route,
+        default_color_scheme,
+        default_color_scheme_dark,
+        tar_enabled,
+        tar_gz_enabled,
+        zip_enabled,
+        torrent_enabled,
+        hide_version_footer,
+        show_logout,
+        conf.share_links,
+        max_edit_size,
+        rename_enabled,
+        extract_enabled,
+        events_route,
+        fragments,
+        conf.upload_only,
+        storage_usage,
+        conf.photo_metadata,
+        hls_enabled,
+        resize_enabled,
+        search_enabled,
+        duplicates_enabled,
+        usage_enabled,
+    );
+    let etag = listing_etag(&entries, &options);
+    let last_modified = entries
+        .iter()
+        .filter_map(|entry| entry.last_modification_date)
+        .chain(modified)
+        .max();
+
+    let not_modified = is_not_modified(req, &etag, last_modified);
+    let mut res = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    res.insert_header((header::ETAG, etag))
+        // Listings are revalidated on every visit, instead of being considered fresh for a while
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header((header::VARY, "Accept"));
+    if let Some(last_modified) = last_modified {
+        res.insert_header((header::LAST_MODIFIED, http_date(last_modified)));
+    }
+    if let Some(share_cookie) = share_cookie {
+        res.append_header((header::SET_COOKIE, share_cookie));
+    }
+    if not_modified {
+        return res.finish();
+    }
 
+    if let Some(DirectoryDownload::Playlist(playlist_format)) = query_params.download {
+        let host = format!("{}://{}", proxy::scheme(req), proxy::host(req));
+        let tracks = entries
+            .iter()
+            .filter(|entry| !entry.is_dir() && playlist::is_audio(&entry.name))
+            .map(|entry| (entry.name.as_str(), format!("{}{}", host, entry.link)))
+            .collect::<Vec<_>>();
         let file_name = format!(
             "{}.{}",
-            dir.path.file_name().unwrap().to_str().unwrap(),
-            archive_method.extension()
+            breadcrumbs
+                .last()
+                .map_or("playlist", |breadcrumb| breadcrumb.name.as_str()),
+            playlist_format.extension()
         );
+        return res
+            .content_type(playlist_format.content_type())
+            .append_header((
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename={:?}", file_name),
+            ))
+            .body(playlist::render_playlist(&tracks));
+    }
 
-        // We will create the archive in a separate thread, and stream the content using a pipe.
-        // The pipe is made of a futures channel, and an adapter to implement the `Write` trait.
-        // Include 10 messages of buffer for erratic connection speeds.
-        let (tx, rx) = futures::channel::mpsc::channel::<io::Result<actix_web::web::Bytes>>(10);
-        let pipe = crate::pipe::Pipe::new(tx);
+    match format {
+        ListingFormat::Html => (),
+        ListingFormat::Txt => {
+            let lines = entries
+                .iter()
+                .map(|entry| {
+                    let slash = if entry.is_dir() { "/" } else { "" };
+                    format!("{}{}\n", entry.name, slash)
+                })
+                .collect::<String>();
+            return res.content_type("text/plain; charset=utf-8").body(lines);
+        }
+        ListingFormat::Json => {
+            let entries = entries.iter().map(JsonEntry::from).collect::<Vec<_>>();
+            return match serde_json::to_string(&entries) {
+                Ok(json) => res.content_type("application/json").body(json),
+                Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+            };
+        }
+    }
 
-        // Start the actual archive creation in a separate thread.
-        let dir = dir.path.to_path_buf();
-        std::thread::spawn(move || {
-            if let Err(err) = archive_method.create_archive(dir, skip_symlinks, pipe) {
-                log::error!("Error during archive creation: {:?}", err);
-            }
-        });
+    res.content_type("text/html; charset=utf-8").body(
+        renderer::page(
+            entries,
+            is_root,
+            query_params.sort,
+            query_params.order,
+            show_qrcode,
+            file_upload,
+            &upload_route,
+            &favicon_route,
+            &css_route,
+            default_color_scheme,
+            default_color_scheme_dark,
+            &encoded_dir,
+            breadcrumbs,
+            tar_enabled,
+            tar_gz_enabled,
+            zip_enabled,
+            torrent_enabled,
+            hls_enabled,
+            resize_enabled,
+            hide_version_footer,
+            show_logout,
+            conf.share_links,
+            rename_enabled,
+            max_edit_size,
+            extract_enabled,
+            events_route.as_deref(),
+            &fragments,
+            conf.upload_only,
+            storage_usage,
+            search_enabled,
+            duplicates_enabled,
+            usage_enabled,
+        )
+        .into_string(),
+    )
+}
 
-        Ok(ServiceResponse::new(
-            req.clone(),
-            HttpResponse::Ok()
-                .content_type(archive_method.content_type())
-                .encoding(archive_method.content_encoding())
-                .append_header(("Content-Transfer-Encoding", "binary"))
-                .append_header((
-                    "Content-Disposition",
-                    format!("attachment; filename={:?}", file_name),
-                ))
-                .body(actix_web::body::BodyStream::new(rx)),
-        ))
-    } else {
-        Ok(ServiceResponse::new(
-            req.clone(),
-            HttpResponse::Ok()
-                .content_type("text/html; charset=utf-8")
-                .body(
-                    renderer::page(
-                        entries,
-                        is_root,
-                        query_params.sort,
-                        query_params.order,
-                        show_qrcode,
-                        file_upload,
-                        &upload_route,
-                        &favicon_route,
-                        &css_route,
-                        default_color_scheme,
-                        default_color_scheme_dark,
-                        &encoded_dir,
-                        breadcrumbs,
-                        tar_enabled,
-                        tar_gz_enabled,
-                        zip_enabled,
-                        hide_version_footer,
-                    )
-                    .into_string(),
-                ),
-        ))
+/// Weak ETag of the listing of `entries` rendered with `options`
+fn listing_etag(entries: &[Entry], options: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(options.as_bytes());
+    for entry in entries {
+        let modified = entry
+            .last_modification_date
+            .and_then(|date| date.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_nanos());
+        hasher.update(
+            format!(
+                "\n{}|{}|{}|{:?}|{}|{:?}",
+                entry.name,
+                entry.is_dir(),
+                entry.is_symlink,
+                entry.size.map(|size| size.as_u64()),
+                modified,
+                entry.downloads
+            )
+            .as_bytes(),
+        );
+    }
+    format!("W/\"{}\"", hex::encode(&hasher.finalize()[..16]))
+}
+
+/// Format `time` for the HTTP date headers
+fn http_date(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// Whether the copy of the listing cached by the client, as described by the conditional headers
+/// of `req`, is still the current one
+fn is_not_modified(req: &HttpRequest, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since
+    if let Some(if_none_match) = req.headers().get(header::IF_NONE_MATCH) {
+        let tag = etag.trim_start_matches("W/");
+        return if_none_match
+            .to_str()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == tag);
+    }
+
+    let since = req
+        .headers()
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|since| since.to_str().ok())
+        .and_then(|since| chrono::DateTime::parse_from_rfc2822(since).ok());
+    match (since, last_modified) {
+        // The header only has a precision of one second
+        (Some(since), Some(last_modified)) => last_modified
+            .duration_since(UNIX_EPOCH)
+            .is_ok_and(|modified| modified.as_secs() as i64 <= since.timestamp()),
+        _ => false,
     }
 }
 
@@ -421,8 +1076,11 @@ pub fn extract_query_parameters(req: &HttpRequest) -> QueryParameters {
             sort: query.sort,
             order: query.order,
             download: query.download,
+            manifest: query.manifest,
+            view: query.view,
             qrcode: query.qrcode.to_owned(),
             path: query.path.clone(),
+            format: query.format,
         },
         Err(e) => {
             let err = ContextualError::ParseError("query parameters".to_string(), e.to_string());
@@ -431,8 +1089,11 @@ pub fn extract_query_parameters(req: &HttpRequest) -> QueryParameters {
                 sort: None,
                 order: None,
                 download: None,
+                manifest: None,
+                view: None,
                 qrcode: None,
                 path: None,
+                format: None,
             }
         }
     }
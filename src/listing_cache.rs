@@ -0,0 +1,217 @@
+//! Directory contents kept in memory with `--listing-cache`, so that huge directories aren't read
+//! again on every request for their listing.
+//!
+//! Cached directories are watched, and dropped from the cache when they change. As changes may be
+//! missed, e.g. on network filesystems, cached directories are read again after a while anyway.
+use notify::{RawEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long a directory is cached, in case its changes aren't seen by the watcher
+const TTL: Duration = Duration::from_secs(60);
+
+/// Number of directories which can be cached, each of them being watched
+const MAX_CACHED_DIRECTORIES: usize = 1024;
+
+/// Entry of a directory, before hidden files and symlinks are filtered out of the listing
+#[derive(Clone, Debug)]
+pub struct ListedEntry {
+    pub name: String,
+
+    /// Original name, if it isn't valid UTF-8 and `name` has replacement characters instead
+    pub raw_name: Option<OsString>,
+
+    pub is_dir: bool,
+    pub is_symlink: bool,
+
+    /// Size in bytes, for files
+    pub size: u64,
+
+    pub modified: Option<SystemTime>,
+}
+
+struct CachedDirectory {
+    entries: Arc<Vec<ListedEntry>>,
+    read: Instant,
+}
+
+#[derive(Default)]
+struct Directories {
+    cached: HashMap<PathBuf, CachedDirectory>,
+
+    /// Number of changes seen by the watcher, so that directories read while they change aren't
+    /// cached
+    changes: u64,
+}
+
+/// Contents of the listed directories, if `--listing-cache` is enabled
+pub struct ListingCache {
+    enabled: bool,
+    directories: Arc<Mutex<Directories>>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl ListingCache {
+    pub fn new(enabled: bool) -> Self {
+        let directories = Arc::new(Mutex::new(Directories::default()));
+        let watcher = if enabled {
+            let (tx, rx) = mpsc::channel();
+            match notify::raw_watcher(tx) {
+                Ok(watcher) => {
+                    let directories = directories.clone();
+                    std::thread::spawn(move || invalidate_changed(&directories, rx));
+                    Some(watcher)
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Failed to watch the listed directories, they are cached for {}s: {}",
+                        TTL.as_secs(),
+                        e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        ListingCache {
+            enabled,
+            directories,
+            watcher: Mutex::new(watcher),
+        }
+    }
+
+    /// Entries of the directory at `path`, read from the filesystem unless they are cached
+    pub fn read_dir(&self, path: &Path) -> io::Result<Arc<Vec<ListedEntry>>> {
+        if !self.enabled {
+            return read_entries(path).map(Arc::new);
+        }
+
+        let changes = {
+            let directories = self.directories.lock().unwrap();
+            match directories.cached.get(path) {
+                Some(cached) if cached.read.elapsed() < TTL => return Ok(cached.entries.clone()),
+                _ => directories.changes,
+            }
+        };
+
+        // Watch before reading, so that the changes made in between aren't missed
+        if let Some(watcher) = self.watcher.lock().unwrap().as_mut() {
+            if let Err(e) = watcher.watch(path, RecursiveMode::NonRecursive) {
+                log::warn!("Failed to watch {}: {}", path.display(), e);
+            }
+        }
+        let entries = Arc::new(read_entries(path)?);
+
+        let mut directories = self.directories.lock().unwrap();
+        if directories.changes != changes {
+            return Ok(entries);
+        }
+        if directories.cached.len() >= MAX_CACHED_DIRECTORIES
+            && !directories.cached.contains_key(path)
+        {
+            let oldest = directories
+                .cached
+                .iter()
+                .min_by_key(|(_, cached)| cached.read)
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                directories.cached.remove(&oldest);
+                if let Some(watcher) = self.watcher.lock().unwrap().as_mut() {
+                    let _ = watcher.unwatch(&oldest);
+                }
+            }
+        }
+        directories.cached.insert(
+            path.to_path_buf(),
+            CachedDirectory {
+                entries: entries.clone(),
+                read: Instant::now(),
+            },
+        );
+        Ok(entries)
+    }
+}
+
+/// Drop the directories changed according to the events of the watcher from the cache
+fn invalidate_changed(directories: &Mutex<Directories>, events: mpsc::Receiver<RawEvent>) {
+    for event in events {
+        let mut directories = directories.lock().unwrap();
+        directories.changes += 1;
+        match event.path {
+            Some(path) => {
+                directories.cached.remove(&path);
+                if let Some(parent) = path.parent() {
+                    directories.cached.remove(parent);
+                }
+            }
+            // Events may have been lost
+            None => directories.cached.clear(),
+        }
+    }
+}
+
+/// Read the entries of the directory at `path`, which are either files or directories, or
+/// symlinks to these
+pub fn read_entries(path: &Path) -> io::Result<Vec<ListedEntry>> {
+    let mut entries = vec![];
+    for entry in path.read_dir()? {
+        let entry = entry?;
+        let (is_symlink, metadata) = match entry.metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                // for symlinks, get the metadata of the original file
+                (true, std::fs::metadata(entry.path()))
+            }
+            res => (false, res),
+        };
+        let metadata = match metadata {
+            Ok(metadata) if metadata.is_dir() || metadata.is_file() => metadata,
+            _ => continue,
+        };
+
+        let file_name = entry.file_name();
+        entries.push(ListedEntry {
+            name: file_name.to_string_lossy().to_string(),
+            raw_name: file_name.to_str().is_none().then(|| file_name.clone()),
+            is_dir: metadata.is_dir(),
+            is_symlink,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
+    }
+    Ok(entries)
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    fn names(entries: &[ListedEntry]) -> Vec<String> {
+        let mut names = entries.iter().map(|entry| entry.name.clone()).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[rstest]
+    fn changed_directories_are_read_again() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a"), "a").unwrap();
+        let cache = ListingCache::new(true);
+
+        assert_eq!(names(&cache.read_dir(dir.path()).unwrap()), vec!["a"]);
+        assert!(cache.directories.lock().unwrap().cached.contains_key(dir.path()));
+
+        std::fs::write(dir.path().join("b"), "b").unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        assert_eq!(names(&cache.read_dir(dir.path()).unwrap()), vec!["a", "b"]);
+    }
+}
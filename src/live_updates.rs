@@ -0,0 +1,215 @@
+//! Changes of the listed directories pushed to the browsers with Server-Sent Events, when
+//! `--live-updates` is enabled, so that new uploads appear without refreshing the page.
+//!
+//! The listing subscribes to the changes of its directory, which is watched for as long as a page
+//! listing it is open, and reloads its entries when one of them changes.
+use actix_web::dev::BodyEncoding;
+use actix_web::http::{header, ContentEncoding};
+use actix_web::web::{self, Bytes};
+use actix_web::{HttpRequest, HttpResponse};
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use notify::{op, RawEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::dir_config;
+use crate::storage::served_path;
+use crate::MiniserveConfig;
+
+/// Route of the event stream, relative to the route of the served directory
+pub const EVENTS_ROUTE: &str = "/__events";
+
+/// Interval of the comments sent to keep the connections open, and to notice closed ones
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Query parameters of the event stream
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// Path of the listed directory, relative to the served directory
+    path: String,
+}
+
+/// Kind of change of an entry
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EntryChange {
+    Create,
+    Modify,
+    Delete,
+    Rename,
+}
+
+/// Payload of the events
+#[derive(Serialize)]
+struct ChangeEvent<'a> {
+    action: EntryChange,
+
+    /// Name of the changed entry
+    name: &'a str,
+}
+
+/// Page listening to the changes of a directory
+struct Subscriber {
+    events: mpsc::Sender<Bytes>,
+    show_hidden: bool,
+}
+
+type Subscribers = Arc<Mutex<HashMap<PathBuf, Vec<Subscriber>>>>;
+
+/// Directories watched for the open listings
+pub struct LiveUpdates {
+    subscribers: Subscribers,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl LiveUpdates {
+    pub fn new(enabled: bool) -> Self {
+        let subscribers = Subscribers::default();
+        let watcher = if enabled {
+            let (tx, rx) = std::sync::mpsc::channel();
+            match notify::raw_watcher(tx) {
+                Ok(watcher) => {
+                    let subscribers = subscribers.clone();
+                    std::thread::spawn(move || {
+                        for event in rx {
+                            broadcast(&subscribers, event);
+                        }
+                    });
+                    Some(watcher)
+                }
+                Err(e) => {
+                    log::error!("Failed to watch the listed directories: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        LiveUpdates {
+            subscribers,
+            watcher: Mutex::new(watcher),
+        }
+    }
+
+    /// Send the changes of the directory at `dir` to `subscriber`
+    fn subscribe(&self, dir: PathBuf, subscriber: Subscriber) -> notify::Result<()> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if !subscribers.contains_key(&dir) {
+            match self.watcher.lock().unwrap().as_mut() {
+                Some(watcher) => watcher.watch(&dir, RecursiveMode::NonRecursive)?,
+                None => return Err(notify::Error::Generic("no watcher".to_string())),
+            }
+        }
+        subscribers.entry(dir).or_default().push(subscriber);
+        Ok(())
+    }
+
+    /// Forget the subscribers of `dir` whose page was closed, and stop watching `dir` if none is
+    /// left
+    fn unsubscribe_closed(&self, dir: &Path) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if let Some(dir_subscribers) = subscribers.get_mut(dir) {
+            dir_subscribers.retain(|subscriber| !subscriber.events.is_closed());
+            if dir_subscribers.is_empty() {
+                subscribers.remove(dir);
+                if let Some(watcher) = self.watcher.lock().unwrap().as_mut() {
+                    let _ = watcher.unwatch(dir);
+                }
+            }
+        }
+    }
+}
+
+/// Send the change described by `event` to the subscribers of the changed directory
+fn broadcast(subscribers: &Mutex<HashMap<PathBuf, Vec<Subscriber>>>, event: RawEvent) {
+    let (path, op) = match (event.path, event.op) {
+        (Some(path), Ok(op)) => (path, op),
+        _ => return,
+    };
+    let action = if op.contains(op::CREATE) {
+        EntryChange::Create
+    } else if op.contains(op::REMOVE) {
+        EntryChange::Delete
+    } else if op.contains(op::RENAME) {
+        EntryChange::Rename
+    } else if op.intersects(op::WRITE | op::CLOSE_WRITE) {
+        EntryChange::Modify
+    } else {
+        return;
+    };
+    let (dir, name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => (dir, name.to_string_lossy()),
+        _ => return,
+    };
+    let data = match serde_json::to_string(&ChangeEvent {
+        action,
+        name: &name,
+    }) {
+        Ok(data) => data,
+        Err(_) => return,
+    };
+    let message = Bytes::from(format!("event: change\ndata: {}\n\n", data));
+
+    if let Some(dir_subscribers) = subscribers.lock().unwrap().get_mut(dir) {
+        for subscriber in dir_subscribers {
+            if subscriber.show_hidden || !name.starts_with('.') {
+                // The pages reload their entries anyway, so that a missed event doesn't matter
+                let _ = subscriber.events.try_send(message.clone());
+            }
+        }
+    }
+}
+
+/// Stream the changes of the directory listed at `query.path`
+pub async fn events(
+    req: HttpRequest,
+    query: web::Query<EventsQuery>,
+    live_updates: web::Data<LiveUpdates>,
+) -> HttpResponse {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let dir = match served_path(conf, &query.path) {
+        Some(dir) if dir.is_dir() => dir,
+        _ => return crate::error_404(req).await,
+    };
+    if let Err(err) = dir_config::check_access(&req, conf, &dir, false) {
+        return dir_config::access_denied_response(&req, err);
+    }
+
+    let (mut tx, rx) = mpsc::channel(16);
+    let subscriber = Subscriber {
+        events: tx.clone(),
+        show_hidden: conf.show_hidden,
+    };
+    if let Err(e) = live_updates.subscribe(dir.clone(), subscriber) {
+        log::error!("Failed to watch {}: {}", dir.display(), e);
+        return HttpResponse::ServiceUnavailable()
+            .content_type("text/plain; charset=utf-8")
+            .body("Live updates are unavailable");
+    }
+
+    actix_web::rt::spawn(async move {
+        loop {
+            actix_web::rt::time::sleep(KEEPALIVE_INTERVAL).await;
+            if tx
+                .send(Bytes::from_static(b": keepalive\n\n"))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+        live_updates.unsubscribe_closed(&dir);
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Compression would hold the events back
+        .encoding(ContentEncoding::Identity)
+        .streaming(rx.map(Ok::<_, actix_web::Error>))
+}
@@ -0,0 +1,218 @@
+//! Log files written by miniserve, rotated by size or time and optionally compressed.
+use libflate::gzip::Encoder;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+use crate::session::unix_now;
+
+/// How often a log file is rotated, regardless of its size
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum RotationInterval {
+    Hourly,
+    Daily,
+}
+
+impl RotationInterval {
+    fn seconds(self) -> u64 {
+        match self {
+            RotationInterval::Hourly => 60 * 60,
+            RotationInterval::Daily => 60 * 60 * 24,
+        }
+    }
+}
+
+/// When a log file is rotated, and what happens to the rotated files
+#[derive(Clone, Debug)]
+pub struct RotationPolicy {
+    /// Rotate the file once it reaches this size in bytes
+    pub max_size: Option<u64>,
+
+    /// Rotate the file when a new hour or day starts (UTC)
+    pub interval: Option<RotationInterval>,
+
+    /// Number of rotated files which are kept
+    pub keep: usize,
+
+    /// Compress rotated files with gzip
+    pub compress: bool,
+}
+
+/// File which is currently written to
+struct LogFileState {
+    file: File,
+    size: u64,
+
+    /// Rotation period in which the file was last written to
+    period: Option<u64>,
+}
+
+/// Log file, rotated according to its policy. Rotated files are named `<path>.1`, `<path>.2`,
+/// etc. from the newest to the oldest.
+pub struct LogFile {
+    path: PathBuf,
+    policy: RotationPolicy,
+    state: Mutex<LogFileState>,
+}
+
+impl LogFile {
+    /// Open the log file at `path`, appending to it if it exists
+    pub fn open(path: PathBuf, policy: RotationPolicy) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+
+        // Content left by a previous run may belong to a past period
+        let period = policy.interval.map(|interval| {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or_else(unix_now, |d| d.as_secs());
+            modified / interval.seconds()
+        });
+
+        Ok(LogFile {
+            path,
+            policy,
+            state: Mutex::new(LogFileState {
+                file,
+                size: metadata.len(),
+                period,
+            }),
+        })
+    }
+
+    /// Append `line` to the log file, after rotating it if needed
+    pub fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let len = line.len() as u64 + 1;
+
+        let period = self
+            .policy
+            .interval
+            .map(|interval| unix_now() / interval.seconds());
+        let too_large = self
+            .policy
+            .max_size
+            .is_some_and(|max_size| state.size > 0 && state.size + len > max_size);
+        if period != state.period || too_large {
+            self.rotate(&mut state)?;
+            state.period = period;
+        }
+
+        writeln!(state.file, "{}", line)?;
+        state.size += len;
+        Ok(())
+    }
+
+    /// Path of the rotated file with the given index
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        if self.policy.compress {
+            name.push(".gz");
+        }
+        PathBuf::from(name)
+    }
+
+    /// Move the current file out of the way, and start a new one
+    fn rotate(&self, state: &mut LogFileState) -> io::Result<()> {
+        state.file.flush()?;
+
+        if self.policy.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            ignore_not_found(std::fs::remove_file(self.rotated_path(self.policy.keep)))?;
+            for index in (1..self.policy.keep).rev() {
+                ignore_not_found(std::fs::rename(
+                    self.rotated_path(index),
+                    self.rotated_path(index + 1),
+                ))?;
+            }
+
+            let rotated_path = self.rotated_path(1);
+            if self.policy.compress {
+                // The current file is renamed at once, and compressed in the background
+                let mut uncompressed_path = rotated_path.clone();
+                uncompressed_path.set_extension("");
+                std::fs::rename(&self.path, &uncompressed_path)?;
+                std::thread::spawn(move || {
+                    if let Err(e) = compress_file(&uncompressed_path, &rotated_path) {
+                        log::error!("Failed to compress {}: {}", uncompressed_path.display(), e);
+                    }
+                });
+            } else {
+                std::fs::rename(&self.path, &rotated_path)?;
+            }
+        }
+
+        state.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        state.size = 0;
+        Ok(())
+    }
+}
+
+/// Consider the removal or renaming of a missing file as successful
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Compress the file at `source` with gzip into `destination`, and remove it
+fn compress_file(source: &Path, destination: &Path) -> io::Result<()> {
+    let mut input = File::open(source)?;
+    let mut encoder = Encoder::new(File::create(destination)?)?;
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish().into_result()?;
+    std::fs::remove_file(source)
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    fn policy(max_size: Option<u64>, keep: usize) -> RotationPolicy {
+        RotationPolicy { max_size, interval: None, keep, compress: false }
+    }
+
+    #[rstest]
+    fn log_file_rotates_by_size() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("access.log");
+        let log_file = LogFile::open(path.clone(), policy(Some(10), 2)).unwrap();
+        for line in &["first", "second", "third", "fourth"] {
+            log_file.write_line(line).unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("access.log.1")).unwrap(), "third\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("access.log.2")).unwrap(), "second\n");
+        assert!(!dir.path().join("access.log.3").exists());
+    }
+
+    #[rstest]
+    fn log_file_is_not_rotated_without_policy() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("access.log");
+        let log_file = LogFile::open(path.clone(), policy(None, 2)).unwrap();
+        for line in &["first", "second"] {
+            log_file.write_line(line).unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+        assert!(!dir.path().join("access.log.1").exists());
+    }
+}
@@ -1,45 +1,50 @@
 use std::io;
 use std::io::Write;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::thread;
 use std::time::Duration;
 
-use actix_web::web;
-use actix_web::{
-    http::{header::ContentType, StatusCode},
-    Responder,
-};
-use actix_web::{middleware, App, HttpRequest, HttpResponse};
 use anyhow::Result;
 use log::{error, warn};
+use miniserve::system_log::SystemLog;
+use miniserve::{args, canonical_path, config_file, errors, service};
+use miniserve::{ContextualError, LogFormat, LogTarget, MiniserveConfig, Server, ServerHandle};
+use qrcodegen::{QrCode, QrCodeEcc};
 use structopt::clap::crate_version;
 use structopt::StructOpt;
 use yansi::{Color, Paint};
 
-mod archive;
-mod args;
-mod auth;
-mod config;
-mod errors;
-mod file_upload;
-mod listing;
-mod pipe;
-mod renderer;
-
-use crate::config::MiniserveConfig;
-use crate::errors::ContextualError;
-
 fn main() -> Result<()> {
-    let args = args::CliArgs::from_args();
+    let cli_args = std::env::args_os().collect::<Vec<_>>();
+    let args = args::CliArgs::from_iter(&cli_args);
+    let config_path = args
+        .config
+        .clone()
+        .or_else(|| config_file::default_path().filter(|path| path.is_file()));
+    let config_source = config_path.map(|path| config_file::ConfigSource {
+        path,
+        cli_args: cli_args.clone(),
+    });
+    let args = match &config_source {
+        Some(source) => args::CliArgs::from_iter(source.merged_args()?),
+        None => args,
+    };
 
     if let Some(shell) = args.print_completions {
         args::CliArgs::clap().gen_completions_to("miniserve", shell, &mut std::io::stdout());
         return Ok(());
     }
 
+    if let Some(manager) = args.print_service {
+        print!(
+            "{}",
+            service::service_definition(manager, &cli_args, &args)?
+        );
+        return Ok(());
+    }
+
     let miniserve_config = MiniserveConfig::try_from_args(args)?;
 
-    match run(miniserve_config) {
+    match run(miniserve_config, config_source) {
         Ok(()) => (),
         Err(e) => errors::log_error_chain(e.to_string()),
     }
@@ -47,7 +52,10 @@ fn main() -> Result<()> {
 }
 
 #[actix_web::main(miniserve)]
-async fn run(miniserve_config: MiniserveConfig) -> Result<(), ContextualError> {
+async fn run(
+    miniserve_config: MiniserveConfig,
+    config_source: Option<config_file::ConfigSource>,
+) -> Result<(), ContextualError> {
     if cfg!(windows) && !Paint::enable_windows_ascii() {
         Paint::disable();
     }
@@ -58,68 +66,43 @@ async fn run(miniserve_config: MiniserveConfig) -> Result<(), ContextualError> {
         simplelog::LevelFilter::Warn
     };
 
-    if simplelog::TermLogger::init(
-        log_level,
-        simplelog::Config::default(),
-        simplelog::TerminalMode::Mixed,
-        simplelog::ColorChoice::Auto,
-    )
-    .is_err()
-    {
-        simplelog::SimpleLogger::init(log_level, simplelog::Config::default())
-            .expect("Couldn't initialize logger")
-    }
+    match miniserve_config.log_target {
+        LogTarget::Stdout => {
+            let mut log_config = simplelog::ConfigBuilder::new();
+            if miniserve_config.log_format == LogFormat::Json {
+                // Requests are logged as JSON on the standard output instead
+                log_config.add_filter_ignore_str("actix_web::middleware::logger");
+            }
+            let log_config = log_config.build();
 
-    if miniserve_config.no_symlinks {
-        let is_symlink = miniserve_config
-            .path
-            .symlink_metadata()
-            .map_err(|e| {
-                ContextualError::IoError("Failed to retrieve symlink's metadata".to_string(), e)
-            })?
-            .file_type()
-            .is_symlink();
-
-        if is_symlink {
-            return Err(ContextualError::NoSymlinksOptionWithSymlinkServePath(
-                miniserve_config.path.to_string_lossy().to_string(),
-            ));
+            if simplelog::TermLogger::init(
+                log_level,
+                log_config.clone(),
+                simplelog::TerminalMode::Mixed,
+                simplelog::ColorChoice::Auto,
+            )
+            .is_err()
+            {
+                simplelog::SimpleLogger::init(log_level, log_config)
+                    .expect("Couldn't initialize logger")
+            }
         }
+        target => SystemLog::init(target, log_level)
+            .map_err(|e| ContextualError::IoError(format!("Failed to log to {}", target), e))?,
     }
 
-    let inside_config = miniserve_config.clone();
-
-    let interfaces = miniserve_config
-        .interfaces
-        .iter()
-        .map(|&interface| {
-            if interface == IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)) {
-                // If the interface is 0.0.0.0, we'll change it to 127.0.0.1 so that clicking the link will
-                // also work on Windows. Why can't Windows interpret 0.0.0.0?
-                "127.0.0.1".to_string()
-            } else if interface.is_ipv6() {
-                // If the interface is IPv6 then we'll print it with brackets so that it is clickable.
-                format!("[{}]", interface)
-            } else {
-                format!("{}", interface)
-            }
-        })
-        .collect::<Vec<String>>();
+    #[cfg(feature = "otlp")]
+    if let Some(endpoint) = &miniserve_config.otlp_endpoint {
+        miniserve::telemetry::init(endpoint)?;
+    }
 
-    let canon_path = miniserve_config.path.canonicalize().map_err(|e| {
+    let canon_path = canonical_path::canonicalize(&miniserve_config.path).map_err(|e| {
         ContextualError::IoError("Failed to resolve path to be served".to_string(), e)
     })?;
-
-    if let Some(index_path) = &miniserve_config.index {
-        let has_index: std::path::PathBuf = [&canon_path, index_path].iter().collect();
-        if !has_index.exists() {
-            error!(
-                "The file '{}' provided for option --index could not be found.",
-                index_path.to_string_lossy()
-            );
-        }
-    }
-    let path_string = canon_path.to_string_lossy();
+    let path_string = match &miniserve_config.storage {
+        Some(storage) => storage.location(),
+        None => canon_path.to_string_lossy().to_string(),
+    };
 
     println!(
         "{name} v{version}",
@@ -151,8 +134,19 @@ async fn run(miniserve_config: MiniserveConfig) -> Result<(), ContextualError> {
             thread::sleep(Duration::from_millis(500));
         }
     }
+
+    #[cfg(unix)]
+    if let Some(source) = config_source {
+        actix_web::rt::spawn(handle_reload_signals(miniserve_config.clone(), source));
+    }
+    #[cfg(not(unix))]
+    let _ = config_source;
+
+    let server = Server::new(miniserve_config).run().await?;
+    let miniserve_config = server.config();
+
     let mut addresses = String::new();
-    for interface in &interfaces {
+    for interface in &miniserve_config.interface_hosts() {
         if !addresses.is_empty() {
             addresses.push_str(", ");
         }
@@ -181,251 +175,175 @@ async fn run(miniserve_config: MiniserveConfig) -> Result<(), ContextualError> {
                     .bold()
             ));
         }
-    }
-
-    let socket_addresses = interfaces
-        .iter()
-        .map(|interface| {
-            format!(
-                "{interface}:{port}",
-                interface = &interface,
-                port = miniserve_config.port,
-            )
-            .parse::<SocketAddr>()
-        })
-        .collect::<Result<Vec<SocketAddr>, _>>();
 
-    let socket_addresses = match socket_addresses {
-        Ok(addresses) => addresses,
-        Err(e) => {
-            // Note that this should never fail, since CLI parsing succeeded
-            // This means the format of each IP address is valid, and so is the port
-            // Valid IpAddr + valid port == valid SocketAddr
-            return Err(ContextualError::ParseError(
-                "string as socket address".to_string(),
-                e.to_string(),
+        if let Some(http_port) = miniserve_config.http_port {
+            addresses.push_str(&format!(
+                ", {}",
+                Color::Green
+                    .paint(format!("http://{}:{}", interface, http_port))
+                    .bold()
             ));
         }
-    };
+    }
 
-    let srv = actix_web::HttpServer::new(move || {
-        App::new()
-            .wrap(configure_header(&inside_config.clone()))
-            .app_data(inside_config.clone())
-            // we should use `actix_web_httpauth::middleware::HttpAuthentication`
-            // but it is unfortuantrly broken
-            // see: https://github.com/actix/actix-extras/issues/127
-            // TODO replace this when fixed upstream
-            .wrap_fn(auth::auth_middleware)
-            .wrap(middleware::Logger::default())
-            .route(
-                &format!("/{}", inside_config.favicon_route),
-                web::get().to(favicon),
-            )
-            .route(&format!("/{}", inside_config.css_route), web::get().to(css))
-            .configure(|c| configure_app(c, &inside_config))
-            .default_service(web::get().to(error_404))
-    });
+    if let Some(unix_socket) = &miniserve_config.unix_socket {
+        if !addresses.is_empty() {
+            addresses.push_str(", ");
+        }
+        addresses.push_str(&format!(
+            "{}",
+            Color::Green
+                .paint(format!("unix:{}", unix_socket.display()))
+                .bold()
+        ));
+    }
 
-    #[cfg(feature = "tls")]
-    let srv = if let Some(tls_config) = miniserve_config.tls_rustls_config {
-        srv.bind_rustls(socket_addresses.as_slice(), tls_config)
-            .map_err(|e| ContextualError::IoError("Failed to bind server".to_string(), e))?
-            .shutdown_timeout(0)
-            .run()
-    } else {
-        srv.bind(socket_addresses.as_slice())
-            .map_err(|e| ContextualError::IoError("Failed to bind server".to_string(), e))?
-            .shutdown_timeout(0)
-            .run()
-    };
+    match &miniserve_config.stdin_file {
+        Some(file) => println!(
+            "Serving standard input as {name} at {addresses}",
+            name = Color::Yellow
+                .paint(file.file_name().unwrap_or_default().to_string_lossy())
+                .bold(),
+            addresses = addresses,
+        ),
+        None => println!(
+            "Serving path {path} at {addresses}",
+            path = Color::Yellow.paint(path_string).bold(),
+            addresses = addresses,
+        ),
+    }
 
-    #[cfg(not(feature = "tls"))]
-    let srv = srv
-        .bind(socket_addresses.as_slice())
-        .map_err(|e| ContextualError::IoError("Failed to bind server".to_string(), e))?
-        .shutdown_timeout(0)
-        .run();
+    for vhost in &miniserve_config.vhosts {
+        println!(
+            "Serving path {path} for {host}",
+            path = Color::Yellow.paint(vhost.path.display()).bold(),
+            host = Color::Green.paint(&vhost.host).bold(),
+        );
+    }
 
-    println!(
-        "Serving path {path} at {addresses}",
-        path = Color::Yellow.paint(path_string).bold(),
-        addresses = addresses,
-    );
+    for mount in &miniserve_config.mounts {
+        let mount_conf = mount.config(miniserve_config);
+        println!(
+            "Serving path {path} at {route}",
+            path = Color::Yellow.paint(mount.path.display()).bold(),
+            route = Color::Green
+                .paint(mount_conf.mount_route.unwrap_or_default())
+                .bold(),
+        );
+    }
+
+    if let Some(url) = server.public_url() {
+        println!(
+            "Reachable from the internet at {}",
+            Color::Green.paint(&url).bold()
+        );
+        if let Some(qr_code) = terminal_qr_code(&url) {
+            println!("{}", qr_code);
+        }
+    }
+
+    if let Some(fingerprint) = &miniserve_config.tls_self_signed_fingerprint {
+        println!(
+            "Using a self-signed certificate with SHA-256 fingerprint {}",
+            Color::Yellow.paint(fingerprint).bold()
+        );
+    }
 
     if atty::is(atty::Stream::Stdout) {
         println!("\nQuit by pressing CTRL-C");
     }
 
-    srv.await
-        .map_err(|e| ContextualError::IoError("".to_owned(), e))
-}
+    actix_web::rt::spawn(handle_shutdown_signals(
+        server.handle(),
+        miniserve_config.shutdown_grace_period,
+    ));
 
-fn configure_header(conf: &MiniserveConfig) -> middleware::DefaultHeaders {
-    let headers = conf.clone().header;
+    #[cfg(feature = "otlp")]
+    let otlp_enabled = miniserve_config.otlp_endpoint.is_some();
 
-    let mut default_headers = middleware::DefaultHeaders::new();
-    for header in headers {
-        for (header_name, header_value) in header.into_iter() {
-            if let Some(header_name) = header_name {
-                default_headers = default_headers.header(&header_name, header_value);
-            }
-        }
+    let result = server.wait().await;
+
+    #[cfg(feature = "otlp")]
+    if otlp_enabled {
+        miniserve::telemetry::shutdown();
     }
-    default_headers
+
+    result
 }
 
-/// Configures the Actix application
-fn configure_app(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
-    let random_route = conf.random_route.clone().unwrap_or_default();
-    let uses_random_route = conf.random_route.clone().is_some();
-    let full_route = format!("/{}", random_route);
+/// Draw `text` as a QR code with Unicode blocks, two rows of modules per line. The light modules
+/// are drawn, for terminals with a dark background.
+fn terminal_qr_code(text: &str) -> Option<String> {
+    let qr = QrCode::encode_text(text, QrCodeEcc::Low).ok()?;
+    let border = 2;
+    let range = -border..qr.size() + border;
+    let mut lines = String::new();
+    for y in range.clone().step_by(2) {
+        for x in range.clone() {
+            let top = !qr.get_module(x, y);
+            let bottom = y + 1 < qr.size() + border && !qr.get_module(x, y + 1);
+            lines.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        lines.push('\n');
+    }
+    Some(lines)
+}
 
-    let upload_route = if let Some(random_route) = conf.random_route.clone() {
-        format!("/{}/upload", random_route)
-    } else {
-        "/upload".to_string()
-    };
+/// Reload the configuration file on SIGHUP
+#[cfg(unix)]
+async fn handle_reload_signals(conf: MiniserveConfig, source: config_file::ConfigSource) {
+    use actix_web::rt::signal::unix::{signal, SignalKind};
 
-    let serve_path = {
-        let path = &conf.path;
-        let no_symlinks = conf.no_symlinks;
-        let show_hidden = conf.show_hidden;
-        let random_route = conf.random_route.clone();
-        let favicon_route = conf.favicon_route.clone();
-        let css_route = conf.css_route.clone();
-        let default_color_scheme = conf.default_color_scheme.clone();
-        let default_color_scheme_dark = conf.default_color_scheme_dark.clone();
-        let show_qrcode = conf.show_qrcode;
-        let file_upload = conf.file_upload;
-        let tar_enabled = conf.tar_enabled;
-        let tar_gz_enabled = conf.tar_gz_enabled;
-        let zip_enabled = conf.zip_enabled;
-        let dirs_first = conf.dirs_first;
-        let hide_version_footer = conf.hide_version_footer;
-        let title = conf.title.clone();
-
-        if path.is_file() {
-            None
-        } else {
-            let u_r = upload_route.clone();
-
-            // build `Files` service using configuraion parameters
-            let files = actix_files::Files::new(&full_route, path);
-            let files = match &conf.index {
-                Some(index_file) => files.index_file(index_file.to_string_lossy()),
-                None => files,
-            };
-            let files = match show_hidden {
-                true => files.use_hidden_files(),
-                false => files,
-            };
-            let files = files
-                .show_files_listing()
-                .files_listing_renderer(move |dir, req| {
-                    listing::directory_listing(
-                        dir,
-                        req,
-                        no_symlinks,
-                        show_hidden,
-                        file_upload,
-                        random_route.clone(),
-                        favicon_route.clone(),
-                        css_route.clone(),
-                        &default_color_scheme,
-                        &default_color_scheme_dark,
-                        show_qrcode,
-                        u_r.clone(),
-                        tar_enabled,
-                        tar_gz_enabled,
-                        zip_enabled,
-                        dirs_first,
-                        hide_version_footer,
-                        title.clone(),
-                    )
-                })
-                .prefer_utf8(true)
-                .redirect_to_slash_directory()
-                .default_handler(web::to(error_404));
-            Some(files)
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(e) => {
+            error!("Failed to listen for SIGHUP: {}", e);
+            return;
         }
     };
-
-    let favicon_route = conf.favicon_route.clone();
-    let css_route = conf.css_route.clone();
-
-    let default_color_scheme = conf.default_color_scheme.clone();
-    let default_color_scheme_dark = conf.default_color_scheme_dark.clone();
-    let hide_version_footer = conf.hide_version_footer;
-
-    if let Some(serve_path) = serve_path {
-        if conf.file_upload {
-            // Allow file upload
-            app.service(
-                web::resource(&upload_route).route(web::post().to(move |req, payload| {
-                    file_upload::upload_file(
-                        req,
-                        payload,
-                        uses_random_route,
-                        favicon_route.clone(),
-                        css_route.clone(),
-                        default_color_scheme.clone(),
-                        default_color_scheme_dark.clone(),
-                        hide_version_footer,
-                    )
-                })),
-            )
-            // Handle directories
-            .service(serve_path);
-        } else {
-            // Handle directories
-            app.service(serve_path);
+    while sighup.recv().await.is_some() {
+        match source.reload() {
+            Ok(args) => conf.reload(args),
+            Err(e) => error!("Failed to reload {}: {}", source.path.display(), e),
         }
-    } else {
-        // Handle single files
-        app.service(web::resource(&full_route).route(web::to(listing::file_handler)));
     }
 }
 
-async fn error_404(req: HttpRequest) -> HttpResponse {
-    let err_404 = ContextualError::RouteNotFoundError(req.path().to_string());
-    let conf = req.app_data::<MiniserveConfig>().unwrap();
-    let uses_random_route = conf.random_route.is_some();
-    let favicon_route = conf.favicon_route.clone();
-    let css_route = conf.css_route.clone();
-    let query_params = listing::extract_query_parameters(&req);
-
-    errors::log_error_chain(err_404.to_string());
-
-    actix_web::HttpResponse::NotFound().body(
-        renderer::render_error(
-            &err_404.to_string(),
-            StatusCode::NOT_FOUND,
-            "/",
-            query_params.sort,
-            query_params.order,
-            false,
-            !uses_random_route,
-            &favicon_route,
-            &css_route,
-            &conf.default_color_scheme,
-            &conf.default_color_scheme_dark,
-            conf.hide_version_footer,
-        )
-        .into_string(),
-    )
-}
+/// Stop accepting connections on SIGINT or SIGTERM, and let running transfers finish within
+/// `grace_period` seconds. A second signal stops the server at once.
+async fn handle_shutdown_signals(handle: ServerHandle, grace_period: u64) {
+    wait_for_shutdown_signal().await;
+    warn!(
+        "Shutting down, waiting up to {} seconds for running transfers to finish",
+        grace_period
+    );
 
-async fn favicon() -> impl Responder {
-    let logo = include_str!("../data/logo.svg");
-    HttpResponse::Ok()
-        .insert_header(ContentType(mime::IMAGE_SVG))
-        .message_body(logo.into())
+    let graceful_stop = Box::pin(handle.stop(true));
+    let forced_stop = Box::pin(async {
+        wait_for_shutdown_signal().await;
+        warn!("Stopping at once");
+        handle.stop(false).await
+    });
+    futures::future::select(graceful_stop, forced_stop).await;
 }
 
-async fn css() -> impl Responder {
-    let css = include_str!(concat!(env!("OUT_DIR"), "/style.css"));
-    HttpResponse::Ok()
-        .insert_header(ContentType(mime::TEXT_CSS))
-        .message_body(css.into())
+/// Wait for SIGINT, or SIGTERM on Unix
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut sigterm) = signal(SignalKind::terminate()) {
+            let sigint = Box::pin(actix_web::rt::signal::ctrl_c());
+            futures::future::select(sigint, Box::pin(sigterm.recv())).await;
+            return;
+        }
+    }
+
+    let _ = actix_web::rt::signal::ctrl_c().await;
 }
@@ -0,0 +1,322 @@
+//! Manifests of the files of a directory and its subdirectories, in the format of `sha256sum`,
+//! generated with `?manifest=sha256` when `--enable-manifest` is given.
+//!
+//! Sync tools can compare them with their copy of the tree, and only fetch the files which
+//! changed. The hash of each file is kept in memory until it changes, so that only the new or
+//! modified files are read again.
+//!
+//! The same hashes find the duplicate files of a directory and its subdirectories, listed with
+//! `?view=duplicates` when `--enable-duplicates` is given. Only the files which have the same size
+//! as another one are hashed.
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use strum_macros::{Display, EnumString};
+
+use crate::canonical_path;
+use crate::dir_config::{self, DIR_CONFIG_FILE};
+use crate::ignore::IgnoredEntries;
+use crate::listing_cache;
+
+/// Hash algorithms of the manifests
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ManifestAlgorithm {
+    Sha256,
+}
+
+impl ManifestAlgorithm {
+    /// Conventional name of the manifest file
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ManifestAlgorithm::Sha256 => "SHA256SUMS",
+        }
+    }
+}
+
+/// Entries of the directories which are listed in the manifests
+struct ListOptions {
+    skip_symlinks: bool,
+    show_hidden: bool,
+
+    /// Leave out the subdirectories restricted by a .miniserve.toml, and these files
+    dir_config: bool,
+}
+
+/// File listed in a manifest
+struct ListedFile {
+    /// Path relative to the directory of the manifest
+    relative_path: String,
+    path: PathBuf,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Files with the same content
+pub struct DuplicateFiles {
+    pub size: u64,
+    pub hash: String,
+
+    /// Paths relative to the searched directory, sorted
+    pub paths: Vec<String>,
+}
+
+/// Hash of a file, as it was when it was last modified at `modified`
+struct FileHash {
+    modified: SystemTime,
+    size: u64,
+    hash: String,
+}
+
+/// Hashes of the files which were listed in a manifest
+#[derive(Default)]
+pub struct ManifestCache {
+    files: Mutex<HashMap<PathBuf, FileHash>>,
+}
+
+impl ManifestCache {
+    /// Hash of the file at `path`, computed again if it changed
+    fn hash(&self, path: &Path, modified: Option<SystemTime>, size: u64) -> io::Result<String> {
+        if let Some(modified) = modified {
+            let files = self.files.lock().unwrap();
+            if let Some(file) = files.get(path) {
+                if file.modified == modified && file.size == size {
+                    return Ok(file.hash.clone());
+                }
+            }
+        }
+
+        let mut hasher = Sha256::new();
+        io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+        let hash = hex::encode(hasher.finalize());
+        if let Some(modified) = modified {
+            let mut files = self.files.lock().unwrap();
+            files.insert(
+                path.to_path_buf(),
+                FileHash {
+                    modified,
+                    size,
+                    hash: hash.clone(),
+                },
+            );
+        }
+        Ok(hash)
+    }
+
+    /// Manifest of the files of `dir` and of its subdirectories, sorted by path. The
+    /// subdirectories restricted by a .miniserve.toml are left out with `dir_config`, and the
+    /// entries matching `ignored`.
+    pub fn manifest(
+        &self,
+        dir: &Path,
+        skip_symlinks: bool,
+        show_hidden: bool,
+        dir_config: bool,
+        ignored: Option<IgnoredEntries>,
+    ) -> io::Result<String> {
+        let mut files = vec![];
+        let options = ListOptions {
+            skip_symlinks,
+            show_hidden,
+            dir_config,
+        };
+        list_files(
+            dir,
+            "",
+            &options,
+            ignored.as_ref(),
+            &mut HashSet::new(),
+            &mut files,
+        )?;
+        files.sort_by(|file1, file2| file1.relative_path.cmp(&file2.relative_path));
+        let mut manifest = String::new();
+        for file in &files {
+            let hash = self.hash(&file.path, file.modified, file.size)?;
+            manifest += &manifest_line(&hash, &file.relative_path);
+        }
+        Ok(manifest)
+    }
+
+    /// Groups of the files of `dir` and of its subdirectories which have the same content, the
+    /// largest files first. The files are listed like in the manifests.
+    pub fn duplicates(
+        &self,
+        dir: &Path,
+        skip_symlinks: bool,
+        show_hidden: bool,
+        dir_config: bool,
+        ignored: Option<IgnoredEntries>,
+    ) -> io::Result<Vec<DuplicateFiles>> {
+        let mut files = vec![];
+        let options = ListOptions {
+            skip_symlinks,
+            show_hidden,
+            dir_config,
+        };
+        list_files(
+            dir,
+            "",
+            &options,
+            ignored.as_ref(),
+            &mut HashSet::new(),
+            &mut files,
+        )?;
+
+        let mut sizes = HashMap::<u64, Vec<ListedFile>>::new();
+        // Empty files are all the same, but there's nothing to gain by removing them
+        for file in files.into_iter().filter(|file| file.size > 0) {
+            sizes.entry(file.size).or_default().push(file);
+        }
+        let mut duplicates = vec![];
+        for (size, files) in sizes.into_iter().filter(|(_, files)| files.len() > 1) {
+            let mut hashes = HashMap::<String, Vec<String>>::new();
+            for file in files {
+                let hash = self.hash(&file.path, file.modified, file.size)?;
+                hashes.entry(hash).or_default().push(file.relative_path);
+            }
+            duplicates.extend(hashes.into_iter().filter(|(_, paths)| paths.len() > 1).map(
+                |(hash, mut paths)| {
+                    paths.sort();
+                    DuplicateFiles { size, hash, paths }
+                },
+            ));
+        }
+        duplicates.sort_by(|group1, group2| {
+            group2
+                .size
+                .cmp(&group1.size)
+                .then_with(|| group1.paths.cmp(&group2.paths))
+        });
+        Ok(duplicates)
+    }
+}
+
+/// Add the files under `dir` to `files`, their relative paths prefixed with `prefix`
+fn list_files(
+    dir: &Path,
+    prefix: &str,
+    options: &ListOptions,
+    ignored: Option<&IgnoredEntries>,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<ListedFile>,
+) -> io::Result<()> {
+    // Symlinks may lead back to a parent directory
+    if !visited.insert(canonical_path::canonicalize(dir)?) {
+        return Ok(());
+    }
+    for entry in listing_cache::read_entries(dir)? {
+        if (!options.show_hidden && entry.name.starts_with('.'))
+            || (options.skip_symlinks && entry.is_symlink)
+        {
+            continue;
+        }
+        let path = dir.join(&entry.name);
+        if options.dir_config
+            && (entry.name == DIR_CONFIG_FILE || (entry.is_dir && dir_config::is_restricted(&path)))
+        {
+            continue;
+        }
+        if ignored.is_some_and(|ignored| ignored.is_ignored(&entry.name, entry.is_dir)) {
+            continue;
+        }
+        let relative_path = format!("{}{}", prefix, entry.name);
+        if entry.is_dir {
+            let prefix = format!("{}/", relative_path);
+            let ignored = ignored.and_then(|ignored| ignored.subdir(&entry.name));
+            list_files(&path, &prefix, options, ignored.as_ref(), visited, files)?;
+        } else {
+            files.push(ListedFile {
+                relative_path,
+                path,
+                size: entry.size,
+                modified: entry.modified,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Line of the manifest for the file at `path`, escaped like `sha256sum` does when it contains a
+/// backslash or a newline
+fn manifest_line(hash: &str, path: &str) -> String {
+    if path.contains(['\\', '\n']) {
+        let path = path.replace('\\', "\\\\").replace('\n', "\\n");
+        format!("\\{}  {}\n", hash, path)
+    } else {
+        format!("{}  {}\n", hash, path)
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[rstest]
+    fn manifest_lists_files_recursively() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub/deep")).unwrap();
+        std::fs::write(dir.path().join("b.txt"), "hello").unwrap();
+        std::fs::write(dir.path().join("sub/deep/a.txt"), "hello").unwrap();
+        std::fs::write(dir.path().join(".hidden"), "hello").unwrap();
+
+        let manifest = ManifestCache::default().manifest(dir.path(), false, false, false, None).unwrap();
+        assert_eq!(
+            manifest,
+            format!("{hash}  b.txt\n{hash}  sub/deep/a.txt\n", hash = HELLO_SHA256)
+        );
+    }
+
+    #[rstest]
+    fn changed_files_are_hashed_again() {
+        let dir = TempDir::new().unwrap();
+        let cache = ManifestCache::default();
+        std::fs::write(dir.path().join("a.txt"), "hello").unwrap();
+        assert!(cache.manifest(dir.path(), false, false, false, None).unwrap().starts_with(HELLO_SHA256));
+
+        std::fs::write(dir.path().join("a.txt"), "hello, world").unwrap();
+        assert!(!cache.manifest(dir.path(), false, false, false, None).unwrap().starts_with(HELLO_SHA256));
+    }
+
+    #[rstest]
+    fn duplicates_are_grouped_by_content() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "hello").unwrap();
+        std::fs::write(dir.path().join("sub/copy of a.txt"), "hello").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "world").unwrap();
+        std::fs::write(dir.path().join("c.txt"), "hello, world").unwrap();
+        std::fs::write(dir.path().join("d.txt"), "hello, world").unwrap();
+        std::fs::write(dir.path().join("empty1"), "").unwrap();
+        std::fs::write(dir.path().join("empty2"), "").unwrap();
+
+        let duplicates = ManifestCache::default().duplicates(dir.path(), false, false, false, None).unwrap();
+        assert_eq!(
+            duplicates.iter().map(|group| (group.size, group.paths.clone())).collect::<Vec<_>>(),
+            vec![
+                (12, vec!["c.txt".to_string(), "d.txt".to_string()]),
+                (5, vec!["a.txt".to_string(), "sub/copy of a.txt".to_string()]),
+            ]
+        );
+        assert_eq!(duplicates[1].hash, HELLO_SHA256);
+    }
+
+    #[rstest(path, expected,
+        case("a b.txt", "abc  a b.txt\n"),
+        case("a\\b.txt", "\\abc  a\\\\b.txt\n"),
+        case("a\nb.txt", "\\abc  a\\nb.txt\n")
+    )]
+    fn special_paths_are_escaped(path: &str, expected: &str) {
+        assert_eq!(manifest_line("abc", path), expected);
+    }
+}
@@ -0,0 +1,296 @@
+//! Small files kept in memory with `--memory-cache`, like the icons, stylesheets or tiny JSON
+//! files of a static site, so that hot paths don't open and read them on every request.
+//!
+//! The least recently used files are evicted when the cache is full. A cached file is served as
+//! is for a second, after which its modification time and size are checked again.
+use actix_web::dev::RequestHead;
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::http::Method;
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::mime_types::is_inline;
+use crate::storage::requested_file;
+use crate::MiniserveConfig;
+
+/// Largest file kept in memory, in bytes
+const MAX_CACHED_FILE_SIZE: u64 = 256 * 1024;
+
+/// How long a cached file is served without checking that it didn't change
+const REVALIDATION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Content of a file, along with what tells whether it is still current
+#[derive(Clone)]
+pub struct CachedFile {
+    path: PathBuf,
+    content: Bytes,
+    modified: SystemTime,
+
+    /// Same ETag as the one of actix-files, so that it doesn't change when the file is cached
+    etag: String,
+}
+
+struct CacheEntry {
+    file: CachedFile,
+
+    /// Last time the metadata of the file was checked
+    checked: Instant,
+
+    /// Value of `Entries::clock` when the file was last requested
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    files: HashMap<PathBuf, CacheEntry>,
+
+    /// Total size of the cached files
+    size: u64,
+
+    /// Incremented on each request, to find the least recently used file
+    clock: u64,
+}
+
+/// Files kept in memory, within a total size
+pub struct MemoryCache {
+    capacity: u64,
+    entries: Mutex<Entries>,
+}
+
+impl MemoryCache {
+    pub fn new(capacity: u64) -> Self {
+        MemoryCache {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Largest file which can be cached
+    fn max_file_size(&self) -> u64 {
+        MAX_CACHED_FILE_SIZE.min(self.capacity)
+    }
+
+    /// Current content of the file at `path`, read again if it changed or wasn't cached yet,
+    /// unless it is too large to be cached
+    fn get(&self, path: &Path) -> Option<CachedFile> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        if let Some(entry) = entries.files.get_mut(path) {
+            if entry.checked.elapsed() < REVALIDATION_INTERVAL {
+                entry.last_used = clock;
+                return Some(entry.file.clone());
+            }
+        }
+
+        let metadata = std::fs::metadata(path).ok();
+        let metadata = match metadata {
+            Some(metadata) if metadata.is_file() && metadata.len() <= self.max_file_size() => {
+                metadata
+            }
+            _ => {
+                entries.remove(path);
+                return None;
+            }
+        };
+        let modified = metadata.modified().ok()?;
+
+        if let Some(entry) = entries.files.get_mut(path) {
+            if entry.file.modified == modified && entry.file.content.len() as u64 == metadata.len()
+            {
+                entry.checked = Instant::now();
+                entry.last_used = clock;
+                return Some(entry.file.clone());
+            }
+        }
+        entries.remove(path);
+
+        let content = Bytes::from(std::fs::read(path).ok()?);
+        let file = CachedFile {
+            path: path.to_path_buf(),
+            etag: etag(&metadata, modified, content.len() as u64),
+            content,
+            modified,
+        };
+        entries.size += file.content.len() as u64;
+        entries.files.insert(
+            path.to_path_buf(),
+            CacheEntry {
+                file: file.clone(),
+                checked: Instant::now(),
+                last_used: clock,
+            },
+        );
+        while entries.size > self.capacity {
+            let least_recently_used = entries
+                .files
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            match least_recently_used {
+                Some(path) => entries.remove(&path),
+                None => break,
+            }
+        }
+        Some(file)
+    }
+}
+
+impl Entries {
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.files.remove(path) {
+            self.size -= entry.file.content.len() as u64;
+        }
+    }
+}
+
+/// ETag of a file, in the format of actix-files
+fn etag(metadata: &std::fs::Metadata, modified: SystemTime, len: u64) -> String {
+    #[cfg(unix)]
+    let ino = std::os::unix::fs::MetadataExt::ino(metadata);
+    #[cfg(not(unix))]
+    let ino = {
+        let _ = metadata;
+        0
+    };
+    let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!(
+        "\"{:x}:{:x}:{:x}:{:x}\"",
+        ino,
+        len,
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos()
+    )
+}
+
+/// Whether the file requested by `head` can be served from the cache, in which case it is kept in
+/// the extensions of the request for `cached_file`
+pub fn find_cached(conf: &MiniserveConfig, head: &RequestHead) -> bool {
+    let cache = match &conf.memory_cache {
+        Some(cache) => cache,
+        None => return false,
+    };
+    // Ranges are left to actix-files
+    if (head.method != Method::GET && head.method != Method::HEAD)
+        || head.headers.contains_key(header::RANGE)
+    {
+        return false;
+    }
+
+    let file = requested_file(conf, head.uri.path()).and_then(|path| cache.get(&path));
+    match file {
+        Some(file) => {
+            head.extensions_mut().insert(file);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Whether the copy of the file cached by the client, as described by the conditional headers of
+/// `req`, is still the current one
+fn is_not_modified(req: &HttpRequest, file: &CachedFile) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since
+    if let Some(if_none_match) = req.headers().get(header::IF_NONE_MATCH) {
+        return if_none_match
+            .to_str()
+            .unwrap_or_default()
+            .split(',')
+            .map(|candidate| candidate.trim().trim_start_matches("W/"))
+            .any(|candidate| candidate == "*" || candidate == file.etag);
+    }
+
+    req.headers()
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|since| since.to_str().ok())
+        .and_then(|since| chrono::DateTime::parse_from_rfc2822(since).ok())
+        .is_some_and(|since| {
+            // The header only has a precision of one second
+            file.modified
+                .duration_since(UNIX_EPOCH)
+                .is_ok_and(|modified| modified.as_secs() as i64 <= since.timestamp())
+        })
+}
+
+/// Send the file found by `find_cached`
+pub async fn cached_file(req: HttpRequest) -> HttpResponse {
+    let file = req.extensions().get::<CachedFile>().cloned();
+    let file = match file {
+        Some(file) => file,
+        None => return crate::error_404(req).await,
+    };
+
+    let last_modified = chrono::DateTime::<chrono::Utc>::from(file.modified)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    let not_modified = is_not_modified(&req, &file);
+    let mut res = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    res.insert_header((header::ETAG, file.etag.clone()))
+        .insert_header((header::LAST_MODIFIED, last_modified));
+    if not_modified {
+        return res.finish();
+    }
+
+    let content_type = mime_guess::from_path(&file.path).first_or_octet_stream();
+    let disposition = ContentDisposition {
+        disposition: if is_inline(&content_type) {
+            DispositionType::Inline
+        } else {
+            DispositionType::Attachment
+        },
+        parameters: vec![DispositionParam::Filename(
+            file.path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        )],
+    };
+    res.insert_header(disposition)
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .content_type(content_type.as_ref())
+        .body(file.content)
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn least_recently_used_files_are_evicted() {
+        let dir = std::env::temp_dir().join(format!("miniserve-memory-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in &["a", "b", "c"] {
+            std::fs::write(dir.join(name), vec![0u8; 10]).unwrap();
+        }
+
+        let cache = MemoryCache::new(25);
+        assert!(cache.get(&dir.join("a")).is_some());
+        assert!(cache.get(&dir.join("b")).is_some());
+        assert!(cache.get(&dir.join("a")).is_some());
+        assert!(cache.get(&dir.join("c")).is_some());
+        {
+            let entries = cache.entries.lock().unwrap();
+            assert_eq!(entries.size, 20);
+            assert!(entries.files.contains_key(&dir.join("a")));
+            assert!(!entries.files.contains_key(&dir.join("b")));
+        }
+
+        std::fs::write(dir.join("big"), vec![0u8; 30]).unwrap();
+        assert!(cache.get(&dir.join("big")).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
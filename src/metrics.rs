@@ -0,0 +1,219 @@
+//! Metrics of the server, exposed in the Prometheus text format with `--enable-metrics`.
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{Extensions, Service, ServiceRequest, ServiceResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures::future::Either;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::archive::ArchiveMethod;
+use crate::webhook::{Change, ChangeAction};
+
+/// Counters updated while the server is running
+#[derive(Default)]
+pub struct Metrics {
+    /// Number of responses sent, by status code
+    responses: Mutex<BTreeMap<u16, u64>>,
+
+    /// Size of the response bodies whose size was known
+    bytes_served: AtomicU64,
+
+    active_connections: AtomicU64,
+
+    uploads: AtomicU64,
+    uploaded_bytes: AtomicU64,
+
+    /// Number of archives created and total time spent creating them, by archive method
+    archives: Mutex<BTreeMap<String, (u64, Duration)>>,
+}
+
+/// Stored in the data of every connection, so that the connection is counted until it is closed
+struct ConnectionGuard(web::Data<Metrics>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    /// Count a new connection until its data is dropped
+    pub fn track_connection(metrics: &web::Data<Metrics>, data: &mut Extensions) {
+        metrics.active_connections.fetch_add(1, Ordering::Relaxed);
+        data.insert(ConnectionGuard(metrics.clone()));
+    }
+
+    /// Record a response sent with the given status, and its size if it is known
+    fn record_response(&self, status: u16, bytes: Option<u64>) {
+        let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        *responses.entry(status).or_insert(0) += 1;
+        if let Some(bytes) = bytes {
+            self.bytes_served.fetch_add(bytes, Ordering::Relaxed);
+        }
+    }
+
+    /// Record the files written by uploads among `changes`
+    pub fn record_changes(&self, changes: &[Change]) {
+        for change in changes {
+            if change.action == ChangeAction::Upload {
+                self.uploads.fetch_add(1, Ordering::Relaxed);
+                self.uploaded_bytes
+                    .fetch_add(change.size, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Record the creation of an archive which took `duration`
+    pub fn record_archive(&self, method: ArchiveMethod, duration: Duration) {
+        let mut archives = self.archives.lock().unwrap_or_else(|e| e.into_inner());
+        let (count, total) = archives.entry(method.to_string()).or_default();
+        *count += 1;
+        *total += duration;
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP miniserve_responses_total Number of responses sent, by status code.\n",
+        );
+        out.push_str("# TYPE miniserve_responses_total counter\n");
+        let responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        for (status, count) in responses.iter() {
+            let _ = writeln!(
+                out,
+                "miniserve_responses_total{{status=\"{}\"}} {}",
+                status, count
+            );
+        }
+        drop(responses);
+
+        for (name, kind, help, value) in &[
+            (
+                "miniserve_served_bytes_total",
+                "counter",
+                "Size of the response bodies sent.",
+                &self.bytes_served,
+            ),
+            (
+                "miniserve_active_connections",
+                "gauge",
+                "Number of open connections.",
+                &self.active_connections,
+            ),
+            (
+                "miniserve_uploads_total",
+                "counter",
+                "Number of uploaded files.",
+                &self.uploads,
+            ),
+            (
+                "miniserve_uploaded_bytes_total",
+                "counter",
+                "Size of the uploaded files.",
+                &self.uploaded_bytes,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+
+        out.push_str("# HELP miniserve_archive_duration_seconds Time spent creating archives, by archive method.\n");
+        out.push_str("# TYPE miniserve_archive_duration_seconds summary\n");
+        let archives = self.archives.lock().unwrap_or_else(|e| e.into_inner());
+        for (method, (count, total)) in archives.iter() {
+            let _ = writeln!(
+                out,
+                "miniserve_archive_duration_seconds_sum{{method=\"{}\"}} {}",
+                method,
+                total.as_secs_f64()
+            );
+            let _ = writeln!(
+                out,
+                "miniserve_archive_duration_seconds_count{{method=\"{}\"}} {}",
+                method, count
+            );
+        }
+
+        out
+    }
+}
+
+/// Count every response once it was sent, if metrics are enabled
+pub fn record_request<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    if !conf.metrics_enabled {
+        return Either::Left(srv.call(req));
+    }
+
+    let metrics = req.app_data::<web::Data<Metrics>>().unwrap().clone();
+    let fut = srv.call(req);
+
+    Either::Right(async move {
+        let result = fut.await;
+        match &result {
+            Ok(res) => {
+                let bytes = match res.response().body().size() {
+                    BodySize::Sized(size) => Some(size),
+                    _ => None,
+                };
+                metrics.record_response(res.status().as_u16(), bytes);
+            }
+            Err(e) => metrics.record_response(e.as_response_error().status_code().as_u16(), None),
+        }
+        result
+    })
+}
+
+/// Handle the metrics route
+pub async fn metrics(req: HttpRequest) -> HttpResponse {
+    let metrics = req.app_data::<web::Data<Metrics>>().unwrap();
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(metrics.render())
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::path::PathBuf;
+
+    fn upload(size: u64) -> Change {
+        Change { action: ChangeAction::Upload, path: PathBuf::from("/file"), previous_path: None, size, extracted_files: Vec::new() }
+    }
+
+    #[rstest]
+    fn metrics_are_rendered() {
+        let metrics = Metrics::default();
+        metrics.record_response(200, Some(10));
+        metrics.record_response(200, None);
+        metrics.record_response(404, Some(5));
+        metrics.record_changes(&[upload(3), upload(4)]);
+        metrics.record_archive(ArchiveMethod::TarGz, Duration::from_millis(1500));
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("miniserve_responses_total{status=\"200\"} 2\n"));
+        assert!(rendered.contains("miniserve_responses_total{status=\"404\"} 1\n"));
+        assert!(rendered.contains("miniserve_served_bytes_total 15\n"));
+        assert!(rendered.contains("miniserve_active_connections 0\n"));
+        assert!(rendered.contains("miniserve_uploads_total 2\n"));
+        assert!(rendered.contains("miniserve_uploaded_bytes_total 7\n"));
+        assert!(rendered.contains("miniserve_archive_duration_seconds_sum{method=\"tar_gz\"} 1.5\n"));
+        assert!(rendered.contains("miniserve_archive_duration_seconds_count{method=\"tar_gz\"} 1\n"));
+    }
+}
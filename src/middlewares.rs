@@ -0,0 +1,113 @@
+//! Chains of middlewares, which are added to the app as a single one.
+//!
+//! `App::wrap_fn` nests the type of every middleware in the type of the next one, which makes the
+//! compilation of long chains of middlewares take exponentially more time and memory. The
+//! middlewares of a `Middlewares` chain are rather given the next one as a `BoxedService`, so
+//! that their types stay the same whatever their number.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::future::Future;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+/// Future of the response of a middleware
+type ResponseFuture = LocalBoxFuture<'static, actix_web::Result<ServiceResponse>>;
+
+/// Service given to the middlewares of a chain, which is either the next middleware or the app
+pub type BoxedService = Rc<
+    dyn Service<
+        ServiceRequest,
+        Response = ServiceResponse,
+        Error = actix_web::Error,
+        Future = ResponseFuture,
+    >,
+>;
+
+/// Middleware of a chain, with the same signature as the ones given to `App::wrap_fn`
+type Middleware = Rc<dyn Fn(ServiceRequest, &BoxedService) -> ResponseFuture>;
+
+/// Chain of middlewares, which are called in the reverse order they are added in, like the ones
+/// added to the app one by one
+#[derive(Clone, Default)]
+pub struct Middlewares(Vec<Middleware>);
+
+impl Middlewares {
+    pub fn new() -> Self {
+        Middlewares::default()
+    }
+
+    /// Add `middleware` around the ones already in the chain
+    pub fn wrap_fn<F, R>(mut self, middleware: F) -> Self
+    where
+        F: Fn(ServiceRequest, &BoxedService) -> R + 'static,
+        R: Future<Output = actix_web::Result<ServiceResponse>> + 'static,
+    {
+        self.0
+            .push(Rc::new(move |req, srv| Box::pin(middleware(req, srv))));
+        self
+    }
+}
+
+/// Service calling a middleware of the chain with the service following it
+struct Link {
+    middleware: Middleware,
+    next: BoxedService,
+}
+
+impl Service<ServiceRequest> for Link {
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.next.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        (self.middleware)(req, &self.next)
+    }
+}
+
+/// Service wrapped by the chain, with its futures boxed
+struct Wrapped<S>(S);
+
+impl<S> Service<ServiceRequest> for Wrapped<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        Box::pin(self.0.call(req))
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for Middlewares
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    type Transform = BoxedService;
+    type InitError = ();
+    type Future = Ready<Result<BoxedService, ()>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let app: BoxedService = Rc::new(Wrapped(service));
+        let chain = self.0.iter().fold(app, |next, middleware| {
+            Rc::new(Link {
+                middleware: middleware.clone(),
+                next,
+            })
+        });
+        ready(Ok(chain))
+    }
+}
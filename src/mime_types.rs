@@ -0,0 +1,145 @@
+//! MIME types of file extensions given with `--mime-type` or `--mime-types-file`, sent instead of
+//! the guessed ones, e.g. for firmware, 3D printing or scientific formats.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use futures::future::TryFutureExt;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+
+use crate::errors::ContextualError;
+use crate::MiniserveConfig;
+
+/// Parse a mapping like `wasm=application/wasm`, into the lowercase extension and its MIME type
+pub fn parse_mime_type(src: &str) -> Result<(String, mime::Mime), ContextualError> {
+    let parse_error =
+        |cause: &str| ContextualError::ParseError(format!("MIME type {}", src), cause.to_string());
+
+    let (extension, mime) = src
+        .split_once('=')
+        .ok_or_else(|| parse_error("expected <extension>=<type>"))?;
+    let extension = extension.trim().trim_start_matches('.').to_lowercase();
+    if extension.is_empty() {
+        return Err(parse_error("empty extension"));
+    }
+    let mime = mime
+        .trim()
+        .parse::<mime::Mime>()
+        .map_err(|e| parse_error(&e.to_string()))?;
+    Ok((extension, mime))
+}
+
+/// Parse the content of a file in the format of /etc/mime.types: a MIME type followed by its
+/// extensions on each line, and comments starting with `#`
+pub fn parse_mime_types_file(
+    content: &str,
+) -> Result<HashMap<String, mime::Mime>, ContextualError> {
+    let mut mime_types = HashMap::new();
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        let mime = match fields.next() {
+            Some(mime) => mime.parse::<mime::Mime>().map_err(|e| {
+                ContextualError::ParseError(format!("MIME type {}", mime), e.to_string())
+            })?,
+            None => continue,
+        };
+        for extension in fields {
+            mime_types.insert(
+                extension.trim_start_matches('.').to_lowercase(),
+                mime.clone(),
+            );
+        }
+    }
+    Ok(mime_types)
+}
+
+/// Whether files of the MIME type `mime` are displayed by browsers rather than downloaded, like
+/// actix-files does
+pub fn is_inline(mime: &mime::Mime) -> bool {
+    matches!(
+        (mime.type_(), mime.subtype()),
+        (mime::TEXT, _)
+            | (mime::IMAGE, _)
+            | (mime::VIDEO, _)
+            | (mime::APPLICATION, mime::JAVASCRIPT)
+            | (mime::APPLICATION, mime::JSON)
+    )
+}
+
+/// Send the MIME types of `--mime-type` instead of the guessed ones, and display the files whose
+/// type became displayable instead of downloading them
+pub fn set_content_type<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    // Listings end with a slash, so they have no extension
+    let name = req.path().rsplit('/').next().unwrap_or_default();
+    let mime = Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .and_then(|extension| conf.mime_types.get(&extension))
+        .cloned();
+
+    srv.call(req).map_ok(move |mut res| {
+        let served = matches!(res.status(), StatusCode::OK | StatusCode::PARTIAL_CONTENT);
+        if let (Some(mime), true) = (mime, served) {
+            if let Ok(content_type) = HeaderValue::from_str(mime.as_ref()) {
+                res.headers_mut().insert(header::CONTENT_TYPE, content_type);
+            }
+            let disposition = res
+                .headers()
+                .get(header::CONTENT_DISPOSITION)
+                .and_then(|disposition| disposition.to_str().ok())
+                .and_then(|disposition| disposition.strip_prefix("attachment"))
+                .map(|parameters| format!("inline{}", parameters));
+            if let (Some(disposition), true) = (disposition, is_inline(&mime)) {
+                if let Ok(disposition) = HeaderValue::from_str(&disposition) {
+                    res.headers_mut()
+                        .insert(header::CONTENT_DISPOSITION, disposition);
+                }
+            }
+        }
+        res
+    })
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(src, extension, mime,
+        case("wasm=application/wasm", "wasm", "application/wasm"),
+        case(".GCODE = text/plain", "gcode", "text/plain")
+    )]
+    fn mime_type_is_parsed(src: &str, extension: &str, mime: &str) {
+        assert_eq!(parse_mime_type(src).unwrap(), (extension.to_string(), mime.parse().unwrap()));
+    }
+
+    #[rstest(src,
+        case("application/wasm"),
+        case("=text/plain"),
+        case("wasm=wasm")
+    )]
+    fn invalid_mime_types_are_rejected(src: &str) {
+        assert!(parse_mime_type(src).is_err());
+    }
+
+    #[rstest]
+    fn mime_types_file_is_parsed() {
+        let content = "# comment\napplication/wasm wasm\n\ntext/plain gcode nc # G-code\n";
+        let mime_types = parse_mime_types_file(content).unwrap();
+        assert_eq!(mime_types.len(), 3);
+        assert_eq!(mime_types["wasm"], "application/wasm".parse::<mime::Mime>().unwrap());
+        assert_eq!(mime_types["nc"], mime::TEXT_PLAIN);
+    }
+}
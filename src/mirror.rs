@@ -0,0 +1,190 @@
+//! Pull-through mirror of an upstream HTTP server with `--mirror-upstream`, for package mirrors
+//! and artifact caches.
+//!
+//! The requests of files missing from the served directory are forwarded to the same path on the
+//! upstream server. The files it sends are stored in the served directory, then served like the
+//! other files, so that the next requests don't reach the upstream server.
+use actix_files::NamedFile;
+use actix_web::dev::RequestHead;
+use actix_web::http::{Method, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use futures::TryStreamExt;
+use percent_encoding::percent_decode_str;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::canonical_path;
+use crate::errors::{self, ContextualError};
+use crate::storage::normalize;
+use crate::MiniserveConfig;
+
+/// Path the file requested at the URL path `uri_path` is stored at, along with the path of its
+/// URL relative to the served directory, which is still percent-encoded. Directories, hidden
+/// files and the files reached through a symlink while these aren't served are never mirrored.
+fn mirrored_file<'a>(conf: &MiniserveConfig, uri_path: &'a str) -> Option<(PathBuf, &'a str)> {
+    let route = format!("/{}", conf.random_route.clone().unwrap_or_default());
+    let encoded_path = uri_path
+        .strip_prefix(route.trim_end_matches('/'))?
+        .trim_start_matches('/');
+    if encoded_path.is_empty() || encoded_path.ends_with('/') {
+        return None;
+    }
+    let decoded_path = percent_decode_str(encoded_path).decode_utf8().ok()?;
+    let relative_path = normalize(Path::new(&*decoded_path))?;
+    let is_hidden = relative_path
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'));
+    if is_hidden && !conf.show_hidden {
+        return None;
+    }
+
+    if conf.no_symlinks {
+        // The file doesn't exist yet, so its closest existing ancestor is checked instead
+        let root = canonical_path::canonicalize(&conf.path).ok()?;
+        let ancestor = relative_path
+            .ancestors()
+            .find(|ancestor| conf.path.join(ancestor).exists())?;
+        if canonical_path::canonicalize(conf.path.join(ancestor)).ok()?
+            != canonical_path::join(&root, ancestor)
+        {
+            return None;
+        }
+    }
+    Some((conf.path.join(relative_path), encoded_path))
+}
+
+/// Guard for the requests of files missing from the served directory, which are fetched from the
+/// upstream server
+pub fn is_mirror_request(conf: &MiniserveConfig, head: &RequestHead) -> bool {
+    (head.method == Method::GET || head.method == Method::HEAD)
+        && conf.mirror_upstream.is_some()
+        && conf.storage.is_none()
+        && mirrored_file(conf, head.uri.path()).is_some_and(|(path, _)| !path.exists())
+}
+
+/// Fetch the requested file from the upstream server, store it, then serve it
+pub async fn mirror_file(req: HttpRequest) -> HttpResponse {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let (upstream, (path, encoded_path)) =
+        match (&conf.mirror_upstream, mirrored_file(conf, req.path())) {
+            (Some(upstream), Some(file)) => (upstream, file),
+            _ => return crate::error_404(req).await,
+        };
+    let url = format!(
+        "{}/{}",
+        upstream.to_string().trim_end_matches('/'),
+        encoded_path
+    );
+
+    match fetch(&url, &path).await {
+        Ok(true) => (),
+        Ok(false) => return crate::error_404(req).await,
+        Err(err) => {
+            errors::log_error_chain(err.to_string());
+            return HttpResponse::BadGateway()
+                .content_type("text/plain; charset=utf-8")
+                .body(err.to_string());
+        }
+    }
+    log::info!("Mirrored {} to {}", url, path.display());
+
+    match NamedFile::open(&path) {
+        Ok(file) => file.prefer_utf8(true).respond_to(&req),
+        Err(_) => crate::error_404(req).await,
+    }
+}
+
+/// Download the file at `url` to `path`, unless the upstream server doesn't have it. The file is
+/// written next to `path` under a hidden name until it is complete, so that the concurrent
+/// requests of the same file never see it partially written.
+async fn fetch(url: &str, path: &Path) -> Result<bool, ContextualError> {
+    let mirror_error = |e: &dyn std::fmt::Display| {
+        ContextualError::MirrorError(format!("Failed to fetch {}: {}", url, e))
+    };
+    let response = awc::Client::new()
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| mirror_error(&e))?;
+    match response.status() {
+        StatusCode::OK => (),
+        StatusCode::NOT_FOUND | StatusCode::GONE => return Ok(false),
+        status => return Err(mirror_error(&status)),
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            ContextualError::IoError(format!("Failed to create {}", dir.display()), e)
+        })?;
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.{}.part", name, nanoid::nanoid!(6)));
+    let written = write_response(response, &temp_path).await;
+    if let Err(err) = written.and_then(|()| {
+        std::fs::rename(&temp_path, path)
+            .map_err(|e| ContextualError::IoError(format!("Failed to store {}", path.display()), e))
+    }) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    Ok(true)
+}
+
+/// Write the body of `response` to the file at `path`
+async fn write_response<S>(mut response: S, path: &Path) -> Result<(), ContextualError>
+where
+    S: futures::Stream<Item = Result<actix_web::web::Bytes, awc::error::PayloadError>> + Unpin,
+{
+    let mut file = File::create(path)
+        .map_err(|e| ContextualError::IoError(format!("Failed to create {}", path.display()), e))?;
+    while let Some(bytes) = response
+        .try_next()
+        .await
+        .map_err(|e| ContextualError::MirrorError(e.to_string()))?
+    {
+        file.write_all(&bytes)
+            .map_err(|e| ContextualError::IoError("Failed to write to file".to_string(), e))?;
+    }
+    Ok(())
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::CliArgs;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+    use structopt::StructOpt;
+
+    fn config(dir: &TempDir, args: &[&str]) -> MiniserveConfig {
+        let mut argv = vec!["miniserve", "--mirror-upstream", "http://localhost:1"];
+        argv.extend_from_slice(args);
+        argv.push(dir.path().to_str().unwrap());
+        MiniserveConfig::try_from_args(CliArgs::from_iter(argv)).unwrap()
+    }
+
+    #[rstest(uri_path, expected,
+        case("/pool/main/a.deb", Some("pool/main/a.deb")),
+        case("/pool/main%20b/a%2Bb.deb", Some("pool/main%20b/a%2Bb.deb")),
+        case("/pool/main/", None),
+        case("/", None),
+        case("/pool/.hidden", None),
+        case("/pool/../a.deb", None)
+    )]
+    fn requested_files_are_mirrored(uri_path: &str, expected: Option<&str>) {
+        let dir = TempDir::new().unwrap();
+        let conf = config(&dir, &[]);
+        assert_eq!(mirrored_file(&conf, uri_path).map(|(_, url_path)| url_path), expected);
+    }
+
+    #[rstest]
+    fn files_are_stored_in_the_served_directory() {
+        let dir = TempDir::new().unwrap();
+        let conf = config(&dir, &[]);
+        let (path, _) = mirrored_file(&conf, "/pool/main%20b/a.deb").unwrap();
+        assert_eq!(path, dir.path().join("pool").join("main b").join("a.deb"));
+    }
+}
@@ -0,0 +1,239 @@
+//! Additional directories served under their own route with `--mount`, e.g. `/docs=/srv/docs`,
+//! or for their own host name with `--vhost`, e.g. `docs.example.com=/srv/docs`.
+//!
+//! Every mount is served with a copy of the main configuration, in which the mount takes the
+//! place of the random route, so that the listing, the uploads and the archives stay below it.
+use std::path::PathBuf;
+use std::str::FromStr;
+use strum::VariantNames;
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+use crate::errors::ContextualError;
+use crate::MiniserveConfig;
+
+/// Setting of a mount, given after `@`, e.g. `/builds=/var/builds@upload,zip`
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum MountOption {
+    /// Allow uploads, like `--upload-files`
+    Upload,
+
+    /// Allow downloading directories as .tar, like `--enable-tar`
+    Tar,
+
+    /// Allow downloading directories as .tar.gz, like `--enable-tar-gz`
+    TarGz,
+
+    /// Allow downloading directories as .zip, like `--enable-zip`
+    Zip,
+
+    /// Show hidden files, like `--hidden`
+    Hidden,
+
+    /// List directories first, like `--dirs-first`
+    DirsFirst,
+}
+
+/// Directory served under a route of its own
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mount {
+    /// Route starting with a slash, without trailing slash
+    pub route: String,
+
+    /// Directory or file to serve
+    pub path: PathBuf,
+
+    /// Settings replacing the ones of the main directory, if any are given
+    pub options: Option<Vec<MountOption>>,
+}
+
+impl FromStr for Mount {
+    type Err = ContextualError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let parse_error =
+            |reason: String| ContextualError::ParseError(format!("mount {}", src), reason);
+
+        let (route, path) = src
+            .split_once('=')
+            .ok_or_else(|| parse_error("expected ROUTE=PATH".to_string()))?;
+
+        if !route.starts_with('/') {
+            return Err(parse_error("the route must start with a slash".to_string()));
+        }
+        let route = route.trim_end_matches('/');
+        if route.is_empty() {
+            return Err(parse_error("the route can't be /".to_string()));
+        }
+        if route.starts_with("/__") || route.contains(['{', '}', '?', '#']) {
+            return Err(parse_error(format!("invalid route {}", route)));
+        }
+
+        // Options are only taken as such if they are all valid, so that paths may contain @
+        let options = path.rsplit_once('@').and_then(|(path, options)| {
+            options
+                .split(',')
+                .map(MountOption::from_str)
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+                .map(|options| (path, options))
+        });
+        let (path, options) = match options {
+            Some((path, options)) => (path, Some(options)),
+            None => (path, None),
+        };
+        if path.is_empty() {
+            return Err(parse_error(format!(
+                "missing path, options are {}",
+                MountOption::VARIANTS.join(", ")
+            )));
+        }
+
+        Ok(Mount {
+            route: route.to_string(),
+            path: PathBuf::from(path),
+            options,
+        })
+    }
+}
+
+impl Mount {
+    /// Configuration serving this mount, derived from the configuration `conf` of the main
+    /// directory
+    pub fn config(&self, conf: &MiniserveConfig) -> MiniserveConfig {
+        let route = match &conf.random_route {
+            Some(random_route) => format!("/{}{}", random_route, self.route),
+            None => self.route.clone(),
+        };
+
+        let mut mount_conf = conf.clone();
+        mount_conf.path = self.path.clone();
+        mount_conf.random_route = Some(route[1..].to_string());
+        mount_conf.mount_route = Some(route);
+        mount_conf.storage = None;
+        mount_conf.mounts = vec![];
+        mount_conf.vhosts = vec![];
+
+        // Renaming, editing and extracting work on paths of the main directory
+        mount_conf.rename_enabled = false;
+        mount_conf.edit_enabled = false;
+        mount_conf.extract_enabled = false;
+
+        if let Some(options) = &self.options {
+            mount_conf.file_upload = options.contains(&MountOption::Upload);
+            mount_conf.tar_enabled = options.contains(&MountOption::Tar);
+            mount_conf.tar_gz_enabled = options.contains(&MountOption::TarGz);
+            mount_conf.zip_enabled = options.contains(&MountOption::Zip);
+            mount_conf.show_hidden = options.contains(&MountOption::Hidden);
+            mount_conf.dirs_first = options.contains(&MountOption::DirsFirst);
+        }
+        mount_conf
+    }
+}
+
+/// Directory served instead of the main one to the requests for a host name
+#[derive(Clone, Debug, PartialEq)]
+pub struct VirtualHost {
+    /// Host name, in lowercase and without port
+    pub host: String,
+
+    /// Directory or file to serve
+    pub path: PathBuf,
+}
+
+impl FromStr for VirtualHost {
+    type Err = ContextualError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let parse_error = |reason: &str| {
+            ContextualError::ParseError(format!("virtual host {}", src), reason.to_string())
+        };
+
+        let (host, path) = src
+            .split_once('=')
+            .ok_or_else(|| parse_error("expected HOST=PATH"))?;
+        if host.is_empty() || host.contains([':', '/', '@']) {
+            return Err(parse_error("the host must be a name without port"));
+        }
+        if path.is_empty() {
+            return Err(parse_error("missing path"));
+        }
+
+        Ok(VirtualHost {
+            host: host.to_lowercase(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl VirtualHost {
+    /// Configuration serving this host, derived from the configuration `conf` of the main
+    /// directory
+    pub fn config(&self, conf: &MiniserveConfig) -> MiniserveConfig {
+        let mut vhost_conf = conf.clone();
+        vhost_conf.path = self.path.clone();
+        vhost_conf.storage = None;
+        vhost_conf.mounts = vec![];
+        vhost_conf.vhosts = vec![];
+
+        // Renaming, editing and extracting work on paths of the main directory
+        vhost_conf.rename_enabled = false;
+        vhost_conf.edit_enabled = false;
+        vhost_conf.extract_enabled = false;
+        vhost_conf
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(mount, route, path, options,
+        case("/docs=/srv/docs", "/docs", "/srv/docs", None),
+        case("/docs/=docs", "/docs", "docs", None),
+        case("/a/b=/srv/b@upload,zip", "/a/b", "/srv/b", Some(vec![MountOption::Upload, MountOption::Zip])),
+        case("/mail=/srv/me@example.com", "/mail", "/srv/me@example.com", None)
+    )]
+    fn parse_mount(mount: &str, route: &str, path: &str, options: Option<Vec<MountOption>>) {
+        assert_eq!(
+            mount.parse::<Mount>().unwrap(),
+            Mount { route: route.to_string(), path: PathBuf::from(path), options }
+        );
+    }
+
+    #[rstest(mount,
+        case("/srv/docs"),
+        case("docs=/srv/docs"),
+        case("/=/srv/docs"),
+        case("/__admin=/srv/docs"),
+        case("/{path}=/srv/docs"),
+        case("/docs=")
+    )]
+    fn parse_mount_invalid(mount: &str) {
+        assert!(mount.parse::<Mount>().is_err());
+    }
+
+    #[rstest(vhost, host, path,
+        case("docs.example.com=/srv/docs", "docs.example.com", "/srv/docs"),
+        case("Docs.Example.com=docs", "docs.example.com", "docs")
+    )]
+    fn parse_virtual_host(vhost: &str, host: &str, path: &str) {
+        assert_eq!(
+            vhost.parse::<VirtualHost>().unwrap(),
+            VirtualHost { host: host.to_string(), path: PathBuf::from(path) }
+        );
+    }
+
+    #[rstest(vhost,
+        case("docs.example.com"),
+        case("docs.example.com:8080=/srv/docs"),
+        case("=/srv/docs"),
+        case("docs.example.com=")
+    )]
+    fn parse_virtual_host_invalid(vhost: &str) {
+        assert!(vhost.parse::<VirtualHost>().is_err());
+    }
+}
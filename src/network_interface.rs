@@ -0,0 +1,159 @@
+//! Network interfaces given by name with `--interface`, e.g. eth0, instead of their addresses.
+//!
+//! On Linux, the sockets are bound to the interfaces themselves, so that they keep accepting
+//! connections when the addresses change, e.g. after a DHCP renewal. Elsewhere, the addresses the
+//! interfaces have at startup are bound. In both cases, the addresses are checked regularly so
+//! that the new URLs are printed.
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::MiniserveConfig;
+
+/// How often the addresses of the interfaces are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Whether `address` is an IPv6 link-local address, which can't be bound without its scope
+fn is_ipv6_link_local(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V6(address) => (address.segments()[0] & 0xffc0) == 0xfe80,
+        IpAddr::V4(_) => false,
+    }
+}
+
+/// Addresses of the network interfaces named `names`, in a stable order. Fails if one of them
+/// doesn't exist.
+pub fn interface_addresses(names: &[String]) -> io::Result<Vec<IpAddr>> {
+    let interfaces = if_addrs::get_if_addrs()?;
+    let mut addresses = vec![];
+    for name in names {
+        if !interfaces.iter().any(|interface| &interface.name == name) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No network interface is named {}", name),
+            ));
+        }
+        addresses.extend(
+            interfaces
+                .iter()
+                .filter(|interface| &interface.name == name)
+                .map(|interface| interface.ip())
+                .filter(|address| !is_ipv6_link_local(address)),
+        );
+    }
+    addresses.sort();
+    addresses.dedup();
+    Ok(addresses)
+}
+
+/// Listeners on `port` of the interfaces of `conf`, for each IP version they have an address
+/// of. They accept connections on any address the interfaces have.
+#[cfg(target_os = "linux")]
+pub fn bind_interfaces(
+    conf: &MiniserveConfig,
+    port: u16,
+) -> io::Result<Vec<std::net::TcpListener>> {
+    use socket2::{Domain, Socket, Type};
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    let mut listeners = vec![];
+    for name in &conf.network_interfaces {
+        let addresses = interface_addresses(std::slice::from_ref(name))?;
+        let unspecified = [
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        ];
+        for unspecified in unspecified.iter() {
+            if !addresses
+                .iter()
+                .any(|address| address.is_ipv6() == unspecified.is_ipv6())
+            {
+                continue;
+            }
+            let address = SocketAddr::new(*unspecified, port);
+            let socket = Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+            if unspecified.is_ipv6() {
+                // The IPv4 addresses get their own socket
+                socket.set_only_v6(true)?;
+            }
+            socket.set_reuse_address(true)?;
+            socket.bind_device(Some(name.as_bytes()))?;
+            socket.bind(&address.into())?;
+            socket.listen(1024)?;
+            listeners.push(socket.into());
+        }
+    }
+    Ok(listeners)
+}
+
+/// Print the URLs of the server again whenever the addresses of its interfaces change
+pub async fn watch_addresses(mut conf: MiniserveConfig) {
+    loop {
+        actix_web::rt::time::sleep(WATCH_INTERVAL).await;
+        let addresses = match interface_addresses(&conf.network_interfaces) {
+            Ok(addresses) => addresses,
+            Err(e) => {
+                log::warn!("Failed to look up the addresses of the interfaces: {}", e);
+                continue;
+            }
+        };
+        if addresses == conf.interfaces {
+            continue;
+        }
+
+        conf.interfaces = addresses;
+        let protocol = if conf.tls_rustls_config.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        let route = conf
+            .random_route
+            .as_ref()
+            .map(|route| format!("/{}", route))
+            .unwrap_or_default();
+        let urls = conf
+            .interface_hosts()
+            .iter()
+            .map(|host| format!("{}://{}:{}{}", protocol, host, conf.port, route))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if cfg!(target_os = "linux") {
+            log::info!(
+                "The addresses of {} changed, now serving at {}",
+                conf.network_interfaces.join(", "),
+                urls
+            );
+        } else {
+            log::warn!(
+                "The addresses of {} changed to {}, restart miniserve to listen on them",
+                conf.network_interfaces.join(", "),
+                urls
+            );
+        }
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(address, expected,
+        case("fe80::1", true),
+        case("febf::1", true),
+        case("fec0::1", false),
+        case("2001:db8::1", false),
+        case("169.254.0.1", false)
+    )]
+    fn link_local_addresses_are_recognized(address: &str, expected: bool) {
+        assert_eq!(is_ipv6_link_local(&address.parse().unwrap()), expected);
+    }
+
+    #[rstest]
+    fn unknown_interfaces_are_refused() {
+        assert!(interface_addresses(&["miniserve-missing0".to_string()]).is_err());
+    }
+}
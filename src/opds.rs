@@ -0,0 +1,305 @@
+//! OPDS 1.2 catalog of the ebooks of the served directory with `--enable-opds`, so that ebook
+//! readers like KOReader can browse the directories and download the books directly.
+//!
+//! The catalog of the whole directory is at `/opds`, and the one of a subdirectory at
+//! `/opds?path=/subdirectory`. Subdirectories are navigation entries, and ebooks are acquisition
+//! entries. Other files are left out.
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::dir_config;
+use crate::feed::{encode_path, escape, rfc3339, root_url};
+use crate::ignore::IgnoredEntries;
+use crate::proxy;
+use crate::storage::served_path;
+use crate::MiniserveConfig;
+
+/// Route of the catalog, relative to the route of the served directory
+pub const OPDS_ROUTE: &str = "/opds";
+
+/// Media type of navigation feeds
+const NAVIGATION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+
+/// Extensions of the files listed in the catalog, with their media types
+const EBOOK_TYPES: &[(&str, &str)] = &[
+    ("epub", "application/epub+zip"),
+    ("pdf", "application/pdf"),
+    ("mobi", "application/x-mobipocket-ebook"),
+    ("azw3", "application/vnd.amazon.ebook"),
+    ("fb2", "application/x-fictionbook+xml"),
+    ("djvu", "image/vnd.djvu"),
+    ("cbz", "application/vnd.comicbook+zip"),
+    ("cbr", "application/vnd.comicbook-rar"),
+];
+
+/// Query parameters of the catalog
+#[derive(Deserialize)]
+pub struct OpdsQuery {
+    /// Directory of the catalog, relative to the served directory
+    path: Option<String>,
+}
+
+/// Entry of the catalog
+struct CatalogEntry {
+    name: String,
+
+    /// Media type of the ebook, or `None` for a subdirectory
+    ebook_type: Option<&'static str>,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Media type of the ebook `name`, if it is one
+fn ebook_type(name: &str) -> Option<&'static str> {
+    let extension = Path::new(name).extension()?.to_str()?.to_lowercase();
+    EBOOK_TYPES
+        .iter()
+        .find(|(ebook_extension, _)| *ebook_extension == extension)
+        .map(|(_, media_type)| *media_type)
+}
+
+/// Subdirectories and ebooks of `dir`, the subdirectories first, sorted by name
+fn list_catalog(
+    conf: &MiniserveConfig,
+    dir: &Path,
+    ignored: Option<&IgnoredEntries>,
+) -> io::Result<Vec<CatalogEntry>> {
+    let mut entries = vec![];
+    for entry in dir.read_dir()? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') && !conf.show_hidden {
+            continue;
+        }
+        if entry.file_type()?.is_symlink() && conf.no_symlinks {
+            continue;
+        }
+        // for symlinks, get the metadata of the original file
+        let metadata = match std::fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if ignored.is_some_and(|ignored| ignored.is_ignored(&name, metadata.is_dir())) {
+            continue;
+        }
+
+        let ebook_type = if metadata.is_dir() {
+            None
+        } else {
+            match ebook_type(&name) {
+                Some(ebook_type) if metadata.is_file() => Some(ebook_type),
+                _ => continue,
+            }
+        };
+        entries.push(CatalogEntry {
+            name,
+            ebook_type,
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+    entries.sort_by_key(|entry| (entry.ebook_type.is_some(), entry.name.to_lowercase()));
+    Ok(entries)
+}
+
+/// URL of the catalog of the directory at the relative `path`
+fn catalog_url(root_url: &str, path: &str) -> String {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        format!("{}{}", root_url.trim_end_matches('/'), OPDS_ROUTE)
+    } else {
+        format!(
+            "{}{}?path={}",
+            root_url.trim_end_matches('/'),
+            OPDS_ROUTE,
+            utf8_percent_encode(&format!("/{}", path), NON_ALPHANUMERIC)
+        )
+    }
+}
+
+/// Render the catalog of `entries`, the content of the directory at the relative `path`
+fn render_catalog(title: &str, root_url: &str, path: &str, entries: &[CatalogEntry]) -> String {
+    let path = path.trim_matches('/');
+    let self_url = catalog_url(root_url, path);
+    let updated = entries
+        .iter()
+        .map(|entry| entry.modified)
+        .max()
+        .unwrap_or_else(SystemTime::now);
+
+    let mut catalog = String::new();
+    catalog += "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n";
+    catalog += "<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:opds=\"http://opds-spec.org/2010/catalog\">\n";
+    catalog += &format!("  <title>{}</title>\n", escape(title));
+    catalog += &format!("  <id>{}</id>\n", escape(&self_url));
+    catalog += &format!(
+        "  <link rel=\"self\" href=\"{}\" type=\"{}\"/>\n",
+        escape(&self_url),
+        NAVIGATION_TYPE
+    );
+    catalog += &format!(
+        "  <link rel=\"start\" href=\"{}\" type=\"{}\"/>\n",
+        escape(&catalog_url(root_url, "")),
+        NAVIGATION_TYPE
+    );
+    if !path.is_empty() {
+        let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        catalog += &format!(
+            "  <link rel=\"up\" href=\"{}\" type=\"{}\"/>\n",
+            escape(&catalog_url(root_url, parent)),
+            NAVIGATION_TYPE
+        );
+    }
+    catalog += &format!("  <updated>{}</updated>\n", rfc3339(updated));
+    catalog += &format!("  <author><name>{}</name></author>\n", escape(title));
+    for entry in entries {
+        let entry_path = if path.is_empty() {
+            entry.name.clone()
+        } else {
+            format!("{}/{}", path, entry.name)
+        };
+        catalog += "  <entry>\n";
+        catalog += &format!("    <title>{}</title>\n", escape(&entry.name));
+        catalog += &format!("    <updated>{}</updated>\n", rfc3339(entry.modified));
+        match entry.ebook_type {
+            Some(ebook_type) => {
+                let url = format!("{}{}", root_url, encode_path(&entry_path));
+                catalog += &format!("    <id>{}</id>\n", escape(&url));
+                catalog += &format!(
+                    "    <link rel=\"http://opds-spec.org/acquisition\" href=\"{}\" type=\"{}\" length=\"{}\"/>\n",
+                    escape(&url),
+                    ebook_type,
+                    entry.size
+                );
+            }
+            None => {
+                let url = catalog_url(root_url, &entry_path);
+                catalog += &format!("    <id>{}</id>\n", escape(&url));
+                catalog += &format!(
+                    "    <link rel=\"subsection\" href=\"{}\" type=\"{}\"/>\n",
+                    escape(&url),
+                    NAVIGATION_TYPE
+                );
+            }
+        }
+        catalog += "  </entry>\n";
+    }
+    catalog += "</feed>\n";
+    catalog
+}
+
+/// Serve the catalog of the directory at `query.path`
+pub async fn catalog(req: HttpRequest, query: web::Query<OpdsQuery>) -> HttpResponse {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let relative_path = query.path.clone().unwrap_or_default();
+    let is_ignored = |dir: &Path| {
+        conf.ignore
+            .as_ref()
+            .is_some_and(|ignore| !conf.serve_ignored && ignore.is_ignored(&conf.path, dir))
+    };
+    let dir = match served_path(conf, &relative_path) {
+        Some(dir) if dir.is_dir() && !is_ignored(&dir) => dir,
+        _ => return crate::error_404(req).await,
+    };
+    if let Err(err) = dir_config::check_access(&req, conf, &dir, false) {
+        return dir_config::access_denied_response(&req, err);
+    }
+
+    let list_conf = conf.clone();
+    let entries = web::block(move || {
+        let ignored = list_conf
+            .ignore
+            .as_ref()
+            .and_then(|ignore| ignore.dir(&list_conf.path, &dir));
+        list_catalog(&list_conf, &dir, ignored.as_ref())
+    })
+    .await
+    .unwrap_or_else(|e| Err(io::Error::other(e.to_string())));
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::error!("Failed to list the files of the catalog: {}", e);
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body("Failed to list the files of the catalog");
+        }
+    };
+
+    let host = proxy::host(&req);
+    let relative_dir = relative_path.trim_matches('/');
+    let title = match (&conf.title, relative_dir) {
+        (Some(title), "") => title.clone(),
+        (Some(title), dir) => format!("{} - {}", title, dir),
+        (None, "") => host,
+        (None, dir) => format!("{} - {}", host, dir),
+    };
+
+    HttpResponse::Ok()
+        .content_type(format!("{}; charset=utf-8", NAVIGATION_TYPE))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .body(render_catalog(
+            &title,
+            &root_url(&req, conf),
+            relative_dir,
+            &entries,
+        ))
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(name, expected,
+        case("book.epub", Some("application/epub+zip")),
+        case("Book.PDF", Some("application/pdf")),
+        case("comic.cbz", Some("application/vnd.comicbook+zip")),
+        case("notes.txt", None),
+        case("epub", None)
+    )]
+    fn ebooks_are_recognized(name: &str, expected: Option<&str>) {
+        assert_eq!(ebook_type(name), expected);
+    }
+
+    #[rstest]
+    fn catalog_links_subdirectories_and_ebooks() {
+        let entries = vec![
+            CatalogEntry {
+                name: "Sci-Fi & Fantasy".to_string(),
+                ebook_type: None,
+                size: 0,
+                modified: SystemTime::UNIX_EPOCH,
+            },
+            CatalogEntry {
+                name: "a book.epub".to_string(),
+                ebook_type: Some("application/epub+zip"),
+                size: 3,
+                modified: SystemTime::UNIX_EPOCH,
+            },
+        ];
+        let catalog = render_catalog("Library", "http://localhost/", "/books/", &entries);
+        assert!(catalog.contains("<link rel=\"up\" href=\"http://localhost/opds\""));
+        assert!(catalog.contains(
+            "<link rel=\"subsection\" href=\"http://localhost/opds?path=%2Fbooks%2FSci%2DFi%20%26%20Fantasy\""
+        ));
+        assert!(catalog.contains(
+            "href=\"http://localhost/books/a%20book.epub\" type=\"application/epub+zip\" length=\"3\""
+        ));
+    }
+
+    #[rstest(path, expected,
+        case("", "http://localhost/opds"),
+        case("/", "http://localhost/opds"),
+        case("/books/", "http://localhost/opds?path=%2Fbooks")
+    )]
+    fn catalog_urls(path: &str, expected: &str) {
+        assert_eq!(catalog_url("http://localhost/", path), expected);
+    }
+}
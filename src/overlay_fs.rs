@@ -0,0 +1,199 @@
+//! Several directories served as one read-only tree with `--overlay`, like a union mount.
+//!
+//! The directories are layered in the order they are given, PATH first: an entry is read from the
+//! first directory which has it, and the listings merge the entries of all of them, so that e.g.
+//! generated artifacts and static documentation can be shared together.
+use actix_web::web::Bytes;
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use futures::stream::StreamExt;
+use std::collections::BTreeMap;
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::canonical_path;
+use crate::errors::ContextualError;
+use crate::listing_cache;
+use crate::storage::{ByteStream, Storage, StorageEntry};
+
+/// Directories merged into one tree
+#[derive(Debug)]
+pub struct OverlayFs {
+    /// Layers of the tree, the first ones taking precedence
+    roots: Vec<PathBuf>,
+
+    skip_symlinks: bool,
+}
+
+impl OverlayFs {
+    pub fn new(roots: Vec<PathBuf>, skip_symlinks: bool) -> Result<Self, ContextualError> {
+        if let Some(root) = roots.iter().find(|root| !root.is_dir()) {
+            return Err(ContextualError::InvalidPathError(format!(
+                "{} is not a directory",
+                root.display()
+            )));
+        }
+        Ok(OverlayFs {
+            roots,
+            skip_symlinks,
+        })
+    }
+
+    /// Path and metadata of the entry at `path` in the directory `root`, unless it is reached
+    /// through a symlink while these aren't followed
+    fn resolve(&self, root: &Path, path: &Path) -> Option<(PathBuf, Metadata)> {
+        let full_path = root.join(path);
+        let metadata = fs::metadata(&full_path).ok()?;
+        if !(metadata.is_dir() || metadata.is_file()) {
+            return None;
+        }
+        if self.skip_symlinks
+            && canonical_path::canonicalize(&full_path).ok()?
+                != canonical_path::join(&canonical_path::canonicalize(root).ok()?, path)
+        {
+            return None;
+        }
+        Some((full_path, metadata))
+    }
+
+    /// Path and metadata of the entry at `path` in the first directory which has it
+    fn find(&self, path: &Path) -> Option<(PathBuf, Metadata)> {
+        self.roots.iter().find_map(|root| self.resolve(root, path))
+    }
+
+    /// Entries of the directory `dir` in all the directories, the first ones taking precedence
+    fn merged_entries(&self, dir: &Path) -> io::Result<Vec<StorageEntry>> {
+        let mut entries = BTreeMap::new();
+        for root in &self.roots {
+            let full_dir = match self.resolve(root, dir) {
+                Some((full_dir, metadata)) if metadata.is_dir() => full_dir,
+                _ => continue,
+            };
+            for entry in listing_cache::read_entries(&full_dir)? {
+                if self.skip_symlinks && entry.is_symlink {
+                    continue;
+                }
+                entries
+                    .entry(entry.name.clone())
+                    .or_insert_with(|| StorageEntry {
+                        name: entry.name,
+                        size: if entry.is_dir { None } else { Some(entry.size) },
+                        modified: entry.modified,
+                    });
+            }
+        }
+        Ok(entries.into_values().collect())
+    }
+}
+
+impl Storage for OverlayFs {
+    fn location(&self) -> String {
+        self.roots
+            .iter()
+            .map(|root| {
+                canonical_path::canonicalize(root)
+                    .unwrap_or_else(|_| root.clone())
+                    .display()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+
+    fn entry<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<Option<StorageEntry>, ContextualError>> {
+        let entry = self.find(path).map(|(_, metadata)| StorageEntry {
+            name: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            size: if metadata.is_dir() {
+                None
+            } else {
+                Some(metadata.len())
+            },
+            modified: metadata.modified().ok(),
+        });
+        future::ready(Ok(entry)).boxed_local()
+    }
+
+    fn list<'a>(
+        &'a self,
+        dir: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<Vec<StorageEntry>, ContextualError>> {
+        let entries = self
+            .merged_entries(dir)
+            .map_err(|e| ContextualError::IoError(format!("Failed to list {}", dir.display()), e));
+        future::ready(entries).boxed_local()
+    }
+
+    fn read(
+        self: Arc<Self>,
+        path: PathBuf,
+    ) -> LocalBoxFuture<'static, Result<ByteStream, ContextualError>> {
+        let file = match self.find(&path) {
+            Some((full_path, _)) => File::open(full_path),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "File removed")),
+        };
+        let mut file = match file {
+            Ok(file) => file,
+            Err(e) => {
+                let err = ContextualError::IoError(format!("Failed to read {}", path.display()), e);
+                return future::ready(Err(err)).boxed_local();
+            }
+        };
+
+        // The file is read in a separate thread, like archives are created
+        let (tx, rx) = futures::channel::mpsc::channel::<io::Result<Bytes>>(10);
+        let mut pipe = crate::pipe::Pipe::new(tx);
+        std::thread::spawn(move || {
+            if let Err(err) = io::copy(&mut file, &mut pipe) {
+                log::error!("Error while reading {}: {}", path.display(), err);
+            }
+        });
+        future::ready(Ok(rx.boxed_local())).boxed_local()
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn first_directories_take_precedence() {
+        let (generated, docs) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        std::fs::create_dir(generated.path().join("api")).unwrap();
+        std::fs::write(generated.path().join("api/index.html"), "generated").unwrap();
+        std::fs::write(generated.path().join("readme.txt"), "generated readme").unwrap();
+        std::fs::create_dir(docs.path().join("api")).unwrap();
+        std::fs::write(docs.path().join("api/guide.html"), "guide").unwrap();
+        std::fs::write(docs.path().join("readme.txt"), "readme").unwrap();
+
+        let overlay = OverlayFs::new(vec![generated.path().to_path_buf(), docs.path().to_path_buf()], false).unwrap();
+        let names = |dir: &str| {
+            overlay.merged_entries(Path::new(dir)).unwrap()
+                .into_iter().map(|entry| (entry.name, entry.size)).collect::<Vec<_>>()
+        };
+        assert_eq!(names(""), vec![("api".to_string(), None), ("readme.txt".to_string(), Some(16))]);
+        assert_eq!(
+            names("api"),
+            vec![("guide.html".to_string(), Some(5)), ("index.html".to_string(), Some(9))]
+        );
+        assert_eq!(overlay.find(Path::new("api/guide.html")).unwrap().0, docs.path().join("api/guide.html"));
+    }
+
+    #[rstest]
+    fn overlays_have_to_be_directories() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("file.txt"), "").unwrap();
+        assert!(OverlayFs::new(vec![dir.path().join("file.txt")], false).is_err());
+    }
+}
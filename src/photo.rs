@@ -0,0 +1,195 @@
+//! Capture dates, cameras and resolutions of the photos of the listings, read from their EXIF
+//! metadata with `--photo-metadata`.
+//!
+//! Modification times are often meaningless for photos which were copied around, so the listings
+//! can be sorted and grouped by the date the photos were taken instead.
+use chrono::NaiveDateTime;
+use exif::{In, Tag, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Extensions of the files whose metadata is read
+const PHOTO_EXTENSIONS: &[&str] = &["jpg", "jpeg", "tif", "tiff", "heic", "heif", "png", "webp"];
+
+/// Number of photos whose metadata is kept in memory, after which the cache is cleared
+const MAX_CACHED_PHOTOS: usize = 10_000;
+
+/// Metadata of a photo
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PhotoMetadata {
+    /// Date the photo was taken, in the time zone the camera was set to
+    pub taken: Option<NaiveDateTime>,
+
+    /// Make and model of the camera
+    pub camera: Option<String>,
+
+    /// Width and height, in pixels
+    pub resolution: Option<(u32, u32)>,
+}
+
+impl PhotoMetadata {
+    /// Description of the photo, shown when hovering it in the listings
+    pub fn description(&self) -> String {
+        let mut lines = vec![];
+        if let Some(taken) = self.taken {
+            lines.push(format!("Taken: {}", taken.format("%Y-%m-%d %H:%M:%S")));
+        }
+        if let Some(camera) = &self.camera {
+            lines.push(format!("Camera: {}", camera));
+        }
+        if let Some((width, height)) = self.resolution {
+            lines.push(format!("Resolution: {}×{}", width, height));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Whether the file `name` may be a photo with EXIF metadata
+pub fn is_photo(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| PHOTO_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Parse an EXIF date like `2021:07:14 12:03:00`
+fn parse_exif_date(date: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(date.trim(), "%Y:%m:%d %H:%M:%S").ok()
+}
+
+/// Text of the EXIF field `tag`, if it is set
+fn ascii_field(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).trim().to_string())
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    }
+}
+
+/// Number in the EXIF field `tag`, if it is set
+fn uint_field(exif: &exif::Exif, tag: Tag) -> Option<u32> {
+    exif.get_field(tag, In::PRIMARY)?.value.get_uint(0)
+}
+
+/// Camera described by its `make` and `model`, which often already starts with the make
+fn camera_name(make: Option<String>, model: Option<String>) -> Option<String> {
+    match (make, model) {
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => {
+            Some(model)
+        }
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    }
+}
+
+/// Read the metadata of the photo at `path`, if it has any
+pub fn read_metadata(path: &Path) -> Option<PhotoMetadata> {
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+
+    let taken = [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime]
+        .iter()
+        .find_map(|tag| ascii_field(&exif, *tag).and_then(|date| parse_exif_date(&date)));
+    let camera = camera_name(
+        ascii_field(&exif, Tag::Make),
+        ascii_field(&exif, Tag::Model),
+    );
+    let resolution = match (
+        uint_field(&exif, Tag::PixelXDimension).or_else(|| uint_field(&exif, Tag::ImageWidth)),
+        uint_field(&exif, Tag::PixelYDimension).or_else(|| uint_field(&exif, Tag::ImageLength)),
+    ) {
+        (Some(width), Some(height)) => Some((width, height)),
+        _ => None,
+    };
+
+    Some(PhotoMetadata {
+        taken,
+        camera,
+        resolution,
+    })
+}
+
+/// Modification time of a photo when it was read, and its metadata
+type CachedMetadata = (Option<SystemTime>, Option<PhotoMetadata>);
+
+/// Metadata of the listed photos, so that they aren't read again on each visit. They are read
+/// again when the photos are modified.
+#[derive(Default)]
+pub struct PhotoMetadataCache {
+    photos: Mutex<HashMap<PathBuf, CachedMetadata>>,
+}
+
+impl PhotoMetadataCache {
+    /// Metadata of the photo at `path`, which was last modified at `modified`
+    pub fn get(&self, path: &Path, modified: Option<SystemTime>) -> Option<PhotoMetadata> {
+        {
+            let photos = self.photos.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some((cached_modified, metadata)) = photos.get(path) {
+                if *cached_modified == modified {
+                    return metadata.clone();
+                }
+            }
+        }
+
+        let metadata = read_metadata(path);
+        let mut photos = self.photos.lock().unwrap_or_else(|e| e.into_inner());
+        if photos.len() >= MAX_CACHED_PHOTOS {
+            photos.clear();
+        }
+        photos.insert(path.to_path_buf(), (modified, metadata.clone()));
+        metadata
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(name, expected,
+        case("IMG_0001.JPG", true),
+        case("photo.heic", true),
+        case("notes.txt", false),
+        case("jpg", false)
+    )]
+    fn photos_are_recognized(name: &str, expected: bool) {
+        assert_eq!(is_photo(name), expected);
+    }
+
+    #[rstest(make, model, expected,
+        case(Some("Canon"), Some("Canon EOS 5D"), Some("Canon EOS 5D")),
+        case(Some("FUJIFILM"), Some("X-T3"), Some("FUJIFILM X-T3")),
+        case(None, Some("Pixel 5"), Some("Pixel 5")),
+        case(None, None, None)
+    )]
+    fn cameras_are_named(make: Option<&str>, model: Option<&str>, expected: Option<&str>) {
+        assert_eq!(
+            camera_name(make.map(str::to_string), model.map(str::to_string)).as_deref(),
+            expected
+        );
+    }
+
+    #[rstest]
+    fn metadata_is_described() {
+        let metadata = PhotoMetadata {
+            taken: parse_exif_date("2021:07:14 12:03:00"),
+            camera: Some("Canon EOS 5D".to_string()),
+            resolution: Some((6000, 4000)),
+        };
+        assert_eq!(
+            metadata.description(),
+            "Taken: 2021-07-14 12:03:00\nCamera: Canon EOS 5D\nResolution: 6000×4000"
+        );
+        assert_eq!(parse_exif_date("0000:00:00 00:00:00"), None);
+    }
+}
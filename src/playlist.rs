@@ -0,0 +1,97 @@
+//! M3U playlists of the audio files of a directory, downloaded with `?download=m3u` or
+//! `?download=m3u8`, so that a whole directory can be queued in a player like VLC or mpd.
+//!
+//! The playlists list the absolute URLs of the audio files, in the order of the listing. Both
+//! formats are encoded in UTF-8, which players expect of `.m3u8` files and accept of `.m3u` ones.
+use serde::Deserialize;
+use std::path::Path;
+use strum_macros::{Display, EnumString};
+
+/// Extensions of the files listed in the playlists
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "flac", "ogg", "oga", "opus", "m4a", "aac", "wav", "aif", "aiff", "wma", "ape", "wv",
+    "mka", "mpc",
+];
+
+/// Available playlist formats
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum PlaylistFormat {
+    /// M3U playlist
+    M3u,
+
+    /// M3U playlist, explicitly encoded in UTF-8
+    M3u8,
+}
+
+impl PlaylistFormat {
+    pub fn extension(self) -> String {
+        self.to_string()
+    }
+
+    pub fn content_type(self) -> String {
+        match self {
+            PlaylistFormat::M3u => "audio/x-mpegurl",
+            PlaylistFormat::M3u8 => "audio/x-mpegurl; charset=utf-8",
+        }
+        .to_string()
+    }
+}
+
+/// Whether the file `name` is an audio file which is listed in the playlists
+pub fn is_audio(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| AUDIO_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Render the playlist of `tracks`, the names and absolute URLs of the audio files
+pub fn render_playlist(tracks: &[(&str, String)]) -> String {
+    let mut playlist = String::from("#EXTM3U\n");
+    for (name, url) in tracks {
+        // Players show the title instead of the URL, which has to fit on one line
+        let title = Path::new(name)
+            .file_stem()
+            .map_or_else(
+                || name.to_string(),
+                |stem| stem.to_string_lossy().to_string(),
+            )
+            .replace(|c: char| c.is_control(), " ");
+        playlist += &format!("#EXTINF:-1,{}\n{}\n", title, url);
+    }
+    playlist
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(name, expected,
+        case("01 - Intro.mp3", true),
+        case("Track.FLAC", true),
+        case("cover.jpg", false),
+        case("mp3", false)
+    )]
+    fn audio_files_are_recognized(name: &str, expected: bool) {
+        assert_eq!(is_audio(name), expected);
+    }
+
+    #[rstest]
+    fn playlists_list_titles_and_urls() {
+        let tracks = vec![
+            ("01 - Intro.mp3", "http://localhost:8080/album/01%20-%20Intro.mp3".to_string()),
+            ("02\nOutro.ogg", "http://localhost:8080/album/02%0AOutro.ogg".to_string()),
+        ];
+        assert_eq!(
+            render_playlist(&tracks),
+            "#EXTM3U\n\
+             #EXTINF:-1,01 - Intro\nhttp://localhost:8080/album/01%20-%20Intro.mp3\n\
+             #EXTINF:-1,02 Outro\nhttp://localhost:8080/album/02%0AOutro.ogg\n"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Compressed copies of files, like `app.js.br` or `app.js.gz` next to `app.js`, served instead
+//! of the files themselves with `--precompressed` to the clients accepting their encoding.
+use actix_files::NamedFile;
+use actix_web::dev::RequestHead;
+use actix_web::http::header::{
+    self, ContentDisposition, DispositionParam, DispositionType, HeaderValue,
+};
+use actix_web::http::Method;
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use std::path::PathBuf;
+
+use crate::canonical_path;
+use crate::compress::ClientAcceptEncoding;
+use crate::storage::requested_file;
+use crate::MiniserveConfig;
+
+/// Encoding of a compressed copy
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// Name in the Accept-Encoding and Content-Encoding headers
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Extension appended to the name of the file
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+/// Compressed copy of a requested file
+#[derive(Debug)]
+pub struct Precompressed {
+    /// Requested file
+    original: PathBuf,
+
+    /// File holding the compressed content
+    file: PathBuf,
+
+    encoding: Encoding,
+}
+
+/// Encodings accepted by the `Accept-Encoding` header value `accept`, most efficient first
+fn accepted_encodings(accept: &str) -> Vec<Encoding> {
+    let mut accepted = vec![];
+    let mut refused = vec![];
+    let mut any = false;
+    for coding in accept.split(',') {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or_default().trim().to_lowercase();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|quality| quality.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let target = if quality > 0.0 {
+            &mut accepted
+        } else {
+            &mut refused
+        };
+        match name.as_str() {
+            "br" => target.push(Encoding::Brotli),
+            "gzip" | "x-gzip" => target.push(Encoding::Gzip),
+            "*" => any = quality > 0.0,
+            _ => (),
+        }
+    }
+
+    [Encoding::Brotli, Encoding::Gzip]
+        .iter()
+        .copied()
+        .filter(|encoding| !refused.contains(encoding) && (any || accepted.contains(encoding)))
+        .collect()
+}
+
+/// Compressed copy of the file requested by `head` which can be sent to the client, if any
+pub fn find_precompressed(conf: &MiniserveConfig, head: &RequestHead) -> Option<Precompressed> {
+    if !conf.precompressed || (head.method != Method::GET && head.method != Method::HEAD) {
+        return None;
+    }
+    // The encodings accepted by the client, not only the ones enabled for `--compress`
+    let accept = match head.extensions().get::<ClientAcceptEncoding>() {
+        Some(accept) => accept.0.clone(),
+        None => head
+            .headers
+            .get(header::ACCEPT_ENCODING)?
+            .to_str()
+            .ok()?
+            .to_string(),
+    };
+    let encodings = accepted_encodings(&accept);
+    if encodings.is_empty() {
+        return None;
+    }
+
+    let original = requested_file(conf, head.uri.path())?;
+    if !original.is_file() {
+        return None;
+    }
+    let root = canonical_path::canonicalize(&conf.path).ok()?;
+    let canonical_original = root.join(original.strip_prefix(&conf.path).ok()?);
+
+    encodings.into_iter().find_map(|encoding| {
+        let mut file = original.clone().into_os_string();
+        file.push(".");
+        file.push(encoding.extension());
+        let file = PathBuf::from(file);
+
+        let canonical_file = canonical_path::canonicalize(&file).ok()?;
+        if !canonical_file.is_file() || !canonical_file.starts_with(&root) {
+            return None;
+        }
+        // Without symlinks, the copy may not be reached through one either
+        if conf.no_symlinks
+            && canonical_file != canonical_original.with_file_name(file.file_name()?)
+        {
+            return None;
+        }
+
+        Some(Precompressed {
+            original: original.clone(),
+            file,
+            encoding,
+        })
+    })
+}
+
+/// Send the compressed copy of the requested file
+pub async fn precompressed_file(req: HttpRequest) -> actix_web::Result<HttpResponse> {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let precompressed = match find_precompressed(conf, req.head()) {
+        Some(precompressed) => precompressed,
+        // The copy was removed since the request was routed here
+        None => return Ok(crate::error_404(req).await),
+    };
+
+    let content_type = mime_guess::from_path(&precompressed.original).first_or_octet_stream();
+    let disposition = match (content_type.type_(), content_type.subtype()) {
+        (mime::TEXT, _) | (mime::IMAGE, _) | (mime::VIDEO, _) => DispositionType::Inline,
+        (mime::APPLICATION, mime::JAVASCRIPT) | (mime::APPLICATION, mime::JSON) => {
+            DispositionType::Inline
+        }
+        _ => DispositionType::Attachment,
+    };
+    let filename = precompressed
+        .original
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+
+    let mut res = NamedFile::open(&precompressed.file)?
+        .set_content_type(content_type)
+        .set_content_disposition(ContentDisposition {
+            disposition,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .respond_to(&req);
+    res.headers_mut().insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(precompressed.encoding.name()),
+    );
+    res.headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    Ok(res)
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(accept, expected,
+        case("gzip, deflate, br", vec![Encoding::Brotli, Encoding::Gzip]),
+        case("gzip", vec![Encoding::Gzip]),
+        case("br;q=0, gzip;q=0.5", vec![Encoding::Gzip]),
+        case("*", vec![Encoding::Brotli, Encoding::Gzip]),
+        case("*;q=1, br;q=0", vec![Encoding::Gzip]),
+        case("identity", vec![])
+    )]
+    fn accept_encoding_is_parsed(accept: &str, expected: Vec<Encoding>) {
+        assert_eq!(accepted_encodings(accept), expected);
+    }
+}
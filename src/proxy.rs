@@ -0,0 +1,220 @@
+//! Identity of the clients behind the reverse proxies given with `--trusted-proxy`, taken from the
+//! `Forwarded` header, or from the `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`
+//! headers.
+//!
+//! These headers are ignored in the requests which don't come from a trusted proxy, as any client
+//! could send them.
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::HttpRequest;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::ip_filter::{canonical_ip, IpNet};
+use crate::proxy_protocol::ProxiedClient;
+use crate::MiniserveConfig;
+
+/// Whether `ip` is the address of one of the trusted proxies
+fn is_trusted(trusted_proxies: &[IpNet], ip: IpAddr) -> bool {
+    let ip = canonical_ip(ip);
+    trusted_proxies.iter().any(|net| net.contains(ip))
+}
+
+/// Whether `req` was forwarded by a trusted proxy
+fn is_forwarded_by_trusted_proxy(req: &HttpRequest) -> bool {
+    match (req.app_data::<MiniserveConfig>(), peer_ip(req)) {
+        (Some(conf), Some(peer)) => is_trusted(&conf.trusted_proxies, peer),
+        _ => false,
+    }
+}
+
+/// Remove the quotes around a value of the `Forwarded` header
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Parse a node of the `Forwarded` or `X-Forwarded-For` headers, like `192.0.2.43`,
+/// `192.0.2.43:47011` or `[2001:db8:cafe::17]:4711`. Obfuscated and unknown nodes have no address.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = unquote(node.trim());
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|node| node.strip_suffix(']'))
+                .and_then(|node| node.parse().ok())
+        })
+        .map(canonical_ip)
+}
+
+/// Values of the parameter `name` in the elements of the `Forwarded` headers of `headers`, from
+/// the one added by the first proxy
+fn forwarded_params(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(header::FORWARDED)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+                .map(|(_, value)| unquote(value.trim()).to_string())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Comma separated values of the `X-Forwarded-*` headers named `name` in `headers`
+fn x_forwarded_values(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|value| value.trim().to_string())
+        .collect()
+}
+
+/// Addresses the request went through before reaching the proxy, from the client
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let nodes = if headers.contains_key(header::FORWARDED) {
+        forwarded_params(headers, "for")
+    } else {
+        x_forwarded_values(headers, "x-forwarded-for")
+    };
+    nodes.iter().map(|node| parse_node(node)).collect()
+}
+
+/// Address of the client which sent `ip` the request with `headers`, going back through the
+/// trusted proxies
+fn resolve_client_ip(trusted_proxies: &[IpNet], ip: IpAddr, headers: &HeaderMap) -> IpAddr {
+    let mut client_ip = canonical_ip(ip);
+    if !is_trusted(trusted_proxies, client_ip) {
+        return client_ip;
+    }
+    for node in forwarded_for(headers).into_iter().rev() {
+        match node {
+            Some(ip) => {
+                client_ip = ip;
+                if !is_trusted(trusted_proxies, ip) {
+                    break;
+                }
+            }
+            // The client is hidden behind this proxy
+            None => break,
+        }
+    }
+    client_ip
+}
+
+/// Address of the peer of `req`, as given by the PROXY protocol header if any
+fn peer_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let proxied_client = req.extensions().get::<ProxiedClient>().copied();
+    match proxied_client {
+        Some(ProxiedClient(client)) => Some(client.ip()),
+        None => req.peer_addr().map(|addr| addr.ip()),
+    }
+}
+
+/// IP address of the client which sent `req`, if it came over TCP
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = peer_ip(req)?;
+    match req.app_data::<MiniserveConfig>() {
+        Some(conf) => Some(resolve_client_ip(
+            &conf.trusted_proxies,
+            peer,
+            req.headers(),
+        )),
+        None => Some(peer),
+    }
+}
+
+/// Value given by the proxy which received `req` from the client, with the parameter `name` of
+/// the `Forwarded` header or with the `X-Forwarded-*` header `x_forwarded_name`
+fn forwarded_value(req: &HttpRequest, name: &str, x_forwarded_name: &str) -> Option<String> {
+    if !is_forwarded_by_trusted_proxy(req) {
+        return None;
+    }
+    let values = if req.headers().contains_key(header::FORWARDED) {
+        forwarded_params(req.headers(), name)
+    } else {
+        x_forwarded_values(req.headers(), x_forwarded_name)
+    };
+    values.into_iter().next().filter(|value| !value.is_empty())
+}
+
+/// Scheme of the URL requested by the client, to generate absolute URLs
+pub fn scheme(req: &HttpRequest) -> String {
+    forwarded_value(req, "proto", "x-forwarded-proto")
+        .map(|scheme| scheme.to_lowercase())
+        .unwrap_or_else(|| {
+            if req.app_config().secure() {
+                "https".to_string()
+            } else {
+                "http".to_string()
+            }
+        })
+}
+
+/// Host of the URL requested by the client, to generate absolute URLs
+pub fn host(req: &HttpRequest) -> String {
+    forwarded_value(req, "host", "x-forwarded-host").unwrap_or_else(|| {
+        req.headers()
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| req.uri().authority().map(|authority| authority.as_str()))
+            .unwrap_or_else(|| req.app_config().host())
+            .to_string()
+    })
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderValue;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    fn headers(name: &'static str, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HeaderName::from_static(name), HeaderValue::from_static(value));
+        headers
+    }
+
+    #[rstest(node, expected,
+        case("192.0.2.43", Some("192.0.2.43")),
+        case("\"192.0.2.43:47011\"", Some("192.0.2.43")),
+        case("\"[2001:db8:cafe::17]:4711\"", Some("2001:db8:cafe::17")),
+        case("[2001:db8:cafe::17]", Some("2001:db8:cafe::17")),
+        case("::ffff:192.0.2.43", Some("192.0.2.43")),
+        case("unknown", None),
+        case("_hidden", None)
+    )]
+    fn nodes_are_parsed(node: &str, expected: Option<&str>) {
+        assert_eq!(parse_node(node), expected.map(|ip| ip.parse().unwrap()));
+    }
+
+    #[rstest(peer, name, value, expected,
+        case("127.0.0.1", "x-forwarded-for", "203.0.113.7", "203.0.113.7"),
+        case("127.0.0.1", "x-forwarded-for", "203.0.113.7, 10.0.0.2", "203.0.113.7"),
+        case("127.0.0.1", "x-forwarded-for", "198.51.100.1, 203.0.113.7", "203.0.113.7"),
+        case("127.0.0.1", "forwarded", "for=203.0.113.7;proto=https, for=10.0.0.2", "203.0.113.7"),
+        case("127.0.0.1", "forwarded", "for=_hidden, for=10.0.0.2", "10.0.0.2"),
+        case("198.51.100.1", "x-forwarded-for", "203.0.113.7", "198.51.100.1")
+    )]
+    fn client_ip_is_resolved(peer: &str, name: &'static str, value: &'static str, expected: &str) {
+        let trusted_proxies = vec!["127.0.0.1".parse().unwrap(), "10.0.0.0/8".parse().unwrap()];
+        let client_ip = resolve_client_ip(&trusted_proxies, peer.parse().unwrap(), &headers(name, value));
+        assert_eq!(client_ip, expected.parse::<IpAddr>().unwrap());
+    }
+
+    #[rstest]
+    fn forwarded_params_are_parsed() {
+        let headers = headers("forwarded", "for=203.0.113.7;proto=https;host=\"example.com\", for=10.0.0.2");
+        assert_eq!(forwarded_params(&headers, "proto"), vec!["https", ""]);
+        assert_eq!(forwarded_params(&headers, "host"), vec!["example.com", ""]);
+    }
+}
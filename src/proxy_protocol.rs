@@ -0,0 +1,192 @@
+//! PROXY protocol header sent by TCP load balancers like HAProxy before the request, with
+//! `--proxy-protocol`, so that the address of the client is known without HTTP headers.
+//!
+//! Both the text format of version 1 and the binary format of version 2 are accepted. The header
+//! is read when the connection is accepted, before the request is parsed.
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use std::any::Any;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::ip_filter;
+
+/// How long the proxy has to send the header, during which the worker is blocked
+const HEADER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Longest header of version 1, including the final CRLF
+const MAX_V1_HEADER_LEN: usize = 107;
+
+/// Signature starting the headers of version 2
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Address of the client, given by the proxy which opened the connection
+#[derive(Clone, Copy, Debug)]
+pub struct ProxiedClient(pub SocketAddr);
+
+fn invalid_header(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid PROXY protocol header: {}", reason),
+    )
+}
+
+/// Parse the header of version 1 `line`, like `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443`,
+/// without its final CRLF
+fn parse_v1(line: &str) -> io::Result<Option<SocketAddr>> {
+    let fields = line.split(' ').collect::<Vec<_>>();
+    match fields.as_slice() {
+        ["PROXY", "TCP4", source, _, source_port, _]
+        | ["PROXY", "TCP6", source, _, source_port, _] => {
+            let ip = source
+                .parse::<IpAddr>()
+                .map_err(|_| invalid_header("invalid source address"))?;
+            let port = source_port
+                .parse::<u16>()
+                .map_err(|_| invalid_header("invalid source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        _ => Err(invalid_header("unsupported protocol")),
+    }
+}
+
+/// Parse the header of version 2, made of the fixed part `header` starting with the signature,
+/// and of `addresses`
+fn parse_v2(header: &[u8; 16], addresses: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if header[12] >> 4 != 2 {
+        return Err(invalid_header("unsupported version"));
+    }
+    match header[12] & 0x0f {
+        // Connections of the proxy itself, like health checks
+        0 => return Ok(None),
+        1 => (),
+        _ => return Err(invalid_header("unsupported command")),
+    }
+
+    match (header[13], addresses.len()) {
+        // TCP over IPv4
+        (0x11, len) if len >= 12 => {
+            let mut ip = [0; 4];
+            ip.copy_from_slice(&addresses[..4]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(Ipv4Addr::from(ip).into(), port)))
+        }
+        // TCP over IPv6
+        (0x21, len) if len >= 36 => {
+            let mut ip = [0; 16];
+            ip.copy_from_slice(&addresses[..16]);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+        }
+        (0x11, _) | (0x21, _) => Err(invalid_header("truncated addresses")),
+        // Other protocols have no client address which would make sense here
+        _ => Ok(None),
+    }
+}
+
+/// Read the header at the start of `stream`, and nothing more, and return the address of the
+/// client it gives, if any
+fn read_header(mut stream: impl Read) -> io::Result<Option<SocketAddr>> {
+    let mut start = [0; 5];
+    stream.read_exact(&mut start)?;
+
+    if &start == b"PROXY" {
+        // The header is short, and mustn't be read past its end
+        let mut line = start.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() >= MAX_V1_HEADER_LEN {
+                return Err(invalid_header("header too long"));
+            }
+            let mut byte = [0; 1];
+            stream.read_exact(&mut byte)?;
+            line.push(byte[0]);
+        }
+        let line = std::str::from_utf8(&line[..line.len() - 2])
+            .map_err(|_| invalid_header("invalid characters"))?;
+        parse_v1(line)
+    } else {
+        let mut header = [0; 16];
+        header[..5].copy_from_slice(&start);
+        stream.read_exact(&mut header[5..])?;
+        if &header[..12] != V2_SIGNATURE {
+            return Err(invalid_header("missing signature"));
+        }
+        let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+        let mut addresses = vec![0; len];
+        stream.read_exact(&mut addresses)?;
+        parse_v2(&header, &addresses)
+    }
+}
+
+/// Read the header at the start of the new connection `conn`, and keep the address of the client
+/// in `data`. Connections without a valid header are closed.
+pub fn read_connection_header(conn: &dyn Any, data: &mut Extensions) {
+    let socket = match conn.downcast_ref::<TcpStream>() {
+        Some(stream) => socket2::SockRef::from(stream),
+        #[cfg(unix)]
+        None => match conn.downcast_ref::<actix_web::rt::net::UnixStream>() {
+            Some(stream) => socket2::SockRef::from(stream),
+            None => return,
+        },
+        #[cfg(not(unix))]
+        None => return,
+    };
+
+    // The header is read before the connection is handed to the asynchronous runtime
+    let result = socket
+        .set_nonblocking(false)
+        .and_then(|_| socket.set_read_timeout(Some(HEADER_TIMEOUT)))
+        .and_then(|_| read_header(&*socket));
+    let restored = socket
+        .set_read_timeout(None)
+        .and_then(|_| socket.set_nonblocking(true));
+
+    match (result, restored) {
+        (Ok(Some(client)), Ok(())) => {
+            data.insert(ProxiedClient(client));
+        }
+        (Ok(None), Ok(())) => (),
+        (Err(e), _) | (_, Err(e)) => {
+            log::warn!("Closing a connection: {}", e);
+            ip_filter::close_connection(conn);
+        }
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(header, expected,
+        case(&b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET"[..], Some("192.0.2.1:56324")),
+        case(&b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n"[..], Some("[2001:db8::1]:56324")),
+        case(&b"PROXY UNKNOWN\r\n"[..], None),
+        case(&b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c\xc0\x00\x02\x01\xc6\x33\x64\x01\xdc\x04\x01\xbbGET"[..], Some("192.0.2.1:56324")),
+        case(&b"\r\n\r\n\0\r\nQUIT\n\x20\x00\x00\x00"[..], None)
+    )]
+    fn headers_are_read(header: &[u8], expected: Option<&str>) {
+        assert_eq!(read_header(header).unwrap(), expected.map(|addr| addr.parse().unwrap()));
+    }
+
+    #[rstest(header,
+        case(&b"GET / HTTP/1.1\r\n\r\n"[..]),
+        case(&b"PROXY TCP4 192.0.2.1\r\n"[..]),
+        case(&b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443"[..]),
+        case(&b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x04\xc0\x00\x02\x01"[..])
+    )]
+    fn invalid_headers_are_rejected(header: &[u8]) {
+        assert!(read_header(header).is_err());
+    }
+
+    #[rstest]
+    fn header_is_read_up_to_its_end() {
+        let mut stream = &b"PROXY UNKNOWN\r\nGET / HTTP/1.1\r\n"[..];
+        read_header(&mut stream).unwrap();
+        assert_eq!(stream, b"GET / HTTP/1.1\r\n");
+    }
+}
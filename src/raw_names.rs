@@ -0,0 +1,118 @@
+//! Files and directories whose names aren't valid UTF-8, which `Files` can't serve.
+//!
+//! Their links percent-encode the raw bytes of their names, and the requests of these links are
+//! handled here. The listings show the names with replacement characters, and mark them.
+use actix_files::{Directory, NamedFile};
+use actix_web::dev::{RequestHead, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use percent_encoding::percent_decode_str;
+use std::ffi::OsStr;
+use std::io;
+use std::path::PathBuf;
+
+use crate::canonical_url::{self, DirectorySlash};
+use crate::dir_config;
+use crate::listing::percent_encode_sets::PATH_SEGMENT;
+#[cfg(unix)]
+use crate::storage::served_entry;
+use crate::MiniserveConfig;
+
+/// Name of the entry `name`, percent-encoded as a segment of its URL path. The raw bytes of the
+/// names which aren't valid UTF-8 are encoded, so that the links lead to the entries.
+pub fn encode_name(name: &OsStr) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        percent_encoding::percent_encode(name.as_bytes(), PATH_SEGMENT).to_string()
+    }
+    #[cfg(not(unix))]
+    {
+        percent_encoding::utf8_percent_encode(&name.to_string_lossy(), PATH_SEGMENT).to_string()
+    }
+}
+
+/// Entry of the served directory requested at the URL path `uri_path`, if the decoded path isn't
+/// valid UTF-8
+fn requested_raw_entry(conf: &MiniserveConfig, uri_path: &str) -> Option<PathBuf> {
+    let route = format!("/{}", conf.random_route.clone().unwrap_or_default());
+    let encoded_path = uri_path.strip_prefix(&route)?;
+    let decoded_path = percent_decode_str(encoded_path).collect::<Vec<_>>();
+    if std::str::from_utf8(&decoded_path).is_ok() {
+        return None;
+    }
+
+    // Only Unix paths can be made of arbitrary bytes
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let relative_path = OsStr::from_bytes(&decoded_path);
+        served_entry(conf, relative_path.as_ref()).filter(|path| path.exists())
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Guard for the requests of the entries whose names aren't valid UTF-8
+pub fn is_raw_name_request(conf: &MiniserveConfig, head: &RequestHead) -> bool {
+    (head.method == Method::GET || head.method == Method::HEAD)
+        && conf.storage.is_none()
+        && requested_raw_entry(conf, head.uri.path()).is_some()
+}
+
+/// Serve the file, or list the directory with `render_listing`, requested at a path which isn't
+/// valid UTF-8
+pub async fn serve_raw_name<F>(req: HttpRequest, render_listing: F) -> HttpResponse
+where
+    F: Fn(&Directory, &HttpRequest) -> io::Result<ServiceResponse>,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let path = match requested_raw_entry(conf, req.path()) {
+        Some(path) => path,
+        // The entry was removed since the request was routed here
+        None => return crate::error_404(req).await,
+    };
+    if let Err(err) = dir_config::check_access(&req, conf, &path, false) {
+        return dir_config::access_denied_response(&req, err);
+    }
+
+    if path.is_dir() {
+        // Like `canonical_url` does for the other directories
+        if conf.directory_slash == DirectorySlash::Redirect && !req.path().ends_with('/') {
+            return canonical_url::redirect(conf, &format!("{}/", req.path()));
+        }
+        return match render_listing(&Directory::new(conf.path.clone(), path), &req) {
+            Ok(res) => res.into(),
+            Err(e) => {
+                log::error!("Failed to list the directory: {}", e);
+                crate::error_404(req).await
+            }
+        };
+    }
+    match NamedFile::open(&path) {
+        Ok(file) => file.prefer_utf8(true).respond_to(&req),
+        Err(_) => crate::error_404(req).await,
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn valid_names_are_encoded_as_utf8() {
+        assert_eq!(encode_name(OsStr::new("été 2021.txt")), "%C3%A9t%C3%A9%202021.txt");
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    fn invalid_names_are_encoded_as_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+        assert_eq!(encode_name(OsStr::from_bytes(b"caf\xe9.txt")), "caf%E9.txt");
+    }
+}
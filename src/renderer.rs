@@ -2,12 +2,26 @@ use actix_web::http::StatusCode;
 use chrono::{DateTime, Utc};
 use chrono_humanize::Humanize;
 use maud::{html, Markup, PreEscaped, DOCTYPE};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use std::path::Path;
 use std::time::SystemTime;
 use structopt::clap::{crate_name, crate_version};
 use strum::IntoEnumIterator;
 
-use crate::archive::ArchiveMethod;
+use crate::admin::{AdminStatus, ADMIN_READ_ONLY_ROUTE};
+use crate::archive::{self, ArchiveMethod};
+use crate::disk_usage::{self, DirUsage};
+use crate::feed::encode_path;
+use crate::file_op::{self, EDIT_ROUTE, EXTRACT_ROUTE, RENAME_ROUTE};
+use crate::fragments::Fragments;
+use crate::hls;
 use crate::listing::{Breadcrumb, Entry, SortingMethod, SortingOrder};
+use crate::manifest::DuplicateFiles;
+use crate::playlist::{self, PlaylistFormat};
+use crate::resize;
+use crate::search::{SearchMatch, SearchMode};
+use crate::session::{LOGIN_ROUTE, LOGOUT_ROUTE};
+use crate::share::SHARE_ROUTE;
 
 /// Renders the file listing
 #[allow(clippy::too_many_arguments)]
@@ -28,9 +42,54 @@ pub fn page(
     tar_enabled: bool,
     tar_gz_enabled: bool,
     zip_enabled: bool,
+    torrent_enabled: bool,
+    hls_enabled: bool,
+    resize_enabled: bool,
     hide_version_footer: bool,
+    show_logout: bool,
+    share_links: bool,
+    rename_enabled: bool,
+    max_edit_size: Option<u64>,
+    extract_enabled: bool,
+    events_route: Option<&str>,
+    fragments: &Fragments,
+    upload_only: bool,
+    storage_usage: Option<(u64, u64)>,
+    search_enabled: bool,
+    duplicates_enabled: bool,
+    usage_enabled: bool,
 ) -> Markup {
     let upload_action = build_upload_action(upload_route, encoded_dir, sort_method, sort_order);
+    let resumable_upload_action = format!("{}/resumable?path={}", upload_route, encoded_dir);
+    let new_file_action = format!("{}/new-file?path={}", upload_route, encoded_dir);
+
+    // The counts are only set with `--show-download-counts`
+    let show_downloads = entries.iter().any(|entry| entry.downloads.is_some());
+
+    // The metadata is only read with `--photo-metadata`
+    let show_taken = entries.iter().any(|entry| entry.photo.is_some());
+    // Directories with audio files can be queued in players at once
+    let has_audio = entries
+        .iter()
+        .any(|entry| !entry.is_dir() && playlist::is_audio(&entry.name));
+
+    // Entries sorted by the date they were taken are grouped by day
+    let group_labels = if matches!(sort_method, Some(SortingMethod::Taken)) {
+        let mut previous_day = None;
+        entries
+            .iter()
+            .map(|entry| {
+                let day = entry.date_taken().map(|date| date.date());
+                if day.is_none() || day == previous_day {
+                    return None;
+                }
+                previous_day = day;
+                day.map(|day| day.format("%A %-d %B %Y").to_string())
+            })
+            .collect::<Vec<_>>()
+    } else {
+        vec![None; entries.len()]
+    };
 
     let title_path = breadcrumbs
         .iter()
@@ -78,7 +137,7 @@ pub fn page(
                         }
                     }
                 }
-                (color_scheme_selector(show_qrcode))
+                (color_scheme_selector(show_qrcode, show_logout))
                 div.container {
                     span#top { }
                     h1.title dir="ltr" {
@@ -95,52 +154,124 @@ pub fn page(
                         }
                     }
                     div.toolbar {
-                        @if tar_enabled || tar_gz_enabled || zip_enabled {
+                        @if search_enabled {
+                            form.search method="GET" {
+                                input type="search" name="q" placeholder="Search the files" required="";
+                                (search_mode_select(SearchMode::Content))
+                                button type="submit" { "Search" }
+                            }
+                        }
+                        @if duplicates_enabled || usage_enabled {
+                            div.views {
+                                @if duplicates_enabled {
+                                    a href="?view=duplicates" { "Find duplicate files" }
+                                }
+                                @if usage_enabled {
+                                    a href="?view=usage" { "Disk usage" }
+                                }
+                            }
+                        }
+                        @if tar_enabled || tar_gz_enabled || zip_enabled || has_audio {
                             div.download {
                                 @for archive_method in ArchiveMethod::iter() {
                                     @if archive_method.is_enabled(tar_enabled, tar_gz_enabled, zip_enabled) {
                                         (archive_button(archive_method, sort_method, sort_order))
                                     }
                                 }
+                                @if has_audio {
+                                    (playlist_button(PlaylistFormat::M3u, sort_method, sort_order))
+                                }
                             }
                         }
                         @if file_upload {
                             div.upload {
-                                form id="file_submit" action=(upload_action) method="POST" enctype="multipart/form-data" {
-                                    p { "Select a file to upload or drag it anywhere into the window" }
-                                    div {
-                                        input#file-input type="file" name="file_to_upload" required="" multiple {}
-                                        button type="submit" { "Upload file" }
+                                div.upload-forms {
+                                    form id="file_submit" action=(upload_action) data-resumable-action=(resumable_upload_action) method="POST" enctype="multipart/form-data" {
+                                        p { "Select files or a folder to upload or drag them anywhere into the window" }
+                                        div {
+                                            input#file-input type="file" name="file_to_upload" required="" multiple {}
+                                            button type="submit" { "Upload file" }
+                                        }
+                                        div {
+                                            label for="dir-input" { "Upload a folder: " }
+                                            input#dir-input type="file" webkitdirectory="" {}
+                                        }
+                                        ul#upload-queue {}
+                                    }
+                                    @if !upload_only {
+                                        form.new-file action=(new_file_action) method="POST" {
+                                            input type="text" name="name" placeholder="New file name" required="";
+                                            button type="submit" { "Create file" }
+                                        }
+                                    }
+                                    @if let Some((used, quota)) = storage_usage {
+                                        p.storage-usage {
+                                            "Storage used: " (bytesize::ByteSize::b(used).to_string())
+                                            " of " (bytesize::ByteSize::b(quota).to_string())
+                                        }
                                     }
                                 }
                             }
                         }
                     }
-                    table {
-                        thead {
-                            th.name { (build_link("name", "Name", sort_method, sort_order)) }
-                            th.size { (build_link("size", "Size", sort_method, sort_order)) }
-                            th.date { (build_link("date", "Last modification", sort_method, sort_order)) }
-                        }
-                        tbody {
-                            @if !is_root {
-                                tr {
-                                    td colspan="3" {
-                                        span.root-chevron { (chevron_left()) }
-                                        a.root href=(parametrized_link("../", sort_method, sort_order)) {
-                                            "Parent directory"
-                                        }
+                    @if let Some(header) = &fragments.header {
+                        div.fragment.header-fragment { (PreEscaped(header)) }
+                    }
+                    @if !upload_only {
+                        table data-dir=(encoded_dir) {
+                            thead {
+                                th.name { (build_link("name", "Name", sort_method, sort_order)) }
+                                th.size { (build_link("size", "Size", sort_method, sort_order)) }
+                                th.date {
+                                    (build_link("date", "Last modification", sort_method, sort_order))
+                                    @if show_taken {
+                                        (build_link("taken", "Date taken", sort_method, sort_order))
                                     }
                                 }
+                                @if show_downloads {
+                                    th.downloads { "Downloads" }
+                                }
                             }
-                            @for entry in entries {
-                                (entry_row(entry, sort_method, sort_order))
+                            tbody {
+                                @if !is_root {
+                                    tr {
+                                        td colspan=(if show_downloads { 4 } else { 3 }) {
+                                            span.root-chevron { (chevron_left()) }
+                                            a.root href=(parametrized_link("../", sort_method, sort_order)) {
+                                                "Parent directory"
+                                            }
+                                        }
+                                    }
+                                }
+                                @for (entry, group_label) in entries.into_iter().zip(group_labels) {
+                                    @if let Some(group_label) = group_label {
+                                        tr.date-group {
+                                            td colspan=(if show_downloads { 4 } else { 3 }) { (group_label) }
+                                        }
+                                    }
+                                    (entry_row(entry, sort_method, sort_order, share_links, rename_enabled, max_edit_size, extract_enabled, show_qrcode, torrent_enabled, hls_enabled, resize_enabled, show_downloads, encoded_dir))
+                                }
                             }
                         }
                     }
+                    @if let Some(footer) = &fragments.footer {
+                        div.fragment.footer-fragment { (PreEscaped(footer)) }
+                    }
                     a.back href="#top" {
                         (arrow_up())
                     }
+                    @if rename_enabled {
+                        (rename_script())
+                    }
+                    @if extract_enabled {
+                        (extract_script())
+                    }
+                    @if show_qrcode {
+                        (qrcode_dialog())
+                    }
+                    @if let Some(events_route) = events_route {
+                        (live_updates_script(events_route))
+                    }
                     @if !hide_version_footer {
                         (version_footer())
                     }
@@ -188,9 +319,14 @@ const THEME_PICKER_CHOICES: &[(&str, &str)] = &[
 pub const THEME_SLUGS: &[&str] = &["squirrel", "archlinux", "zenburn", "monokai"];
 
 /// Partial: color scheme selector
-fn color_scheme_selector(show_qrcode: bool) -> Markup {
+fn color_scheme_selector(show_qrcode: bool, show_logout: bool) -> Markup {
     html! {
         nav {
+            @if show_logout {
+                div {
+                    a.logout href=(LOGOUT_ROUTE) { "Log out" }
+                }
+            }
             @if show_qrcode {
                 div {
                     p onmouseover="document.querySelector('#qrcode').src = `?qrcode=${encodeURIComponent(window.location.href)}`" {
@@ -253,6 +389,31 @@ fn archive_button(
     }
 }
 
+/// Partial: playlist button
+fn playlist_button(
+    playlist_format: PlaylistFormat,
+    sort_method: Option<SortingMethod>,
+    sort_order: Option<SortingOrder>,
+) -> Markup {
+    let link = if sort_method.is_none() && sort_order.is_none() {
+        format!("?download={}", playlist_format)
+    } else {
+        format!(
+            "{}&download={}",
+            parametrized_link("", sort_method, sort_order),
+            playlist_format
+        )
+    };
+
+    let text = format!("Playlist .{}", playlist_format.extension());
+
+    html! {
+        a href=(link) title="Playlist of the audio files, for players like VLC" {
+            (text)
+        }
+    }
+}
+
 /// Ensure that there's always a trailing slash behind the `link`.
 fn make_link_with_trailing_slash(link: &str) -> String {
     if link.ends_with('/') {
@@ -318,11 +479,29 @@ fn build_link(
 }
 
 /// Partial: row for an entry
+#[allow(clippy::too_many_arguments)]
 fn entry_row(
     entry: Entry,
     sort_method: Option<SortingMethod>,
     sort_order: Option<SortingOrder>,
+    share_links: bool,
+    rename_enabled: bool,
+    max_edit_size: Option<u64>,
+    extract_enabled: bool,
+    show_qrcode: bool,
+    torrent_enabled: bool,
+    hls_enabled: bool,
+    resize_enabled: bool,
+    show_downloads: bool,
+    encoded_dir: &str,
 ) -> Markup {
+    let editable = match (max_edit_size, entry.size) {
+        (Some(max_edit_size), Some(size)) => {
+            size.as_u64() <= max_edit_size && file_op::is_editable(&entry.name)
+        }
+        _ => false,
+    };
+
     html! {
         tr {
             td {
@@ -333,6 +512,18 @@ fn entry_row(
                             @if entry.is_symlink {
                                 span.symlink-symbol { }
                             }
+                            @if entry.invalid_name {
+                                span.invalid-name title="This name isn't valid UTF-8, it is shown with replacement characters" { }
+                            }
+                        }
+                        @if share_links {
+                            (share_link(&make_link_with_trailing_slash(&entry.link)))
+                        }
+                        @if rename_enabled && !entry.invalid_name {
+                            (rename_link(&entry.name))
+                        }
+                        @if show_qrcode {
+                            (qrcode_link(&make_link_with_trailing_slash(&entry.link)))
                         }
                     } @else if entry.is_file() {
                         div.file-entry {
@@ -341,6 +532,36 @@ fn entry_row(
                                 @if entry.is_symlink {
                                     span.symlink-symbol { }
                                 }
+                                @if entry.invalid_name {
+                                    span.invalid-name title="This name isn't valid UTF-8, it is shown with replacement characters" { }
+                                }
+                            }
+                            @if share_links {
+                                (share_link(&entry.link))
+                            }
+                            @if rename_enabled && !entry.invalid_name {
+                                (rename_link(&entry.name))
+                            }
+                            @if editable && !entry.invalid_name {
+                                (edit_link(encoded_dir, &entry.name))
+                            }
+                            @if extract_enabled && !entry.invalid_name && archive::is_extractable(Path::new(&entry.name)) {
+                                (extract_link(&entry.name))
+                            }
+                            @if show_qrcode {
+                                (qrcode_link(&entry.link))
+                            }
+                            @if torrent_enabled {
+                                (torrent_link(&entry.link))
+                            }
+                            @if hls_enabled && hls::is_video(&entry.name) {
+                                (hls_link(&entry.link))
+                            }
+                            @if resize_enabled && resize::is_image(&entry.name) {
+                                (preview_link(&entry.link))
+                            }
+                            @if let Some(photo) = &entry.photo {
+                                span.photo-info title=(photo.description()) { "info" }
                             }
                             @if let Some(size) = entry.size {
                                 span.mobile-info.size {
@@ -370,10 +591,240 @@ fn entry_row(
                     }
                 }
             }
+            @if show_downloads {
+                td.downloads-cell {
+                    @if let Some(downloads) = entry.downloads {
+                        (downloads)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Partial: link generating a share link for `link`
+fn share_link(link: &str) -> Markup {
+    html! {
+        a.share href=(format!("{}?path={}", SHARE_ROUTE, utf8_percent_encode(link, NON_ALPHANUMERIC)))
+            target="_blank" title="Create a link granting access without credentials for one day" {
+            "share"
+        }
+    }
+}
+
+/// Partial: link showing the QR code of `link`
+fn qrcode_link(link: &str) -> Markup {
+    html! {
+        a.qrcode-link href=(link) title="Show the QR code and the URL of this link" {
+            "QR"
+        }
+    }
+}
+
+/// Partial: link streaming the video at `link` as HLS
+fn hls_link(link: &str) -> Markup {
+    html! {
+        a.hls href=(format!("{}?hls={}", link, hls::PLAYLIST))
+            title="Stream this video as HLS, for players which can't play it as is" {
+            "stream"
+        }
+    }
+}
+
+/// Partial: link showing a downscaled copy of the image at `link`
+fn preview_link(link: &str) -> Markup {
+    html! {
+        a.preview href=(format!("{}?width={}", link, resize::PREVIEW_WIDTH))
+            title="Show a smaller copy of this image, which loads faster" {
+            "preview"
+        }
+    }
+}
+
+/// Partial: link downloading the torrent of the file at `link`
+fn torrent_link(link: &str) -> Markup {
+    html! {
+        a.torrent href=(format!("{}?download=torrent", link))
+            title="Download a torrent of this file, with this server as web seed" {
+            "torrent"
+        }
+    }
+}
+
+/// Partial: dialog showing the QR code and the URL of an entry, opened by its QR link
+fn qrcode_dialog() -> Markup {
+    html! {
+        dialog#qrcode-dialog {
+            img alt="QR code";
+            div {
+                input type="text" readonly="";
+                button.copy type="button" { "Copy" }
+                button.close type="button" { "Close" }
+            }
+        }
+        (PreEscaped(r#"
+        <script>
+            (function() {
+                const dialog = document.querySelector('#qrcode-dialog');
+                const url = dialog.querySelector('input');
+                // Delegated, so that it keeps working when the entries are reloaded
+                document.addEventListener('click', function(e) {
+                    const link = e.target.closest('.qrcode-link');
+                    if (link === null || typeof dialog.showModal !== 'function') {
+                        return;
+                    }
+                    e.preventDefault();
+                    url.value = new URL(link.getAttribute('href'), window.location.href).href;
+                    dialog.querySelector('img').src = `?qrcode=${encodeURIComponent(url.value)}`;
+                    dialog.showModal();
+                    url.select();
+                });
+                dialog.querySelector('.copy').onclick = function() {
+                    url.select();
+                    if (navigator.clipboard) {
+                        navigator.clipboard.writeText(url.value);
+                    } else {
+                        document.execCommand('copy');
+                    }
+                };
+                dialog.querySelector('.close').onclick = function() {
+                    dialog.close();
+                };
+            })();
+        </script>
+        "#))
+    }
+}
+
+/// Partial: link renaming the entry called `name`
+fn rename_link(name: &str) -> Markup {
+    html! {
+        a.rename href="#" data-name=(name) title="Rename or move" {
+            "rename"
+        }
+    }
+}
+
+/// Partial: link opening the file called `name` of the directory `encoded_dir` in the text editor
+fn edit_link(encoded_dir: &str, name: &str) -> Markup {
+    let dir = percent_decode_str(encoded_dir).decode_utf8_lossy();
+    let path = format!("{}/{}", dir.trim_end_matches('/'), name);
+    html! {
+        a.edit href=(format!("{}?path={}", EDIT_ROUTE, utf8_percent_encode(&path, NON_ALPHANUMERIC)))
+            title="Edit this file in the browser" {
+            "edit"
         }
     }
 }
 
+/// Partial: link extracting the archive called `name` next to it
+fn extract_link(name: &str) -> Markup {
+    html! {
+        a.extract href="#" data-name=(name) title="Extract this archive here" {
+            "extract"
+        }
+    }
+}
+
+/// Partial: script handling the rename links
+fn rename_script() -> Markup {
+    html! {
+        (PreEscaped(format!(r#"
+        <script>
+            const currentDir = decodeURIComponent(document.querySelector('table').dataset.dir).replace(/\/?$/, '/');
+            // Delegated, so that it keeps working when the entries are reloaded
+            document.addEventListener('click', async function(e) {{
+                const link = e.target.closest('.rename');
+                if (link === null) {{
+                    return;
+                }}
+                e.preventDefault();
+                const name = link.dataset.name;
+                const target = prompt('New name, or new path starting with / to move it', name);
+                if (target === null || target === '' || target === name) {{
+                    return;
+                }}
+                const body = new URLSearchParams({{
+                    from: currentDir + name,
+                    to: target.startsWith('/') ? target : currentDir + target,
+                }});
+                const response = await fetch('{}', {{ method: 'POST', body }});
+                if (response.ok) {{
+                    window.location.reload();
+                }} else {{
+                    alert(await response.text());
+                }}
+            }});
+        </script>
+        "#, RENAME_ROUTE)))
+    }
+}
+
+/// Partial: script handling the extract links, which show that the extraction is in progress
+fn extract_script() -> Markup {
+    html! {
+        (PreEscaped(format!(r#"
+        <script>
+            (function() {{
+                const dir = decodeURIComponent(document.querySelector('table').dataset.dir).replace(/\/?$/, '/');
+                // Delegated, so that it keeps working when the entries are reloaded
+                document.addEventListener('click', async function(e) {{
+                    const link = e.target.closest('.extract');
+                    if (link === null) {{
+                        return;
+                    }}
+                    e.preventDefault();
+                    if (link.classList.contains('in-progress')) {{
+                        return;
+                    }}
+                    link.classList.add('in-progress');
+                    link.textContent = 'extracting…';
+                    const body = new URLSearchParams({{ path: dir + link.dataset.name }});
+                    const response = await fetch('{}', {{ method: 'POST', body }});
+                    if (response.ok) {{
+                        window.location.reload();
+                    }} else {{
+                        link.classList.remove('in-progress');
+                        link.textContent = 'extract';
+                        alert(await response.text());
+                    }}
+                }});
+            }})();
+        </script>
+        "#, EXTRACT_ROUTE)))
+    }
+}
+
+/// Partial: script reloading the entries of the listing when the server reports a change
+fn live_updates_script(events_route: &str) -> Markup {
+    html! {
+        (PreEscaped(format!(r#"
+        <script>
+            (function() {{
+                const table = document.querySelector('table');
+                const events = new EventSource({} + '?path=' + table.dataset.dir);
+                let pending = null;
+                // Changes come in bursts, e.g. while a file is uploaded
+                events.addEventListener('change', function() {{
+                    clearTimeout(pending);
+                    pending = setTimeout(async function() {{
+                        const response = await fetch(window.location.href);
+                        if (!response.ok) {{
+                            return;
+                        }}
+                        const page = new DOMParser().parseFromString(await response.text(), 'text/html');
+                        const entries = page.querySelector('table tbody');
+                        if (entries !== null) {{
+                            table.querySelector('tbody').replaceWith(entries);
+                        }}
+                    }}, 300);
+                }});
+            }})();
+        </script>
+        "#, serde_json::to_string(events_route).unwrap_or_default())))
+    }
+}
+
 /// Partial: up arrow
 fn arrow_up() -> Markup {
     PreEscaped("⇪".to_string())
@@ -414,8 +865,184 @@ fn page_header(title: &str, file_upload: bool, favicon_route: &str, css_route: &
                         const dropContainer = document.querySelector('#drop-container');
                         const dragForm = document.querySelector('.drag-form');
                         const fileInput = document.querySelector('#file-input');
+                        const dirInput = document.querySelector('#dir-input');
+                        const fileSubmit = document.querySelector('#file_submit');
                         const collection = [];
 
+                        // Files are sent in chunks, so that uploads can resume after a connection failure
+                        const CHUNK_SIZE = 8 * 1024 * 1024;
+                        const MAX_RETRIES = 10;
+
+                        function sleep(ms) {
+                            return new Promise(resolve => setTimeout(resolve, ms));
+                        }
+
+                        async function uploadOffset(url) {
+                            const response = await fetch(url, { cache: 'no-store' });
+                            if (!response.ok) {
+                                throw new Error(await response.text());
+                            }
+                            return parseInt(response.headers.get('Upload-Offset'), 10);
+                        }
+
+                        // XMLHttpRequest is used instead of fetch, as it reports the upload progress
+                        function sendChunk(url, file, offset, onProgress) {
+                            return new Promise((resolve, reject) => {
+                                const xhr = new XMLHttpRequest();
+                                xhr.open('PATCH', url);
+                                xhr.setRequestHeader('Upload-Offset', offset);
+                                xhr.setRequestHeader('Upload-Length', file.size);
+                                xhr.upload.onprogress = e => onProgress(offset + e.loaded);
+                                xhr.onload = () => resolve(xhr);
+                                xhr.onerror = () => reject(new Error('Connection failed'));
+                                xhr.send(file.slice(offset, offset + CHUNK_SIZE));
+                            });
+                        }
+
+                        // `path` is relative to the current directory, it contains the folders of the file
+                        // when a whole folder is uploaded
+                        async function uploadFile(file, path, onProgress) {
+                            const url = fileSubmit.dataset.resumableAction + '&name=' + encodeURIComponent(path);
+                            let offset = null;
+                            let retries = 0;
+                            while (true) {
+                                let xhr;
+                                try {
+                                    // Ask where to resume when starting and after a connection failure
+                                    if (offset === null) {
+                                        offset = await uploadOffset(url);
+                                    }
+                                    xhr = await sendChunk(url, file, offset, onProgress);
+                                } catch (e) {
+                                    if (++retries > MAX_RETRIES) {
+                                        throw e;
+                                    }
+                                    await sleep(1000 * retries);
+                                    offset = null;
+                                    continue;
+                                }
+                                if (xhr.status === 409 && xhr.getResponseHeader('Upload-Offset') !== null) {
+                                    // The server received a different amount of data than expected
+                                    offset = parseInt(xhr.getResponseHeader('Upload-Offset'), 10);
+                                    continue;
+                                }
+                                if (xhr.status === 423) {
+                                    // A previous attempt is still being received, resume once it ended
+                                    if (++retries > MAX_RETRIES) {
+                                        throw new Error(xhr.responseText);
+                                    }
+                                    await sleep(1000 * retries);
+                                    offset = null;
+                                    continue;
+                                }
+                                if (xhr.status >= 400) {
+                                    throw new Error(xhr.responseText);
+                                }
+                                retries = 0;
+                                offset = parseInt(xhr.getResponseHeader('Upload-Offset'), 10);
+                                onProgress(offset);
+                                if (offset >= file.size) {
+                                    const name = xhr.getResponseHeader('Upload-Name');
+                                    return {
+                                        skipped: xhr.getResponseHeader('Upload-Result') === 'skipped',
+                                        duplicate: xhr.getResponseHeader('Upload-Result') === 'duplicate',
+                                        name: name === null ? file.name : decodeURIComponent(name),
+                                    };
+                                }
+                            }
+                        }
+
+                        // Files are uploaded one after the other, more can be queued in the meantime
+                        const queue = [];
+                        let uploading = false;
+                        let reports = 0;
+
+                        function queueFiles(uploads) {
+                            const list = document.querySelector('#upload-queue');
+                            for (const { file, path } of uploads) {
+                                const item = document.createElement('li');
+                                const name = document.createElement('span');
+                                name.className = 'upload-name';
+                                name.textContent = path;
+                                const progress = document.createElement('progress');
+                                progress.max = file.size || 1;
+                                progress.value = 0;
+                                const status = document.createElement('span');
+                                status.className = 'upload-status';
+                                status.textContent = 'Queued';
+                                item.append(name, progress, status);
+                                list.append(item);
+                                queue.push({ file, path, item, progress, status });
+                            }
+                            fileInput.value = '';
+                            dirInput.value = '';
+                            processQueue();
+                        }
+
+                        async function processQueue() {
+                            if (uploading) {
+                                return;
+                            }
+                            uploading = true;
+                            while (queue.length > 0) {
+                                const { file, path, item, progress, status } = queue.shift();
+                                status.textContent = 'Uploading';
+                                try {
+                                    const result = await uploadFile(file, path, bytes => {
+                                        progress.value = bytes;
+                                        status.textContent = Math.floor(100 * bytes / (file.size || 1)) + '%';
+                                    });
+                                    progress.value = progress.max;
+                                    item.className = 'upload-success';
+                                    if (result.skipped) {
+                                        reports++;
+                                        status.textContent = 'Skipped, already exists';
+                                    } else if (result.duplicate) {
+                                        status.textContent = 'Done, already uploaded';
+                                    } else if (result.name !== file.name) {
+                                        reports++;
+                                        status.textContent = 'Saved as ' + result.name;
+                                    } else {
+                                        status.textContent = 'Done';
+                                    }
+                                } catch (e) {
+                                    reports++;
+                                    item.className = 'upload-failure';
+                                    status.textContent = 'Failed: ' + e.message;
+                                }
+                            }
+                            uploading = false;
+                            // Show the new files, unless some results have to be reported
+                            if (reports === 0) {
+                                window.location.reload();
+                            }
+                        }
+
+                        // Collect the files of a dropped file or folder, along with their path
+                        async function readEntry(entry, parentPath) {
+                            if (entry.isFile) {
+                                const file = await new Promise((resolve, reject) => entry.file(resolve, reject));
+                                return [{ file, path: parentPath + file.name }];
+                            }
+                            const reader = entry.createReader();
+                            const entries = [];
+                            // Entries of a folder are returned in batches, until an empty one
+                            while (true) {
+                                const batch = await new Promise((resolve, reject) => reader.readEntries(resolve, reject));
+                                if (batch.length === 0) {
+                                    break;
+                                }
+                                entries.push(...batch);
+                            }
+                            const path = parentPath + entry.name + '/';
+                            const uploads = await Promise.all(entries.map(child => readEntry(child, path)));
+                            return uploads.flat();
+                        }
+
+                        function filesOf(files) {
+                            return Array.from(files).map(file => ({ file, path: file.webkitRelativePath || file.name }));
+                        }
+
                         dropContainer.ondragover = function(e) {
                             e.preventDefault();
                         }
@@ -438,9 +1065,25 @@ fn page_header(title: &str, file_upload: bool, favicon_route: &str, css_route: &
 
                         dropContainer.ondrop = function(e) {
                             e.preventDefault();
-                            fileInput.files = e.dataTransfer.files;
-                            file_submit.submit();
                             dragForm.style.display = 'none';
+                            const items = Array.from(e.dataTransfer.items || []);
+                            if (items.length > 0 && items[0].webkitGetAsEntry) {
+                                // Entries have to be retrieved before the drop handler returns
+                                const entries = items.map(item => item.webkitGetAsEntry()).filter(entry => entry);
+                                Promise.all(entries.map(entry => readEntry(entry, '')))
+                                    .then(uploads => queueFiles(uploads.flat()));
+                            } else {
+                                queueFiles(filesOf(e.dataTransfer.files));
+                            }
+                        };
+
+                        fileSubmit.onsubmit = function(e) {
+                            e.preventDefault();
+                            queueFiles(filesOf(fileInput.files));
+                        };
+
+                        dirInput.onchange = function() {
+                            queueFiles(filesOf(dirInput.files));
                         };
                     }
                 </script>
@@ -518,3 +1161,442 @@ pub fn render_error(
         }
     }
 }
+
+/// Renders the login page
+pub fn login_page(
+    error: Option<&str>,
+    next: &str,
+    favicon_route: &str,
+    css_route: &str,
+    default_color_scheme: &str,
+    default_color_scheme_dark: &str,
+    hide_version_footer: bool,
+) -> Markup {
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header("Log in", false, favicon_route, css_route))
+
+            body.(format!("default_theme_{}", default_color_scheme))
+                .(format!("default_theme_dark_{}", default_color_scheme_dark)) {
+
+                div.login {
+                    h1 { "Log in" }
+                    @if let Some(error) = error {
+                        p.login-error { (error) }
+                    }
+                    form action=(LOGIN_ROUTE) method="POST" {
+                        input type="hidden" name="next" value=(next);
+                        input type="text" name="username" placeholder="Username" autocomplete="username" required="" autofocus="";
+                        input type="password" name="password" placeholder="Password" autocomplete="current-password";
+                        button type="submit" { "Log in" }
+                    }
+                    @if !hide_version_footer {
+                        (version_footer())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the admin status page
+pub fn admin_page(
+    status: &AdminStatus,
+    favicon_route: &str,
+    css_route: &str,
+    default_color_scheme: &str,
+    default_color_scheme_dark: &str,
+    hide_version_footer: bool,
+) -> Markup {
+    let uptime = status.uptime_secs;
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header("Status", false, favicon_route, css_route))
+
+            body.(format!("default_theme_{}", default_color_scheme))
+                .(format!("default_theme_dark_{}", default_color_scheme_dark)) {
+
+                div.admin {
+                    h1 { "Status" }
+                    p {
+                        "Up for " (format!("{}d {:02}h {:02}m {:02}s", uptime / 86400, uptime / 3600 % 24, uptime / 60 % 60, uptime % 60))
+                    }
+                    form action=(ADMIN_READ_ONLY_ROUTE) method="POST" {
+                        @if status.read_only {
+                            span { "Read-only mode is enabled" }
+                            input type="hidden" name="read_only" value="false";
+                            button type="submit" { "Disable read-only mode" }
+                        } @else {
+                            span { "Read-only mode is disabled" }
+                            input type="hidden" name="read_only" value="true";
+                            button type="submit" { "Enable read-only mode" }
+                        }
+                    }
+                    h2 { "Transfers" }
+                    @if status.transfers.is_empty() {
+                        p { "No transfer in progress" }
+                    } @else {
+                        table {
+                            thead {
+                                tr { th { "Path" } th { "Client" } th { "User" } th { "Started" } th { "Progress" } }
+                            }
+                            tbody {
+                                @for transfer in &status.transfers {
+                                    tr {
+                                        td { (transfer.path) }
+                                        td { (transfer.client.as_deref().unwrap_or("")) }
+                                        td { (transfer.user.as_deref().unwrap_or("")) }
+                                        td { (transfer.started) }
+                                        td {
+                                            (bytesize::ByteSize::b(transfer.sent).to_string())
+                                            @if let Some(size) = transfer.size {
+                                                " / " (bytesize::ByteSize::b(size).to_string())
+                                                @if let Some(percent) = (transfer.sent * 100).checked_div(size) {
+                                                    (format!(" ({}%)", percent))
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    h2 { "Recent requests" }
+                    table {
+                        thead {
+                            tr { th { "Time" } th { "Client" } th { "User" } th { "Request" } th { "Status" } }
+                        }
+                        tbody {
+                            @for request in &status.recent_requests {
+                                tr {
+                                    td { (request.time) }
+                                    td { (request.client.as_deref().unwrap_or("")) }
+                                    td { (request.user.as_deref().unwrap_or("")) }
+                                    td { (request.method) " " (request.path) }
+                                    td { (request.status) }
+                                }
+                            }
+                        }
+                    }
+                    @if let Some(downloads) = &status.downloads {
+                        h2 { "Downloads" }
+                        @if downloads.is_empty() {
+                            p { "No file downloaded yet" }
+                        } @else {
+                            table {
+                                thead {
+                                    tr { th { "Path" } th { "Downloads" } th { "Sent" } }
+                                }
+                                tbody {
+                                    @for file in downloads {
+                                        tr {
+                                            td { (file.path) }
+                                            td { (file.stats.downloads) }
+                                            td { (bytesize::ByteSize::b(file.stats.bytes).to_string()) }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    @if !hide_version_footer {
+                        (version_footer())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the text editor of the file at `path`
+#[allow(clippy::too_many_arguments)]
+pub fn edit_page(
+    path: &str,
+    action: &str,
+    return_path: &str,
+    content: &str,
+    modified: &str,
+    error: Option<&str>,
+    saved: bool,
+    favicon_route: &str,
+    css_route: &str,
+    default_color_scheme: &str,
+    default_color_scheme_dark: &str,
+    hide_version_footer: bool,
+) -> Markup {
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header(path, false, favicon_route, css_route))
+
+            body.(format!("default_theme_{}", default_color_scheme))
+                .(format!("default_theme_dark_{}", default_color_scheme_dark)) {
+
+                div.editor {
+                    h1 { (path) }
+                    @if let Some(error) = error {
+                        p.editor-error { (error) }
+                    } @else if saved {
+                        p.editor-saved { "Saved" }
+                    }
+                    form action=(action) method="POST" {
+                        input type="hidden" name="modified" value=(modified);
+                        textarea name="content" spellcheck="false" autofocus="" { (content) }
+                        div {
+                            a href=(return_path) { "Back to the directory" }
+                            button type="submit" { "Save" }
+                        }
+                    }
+                    @if !hide_version_footer {
+                        (version_footer())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Partial: selection of the search mode, `mode` being selected
+fn search_mode_select(mode: SearchMode) -> Markup {
+    let modes = [
+        (SearchMode::Content, "In the content"),
+        (SearchMode::Fuzzy, "In the file names"),
+    ];
+    html! {
+        select name="mode" {
+            @for (value, label) in &modes {
+                @if *value == mode {
+                    option value=(value) selected="" { (label) }
+                } @else {
+                    option value=(value) { (label) }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the results of the search of `query` in the directory at `dir_link`, or `None` if the
+/// query is too short
+#[allow(clippy::too_many_arguments)]
+pub fn search_page(
+    query: &str,
+    mode: SearchMode,
+    results: Option<&[SearchMatch]>,
+    indexing: bool,
+    dir_link: &str,
+    favicon_route: &str,
+    css_route: &str,
+    default_color_scheme: &str,
+    default_color_scheme_dark: &str,
+    hide_version_footer: bool,
+) -> Markup {
+    let dir = percent_decode_str(dir_link).decode_utf8_lossy();
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header(&format!("Search in {}", dir), false, favicon_route, css_route))
+
+            body.(format!("default_theme_{}", default_color_scheme))
+                .(format!("default_theme_dark_{}", default_color_scheme_dark)) {
+
+                div.search-results {
+                    h1 { "Search in " (dir) }
+                    form.search action=(dir_link) method="GET" {
+                        input type="search" name="q" value=(query) required="" autofocus="";
+                        (search_mode_select(mode))
+                        button type="submit" { "Search" }
+                    }
+                    @if indexing {
+                        p.search-message { "The files are still being indexed, some results may be missing" }
+                    }
+                    @match results {
+                        None => p.search-message { "Search for at least 3 characters, or search the file names" },
+                        Some([]) => p.search-message { "No file found" },
+                        Some(results) => {
+                            p.search-message {
+                                (results.len()) @if results.len() == 1 { " file found" } @else { " files found" }
+                            }
+                            ul {
+                                @for result in results {
+                                    li {
+                                        a href=(result.link) {
+                                            @for (i, c) in result.path.chars().enumerate() {
+                                                @if result.matched.contains(&i) {
+                                                    mark { (c) }
+                                                } @else {
+                                                    (c)
+                                                }
+                                            }
+                                        }
+                                        @for snippet in &result.snippets {
+                                            pre.snippet { (snippet) }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    a href=(dir_link) { "Back to the directory" }
+                    @if !hide_version_footer {
+                        (version_footer())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the report of the `duplicates` of the directory at `dir_link`
+pub fn duplicates_page(
+    duplicates: &[DuplicateFiles],
+    dir_link: &str,
+    favicon_route: &str,
+    css_route: &str,
+    default_color_scheme: &str,
+    default_color_scheme_dark: &str,
+    hide_version_footer: bool,
+) -> Markup {
+    let dir = percent_decode_str(dir_link).decode_utf8_lossy();
+    // Keeping a single copy of each file would free this much space
+    let wasted = duplicates
+        .iter()
+        .map(|group| group.size * (group.paths.len() as u64 - 1))
+        .sum::<u64>();
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header(&format!("Duplicate files in {}", dir), false, favicon_route, css_route))
+
+            body.(format!("default_theme_{}", default_color_scheme))
+                .(format!("default_theme_dark_{}", default_color_scheme_dark)) {
+
+                div.duplicates {
+                    h1 { "Duplicate files in " (dir) }
+                    @if duplicates.is_empty() {
+                        p { "No duplicate files" }
+                    } @else {
+                        p {
+                            (duplicates.len()) @if duplicates.len() == 1 { " file has" } @else { " files have" }
+                            " copies, removing them would free " (bytesize::ByteSize::b(wasted).to_string())
+                        }
+                        @for group in duplicates {
+                            div.duplicate-group {
+                                p.duplicate-info {
+                                    (group.paths.len()) " copies of " (bytesize::ByteSize::b(group.size).to_string())
+                                    " " code title=(group.hash) { (&group.hash[..12]) }
+                                }
+                                ul {
+                                    @for path in &group.paths {
+                                        li { a href=(format!("{}{}", dir_link, encode_path(path))) { (path) } }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    a href=(dir_link) { "Back to the directory" }
+                    @if !hide_version_footer {
+                        (version_footer())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the disk `usage` of the directory at `dir_link`, as a treemap and a table of the
+/// sizes of its entries
+pub fn usage_page(
+    usage: &DirUsage,
+    dir_link: &str,
+    favicon_route: &str,
+    css_route: &str,
+    default_color_scheme: &str,
+    default_color_scheme_dark: &str,
+    hide_version_footer: bool,
+) -> Markup {
+    let dir = percent_decode_str(dir_link).decode_utf8_lossy();
+    let sizes = usage
+        .entries
+        .iter()
+        .map(|entry| entry.size)
+        .collect::<Vec<_>>();
+    // The map is twice as wide as high, see `.treemap`
+    let tiles = disk_usage::treemap(&sizes, 2.0);
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header(&format!("Disk usage of {}", dir), false, favicon_route, css_route))
+
+            body.(format!("default_theme_{}", default_color_scheme))
+                .(format!("default_theme_dark_{}", default_color_scheme_dark)) {
+
+                div.usage {
+                    h1 { "Disk usage of " (dir) }
+                    p {
+                        (bytesize::ByteSize::b(usage.size).to_string()) " in " (usage.files)
+                        @if usage.files == 1 { " file" } @else { " files" }
+                    }
+                    @if usage.size > 0 {
+                        div.treemap {
+                            @for (entry, tile) in usage.entries.iter().zip(&tiles) {
+                                @if let Some(tile) = tile {
+                                    a class=(if entry.is_dir { "tile directory" } else { "tile" })
+                                        href=(usage_link(dir_link, &entry.name, entry.is_dir))
+                                        title=(format!("{} — {}", entry.name, bytesize::ByteSize::b(entry.size)))
+                                        style=(format!(
+                                            "left: {:.3}%; top: {:.3}%; width: {:.3}%; height: {:.3}%",
+                                            tile.left, tile.top, tile.width, tile.height
+                                        )) {
+                                        span { (entry.name) }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    table {
+                        thead {
+                            th { "Name" }
+                            th { "Size" }
+                            th { "Share" }
+                            th { "Files" }
+                        }
+                        tbody {
+                            @for entry in &usage.entries {
+                                tr {
+                                    td {
+                                        a href=(usage_link(dir_link, &entry.name, entry.is_dir)) {
+                                            (entry.name) @if entry.is_dir { "/" }
+                                        }
+                                    }
+                                    td.size-cell { (bytesize::ByteSize::b(entry.size).to_string()) }
+                                    td {
+                                        @if usage.size > 0 {
+                                            (format!("{:.1}%", entry.size as f64 * 100.0 / usage.size as f64))
+                                        }
+                                    }
+                                    td { (entry.files) }
+                                }
+                            }
+                        }
+                    }
+                    a href=(dir_link) { "Back to the directory" }
+                    @if !hide_version_footer {
+                        (version_footer())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Link of the entry `name` of the directory at `dir_link` on the disk usage page, which shows
+/// the disk usage of the subdirectories
+fn usage_link(dir_link: &str, name: &str, is_dir: bool) -> String {
+    if is_dir {
+        format!("{}{}/?view=usage", dir_link, encode_path(name))
+    } else {
+        format!("{}{}", dir_link, encode_path(name))
+    }
+}
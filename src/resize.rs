@@ -0,0 +1,220 @@
+//! Downscaled copies of the images with `--enable-resize`, e.g. `photos/img.jpg?width=800`, so that
+//! large photos can be viewed quickly without downloading them.
+//!
+//! The width is rounded up to a multiple of `WIDTH_STEP`, so that the copies of an image are few.
+//! They are kept in the `--resize-cache` directory, named after the path, size and modification
+//! time of the image, and never made larger than the image itself.
+use actix_files::NamedFile;
+use actix_web::dev::RequestHead;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::http::Method;
+use actix_web::web::{self, Query};
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::canonical_path;
+use crate::storage::requested_file;
+use crate::MiniserveConfig;
+
+/// Extensions of the images which can be resized
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp", "tif", "tiff"];
+
+/// Widths are rounded up to a multiple of this
+const WIDTH_STEP: u32 = 100;
+
+/// Largest width of the copies
+const MAX_WIDTH: u32 = 4000;
+
+/// Width of the copies linked from the listings
+pub const PREVIEW_WIDTH: u32 = 1600;
+
+/// Query parameter of the resize requests
+#[derive(Deserialize)]
+struct ResizeQuery {
+    width: u32,
+}
+
+/// Whether the file `name` is an image which can be resized
+pub fn is_image(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Whether `head` requests a downscaled copy of an image of the served directory
+pub fn is_resize_request(conf: &MiniserveConfig, head: &RequestHead) -> bool {
+    if head.method != Method::GET {
+        return false;
+    }
+    if Query::<ResizeQuery>::from_query(head.uri.query().unwrap_or_default()).is_err() {
+        return false;
+    }
+    requested_file(conf, head.uri.path())
+        .is_some_and(|file| file.is_file() && is_image(&file.to_string_lossy()))
+}
+
+/// Width of the copy sent for the requested `width`, if it is valid
+fn copy_width(width: u32) -> Option<u32> {
+    if width == 0 {
+        return None;
+    }
+    let width = width.min(MAX_WIDTH);
+    Some(width.div_ceil(WIDTH_STEP) * WIDTH_STEP)
+}
+
+/// Format of the copies of `image`: PNG for the formats which may be transparent, JPEG otherwise
+fn copy_format(image: &Path) -> ImageFormat {
+    match ImageFormat::from_path(image) {
+        Ok(ImageFormat::Png) | Ok(ImageFormat::Gif) | Ok(ImageFormat::WebP) => ImageFormat::Png,
+        _ => ImageFormat::Jpeg,
+    }
+}
+
+/// Path of the copy of `image` at `width`, which changes along with the image
+fn copy_path(conf: &MiniserveConfig, image: &Path, width: u32) -> io::Result<PathBuf> {
+    let image = canonical_path::canonicalize(image)?;
+    let metadata = image.metadata()?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos());
+
+    let mut hasher = Sha256::new();
+    hasher.update(image.to_string_lossy().as_bytes());
+    hasher.update(format!("|{}|{}|{}", metadata.len(), modified, width).as_bytes());
+    let extension = match copy_format(&image) {
+        ImageFormat::Png => "png",
+        _ => "jpg",
+    };
+    Ok(conf.resize_cache.join(format!(
+        "{}.{}",
+        hex::encode(&hasher.finalize()[..16]),
+        extension
+    )))
+}
+
+/// Write the copy of `image` at `width` to `copy`, unless it is there already. Returns `false`
+/// if the image isn't wider than `width`, in which case it is sent as is.
+fn resize(image: &Path, width: u32, copy: &Path) -> io::Result<bool> {
+    if copy.exists() {
+        return Ok(true);
+    }
+    let to_io_error = |e: image::ImageError| io::Error::other(e.to_string());
+
+    let original = image::open(image).map_err(to_io_error)?;
+    if original.width() <= width {
+        return Ok(false);
+    }
+    let resized = original.resize(width, u32::MAX, FilterType::CatmullRom);
+    let format = copy_format(image);
+    let resized = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(resized.to_rgb8()),
+        _ => resized,
+    };
+
+    // Concurrent requests never send a partially written copy
+    if let Some(dir) = copy.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut temp_path = copy.to_path_buf().into_os_string();
+    temp_path.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = PathBuf::from(temp_path);
+    resized
+        .save_with_format(&temp_path, format)
+        .map_err(to_io_error)?;
+    std::fs::rename(&temp_path, copy)?;
+    Ok(true)
+}
+
+/// Send the downscaled copy of the image requested by `req`
+pub async fn resized_image(req: HttpRequest) -> HttpResponse {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let width = Query::<ResizeQuery>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| copy_width(query.width));
+    let width = match width {
+        Some(width) => width,
+        None => {
+            return HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body("Invalid width");
+        }
+    };
+    let image = match requested_file(conf, req.path()) {
+        Some(image) if image.is_file() => image,
+        _ => return crate::error_404(req).await,
+    };
+    let copy = match copy_path(conf, &image, width) {
+        Ok(copy) => copy,
+        Err(e) => {
+            log::error!("Failed to read {}: {}", image.display(), e);
+            return crate::error_404(req).await;
+        }
+    };
+
+    // Saved copies keep the name of the image
+    let name = image
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let (resize_image, resize_copy) = (image.clone(), copy.clone());
+    let resized = web::block(move || resize(&resize_image, width, &resize_copy))
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e.to_string())));
+    let file = match resized {
+        Ok(true) => copy,
+        Ok(false) => image,
+        Err(e) => {
+            log::error!("Failed to resize {}: {}", image.display(), e);
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body("Failed to resize the image");
+        }
+    };
+    match NamedFile::open(&file) {
+        Ok(file) => file
+            .set_content_disposition(ContentDisposition {
+                disposition: DispositionType::Inline,
+                parameters: vec![DispositionParam::Filename(name)],
+            })
+            .respond_to(&req),
+        Err(_) => crate::error_404(req).await,
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(width, expected,
+        case(0, None),
+        case(1, Some(100)),
+        case(800, Some(800)),
+        case(801, Some(900)),
+        case(100_000, Some(MAX_WIDTH))
+    )]
+    fn widths_are_rounded(width: u32, expected: Option<u32>) {
+        assert_eq!(copy_width(width), expected);
+    }
+
+    #[rstest(name, expected,
+        case("IMG_0001.JPG", ImageFormat::Jpeg),
+        case("scan.tiff", ImageFormat::Jpeg),
+        case("logo.png", ImageFormat::Png),
+        case("animation.gif", ImageFormat::Png)
+    )]
+    fn copies_keep_transparency(name: &str, expected: ImageFormat) {
+        assert_eq!(copy_format(Path::new(name)), expected);
+    }
+}
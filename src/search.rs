@@ -0,0 +1,576 @@
+//! Search of the content of the text files with `--enable-content-search`, e.g.
+//! `/docs/?q=invoice`, which lists the files of the directory containing the query along with
+//! the matching lines. With `&mode=fuzzy`, the names of the files are searched instead, and
+//! ranked like fzf does, e.g. `?q=invc&mode=fuzzy` finds `2021/invoice-03.pdf`.
+//!
+//! An index of the files of the served directory, with the trigrams of the text files, is built
+//! in the background when miniserve starts, and updated every `REINDEX_INTERVAL` with the files
+//! which changed. Only the files containing all the trigrams of a query are read again to find the
+//! query itself.
+use actix_web::dev::RequestHead;
+use actix_web::http::{header, Method};
+use actix_web::web::{self, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime};
+use strum_macros::{Display, EnumString};
+
+use crate::canonical_path;
+use crate::dir_config::{self, DIR_CONFIG_FILE};
+use crate::feed::encode_path;
+use crate::ignore::IgnoredEntries;
+use crate::renderer;
+use crate::upload_ttl;
+use crate::MiniserveConfig;
+
+/// Size of the largest files which are indexed
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Bytes at the start of the files looked at to tell binary files apart
+const BINARY_CHECK_LENGTH: usize = 8192;
+
+/// Interval between the updates of the index
+const REINDEX_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Depth of the indexed subdirectories, which also stops symlink loops
+const MAX_DEPTH: usize = 16;
+
+/// Shortest query, as shorter ones have no trigram to look up
+const MIN_QUERY_LENGTH: usize = 3;
+
+/// Number of files listed in the results
+const MAX_RESULTS: usize = 100;
+
+/// Number of matching lines shown for each file
+const MAX_SNIPPETS: usize = 3;
+
+/// Length of the matching lines shown, in characters
+const SNIPPET_LENGTH: usize = 160;
+
+/// Available search modes
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SearchMode {
+    /// Text files containing the query
+    #[default]
+    Content,
+
+    /// Files whose path contains the characters of the query in order, best matches first
+    Fuzzy,
+}
+
+/// Query parameters of the searches
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: String,
+
+    #[serde(default)]
+    mode: SearchMode,
+}
+
+/// File of the index
+struct IndexedFile {
+    size: u64,
+    modified: Option<SystemTime>,
+
+    /// Sorted trigrams of the lowercase content, or `None` for binary files
+    trigrams: Option<Vec<u32>>,
+}
+
+/// File matching a search, as shown in the results
+pub struct SearchMatch {
+    /// URL of the file
+    pub link: String,
+
+    /// Path of the file, relative to the searched directory
+    pub path: String,
+
+    /// Positions of the characters of `path` matching the query, in fuzzy searches
+    pub matched: Vec<usize>,
+
+    /// Lines containing the query, in content searches
+    pub snippets: Vec<String>,
+}
+
+/// Index of the files of the served directory, keyed by their canonical path
+#[derive(Default)]
+pub struct ContentIndex {
+    files: RwLock<HashMap<PathBuf, IndexedFile>>,
+
+    /// Whether the served directory was indexed once already
+    ready: AtomicBool,
+}
+
+/// Sorted and deduplicated trigrams of the bytes of `text`
+fn trigrams(text: &str) -> Vec<u32> {
+    let mut trigrams = text
+        .as_bytes()
+        .windows(3)
+        .map(|bytes| u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]))
+        .collect::<Vec<_>>();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+/// Content of the file at `path`, or `None` if it is a binary file
+fn read_text(path: &Path) -> io::Result<Option<String>> {
+    let mut content = vec![];
+    File::open(path)?
+        .take(MAX_FILE_SIZE)
+        .read_to_end(&mut content)?;
+    if content
+        .iter()
+        .take(BINARY_CHECK_LENGTH)
+        .any(|byte| *byte == 0)
+    {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&content).into_owned()))
+}
+
+/// `line` shortened to `SNIPPET_LENGTH` characters around the match at the character `start`
+fn snippet(line: &str, start: usize) -> String {
+    let chars = line.trim_end().chars().collect::<Vec<_>>();
+    if chars.len() <= SNIPPET_LENGTH {
+        return line.trim().to_string();
+    }
+    let start = start
+        .saturating_sub(SNIPPET_LENGTH / 4)
+        .min(chars.len() - SNIPPET_LENGTH);
+    let end = start + SNIPPET_LENGTH;
+    format!(
+        "{}{}{}",
+        if start > 0 { "…" } else { "" },
+        chars[start..end].iter().collect::<String>().trim(),
+        if end < chars.len() { "…" } else { "" }
+    )
+}
+
+/// Snippets of the lines of `text` containing the lowercase `query`
+fn matching_lines(text: &str, query: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let lowercase = line.to_lowercase();
+            let start = lowercase.find(query)?;
+            Some(snippet(line, lowercase[..start].chars().count()))
+        })
+        .take(MAX_SNIPPETS)
+        .collect()
+}
+
+/// Add the files of `dir` and of its subdirectories which may be searched to `files`, with their
+/// size and modification time
+fn find_files(
+    conf: &MiniserveConfig,
+    dir: &Path,
+    depth: usize,
+    ignored: Option<&IgnoredEntries>,
+    files: &mut HashMap<PathBuf, (u64, Option<SystemTime>)>,
+) -> io::Result<()> {
+    for entry in dir.read_dir()? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') && !conf.show_hidden {
+            continue;
+        }
+        if entry.file_type()?.is_symlink() && conf.no_symlinks {
+            continue;
+        }
+        // for symlinks, get the metadata of the original file
+        let metadata = match std::fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if ignored.is_some_and(|ignored| ignored.is_ignored(&name, metadata.is_dir())) {
+            continue;
+        }
+
+        if metadata.is_dir() {
+            if depth < MAX_DEPTH && !(conf.dir_config && dir_config::is_hidden(&entry.path())) {
+                // Unreadable subdirectories are left out of the index
+                let ignored = ignored.and_then(|ignored| ignored.subdir(&name));
+                let _ = find_files(conf, &entry.path(), depth + 1, ignored.as_ref(), files);
+            }
+        } else if metadata.is_file()
+            && !(conf.dir_config && name == DIR_CONFIG_FILE)
+            && !(conf.upload_ttl.is_some() && name == upload_ttl::EXPIRY_FILE)
+        {
+            if let Ok(path) = canonical_path::canonicalize(entry.path()) {
+                files.insert(path, (metadata.len(), metadata.modified().ok()));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl ContentIndex {
+    /// Whether the served directory was indexed once already, so that the results are complete
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    /// Index the new and modified files of the served directory, and forget the deleted ones.
+    /// Returns the number of text files whose content is indexed.
+    fn update(&self, conf: &MiniserveConfig) -> io::Result<usize> {
+        let root = canonical_path::canonicalize(&conf.path)?;
+        let ignored = conf
+            .ignore
+            .as_ref()
+            .and_then(|ignore| ignore.dir(&root, &root));
+        let mut found = HashMap::new();
+        find_files(conf, &root, 0, ignored.as_ref(), &mut found)?;
+
+        let changed = {
+            let files = self.files.read().unwrap_or_else(|e| e.into_inner());
+            found
+                .iter()
+                .filter(|(path, (size, modified))| {
+                    files
+                        .get(*path)
+                        .is_none_or(|file| file.size != *size || file.modified != *modified)
+                })
+                .map(|(path, (size, modified))| (path.clone(), *size, *modified))
+                .collect::<Vec<_>>()
+        };
+        // The files are read without blocking the searches meanwhile
+        let indexed = changed
+            .into_iter()
+            .map(|(path, size, modified)| {
+                // Larger files can only be found by their name
+                let trigrams = Some(&path)
+                    .filter(|_| size <= MAX_FILE_SIZE)
+                    .and_then(|path| read_text(path).ok().flatten())
+                    .map(|text| trigrams(&text.to_lowercase()));
+                let file = IndexedFile {
+                    size,
+                    modified,
+                    trigrams,
+                };
+                (path, file)
+            })
+            .collect::<Vec<_>>();
+
+        let mut files = self.files.write().unwrap_or_else(|e| e.into_inner());
+        files.retain(|path, _| found.contains_key(path));
+        files.extend(indexed);
+        self.ready.store(true, Ordering::Relaxed);
+        Ok(files
+            .values()
+            .filter(|file| file.trigrams.is_some())
+            .count())
+    }
+
+    /// Text files of `dir`, a canonical path, and of its subdirectories containing `query`, with
+    /// the lines containing it
+    fn search(&self, dir: &Path, query: &str) -> Vec<(PathBuf, Vec<String>)> {
+        let query = query.to_lowercase();
+        let query_trigrams = trigrams(&query);
+        let mut candidates = {
+            let files = self.files.read().unwrap_or_else(|e| e.into_inner());
+            files
+                .iter()
+                .filter(|(path, _)| path.starts_with(dir))
+                .filter(|(_, file)| {
+                    file.trigrams.as_ref().is_some_and(|trigrams| {
+                        query_trigrams
+                            .iter()
+                            .all(|trigram| trigrams.binary_search(trigram).is_ok())
+                    })
+                })
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>()
+        };
+        candidates.sort();
+
+        let mut results = vec![];
+        for path in candidates {
+            if results.len() >= MAX_RESULTS {
+                break;
+            }
+            let text = match read_text(&path) {
+                Ok(Some(text)) => text,
+                _ => continue,
+            };
+            let snippets = matching_lines(&text, &query);
+            if !snippets.is_empty() {
+                results.push((path, snippets));
+            }
+        }
+        results
+    }
+
+    /// Files of `dir`, a canonical path, and of its subdirectories whose path relative to `dir`
+    /// fuzzily matches `query`, best matches first, with the positions of the matched characters
+    fn fuzzy_search(&self, dir: &Path, query: &str) -> Vec<(PathBuf, Vec<usize>)> {
+        let mut results = {
+            let files = self.files.read().unwrap_or_else(|e| e.into_inner());
+            files
+                .keys()
+                .filter_map(|path| {
+                    let relative_path = relative_path(dir, path)?;
+                    let (score, matched) = fuzzy_match(&relative_path, query)?;
+                    Some((score, relative_path, path.clone(), matched))
+                })
+                .collect::<Vec<_>>()
+        };
+        // Shorter paths first when the scores are equal, as less of them is left unmatched
+        results.sort_by(|(score1, path1, ..), (score2, path2, ..)| {
+            score2
+                .cmp(score1)
+                .then(path1.chars().count().cmp(&path2.chars().count()))
+                .then(path1.cmp(path2))
+        });
+        results.truncate(MAX_RESULTS);
+        results
+            .into_iter()
+            .map(|(_, _, path, matched)| (path, matched))
+            .collect()
+    }
+
+    /// Update the index regularly while the server is running, starting right away
+    pub async fn index_regularly(index: web::Data<ContentIndex>, conf: MiniserveConfig) {
+        loop {
+            let (update_index, update_conf) = (index.clone(), conf.clone());
+            let start = Instant::now();
+            match web::block(move || update_index.update(&update_conf)).await {
+                Ok(Ok(count)) => {
+                    log::debug!("Indexed {} text files in {:.1?}", count, start.elapsed())
+                }
+                Ok(Err(e)) => log::error!("Failed to index the text files: {}", e),
+                Err(e) => log::error!("Failed to index the text files: {}", e),
+            }
+            actix_web::rt::time::sleep(REINDEX_INTERVAL).await;
+        }
+    }
+}
+
+/// Path of `path` relative to `dir`, with slashes
+fn relative_path(dir: &Path, path: &Path) -> Option<String> {
+    Some(
+        path.strip_prefix(dir)
+            .ok()?
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Whether the character at `position` of `chars` starts a word: it follows a separator, or it is
+/// an uppercase letter following a lowercase one
+fn is_word_start(chars: &[char], position: usize) -> bool {
+    match position.checked_sub(1).map(|previous| chars[previous]) {
+        None => true,
+        Some(previous) if "/_-. ".contains(previous) => true,
+        Some(previous) => previous.is_lowercase() && chars[position].is_uppercase(),
+    }
+}
+
+/// Score of the fuzzy match of `query` in `path`, with the positions of the matched characters,
+/// if the characters of the query appear in `path` in order, regardless of their case.
+///
+/// Like in fzf, matches at the start of words and consecutive matches score more, and gaps
+/// between the matches less. The characters are matched from the end of the path, so that
+/// matches in the file name are preferred to matches in the directories.
+fn fuzzy_match(path: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+    let lowercase = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let query = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(lowercase)
+        .collect::<Vec<_>>();
+    if query.is_empty() {
+        return None;
+    }
+    let chars = path.chars().collect::<Vec<_>>();
+
+    let mut matched = Vec::with_capacity(query.len());
+    let mut remaining = query.iter().rev().peekable();
+    for (position, c) in chars.iter().enumerate().rev() {
+        match remaining.peek() {
+            Some(expected) if lowercase(*c) == **expected => {
+                matched.push(position);
+                remaining.next();
+            }
+            Some(_) => (),
+            None => break,
+        }
+    }
+    if remaining.peek().is_some() {
+        return None;
+    }
+    matched.reverse();
+
+    let name_start = chars
+        .iter()
+        .rposition(|c| *c == '/')
+        .map_or(0, |slash| slash + 1);
+    let mut score = 0;
+    for (i, position) in matched.iter().enumerate() {
+        score += 16;
+        if is_word_start(&chars, *position) {
+            score += 8;
+        }
+        if *position >= name_start {
+            score += 2;
+        }
+        if let Some(previous) = i.checked_sub(1).map(|previous| matched[previous]) {
+            let gap = (position - previous - 1) as i64;
+            score += if gap == 0 { 8 } else { -gap.min(10) };
+        }
+    }
+    Some((score, matched))
+}
+
+/// Whether `head` searches the files of a directory of the served directory
+pub fn is_search_request(conf: &MiniserveConfig, head: &RequestHead) -> bool {
+    if head.method != Method::GET || conf.storage.is_some() {
+        return false;
+    }
+    if Query::<SearchQuery>::from_query(head.uri.query().unwrap_or_default()).is_err() {
+        return false;
+    }
+    dir_config::requested_entry(conf, head.uri.path()).is_some_and(|path| path.is_dir())
+}
+
+/// Search the files of the directory requested by `req`
+pub async fn search(req: HttpRequest, query: web::Query<SearchQuery>) -> HttpResponse {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let index = req.app_data::<web::Data<ContentIndex>>().unwrap().clone();
+    let indexing = !index.is_ready();
+    let is_ignored = |dir: &Path| {
+        conf.ignore
+            .as_ref()
+            .is_some_and(|ignore| !conf.serve_ignored && ignore.is_ignored(&conf.path, dir))
+    };
+    let dir = match dir_config::requested_entry(conf, req.path()) {
+        Some(dir) if dir.is_dir() && !is_ignored(&dir) => dir,
+        _ => return crate::error_404(req).await,
+    };
+    if let Err(err) = dir_config::check_access(&req, conf, &dir, false) {
+        return dir_config::access_denied_response(&req, err);
+    }
+    let dir = match canonical_path::canonicalize(&dir) {
+        Ok(dir) => dir,
+        Err(_) => return crate::error_404(req).await,
+    };
+
+    let dir_link = format!("{}/", req.path().trim_end_matches('/'));
+    let SearchQuery { q: query, mode } = query.into_inner();
+    let min_length = match mode {
+        SearchMode::Content => MIN_QUERY_LENGTH,
+        SearchMode::Fuzzy => 1,
+    };
+    let results = if query.trim().chars().count() < min_length {
+        None
+    } else {
+        let (search_dir, search_query) = (dir.clone(), query.trim().to_string());
+        let results = web::block(move || match mode {
+            SearchMode::Content => index
+                .search(&search_dir, &search_query)
+                .into_iter()
+                .map(|(path, snippets)| (path, vec![], snippets))
+                .collect(),
+            SearchMode::Fuzzy => index
+                .fuzzy_search(&search_dir, &search_query)
+                .into_iter()
+                .map(|(path, matched)| (path, matched, vec![]))
+                .collect(),
+        })
+        .await
+        .unwrap_or_else(|_| vec![]);
+        let matches = results
+            .into_iter()
+            // Files of restricted subdirectories are left out for users who can't read them
+            .filter(|(path, ..)| dir_config::check_access(&req, conf, path, false).is_ok())
+            .filter_map(|(path, matched, snippets)| {
+                let relative_path = relative_path(&dir, &path)?;
+                Some(SearchMatch {
+                    link: format!("{}{}", dir_link, encode_path(&relative_path)),
+                    path: relative_path,
+                    matched,
+                    snippets,
+                })
+            })
+            .collect::<Vec<_>>();
+        Some(matches)
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .body(
+            renderer::search_page(
+                &query,
+                mode,
+                results.as_deref(),
+                indexing,
+                &dir_link,
+                &conf.favicon_route,
+                &conf.css_route,
+                &conf.default_color_scheme,
+                &conf.default_color_scheme_dark,
+                conf.hide_version_footer,
+            )
+            .into_string(),
+        )
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn trigrams_are_deduplicated() {
+        assert_eq!(trigrams("abcabc"), vec![0x616263, 0x626361, 0x636162]);
+        assert_eq!(trigrams("ab"), Vec::<u32>::new());
+    }
+
+    #[rstest]
+    fn matching_lines_are_found() {
+        let text = "First line\nThe Invoice of May\nnothing\ninvoices\n";
+        assert_eq!(matching_lines(text, "invoice"), vec!["The Invoice of May", "invoices"]);
+    }
+
+    #[rstest(path, query, expected,
+        case("src/miniserve.rs", "mnsrv", Some(vec![4, 6, 8, 10, 11])),
+        case("README.md", "rdme", Some(vec![0, 3, 4, 5])),
+        case("README.md", "readme.txt", None),
+        case("README.md", "", None)
+    )]
+    fn fuzzy_matches_are_found(path: &str, query: &str, expected: Option<Vec<usize>>) {
+        assert_eq!(fuzzy_match(path, query).map(|(_, matched)| matched), expected);
+    }
+
+    #[rstest(query, better, worse,
+        case("rdme", "README.md", "docs/random/media.txt"),
+        case("inv", "invoice.pdf", "archive/in_review.txt"),
+        case("report", "2021/report.pdf", "report/2021.pdf"),
+        case("fb", "FooBar.txt", "fabric.txt")
+    )]
+    fn fuzzy_matches_are_ranked(query: &str, better: &str, worse: &str) {
+        let (better_score, _) = fuzzy_match(better, query).unwrap();
+        let (worse_score, _) = fuzzy_match(worse, query).unwrap();
+        assert!(better_score > worse_score, "{} <= {}", better_score, worse_score);
+    }
+
+    #[rstest]
+    fn long_lines_are_shortened() {
+        let line = format!("{}needle{}", "a".repeat(200), "b".repeat(200));
+        let snippet = snippet(&line, 200);
+        assert_eq!(snippet.chars().count(), SNIPPET_LENGTH + 2);
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains("needle"));
+    }
+}
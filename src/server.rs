@@ -0,0 +1,706 @@
+//! Server of a directory, started by the `miniserve` binary or embedded in another program with
+//! [`Server::builder`].
+use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use actix_web::{middleware, web, App};
+use anyhow::{anyhow, bail};
+use log::error;
+use structopt::StructOpt;
+
+use crate::args::CliArgs;
+use crate::errors::{self, ContextualError};
+use crate::middlewares::Middlewares;
+use crate::system_log::{LogTarget, SystemLog};
+use crate::MiniserveConfig;
+use crate::{
+    access_log, admin, auth, auto_shutdown, cache_control, canonical_url, case_insensitive,
+    compress, dir_config, disk_usage, download_stats, error_pages, file_upload, force_download,
+    hls, hooks, https, ignore, ip_filter, listing_cache, live_updates, log_file, manifest, metrics,
+    mime_types, network_interface, photo, proxy_protocol, search, share, stdin, telemetry,
+    throttle, timeout, torrent, upload_only, upload_ttl, upnp, user_dirs, webhook,
+};
+#[cfg(feature = "tls")]
+use crate::{acme, tls};
+use crate::{
+    add_custom_headers, configure_admin, configure_app, configure_file_ops, configure_login,
+    configure_metrics, configure_mounts, configure_share_links, configure_user_dirs,
+    configure_vhosts, css, error_404, favicon,
+};
+
+/// Builder of a [`Server`], accepting the options of the `miniserve` binary
+#[derive(Default)]
+pub struct ServerBuilder {
+    root: Option<PathBuf>,
+    addrs: Vec<SocketAddr>,
+    args: Vec<OsString>,
+}
+
+impl ServerBuilder {
+    /// Serve `path`, a directory or a single file
+    pub fn root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.root = Some(path.into());
+        self
+    }
+
+    /// Require the credentials of `username`, with `password` in any format of `--auth`, e.g.
+    /// `123` or `sha256:<hash>` (can be repeated)
+    pub fn auth(self, username: &str, password: &str) -> Self {
+        self.arg("--auth").arg(format!("{}:{}", username, password))
+    }
+
+    /// Listen on `addr` (can be repeated, with the same port). A port of 0 picks a free one, given
+    /// by [`RunningServer::addrs`].
+    pub fn bind(mut self, addr: impl Into<SocketAddr>) -> Self {
+        self.addrs.push(addr.into());
+        self
+    }
+
+    /// Add a command line argument of the `miniserve` binary, e.g. `--upload-files`
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Add command line arguments of the `miniserve` binary
+    pub fn args<I>(mut self, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Check the options, and build the server
+    pub fn build(self) -> anyhow::Result<Server> {
+        let root = match self.root {
+            Some(root) => root,
+            None => bail!("No path to serve"),
+        };
+
+        let mut args = vec![OsString::from("miniserve")];
+        if let Some(addr) = self.addrs.first() {
+            if self.addrs.iter().any(|other| other.port() != addr.port()) {
+                bail!("All the addresses must have the same port");
+            }
+            args.push("--port".into());
+            args.push(addr.port().to_string().into());
+        }
+        for addr in &self.addrs {
+            args.push("--interfaces".into());
+            args.push(addr.ip().to_string().into());
+        }
+        args.extend(self.args);
+        args.push("--".into());
+        args.push(root.into());
+
+        let args = CliArgs::from_iter_safe(args).map_err(|e| anyhow!(e.message))?;
+        Ok(Server::new(MiniserveConfig::try_from_args(args)?))
+    }
+
+    /// Build the server, and start it
+    pub async fn run(self) -> anyhow::Result<RunningServer> {
+        Ok(self.build()?.run().await?)
+    }
+}
+
+/// Server of a directory, like the one of the `miniserve` binary
+///
+/// It runs on the actix-web runtime of the current thread, e.g. in a function marked with
+/// `#[actix_web::main]`, and doesn't install a logger nor handle any signal.
+///
+/// ```no_run
+/// # async fn share() -> anyhow::Result<()> {
+/// let server = miniserve::Server::builder()
+///     .root("/srv/share")
+///     .auth("joe", "123")
+///     .bind(([127, 0, 0, 1], 8080))
+///     .run()
+///     .await?;
+///
+/// // The handle can stop the server from anywhere, e.g. when the user closes the share
+/// let handle = server.handle();
+/// actix_web::rt::spawn(async move { handle.stop(true).await });
+///
+/// server.wait().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Server {
+    conf: MiniserveConfig,
+}
+
+impl Server {
+    /// Start building a server
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// Server configured with `conf`, e.g. parsed from the command line
+    pub fn new(conf: MiniserveConfig) -> Self {
+        Server { conf }
+    }
+
+    /// Bind the sockets, and start accepting connections
+    pub async fn run(self) -> Result<RunningServer, ContextualError> {
+        #[cfg(feature = "tls")]
+        let miniserve_config = {
+            let mut miniserve_config = self.conf;
+            acme::configure_tls(&mut miniserve_config).await?;
+            miniserve_config
+        };
+        #[cfg(not(feature = "tls"))]
+        let miniserve_config = self.conf;
+
+        if miniserve_config.no_symlinks {
+            let is_symlink = miniserve_config
+                .path
+                .symlink_metadata()
+                .map_err(|e| {
+                    ContextualError::IoError("Failed to retrieve symlink's metadata".to_string(), e)
+                })?
+                .file_type()
+                .is_symlink();
+
+            if is_symlink {
+                return Err(ContextualError::NoSymlinksOptionWithSymlinkServePath(
+                    miniserve_config.path.to_string_lossy().to_string(),
+                ));
+            }
+        }
+
+        if let Some(index_path) = &miniserve_config.index {
+            let has_index: PathBuf = [&miniserve_config.path, index_path].iter().collect();
+            if !has_index.exists() {
+                error!(
+                    "The file '{}' provided for option --index could not be found.",
+                    index_path.to_string_lossy()
+                );
+            }
+        }
+
+        let inside_config = miniserve_config.clone();
+
+        let fail2ban_log = match &miniserve_config.auth_fail2ban_log {
+            Some(path) => Some(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| {
+                        ContextualError::IoError(
+                            format!("Failed to open {}", path.to_string_lossy()),
+                            e,
+                        )
+                    })?,
+            ),
+            None => None,
+        };
+        let auth_failures = web::Data::new(auth::AuthFailureTracker::new(
+            miniserve_config.auth_max_failures,
+            miniserve_config.auth_lockout,
+            fail2ban_log,
+        ));
+        let access_log = web::Data::new(access_log::AccessLog {
+            file: match &miniserve_config.log_file {
+                Some(path) => Some(
+                    log_file::LogFile::open(path.clone(), miniserve_config.log_rotation.clone())
+                        .map_err(|e| {
+                            ContextualError::IoError(
+                                format!("Failed to open {}", path.display()),
+                                e,
+                            )
+                        })?,
+                ),
+                None => None,
+            },
+            system: match miniserve_config.log_target {
+                LogTarget::Stdout => None,
+                target => Some(SystemLog::new(target, log::LevelFilter::Info).map_err(|e| {
+                    ContextualError::IoError(format!("Failed to connect to {}", target), e)
+                })?),
+            },
+        });
+        let metrics = web::Data::new(metrics::Metrics::default());
+        let admin_state = web::Data::new(admin::AdminState::new());
+        let listing_cache = web::Data::new(listing_cache::ListingCache::new(
+            miniserve_config.listing_cache,
+        ));
+        let live_updates = web::Data::new(live_updates::LiveUpdates::new(
+            miniserve_config.live_updates,
+        ));
+        let transfer_limits = web::Data::new(throttle::TransferLimits::new(
+            miniserve_config.max_connections,
+            miniserve_config.throttle_total,
+        ));
+        let upload_limits = web::Data::new(file_upload::UploadLimits::new(
+            miniserve_config.max_upload_size,
+            miniserve_config.upload_quota,
+            miniserve_config.quotas.clone(),
+            Some(miniserve_config.path.as_path()).filter(|_| miniserve_config.user_dirs),
+        ));
+        let auto_shutdown = web::Data::new(auto_shutdown::AutoShutdown::new(
+            miniserve_config.max_downloads,
+        ));
+        let torrent_cache = web::Data::new(torrent::TorrentCache::default());
+        let used_share_links = web::Data::new(share::UsedShareLinks::default());
+        let resumable_uploads = web::Data::new(file_upload::ResumableUploads::default());
+        let photo_metadata = web::Data::new(photo::PhotoMetadataCache::default());
+        let transcodes = web::Data::new(hls::Transcodes::default());
+        let content_index = web::Data::new(search::ContentIndex::default());
+        if miniserve_config.content_search_enabled && miniserve_config.storage.is_none() {
+            actix_web::rt::spawn(search::ContentIndex::index_regularly(
+                content_index.clone(),
+                miniserve_config.clone(),
+            ));
+        }
+        let manifest_cache = web::Data::new(manifest::ManifestCache::default());
+        let disk_usage = web::Data::new(disk_usage::DiskUsageCache::default());
+        let download_stats = web::Data::new(
+            download_stats::DownloadStats::load(miniserve_config.download_stats.clone()).map_err(
+                |e| {
+                    ContextualError::IoError(
+                        "Failed to read the download statistics".to_string(),
+                        e,
+                    )
+                },
+            )?,
+        );
+        if download_stats.is_enabled() {
+            actix_web::rt::spawn(download_stats::DownloadStats::save_regularly(
+                download_stats.clone(),
+            ));
+        }
+        let upload_expiry = web::Data::new(
+            upload_ttl::UploadExpiry::load(&miniserve_config.path, miniserve_config.upload_ttl)
+                .map_err(|e| {
+                    ContextualError::IoError(
+                        "Failed to read the expiry of the uploads".to_string(),
+                        e,
+                    )
+                })?,
+        );
+        if upload_expiry.is_enabled() {
+            actix_web::rt::spawn(upload_ttl::UploadExpiry::delete_regularly(
+                upload_expiry.clone(),
+            ));
+        }
+
+        let socket_addresses = miniserve_config
+            .interface_hosts()
+            .iter()
+            .map(|interface| {
+                format!(
+                    "{interface}:{port}",
+                    interface = &interface,
+                    port = miniserve_config.port,
+                )
+                .parse::<SocketAddr>()
+            })
+            .collect::<Result<Vec<SocketAddr>, _>>();
+
+        let socket_addresses = match socket_addresses {
+            Ok(addresses) => addresses,
+            Err(e) => {
+                // Note that this should never fail, since CLI parsing succeeded
+                // This means the format of each IP address is valid, and so is the port
+                // Valid IpAddr + valid port == valid SocketAddr
+                return Err(ContextualError::ParseError(
+                    "string as socket address".to_string(),
+                    e.to_string(),
+                ));
+            }
+        };
+
+        if !miniserve_config.network_interfaces.is_empty() {
+            actix_web::rt::spawn(network_interface::watch_addresses(miniserve_config.clone()));
+        }
+
+        // On Linux, the sockets of --interface are bound to the interfaces instead of their
+        // current addresses
+        #[cfg(target_os = "linux")]
+        let (socket_addresses, listeners) = if miniserve_config.network_interfaces.is_empty() {
+            (socket_addresses, vec![])
+        } else {
+            let listeners =
+                network_interface::bind_interfaces(&miniserve_config, miniserve_config.port)
+                    .map_err(|e| {
+                        ContextualError::IoError("Failed to bind server".to_string(), e)
+                    })?;
+            (vec![], listeners)
+        };
+
+        #[cfg(feature = "tls")]
+        if let Some(certificate) = &miniserve_config.tls_certificate {
+            actix_web::rt::spawn(tls::watch_certificate(certificate.clone()));
+        }
+
+        let connection_metrics = metrics.clone();
+        let connection_limits = transfer_limits.clone();
+        let idle_limits = transfer_limits.clone();
+        let metrics_enabled = miniserve_config.metrics_enabled;
+        let ip_filter = miniserve_config.ip_filter.clone();
+        let trusted_proxies = miniserve_config.trusted_proxies.clone();
+        let proxy_protocol = miniserve_config.proxy_protocol;
+        let app_download_stats = download_stats.clone();
+        let app_auto_shutdown = auto_shutdown.clone();
+        let srv = actix_web::HttpServer::new(move || {
+            let user_dirs = user_dirs::UserDirs::new(&inside_config);
+            App::new()
+                .wrap(
+                    Middlewares::new()
+                        .wrap_fn(mime_types::set_content_type)
+                        .wrap_fn(force_download::force_download)
+                        .wrap_fn(cache_control::set_cache_control)
+                        .wrap_fn(add_custom_headers)
+                        .wrap_fn(compress::skip_uncompressible),
+                )
+                .wrap(middleware::Compress::default())
+                .wrap_fn(compress::negotiate_encoding)
+                .wrap(
+                    Middlewares::new()
+                        .wrap_fn(throttle::throttle_transfers)
+                        .wrap_fn(timeout::limit_request_time),
+                )
+                .app_data(inside_config.clone())
+                .app_data(auth_failures.clone())
+                .app_data(upload_limits.clone())
+                .app_data(access_log.clone())
+                .app_data(metrics.clone())
+                .app_data(admin_state.clone())
+                .app_data(listing_cache.clone())
+                .app_data(live_updates.clone())
+                .app_data(transfer_limits.clone())
+                .app_data(app_download_stats.clone())
+                .app_data(torrent_cache.clone())
+                .app_data(manifest_cache.clone())
+                .app_data(disk_usage.clone())
+                .app_data(upload_expiry.clone())
+                .app_data(user_dirs.clone())
+                .app_data(app_auto_shutdown.clone())
+                .app_data(used_share_links.clone())
+                .app_data(resumable_uploads.clone())
+                .app_data(photo_metadata.clone())
+                .app_data(transcodes.clone())
+                .app_data(content_index.clone())
+                .wrap(
+                    Middlewares::new()
+                        .wrap_fn(case_insensitive::redirect_case_insensitive)
+                        .wrap_fn(canonical_url::redirect_to_canonical_url)
+                        .wrap_fn(upload_ttl::hide_expiry_file)
+                        .wrap_fn(upload_only::hide_uploads)
+                        .wrap_fn(ignore::hide_ignored)
+                        .wrap_fn(dir_config::enforce_dir_config)
+                        .wrap_fn(admin::enforce_read_only)
+                        .wrap_fn(user_dirs::select_user_dir)
+                        // we should use `actix_web_httpauth::middleware::HttpAuthentication`
+                        // but it is unfortuantrly broken
+                        // see: https://github.com/actix/actix-extras/issues/127
+                        // TODO replace this when fixed upstream
+                        .wrap_fn(auth::auth_middleware)
+                        .wrap_fn(https::enforce_https)
+                        .wrap_fn(ip_filter::filter_ip)
+                        .wrap_fn(error_pages::replace_error_page)
+                        .wrap_fn(hooks::hook_downloads)
+                        .wrap_fn(webhook::notify_downloads)
+                        .wrap_fn(download_stats::count_downloads)
+                        .wrap_fn(share::use_up_single_use_links)
+                        .wrap_fn(access_log::log_request)
+                        .wrap_fn(metrics::record_request)
+                        .wrap_fn(admin::record_request)
+                        .wrap_fn(telemetry::trace_request),
+                )
+                .wrap(middleware::Logger::default())
+                .route(
+                    &format!("/{}", inside_config.favicon_route),
+                    web::get().to(favicon),
+                )
+                .route(&format!("/{}", inside_config.css_route), web::get().to(css))
+                .configure(|c| configure_metrics(c, &inside_config))
+                .configure(|c| configure_admin(c, &inside_config))
+                .configure(|c| configure_login(c, &inside_config))
+                .configure(|c| configure_share_links(c, &inside_config))
+                .configure(|c| configure_file_ops(c, &inside_config))
+                .configure(|c| configure_vhosts(c, &inside_config))
+                .configure(|c| configure_mounts(c, &inside_config))
+                .configure(|c| configure_user_dirs(c, &user_dirs))
+                .configure(|c| configure_app(c, &inside_config))
+                .default_service(web::get().to(error_404))
+        });
+
+        let srv = srv.on_connect(move |conn, data| {
+            if proxy_protocol {
+                proxy_protocol::read_connection_header(conn, data);
+            }
+            ip_filter.drop_denied_connection(conn, data, &trusted_proxies);
+            throttle::TransferLimits::track_connection(&connection_limits, conn, data);
+            #[cfg(feature = "tls")]
+            auth::extract_client_certificate_name(conn, data);
+            if metrics_enabled {
+                metrics::Metrics::track_connection(&connection_metrics, data);
+            }
+        });
+
+        // Signals are left to the program running the server, so that SIGINT can also wait for
+        // transfers
+        let srv = srv
+            .disable_signals()
+            .shutdown_timeout(miniserve_config.shutdown_grace_period);
+
+        let keep_alive = match miniserve_config.keep_alive {
+            0 => None,
+            keep_alive => Some(keep_alive as usize),
+        };
+        let srv = srv
+            .keep_alive(keep_alive)
+            .client_timeout(miniserve_config.client_timeout * 1000);
+
+        #[cfg(feature = "tls")]
+        let srv = match miniserve_config.tls_rustls_config.clone() {
+            _ if socket_addresses.is_empty() => srv,
+            Some(tls_config) => srv
+                .bind_rustls(socket_addresses.as_slice(), tls_config)
+                .map_err(|e| ContextualError::IoError("Failed to bind server".to_string(), e))?,
+            None => srv
+                .bind(socket_addresses.as_slice())
+                .map_err(|e| ContextualError::IoError("Failed to bind server".to_string(), e))?,
+        };
+
+        #[cfg(target_os = "linux")]
+        let srv = listeners
+            .into_iter()
+            .try_fold(srv, |srv, listener| {
+                #[cfg(feature = "tls")]
+                if let Some(tls_config) = miniserve_config.tls_rustls_config.clone() {
+                    return srv.listen_rustls(listener, tls_config);
+                }
+                srv.listen(listener)
+            })
+            .map_err(|e| ContextualError::IoError("Failed to bind server".to_string(), e))?;
+
+        #[cfg(all(feature = "tls", target_os = "linux"))]
+        let srv = match miniserve_config.http_port {
+            Some(http_port) if !miniserve_config.network_interfaces.is_empty() => {
+                network_interface::bind_interfaces(&miniserve_config, http_port)
+                    .and_then(|listeners| {
+                        listeners
+                            .into_iter()
+                            .try_fold(srv, |srv, listener| srv.listen(listener))
+                    })
+                    .map_err(|e| {
+                        ContextualError::IoError(format!("Failed to bind port {}", http_port), e)
+                    })?
+            }
+            _ => srv,
+        };
+
+        #[cfg(feature = "tls")]
+        let srv = match miniserve_config.http_port {
+            Some(http_port) if !socket_addresses.is_empty() => {
+                let http_addresses = socket_addresses
+                    .iter()
+                    .map(|address| SocketAddr::new(address.ip(), http_port))
+                    .collect::<Vec<_>>();
+                srv.bind(http_addresses.as_slice()).map_err(|e| {
+                    ContextualError::IoError(format!("Failed to bind port {}", http_port), e)
+                })?
+            }
+            _ => srv,
+        };
+
+        #[cfg(not(feature = "tls"))]
+        let srv = if socket_addresses.is_empty() {
+            srv
+        } else {
+            srv.bind(socket_addresses.as_slice())
+                .map_err(|e| ContextualError::IoError("Failed to bind server".to_string(), e))?
+        };
+
+        // The Unix domain socket has no address
+        let addrs = srv.addrs();
+
+        #[cfg(unix)]
+        let srv = match &miniserve_config.unix_socket {
+            Some(path) => {
+                remove_stale_socket(path)?;
+                let srv = srv.bind_uds(path).map_err(|e| {
+                    ContextualError::IoError(format!("Failed to bind {}", path.display()), e)
+                })?;
+                if let Some(mode) = miniserve_config.unix_socket_mode {
+                    use std::os::unix::fs::PermissionsExt;
+                    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).map_err(
+                        |e| {
+                            ContextualError::IoError(
+                                format!("Failed to set the permissions of {}", path.display()),
+                                e,
+                            )
+                        },
+                    )?;
+                }
+                srv
+            }
+            None => srv,
+        };
+
+        let srv = srv.run();
+
+        auto_shutdown.set_server(srv.clone());
+        if let Some(idle_timeout) = miniserve_config.shutdown_after_idle {
+            actix_web::rt::spawn(auto_shutdown::AutoShutdown::stop_when_idle(
+                auto_shutdown,
+                idle_limits,
+                idle_timeout,
+            ));
+        }
+
+        let port_mapping = match addrs.first() {
+            Some(addr) if miniserve_config.upnp_forward => {
+                let port = addr.port();
+                match web::block(move || upnp::PortMapping::request(port)).await {
+                    Ok(Ok(mapping)) => {
+                        let mapping = Arc::new(mapping);
+                        actix_web::rt::spawn(upnp::PortMapping::renew_regularly(mapping.clone()));
+                        Some(mapping)
+                    }
+                    Ok(Err(e)) => {
+                        errors::log_error_chain(e.to_string());
+                        None
+                    }
+                    Err(e) => {
+                        error!("Failed to forward the port with UPnP: {}", e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        hooks::run_hooks(
+            &miniserve_config.hooks,
+            hooks::HookEvent::Startup,
+            &hooks::EventDetails {
+                path: Some(miniserve_config.path.to_string_lossy().to_string()),
+                addresses: Some(
+                    addrs
+                        .iter()
+                        .map(|addr| addr.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ),
+                ..hooks::EventDetails::default()
+            },
+        );
+
+        Ok(RunningServer {
+            srv,
+            addrs,
+            conf: miniserve_config,
+            download_stats,
+            port_mapping,
+        })
+    }
+}
+
+/// Server accepting connections, until it is stopped with its [`ServerHandle`]
+pub struct RunningServer {
+    srv: actix_web::dev::Server,
+    addrs: Vec<SocketAddr>,
+    conf: MiniserveConfig,
+    download_stats: web::Data<download_stats::DownloadStats>,
+    port_mapping: Option<Arc<upnp::PortMapping>>,
+}
+
+impl RunningServer {
+    /// Addresses the server listens on, with the ports which were picked for port 0
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    /// Configuration of the server, including the certificate obtained with ACME
+    pub fn config(&self) -> &MiniserveConfig {
+        &self.conf
+    }
+
+    /// URL the server can be reached at from the internet, through the port forwarded with
+    /// --upnp-forward
+    pub fn public_url(&self) -> Option<String> {
+        let mapping = self.port_mapping.as_ref()?;
+        let protocol = if self.conf.tls_rustls_config.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        let route = self
+            .conf
+            .random_route
+            .as_ref()
+            .map(|route| format!("/{}", route))
+            .unwrap_or_default();
+        Some(format!(
+            "{}://{}:{}{}",
+            protocol,
+            mapping.external_ip,
+            mapping.port(),
+            route
+        ))
+    }
+
+    /// Handle to stop the server
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle(self.srv.clone())
+    }
+
+    /// Wait until the server is stopped, and remove the files it created
+    pub async fn wait(self) -> Result<(), ContextualError> {
+        let result = self
+            .srv
+            .await
+            .map_err(|e| ContextualError::IoError("".to_owned(), e));
+
+        if let Some(path) = &self.conf.unix_socket {
+            let _ = std::fs::remove_file(path);
+        }
+        if let Some(path) = &self.conf.stdin_file {
+            stdin::remove_spool(path);
+        }
+        if let Err(e) = self.download_stats.save() {
+            error!("Failed to save the download statistics: {}", e);
+        }
+        if let Some(mapping) = self.port_mapping {
+            let _ = web::block(move || mapping.remove()).await;
+        }
+
+        result
+    }
+}
+
+/// Handle to stop a [`RunningServer`], which can be sent to other threads
+#[derive(Clone)]
+pub struct ServerHandle(actix_web::dev::Server);
+
+impl ServerHandle {
+    /// Stop accepting connections. With `graceful`, running transfers have the shutdown grace
+    /// period to finish, otherwise they are interrupted at once.
+    pub async fn stop(&self, graceful: bool) {
+        self.0.stop(graceful).await
+    }
+}
+
+/// Remove the Unix domain socket left at `path` by a previous run, if any
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> Result<(), ContextualError> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let is_socket = std::fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    if is_socket {
+        std::fs::remove_file(path).map_err(|e| {
+            ContextualError::IoError(format!("Failed to remove {}", path.display()), e)
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,240 @@
+//! Service definitions printed with `--print-service`, which run miniserve with the same arguments
+//! as a systemd unit or a launchd agent.
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+use crate::args::CliArgs;
+
+/// Name of the argument printing the service definition, which the service doesn't get
+const PRINT_SERVICE_ARG: &str = "--print-service";
+
+/// Label of the launchd agent
+const LAUNCHD_LABEL: &str = "miniserve";
+
+/// Service manager to write a definition for
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum ServiceManager {
+    /// Unit of the systemd system instance, running as a dynamic user in a sandbox
+    Systemd,
+
+    /// Agent of the launchd of the user, on macOS
+    Launchd,
+}
+
+/// Arguments the service runs miniserve with: those of the command line but the program name and
+/// `--print-service`. The served directory is added when it was implicit, as services have no
+/// terminal to confirm it.
+fn service_args(cli_args: &[OsString], args: &CliArgs, working_dir: &Path) -> Vec<String> {
+    let mut service_args = vec![];
+    if args.path.is_none() {
+        service_args.push(working_dir.to_string_lossy().to_string());
+    }
+
+    let mut cli_args = cli_args.iter().skip(1).map(|arg| arg.to_string_lossy());
+    while let Some(arg) = cli_args.next() {
+        if arg == PRINT_SERVICE_ARG {
+            cli_args.next();
+        } else if !arg.starts_with(&format!("{}=", PRINT_SERVICE_ARG)) {
+            service_args.push(arg.to_string());
+        }
+    }
+    service_args
+}
+
+/// Paths miniserve writes to, which the systemd sandbox has to leave writable
+fn writable_paths(args: &CliArgs, working_dir: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    if args.file_upload {
+        paths.push(working_dir.join(args.path.as_deref().unwrap_or_else(|| Path::new("."))));
+    }
+    let files = [&args.log_file, &args.download_stats];
+    paths.extend(
+        files
+            .iter()
+            .filter_map(|file| file.as_ref())
+            .map(|file| working_dir.join(file))
+            .filter_map(|file| file.parent().map(Path::to_path_buf)),
+    );
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Quote `arg` for the command lines of systemd units, whose specifiers and variables have to be
+/// escaped as well
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+    if !escaped.is_empty()
+        && !escaped
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\' || c == ';')
+    {
+        return escaped;
+    }
+    format!(
+        "\"{}\"",
+        escaped
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+/// Escape `text` for XML property lists
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// systemd unit running `exe` with `args`
+fn systemd_unit(exe: &Path, service_args: &[String], args: &CliArgs, working_dir: &Path) -> String {
+    let command = std::iter::once(exe.to_string_lossy().to_string())
+        .chain(service_args.iter().cloned())
+        .map(|arg| systemd_quote(&arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let writable_paths = writable_paths(args, working_dir)
+        .iter()
+        .map(|path| systemd_quote(&path.to_string_lossy()))
+        .collect::<Vec<_>>();
+
+    let mut unit = format!(
+        "[Unit]
+Description=miniserve
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+ExecStart={}
+WorkingDirectory={}
+Restart=on-failure
+DynamicUser=yes
+NoNewPrivileges=yes
+ProtectSystem=strict
+ProtectHome=read-only
+PrivateTmp=yes
+PrivateDevices=yes
+ProtectKernelTunables=yes
+ProtectKernelModules=yes
+ProtectControlGroups=yes
+RestrictAddressFamilies=AF_INET AF_INET6 AF_UNIX AF_NETLINK
+",
+        command,
+        systemd_quote(&working_dir.to_string_lossy())
+    );
+    if !writable_paths.is_empty() {
+        unit.push_str("# The dynamic user needs write permission on these paths as well\n");
+        unit.push_str(&format!("ReadWritePaths={}\n", writable_paths.join(" ")));
+    }
+    #[allow(unused_mut)]
+    let mut ports = vec![args.port];
+    #[cfg(feature = "tls")]
+    ports.extend(args.http_port);
+    if ports.iter().any(|port| *port < 1024) {
+        unit.push_str("AmbientCapabilities=CAP_NET_BIND_SERVICE\n");
+    }
+    unit.push_str(
+        "
+[Install]
+WantedBy=multi-user.target
+",
+    );
+    unit
+}
+
+/// launchd property list running `exe` with `args`
+fn launchd_plist(exe: &Path, service_args: &[String], working_dir: &Path) -> String {
+    let program_arguments = std::iter::once(exe.to_string_lossy().to_string())
+        .chain(service_args.iter().cloned())
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(&arg)))
+        .collect::<String>();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>WorkingDirectory</key>
+    <string>{}</string>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+</dict>
+</plist>
+"#,
+        LAUNCHD_LABEL,
+        program_arguments,
+        xml_escape(&working_dir.to_string_lossy())
+    )
+}
+
+/// Definition of a service of `manager` running miniserve with the arguments `cli_args`, parsed
+/// into `args`
+pub fn service_definition(
+    manager: ServiceManager,
+    cli_args: &[OsString],
+    args: &CliArgs,
+) -> std::io::Result<String> {
+    let exe = std::env::current_exe()?;
+    let working_dir = std::env::current_dir()?;
+    let service_args = service_args(cli_args, args, &working_dir);
+    Ok(match manager {
+        ServiceManager::Systemd => systemd_unit(&exe, &service_args, args, &working_dir),
+        ServiceManager::Launchd => launchd_plist(&exe, &service_args, &working_dir),
+    })
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+    use structopt::StructOpt;
+
+    fn parse(args: &[&str]) -> (Vec<OsString>, CliArgs) {
+        let cli_args = args.iter().map(OsString::from).collect::<Vec<_>>();
+        let args = CliArgs::from_iter(&cli_args);
+        (cli_args, args)
+    }
+
+    #[rstest(args, expected,
+        case(&["miniserve", "--print-service", "systemd", "-u", "/srv"], &["-u", "/srv"]),
+        case(&["miniserve", "/srv", "--print-service=launchd"], &["/srv"]),
+        case(&["miniserve", "-p", "80"], &["/home/joe", "-p", "80"])
+    )]
+    fn print_service_is_removed_from_args(args: &[&str], expected: &[&str]) {
+        let (cli_args, args) = parse(args);
+        assert_eq!(service_args(&cli_args, &args, Path::new("/home/joe")), expected);
+    }
+
+    #[rstest(arg, expected,
+        case("-u", "-u"),
+        case("/srv/my files", "\"/srv/my files\""),
+        case("100%", "100%%"),
+        case("$HOME", "$$HOME"),
+        case("", "\"\""),
+        case("a\"b", "\"a\\\"b\"")
+    )]
+    fn systemd_arguments_are_quoted(arg: &str, expected: &str) {
+        assert_eq!(systemd_quote(arg), expected);
+    }
+
+    #[rstest]
+    fn writable_paths_are_listed() {
+        let (_, args) = parse(&["miniserve", "-u", "--log-file", "logs/access.log", "srv"]);
+        assert_eq!(
+            writable_paths(&args, Path::new("/home/joe")),
+            vec![PathBuf::from("/home/joe/logs"), PathBuf::from("/home/joe/srv")]
+        );
+    }
+}
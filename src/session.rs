@@ -0,0 +1,356 @@
+//! Cookie-based login sessions, used as an alternative to HTTP basic authentication popups.
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use hmac::{Hmac, Mac, NewMac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::auth::{self, AuthFailureTracker, BasicAuthParams, RequiredAuth, RequiredAuthPassword};
+use crate::errors::{self, ContextualError};
+use crate::proxy;
+use crate::renderer;
+
+/// Name of the cookie holding the session token
+pub const SESSION_COOKIE: &str = "miniserve_session";
+
+/// Route of the login page
+pub const LOGIN_ROUTE: &str = "/__login";
+
+/// Route which ends the current session
+pub const LOGOUT_ROUTE: &str = "/__logout";
+
+/// Purpose of the signed tokens stored in session cookies, followed by the credentials of their
+/// account, see `session_token_purpose`
+const SESSION_TOKEN_PURPOSE: &str = "session";
+
+/// Query parameters of the login page
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    next: Option<String>,
+}
+
+/// Fields submitted by the login form
+#[derive(Deserialize)]
+pub struct LoginForm {
+    username: String,
+    password: String,
+    next: Option<String>,
+}
+
+/// Sign `data` with `key` using HMAC-SHA256
+pub fn sign(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Return `true` if `signature` is the signature of `data` with `key`
+pub fn verify(key: &[u8], data: &str, signature: &[u8]) -> bool {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac.verify(signature).is_ok()
+}
+
+/// Seconds elapsed since UNIX_EPOCH
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Build a token holding `value` which is valid until `expires` (UNIX timestamp).
+///
+/// `purpose` is part of the signature, so that a token can't be used in place of a token
+/// created for another purpose.
+pub fn create_signed_token(key: &[u8], purpose: &str, value: &str, expires: u64) -> String {
+    let data = format!("{}.{}", hex::encode(value), expires);
+    let signature = hex::encode(sign(key, &format!("{}:{}", purpose, data)));
+    format!("{}.{}", data, signature)
+}
+
+/// Return the value and expiration date stored in `token` if the token is authentic,
+/// was created for `purpose` and has not expired
+pub fn parse_signed_token(
+    key: &[u8],
+    purpose: &str,
+    token: &str,
+    now: u64,
+) -> Option<(String, u64)> {
+    let (data, signature) = token.rsplit_once('.')?;
+    if !verify(
+        key,
+        &format!("{}:{}", purpose, data),
+        &hex::decode(signature).ok()?,
+    ) {
+        return None;
+    }
+
+    let (value, expires) = data.split_once('.')?;
+    let expires = expires.parse::<u64>().ok()?;
+    if expires < now {
+        return None;
+    }
+
+    Some((String::from_utf8(hex::decode(value).ok()?).ok()?, expires))
+}
+
+/// Purpose of the session tokens of `auth`. Its password and permission are part of it, so that
+/// its sessions end when they are changed by reloading the configuration.
+fn session_token_purpose(auth: &RequiredAuth) -> String {
+    let password = match &auth.password {
+        RequiredAuthPassword::Plain(password) => format!("plain:{}", password),
+        RequiredAuthPassword::Sha256(hash) => format!("sha256:{}", hex::encode(hash)),
+        RequiredAuthPassword::Sha512(hash) => format!("sha512:{}", hex::encode(hash)),
+    };
+    format!(
+        "{}:{:?}:{}",
+        SESSION_TOKEN_PURPOSE, auth.permission, password
+    )
+}
+
+/// Return the account of `required_auth` logged in with the session cookie of `req`, if any
+pub fn get_session_auth<'a>(
+    req: &HttpRequest,
+    conf: &crate::MiniserveConfig,
+    required_auth: &'a [RequiredAuth],
+) -> Option<&'a RequiredAuth> {
+    if !conf.login_form {
+        return None;
+    }
+
+    let cookie = req.cookie(SESSION_COOKIE)?;
+    let now = unix_now();
+    required_auth.iter().find(|auth| {
+        parse_signed_token(
+            &conf.signing_key,
+            &session_token_purpose(auth),
+            cookie.value(),
+            now,
+        )
+        .is_some_and(|(username, _)| username == auth.username)
+    })
+}
+
+/// Return `true` if `req` targets a route which must stay reachable without being logged in
+pub fn is_public_route(req: &HttpRequest) -> bool {
+    req.path() == LOGIN_ROUTE || req.path() == LOGOUT_ROUTE
+}
+
+/// Build the URL of the login page, which will redirect to `req`'s URL once logged in
+pub fn login_url(req: &HttpRequest) -> String {
+    let next = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+    format!(
+        "{}?next={}",
+        LOGIN_ROUTE,
+        utf8_percent_encode(next, NON_ALPHANUMERIC)
+    )
+}
+
+/// Only allow redirections to local absolute paths after logging in. Browsers read backslashes
+/// as slashes and drop tabs and newlines, so paths containing them are refused too.
+fn sanitize_next(next: Option<&str>) -> String {
+    match next {
+        Some(next)
+            if next.starts_with('/')
+                && !next.starts_with("//")
+                && !next.chars().any(|c| c == '\\' || c.is_control()) =>
+        {
+            next.to_string()
+        }
+        _ => "/".to_string(),
+    }
+}
+
+/// Build the `Set-Cookie` header value holding `token`
+fn session_cookie(token: &str, lifetime: u64, secure: bool) -> String {
+    format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        SESSION_COOKIE,
+        token,
+        lifetime,
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+/// Render the login page
+fn login_page_response(
+    conf: &crate::MiniserveConfig,
+    error: Option<&str>,
+    next: &str,
+    status: StatusCode,
+) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .body(
+            renderer::login_page(
+                error,
+                next,
+                &conf.favicon_route,
+                &conf.css_route,
+                &conf.default_color_scheme,
+                &conf.default_color_scheme_dark,
+                conf.hide_version_footer,
+            )
+            .into_string(),
+        )
+}
+
+/// Handle requests to the login page
+pub async fn login_page(req: HttpRequest, query: web::Query<LoginQuery>) -> HttpResponse {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let next = sanitize_next(query.next.as_deref());
+
+    login_page_response(conf, None, &next, StatusCode::OK)
+}
+
+/// Handle submissions of the login form
+pub async fn login(req: HttpRequest, form: web::Form<LoginForm>) -> HttpResponse {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let failure_tracker = req.app_data::<web::Data<AuthFailureTracker>>().unwrap();
+    let client_ip = proxy::client_ip(&req);
+    let form = form.into_inner();
+    let next = sanitize_next(form.next.as_deref());
+
+    if let Some(remaining) = client_ip.and_then(|ip| failure_tracker.lockout_remaining(ip)) {
+        let err = ContextualError::TooManyAuthFailuresError(remaining.as_secs() + 1);
+        return login_page_response(
+            conf,
+            Some(&err.to_string()),
+            &next,
+            StatusCode::TOO_MANY_REQUESTS,
+        );
+    }
+
+    let cred = BasicAuthParams {
+        username: form.username,
+        password: form.password,
+    };
+
+    match auth::get_matching_auth(&cred, &conf.auth.get()) {
+        Some(auth) => {
+            if let Some(ip) = client_ip {
+                failure_tracker.record_success(ip);
+            }
+            let token = create_signed_token(
+                &conf.signing_key,
+                &session_token_purpose(auth),
+                &auth.username,
+                unix_now() + conf.session_lifetime,
+            );
+            HttpResponse::SeeOther()
+                .append_header((header::LOCATION, next))
+                .append_header((
+                    header::SET_COOKIE,
+                    session_cookie(
+                        &token,
+                        conf.session_lifetime,
+                        conf.tls_rustls_config.is_some(),
+                    ),
+                ))
+                .finish()
+        }
+        None => {
+            let err = ContextualError::HttpAuthenticationError(Box::new(
+                ContextualError::InvalidHttpCredentials,
+            ));
+            errors::log_error_chain(err.to_string());
+            if let Some(ip) = client_ip {
+                failure_tracker.record_failure(ip, &cred.username);
+                auth::run_auth_failure_hooks(conf, ip, &cred.username);
+            }
+            login_page_response(
+                conf,
+                Some("Invalid username or password"),
+                &next,
+                StatusCode::UNAUTHORIZED,
+            )
+        }
+    }
+}
+
+/// End the current session
+pub async fn logout() -> HttpResponse {
+    HttpResponse::SeeOther()
+        .append_header((header::LOCATION, LOGIN_ROUTE))
+        .append_header((header::SET_COOKIE, session_cookie("", 0, false)))
+        .finish()
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthPermission;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    const KEY: &[u8] = b"0123456789abcdef";
+
+    #[rstest(
+        username, expires, now, expected,
+        case("joe", 100, 50, Some("joe")),
+        case("joe", 100, 100, Some("joe")),
+        case("joe", 100, 101, None),
+        case("j.o:e", 100, 50, Some("j.o:e"))
+    )]
+    fn signed_token_roundtrip(username: &str, expires: u64, now: u64, expected: Option<&str>) {
+        let token = create_signed_token(KEY, "session", username, expires);
+        assert_eq!(
+            parse_signed_token(KEY, "session", &token, now).map(|(value, _)| value).as_deref(),
+            expected,
+        );
+    }
+
+    #[rstest]
+    fn signed_token_rejects_other_key() {
+        let token = create_signed_token(KEY, "session", "joe", 100);
+        assert_eq!(parse_signed_token(b"another key", "session", &token, 50), None);
+    }
+
+    #[rstest]
+    fn signed_token_rejects_other_purpose() {
+        let token = create_signed_token(KEY, "share", "joe", 100);
+        assert_eq!(parse_signed_token(KEY, "session", &token, 50), None);
+    }
+
+    #[rstest]
+    fn signed_token_rejects_tampering() {
+        let token = create_signed_token(KEY, "session", "joe", 100);
+        let tampered = token.replacen(&hex::encode("joe"), &hex::encode("admin"), 1);
+        assert_eq!(parse_signed_token(KEY, "session", &tampered, 50), None);
+    }
+
+    #[rstest]
+    fn session_tokens_depend_on_the_credentials() {
+        let auth = |password: &str, permission| RequiredAuth {
+            username: "joe".to_string(),
+            password: RequiredAuthPassword::Plain(password.to_string()),
+            permission,
+        };
+        let purpose = session_token_purpose(&auth("123", AuthPermission::ReadWrite));
+        assert_eq!(session_token_purpose(&auth("123", AuthPermission::ReadWrite)), purpose);
+        assert_ne!(session_token_purpose(&auth("456", AuthPermission::ReadWrite)), purpose);
+        assert_ne!(session_token_purpose(&auth("123", AuthPermission::ReadOnly)), purpose);
+    }
+
+    #[rstest(
+        next, expected,
+        case(None, "/"),
+        case(Some("/dir/"), "/dir/"),
+        case(Some("//evil.example.com"), "/"),
+        case(Some("https://evil.example.com"), "/"),
+        case(Some("/\\evil.example.com"), "/"),
+        case(Some("/\t/evil.example.com"), "/"),
+        case(Some("/dir\\file"), "/")
+    )]
+    fn sanitize_next_only_allows_local_paths(next: Option<&str>, expected: &str) {
+        assert_eq!(sanitize_next(next), expected);
+    }
+}
@@ -0,0 +1,301 @@
+//! Time-limited share links, granting read access to a file or directory without credentials.
+//!
+//! Links to a file can be single-use: once the file, or a part of it, was downloaded with it, the
+//! link answers 410 Gone. A request claims the link before the file is served, so that parallel
+//! requests can't all use it, and gives it back if it doesn't download anything. The used links
+//! are only remembered until miniserve restarts.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::web::{self, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use futures::future::TryFutureExt;
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Component, Path};
+use std::sync::Mutex;
+
+use crate::dir_config;
+use crate::errors::{self, ContextualError};
+use crate::listing::percent_encode_sets::PATH;
+use crate::proxy;
+use crate::session::{create_signed_token, parse_signed_token, unix_now};
+
+/// Route generating share links
+pub const SHARE_ROUTE: &str = "/__share";
+
+/// Name of the cookie which keeps a shared directory accessible while browsing it
+pub const SHARE_COOKIE: &str = "miniserve_share";
+
+/// Purpose of the signed tokens of share links
+const SHARE_TOKEN_PURPOSE: &str = "share";
+
+/// Purpose of the signed tokens of single-use share links
+const SINGLE_USE_SHARE_TOKEN_PURPOSE: &str = "share-once";
+
+/// Share links are valid for one day, unless specified otherwise
+const DEFAULT_SHARE_LIFETIME: u64 = 60 * 60 * 24;
+
+/// Query parameters of the share link generation route
+#[derive(Deserialize)]
+pub struct ShareQuery {
+    /// Path of the file or directory to share, as used in URLs
+    path: String,
+
+    /// Number of seconds the link remains valid for
+    expires_in: Option<u64>,
+
+    /// Whether the link stops working once the file was downloaded
+    #[serde(default)]
+    once: bool,
+}
+
+/// Query parameters carrying a share token
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: String,
+}
+
+/// Access granted by a share link. It is stored in the request extensions by the auth middleware.
+#[derive(Clone, Debug)]
+pub struct SharedAccess {
+    /// `Set-Cookie` header value which keeps the shared directory accessible, if it has to be set
+    pub cookie: Option<String>,
+
+    /// Token of the single-use link claimed by the request, which is given back unless it
+    /// downloads the file
+    pub single_use: Option<String>,
+}
+
+/// Single-use share links which were used, with their expiration dates
+#[derive(Default)]
+pub struct UsedShareLinks {
+    tokens: Mutex<HashMap<String, u64>>,
+}
+
+impl UsedShareLinks {
+    /// Remember that `token` was used, and return `false` if it already was. The expired tokens
+    /// are forgotten, as they are refused anyway.
+    fn claim(&self, token: &str, expires: u64) -> bool {
+        let now = unix_now();
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.retain(|_, expires| *expires >= now);
+        tokens.insert(token.to_string(), expires).is_none()
+    }
+
+    /// Forget that `token` was used, by a request which didn't download the file
+    fn release(&self, token: &str) {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.remove(token);
+    }
+}
+
+/// Return `true` if `path` is `shared_path`, or is inside of it when it is a directory
+fn is_path_shared(path: &str, shared_path: &str) -> bool {
+    if Path::new(path)
+        .components()
+        .any(|c| c == Component::ParentDir)
+    {
+        return false;
+    }
+
+    path == shared_path || (shared_path.ends_with('/') && path.starts_with(shared_path))
+}
+
+/// Return the access granted to `req` by a share token, if any.
+///
+/// The token is either found in the `token` query parameter or in the share cookie. A single-use
+/// token is claimed by the request, and fails if it was already used.
+pub fn get_shared_access(
+    req: &HttpRequest,
+    conf: &crate::MiniserveConfig,
+) -> Result<Option<SharedAccess>, ContextualError> {
+    if !conf.share_links {
+        return Ok(None);
+    }
+
+    let (token, from_query) = match Query::<TokenQuery>::from_query(req.query_string()) {
+        Ok(query) => (query.into_inner().token, true),
+        Err(_) => match req.cookie(SHARE_COOKIE) {
+            Some(cookie) => (cookie.value().to_string(), false),
+            None => return Ok(None),
+        },
+    };
+
+    let now = unix_now();
+    let parsed = parse_signed_token(&conf.signing_key, SHARE_TOKEN_PURPOSE, &token, now)
+        .map(|(shared_path, expires)| (shared_path, expires, false))
+        .or_else(|| {
+            parse_signed_token(
+                &conf.signing_key,
+                SINGLE_USE_SHARE_TOKEN_PURPOSE,
+                &token,
+                now,
+            )
+            .map(|(shared_path, expires)| (shared_path, expires, true))
+        });
+    let (shared_path, expires, single_use) = match parsed {
+        Some(parsed) => parsed,
+        None => return Ok(None),
+    };
+    let path = percent_decode_str(req.path()).decode_utf8_lossy();
+    if !is_path_shared(&path, &shared_path) {
+        return Ok(None);
+    }
+
+    if single_use {
+        let claimed = req
+            .app_data::<web::Data<UsedShareLinks>>()
+            .is_none_or(|used_links| used_links.claim(&token, expires));
+        if !claimed {
+            return Err(ContextualError::ShareLinkUsedError);
+        }
+        return Ok(Some(SharedAccess {
+            cookie: None,
+            single_use: Some(token),
+        }));
+    }
+
+    // Links inside of a shared directory don't carry the token, so it is kept in a cookie
+    let cookie = if from_query && shared_path.ends_with('/') {
+        Some(format!(
+            "{}={}; Path={}; Max-Age={}; HttpOnly; SameSite=Lax",
+            SHARE_COOKIE,
+            token,
+            utf8_percent_encode(&shared_path, PATH),
+            expires - now
+        ))
+    } else {
+        None
+    };
+
+    Ok(Some(SharedAccess {
+        cookie,
+        single_use: None,
+    }))
+}
+
+/// Give back the single-use share link claimed by a request, unless it downloads the file or a
+/// part of it
+pub fn use_up_single_use_links<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    srv.call(req).map_ok(|res| {
+        let req = res.request();
+        let downloaded = req.method() == Method::GET
+            && matches!(res.status(), StatusCode::OK | StatusCode::PARTIAL_CONTENT);
+        if downloaded {
+            return res;
+        }
+        let single_use = req
+            .extensions()
+            .get::<SharedAccess>()
+            .and_then(|access| access.single_use.clone());
+        if let (Some(token), Some(used_links)) =
+            (single_use, req.app_data::<web::Data<UsedShareLinks>>())
+        {
+            used_links.release(&token);
+        }
+        res
+    })
+}
+
+/// Generate a share link for the requested path, returned as plain text
+pub async fn create_share_link(req: HttpRequest, query: web::Query<ShareQuery>) -> HttpResponse {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    let shared_path = percent_decode_str(&query.path).decode_utf8_lossy();
+
+    if !shared_path.starts_with('/') {
+        let err = ContextualError::InvalidHttpRequestError(
+            "Invalid value for 'path' parameter".to_string(),
+        );
+        errors::log_error_chain(err.to_string());
+        return HttpResponse::BadRequest().body(err.to_string());
+    }
+    if query.once && shared_path.ends_with('/') {
+        let err = ContextualError::InvalidHttpRequestError(
+            "Single-use links can only share files".to_string(),
+        );
+        errors::log_error_chain(err.to_string());
+        return HttpResponse::BadRequest().body(err.to_string());
+    }
+    if let Some(entry) = dir_config::requested_entry(conf, &shared_path) {
+        if let Err(err) = dir_config::check_access(&req, conf, &entry, false) {
+            return dir_config::access_denied_response(&req, err);
+        }
+    }
+
+    let expires_in = query.expires_in.unwrap_or(DEFAULT_SHARE_LIFETIME);
+    let purpose = if query.once {
+        SINGLE_USE_SHARE_TOKEN_PURPOSE
+    } else {
+        SHARE_TOKEN_PURPOSE
+    };
+    let token = create_signed_token(
+        &conf.signing_key,
+        purpose,
+        &shared_path,
+        unix_now() + expires_in,
+    );
+
+    let link = format!(
+        "{}://{}{}?token={}",
+        proxy::scheme(&req),
+        proxy::host(&req),
+        utf8_percent_encode(&shared_path, PATH),
+        token
+    );
+
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .append_header((header::CACHE_CONTROL, "no-store"))
+        .body(link)
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(
+        path, shared_path, expected,
+        case("/file.txt", "/file.txt", true),
+        case("/file.txt.bak", "/file.txt", false),
+        case("/dir/", "/dir/", true),
+        case("/dir/file.txt", "/dir/", true),
+        case("/dir/sub/file.txt", "/dir/", true),
+        case("/dir2/file.txt", "/dir/", false),
+        case("/dir/../secret.txt", "/dir/", false),
+        case("/", "/dir/", false)
+    )]
+    fn shared_paths(path: &str, shared_path: &str, expected: bool) {
+        assert_eq!(is_path_shared(path, shared_path), expected);
+    }
+
+    #[rstest]
+    fn used_links_are_remembered_until_they_expire() {
+        let used_links = UsedShareLinks::default();
+        assert!(used_links.claim("expired", 0));
+        assert!(used_links.claim("valid", unix_now() + 60));
+        assert!(!used_links.claim("valid", unix_now() + 60));
+
+        assert!(used_links.claim("other", unix_now() + 60));
+        assert!(used_links.claim("expired", 0));
+    }
+
+    #[rstest]
+    fn released_links_can_be_claimed_again() {
+        let used_links = UsedShareLinks::default();
+        assert!(used_links.claim("token", unix_now() + 60));
+        used_links.release("token");
+        assert!(used_links.claim("token", unix_now() + 60));
+    }
+}
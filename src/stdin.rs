@@ -0,0 +1,41 @@
+//! Output of a command piped to miniserve with `--from-stdin`, served as a single file.
+//!
+//! The standard input is read until its end before miniserve starts listening, so that the file
+//! is served with its full size, and can be downloaded any number of times.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::errors::ContextualError;
+
+/// Name of the file when `--filename` is not given
+pub const DEFAULT_FILENAME: &str = "stdin.txt";
+
+/// Copy the standard input to a file named `filename` in a new temporary directory, and return
+/// the path of the file
+pub fn spool_stdin(filename: &str) -> Result<PathBuf, ContextualError> {
+    let dir = std::env::temp_dir().join(format!("miniserve-stdin-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).map_err(|e| {
+        ContextualError::IoError(format!("Failed to create directory {}", dir.display()), e)
+    })?;
+    let path = dir.join(filename);
+
+    let write_error =
+        |e| ContextualError::IoError(format!("Failed to write {}", path.display()), e);
+    let file = File::create(&path).map_err(write_error)?;
+    let mut out = BufWriter::new(file);
+    io::copy(&mut io::stdin().lock(), &mut out)
+        .and_then(|_| out.flush())
+        .map_err(|e| {
+            remove_spool(&path);
+            write_error(e)
+        })?;
+    Ok(path)
+}
+
+/// Remove the file written by `spool_stdin`, along with its directory
+pub fn remove_spool(path: &Path) {
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,255 @@
+//! Storage backends served as a read-only directory tree instead of the local filesystem, like
+//! an archive with `--from-archive` or an object storage bucket with `--s3-bucket`.
+//!
+//! Every backend answers the same requests, so that the listing, the downloads and the
+//! authentication work the same way whatever stores the files.
+use actix_web::body::SizedStream;
+use actix_web::http::{header, Method};
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse};
+use bytesize::ByteSize;
+use futures::future::LocalBoxFuture;
+use futures::stream::{self, LocalBoxStream, StreamExt};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::canonical_path;
+use crate::errors::{self, ContextualError};
+use crate::fragments::Fragments;
+use crate::listing::{self, percent_encode_sets::PATH_SEGMENT, Entry};
+use crate::MiniserveConfig;
+
+/// File or directory of a storage backend
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageEntry {
+    /// Name of the entry in its directory, empty for the root directory
+    pub name: String,
+
+    /// Size in bytes, `None` for directories
+    pub size: Option<u64>,
+
+    pub modified: Option<SystemTime>,
+}
+
+/// Content of a file, read from a storage backend while it is sent
+pub type ByteStream = LocalBoxStream<'static, io::Result<Bytes>>;
+
+/// Read-only directory tree served instead of the local filesystem.
+///
+/// Paths are relative, without leading or trailing slash, and the root directory is the empty
+/// path.
+pub trait Storage: Send + Sync {
+    /// Location of the storage, shown when miniserve starts
+    fn location(&self) -> String;
+
+    /// The file or directory at `path`, or `None` if it doesn't exist
+    fn entry<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<Option<StorageEntry>, ContextualError>>;
+
+    /// Files and directories directly in the directory `dir`
+    fn list<'a>(
+        &'a self,
+        dir: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<Vec<StorageEntry>, ContextualError>>;
+
+    /// Content of the file at `path`
+    fn read(
+        self: Arc<Self>,
+        path: PathBuf,
+    ) -> LocalBoxFuture<'static, Result<ByteStream, ContextualError>>;
+}
+
+/// Path of a storage entry, without the components which would lead outside of the storage
+pub fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir => (),
+            _ => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// Entry of the served directory at `relative_path`, unless it is hidden, or reached through a
+/// symlink, while these aren't served
+pub fn served_path(conf: &MiniserveConfig, relative_path: &str) -> Option<PathBuf> {
+    served_entry(conf, Path::new(relative_path.trim_start_matches('/')))
+}
+
+/// Entry of the served directory at `relative_path`, like `served_path`, for the paths which may
+/// not be valid UTF-8
+pub fn served_entry(conf: &MiniserveConfig, relative_path: &Path) -> Option<PathBuf> {
+    let relative_path = normalize(relative_path.strip_prefix("/").unwrap_or(relative_path))?;
+    let is_hidden = relative_path
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'));
+    if is_hidden && !conf.show_hidden {
+        return None;
+    }
+
+    let path = conf.path.join(&relative_path);
+    if conf.no_symlinks
+        && canonical_path::canonicalize(&path).ok()?
+            != canonical_path::join(
+                &canonical_path::canonicalize(&conf.path).ok()?,
+                &relative_path,
+            )
+    {
+        return None;
+    }
+    Some(path)
+}
+
+/// File of the served directory requested at the URL path `uri_path`, see `served_path`
+pub fn requested_file(conf: &MiniserveConfig, uri_path: &str) -> Option<PathBuf> {
+    let route = format!("/{}", conf.random_route.clone().unwrap_or_default());
+    let encoded_path = uri_path.strip_prefix(&route)?;
+    let decoded_path = percent_decode_str(encoded_path).decode_utf8_lossy();
+    if decoded_path.trim_matches('/').is_empty() {
+        return None;
+    }
+    served_path(conf, &decoded_path)
+}
+
+/// Convenience method for creating plain text responses, if the storage can't be read
+fn storage_error(err: ContextualError) -> HttpResponse {
+    errors::log_error_chain(err.to_string());
+    let mut resp = match &err {
+        ContextualError::StorageError(_) => HttpResponse::BadGateway(),
+        _ => HttpResponse::InternalServerError(),
+    };
+    resp.content_type("text/plain; charset=utf-8")
+        .body(err.to_string())
+}
+
+/// Handle the requests for the files and directories of the storage backend
+pub async fn storage_handler(req: HttpRequest) -> HttpResponse {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let storage = match &conf.storage {
+        Some(storage) => storage.clone(),
+        None => return crate::error_404(req).await,
+    };
+
+    let route = format!("/{}", conf.random_route.clone().unwrap_or_default());
+    let encoded_path = req
+        .path()
+        .strip_prefix(&route)
+        .unwrap_or_default()
+        .trim_start_matches('/');
+    let decoded_path = percent_decode_str(encoded_path).decode_utf8_lossy();
+    let path = match normalize(Path::new(&*decoded_path)) {
+        Some(path) => path,
+        None => return crate::error_404(req).await,
+    };
+
+    let entry = match storage.entry(&path).await {
+        Ok(Some(entry)) => entry,
+        Ok(None) => return crate::error_404(req).await,
+        Err(err) => return storage_error(err),
+    };
+    let size = match entry.size {
+        Some(size) => size,
+        None => {
+            if !req.path().ends_with('/') {
+                return HttpResponse::Found()
+                    .append_header((header::LOCATION, format!("{}/", req.path())))
+                    .finish();
+            }
+            return match storage.list(&path).await {
+                Ok(entries) => storage_listing(&req, entries, conf),
+                Err(err) => storage_error(err),
+            };
+        }
+    };
+
+    let content_type = mime_guess::from_path(&path).first_or_octet_stream();
+    let content = if req.method() == Method::HEAD {
+        stream::empty().boxed_local()
+    } else {
+        match storage.read(path).await {
+            Ok(content) => content,
+            Err(err) => return storage_error(err),
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type(content_type.to_string())
+        .body(SizedStream::new(size, content))
+}
+
+/// List the `entries` of a directory of the storage
+fn storage_listing(
+    req: &HttpRequest,
+    entries: Vec<StorageEntry>,
+    conf: &MiniserveConfig,
+) -> HttpResponse {
+    let base = Path::new(req.path());
+    let entries = entries
+        .into_iter()
+        .filter(|entry| conf.show_hidden || !entry.name.starts_with('.'))
+        .map(|entry| {
+            let link = base
+                .join(utf8_percent_encode(&entry.name, PATH_SEGMENT).to_string())
+                .to_string_lossy()
+                .to_string();
+            let entry_type = match entry.size {
+                Some(_) => listing::EntryType::File,
+                None => listing::EntryType::Directory,
+            };
+            Entry::new(
+                entry.name,
+                entry_type,
+                false,
+                link,
+                entry.size.map(ByteSize::b),
+                entry.modified,
+            )
+        })
+        .collect();
+
+    // Uploads, archive downloads and fragments don't apply to read-only storages
+    listing::render_listing(
+        req,
+        entries,
+        None,
+        false,
+        conf.random_route.clone(),
+        conf.favicon_route.clone(),
+        conf.css_route.clone(),
+        &conf.default_color_scheme,
+        &conf.default_color_scheme_dark,
+        conf.show_qrcode,
+        String::new(),
+        false,
+        false,
+        false,
+        conf.dirs_first,
+        conf.hide_version_footer,
+        conf.title.clone(),
+        Fragments::default(),
+    )
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(path, expected,
+        case("a/./b", Some("a/b")),
+        case("/a", None),
+        case("a/../../b", None)
+    )]
+    fn normalize_paths(path: &str, expected: Option<&str>) {
+        assert_eq!(normalize(Path::new(path)), expected.map(PathBuf::from));
+    }
+}
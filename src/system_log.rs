@@ -0,0 +1,234 @@
+//! Messages and access log written to syslog or journald with `--log-target`, for the servers on
+//! which the standard output isn't captured.
+//!
+//! Syslog gets a line per message on `/dev/log`. Journald gets the native protocol on its socket,
+//! in which the priority, the module and the details of the requests, like the client IP, are
+//! fields of their own that can be matched with `journalctl CLIENT_IP=...`.
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::io;
+use std::path::{Path, PathBuf};
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+/// Name miniserve logs under
+const IDENTIFIER: &str = "miniserve";
+
+/// Syslog facility of the messages, which is "daemon"
+const FACILITY: u8 = 3;
+
+/// Module of the messages of the `Logger` middleware of actix-web, which are replaced by the
+/// structured access log
+const ACTIX_LOGGER: &str = "actix_web::middleware::logger";
+
+/// Where the messages are written
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum LogTarget {
+    /// The terminal, or the standard output if it isn't one
+    Stdout,
+
+    /// The local syslog daemon, through /dev/log
+    Syslog,
+
+    /// The systemd journal, with structured fields
+    Journald,
+}
+
+impl LogTarget {
+    /// Socket of the system logger
+    fn socket_path(self) -> Option<&'static Path> {
+        match self {
+            LogTarget::Stdout => None,
+            LogTarget::Syslog => Some(Path::new("/dev/log")),
+            LogTarget::Journald => Some(Path::new("/run/systemd/journal/socket")),
+        }
+    }
+}
+
+/// Syslog severity of the messages of `level`
+fn priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Line of a syslog message, in the format of RFC 3164 without the timestamp, which the daemon
+/// adds
+fn syslog_line(level: Level, message: &str) -> Vec<u8> {
+    let line = format!(
+        "<{}>{}[{}]: {}",
+        FACILITY * 8 + priority(level),
+        IDENTIFIER,
+        std::process::id(),
+        message
+    );
+    line.into_bytes()
+}
+
+/// Datagram of a journald message, in its native protocol. The values spanning several lines are
+/// prefixed with their length instead of being followed by `=`.
+fn journald_datagram(level: Level, message: &str, fields: &[(&str, &str)]) -> Vec<u8> {
+    let priority = priority(level).to_string();
+    let pid = std::process::id().to_string();
+    let mut datagram = Vec::new();
+    let all_fields = [
+        ("MESSAGE", message),
+        ("PRIORITY", priority.as_str()),
+        ("SYSLOG_IDENTIFIER", IDENTIFIER),
+        ("SYSLOG_PID", pid.as_str()),
+    ];
+    for (name, value) in all_fields.iter().chain(fields) {
+        datagram.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            datagram.push(b'\n');
+            datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            datagram.push(b'=');
+        }
+        datagram.extend_from_slice(value.as_bytes());
+        datagram.push(b'\n');
+    }
+    datagram
+}
+
+/// Connection to syslog or journald
+pub struct SystemLog {
+    target: LogTarget,
+
+    /// Socket of the system logger, which is looked up for every message so that the logger can
+    /// be restarted
+    path: PathBuf,
+
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+
+    /// Most verbose level of the messages which are written
+    level: LevelFilter,
+}
+
+impl SystemLog {
+    /// Connection to the system logger of `target`, writing the messages up to `level`
+    pub fn new(target: LogTarget, level: LevelFilter) -> io::Result<Self> {
+        let path = target
+            .socket_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a system logger"))?;
+        SystemLog::with_path(target, path.to_path_buf(), level)
+    }
+
+    #[cfg(unix)]
+    fn with_path(target: LogTarget, path: PathBuf, level: LevelFilter) -> io::Result<Self> {
+        if !path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} doesn't exist", path.display()),
+            ));
+        }
+        Ok(SystemLog {
+            target,
+            path,
+            socket: std::os::unix::net::UnixDatagram::unbound()?,
+            level,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn with_path(target: LogTarget, _path: PathBuf, _level: LevelFilter) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("Logging to {} is only supported on Unix", target),
+        ))
+    }
+
+    /// Log the messages of miniserve to the system logger of `target`, up to `level`
+    pub fn init(target: LogTarget, level: LevelFilter) -> io::Result<()> {
+        let logger = SystemLog::new(target, level)?;
+        log::set_boxed_logger(Box::new(logger))
+            .map(|()| log::set_max_level(level))
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    /// Write `message`, whatever the level of the logger. The structured `fields` are only kept by
+    /// journald.
+    pub fn send(&self, level: Level, message: &str, fields: &[(&str, &str)]) {
+        let datagram = match self.target {
+            LogTarget::Journald => journald_datagram(level, message, fields),
+            _ => syslog_line(level, message),
+        };
+        #[cfg(unix)]
+        if let Err(e) = self.socket.send_to(&datagram, &self.path) {
+            // Logging the failure would fail as well
+            eprintln!("Failed to write to {}: {}", self.target, e);
+        }
+        #[cfg(not(unix))]
+        let _ = (datagram, &self.path);
+    }
+}
+
+impl Log for SystemLog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Requests are logged by the access log, with their details as fields
+        metadata.level() <= self.level && !metadata.target().starts_with(ACTIX_LOGGER)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let module = record.module_path().unwrap_or_else(|| record.target());
+        self.send(
+            record.level(),
+            &record.args().to_string(),
+            &[("CODE_MODULE", module)],
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn syslog_lines_have_a_priority() {
+        assert_eq!(
+            String::from_utf8(syslog_line(Level::Warn, "Disk full")).unwrap(),
+            format!("<28>miniserve[{}]: Disk full", std::process::id())
+        );
+    }
+
+    #[rstest]
+    fn journald_fields_are_encoded() {
+        let datagram = journald_datagram(Level::Error, "two\nlines", &[("CLIENT_IP", "::1")]);
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"two\nlines\nPRIORITY=3\nSYSLOG_IDENTIFIER=miniserve\n");
+        expected.extend_from_slice(format!("SYSLOG_PID={}\nCLIENT_IP=::1\n", std::process::id()).as_bytes());
+        assert_eq!(datagram, expected);
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    fn messages_are_sent_to_the_socket() {
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("journal.socket");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        let logger = SystemLog::with_path(LogTarget::Journald, path, LevelFilter::Info).unwrap();
+        assert!(!logger.enabled(&Metadata::builder().level(Level::Debug).build()));
+        assert!(!logger.enabled(&Metadata::builder().level(Level::Info).target(ACTIX_LOGGER).build()));
+
+        logger.send(Level::Info, "GET /", &[("CLIENT_IP", "::1")]);
+        let mut buffer = [0; 1024];
+        let len = receiver.recv(&mut buffer).unwrap();
+        let datagram = String::from_utf8_lossy(&buffer[..len]);
+        assert!(datagram.starts_with("MESSAGE=GET /\nPRIORITY=6\n"));
+        assert!(datagram.ends_with("CLIENT_IP=::1\n"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Last lines of the files of the served directory, requested with `?tail=100` when
+//! `--enable-tail` is given, and followed with `?tail=100&follow=1` like with `tail -f`.
+//!
+//! Followed files are checked for appended data every `FOLLOW_INTERVAL`, which is sent in the
+//! chunks of the response. Files which were truncated, e.g. by a log rotation, are followed from
+//! their start again.
+use actix_web::dev::{BodyEncoding, RequestHead};
+use actix_web::http::{header, ContentEncoding, Method};
+use actix_web::web::{self, Bytes, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::storage::requested_file;
+use crate::MiniserveConfig;
+
+/// Maximum number of lines which can be requested
+const MAX_TAIL_LINES: usize = 10_000;
+
+/// How often followed files are checked for appended data
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Size of the blocks read from the end of the files, and of the chunks of appended data
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Query parameters of the tail of a file
+#[derive(Deserialize)]
+pub struct TailQuery {
+    tail: usize,
+
+    /// Whether the appended data is sent as well, with `follow=1` or `follow=true`
+    follow: Option<String>,
+}
+
+impl TailQuery {
+    fn follow(&self) -> bool {
+        matches!(self.follow.as_deref(), Some("1") | Some("true"))
+    }
+}
+
+/// Whether `head` requests the last lines of a file of the served directory
+pub fn is_tail_request(conf: &MiniserveConfig, head: &RequestHead) -> bool {
+    if head.method != Method::GET {
+        return false;
+    }
+    if Query::<TailQuery>::from_query(head.uri.query().unwrap_or_default()).is_err() {
+        return false;
+    }
+    requested_file(conf, head.uri.path()).is_some_and(|file| file.is_file())
+}
+
+/// Index of the start of the last `lines` lines of `data`, if it has that many
+fn lines_start(data: &[u8], lines: usize) -> Option<usize> {
+    if lines == 0 {
+        return Some(data.len());
+    }
+    // The final line break doesn't start another line
+    let content = data.strip_suffix(b"\n").unwrap_or(data);
+    content
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, &byte)| byte == b'\n')
+        .nth(lines - 1)
+        .map(|(i, _)| i + 1)
+}
+
+/// Last `lines` lines of `file`, with the offset of the end of the file
+fn last_lines(file: &mut File, lines: usize) -> io::Result<(Vec<u8>, u64)> {
+    let end = file.seek(SeekFrom::End(0))?;
+    let mut start = end;
+    let mut tail = Vec::new();
+    loop {
+        if let Some(i) = lines_start(&tail, lines) {
+            tail.drain(..i);
+            break;
+        }
+        if start == 0 {
+            break;
+        }
+        let block_size = (start as usize).min(BLOCK_SIZE);
+        start -= block_size as u64;
+        let mut block = vec![0; block_size];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&tail);
+        tail = block;
+    }
+    Ok((tail, end))
+}
+
+/// Data appended to the file at `path` since `offset`, and the offset of its end. The file is
+/// read from its start again if it was truncated.
+fn appended_data(path: &Path, offset: u64) -> io::Result<(Vec<u8>, u64)> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let offset = if len < offset { 0 } else { offset };
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::new();
+    file.take(BLOCK_SIZE as u64).read_to_end(&mut data)?;
+    let end = offset + data.len() as u64;
+    Ok((data, end))
+}
+
+/// Stream of the data appended to the file at `path` from `offset`, for as long as the client is
+/// connected
+fn follow(path: PathBuf, offset: u64) -> impl futures::Stream<Item = actix_web::Result<Bytes>> {
+    futures::stream::unfold((path, offset), |(path, mut offset)| async move {
+        loop {
+            let file = path.clone();
+            let result = web::block(move || appended_data(&file, offset))
+                .await
+                .unwrap_or_else(|e| Err(io::Error::other(e.to_string())));
+            match result {
+                Ok((data, end)) if !data.is_empty() => {
+                    return Some((Ok(Bytes::from(data)), (path, end)));
+                }
+                Ok((_, end)) => offset = end,
+                Err(e) => {
+                    log::warn!("Stopped following {}: {}", path.display(), e);
+                    return None;
+                }
+            }
+            actix_web::rt::time::sleep(FOLLOW_INTERVAL).await;
+        }
+    })
+}
+
+/// Send the last lines of the file requested by `req`, and then its appended data if followed
+pub async fn tail_file(req: HttpRequest, query: web::Query<TailQuery>) -> HttpResponse {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let file = match requested_file(conf, req.path()) {
+        Some(file) if file.is_file() => file,
+        _ => return crate::error_404(req).await,
+    };
+    if query.tail > MAX_TAIL_LINES {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("At most {} lines can be requested", MAX_TAIL_LINES));
+    }
+
+    let lines = query.tail;
+    let path = file.clone();
+    let tail =
+        web::block(move || File::open(&path).and_then(|mut file| last_lines(&mut file, lines)))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e.to_string())));
+    let (tail, end) = match tail {
+        Ok(tail) => tail,
+        Err(e) => {
+            log::error!("Failed to read the end of {}: {}", file.display(), e);
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body("Failed to read the file");
+        }
+    };
+
+    let mut res = HttpResponse::Ok();
+    res.content_type("text/plain; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-cache"));
+    if !query.follow() {
+        return res.body(tail);
+    }
+    // Compression would hold the appended data back
+    res.encoding(ContentEncoding::Identity)
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(futures::StreamExt::chain(
+            futures::stream::once(futures::future::ok(Bytes::from(tail))),
+            Box::pin(follow(file, end)),
+        ))
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(content, lines, expected,
+        case("a\nb\nc\n", 2, "b\nc\n"),
+        case("a\nb\nc", 2, "b\nc"),
+        case("a\nb\nc\n", 5, "a\nb\nc\n"),
+        case("a\nb\nc\n", 0, ""),
+        case("", 3, "")
+    )]
+    fn last_lines_are_read(content: &str, lines: usize, expected: &str) {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, content).unwrap();
+
+        let (tail, end) = last_lines(&mut File::open(&path).unwrap(), lines).unwrap();
+        assert_eq!(String::from_utf8(tail).unwrap(), expected);
+        assert_eq!(end, content.len() as u64);
+    }
+
+    #[rstest]
+    fn truncated_files_are_read_again() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "new\n").unwrap();
+
+        let (data, end) = appended_data(&path, 100).unwrap();
+        assert_eq!(data, b"new\n");
+        assert_eq!(end, 4);
+    }
+}
@@ -0,0 +1,84 @@
+//! Tracing spans of the requests, archive creations and uploads, exported to an OpenTelemetry
+//! collector with `--otlp-endpoint`, so that slow requests can be diagnosed.
+//!
+//! Without the `otlp` feature, or without `--otlp-endpoint`, nothing collects the spans, which
+//! then cost next to nothing.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use futures::future::TryFutureExt;
+use std::future::Future;
+use tracing::Instrument;
+
+#[cfg(feature = "otlp")]
+use crate::errors::ContextualError;
+use crate::proxy;
+
+/// Export the spans to the OTLP collector at `endpoint`, e.g. http://localhost:4317
+#[cfg(feature = "otlp")]
+pub fn init(endpoint: &str) -> Result<(), ContextualError> {
+    use opentelemetry::sdk::{trace, Resource};
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                "miniserve",
+            )])),
+        )
+        // The workers of actix-web run single threaded runtimes
+        .install_batch(opentelemetry::runtime::TokioCurrentThread)
+        .map_err(|e| ContextualError::TelemetryError(e.to_string()))?;
+
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber)
+        .map_err(|e| ContextualError::TelemetryError(e.to_string()))
+}
+
+/// Send the spans which weren't exported yet
+#[cfg(feature = "otlp")]
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Trace the handling of `req`, which the spans of the archive creations and uploads are part of
+pub fn trace_request<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let (http_req, payload) = req.into_parts();
+    let client_ip = proxy::client_ip(&http_req);
+    let req = ServiceRequest::from_parts(http_req, payload);
+
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format!("HTTP {}", req.method()),
+        otel.kind = "server",
+        http.method = %req.method(),
+        http.target = %req.path(),
+        http.client_ip = ?client_ip,
+        http.status_code = tracing::field::Empty,
+    );
+
+    // The middlewares and handlers may do some work before returning their future
+    let call = {
+        let _entered = span.enter();
+        srv.call(req)
+    };
+    call.instrument(span.clone()).map_ok(move |res| {
+        span.record("http.status_code", &res.status().as_u16());
+        res
+    })
+}
@@ -0,0 +1,257 @@
+//! Bandwidth limits of downloads with `--throttle` and of uploads with `--throttle-upload`, and
+//! limits shared by all the clients with `--throttle-total` and `--max-connections`.
+//!
+//! Every request is limited on its own. As requests on a connection are handled one after the
+//! other, this limits every connection.
+use actix_web::body::{AnyBody, BodySize, MessageBody};
+use actix_web::dev::{Extensions, Payload, Service, ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::rt::time::{sleep_until, Sleep};
+use actix_web::web::{self, Bytes};
+use actix_web::HttpMessage;
+use futures::{ready, Stream};
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::ip_filter;
+use crate::MiniserveConfig;
+
+/// Limits shared by all the connections
+pub struct TransferLimits {
+    max_connections: Option<usize>,
+    active_connections: AtomicUsize,
+
+    /// Time at which a connection was last opened or closed
+    last_connection: Mutex<Instant>,
+
+    /// Bytes per second of all the transfers together
+    total_rate: Option<u64>,
+
+    /// Time until which the data transferred so far fills `total_rate`
+    next_transfer: Mutex<Instant>,
+}
+
+/// Slot of an open connection, released when the connection is closed
+struct ConnectionSlot(web::Data<TransferLimits>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+        *self
+            .0
+            .last_connection
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+}
+
+impl TransferLimits {
+    pub fn new(max_connections: Option<usize>, total_rate: Option<u64>) -> Self {
+        TransferLimits {
+            max_connections,
+            active_connections: AtomicUsize::new(0),
+            last_connection: Mutex::new(Instant::now()),
+            total_rate,
+            next_transfer: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Count the new connection `conn` until it is closed, and close it right away if there are
+    /// already `--max-connections` connections
+    pub fn track_connection(limits: &web::Data<Self>, conn: &dyn Any, data: &mut Extensions) {
+        let active = limits.active_connections.fetch_add(1, Ordering::Relaxed) + 1;
+        *limits
+            .last_connection
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Instant::now();
+        data.insert(ConnectionSlot(limits.clone()));
+        if let Some(max_connections) = limits.max_connections {
+            if active > max_connections {
+                log::warn!(
+                    "Closing a connection, the limit of {} connections is reached",
+                    max_connections
+                );
+                ip_filter::close_connection(conn);
+            }
+        }
+    }
+
+    /// Time since the last connection was closed, unless connections are open
+    pub fn idle_time(&self) -> Option<Duration> {
+        if self.active_connections.load(Ordering::Relaxed) > 0 {
+            return None;
+        }
+        let last_connection = self
+            .last_connection
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        Some(last_connection.elapsed())
+    }
+
+    /// Account for a chunk of `len` bytes transferred by any client, and return when the next
+    /// chunk can be transferred so that all the transfers together stay within `--throttle-total`
+    fn reserve(&self, len: usize) -> Option<Instant> {
+        let rate = self.total_rate?;
+        let mut next_transfer = self.next_transfer.lock().unwrap_or_else(|e| e.into_inner());
+        let start = (*next_transfer).max(Instant::now());
+        *next_transfer = start + Duration::from_secs_f64(len as f64 / rate as f64);
+        Some(*next_transfer)
+    }
+}
+
+/// Delays a transfer so that it doesn't exceed its own rate, nor the total rate of `limits`
+struct RateLimiter {
+    /// Bytes per second
+    rate: Option<u64>,
+    limits: web::Data<TransferLimits>,
+    started: Instant,
+    transferred: u64,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl RateLimiter {
+    fn new(rate: Option<u64>, limits: web::Data<TransferLimits>) -> Self {
+        RateLimiter {
+            rate,
+            limits,
+            started: Instant::now(),
+            transferred: 0,
+            sleep: None,
+        }
+    }
+
+    /// Wait until the next chunk can be transferred
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(sleep) = &mut self.sleep {
+            ready!(sleep.as_mut().poll(cx));
+            self.sleep = None;
+        }
+        Poll::Ready(())
+    }
+
+    /// Account for a chunk of `len` bytes, delaying the next one if a rate is exceeded
+    fn consume(&mut self, len: usize) {
+        self.transferred += len as u64;
+        let own_due = self.rate.map(|rate| {
+            self.started + Duration::from_secs_f64(self.transferred as f64 / rate as f64)
+        });
+        let due = own_due.max(self.limits.reserve(len));
+        if let Some(due) = due.filter(|&due| due > Instant::now()) {
+            self.sleep = Some(Box::pin(sleep_until(due.into())));
+        }
+    }
+}
+
+/// Response body sent at a limited rate
+struct ThrottledBody<B> {
+    body: B,
+    limiter: RateLimiter,
+}
+
+impl<B: MessageBody + Unpin> MessageBody for ThrottledBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        ready!(this.limiter.poll_ready(cx));
+        let chunk = ready!(Pin::new(&mut this.body).poll_next(cx));
+        if let Some(Ok(bytes)) = &chunk {
+            this.limiter.consume(bytes.len());
+        }
+        Poll::Ready(chunk)
+    }
+}
+
+/// Request payload received at a limited rate
+struct ThrottledPayload {
+    payload: Payload,
+    limiter: RateLimiter,
+}
+
+impl Stream for ThrottledPayload {
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        ready!(this.limiter.poll_ready(cx));
+        let chunk = ready!(Pin::new(&mut this.payload).poll_next(cx));
+        if let Some(Ok(bytes)) = &chunk {
+            this.limiter.consume(bytes.len());
+        }
+        Poll::Ready(chunk)
+    }
+}
+
+/// Limit the rate of response bodies to `--throttle` and of request payloads to
+/// `--throttle-upload`, and of both to `--throttle-total`
+pub fn throttle_transfers<S>(
+    mut req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let (download_rate, upload_rate) = (conf.throttle, conf.throttle_upload);
+    let limits = req.app_data::<web::Data<TransferLimits>>().unwrap().clone();
+    let total_limit = limits.total_rate.is_some();
+
+    if upload_rate.is_some() || total_limit {
+        let payload = ThrottledPayload {
+            payload: req.take_payload(),
+            limiter: RateLimiter::new(upload_rate, limits.clone()),
+        };
+        req.set_payload(Payload::Stream(Box::pin(payload)));
+    }
+    let fut = srv.call(req);
+
+    async move {
+        let res = fut.await?;
+        if download_rate.is_none() && !total_limit {
+            return Ok(res);
+        }
+        Ok(res.map_body(|_, body| {
+            AnyBody::from_message(ThrottledBody {
+                body,
+                limiter: RateLimiter::new(download_rate, limits),
+            })
+        }))
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    fn total_rate_is_shared() {
+        let limits = TransferLimits::new(None, Some(1000));
+        let start = Instant::now();
+
+        // Two clients transferring 500 bytes each fill one second
+        limits.reserve(500).unwrap();
+        let due = limits.reserve(500).unwrap();
+        assert!(due >= start + Duration::from_secs(1));
+        assert!(due < Instant::now() + Duration::from_millis(1100));
+    }
+
+    #[rstest]
+    fn no_total_rate() {
+        assert!(TransferLimits::new(Some(10), None).reserve(500).is_none());
+    }
+}
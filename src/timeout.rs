@@ -0,0 +1,36 @@
+//! Limit on the time taken to receive a request and to start answering it, given with
+//! `--request-timeout`, so that clients sending their request body very slowly can't hold a
+//! worker connection forever. Responses which already started, like downloads, aren't limited.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorRequestTimeout;
+use std::future::Future;
+
+use crate::MiniserveConfig;
+
+/// Answer with `408 Request Timeout` when handling the request takes longer than the timeout
+pub fn limit_request_time<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let timeout = conf.request_timeout;
+    let fut = srv.call(req);
+
+    async move {
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => return fut.await,
+        };
+        match actix_web::rt::time::timeout(timeout, fut).await {
+            Ok(res) => res,
+            Err(_) => {
+                log::warn!("Request timed out after {}s", timeout.as_secs());
+                Err(ErrorRequestTimeout("Request timed out"))
+            }
+        }
+    }
+}
@@ -0,0 +1,369 @@
+//! TLS certificate of the server, reloaded when the certificate or key files change (e.g. when
+//! they are renewed by certbot).
+use anyhow::{anyhow, Context, Result};
+use rustls::internal::pemfile::{certs, pkcs8_private_keys};
+use rustls::sign::{self, CertifiedKey};
+use rustls::{
+    AllowAnyAuthenticatedClient, Certificate, ClientCertVerifier, ClientHello, NoClientAuth,
+    ProtocolVersion, ResolvesServerCert, RootCertStore, ServerConfig, SupportedCipherSuite,
+    ALL_CIPHERSUITES,
+};
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+/// How often the certificate and key files are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Certificate and private key used by the server, which can be replaced while it is running
+pub struct ReloadableCertificate {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    key: RwLock<CertifiedKey>,
+
+    /// Modification times of the certificate and key files when they were last loaded
+    modified: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+/// Modification time of the file at `path`, if it can be read
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Load the certificate chain at `cert_path` and the PKCS8 private key at `key_path`
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+    let cert_file = &mut BufReader::new(
+        File::open(cert_path)
+            .context(format!("Couldn't access TLS certificate {:?}", cert_path))?,
+    );
+    let key_file = &mut BufReader::new(
+        File::open(key_path).context(format!("Couldn't access TLS key {:?}", key_path))?,
+    );
+    let cert_chain = certs(cert_file).map_err(|_| anyhow!("Couldn't load certificates"))?;
+    let key = pkcs8_private_keys(key_file)
+        .map_err(|_| anyhow!("Couldn't load private key"))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Couldn't find a private key in {:?}", key_path))?;
+    let signing_key =
+        sign::any_supported_type(&key).map_err(|_| anyhow!("Unsupported private key type"))?;
+
+    let certified_key = CertifiedKey::new(cert_chain, Arc::new(signing_key));
+    certified_key.cross_check_end_entity_cert(None)?;
+    Ok(certified_key)
+}
+
+impl ReloadableCertificate {
+    /// Load the certificate chain at `cert_path` and the private key at `key_path`
+    pub fn load(cert_path: PathBuf, key_path: PathBuf) -> Result<Self> {
+        let modified = (modification_time(&cert_path), modification_time(&key_path));
+        let key = load_certified_key(&cert_path, &key_path)?;
+        Ok(ReloadableCertificate {
+            cert_path,
+            key_path,
+            key: RwLock::new(key),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    /// Load the certificate and key again if either file changed since they were last loaded.
+    /// The current certificate is kept if the new one can't be loaded.
+    fn reload_if_changed(&self) {
+        let modified = (
+            modification_time(&self.cert_path),
+            modification_time(&self.key_path),
+        );
+        let mut last_modified = self.modified.lock().unwrap_or_else(|e| e.into_inner());
+        if *last_modified == modified {
+            return;
+        }
+        *last_modified = modified;
+
+        match load_certified_key(&self.cert_path, &self.key_path) {
+            Ok(key) => {
+                *self.key.write().unwrap_or_else(|e| e.into_inner()) = key;
+                log::warn!("Reloaded TLS certificate {}", self.cert_path.display());
+            }
+            Err(e) => log::error!(
+                "Failed to reload TLS certificate {}: {:#}",
+                self.cert_path.display(),
+                e
+            ),
+        }
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<CertifiedKey> {
+        Some(self.key.read().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+}
+
+/// Verifier requiring client certificates signed by `client_ca` if given
+fn client_cert_verifier(client_ca: Option<&Path>) -> Result<Arc<dyn ClientCertVerifier>> {
+    match client_ca {
+        Some(client_ca) => {
+            let ca_file = &mut BufReader::new(
+                File::open(client_ca)
+                    .context(format!("Couldn't access TLS client CA {:?}", client_ca))?,
+            );
+            let mut client_roots = RootCertStore::empty();
+            client_roots
+                .add_pem_file(ca_file)
+                .map_err(|_| anyhow!("Couldn't load client CA certificates"))?;
+            Ok(AllowAnyAuthenticatedClient::new(client_roots))
+        }
+        None => Ok(NoClientAuth::new()),
+    }
+}
+
+/// Oldest TLS version accepted by the server
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, EnumVariantNames)]
+pub enum TlsVersion {
+    #[strum(serialize = "1.2")]
+    Tls12,
+
+    #[strum(serialize = "1.3")]
+    Tls13,
+}
+
+/// TLS versions and cipher suites accepted by the server
+#[derive(Clone)]
+pub struct TlsProtocols {
+    versions: Vec<ProtocolVersion>,
+    ciphersuites: Vec<&'static SupportedCipherSuite>,
+}
+
+impl TlsProtocols {
+    /// Accept the TLS versions from `min_version`, with the cipher suites named in `ciphers`
+    /// (e.g. TLS13_AES_256_GCM_SHA384), or with all the supported ones if it is empty
+    pub fn new(min_version: TlsVersion, ciphers: &[String]) -> Result<Self> {
+        let versions = match min_version {
+            TlsVersion::Tls12 => vec![ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2],
+            TlsVersion::Tls13 => vec![ProtocolVersion::TLSv1_3],
+        };
+
+        let mut ciphersuites = Vec::new();
+        for name in ciphers {
+            let suite = ALL_CIPHERSUITES
+                .iter()
+                .find(|suite| format!("{:?}", suite.suite).eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Unsupported TLS cipher suite {}, expected one of {}",
+                        name,
+                        cipher_suite_names().join(", ")
+                    )
+                })?;
+            ciphersuites.push(*suite);
+        }
+        if ciphersuites.is_empty() {
+            ciphersuites = ALL_CIPHERSUITES.to_vec();
+        }
+
+        let usable = ciphersuites.iter().any(|suite| {
+            versions
+                .iter()
+                .any(|version| suite.usable_for_version(*version))
+        });
+        if !usable {
+            return Err(anyhow!(
+                "None of the TLS cipher suites can be used with TLS {} or later",
+                min_version
+            ));
+        }
+        Ok(TlsProtocols {
+            versions,
+            ciphersuites,
+        })
+    }
+
+    /// Only accept these versions and cipher suites in `server_config`
+    fn apply(&self, server_config: &mut ServerConfig) {
+        server_config.versions = self.versions.clone();
+        server_config.ciphersuites = self.ciphersuites.clone();
+    }
+}
+
+/// Names of the TLS cipher suites supported by the server
+fn cipher_suite_names() -> Vec<String> {
+    ALL_CIPHERSUITES
+        .iter()
+        .map(|suite| format!("{:?}", suite.suite))
+        .collect()
+}
+
+/// Server configuration using the certificate chain at `cert_path` and the private key at
+/// `key_path`, and requiring client certificates signed by `client_ca` if given
+pub fn server_config(
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    client_ca: Option<&Path>,
+    protocols: &TlsProtocols,
+) -> Result<(ServerConfig, Arc<ReloadableCertificate>)> {
+    let mut server_config = ServerConfig::new(client_cert_verifier(client_ca)?);
+    protocols.apply(&mut server_config);
+    let certificate = Arc::new(ReloadableCertificate::load(cert_path, key_path)?);
+    server_config.cert_resolver = certificate.clone();
+    Ok((server_config, certificate))
+}
+
+/// SHA-256 fingerprint of `certificate`, in the colon-separated format shown by browsers
+pub fn fingerprint(certificate: &Certificate) -> String {
+    ring::digest::digest(&ring::digest::SHA256, &certificate.0)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Generate a self-signed certificate for localhost, returned as the PEM-encoded certificate and
+/// PKCS8 private key
+fn generate_self_signed() -> Result<(String, String)> {
+    let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+        .context("Couldn't generate a self-signed certificate")?;
+    let cert_pem = certificate
+        .serialize_pem()
+        .context("Couldn't generate a self-signed certificate")?;
+    Ok((cert_pem, certificate.serialize_private_key_pem()))
+}
+
+/// Server configuration using a self-signed certificate, along with the fingerprint of the
+/// certificate.
+///
+/// If `cert_path` and `key_path` are given, the certificate is kept in these files so that it
+/// stays the same across restarts, and only generated if they don't exist yet. Otherwise a new
+/// certificate is generated on every start.
+pub fn self_signed_server_config(
+    cert_path: Option<PathBuf>,
+    key_path: Option<PathBuf>,
+    client_ca: Option<&Path>,
+    protocols: &TlsProtocols,
+) -> Result<(ServerConfig, Option<Arc<ReloadableCertificate>>, String)> {
+    if let (Some(cert_path), Some(key_path)) = (cert_path, key_path) {
+        if !cert_path.exists() {
+            let (cert_pem, key_pem) = generate_self_signed()?;
+            write_private_file(&key_path, key_pem.as_bytes())?;
+            std::fs::write(&cert_path, cert_pem)
+                .context(format!("Couldn't write TLS certificate {:?}", cert_path))?;
+        }
+        let (server_config, certificate) =
+            server_config(cert_path, key_path, client_ca, protocols)?;
+        let fingerprint = fingerprint(&certificate.key.read().unwrap().cert[0]);
+        return Ok((server_config, Some(certificate), fingerprint));
+    }
+
+    let (cert_pem, key_pem) = generate_self_signed()?;
+    let cert_chain =
+        certs(&mut cert_pem.as_bytes()).map_err(|_| anyhow!("Couldn't load certificates"))?;
+    let key = pkcs8_private_keys(&mut key_pem.as_bytes())
+        .map_err(|_| anyhow!("Couldn't load private key"))?
+        .remove(0);
+    let fingerprint = fingerprint(&cert_chain[0]);
+    let mut server_config = ServerConfig::new(client_cert_verifier(client_ca)?);
+    protocols.apply(&mut server_config);
+    server_config.set_single_cert(cert_chain, key)?;
+    Ok((server_config, None, fingerprint))
+}
+
+/// Write `contents` to the file at `path`, only readable by the current user
+fn write_private_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .context(format!("Couldn't write TLS key {:?}", path))
+}
+
+/// Reload `certificate` whenever its files change
+pub async fn watch_certificate(certificate: Arc<ReloadableCertificate>) {
+    loop {
+        actix_web::rt::time::sleep(WATCH_INTERVAL).await;
+        certificate.reload_if_changed();
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    fn test_data(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(name)
+    }
+
+    #[rstest]
+    fn certificate_is_reloaded_when_changed() {
+        let dir = TempDir::new().unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+        std::fs::copy(test_data("cert.pem"), &cert_path).unwrap();
+        std::fs::copy(test_data("key.pem"), &key_path).unwrap();
+        let certificate = ReloadableCertificate::load(cert_path.clone(), key_path).unwrap();
+
+        // An invalid certificate is not used
+        std::fs::write(&cert_path, "not a certificate").unwrap();
+        *certificate.modified.lock().unwrap() = (None, None);
+        certificate.reload_if_changed();
+        assert!(!certificate.key.read().unwrap().cert.is_empty());
+
+        std::fs::copy(test_data("client.pem"), &cert_path).unwrap();
+        *certificate.modified.lock().unwrap() = (None, None);
+        certificate.reload_if_changed();
+        let client_cert = certs(&mut BufReader::new(File::open(test_data("client.pem")).unwrap())).unwrap();
+        assert_eq!(certificate.key.read().unwrap().cert[0], client_cert[0]);
+    }
+
+    #[rstest]
+    fn self_signed_certificate_is_cached() {
+        let dir = TempDir::new().unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+
+        let protocols = TlsProtocols::new(TlsVersion::Tls12, &[]).unwrap();
+        let (_, _, first) = self_signed_server_config(Some(cert_path.clone()), Some(key_path.clone()), None, &protocols).unwrap();
+        let (_, _, second) = self_signed_server_config(Some(cert_path), Some(key_path), None, &protocols).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 32 * 3 - 1);
+
+        let (_, certificate, ephemeral) = self_signed_server_config(None, None, None, &protocols).unwrap();
+        assert!(certificate.is_none());
+        assert_ne!(first, ephemeral);
+    }
+
+    #[rstest(min_version, ciphers, expected_versions, expected_ciphers,
+        case(TlsVersion::Tls12, &[], 2, ALL_CIPHERSUITES.len()),
+        case(TlsVersion::Tls13, &[], 1, ALL_CIPHERSUITES.len()),
+        case(TlsVersion::Tls12, &["TLS13_AES_256_GCM_SHA384", "tls_ecdhe_rsa_with_aes_256_gcm_sha384"], 2, 2)
+    )]
+    fn tls_protocols_are_restricted(min_version: TlsVersion, ciphers: &[&str], expected_versions: usize, expected_ciphers: usize) {
+        let ciphers = ciphers.iter().map(|cipher| cipher.to_string()).collect::<Vec<_>>();
+        let protocols = TlsProtocols::new(min_version, &ciphers).unwrap();
+        assert_eq!(protocols.versions.len(), expected_versions);
+        assert_eq!(protocols.ciphersuites.len(), expected_ciphers);
+    }
+
+    #[rstest(min_version, cipher,
+        case(TlsVersion::Tls12, "TLS_RSA_WITH_RC4_128_MD5"),
+        case(TlsVersion::Tls13, "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384")
+    )]
+    fn unusable_tls_ciphers_are_refused(min_version: TlsVersion, cipher: &str) {
+        assert!(TlsProtocols::new(min_version, &[cipher.to_string()]).is_err());
+    }
+
+    #[rstest]
+    fn missing_key_is_refused() {
+        assert!(ReloadableCertificate::load(test_data("cert.pem"), test_data("cert.pem")).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+//! Torrents of the served files, generated with `?download=torrent` when `--enable-torrent` is
+//! given, with the URL of the file as web seed (BEP 19).
+//!
+//! Clients on the same network can then share the pieces among themselves, e.g. by local peer
+//! discovery, while miniserve stays the origin. The piece hashes of a file are kept in memory
+//! until it changes.
+use actix_web::dev::RequestHead;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::http::Method;
+use actix_web::web::{self, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::proxy;
+use crate::storage::requested_file;
+use crate::MiniserveConfig;
+
+/// Smallest length of the pieces
+const MIN_PIECE_LENGTH: u64 = 256 * 1024;
+
+/// Largest length of the pieces
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// Number of pieces above which their length is doubled, up to `MAX_PIECE_LENGTH`
+const TARGET_PIECES: u64 = 2000;
+
+/// Number of files whose piece hashes are kept
+const CACHED_FILES: usize = 256;
+
+#[derive(Deserialize)]
+struct DownloadQuery {
+    download: Option<String>,
+}
+
+/// Value of the bencoding of the .torrent files
+#[derive(Debug, PartialEq)]
+enum Bencode {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    Dict(Vec<(&'static str, Bencode)>),
+}
+
+impl Bencode {
+    fn string(s: &str) -> Self {
+        Bencode::Bytes(s.as_bytes().to_vec())
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Bencode::Int(i) => out.extend(format!("i{}e", i).as_bytes()),
+            Bencode::Bytes(bytes) => {
+                out.extend(format!("{}:", bytes.len()).as_bytes());
+                out.extend(bytes);
+            }
+            Bencode::List(values) => {
+                out.push(b'l');
+                for value in values {
+                    value.encode(out);
+                }
+                out.push(b'e');
+            }
+            Bencode::Dict(entries) => {
+                // Keys are sorted as raw strings
+                let mut entries = entries.iter().collect::<Vec<_>>();
+                entries.sort_by_key(|(key, _)| *key);
+                out.push(b'd');
+                for (key, value) in entries {
+                    Bencode::string(key).encode(out);
+                    value.encode(out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+}
+
+/// Piece hashes of a file, as they were when it was last modified at `modified`
+struct Pieces {
+    modified: SystemTime,
+    length: u64,
+    piece_length: u64,
+
+    /// SHA-1 hashes of the pieces, concatenated
+    hashes: Vec<u8>,
+}
+
+/// Piece hashes of the files whose torrent was requested
+#[derive(Default)]
+pub struct TorrentCache {
+    files: Mutex<HashMap<PathBuf, Arc<Pieces>>>,
+}
+
+impl TorrentCache {
+    /// Piece hashes of the file at `path`, computed again if it changed
+    fn pieces(&self, path: &Path) -> io::Result<Arc<Pieces>> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata.modified()?;
+        if let Some(pieces) = self.files.lock().unwrap().get(path) {
+            if pieces.modified == modified && pieces.length == metadata.len() {
+                return Ok(pieces.clone());
+            }
+        }
+
+        log::info!(
+            "Hashing the pieces of {} for its torrent...",
+            path.display()
+        );
+        let length = metadata.len();
+        let piece_length = piece_length(length);
+        let hashes = hash_pieces(std::fs::File::open(path)?, piece_length)?;
+        let pieces = Arc::new(Pieces {
+            modified,
+            length,
+            piece_length,
+            hashes,
+        });
+
+        let mut files = self.files.lock().unwrap();
+        if files.len() >= CACHED_FILES {
+            files.clear();
+        }
+        files.insert(path.to_path_buf(), pieces.clone());
+        Ok(pieces)
+    }
+}
+
+/// Length of the pieces of a file of `length` bytes
+fn piece_length(length: u64) -> u64 {
+    let mut piece_length = MIN_PIECE_LENGTH;
+    while length / piece_length > TARGET_PIECES && piece_length < MAX_PIECE_LENGTH {
+        piece_length *= 2;
+    }
+    piece_length
+}
+
+/// SHA-1 hashes of the pieces of `piece_length` bytes read from `reader`, concatenated
+fn hash_pieces(mut reader: impl Read, piece_length: u64) -> io::Result<Vec<u8>> {
+    let mut hashes = vec![];
+    let mut piece = Vec::with_capacity(piece_length as usize);
+    loop {
+        piece.clear();
+        (&mut reader).take(piece_length).read_to_end(&mut piece)?;
+        if piece.is_empty() {
+            return Ok(hashes);
+        }
+        hashes.extend(Sha1::digest(&piece));
+    }
+}
+
+/// Content of the .torrent file of the file `name`, with `url` as web seed
+fn metainfo(name: &str, pieces: &Pieces, url: &str) -> Vec<u8> {
+    let creation_date = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64);
+    let torrent = Bencode::Dict(vec![
+        (
+            "created by",
+            Bencode::string(&format!("miniserve/{}", env!("CARGO_PKG_VERSION"))),
+        ),
+        ("creation date", Bencode::Int(creation_date)),
+        (
+            "info",
+            Bencode::Dict(vec![
+                ("length", Bencode::Int(pieces.length as i64)),
+                ("name", Bencode::string(name)),
+                ("piece length", Bencode::Int(pieces.piece_length as i64)),
+                ("pieces", Bencode::Bytes(pieces.hashes.clone())),
+            ]),
+        ),
+        ("url-list", Bencode::List(vec![Bencode::string(url)])),
+    ]);
+    let mut out = vec![];
+    torrent.encode(&mut out);
+    out
+}
+
+/// Whether `head` requests the torrent of a file of the served directory
+pub fn is_torrent_request(conf: &MiniserveConfig, head: &RequestHead) -> bool {
+    if head.method != Method::GET {
+        return false;
+    }
+    let query = Query::<DownloadQuery>::from_query(head.uri.query().unwrap_or_default());
+    if !matches!(query, Ok(query) if query.download.as_deref() == Some("torrent")) {
+        return false;
+    }
+    requested_file(conf, head.uri.path()).is_some_and(|file| file.is_file())
+}
+
+/// Send the torrent of the file requested by `req`
+pub async fn torrent_file(req: HttpRequest) -> HttpResponse {
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let cache = req.app_data::<web::Data<TorrentCache>>().unwrap().clone();
+    let file = match requested_file(conf, req.path()) {
+        Some(file) if file.is_file() => file,
+        _ => return crate::error_404(req).await,
+    };
+    let name = file
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let url = format!(
+        "{}://{}{}",
+        proxy::scheme(&req),
+        proxy::host(&req),
+        req.path()
+    );
+
+    let pieces = web::block(move || cache.pieces(&file))
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e.to_string())));
+    let pieces = match pieces {
+        Ok(pieces) => pieces,
+        Err(e) => {
+            log::error!("Failed to create the torrent of {}: {}", name, e);
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body("Failed to create the torrent");
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/x-bittorrent")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("{}.torrent", name))],
+        })
+        .body(metainfo(&name, &pieces, &url))
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest]
+    fn values_are_bencoded() {
+        let value = Bencode::Dict(vec![
+            ("spam", Bencode::List(vec![Bencode::string("a"), Bencode::Int(-3)])),
+            ("cow", Bencode::string("moo")),
+        ]);
+        let mut out = vec![];
+        value.encode(&mut out);
+        assert_eq!(String::from_utf8(out).unwrap(), "d3:cow3:moo4:spaml1:ai-3eee");
+    }
+
+    #[rstest(length, expected,
+        case(0, MIN_PIECE_LENGTH),
+        case(100 * 1024 * 1024, MIN_PIECE_LENGTH),
+        case(1024 * 1024 * 1024, 1024 * 1024),
+        case(1024 * 1024 * 1024 * 1024, MAX_PIECE_LENGTH)
+    )]
+    fn piece_length_grows_with_file(length: u64, expected: u64) {
+        assert_eq!(piece_length(length), expected);
+    }
+
+    #[rstest]
+    fn pieces_are_hashed() {
+        let hashes = hash_pieces(&b"Test Hello Yes"[..], 10).unwrap();
+        assert_eq!(hashes.len(), 2 * 20);
+        assert_eq!(&hashes[..20], &Sha1::digest(b"Test Hello")[..]);
+        assert_eq!(&hashes[20..], &Sha1::digest(b" Yes")[..]);
+        assert!(hash_pieces(&b""[..], 10).unwrap().is_empty());
+    }
+
+    #[rstest]
+    fn metainfo_has_web_seed() {
+        let pieces = Pieces {
+            modified: SystemTime::UNIX_EPOCH,
+            length: 4,
+            piece_length: MIN_PIECE_LENGTH,
+            hashes: vec![0; 20],
+        };
+        let torrent = metainfo("a.txt", &pieces, "http://localhost:8080/a.txt");
+        let torrent = String::from_utf8_lossy(&torrent);
+        assert!(torrent.contains("4:infod6:lengthi4e4:name5:a.txt12:piece lengthi262144e6:pieces20:"));
+        assert!(torrent.ends_with("8:url-listl27:http://localhost:8080/a.txtee"));
+    }
+}
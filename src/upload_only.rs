@@ -0,0 +1,54 @@
+//! Drop box mode of `--upload-only`, where the served directory only shows the upload form, so
+//! that files can be sent without seeing or downloading what was uploaded by others.
+//!
+//! The listing of the root is rendered without its entries, and the files and subdirectories
+//! are answered with 404, as if they didn't exist. So are the archives, playlists and manifests
+//! of the root, which would reveal its contents.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::web::Query;
+use actix_web::HttpResponse;
+use futures::future::{ready, Either};
+use serde::Deserialize;
+use std::future::Future;
+
+use crate::dir_config::requested_entry;
+use crate::MiniserveConfig;
+
+/// Query parameters of the listing which make it answer with the contents of the directory
+#[derive(Deserialize)]
+struct ContentsQuery {
+    download: Option<String>,
+    manifest: Option<String>,
+}
+
+/// Whether `query` asks for the contents of the listed directory rather than for its listing
+fn asks_for_contents(query: &str) -> bool {
+    Query::<ContentsQuery>::from_query(query)
+        .is_ok_and(|query| query.download.is_some() || query.manifest.is_some())
+}
+
+/// Answer the requests reading the entries of the served directory, or its contents, with 404
+pub fn hide_uploads<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let is_read = matches!(*req.method(), Method::GET | Method::HEAD);
+    let is_hidden = conf.upload_only
+        && is_read
+        && requested_entry(conf, req.path())
+            .is_some_and(|path| path != conf.path || asks_for_contents(req.query_string()));
+
+    if is_hidden {
+        let (req, _) = req.into_parts();
+        let resp = HttpResponse::NotFound().finish();
+        Either::Right(ready(Ok(ServiceResponse::new(req, resp))))
+    } else {
+        Either::Left(srv.call(req))
+    }
+}
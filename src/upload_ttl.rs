@@ -0,0 +1,305 @@
+//! Uploads deleted once they are older than `--upload-ttl`, so that the served directory cleans
+//! itself like a temporary exchange box.
+//!
+//! The files uploaded or created through miniserve are tagged with their expiry in the
+//! `.miniserve-uploads.json` file of the served directory, so that they are still deleted after
+//! a restart. The other files of the directory are never deleted, except for the partial files of
+//! resumable uploads which weren't resumed for longer than `--upload-ttl`.
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::{web, HttpResponse};
+use futures::future::{ready, Either};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::file_upload;
+use crate::session::unix_now;
+use crate::storage::requested_file;
+use crate::webhook::{Change, ChangeAction};
+use crate::MiniserveConfig;
+
+/// Name of the file of the served directory where the expiry of the uploads is kept
+pub const EXPIRY_FILE: &str = ".miniserve-uploads.json";
+
+/// How often the expired uploads are looked for, at most
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Expiry of the uploaded files
+pub struct UploadExpiry {
+    /// State file, without which nothing expires
+    path: Option<PathBuf>,
+
+    ttl: Duration,
+
+    /// Unix time at which the uploaded files expire, by absolute path
+    files: Mutex<BTreeMap<String, u64>>,
+}
+
+impl UploadExpiry {
+    /// Expiry of the uploads to the served directory at `root`, kept in its state file
+    pub fn load(root: &Path, ttl: Option<Duration>) -> io::Result<Self> {
+        let ttl = match ttl {
+            Some(ttl) => ttl,
+            None => {
+                return Ok(UploadExpiry {
+                    path: None,
+                    ttl: Duration::default(),
+                    files: Mutex::default(),
+                })
+            }
+        };
+        let path = root.join(EXPIRY_FILE);
+        let files = if path.exists() {
+            serde_json::from_slice(&std::fs::read(&path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(UploadExpiry {
+            path: Some(path),
+            ttl,
+            files: Mutex::new(files),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Tag the files uploaded, created or extracted by `changes`, and follow the tagged files
+    /// which were renamed
+    pub fn record(&self, changes: &[Change]) {
+        if !self.is_enabled() {
+            return;
+        }
+        {
+            let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+            for change in changes {
+                let path = change.path.to_string_lossy().to_string();
+                match change.action {
+                    ChangeAction::Upload | ChangeAction::Create => {
+                        files.insert(path, unix_now() + self.ttl.as_secs());
+                    }
+                    ChangeAction::Rename => {
+                        let previous_path = change
+                            .previous_path
+                            .as_ref()
+                            .map(|previous_path| previous_path.to_string_lossy().to_string());
+                        if let Some(expiry) = previous_path.and_then(|path| files.remove(&path)) {
+                            files.insert(path, expiry);
+                        }
+                    }
+                    ChangeAction::Extract => {
+                        for file in &change.extracted_files {
+                            let file = file.to_string_lossy().to_string();
+                            files.insert(file, unix_now() + self.ttl.as_secs());
+                        }
+                    }
+                    ChangeAction::Edit => (),
+                }
+            }
+        }
+        if let Err(e) = self.save() {
+            log::error!("Failed to save the expiry of the uploads: {}", e);
+        }
+    }
+
+    /// Delete the uploaded files which expired
+    pub fn delete_expired(&self) -> io::Result<()> {
+        let now = unix_now();
+        let expired = {
+            let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+            let expired = files
+                .iter()
+                .filter(|(_, &expiry)| expiry <= now)
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>();
+            for path in &expired {
+                files.remove(path);
+            }
+            expired
+        };
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        for path in expired.iter().map(Path::new) {
+            // Directories which replaced an upload are left alone
+            if !path.is_file() {
+                continue;
+            }
+            match std::fs::remove_file(path) {
+                Ok(()) => log::info!("Deleted the expired upload {}", path.display()),
+                Err(e) => log::error!(
+                    "Failed to delete the expired upload {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+        self.save()
+    }
+
+    /// Delete the partial files of the resumable uploads which weren't written to for longer than
+    /// the TTL
+    pub fn delete_abandoned_uploads(&self) -> io::Result<()> {
+        match self.path.as_ref().and_then(|path| path.parent()) {
+            Some(root) => delete_abandoned_uploads(root, self.ttl),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the expiry of the uploads to the state file
+    fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let content = {
+            let files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+            serde_json::to_vec_pretty(&*files)?
+        };
+
+        // Readers never see a partially written file
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, content)?;
+        std::fs::rename(&temp_path, path)
+    }
+
+    /// Delete the expired uploads regularly while the server is running, starting right away
+    pub async fn delete_regularly(expiry: web::Data<UploadExpiry>) {
+        let interval = expiry.ttl.min(CLEANUP_INTERVAL).max(Duration::from_secs(1));
+        loop {
+            if let Err(e) = expiry.delete_expired() {
+                log::error!("Failed to delete the expired uploads: {}", e);
+            }
+            if let Err(e) = expiry.delete_abandoned_uploads() {
+                log::error!("Failed to delete the abandoned uploads: {}", e);
+            }
+            actix_web::rt::time::sleep(interval).await;
+        }
+    }
+}
+
+/// Delete the partial uploads of `dir` and its subdirectories which are older than `ttl`
+fn delete_abandoned_uploads(dir: &Path, ttl: Duration) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            delete_abandoned_uploads(&path, ttl)?;
+            continue;
+        }
+        if !file_type.is_file() || !file_upload::is_partial_upload(&path) {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if modified.elapsed().unwrap_or_default() < ttl {
+            continue;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => log::info!("Deleted the abandoned upload {}", path.display()),
+            Err(e) => log::error!(
+                "Failed to delete the abandoned upload {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Never serve the state file of the expiry of the uploads
+pub fn hide_expiry_file<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    let is_expiry_file = conf.upload_ttl.is_some()
+        && requested_file(conf, req.path()).is_some_and(|path| path == conf.path.join(EXPIRY_FILE));
+
+    if is_expiry_file {
+        let (req, _) = req.into_parts();
+        let resp = HttpResponse::NotFound().finish();
+        Either::Right(ready(Ok(ServiceResponse::new(req, resp))))
+    } else {
+        Either::Left(srv.call(req))
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    fn change(action: ChangeAction, path: &Path, previous_path: Option<&Path>) -> Change {
+        Change { action, path: path.to_path_buf(), previous_path: previous_path.map(Path::to_path_buf), size: 0, extracted_files: Vec::new() }
+    }
+
+    #[rstest]
+    fn only_expired_uploads_are_deleted() {
+        let dir = TempDir::new().unwrap();
+        let (uploaded, renamed, other) = (dir.path().join("a.txt"), dir.path().join("b.txt"), dir.path().join("c.txt"));
+        for path in &[&uploaded, &other] {
+            std::fs::write(path, "test").unwrap();
+        }
+        let expiry = UploadExpiry::load(dir.path(), Some(Duration::from_secs(0))).unwrap();
+        expiry.record(&[change(ChangeAction::Upload, &uploaded, None)]);
+        std::fs::rename(&uploaded, &renamed).unwrap();
+        expiry.record(&[change(ChangeAction::Rename, &renamed, Some(&uploaded))]);
+
+        let reloaded = UploadExpiry::load(dir.path(), Some(Duration::from_secs(0))).unwrap();
+        reloaded.delete_expired().unwrap();
+        assert!(!renamed.exists());
+        assert!(other.exists());
+        assert_eq!(std::fs::read_to_string(dir.path().join(EXPIRY_FILE)).unwrap(), "{}");
+    }
+
+    #[rstest]
+    fn extracted_files_expire() {
+        let dir = TempDir::new().unwrap();
+        let (archive, extracted) = (dir.path().join("a.zip"), dir.path().join("b.txt"));
+        for path in &[&archive, &extracted] {
+            std::fs::write(path, "test").unwrap();
+        }
+        let expiry = UploadExpiry::load(dir.path(), Some(Duration::from_secs(0))).unwrap();
+        let mut extraction = change(ChangeAction::Extract, &archive, None);
+        extraction.extracted_files = vec![extracted.clone()];
+        expiry.record(&[extraction]);
+
+        expiry.delete_expired().unwrap();
+        assert!(archive.exists());
+        assert!(!extracted.exists());
+    }
+
+    #[rstest]
+    fn abandoned_uploads_are_deleted() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("dir")).unwrap();
+        let (partial, other) = (dir.path().join("dir/.big.iso.part"), dir.path().join("dir/c.txt"));
+        for path in &[&partial, &other] {
+            std::fs::write(path, "test").unwrap();
+        }
+
+        let expiry = UploadExpiry::load(dir.path(), Some(Duration::from_secs(3600))).unwrap();
+        expiry.delete_abandoned_uploads().unwrap();
+        assert!(partial.exists());
+
+        let expiry = UploadExpiry::load(dir.path(), Some(Duration::from_secs(0))).unwrap();
+        expiry.delete_abandoned_uploads().unwrap();
+        assert!(!partial.exists());
+        assert!(other.exists());
+    }
+}
@@ -0,0 +1,104 @@
+//! Port forwarding requested from the local router with UPnP when `--upnp-forward` is given, so
+//! that the server can be reached from the internet at the external IP of the router.
+//!
+//! The mapping is leased for `LEASE_DURATION` and renewed regularly, so that routers drop it soon
+//! after miniserve is killed. It is removed when the server stops.
+use actix_web::web;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+
+use igd::{Gateway, PortMappingProtocol, SearchOptions};
+
+use crate::errors::ContextualError;
+
+/// How long the router keeps the mapping unless it is renewed
+const LEASE_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// How long the router is looked for
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Description of the mapping, shown in the interface of the router
+const DESCRIPTION: &str = "miniserve";
+
+/// Port of the router forwarded to the server
+pub struct PortMapping {
+    gateway: Gateway,
+
+    /// Address of the server on the local network, which the port is forwarded to
+    local_addr: SocketAddrV4,
+
+    /// IP address of the router on the internet
+    pub external_ip: Ipv4Addr,
+}
+
+impl PortMapping {
+    /// Ask the router to forward its port `port` to the same port of this machine
+    pub fn request(port: u16) -> Result<Self, ContextualError> {
+        let upnp_error = |e: &dyn std::fmt::Display| ContextualError::UpnpError(e.to_string());
+
+        let gateway = igd::search_gateway(SearchOptions {
+            timeout: Some(SEARCH_TIMEOUT),
+            ..SearchOptions::default()
+        })
+        .map_err(|e| upnp_error(&e))?;
+
+        // The address of the interface which reaches the router, no packet is sent
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(|e| upnp_error(&e))?;
+        socket.connect(gateway.addr).map_err(|e| upnp_error(&e))?;
+        let local_ip = match socket.local_addr() {
+            Ok(SocketAddr::V4(addr)) => *addr.ip(),
+            Ok(addr) => return Err(upnp_error(&format!("unexpected local address {}", addr))),
+            Err(e) => return Err(upnp_error(&e)),
+        };
+
+        let external_ip = gateway.get_external_ip().map_err(|e| upnp_error(&e))?;
+        let mapping = PortMapping {
+            gateway,
+            local_addr: SocketAddrV4::new(local_ip, port),
+            external_ip,
+        };
+        mapping.renew().map_err(|e| upnp_error(&e))?;
+        Ok(mapping)
+    }
+
+    /// Port of the router which is forwarded
+    pub fn port(&self) -> u16 {
+        self.local_addr.port()
+    }
+
+    /// Ask the router to keep the mapping for another `LEASE_DURATION`
+    fn renew(&self) -> Result<(), igd::AddPortError> {
+        self.gateway.add_port(
+            PortMappingProtocol::TCP,
+            self.port(),
+            self.local_addr,
+            LEASE_DURATION.as_secs() as u32,
+            DESCRIPTION,
+        )
+    }
+
+    /// Ask the router to stop forwarding the port
+    pub fn remove(&self) {
+        if let Err(e) = self
+            .gateway
+            .remove_port(PortMappingProtocol::TCP, self.port())
+        {
+            log::warn!("Failed to remove the UPnP port mapping: {}", e);
+        }
+    }
+
+    /// Renew the mapping regularly while the server is running
+    pub async fn renew_regularly(mapping: Arc<PortMapping>) {
+        loop {
+            actix_web::rt::time::sleep(LEASE_DURATION / 2).await;
+            let renewed = mapping.clone();
+            let result = web::block(move || renewed.renew().map_err(|e| e.to_string()))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+            if let Err(e) = result {
+                log::error!("Failed to renew the UPnP port mapping: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Home directories of `--user-dirs`, where every authenticated user is served
+//! `<served-root>/<username>` instead of the served directory, as if it was the only one.
+//!
+//! Each user gets the routes of the main directory, guarded by their name, and the requests of
+//! authenticated users carry the configuration of their directory, so that the routes which
+//! aren't specific to a directory (renaming, editing, ...) also work in it. The directories are
+//! created at startup, since the files of a directory can only be served if it exists then, and
+//! again on the requests of their user if they were deleted since.
+use actix_web::dev::{Extensions, RequestHead, Service, ServiceRequest, ServiceResponse};
+use actix_web::HttpMessage;
+use futures::future::{ready, Either};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Component, Path};
+use std::rc::Rc;
+
+use crate::auth::{self, CurrentUser};
+use crate::errors::{self, ContextualError};
+use crate::MiniserveConfig;
+
+/// Configurations of the directories of the users, built for each worker
+#[derive(Clone, Default)]
+pub struct UserDirs {
+    /// Configuration of the directory of each user, along with the data container carrying it
+    dirs: Rc<BTreeMap<String, (MiniserveConfig, Rc<Extensions>)>>,
+}
+
+impl UserDirs {
+    /// Directories of the users of `conf` with --user-dirs, which are created if they don't exist.
+    /// Users whose name can't be a directory name, or whose directory can't be created, get none.
+    pub fn new(conf: &MiniserveConfig) -> Self {
+        if !conf.user_dirs {
+            return UserDirs::default();
+        }
+
+        let mut dirs = BTreeMap::new();
+        for auth in conf.auth.get().iter() {
+            let name = &auth.username;
+            let mut components = Path::new(name).components();
+            if !matches!(
+                (components.next(), components.next()),
+                (Some(Component::Normal(_)), None)
+            ) {
+                log::warn!("{} can't have a directory of their own", name);
+                continue;
+            }
+
+            let mut user_conf = conf.clone();
+            user_conf.path = conf.path.join(name);
+            if let Err(e) = std::fs::create_dir_all(&user_conf.path) {
+                log::warn!("Failed to create the directory of {}: {}", name, e);
+                continue;
+            }
+            let mut data = Extensions::new();
+            data.insert(user_conf.clone());
+            dirs.insert(name.clone(), (user_conf, Rc::new(data)));
+        }
+        UserDirs {
+            dirs: Rc::new(dirs),
+        }
+    }
+
+    /// Name and configuration of the directory of each user
+    pub fn configs(&self) -> impl Iterator<Item = (&String, &MiniserveConfig)> {
+        self.dirs.iter().map(|(name, (conf, _))| (name, conf))
+    }
+}
+
+/// Whether the request of `head` was made by the user `name`
+pub fn is_user(head: &RequestHead, name: &str) -> bool {
+    head.extensions()
+        .get::<CurrentUser>()
+        .is_some_and(|user| user.name == name)
+}
+
+/// Give the requests of authenticated users the configuration of their directory, which is
+/// created again if it was deleted. Users without a directory are refused.
+pub fn select_user_dir<S>(
+    mut req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let conf = req.app_data::<MiniserveConfig>().unwrap();
+    // The login page and the assets are reachable without credentials
+    let user = req
+        .extensions()
+        .get::<CurrentUser>()
+        .map(|user| user.name.clone());
+    let user = match user {
+        Some(user) if conf.user_dirs => user,
+        _ => return Either::Left(srv.call(req)),
+    };
+
+    let dir = req
+        .app_data::<UserDirs>()
+        .and_then(|user_dirs| user_dirs.dirs.get(&user).cloned());
+    let result = match dir {
+        Some((user_conf, data)) => std::fs::create_dir_all(&user_conf.path)
+            .map(|()| data)
+            .map_err(|e| {
+                ContextualError::IoError(format!("Failed to create the directory of {}", user), e)
+            }),
+        None => Err(ContextualError::NoUserDirectoryError(user)),
+    };
+
+    match result {
+        Ok(data) => {
+            req.add_data_container(data);
+            Either::Left(srv.call(req))
+        }
+        Err(e) => {
+            errors::log_error_chain(e.to_string());
+            let (req, _) = req.into_parts();
+            let resp = auth::forbidden_response(&req, e);
+            Either::Right(ready(Ok(ServiceResponse::new(req, resp))))
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Notifications sent to the URL given with `--on-change-webhook` when the served directory changes,
+//! and to the URL given with `--on-download-webhook` when a file was downloaded.
+use actix_web::body::{AnyBody, BodySize, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::{self, Bytes};
+use actix_web::HttpRequest;
+use futures::future::TryFutureExt;
+use serde::Serialize;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use crate::auth::CurrentUser;
+use crate::canonical_path;
+use crate::hooks::{self, EventDetails, HookEvent};
+use crate::metrics::Metrics;
+use crate::proxy;
+use crate::storage::requested_file;
+use crate::upload_ttl::UploadExpiry;
+
+/// Kind of change made to the served directory
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeAction {
+    /// A file was uploaded
+    Upload,
+
+    /// An uploaded archive was extracted
+    Extract,
+
+    /// A file or directory was renamed or moved
+    Rename,
+
+    /// A file was saved from the text editor
+    Edit,
+
+    /// A new file was created
+    Create,
+}
+
+/// Change made to the served directory
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub action: ChangeAction,
+
+    /// Absolute path of the changed file
+    pub path: PathBuf,
+
+    /// Absolute path of the file before it was renamed
+    pub previous_path: Option<PathBuf>,
+
+    /// Number of bytes written
+    pub size: u64,
+
+    /// Absolute paths of the files written by an extraction
+    pub extracted_files: Vec<PathBuf>,
+}
+
+/// JSON payload of webhook notifications
+#[derive(Serialize)]
+struct ChangeEvent {
+    action: ChangeAction,
+
+    /// Path of the changed file, relative to the served directory
+    path: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_path: Option<String>,
+
+    size: u64,
+    client_ip: Option<String>,
+    user: Option<String>,
+}
+
+/// JSON payload of download notifications
+#[derive(Serialize)]
+struct DownloadEvent {
+    /// Always `download`, to tell these events from the changes when both are sent to one URL
+    action: &'static str,
+
+    /// Path of the downloaded file, relative to the served directory
+    path: String,
+
+    /// Number of bytes sent, which is less than the size of the file if it was compressed
+    bytes: u64,
+
+    /// Time it took to send the file, in milliseconds
+    duration_ms: u64,
+
+    client_ip: Option<String>,
+    user: Option<String>,
+}
+
+/// Path of `path` relative to the served directory `root`, as used in URLs
+fn relative_path(root: &Path, path: &Path) -> String {
+    let root = canonical_path::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    let relative = path.strip_prefix(&root).unwrap_or(path);
+    let components = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>();
+    format!("/{}", components.join("/"))
+}
+
+/// Notify the webhook and the upload hooks of the changes made by `req`, if they are configured.
+///
+/// Notifications are sent in the background, failures are only logged.
+pub fn notify_changes(req: &HttpRequest, changes: Vec<Change>) {
+    let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+    if let Some(metrics) = req.app_data::<web::Data<Metrics>>() {
+        metrics.record_changes(&changes);
+    }
+    if let Some(expiry) = req.app_data::<web::Data<UploadExpiry>>() {
+        expiry.record(&changes);
+    }
+
+    for change in changes
+        .iter()
+        .filter(|change| change.action == ChangeAction::Upload)
+    {
+        let details = EventDetails {
+            path: Some(relative_path(&conf.path, &change.path)),
+            size: Some(change.size),
+            ..EventDetails::from_request(req)
+        };
+        hooks::run_hooks(&conf.hooks, HookEvent::Upload, &details);
+    }
+
+    let url = match &conf.on_change_webhook {
+        Some(url) => url.clone(),
+        None => return,
+    };
+
+    let client_ip = proxy::client_ip(req).map(|ip| ip.to_string());
+    let user = req
+        .extensions()
+        .get::<CurrentUser>()
+        .map(|user| user.name.clone());
+    let events = changes
+        .into_iter()
+        .map(|change| ChangeEvent {
+            action: change.action,
+            path: relative_path(&conf.path, &change.path),
+            previous_path: change
+                .previous_path
+                .map(|path| relative_path(&conf.path, &path)),
+            size: change.size,
+            client_ip: client_ip.clone(),
+            user: user.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    post_events(url, events);
+}
+
+/// POST `events` to the webhook at `url` in the background, only logging the failures
+fn post_events<E: Serialize + 'static>(url: http::Uri, events: Vec<E>) {
+    actix_web::rt::spawn(async move {
+        let client = awc::Client::new();
+        for event in events {
+            match client.post(url.clone()).send_json(&event).await {
+                Ok(response) if !response.status().is_success() => log::warn!(
+                    "Webhook {} responded with status {}",
+                    url,
+                    response.status()
+                ),
+                Ok(_) => (),
+                Err(e) => log::error!("Failed to notify webhook {}: {}", url, e),
+            }
+        }
+    });
+}
+
+/// Response body of a downloaded file, which notifies the download webhook once it was entirely
+/// sent. The downloads interrupted by the client aren't notified.
+struct NotifiedBody<B> {
+    body: B,
+
+    /// Notification sent at the end of the body, if it wasn't yet
+    event: Option<DownloadEvent>,
+
+    started: Instant,
+    url: http::Uri,
+}
+
+impl<B: MessageBody + Unpin> MessageBody for NotifiedBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let chunk = futures::ready!(Pin::new(&mut this.body).poll_next(cx));
+        match (&chunk, &mut this.event) {
+            (Some(Ok(bytes)), Some(event)) => event.bytes += bytes.len() as u64,
+            (None, event) => {
+                if let Some(mut event) = event.take() {
+                    event.duration_ms = this.started.elapsed().as_millis() as u64;
+                    post_events(this.url.clone(), vec![event]);
+                }
+            }
+            _ => (),
+        }
+        Poll::Ready(chunk)
+    }
+}
+
+/// Notify the download webhook when a file of the served directory was entirely sent, if it is
+/// configured
+pub fn notify_downloads<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = actix_web::Result<ServiceResponse>> + 'static
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let started = Instant::now();
+    srv.call(req).map_ok(move |res| {
+        let req = res.request();
+        // Parts of a file requested with a Range header aren't complete downloads
+        if req.method() != Method::GET || res.status() != StatusCode::OK {
+            return res;
+        }
+        let conf = req.app_data::<crate::MiniserveConfig>().unwrap();
+        let url = match &conf.on_download_webhook {
+            Some(url) => url.clone(),
+            None => return res,
+        };
+        let file = match requested_file(conf, req.path()) {
+            Some(file) if file.is_file() => canonical_path::canonicalize(&file).unwrap_or(file),
+            _ => return res,
+        };
+
+        let details = EventDetails::from_request(req);
+        let event = DownloadEvent {
+            action: "download",
+            path: relative_path(&conf.path, &file),
+            bytes: 0,
+            duration_ms: 0,
+            client_ip: details.client_ip,
+            user: details.user,
+        };
+        res.map_body(|_, body| {
+            AnyBody::from_message(NotifiedBody {
+                body,
+                event: Some(event),
+                started,
+                url,
+            })
+        })
+    })
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use pretty_assertions::assert_eq;
+
+    #[rstest(
+        path, expected,
+        case("/srv/files/file.txt", "/file.txt"),
+        case("/srv/files/dir/sub/file.txt", "/dir/sub/file.txt"),
+        case("/srv/files", "/")
+    )]
+    fn relative_paths(path: &str, expected: &str) {
+        assert_eq!(relative_path(Path::new("/srv/files"), Path::new(path)), expected);
+    }
+}
@@ -0,0 +1,99 @@
+mod fixtures;
+
+use assert_cmd::prelude::*;
+use fixtures::{port, tmpdir, Error};
+use pretty_assertions::assert_eq;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+#[test]
+fn json_access_log_has_one_object_per_request() -> Result<(), Error> {
+    let port = port();
+    let tmpdir = tmpdir();
+    let mut child = Command::cargo_bin("miniserve")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .arg("--log-format")
+        .arg("json")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let start = Instant::now();
+    while !port_check::is_port_reachable(format!("localhost:{}", port)) {
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "timeout waiting for port {}",
+            port
+        );
+        sleep(Duration::from_millis(100));
+    }
+
+    let url = format!("http://localhost:{}", port);
+    reqwest::blocking::get(format!("{}/test.txt", url))?.error_for_status()?;
+    reqwest::blocking::get(format!("{}/missing.txt?token=secret", url))?;
+    sleep(Duration::from_millis(100));
+
+    child.kill()?;
+    let output = child.wait_with_output()?;
+    let entries = String::from_utf8(output.stdout)?
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["method"], "GET");
+    assert_eq!(entries[0]["path"], "/test.txt");
+    assert_eq!(entries[0]["status"], 200);
+    assert_eq!(entries[0]["bytes"], "Test Hello Yes".len());
+    assert!(entries[0]["duration_ms"].is_number());
+    assert!(entries[0]["timestamp"].is_string());
+    assert!(entries[0]["client_ip"].is_string());
+    assert_eq!(entries[0]["user"], serde_json::Value::Null);
+
+    // The query string is not logged, as it may contain share tokens
+    assert_eq!(entries[1]["path"], "/missing.txt");
+    assert_eq!(entries[1]["status"], 404);
+
+    Ok(())
+}
+
+#[test]
+fn access_log_is_written_to_file() -> Result<(), Error> {
+    let port = port();
+    let tmpdir = tmpdir();
+    let log_dir = assert_fs::TempDir::new()?;
+    let log_path = log_dir.path().join("access.log");
+    let mut child = Command::cargo_bin("miniserve")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .arg("--log-file")
+        .arg(&log_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let start = Instant::now();
+    while !port_check::is_port_reachable(format!("localhost:{}", port)) {
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "timeout waiting for port {}",
+            port
+        );
+        sleep(Duration::from_millis(100));
+    }
+
+    reqwest::blocking::get(format!("http://localhost:{}/test.txt", port))?.error_for_status()?;
+    sleep(Duration::from_millis(100));
+    child.kill()?;
+    child.wait()?;
+
+    let log = std::fs::read_to_string(&log_path)?;
+    assert!(log.contains("\"GET /test.txt\" 200 14"));
+
+    Ok(())
+}
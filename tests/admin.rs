@@ -0,0 +1,94 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::{multipart, Client};
+use reqwest::StatusCode;
+use rstest::rstest;
+
+#[rstest]
+fn admin_status_lists_recent_requests(
+    #[with(&["--enable-admin", "--auth", "joe:123"])] server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+    client
+        .get(server.url().join("/test.txt")?)
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .error_for_status()?;
+
+    let status = client
+        .get(server.url().join("/__admin/status")?)
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .error_for_status()?
+        .text()?;
+    let status: serde_json::Value = serde_json::from_str(&status)?;
+    assert_eq!(status["read_only"], false);
+    let request = &status["recent_requests"][0];
+    assert_eq!(request["path"], "/test.txt");
+    assert_eq!(request["user"], "joe");
+    assert_eq!(request["status"], 200);
+
+    let page = client
+        .get(server.url().join("/__admin")?)
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert!(page.contains("/test.txt"));
+
+    Ok(())
+}
+
+#[rstest]
+fn admin_requires_read_write_permission(
+    #[with(&["--enable-admin", "--auth", "joe:123", "--auth-read-only", "joe"])] server: TestServer,
+) -> Result<(), Error> {
+    let status = Client::new()
+        .get(server.url().join("/__admin")?)
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let status = reqwest::blocking::get(server.url().join("/__admin")?)?.status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[rstest]
+fn read_only_mode_prevents_uploads(
+    #[with(&["--enable-admin", "--auth", "joe:123", "-u"])] server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let response = client
+        .post(server.url().join("/__admin/read-only")?)
+        .basic_auth("joe", Some("123"))
+        .form(&[("read_only", "true")])
+        .send()?;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    let part = multipart::Part::text("this should not be uploaded").file_name("read-only.txt");
+    let status = client
+        .post(server.url().join("/upload?path=/")?)
+        .basic_auth("joe", Some("123"))
+        .multipart(multipart::Form::new().part("file_to_upload", part))
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(!server.path().join("read-only.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn admin_is_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let status = reqwest::blocking::get(server.url().join("/__admin")?)?.status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+mod fixtures;
+
+use assert_cmd::prelude::*;
+use fixtures::{port, Error};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Text;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+#[rstest]
+fn archive_contents_are_served(port: u16) -> Result<(), Error> {
+    let mut child = Command::cargo_bin("miniserve")?
+        .arg("--from-archive")
+        .arg("tests/data/extract.zip")
+        .arg("-p")
+        .arg(port.to_string())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    sleep(Duration::from_secs(1));
+
+    let url = format!("http://localhost:{}", port);
+    let body = reqwest::blocking::get(&url)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).any(|x| x.text() == "extracted/"));
+
+    let response = reqwest::blocking::get(format!("{}/extracted/hello.txt", url))?;
+    assert_eq!(response.content_length(), Some(22));
+    assert_eq!(
+        response.error_for_status()?.text()?,
+        "hello from the archive"
+    );
+
+    let status = reqwest::blocking::get(format!("{}/missing.txt", url))?.status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    child.kill()?;
+
+    Ok(())
+}
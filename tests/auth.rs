@@ -1,12 +1,12 @@
 mod fixtures;
 
-use fixtures::{server, server_no_stderr, Error, FILES};
+use fixtures::{server, server_no_stderr, Error, TestServer, FILES};
 use pretty_assertions::assert_eq;
-use reqwest::blocking::Client;
+use reqwest::blocking::{multipart, Client};
 use reqwest::StatusCode;
 use rstest::rstest;
 use select::document::Document;
-use select::predicate::Text;
+use select::predicate::{Attr, Text};
 
 #[rstest(
     cli_auth_arg, client_username, client_password,
@@ -177,3 +177,287 @@ fn auth_multiple_accounts_wrong_password(username: &str, password: &str) -> Resu
 
     Ok(())
 }
+
+#[rstest]
+fn auth_read_only_account_can_read(
+    #[with(&["-u", "--auth", "reader:pwd", "--auth", "writer:pwd", "--auth-read-only", "reader"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+
+    let body = client
+        .get(server.url())
+        .basic_auth("reader", Some("pwd"))
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+
+    // Read-only accounts are not offered the upload form
+    assert!(parsed.find(Attr("id", "file_submit")).next().is_none());
+
+    Ok(())
+}
+
+#[rstest(
+    username,
+    expected_status,
+    case("reader", StatusCode::FORBIDDEN),
+    case("writer", StatusCode::SEE_OTHER)
+)]
+fn auth_write_requires_read_write_account(
+    username: &str,
+    expected_status: StatusCode,
+) -> Result<(), Error> {
+    let server = server_no_stderr(&[
+        "-u",
+        "--auth",
+        "reader:pwd",
+        "--auth",
+        "writer:pwd",
+        "--auth-read-only",
+        "reader",
+    ]);
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let part = multipart::Part::text("this is a test")
+        .file_name("permission test.txt")
+        .mime_str("text/plain")?;
+    let form = multipart::Form::new().part("file_to_upload", part);
+
+    let status = client
+        .post(server.url().join("/upload?path=/")?)
+        .basic_auth(username, Some("pwd"))
+        .multipart(form)
+        .send()?
+        .status();
+
+    assert_eq!(status, expected_status);
+
+    Ok(())
+}
+
+#[rstest]
+fn auth_passwords_can_end_like_permissions(
+    #[with(&["-u", "--auth", "joe:pwd@ro"])] server: TestServer,
+) -> Result<(), Error> {
+    let status = Client::new()
+        .put(server.url().join("permission test.txt")?)
+        .basic_auth("joe", Some("pwd@ro"))
+        .body("this is a test")
+        .send()?
+        .status();
+    assert!(status.is_success());
+
+    Ok(())
+}
+
+#[rstest]
+fn login_form_redirects_anonymous_users(
+    #[with(&["--login-form", "--auth", "testuser:testpassword"])] server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let response = client.get(server.url().join("/dira/")?).send()?;
+
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()["Location"], "/__login?next=%2Fdira%2F");
+
+    // The login page itself is reachable without credentials
+    let body = client
+        .get(server.url().join("/__login")?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Attr("name", "password")).next().is_some());
+
+    Ok(())
+}
+
+#[rstest(
+    password,
+    expected_status,
+    case("testpassword", StatusCode::SEE_OTHER),
+    case("wrongpassword", StatusCode::UNAUTHORIZED)
+)]
+fn login_form_sets_session_cookie(
+    password: &str,
+    expected_status: StatusCode,
+) -> Result<(), Error> {
+    let server = server_no_stderr(&["--login-form", "--auth", "testuser:testpassword"]);
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let response = client
+        .post(server.url().join("/__login")?)
+        .form(&[
+            ("username", "testuser"),
+            ("password", password),
+            ("next", "/dira/"),
+        ])
+        .send()?;
+    assert_eq!(response.status(), expected_status);
+
+    if expected_status != StatusCode::SEE_OTHER {
+        assert!(response.headers().get("Set-Cookie").is_none());
+        return Ok(());
+    }
+
+    assert_eq!(response.headers()["Location"], "/dira/");
+    let cookie = response.headers()["Set-Cookie"]
+        .to_str()?
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+
+    // The session cookie grants access to the listing
+    let status = client
+        .get(server.url().join("/dira/")?)
+        .header("Cookie", &cookie)
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::OK);
+
+    // Logging out clears the cookie
+    let response = client.get(server.url().join("/__logout")?).send()?;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert!(response.headers()["Set-Cookie"]
+        .to_str()?
+        .contains("Max-Age=0"));
+
+    Ok(())
+}
+
+#[rstest]
+fn auth_locks_out_after_repeated_failures() -> Result<(), Error> {
+    let server = server_no_stderr(&[
+        "--auth",
+        "testuser:testpassword",
+        "--auth-max-failures",
+        "2",
+    ]);
+    let client = Client::new();
+
+    for _ in 0..2 {
+        let status = client
+            .get(server.url())
+            .basic_auth("testuser", Some("wrongpassword"))
+            .send()?
+            .status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // Even the right password is refused during the lockout
+    let response = client
+        .get(server.url())
+        .basic_auth("testuser", Some("testpassword"))
+        .send()?;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().get("Retry-After").is_some());
+
+    Ok(())
+}
+
+#[rstest(
+    token,
+    expected_status,
+    case("readtoken", StatusCode::OK),
+    case("writetoken", StatusCode::OK),
+    case("wrongtoken", StatusCode::UNAUTHORIZED)
+)]
+fn api_token_grants_read_access(token: &str, expected_status: StatusCode) -> Result<(), Error> {
+    let server = server_no_stderr(&["--api-token", "readtoken@ro", "--api-token", "writetoken"]);
+
+    let response = Client::new().get(server.url()).bearer_auth(token).send()?;
+    assert_eq!(response.status(), expected_status);
+
+    Ok(())
+}
+
+#[rstest(
+    token,
+    expected_status,
+    case("readtoken", StatusCode::FORBIDDEN),
+    case("writetoken", StatusCode::SEE_OTHER)
+)]
+fn api_token_write_requires_read_write_token(
+    token: &str,
+    expected_status: StatusCode,
+) -> Result<(), Error> {
+    let server = server_no_stderr(&[
+        "-u",
+        "--api-token",
+        "readtoken@ro",
+        "--api-token",
+        "writetoken",
+    ]);
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let part = multipart::Part::text("this should be uploaded")
+        .file_name("uploaded.txt")
+        .mime_str("text/plain")?;
+    let form = multipart::Form::new().part("file_to_upload", part);
+
+    let status = client
+        .post(server.url().join("/upload?path=/")?)
+        .bearer_auth(token)
+        .multipart(form)
+        .send()?
+        .status();
+
+    assert_eq!(status, expected_status);
+
+    Ok(())
+}
+
+#[rstest]
+fn public_read_lets_anonymous_users_read(
+    #[with(&["-u", "--auth", "joe:123", "--public-read", "--enable-share-links"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+    let body = client.get(server.url()).send()?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    for &file in FILES {
+        assert!(parsed.find(Text).any(|x| x.text() == file));
+    }
+    // Without credentials, the upload form isn't shown
+    assert!(parsed.find(Attr("id", "file_submit")).next().is_none());
+
+    let content = client
+        .get(server.url().join("test.txt")?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "Test Hello Yes");
+
+    let status = client
+        .put(server.url().join("anonymous.txt")?)
+        .body("test")
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(!server.path().join("anonymous.txt").exists());
+
+    let status = client
+        .get(server.url().join("/__share?path=/test.txt")?)
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    client
+        .put(server.url().join("authenticated.txt")?)
+        .basic_auth("joe", Some("123"))
+        .body("test")
+        .send()?
+        .error_for_status()?;
+
+    Ok(())
+}
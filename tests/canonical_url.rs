@@ -0,0 +1,51 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::Client;
+use reqwest::redirect::Policy;
+use reqwest::StatusCode;
+use rstest::rstest;
+
+fn client() -> Result<Client, Error> {
+    Ok(Client::builder().redirect(Policy::none()).build()?)
+}
+
+#[rstest]
+fn directories_are_redirected_to_their_trailing_slash(server: TestServer) -> Result<(), Error> {
+    let resp = client()?.get(server.url().join("dira?sort=size")?).send()?;
+    assert_eq!(resp.status(), StatusCode::FOUND);
+    assert_eq!(resp.headers()["location"], "/dira/?sort=size");
+
+    // Duplicate slashes are kept by default
+    let resp = client()?.get(server.url().join("dira//test.txt")?).send()?;
+    assert_ne!(resp.status(), StatusCode::FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn directories_can_be_served_without_trailing_slash(
+    #[with(&["--directory-slash", "serve"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = client()?.get(server.url().join("dira")?).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.text()?.contains("/dira/test.txt"));
+
+    Ok(())
+}
+
+#[rstest]
+fn slashes_are_merged_with_the_redirect_status(
+    #[with(&["--merge-slashes", "--redirect-status", "308"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = client()?.get(server.url().join("dira//test.txt")?).send()?;
+    assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(resp.headers()["location"], "/dira/test.txt");
+
+    let resp = client()?.get(server.url().join("/dira")?).send()?;
+    assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(resp.headers()["location"], "/dira/");
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::Client;
+use reqwest::redirect::Policy;
+use reqwest::StatusCode;
+use rstest::rstest;
+
+#[rstest]
+fn paths_are_case_sensitive_by_default(server: TestServer) -> Result<(), Error> {
+    let status = reqwest::blocking::get(server.url().join("DIRA/TEST.TXT")?)?.status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn paths_are_redirected_to_entries_of_another_case(
+    #[with(&["--case-insensitive-paths"])] server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::builder().redirect(Policy::none()).build()?;
+    let resp = client
+        .get(server.url().join("DIRA/Test.TXT?download=1")?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(resp.headers()["location"], "/dira/test.txt?download=1");
+
+    let body = reqwest::blocking::get(server.url().join("DIRA/TEST.TXT")?)?.error_for_status()?;
+    assert_eq!(body.text()?, "This is dira/test.txt");
+
+    // Entries which exist aren't redirected, and missing ones still aren't found
+    let resp = client.get(server.url().join("dira/test.txt")?).send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = client.get(server.url().join("DIRA/missing.txt")?).send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
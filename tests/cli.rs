@@ -34,7 +34,7 @@ fn print_completions() -> Result<(), Error> {
     for shell in &Shell::variants() {
         Command::cargo_bin("miniserve")?
             .arg("--print-completions")
-            .arg(&shell)
+            .arg(shell)
             .assert()
             .success();
     }
@@ -0,0 +1,26 @@
+mod fixtures;
+
+use fixtures::{server, Error};
+use select::document::Document;
+use select::predicate::Name;
+
+#[test]
+fn arguments_are_read_from_config_file() -> Result<(), Error> {
+    let config_dir = assert_fs::TempDir::new()?;
+    let config_path = config_dir.path().join("miniserve.toml");
+    std::fs::write(
+        &config_path,
+        "title = \"from the file\"\nport = 1\nhidden = true\n",
+    )?;
+
+    // The port given on the command line by the fixture takes precedence
+    let server = server(&[std::ffi::OsStr::new("--config"), config_path.as_os_str()]);
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+
+    let title = parsed.find(Name("title")).next().unwrap().text();
+    assert!(title.contains("from the file"));
+    assert!(parsed.find(Name("a")).any(|a| a.text() == ".hidden_file1"));
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer, DEEPLY_NESTED_FILE};
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Attr, Class, Name, Text};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Search page of `query` in the directory at `path`, once the files were indexed
+fn search(server: &TestServer, path: &str, query: &str, mode: &str) -> Result<Document, Error> {
+    let start = Instant::now();
+    loop {
+        let mut url = server.url().join(path)?;
+        url.query_pairs_mut()
+            .append_pair("q", query)
+            .append_pair("mode", mode);
+        let body = reqwest::blocking::get(url)?.error_for_status()?;
+        let parsed = Document::from_read(body)?;
+        let indexing = parsed
+            .find(Class("search-message"))
+            .any(|x| x.text().contains("still being indexed"));
+        if !indexing || start.elapsed() > Duration::from_secs(10) {
+            return Ok(parsed);
+        }
+        sleep(Duration::from_millis(100));
+    }
+}
+
+#[rstest]
+fn content_search_is_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Attr("name", "q")).next().is_none());
+
+    // The directory is listed instead
+    let body = reqwest::blocking::get(server.url().join("?q=nested")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).any(|x| x.text() == "very/"));
+
+    Ok(())
+}
+
+#[rstest]
+fn content_search_finds_files(
+    #[with(&["--enable-content-search"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Attr("name", "q")).next().is_some());
+
+    let parsed = search(&server, "", "DEEPLY nested", "content")?;
+    let links = parsed
+        .find(Name("li"))
+        .filter_map(|x| x.find(Name("a")).next())
+        .map(|x| x.text())
+        .collect::<Vec<_>>();
+    assert_eq!(links, vec![DEEPLY_NESTED_FILE]);
+    assert!(parsed
+        .find(Class("snippet"))
+        .any(|x| x.text() == "File in a deeply nested directory."));
+
+    // Only the files of the searched directory are found
+    let parsed = search(&server, "very/deeply/", "nested", "content")?;
+    assert!(parsed.find(Text).any(|x| x.text() == "nested/test.rs"));
+    let parsed = search(&server, "dira/", "nested", "content")?;
+    assert!(parsed.find(Text).any(|x| x.text() == "No file found"));
+
+    let parsed = search(&server, "", "ne", "content")?;
+    assert!(parsed
+        .find(Text)
+        .any(|x| x.text() == "Search for at least 3 characters, or search the file names"));
+
+    Ok(())
+}
+
+#[rstest]
+fn fuzzy_search_ranks_file_names(
+    #[with(&["--enable-content-search"])] server: TestServer,
+) -> Result<(), Error> {
+    let parsed = search(&server, "", "vrydpnst", "fuzzy")?;
+    let links = parsed
+        .find(Name("li"))
+        .filter_map(|x| x.find(Name("a")).next())
+        .map(|x| x.text())
+        .collect::<Vec<_>>();
+    assert_eq!(links, vec![DEEPLY_NESTED_FILE]);
+
+    let parsed = search(&server, "dira/", "ttxt", "fuzzy")?;
+    let links = parsed
+        .find(Name("li"))
+        .filter_map(|x| x.find(Name("a")).next())
+        .map(|x| x.text())
+        .collect::<Vec<_>>();
+    assert_eq!(links.first().map(String::as_str), Some("test.txt"));
+
+    Ok(())
+}
@@ -1,2 +1,11 @@
 #!/usr/bin/env bash
 openssl req -subj '/CN=localhost' -x509 -newkey rsa:4096 -keyout key.pem -out cert.pem -nodes -days 3650
+
+# CA used to verify client certificates, and a client certificate for the user "testuser"
+openssl req -subj '/CN=miniserve test client CA' -x509 -newkey rsa:2048 -keyout client_ca_key.pem -out client_ca.pem -nodes -days 3650 \
+    -addext basicConstraints=critical,CA:TRUE -addext keyUsage=critical,keyCertSign,cRLSign
+openssl req -subj '/CN=testuser' -newkey rsa:2048 -keyout client_key.pem -out client.csr -nodes
+openssl x509 -req -in client.csr -CA client_ca.pem -CAkey client_ca_key.pem -CAcreateserial -out client_cert.pem -days 3650 \
+    -extfile <(printf "basicConstraints=CA:FALSE\nkeyUsage=digitalSignature,keyEncipherment\nextendedKeyUsage=clientAuth")
+cat client_cert.pem client_key.pem > client.pem
+rm client_ca_key.pem client_key.pem client_cert.pem client.csr client_ca.srl
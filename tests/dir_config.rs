@@ -0,0 +1,118 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Text;
+
+#[rstest]
+fn protected_directories_require_auth(
+    #[with(&["--auth", "joe:123", "--auth", "ann:456", "--dir-config", "--allow-anonymous", "--hidden"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(
+        server.path().join("dira/.miniserve.toml"),
+        "users = [\"joe\"]",
+    )?;
+    let client = Client::new();
+
+    let content = client
+        .get(server.url().join("dirb/test.txt")?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "This is dirb/test.txt");
+
+    let status = client
+        .get(server.url().join("dira/test.txt")?)
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let status = client
+        .get(server.url().join("dira/test.txt")?)
+        .basic_auth("ann", Some("456"))
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let content = client
+        .get(server.url().join("dira/test.txt")?)
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "This is dira/test.txt");
+
+    let status = client
+        .get(server.url().join("dira/.miniserve.toml")?)
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn hidden_directories_are_not_listed(
+    #[with(&["--dir-config"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("dira/.miniserve.toml"), "hidden = true")?;
+
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).all(|x| x.text() != "dira/"));
+    assert!(parsed.find(Text).any(|x| x.text() == "dirb/"));
+
+    reqwest::blocking::get(server.url().join("dira/test.txt")?)?.error_for_status()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn uploads_can_be_disabled(
+    #[with(&["-u", "--dir-config"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(
+        server.path().join("dira/.miniserve.toml"),
+        "uploads = false",
+    )?;
+    let client = Client::new();
+
+    let status = client
+        .put(server.url().join("dira/raw.txt")?)
+        .body("test")
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(!server.path().join("dira/raw.txt").exists());
+
+    let status = client
+        .put(server.url().join("dirb/.miniserve.toml")?)
+        .body("uploads = true")
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    client
+        .put(server.url().join("dirb/raw.txt")?)
+        .body("test")
+        .send()?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn dir_config_is_ignored_by_default(server: TestServer) -> Result<(), Error> {
+    std::fs::write(
+        server.path().join("dira/.miniserve.toml"),
+        "require-auth = true",
+    )?;
+
+    reqwest::blocking::get(server.url().join("dira/test.txt")?)?.error_for_status()?;
+
+    Ok(())
+}
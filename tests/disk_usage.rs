@@ -0,0 +1,54 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Class, Name, Text};
+
+#[rstest]
+fn disk_usage_is_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).all(|x| x.text() != "Disk usage"));
+
+    let status = reqwest::blocking::get(server.url().join("?view=usage")?)?.status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[rstest]
+fn disk_usage_lists_the_largest_entries_first(
+    #[with(&["--enable-disk-usage"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).any(|x| x.text() == "Disk usage"));
+
+    std::fs::create_dir(server.path().join("dira/large"))?;
+    std::fs::write(server.path().join("dira/large/file.bin"), vec![0; 10_000])?;
+    let body =
+        reqwest::blocking::get(server.url().join("dira/?view=usage")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let tiles = parsed
+        .find(Class("tile"))
+        .map(|tile| tile.attr("href").unwrap_or_default().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(tiles[0], "/dira/large/?view=usage");
+    assert!(tiles.contains(&"/dira/test.txt".to_string()));
+
+    // The subdirectories link to their own usage
+    let body =
+        reqwest::blocking::get(server.url().join("dira/large/?view=usage")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let links = parsed
+        .find(Name("tbody"))
+        .flat_map(|tbody| tbody.find(Name("a")).collect::<Vec<_>>())
+        .map(|link| link.text())
+        .collect::<Vec<_>>();
+    assert_eq!(links, vec!["file.bin"]);
+
+    Ok(())
+}
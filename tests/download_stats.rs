@@ -0,0 +1,87 @@
+mod fixtures;
+
+use assert_fs::TempDir;
+use fixtures::{server, Error};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::Client;
+use reqwest::header;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Class;
+
+#[rstest]
+fn downloads_are_counted() -> Result<(), Error> {
+    let state_dir = TempDir::new()?;
+    let server = server(&[
+        "--enable-admin".to_string(),
+        "--auth".to_string(),
+        "joe:123".to_string(),
+        "--download-stats".to_string(),
+        state_dir.path().join("stats.json").display().to_string(),
+    ]);
+    let client = Client::new();
+    for _ in 0..2 {
+        client
+            .get(server.url().join("test.txt")?)
+            .basic_auth("joe", Some("123"))
+            .send()?
+            .error_for_status()?;
+    }
+    client
+        .get(server.url().join("test.txt")?)
+        .basic_auth("joe", Some("123"))
+        .header(header::RANGE, "bytes=0-3")
+        .send()?
+        .error_for_status()?;
+
+    let status = client
+        .get(server.url().join("/__admin/status")?)
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .error_for_status()?
+        .text()?;
+    let status: serde_json::Value = serde_json::from_str(&status)?;
+    let file = &status["downloads"][0];
+    assert_eq!(file["path"], "/test.txt");
+    assert_eq!(file["downloads"], 2);
+    assert_eq!(file["bytes"], 2 * 14 + 4);
+
+    Ok(())
+}
+
+#[rstest]
+fn download_counts_are_shown_in_listings() -> Result<(), Error> {
+    let state_dir = TempDir::new()?;
+    let server = server(&[
+        "--download-stats".to_string(),
+        state_dir.path().join("stats.json").display().to_string(),
+        "--show-download-counts".to_string(),
+    ]);
+    reqwest::blocking::get(server.url().join("test.txt")?)?.error_for_status()?;
+
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let counts = parsed
+        .find(Class("downloads-cell"))
+        .map(|cell| cell.text())
+        .collect::<Vec<_>>();
+    assert!(counts.contains(&"1".to_string()));
+    assert!(counts.contains(&"0".to_string()));
+
+    Ok(())
+}
+
+#[rstest]
+fn download_counts_are_hidden_by_default() -> Result<(), Error> {
+    let state_dir = TempDir::new()?;
+    let server = server(&[
+        "--download-stats".to_string(),
+        state_dir.path().join("stats.json").display().to_string(),
+    ]);
+
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Class("downloads-cell")).next().is_none());
+
+    Ok(())
+}
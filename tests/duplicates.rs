@@ -0,0 +1,59 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer, FILES};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Class, Name, Text};
+
+#[rstest]
+fn duplicates_are_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed
+        .find(Text)
+        .all(|x| x.text() != "Find duplicate files"));
+
+    let status = reqwest::blocking::get(server.url().join("?view=duplicates")?)?.status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[rstest]
+fn duplicates_are_grouped(
+    #[with(&["--enable-duplicates"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed
+        .find(Text)
+        .any(|x| x.text() == "Find duplicate files"));
+
+    // The files of the served directory all have the same content
+    let body =
+        reqwest::blocking::get(server.url().join("?view=duplicates")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let groups = parsed.find(Class("duplicate-group")).collect::<Vec<_>>();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].find(Name("li")).count(), FILES.len());
+
+    // The files of the directories all have different contents
+    std::fs::write(server.path().join("dira/copy.txt"), "This is dira/test.txt")?;
+    let body =
+        reqwest::blocking::get(server.url().join("dira/?view=duplicates")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let groups = parsed
+        .find(Class("duplicate-group"))
+        .map(|group| {
+            group
+                .find(Name("a"))
+                .map(|link| link.attr("href").unwrap_or_default().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(groups, vec![vec!["/dira/copy.txt", "/dira/test.txt"]]);
+
+    Ok(())
+}
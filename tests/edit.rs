@@ -0,0 +1,96 @@
+mod fixtures;
+
+use fixtures::{server, server_no_stderr, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Attr, Class, Name};
+
+/// Open `path` in the editor, and return the content and modification time sent by the server
+fn open_editor(server: &TestServer, path: &str) -> Result<(String, String), Error> {
+    let body = reqwest::blocking::get(server.url().join(&format!("/__edit?path={}", path))?)?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let content = parsed
+        .find(Name("textarea"))
+        .next()
+        .expect("Couldn't find the text area")
+        .text();
+    let modified = parsed
+        .find(Attr("name", "modified"))
+        .next()
+        .and_then(|input| input.attr("value"))
+        .expect("Couldn't find the modification time")
+        .to_string();
+    Ok((content, modified))
+}
+
+#[rstest]
+fn editing_files_works(#[with(&["--enable-edit"])] server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Class("edit")).next().is_some());
+
+    let (content, modified) = open_editor(&server, "/test.txt")?;
+    assert_eq!(content, "Test Hello Yes");
+
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let response = client
+        .post(server.url().join("/__edit?path=/test.txt")?)
+        .form(&[("content", "edited\r\ncontent"), ("modified", &modified)])
+        .send()?;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    let content = std::fs::read_to_string(server.path().join("test.txt"))?;
+    assert_eq!(content, "edited\ncontent");
+
+    Ok(())
+}
+
+#[rstest]
+fn concurrent_edits_are_detected(
+    #[with(&["--enable-edit"])] server: TestServer,
+) -> Result<(), Error> {
+    let response = Client::new()
+        .post(server.url().join("/__edit?path=/test.txt")?)
+        .form(&[("content", "stale content"), ("modified", "0")])
+        .send()?;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let content = std::fs::read_to_string(server.path().join("test.txt"))?;
+    assert_eq!(content, "Test Hello Yes");
+
+    Ok(())
+}
+
+#[rstest(
+    path,
+    expected,
+    case("/../test.txt", StatusCode::BAD_REQUEST),
+    case("/missing.txt", StatusCode::NOT_FOUND),
+    case("/dira", StatusCode::BAD_REQUEST)
+)]
+fn invalid_files_cannot_be_edited(path: &str, expected: StatusCode) -> Result<(), Error> {
+    let server = server_no_stderr(&["--enable-edit"]);
+    let status =
+        reqwest::blocking::get(server.url().join(&format!("/__edit?path={}", path))?)?.status();
+    assert_eq!(status, expected);
+
+    Ok(())
+}
+
+#[rstest]
+fn editing_is_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Class("edit")).next().is_none());
+
+    let status = reqwest::blocking::get(server.url().join("/__edit?path=/test.txt")?)?.status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+mod fixtures;
+
+use fixtures::{server, server_no_stderr, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Class;
+
+#[rstest]
+fn archives_are_extracted_next_to_them(
+    #[with(&["-u", "--enable-extract"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::copy(
+        "tests/data/extract.zip",
+        server.path().join("dira/extract.zip"),
+    )?;
+
+    let body = reqwest::blocking::get(server.url().join("dira/")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let links = parsed.find(Class("extract")).collect::<Vec<_>>();
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].attr("data-name"), Some("extract.zip"));
+
+    let response = Client::new()
+        .post(server.url().join("/__extract")?)
+        .form(&[("path", "/dira/extract.zip")])
+        .send()?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let content = reqwest::blocking::get(server.url().join("/dira/extracted/hello.txt")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "hello from the archive");
+    assert!(server.path().join("dira/extract.zip").exists());
+
+    Ok(())
+}
+
+#[rstest(
+    path,
+    expected,
+    case("/traversal.zip", StatusCode::BAD_REQUEST),
+    case("/test.txt", StatusCode::BAD_REQUEST),
+    case("/missing.zip", StatusCode::NOT_FOUND)
+)]
+fn invalid_extractions_are_refused(path: &str, expected: StatusCode) -> Result<(), Error> {
+    let server = server_no_stderr(&["-u", "--enable-extract"]);
+    std::fs::copy(
+        "tests/data/traversal.zip",
+        server.path().join("traversal.zip"),
+    )?;
+
+    let status = Client::new()
+        .post(server.url().join("/__extract")?)
+        .form(&[("path", path)])
+        .send()?
+        .status();
+    assert_eq!(status, expected);
+    assert!(!server
+        .path()
+        .parent()
+        .unwrap()
+        .join("miniserve-escaped-file.txt")
+        .exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn extraction_is_disabled_by_default(#[with(&["-u"])] server: TestServer) -> Result<(), Error> {
+    std::fs::copy("tests/data/extract.zip", server.path().join("extract.zip"))?;
+
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Class("extract")).next().is_none());
+
+    let status = Client::new()
+        .post(server.url().join("/__extract")?)
+        .form(&[("path", "/extract.zip")])
+        .send()?
+        .status();
+    assert!(!status.is_success());
+    assert!(!server.path().join("extracted").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn extractions_follow_the_rules_of_subdirectories(
+    #[with(&["-u", "--enable-extract", "--dir-config", "--on-duplicate-files", "overwrite"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(
+        server.path().join("dira/.miniserve.toml"),
+        "uploads = false",
+    )?;
+    std::fs::write(
+        server.path().join("dirb/.miniserve.toml"),
+        "require-auth = true",
+    )?;
+
+    for (name, entry) in &[
+        ("restricted.tar", "dira/extracted.txt"),
+        ("config.tar", "dirb/.miniserve.toml"),
+    ] {
+        let mut builder = tar::Builder::new(std::fs::File::create(server.path().join(name))?);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, entry, std::io::empty())?;
+        builder.finish()?;
+
+        let status = Client::new()
+            .post(server.url().join("/__extract")?)
+            .form(&[("path", format!("/{}", name))])
+            .send()?
+            .status();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    assert!(!server.path().join("dira/extracted.txt").exists());
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("dirb/.miniserve.toml"))?,
+        "require-auth = true"
+    );
+
+    Ok(())
+}
@@ -71,7 +71,7 @@ pub fn tmpdir() -> TempDir {
     }
 
     tmpdir
-        .child(&DEEPLY_NESTED_FILE)
+        .child(DEEPLY_NESTED_FILE)
         .write_str("File in a deeply nested directory.")
         .expect("Couldn't write to file");
     tmpdir
@@ -0,0 +1,55 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Class, Name};
+
+#[rstest]
+fn fragments_surround_listing(
+    #[with(&["--enable-fragments"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(
+        server.path().join("dira/HEADER.html"),
+        "<p>Read <a href=\"#rules\">the rules</a></p>",
+    )?;
+    std::fs::write(server.path().join("dira/FOOTER.html"), "<p>Bye</p>")?;
+
+    let body = reqwest::blocking::get(server.url().join("dira/")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let header = parsed.find(Class("header-fragment")).next().unwrap();
+    assert!(header.find(Name("a")).any(|a| a.text() == "the rules"));
+    let footer = parsed.find(Class("footer-fragment")).next().unwrap();
+    assert_eq!(footer.text(), "Bye");
+
+    // The fragments only apply to their own directory
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Class("fragment")).next().is_none());
+
+    Ok(())
+}
+
+#[rstest]
+fn fragment_names_can_be_changed(
+    #[with(&["--enable-fragments", "--header-name", "test.html"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let header = parsed.find(Class("header-fragment")).next().unwrap();
+    assert_eq!(header.text(), "Test Hello Yes");
+
+    Ok(())
+}
+
+#[rstest]
+fn fragments_are_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    std::fs::write(server.path().join("HEADER.html"), "<p>Hello</p>")?;
+
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Class("fragment")).next().is_none());
+
+    Ok(())
+}
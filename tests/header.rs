@@ -20,3 +20,42 @@ fn custom_header_set(headers: Vec<String>) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[rstest(args, expected_csp,
+    case(vec!["--security-headers"], Some("default-src 'self'")),
+    case(vec!["--security-headers", "--csp", "default-src 'none'"], Some("default-src 'none'")),
+    case(vec!["--csp", "default-src 'none'"], Some("default-src 'none'")),
+    case(vec![], None)
+)]
+fn security_headers_set(args: Vec<&str>, expected_csp: Option<&str>) -> Result<(), Error> {
+    let server = server(&args);
+    let resp = reqwest::blocking::get(server.url())?;
+
+    let csp = resp
+        .headers()
+        .get("content-security-policy")
+        .map(|csp| csp.to_str().unwrap());
+    assert_eq!(csp.map(|csp| csp.split(';').next().unwrap()), expected_csp);
+    let nosniff = resp.headers().get("x-content-type-options");
+    assert_eq!(nosniff.is_some(), args.contains(&"--security-headers"));
+
+    Ok(())
+}
+
+#[rstest]
+fn custom_header_overrides_security_headers() -> Result<(), Error> {
+    let server = server(&[
+        "--security-headers",
+        "--header",
+        "x-frame-options: SAMEORIGIN",
+    ]);
+    let resp = reqwest::blocking::get(server.url())?;
+
+    assert_eq!(resp.headers().get("x-frame-options").unwrap(), "SAMEORIGIN");
+    assert_eq!(
+        resp.headers().get("referrer-policy").unwrap(),
+        "no-referrer"
+    );
+
+    Ok(())
+}
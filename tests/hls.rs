@@ -0,0 +1,70 @@
+#![cfg(unix)]
+
+mod fixtures;
+
+use assert_fs::TempDir;
+use fixtures::{server, server_no_stderr, Error};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use rstest::rstest;
+
+/// Transcoding command writing a playlist of a single segment
+const FAKE_TRANSCODE_CMD: &str = r#"printf segment > "$MINISERVE_OUTPUT_DIR/segment0.ts" && printf '#EXTM3U\n#EXTINF:6.0,\nsegment0.ts\n#EXT-X-ENDLIST\n' > "$MINISERVE_OUTPUT_DIR/index.m3u8""#;
+
+#[rstest]
+fn videos_are_streamed_as_hls() -> Result<(), Error> {
+    let cache = TempDir::new()?;
+    let server = server(&[
+        "--enable-hls",
+        "--transcode-cmd",
+        FAKE_TRANSCODE_CMD,
+        "--hls-cache",
+        cache.path().to_str().unwrap(),
+    ]);
+    std::fs::write(server.path().join("dira/my movie.mkv"), "video")?;
+
+    let resp = reqwest::blocking::get(server.url().join("dira/my%20movie.mkv?hls=index.m3u8")?)?
+        .error_for_status()?;
+    assert_eq!(
+        resp.headers()["content-type"],
+        "application/vnd.apple.mpegurl"
+    );
+    let playlist = resp.text()?;
+    assert!(playlist.contains("\nmy%20movie.mkv?hls=segment0%2Ets\n"));
+
+    let resp = reqwest::blocking::get(server.url().join("dira/my%20movie.mkv?hls=segment0%2Ets")?)?
+        .error_for_status()?;
+    assert_eq!(resp.headers()["content-type"], "video/mp2t");
+    assert_eq!(resp.text()?, "segment");
+
+    // The video itself is still served as is
+    let content = reqwest::blocking::get(server.url().join("dira/my%20movie.mkv")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "video");
+
+    Ok(())
+}
+
+#[rstest]
+fn failed_transcodings_are_reported() -> Result<(), Error> {
+    let cache = TempDir::new()?;
+    let server = server_no_stderr(&[
+        "--enable-hls",
+        "--transcode-cmd",
+        "exit 1",
+        "--hls-cache",
+        cache.path().to_str().unwrap(),
+    ]);
+    std::fs::write(server.path().join("movie.mkv"), "video")?;
+
+    let status = reqwest::blocking::get(server.url().join("movie.mkv?hls=index.m3u8")?)?.status();
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    let status =
+        reqwest::blocking::get(server.url().join("movie.mkv?hls=..%2F..%2Fetc%2Fpasswd")?)?
+            .status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+#![cfg(unix)]
+
+mod fixtures;
+
+use assert_fs::TempDir;
+use fixtures::{server, Error};
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use rstest::rstest;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Wait a max of 1s for the hooks to write `path`, and return its content
+fn wait_for_output(path: &Path) -> String {
+    let start = Instant::now();
+    loop {
+        match std::fs::read_to_string(path) {
+            Ok(output) if !output.is_empty() => return output,
+            _ if start.elapsed() > Duration::from_secs(1) => return String::new(),
+            _ => sleep(Duration::from_millis(100)),
+        }
+    }
+}
+
+#[rstest]
+fn hooks_are_run_on_startup_and_downloads() -> Result<(), Error> {
+    let output_dir = TempDir::new()?;
+    let startup_output = output_dir.path().join("startup");
+    let download_output = output_dir.path().join("download");
+    let server = server(&[
+        "--hook".to_string(),
+        format!(
+            "startup=echo $MINISERVE_EVENT > {}",
+            startup_output.display()
+        ),
+        "--hook".to_string(),
+        format!(
+            "download=echo $MINISERVE_EVENT $MINISERVE_PATH $MINISERVE_SIZE > {}",
+            download_output.display()
+        ),
+    ]);
+
+    assert_eq!(wait_for_output(&startup_output), "startup\n");
+
+    reqwest::blocking::get(server.url().join("test.txt")?)?.error_for_status()?;
+    assert_eq!(wait_for_output(&download_output), "download /test.txt 14\n");
+
+    Ok(())
+}
+
+#[rstest]
+fn hooks_are_run_on_auth_failures() -> Result<(), Error> {
+    let output_dir = TempDir::new()?;
+    let output = output_dir.path().join("auth-failure");
+    let server = server(&[
+        "--auth".to_string(),
+        "joe:123".to_string(),
+        "--hook".to_string(),
+        format!(
+            "auth-failure=echo $MINISERVE_USER ${{MINISERVE_CLIENT_IP:+ip}} > {}",
+            output.display()
+        ),
+    ]);
+
+    let status = Client::new()
+        .get(server.url())
+        .basic_auth("joe", Some("wrong"))
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(wait_for_output(&output), "joe ip\n");
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Text;
+
+#[rstest]
+fn ignored_entries_are_hidden(
+    #[with(&["--enable-ignore-files", "--enable-manifest"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join(".miniserveignore"), "*.html\ndirb/\n")?;
+    std::fs::write(server.path().join("dira/.miniserveignore"), "!*.html\n")?;
+
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).all(|x| x.text() != "test.html"));
+    assert!(parsed.find(Text).all(|x| x.text() != "dirb/"));
+    assert!(parsed.find(Text).any(|x| x.text() == "test.txt"));
+
+    let body = reqwest::blocking::get(server.url().join("dira/")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).any(|x| x.text() == "test.html"));
+
+    for path in &["test.html", "dirb/", "dirb/test.txt"] {
+        let status = reqwest::blocking::get(server.url().join(path)?)?.status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    let manifest = reqwest::blocking::get(server.url().join("?manifest=sha256")?)?
+        .error_for_status()?
+        .text()?;
+    assert!(!manifest.contains("dirb/"));
+    assert!(manifest.contains("  dira/test.html"));
+    assert!(!manifest.contains("  test.html"));
+
+    Ok(())
+}
+
+#[rstest]
+fn ignored_entries_can_be_served() -> Result<(), Error> {
+    let ignore_dir = assert_fs::TempDir::new()?;
+    let ignore_path = ignore_dir.path().join("ignore");
+    std::fs::write(&ignore_path, "*.txt\n")?;
+    let server = server(&[
+        std::ffi::OsStr::new("--ignore-file"),
+        ignore_path.as_os_str(),
+        std::ffi::OsStr::new("--serve-ignored"),
+    ]);
+
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).all(|x| x.text() != "test.txt"));
+
+    let content = reqwest::blocking::get(server.url().join("test.txt")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "Test Hello Yes");
+
+    Ok(())
+}
+
+#[rstest]
+fn ignore_files_are_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    std::fs::write(server.path().join(".miniserveignore"), "*.txt\n")?;
+
+    reqwest::blocking::get(server.url().join("test.txt")?)?.error_for_status()?;
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use reqwest::StatusCode;
+use rstest::rstest;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+#[rstest(args, expected,
+    case(&["--allow-ip", "127.0.0.0/8", "--allow-ip", "::1"], StatusCode::OK),
+    case(&["--allow-ip", "10.0.0.0/8"], StatusCode::FORBIDDEN),
+    case(&["--deny-ip", "127.0.0.0/8", "--deny-ip", "::1"], StatusCode::FORBIDDEN),
+    case(&["--allow-ip", "127.0.0.0/8", "--allow-ip", "::1", "--deny-ip", "127.0.0.1", "--deny-ip", "::1"], StatusCode::FORBIDDEN)
+)]
+fn clients_are_filtered_by_ip(args: &[&str], expected: StatusCode) -> Result<(), Error> {
+    let server = server(args);
+    let status = reqwest::blocking::get(server.url())?.status();
+    assert_eq!(status, expected);
+
+    Ok(())
+}
+
+#[rstest]
+fn denied_connections_are_dropped(
+    #[with(&["--deny-ip", "127.0.0.0/8", "--deny-ip", "::1", "--ip-deny-action", "drop"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    assert!(reqwest::blocking::get(server.url()).is_err());
+
+    Ok(())
+}
+
+#[rstest(args, forwarded_for, expected,
+    case(&["--allow-ip", "203.0.113.0/24", "--trusted-proxy", "127.0.0.1", "--trusted-proxy", "::1"], "203.0.113.7", StatusCode::OK),
+    case(&["--allow-ip", "203.0.113.0/24", "--trusted-proxy", "127.0.0.1", "--trusted-proxy", "::1"], "198.51.100.1", StatusCode::FORBIDDEN),
+    case(&["--allow-ip", "203.0.113.0/24"], "203.0.113.7", StatusCode::FORBIDDEN)
+)]
+fn clients_behind_trusted_proxies_are_filtered_by_ip(
+    args: &[&str],
+    forwarded_for: &str,
+    expected: StatusCode,
+) -> Result<(), Error> {
+    let server = server(args);
+    let status = reqwest::blocking::Client::new()
+        .get(server.url())
+        .header("X-Forwarded-For", forwarded_for)
+        .send()?
+        .status();
+    assert_eq!(status, expected);
+
+    Ok(())
+}
+
+#[rstest(
+    header,
+    expected,
+    case("PROXY TCP4 203.0.113.7 127.0.0.1 56324 80\r\n", "HTTP/1.1 200 OK"),
+    case(
+        "PROXY TCP4 198.51.100.1 127.0.0.1 56324 80\r\n",
+        "HTTP/1.1 403 Forbidden"
+    )
+)]
+fn clients_behind_proxy_protocol_are_filtered_by_ip(
+    header: &str,
+    expected: &str,
+) -> Result<(), Error> {
+    let server = server(&["--proxy-protocol", "--allow-ip", "203.0.113.0/24"]);
+    let mut stream = TcpStream::connect(("localhost", server.port()))?;
+    write!(
+        stream,
+        "{}GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        header
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    assert!(response.starts_with(expected));
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+mod fixtures;
+
+use assert_fs::TempDir;
+use fixtures::{tmpdir, Error, FILES};
+use http::StatusCode;
+use miniserve::Server;
+use rstest::rstest;
+use select::document::Document;
+use select::node::Node;
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::thread;
+
+/// Run an embedded server with `builder` in its own thread, and return its address and handle
+fn run_server(
+    builder: miniserve::ServerBuilder,
+) -> (
+    SocketAddr,
+    miniserve::ServerHandle,
+    thread::JoinHandle<Result<(), miniserve::ContextualError>>,
+) {
+    let (sender, receiver) = mpsc::channel();
+    let thread = thread::spawn(move || {
+        actix_web::rt::System::new().block_on(async move {
+            let server = builder.run().await.expect("Couldn't start the server");
+            sender.send((server.addrs()[0], server.handle())).unwrap();
+            server.wait().await
+        })
+    });
+    let (addr, handle) = receiver.recv().expect("Couldn't start the server");
+    (addr, handle, thread)
+}
+
+#[rstest]
+fn embedded_server_serves_directory(tmpdir: TempDir) -> Result<(), Error> {
+    let builder = Server::builder()
+        .root(tmpdir.path())
+        .bind(([127, 0, 0, 1], 0));
+    let (addr, handle, thread) = run_server(builder);
+
+    let body = reqwest::blocking::get(format!("http://{}", addr))?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    for &file in FILES {
+        assert!(parsed.find(|x: &Node| x.text() == file).next().is_some());
+    }
+
+    actix_web::rt::System::new().block_on(handle.stop(true));
+    thread.join().unwrap()?;
+    assert!(reqwest::blocking::get(format!("http://{}", addr)).is_err());
+
+    Ok(())
+}
+
+#[rstest]
+fn embedded_server_requires_auth(tmpdir: TempDir) -> Result<(), Error> {
+    let builder = Server::builder()
+        .root(tmpdir.path())
+        .auth("joe", "123")
+        .bind(([127, 0, 0, 1], 0));
+    let (addr, handle, thread) = run_server(builder);
+
+    let client = reqwest::blocking::Client::new();
+    let status = client.get(format!("http://{}", addr)).send()?.status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let status = client
+        .get(format!("http://{}", addr))
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::OK);
+
+    actix_web::rt::System::new().block_on(handle.stop(false));
+    thread.join().unwrap()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn builder_requires_path() {
+    assert!(Server::builder().bind(([127, 0, 0, 1], 0)).build().is_err());
+}
@@ -0,0 +1,50 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer, DEEPLY_NESTED_FILE, FILES};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use rstest::rstest;
+use sha2::{Digest, Sha256};
+
+#[rstest]
+fn manifest_lists_hashes_of_directory(
+    #[with(&["--enable-manifest"])] server: TestServer,
+) -> Result<(), Error> {
+    let manifest = reqwest::blocking::get(server.url().join("dira/?manifest=sha256")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(manifest.lines().count(), FILES.len());
+
+    let hash = hex::encode(Sha256::digest(b"This is dira/test.txt"));
+    assert!(manifest
+        .lines()
+        .any(|line| line == format!("{}  test.txt", hash)));
+
+    Ok(())
+}
+
+#[rstest]
+fn manifest_includes_subdirectories(
+    #[with(&["--enable-manifest"])] server: TestServer,
+) -> Result<(), Error> {
+    let manifest = reqwest::blocking::get(server.url().join("?manifest=sha256")?)?
+        .error_for_status()?
+        .text()?;
+    assert!(manifest
+        .lines()
+        .any(|line| line.ends_with(&format!("  {}", DEEPLY_NESTED_FILE))));
+    assert!(manifest
+        .lines()
+        .any(|line| line.ends_with("  dirb/test.txt")));
+    assert!(!manifest.contains(".hidden"));
+
+    Ok(())
+}
+
+#[rstest]
+fn manifest_is_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let status = reqwest::blocking::get(server.url().join("?manifest=sha256")?)?.status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    Ok(())
+}
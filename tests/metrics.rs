@@ -0,0 +1,63 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use reqwest::StatusCode;
+use rstest::rstest;
+
+#[rstest]
+fn metrics_are_exposed(#[with(&["--enable-metrics"])] server: TestServer) -> Result<(), Error> {
+    reqwest::blocking::get(server.url().join("/test.txt")?)?.error_for_status()?;
+    reqwest::blocking::get(server.url().join("/missing.txt")?)?;
+
+    let metrics = reqwest::blocking::get(server.url().join("/__metrics")?)?
+        .error_for_status()?
+        .text()?;
+    assert!(metrics.contains("miniserve_responses_total{status=\"200\"} 1\n"));
+    assert!(metrics.contains("miniserve_responses_total{status=\"404\"} 1\n"));
+    assert!(metrics.contains("miniserve_active_connections 1\n"));
+    assert!(metrics.contains("miniserve_uploads_total 0\n"));
+
+    Ok(())
+}
+
+#[rstest]
+fn metrics_route_can_be_changed(
+    #[with(&["--enable-metrics", "--metrics-route", "stats"])] server: TestServer,
+) -> Result<(), Error> {
+    let metrics = reqwest::blocking::get(server.url().join("/stats")?)?
+        .error_for_status()?
+        .text()?;
+    assert!(metrics.contains("# TYPE miniserve_served_bytes_total counter"));
+
+    Ok(())
+}
+
+#[rstest]
+fn metrics_require_authentication(
+    #[with(&["--enable-metrics", "--auth", "joe:123"])] server: TestServer,
+) -> Result<(), Error> {
+    let status = reqwest::blocking::get(server.url().join("/__metrics")?)?.status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[rstest]
+fn metrics_can_be_public(
+    #[with(&["--enable-metrics", "--metrics-public", "--auth", "joe:123"])] server: TestServer,
+) -> Result<(), Error> {
+    reqwest::blocking::get(server.url().join("/__metrics")?)?.error_for_status()?;
+
+    let status = reqwest::blocking::get(server.url())?.status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[rstest]
+fn metrics_are_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let status = reqwest::blocking::get(server.url().join("/__metrics")?)?.status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
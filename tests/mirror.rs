@@ -0,0 +1,65 @@
+mod fixtures;
+
+use assert_cmd::prelude::*;
+use assert_fs::fixture::TempDir;
+use assert_fs::prelude::*;
+use fixtures::{port, tmpdir, Error, DIRECTORIES, FILES};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use rstest::rstest;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+#[rstest]
+fn missing_files_are_fetched_from_upstream(tmpdir: TempDir, port: u16) -> Result<(), Error> {
+    let upstream_port = fixtures::port();
+    let mut upstream = Command::cargo_bin("miniserve")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(upstream_port.to_string())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    let mirror = TempDir::new()?;
+    mirror.child("local.txt").write_str("local")?;
+    let mut child = Command::cargo_bin("miniserve")?
+        .arg(mirror.path())
+        .arg("--mirror-upstream")
+        .arg(format!("http://localhost:{}", upstream_port))
+        .arg("-p")
+        .arg(port.to_string())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    sleep(Duration::from_secs(1));
+
+    let url = format!("http://localhost:{}", port);
+    let content = reqwest::blocking::get(format!("{}/{}{}", url, DIRECTORIES[0], FILES[0]))?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, format!("This is {}{}", DIRECTORIES[0], FILES[0]));
+    let stored = std::fs::read_to_string(mirror.path().join(DIRECTORIES[0]).join(FILES[0]))?;
+    assert_eq!(stored, content);
+
+    let content = reqwest::blocking::get(format!("{}/local.txt", url))?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "local");
+
+    let status = reqwest::blocking::get(format!("{}/missing.txt", url))?.status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(!mirror.path().join("missing.txt").exists());
+
+    // Once stored, the files are served even without the upstream server
+    upstream.kill()?;
+    upstream.wait()?;
+    let content = reqwest::blocking::get(format!("{}/{}{}", url, DIRECTORIES[0], FILES[0]))?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, format!("This is {}{}", DIRECTORIES[0], FILES[0]));
+
+    child.kill()?;
+
+    Ok(())
+}
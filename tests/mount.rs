@@ -0,0 +1,94 @@
+mod fixtures;
+
+use assert_cmd::prelude::*;
+use assert_fs::fixture::TempDir;
+use fixtures::{port, tmpdir, Error, FILES};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::{multipart, Client};
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Attr, Text};
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+#[rstest]
+fn mounted_directories_are_served(tmpdir: TempDir, port: u16) -> Result<(), Error> {
+    let mut child = Command::cargo_bin("miniserve")?
+        .arg(tmpdir.path().join("dira"))
+        .arg("-p")
+        .arg(port.to_string())
+        .arg("--mount")
+        .arg(format!("/all={}@upload", tmpdir.path().display()))
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    sleep(Duration::from_secs(1));
+
+    let url = format!("http://localhost:{}", port);
+    let body = reqwest::blocking::get(&url)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).all(|x| x.text() != "dirb/"));
+
+    let body = reqwest::blocking::get(format!("{}/all/", url))?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).any(|x| x.text() == "dirb/"));
+
+    let content = reqwest::blocking::get(format!("{}/all/dirb/{}", url, FILES[0]))?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, format!("This is dirb/{}", FILES[0]));
+
+    // Uploads go to the mounted directory, as the main directory doesn't allow them
+    let upload_action = parsed
+        .find(Attr("id", "file_submit"))
+        .next()
+        .expect("Couldn't find element with id=file_submit")
+        .attr("action")
+        .expect("Upload form doesn't have action attribute");
+    let part = multipart::Part::text("mounted").file_name("uploaded.txt");
+    Client::new()
+        .post(format!("{}{}", url, upload_action))
+        .multipart(multipart::Form::new().part("file_to_upload", part))
+        .send()?
+        .error_for_status()?;
+    assert!(tmpdir.path().join("uploaded.txt").exists());
+
+    child.kill()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn virtual_hosts_are_served(tmpdir: TempDir, port: u16) -> Result<(), Error> {
+    let mut child = Command::cargo_bin("miniserve")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .arg("--vhost")
+        .arg(format!(
+            "docs.example.com={}",
+            tmpdir.path().join("dirb").display()
+        ))
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    sleep(Duration::from_secs(1));
+
+    let url = format!("http://localhost:{}/{}", port, FILES[0]);
+    let content = Client::new()
+        .get(&url)
+        .header("Host", format!("docs.example.com:{}", port))
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, format!("This is dirb/{}", FILES[0]));
+
+    // Other host names fall back to the main directory
+    let content = reqwest::blocking::get(&url)?.error_for_status()?.text()?;
+    assert_eq!(content, "Test Hello Yes");
+
+    child.kill()?;
+
+    Ok(())
+}
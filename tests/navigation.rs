@@ -50,13 +50,13 @@ fn can_navigate_into_dirs_and_back(server: TestServer) -> Result<(), Error> {
     let initial_body = reqwest::blocking::get(base_url.as_str())?.error_for_status()?;
     let initial_parsed = Document::from_read(initial_body)?;
     for &directory in DIRECTORIES {
-        let dir_elem = get_link_from_text(&initial_parsed, &directory).expect("Dir not found.");
+        let dir_elem = get_link_from_text(&initial_parsed, directory).expect("Dir not found.");
         let body =
-            reqwest::blocking::get(&format!("{}{}", base_url, dir_elem))?.error_for_status()?;
+            reqwest::blocking::get(format!("{}{}", base_url, dir_elem))?.error_for_status()?;
         let parsed = Document::from_read(body)?;
         let back_link =
             get_link_from_text(&parsed, "Parent directory").expect("Back link not found.");
-        let resp = reqwest::blocking::get(&format!("{}{}", base_url, back_link))?;
+        let resp = reqwest::blocking::get(format!("{}{}", base_url, back_link))?;
 
         // Now check that we can actually get back to the original location we came from using the
         // link.
@@ -88,7 +88,7 @@ fn can_navigate_deep_into_dirs_and_back(server: TestServer) -> Result<(), Error>
         let resp = reqwest::blocking::get(next_url.as_str())?;
         let body = resp.error_for_status()?;
         let parsed = Document::from_read(body)?;
-        let dir_elem = get_link_from_text(&parsed, &dir_name).expect("Dir not found.");
+        let dir_elem = get_link_from_text(&parsed, dir_name).expect("Dir not found.");
         next_url = next_url.join(&dir_elem)?;
     }
     assert_ne!(base_url, next_url);
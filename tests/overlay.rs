@@ -0,0 +1,66 @@
+mod fixtures;
+
+use assert_cmd::prelude::*;
+use assert_fs::fixture::TempDir;
+use assert_fs::prelude::*;
+use fixtures::{port, tmpdir, Error, DIRECTORIES, FILES};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Text;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+#[rstest]
+fn overlays_are_merged_into_one_tree(tmpdir: TempDir, port: u16) -> Result<(), Error> {
+    let overlay = TempDir::new()?;
+    overlay.child("generated.txt").write_str("generated")?;
+    overlay.child("dira/extra.txt").write_str("extra")?;
+    overlay.child(FILES[0]).write_str("overridden")?;
+
+    let mut child = Command::cargo_bin("miniserve")?
+        .arg(tmpdir.path())
+        .arg("--overlay")
+        .arg(overlay.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    sleep(Duration::from_secs(1));
+
+    let url = format!("http://localhost:{}", port);
+    let body = reqwest::blocking::get(&url)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).any(|x| x.text() == "generated.txt"));
+    assert!(parsed.find(Text).any(|x| x.text() == DIRECTORIES[0]));
+    assert_eq!(
+        parsed.find(Text).filter(|x| x.text() == FILES[0]).count(),
+        1
+    );
+
+    // The entries of PATH take precedence over the ones of the overlays
+    let content = reqwest::blocking::get(format!("{}/{}", url, FILES[0]))?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "Test Hello Yes");
+
+    let body = reqwest::blocking::get(format!("{}/dira/", url))?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).any(|x| x.text() == "extra.txt"));
+    assert!(parsed.find(Text).any(|x| x.text() == FILES[1]));
+
+    let content = reqwest::blocking::get(format!("{}/dira/extra.txt", url))?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "extra");
+
+    let status = reqwest::blocking::get(format!("{}/missing.txt", url))?.status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    child.kill()?;
+
+    Ok(())
+}
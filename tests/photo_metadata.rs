@@ -0,0 +1,51 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Class;
+
+#[rstest]
+fn photos_are_grouped_by_date_taken(
+    #[with(&["--photo-metadata"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::copy("tests/data/photo.jpg", server.path().join("dira/photo.jpg"))?;
+
+    let body = reqwest::blocking::get(server.url().join("dira/?sort=taken&order=asc")?)?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let groups = parsed
+        .find(Class("date-group"))
+        .map(|group| group.text())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        groups.last().map(String::as_str),
+        Some("Wednesday 14 July 2021")
+    );
+
+    let photo_info = parsed.find(Class("photo-info")).collect::<Vec<_>>();
+    assert_eq!(photo_info.len(), 1);
+    assert_eq!(
+        photo_info[0].attr("title"),
+        Some("Taken: 2021-07-14 12:03:00\nCamera: Canon EOS 5D\nResolution: 6000×4000")
+    );
+
+    let json = reqwest::blocking::get(server.url().join("dira/?format=json")?)?
+        .error_for_status()?
+        .text()?;
+    assert!(json.contains("\"taken\":\"2021-07-14T12:03:00\""));
+
+    Ok(())
+}
+
+#[rstest]
+fn photo_metadata_is_not_read_by_default(server: TestServer) -> Result<(), Error> {
+    std::fs::copy("tests/data/photo.jpg", server.path().join("photo.jpg"))?;
+
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Class("photo-info")).next().is_none());
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Text;
+
+#[rstest]
+fn playlists_list_audio_files(server: TestServer) -> Result<(), Error> {
+    // The link is only shown in directories with audio files
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).all(|x| x.text() != "Playlist .m3u"));
+
+    std::fs::write(server.path().join("dira/02 Outro.ogg"), "Test Hello Yes")?;
+    std::fs::write(server.path().join("dira/01 Intro.mp3"), "Test Hello Yes")?;
+    std::fs::write(server.path().join("dira/cover.jpg"), "Test Hello Yes")?;
+
+    let body = reqwest::blocking::get(server.url().join("dira/")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).any(|x| x.text() == "Playlist .m3u"));
+
+    let resp =
+        reqwest::blocking::get(server.url().join("dira/?download=m3u8")?)?.error_for_status()?;
+    assert_eq!(
+        resp.headers()["content-type"],
+        "audio/x-mpegurl; charset=utf-8"
+    );
+    assert_eq!(
+        resp.headers()["content-disposition"],
+        "attachment; filename=\"dira.m3u8\""
+    );
+    let url = server.url();
+    assert_eq!(
+        resp.text()?,
+        format!(
+            "#EXTM3U\n\
+             #EXTINF:-1,01 Intro\n{url}dira/01%20Intro.mp3\n\
+             #EXTINF:-1,02 Outro\n{url}dira/02%20Outro.ogg\n",
+            url = url
+        )
+    );
+
+    Ok(())
+}
@@ -4,7 +4,7 @@ use fixtures::{server, server_no_stderr, Error, TestServer};
 use reqwest::StatusCode;
 use rstest::rstest;
 use select::document::Document;
-use select::predicate::Attr;
+use select::predicate::{Attr, Class};
 use std::iter::repeat_with;
 
 #[rstest]
@@ -12,6 +12,7 @@ fn hide_qrcode_element(server: TestServer) -> Result<(), Error> {
     let body = reqwest::blocking::get(server.url())?.error_for_status()?;
     let parsed = Document::from_read(body)?;
     assert!(parsed.find(Attr("id", "qrcode")).next().is_none());
+    assert!(parsed.find(Class("qrcode-link")).next().is_none());
 
     Ok(())
 }
@@ -25,6 +26,22 @@ fn show_qrcode_element(#[with(&["-q"])] server: TestServer) -> Result<(), Error>
     Ok(())
 }
 
+#[rstest]
+fn show_qrcode_of_each_entry(#[with(&["-q"])] server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Attr("id", "qrcode-dialog")).next().is_some());
+
+    let links = parsed
+        .find(Class("qrcode-link"))
+        .filter_map(|link| link.attr("href"))
+        .collect::<Vec<_>>();
+    assert!(links.contains(&"/test.txt"));
+    assert!(links.contains(&"/dira/"));
+
+    Ok(())
+}
+
 #[rstest]
 fn get_svg_qrcode(#[from(server_no_stderr)] server: TestServer) -> Result<(), Error> {
     // Ok
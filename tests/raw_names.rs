@@ -0,0 +1,67 @@
+#![cfg(unix)]
+
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Attr, Class};
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+#[rstest]
+fn serves_files_whose_names_are_not_utf8(server: TestServer) -> Result<(), Error> {
+    std::fs::write(
+        server.path().join(OsStr::from_bytes(b"caf\xe9.txt")),
+        "latin-1 name",
+    )?;
+
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let link = parsed.find(Attr("href", "/caf%E9.txt")).next().unwrap();
+    assert_eq!(link.text(), "caf\u{fffd}.txt");
+    assert_eq!(link.find(Class("invalid-name")).count(), 1);
+
+    let body = reqwest::blocking::get(server.url().join("caf%E9.txt")?)?.error_for_status()?;
+    assert_eq!(body.text()?, "latin-1 name");
+
+    Ok(())
+}
+
+#[rstest]
+fn lists_directories_whose_names_are_not_utf8(server: TestServer) -> Result<(), Error> {
+    let dir = server.path().join(OsStr::from_bytes(b"d\xe9j\xe0"));
+    std::fs::create_dir(&dir)?;
+    std::fs::write(dir.join("inner.txt"), "inner")?;
+
+    let body = reqwest::blocking::get(server.url().join("d%E9j%E0")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed
+        .find(Attr("href", "/d%E9j%E0/inner.txt"))
+        .next()
+        .is_some());
+
+    let body =
+        reqwest::blocking::get(server.url().join("d%E9j%E0/inner.txt")?)?.error_for_status()?;
+    assert_eq!(body.text()?, "inner");
+
+    Ok(())
+}
+
+#[rstest]
+fn archives_include_files_whose_names_are_not_utf8(
+    #[with(&["--enable-tar"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join(OsStr::from_bytes(b"caf\xe9.txt")), "")?;
+
+    let body = reqwest::blocking::get(server.url().join("?download=tar")?)?.error_for_status()?;
+    let mut archive = tar::Archive::new(body);
+    let names = archive
+        .entries()?
+        .map(|entry| Ok(entry?.path_bytes().into_owned()))
+        .collect::<Result<Vec<_>, Error>>()?;
+    assert!(names.iter().any(|name| name.ends_with(b"/caf\xe9.txt")));
+
+    Ok(())
+}
@@ -0,0 +1,140 @@
+#![cfg(unix)]
+
+mod fixtures;
+
+use assert_cmd::prelude::*;
+use fixtures::{port, tmpdir, Error};
+use reqwest::blocking::Client;
+use reqwest::header;
+use reqwest::redirect::Policy;
+use reqwest::StatusCode;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Start miniserve with the configuration file at `config_path`, and wait until it listens
+fn start_server(served_dir: &Path, config_path: &Path) -> Result<(Child, u16), Error> {
+    let port = port();
+    let child = Command::cargo_bin("miniserve")?
+        .arg(served_dir)
+        .arg("-p")
+        .arg(port.to_string())
+        .arg("--config")
+        .arg(config_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let start = Instant::now();
+    while !port_check::is_port_reachable(format!("localhost:{}", port)) {
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "timeout waiting for port {}",
+            port
+        );
+        sleep(Duration::from_millis(100));
+    }
+    Ok((child, port))
+}
+
+/// Make `child` reload its configuration file
+fn reload(child: &Child) -> Result<(), Error> {
+    Command::new("kill")
+        .arg("-HUP")
+        .arg(child.id().to_string())
+        .status()?;
+    sleep(Duration::from_millis(500));
+    Ok(())
+}
+
+#[test]
+fn credentials_are_reloaded_on_sighup() -> Result<(), Error> {
+    let tmpdir = tmpdir();
+    let config_dir = assert_fs::TempDir::new()?;
+    let config_path = config_dir.path().join("miniserve.toml");
+    std::fs::write(&config_path, "auth = [\"joe:123\"]\n")?;
+    let (mut child, port) = start_server(tmpdir.path(), &config_path)?;
+
+    let url = format!("http://localhost:{}", port);
+    let status = |password: &str| -> Result<StatusCode, Error> {
+        Ok(Client::new()
+            .get(&url)
+            .basic_auth("joe", Some(password))
+            .send()?
+            .status())
+    };
+    let before = (status("123")?, status("456")?);
+
+    std::fs::write(&config_path, "auth = [\"joe:456\"]\n")?;
+    reload(&child)?;
+    let after = (status("123")?, status("456")?);
+
+    child.kill()?;
+    child.wait()?;
+
+    assert_eq!(before, (StatusCode::OK, StatusCode::UNAUTHORIZED));
+    assert_eq!(after, (StatusCode::UNAUTHORIZED, StatusCode::OK));
+
+    Ok(())
+}
+
+#[test]
+fn route_rules_and_sessions_are_reloaded_on_sighup() -> Result<(), Error> {
+    let tmpdir = tmpdir();
+    let config_dir = assert_fs::TempDir::new()?;
+    let config_path = config_dir.path().join("miniserve.toml");
+    std::fs::write(
+        &config_path,
+        "auth = [\"joe:123\"]\nlogin-form = true\ncache-control = [\"*.txt=no-cache\"]\n",
+    )?;
+    let (mut child, port) = start_server(tmpdir.path(), &config_path)?;
+
+    let client = Client::builder().redirect(Policy::none()).build()?;
+    let url = format!("http://localhost:{}/test.txt", port);
+    let login = client
+        .post(format!("http://localhost:{}/__login", port))
+        .form(&[("username", "joe"), ("password", "123")])
+        .send()?;
+    let session = login.headers()[header::SET_COOKIE]
+        .to_str()?
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+    let session_status = || -> Result<StatusCode, Error> {
+        Ok(client
+            .get(&url)
+            .header(header::COOKIE, &session)
+            .send()?
+            .status())
+    };
+    let cache_control = |password: &str| -> Result<String, Error> {
+        let response = client
+            .get(&url)
+            .basic_auth("joe", Some(password))
+            .send()?
+            .error_for_status()?;
+        Ok(response.headers()[header::CACHE_CONTROL]
+            .to_str()?
+            .to_string())
+    };
+    let before = (session_status()?, cache_control("123")?);
+
+    std::fs::write(
+        &config_path,
+        "auth = [\"joe:456\"]\nlogin-form = true\ncache-control = [\"*.txt=max-age=60\"]\n",
+    )?;
+    reload(&child)?;
+    let after = (session_status()?, cache_control("456")?);
+
+    child.kill()?;
+    child.wait()?;
+
+    assert_eq!(before, (StatusCode::OK, "no-cache".to_string()));
+    // The session of the old password ended
+    assert_ne!(after.0, StatusCode::OK);
+    assert_eq!(after.1, "max-age=60");
+
+    Ok(())
+}
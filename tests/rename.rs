@@ -0,0 +1,70 @@
+mod fixtures;
+
+use fixtures::{server, server_no_stderr, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Class;
+
+#[rstest]
+fn renaming_files_works(#[with(&["--enable-rename"])] server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Class("rename")).next().is_some());
+
+    let response = Client::new()
+        .post(server.url().join("/__rename")?)
+        .form(&[("from", "/test.txt"), ("to", "/dira/moved.txt")])
+        .send()?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    assert!(!server.path().join("test.txt").exists());
+    let content = reqwest::blocking::get(server.url().join("/dira/moved.txt")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "Test Hello Yes");
+
+    Ok(())
+}
+
+#[rstest(
+    from,
+    to,
+    expected,
+    case("/test.txt", "/test.html", StatusCode::CONFLICT),
+    case("/missing.txt", "/renamed.txt", StatusCode::NOT_FOUND),
+    case("/test.txt", "/../escaped.txt", StatusCode::BAD_REQUEST),
+    case("/test.txt", "/missing/renamed.txt", StatusCode::BAD_REQUEST),
+    case("/dira/", "/dira/inside", StatusCode::BAD_REQUEST)
+)]
+fn invalid_renames_are_refused(from: &str, to: &str, expected: StatusCode) -> Result<(), Error> {
+    let server = server_no_stderr(&["--enable-rename"]);
+
+    let status = Client::new()
+        .post(server.url().join("/__rename")?)
+        .form(&[("from", from), ("to", to)])
+        .send()?
+        .status();
+    assert_eq!(status, expected);
+    assert!(!server.path().parent().unwrap().join("escaped.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn renaming_is_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Class("rename")).next().is_none());
+
+    let response = Client::new()
+        .post(server.url().join("/__rename")?)
+        .form(&[("from", "/test.txt"), ("to", "/renamed.txt")])
+        .send()?;
+    assert!(!response.status().is_success());
+    assert!(server.path().join("test.txt").exists());
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+mod fixtures;
+
+use assert_fs::TempDir;
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use rstest::rstest;
+
+/// Width and height of the PNG image `png`
+fn png_size(png: &[u8]) -> (u32, u32) {
+    let dimension = |offset: usize| {
+        u32::from_be_bytes([
+            png[offset],
+            png[offset + 1],
+            png[offset + 2],
+            png[offset + 3],
+        ])
+    };
+    (dimension(16), dimension(20))
+}
+
+#[rstest]
+fn images_are_downscaled() -> Result<(), Error> {
+    let cache = TempDir::new()?;
+    let server = server(&[
+        "--enable-resize",
+        "--resize-cache",
+        cache.path().to_str().unwrap(),
+    ]);
+    std::fs::copy("tests/data/wide.png", server.path().join("wide.png"))?;
+
+    let resp =
+        reqwest::blocking::get(server.url().join("wide.png?width=150")?)?.error_for_status()?;
+    assert_eq!(resp.headers()["content-type"], "image/png");
+    assert_eq!(png_size(&resp.bytes()?), (200, 13));
+
+    // Images are never made larger
+    let png = reqwest::blocking::get(server.url().join("wide.png?width=1000")?)?
+        .error_for_status()?
+        .bytes()?;
+    assert_eq!(png_size(&png), (300, 20));
+
+    let status = reqwest::blocking::get(server.url().join("wide.png?width=0")?)?.status();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
+
+#[rstest]
+fn resizing_is_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    std::fs::copy("tests/data/wide.png", server.path().join("wide.png"))?;
+
+    let png = reqwest::blocking::get(server.url().join("wide.png?width=150")?)?
+        .error_for_status()?
+        .bytes()?;
+    assert_eq!(png_size(&png), (300, 20));
+
+    Ok(())
+}
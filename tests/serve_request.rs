@@ -3,13 +3,15 @@ mod fixtures;
 use assert_cmd::prelude::*;
 use assert_fs::fixture::TempDir;
 use fixtures::{
-    port, server, tmpdir, Error, TestServer, DIRECTORIES, FILES, HIDDEN_DIRECTORIES, HIDDEN_FILES,
+    port, server, server_no_stderr, tmpdir, Error, TestServer, DIRECTORIES, FILES,
+    HIDDEN_DIRECTORIES, HIDDEN_FILES,
 };
 use http::StatusCode;
 use regex::Regex;
 use rstest::rstest;
 use select::document::Document;
 use select::node::Node;
+use std::io::Read;
 use std::process::{Command, Stdio};
 use std::thread::sleep;
 use std::time::Duration;
@@ -58,8 +60,7 @@ fn serves_requests_with_non_default_port(server: TestServer) -> Result<(), Error
             .find(|x: &Node| x.text() == directory)
             .next()
             .is_some());
-        let dir_body =
-            reqwest::blocking::get(server.url().join(&directory)?)?.error_for_status()?;
+        let dir_body = reqwest::blocking::get(server.url().join(directory)?)?.error_for_status()?;
         let dir_body_parsed = Document::from_read(dir_body)?;
         for &file in FILES {
             assert!(dir_body_parsed
@@ -72,12 +73,51 @@ fn serves_requests_with_non_default_port(server: TestServer) -> Result<(), Error
     Ok(())
 }
 
+#[cfg(target_os = "linux")]
+#[rstest]
+fn serves_requests_on_named_interface(
+    #[with(&["--interface", "lo"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    for &file in FILES {
+        assert!(parsed.find(|x: &Node| x.text() == file).next().is_some());
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn serves_requests_when_upnp_forwarding_fails() -> Result<(), Error> {
+    // No router answers in the test environment, which must not stop the server
+    let server = server_no_stderr(&["--upnp-forward"]);
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    for &file in FILES {
+        assert!(parsed.find(|x: &Node| x.text() == file).next().is_some());
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn unknown_interfaces_are_refused(tmpdir: TempDir) -> Result<(), Error> {
+    Command::cargo_bin("miniserve")?
+        .arg(tmpdir.path())
+        .arg("--interface")
+        .arg("miniserve-missing0")
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
 #[rstest]
 fn serves_requests_hidden_files(#[with(&["--hidden"])] server: TestServer) -> Result<(), Error> {
     let body = reqwest::blocking::get(server.url())?.error_for_status()?;
     let parsed = Document::from_read(body)?;
 
-    for &file in FILES.into_iter().chain(HIDDEN_FILES) {
+    for &file in FILES.iter().chain(HIDDEN_FILES) {
         let f = parsed.find(|x: &Node| x.text() == file).next().unwrap();
         assert_eq!(
             format!("/{}", file),
@@ -85,15 +125,14 @@ fn serves_requests_hidden_files(#[with(&["--hidden"])] server: TestServer) -> Re
         );
     }
 
-    for &directory in DIRECTORIES.into_iter().chain(HIDDEN_DIRECTORIES) {
+    for &directory in DIRECTORIES.iter().chain(HIDDEN_DIRECTORIES) {
         assert!(parsed
             .find(|x: &Node| x.text() == directory)
             .next()
             .is_some());
-        let dir_body =
-            reqwest::blocking::get(server.url().join(&directory)?)?.error_for_status()?;
+        let dir_body = reqwest::blocking::get(server.url().join(directory)?)?.error_for_status()?;
         let dir_body_parsed = Document::from_read(dir_body)?;
-        for &file in FILES.into_iter().chain(HIDDEN_FILES) {
+        for &file in FILES.iter().chain(HIDDEN_FILES) {
             assert!(dir_body_parsed
                 .find(|x: &Node| x.text() == file)
                 .next()
@@ -109,12 +148,12 @@ fn serves_requests_no_hidden_files_without_flag(server: TestServer) -> Result<()
     let body = reqwest::blocking::get(server.url())?.error_for_status()?;
     let parsed = Document::from_read(body)?;
 
-    for &hidden_item in HIDDEN_FILES.into_iter().chain(HIDDEN_DIRECTORIES) {
+    for &hidden_item in HIDDEN_FILES.iter().chain(HIDDEN_DIRECTORIES) {
         assert!(parsed
             .find(|x: &Node| x.text() == hidden_item)
             .next()
             .is_none());
-        let resp = reqwest::blocking::get(server.url().join(&hidden_item)?)?;
+        let resp = reqwest::blocking::get(server.url().join(hidden_item)?)?;
         assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
     }
 
@@ -148,7 +187,7 @@ fn serves_requests_symlinks(
     let body = reqwest::blocking::get(server.url())?.error_for_status()?;
     let parsed = Document::from_read(body)?;
 
-    for &entry in files.into_iter().chain(dirs) {
+    for &entry in files.iter().chain(dirs) {
         let node = parsed
             .find(|x: &Node| x.name().unwrap_or_default() == "a" && x.text() == entry)
             .next();
@@ -159,7 +198,7 @@ fn serves_requests_symlinks(
 
         let node = node.unwrap();
         assert_eq!(node.attr("href").unwrap().strip_prefix("/").unwrap(), entry);
-        reqwest::blocking::get(server.url().join(&entry)?)?.error_for_status()?;
+        reqwest::blocking::get(server.url().join(entry)?)?.error_for_status()?;
         if entry.ends_with("/") {
             assert_eq!(node.attr("class").unwrap(), "directory");
         } else {
@@ -178,7 +217,7 @@ fn serves_requests_with_randomly_assigned_port(tmpdir: TempDir) -> Result<(), Er
     let mut child = Command::cargo_bin("miniserve")?
         .arg(tmpdir.path())
         .arg("-p")
-        .arg("0".to_string())
+        .arg("0")
         .stdout(Stdio::piped())
         .spawn()?;
 
@@ -231,3 +270,449 @@ fn index_fallback_to_listing(#[case] server: TestServer) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[rstest]
+fn serves_precompressed_files(
+    #[with(&["--precompressed"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("test.txt.br"), "brotli")?;
+    std::fs::write(server.path().join("test.txt.gz"), "gzip")?;
+    let url = server.url().join("test.txt")?;
+    let client = reqwest::blocking::Client::new();
+
+    let resp = client
+        .get(url.clone())
+        .header("Accept-Encoding", "gzip, br")
+        .send()?
+        .error_for_status()?;
+    assert_eq!(resp.headers()["content-encoding"], "br");
+    assert!(resp.headers()["content-type"]
+        .to_str()?
+        .starts_with("text/plain"));
+    assert_eq!(resp.text()?, "brotli");
+
+    let resp = client
+        .get(url.clone())
+        .header("Accept-Encoding", "gzip")
+        .send()?
+        .error_for_status()?;
+    assert_eq!(resp.headers()["content-encoding"], "gzip");
+    assert_eq!(resp.text()?, "gzip");
+
+    // Clients which don't accept the encodings get the original file
+    let resp = client.get(url).send()?.error_for_status()?;
+    assert!(resp.headers().get("content-encoding").is_none());
+    assert_eq!(resp.text()?, "Test Hello Yes");
+
+    Ok(())
+}
+
+#[rstest]
+fn compresses_text_responses(
+    #[with(&["--compress", "gzip"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("big.txt"), "text ".repeat(1000))?;
+    std::fs::write(server.path().join("big.mkv"), "text ".repeat(1000))?;
+    let client = reqwest::blocking::Client::new();
+    let get = |path: &str, accept: &str| {
+        client
+            .get(server.url().join(path).unwrap())
+            .header("Accept-Encoding", accept)
+            .send()
+    };
+
+    let resp = get("", "br, gzip")?.error_for_status()?;
+    assert_eq!(resp.headers()["content-encoding"], "gzip");
+    let resp = get("big.txt", "gzip")?.error_for_status()?;
+    assert_eq!(resp.headers()["content-encoding"], "gzip");
+
+    // Encodings which aren't enabled, small files and videos are sent as they are
+    for &(path, accept) in &[("big.txt", "br"), ("test.txt", "gzip"), ("big.mkv", "gzip")] {
+        let resp = get(path, accept)?.error_for_status()?;
+        assert!(resp.headers().get("content-encoding").is_none());
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn listings_are_revalidated_with_etags(server: TestServer) -> Result<(), Error> {
+    let client = reqwest::blocking::Client::new();
+
+    let resp = client.get(server.url()).send()?.error_for_status()?;
+    let etag = resp.headers()["etag"].to_str()?.to_string();
+    assert!(etag.starts_with("W/\""));
+    assert!(resp.headers().get("last-modified").is_some());
+
+    let resp = client
+        .get(server.url())
+        .header("If-None-Match", &etag)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(resp.text()?, "");
+
+    // Another sorting renders another page
+    let resp = client
+        .get(server.url().join("?sort=size")?)
+        .header("If-None-Match", &etag)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // So does a new file
+    std::fs::write(server.path().join("new.txt"), "new")?;
+    let resp = client
+        .get(server.url())
+        .header("If-None-Match", &etag)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_ne!(resp.headers()["etag"].to_str()?, etag);
+
+    Ok(())
+}
+
+#[rstest]
+fn sorts_names_with_numbers_naturally(server: TestServer) -> Result<(), Error> {
+    std::fs::create_dir(server.path().join("natural"))?;
+    for name in &[
+        "file10.txt",
+        "v1.10.tar",
+        "file2.txt",
+        "v1.9.tar",
+        "File3.txt",
+    ] {
+        std::fs::write(server.path().join("natural").join(name), "")?;
+    }
+
+    let body =
+        reqwest::blocking::get(server.url().join("natural/?sort=name")?)?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let names = parsed
+        .find(select::predicate::Class("file"))
+        .map(|x| x.text())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "file2.txt",
+            "File3.txt",
+            "file10.txt",
+            "v1.9.tar",
+            "v1.10.tar"
+        ]
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn sets_cache_control_of_matching_files(
+    #[with(&["--cache-control", "*.html=no-cache", "--cache-control", "*.txt,*.css=max-age=60"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(server.url().join("test.txt")?)?.error_for_status()?;
+    assert_eq!(resp.headers()["cache-control"], "max-age=60");
+
+    let resp = reqwest::blocking::get(server.url().join("test.html")?)?.error_for_status()?;
+    assert_eq!(resp.headers()["cache-control"], "no-cache");
+
+    let resp = reqwest::blocking::get(server.url().join("test.mkv")?)?.error_for_status()?;
+    assert!(resp.headers().get("cache-control").is_none());
+
+    // Listings keep being revalidated
+    let resp = reqwest::blocking::get(server.url())?.error_for_status()?;
+    assert_eq!(resp.headers()["cache-control"], "no-cache");
+
+    Ok(())
+}
+
+#[rstest]
+fn overrides_mime_types(
+    #[with(&["--mime-type", "mkv=text/plain", "--mime-type", "TXT=application/x-custom"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(server.url().join("test.mkv")?)?.error_for_status()?;
+    assert_eq!(resp.headers()["content-type"], "text/plain");
+    assert!(resp.headers()["content-disposition"]
+        .to_str()?
+        .starts_with("inline"));
+
+    let resp = reqwest::blocking::get(server.url().join("test.txt")?)?.error_for_status()?;
+    assert_eq!(resp.headers()["content-type"], "application/x-custom");
+
+    let resp = reqwest::blocking::get(server.url().join("test.html")?)?.error_for_status()?;
+    assert!(resp.headers()["content-type"]
+        .to_str()?
+        .starts_with("text/html"));
+
+    Ok(())
+}
+
+#[rstest]
+fn forces_download_of_matching_files(
+    #[with(&["--force-download", "*.html,*.svg"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(server.url().join("test.html")?)?.error_for_status()?;
+    let disposition = resp.headers()["content-disposition"].to_str()?;
+    assert!(disposition.starts_with("attachment"));
+    assert!(disposition.contains("test.html"));
+
+    let resp = reqwest::blocking::get(server.url().join("test.txt")?)?.error_for_status()?;
+    assert!(resp.headers()["content-disposition"]
+        .to_str()?
+        .starts_with("inline"));
+
+    // Listings are still displayed
+    let resp = reqwest::blocking::get(server.url())?.error_for_status()?;
+    assert!(resp.headers().get("content-disposition").is_none());
+
+    Ok(())
+}
+
+#[rstest]
+fn serves_small_files_from_memory(
+    #[with(&["--memory-cache", "1048576"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = server.url().join("test.txt")?;
+    let client = reqwest::blocking::Client::new();
+
+    let resp = client.get(url.clone()).send()?.error_for_status()?;
+    let etag = resp.headers()["etag"].to_str()?.to_string();
+    assert!(resp.headers()["content-type"]
+        .to_str()?
+        .starts_with("text/plain"));
+    assert_eq!(resp.text()?, "Test Hello Yes");
+
+    let resp = client
+        .get(url.clone())
+        .header("If-None-Match", &etag)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+    // Changes are seen once the cached copy is checked again
+    sleep(Duration::from_millis(1100));
+    std::fs::write(server.path().join("test.txt"), "Changed")?;
+    let resp = client.get(url).send()?.error_for_status()?;
+    assert_eq!(resp.text()?, "Changed");
+
+    Ok(())
+}
+
+#[rstest]
+fn cached_listings_show_changes(
+    #[with(&["--listing-cache"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed
+        .find(|x: &Node| x.text() == "new.txt")
+        .next()
+        .is_none());
+
+    std::fs::write(server.path().join("new.txt"), "new")?;
+    sleep(Duration::from_millis(500));
+
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed
+        .find(|x: &Node| x.text() == "new.txt")
+        .next()
+        .is_some());
+
+    Ok(())
+}
+
+#[rstest]
+fn pushes_changes_of_listed_directories(
+    #[with(&["--live-updates"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    assert!(body.text()?.contains("EventSource"));
+
+    let mut events =
+        reqwest::blocking::get(server.url().join("__events?path=/")?)?.error_for_status()?;
+    assert_eq!(events.headers()["content-type"], "text/event-stream");
+
+    std::fs::write(server.path().join("new.txt"), "new")?;
+    let mut buffer = [0; 1024];
+    let read = events.read(&mut buffer)?;
+    let event = String::from_utf8_lossy(&buffer[..read]);
+    assert!(event.starts_with("event: change\n"));
+    assert!(event.contains(r#""name":"new.txt""#));
+
+    // Hidden directories can't be watched
+    let resp = reqwest::blocking::get(server.url().join("__events?path=/.hidden_dir1")?)?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn lists_directories_as_text(server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url().join("?format=txt")?)?
+        .error_for_status()?
+        .text()?;
+    let lines = body.lines().collect::<Vec<_>>();
+    for &file in FILES {
+        assert!(lines.contains(&file));
+    }
+    for &directory in DIRECTORIES {
+        assert!(lines.contains(&directory));
+    }
+    for &hidden_directory in HIDDEN_DIRECTORIES {
+        assert!(!lines.contains(&hidden_directory));
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn lists_directories_as_json(server: TestServer) -> Result<(), Error> {
+    let client = reqwest::blocking::Client::new();
+    let resp = client
+        .get(server.url())
+        .header("Accept", "application/json")
+        .send()?
+        .error_for_status()?;
+    assert_eq!(resp.headers()["content-type"], "application/json");
+    let entries: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    let entries = entries.as_array().unwrap();
+    // The directory of the deeply nested file is listed too
+    assert_eq!(entries.len(), FILES.len() + DIRECTORIES.len() + 1);
+
+    let file = entries
+        .iter()
+        .find(|entry| entry["name"] == "test.txt")
+        .unwrap();
+    assert_eq!(file["type"], "file");
+    assert_eq!(file["url"], "/test.txt");
+    assert_eq!(file["size"], "Test Hello Yes".len());
+    let directory = entries
+        .iter()
+        .find(|entry| entry["name"] == "dira")
+        .unwrap();
+    assert_eq!(directory["type"], "directory");
+    assert!(directory["size"].is_null());
+
+    // Browsers still get the HTML page
+    let resp = client
+        .get(server.url())
+        .header("Accept", "text/html,application/xhtml+xml,*/*;q=0.8")
+        .send()?;
+    assert!(resp.headers()["content-type"]
+        .to_str()?
+        .starts_with("text/html"));
+
+    Ok(())
+}
+
+#[rstest]
+fn serves_feed_of_newest_files(
+    #[with(&["--enable-feed"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("dira").join("newest.mp3"), "new")?;
+
+    let resp = reqwest::blocking::get(server.url().join("feed.xml")?)?.error_for_status()?;
+    assert!(resp.headers()["content-type"]
+        .to_str()?
+        .starts_with("application/atom+xml"));
+    let feed = resp.text()?;
+    assert!(feed.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+    let newest = feed.find("<title>dira/newest.mp3</title>").unwrap();
+    assert!(newest < feed.find("<title>test.txt</title>").unwrap_or(feed.len()));
+    assert!(feed.contains("type=\"audio/mpeg\" length=\"3\""));
+    assert!(!feed.contains(".hidden_file1"));
+
+    let feed = reqwest::blocking::get(server.url().join("feed.xml?path=/dira")?)?
+        .error_for_status()?
+        .text()?;
+    assert!(feed.contains("<title>newest.mp3</title>"));
+    assert!(!feed.contains("<title>dirb/"));
+
+    Ok(())
+}
+
+#[rstest]
+fn serves_opds_catalog_of_ebooks(
+    #[with(&["--enable-opds"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("dira").join("novel.epub"), "book")?;
+
+    let resp = reqwest::blocking::get(server.url().join("opds")?)?.error_for_status()?;
+    assert!(resp.headers()["content-type"]
+        .to_str()?
+        .starts_with("application/atom+xml;profile=opds-catalog"));
+    let catalog = resp.text()?;
+    assert!(catalog.contains("<link rel=\"subsection\" href=\"http://localhost:"));
+    assert!(catalog.contains("/opds?path=%2Fdira\""));
+    assert!(!catalog.contains("<title>test.txt</title>"));
+
+    let catalog = reqwest::blocking::get(server.url().join("opds?path=/dira")?)?
+        .error_for_status()?
+        .text()?;
+    assert!(catalog.contains("<title>novel.epub</title>"));
+    assert!(catalog.contains("/dira/novel.epub\" type=\"application/epub+zip\" length=\"4\""));
+
+    let status = reqwest::blocking::get(server.url().join("opds?path=/missing")?)?.status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn feed_is_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let status = reqwest::blocking::get(server.url().join("feed.xml")?)?.status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn serves_custom_error_pages(
+    #[with(&["--error-page", "404=tests/data/404.html"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(server.url().join("missing.txt")?)?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.headers()["content-type"], "text/html; charset=utf-8");
+    assert!(resp.text()?.contains("Custom Not Found"));
+
+    // Files are still served
+    let body = reqwest::blocking::get(server.url().join("test.txt")?)?.error_for_status()?;
+    assert_eq!(body.text()?, "Test Hello Yes");
+
+    Ok(())
+}
+
+#[rstest]
+fn downloads_notify_webhook() -> Result<(), Error> {
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let webhook_url = format!("http://{}/hook", listener.local_addr()?);
+    let server = server_no_stderr(&["--on-download-webhook", &webhook_url]);
+
+    let body = reqwest::blocking::get(server.url().join("test.txt")?)?.error_for_status()?;
+    assert_eq!(body.text()?, "Test Hello Yes");
+
+    let (mut stream, _) = listener.accept()?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = Vec::new();
+    let mut buffer = [0; 4096];
+    while !String::from_utf8_lossy(&request).ends_with('}') {
+        let len = stream.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..len]);
+    }
+    stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")?;
+
+    let request = String::from_utf8_lossy(&request);
+    assert!(request.starts_with("POST /hook "));
+    assert!(request.contains(r#""action":"download""#));
+    assert!(request.contains(r#""path":"/test.txt""#));
+    assert!(request.contains(r#""bytes":14"#));
+    assert!(request.contains(r#""duration_ms":"#));
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use rstest::rstest;
+use std::sync::{Arc, Barrier};
+
+#[rstest]
+fn share_link_grants_access(
+    #[with(&["--enable-share-links", "--auth", "testuser:testpassword"])] server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+
+    // Creating share links requires credentials
+    let status = client
+        .get(server.url().join("/__share?path=/test.txt")?)
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let link = client
+        .get(server.url().join("/__share?path=/test.txt")?)
+        .basic_auth("testuser", Some("testpassword"))
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert!(link.contains("/test.txt?token="));
+
+    // The link works without credentials, but only for the shared file
+    let body = client.get(&link).send()?.error_for_status()?.text()?;
+    assert_eq!(body, "Test Hello Yes");
+
+    let other_file = link.replace("/test.txt", "/test.html");
+    let status = client.get(&other_file).send()?.status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[rstest]
+fn share_link_grants_access_to_directory(
+    #[with(&["--enable-share-links", "--auth", "testuser:testpassword"])] server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+
+    let link = client
+        .get(server.url().join("/__share?path=/dira/")?)
+        .basic_auth("testuser", Some("testpassword"))
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    let response = client.get(&link).send()?.error_for_status()?;
+    let cookie = response.headers()["Set-Cookie"]
+        .to_str()?
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+
+    // Files inside of the directory are accessible with the share cookie
+    let status = client
+        .get(server.url().join("/dira/test.txt")?)
+        .header("Cookie", &cookie)
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::OK);
+
+    Ok(())
+}
+
+#[rstest]
+fn share_link_rejects_tampered_token(
+    #[with(&["--enable-share-links", "--auth", "testuser:testpassword"])] server: TestServer,
+) -> Result<(), Error> {
+    let status = Client::new()
+        .get(
+            server
+                .url()
+                .join("/test.txt?token=2f746573742e747874.99999999999.00")?,
+        )
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[rstest]
+fn single_use_share_link_expires_after_download(
+    #[with(&["--enable-share-links", "--auth", "testuser:testpassword"])] server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+
+    let link = client
+        .get(server.url().join("/__share?path=/test.txt&once=true")?)
+        .basic_auth("testuser", Some("testpassword"))
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    let body = client.get(&link).send()?.error_for_status()?.text()?;
+    assert_eq!(body, "Test Hello Yes");
+
+    let status = client.get(&link).send()?.status();
+    assert_eq!(status, StatusCode::GONE);
+
+    // Directories can't be shared with single-use links
+    let status = client
+        .get(server.url().join("/__share?path=/dira/&once=true")?)
+        .basic_auth("testuser", Some("testpassword"))
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
+
+#[rstest]
+fn single_use_share_link_expires_after_partial_download(
+    #[with(&["--enable-share-links", "--auth", "testuser:testpassword"])] server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+
+    let link = client
+        .get(server.url().join("/__share?path=/test.txt&once=true")?)
+        .basic_auth("testuser", Some("testpassword"))
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    let response = client.get(&link).header("Range", "bytes=0-3").send()?;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.text()?, "Test");
+
+    let status = client.get(&link).send()?.status();
+    assert_eq!(status, StatusCode::GONE);
+
+    Ok(())
+}
+
+#[rstest]
+fn single_use_share_link_is_used_by_one_of_parallel_requests(
+    #[with(&["--enable-share-links", "--auth", "testuser:testpassword"])] server: TestServer,
+) -> Result<(), Error> {
+    let link = Client::new()
+        .get(server.url().join("/__share?path=/test.txt&once=true")?)
+        .basic_auth("testuser", Some("testpassword"))
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    let barrier = Arc::new(Barrier::new(2));
+    let requests = (0..2)
+        .map(|_| {
+            let (link, barrier) = (link.clone(), barrier.clone());
+            std::thread::spawn(move || {
+                barrier.wait();
+                Client::new()
+                    .get(&link)
+                    .send()
+                    .map(|response| response.status())
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut statuses = requests
+        .into_iter()
+        .map(|request| request.join().unwrap())
+        .collect::<Result<Vec<_>, _>>()?;
+    statuses.sort();
+    assert_eq!(statuses, vec![StatusCode::OK, StatusCode::GONE]);
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+#![cfg(unix)]
+
+mod fixtures;
+
+use assert_cmd::prelude::*;
+use assert_fs::TempDir;
+use fixtures::{port, tmpdir, Error};
+use pretty_assertions::assert_eq;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+#[test]
+fn sigterm_stops_idle_server() -> Result<(), Error> {
+    let port = port();
+    let tmpdir = tmpdir();
+    let mut child = Command::cargo_bin("miniserve")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .arg("--shutdown-grace-period")
+        .arg("10")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let start = Instant::now();
+    while !port_check::is_port_reachable(format!("localhost:{}", port)) {
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "timeout waiting for port {}",
+            port
+        );
+        sleep(Duration::from_millis(100));
+    }
+
+    Command::new("kill")
+        .arg("-TERM")
+        .arg(child.id().to_string())
+        .status()?;
+
+    // Without running transfers, the server doesn't wait for the grace period
+    let start = Instant::now();
+    while child.try_wait()?.is_none() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "timeout waiting for shutdown"
+        );
+        sleep(Duration::from_millis(100));
+    }
+    assert!(!port_check::is_port_reachable(format!(
+        "localhost:{}",
+        port
+    )));
+
+    Ok(())
+}
+
+/// Start miniserve with `args`, and wait until it listens. The served directory is deleted once
+/// the returned `TempDir` is dropped.
+fn start_server(args: &[&str]) -> Result<(std::process::Child, u16, TempDir), Error> {
+    let port = port();
+    let tmpdir = tmpdir();
+    let child = Command::cargo_bin("miniserve")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let start = Instant::now();
+    while !port_check::is_port_reachable(format!("localhost:{}", port)) {
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "timeout waiting for port {}",
+            port
+        );
+        sleep(Duration::from_millis(100));
+    }
+    Ok((child, port, tmpdir))
+}
+
+#[test]
+fn idle_server_stops_itself() -> Result<(), Error> {
+    let (mut child, _, _tmpdir) = start_server(&["--shutdown-after-idle", "2s"])?;
+
+    let start = Instant::now();
+    while child.try_wait()?.is_none() {
+        assert!(
+            start.elapsed() < Duration::from_secs(6),
+            "timeout waiting for shutdown"
+        );
+        sleep(Duration::from_millis(100));
+    }
+    assert!(start.elapsed() >= Duration::from_secs(1));
+
+    Ok(())
+}
+
+#[test]
+fn server_stops_after_max_downloads() -> Result<(), Error> {
+    let (mut child, port, _tmpdir) = start_server(&["--max-downloads", "2"])?;
+    let url = format!("http://localhost:{}/test.txt", port);
+
+    reqwest::blocking::get(&url)?.error_for_status()?;
+    // Listings aren't downloads
+    reqwest::blocking::get(format!("http://localhost:{}/", port))?.error_for_status()?;
+    sleep(Duration::from_secs(1));
+    assert!(child.try_wait()?.is_none());
+
+    let content = reqwest::blocking::get(&url)?.error_for_status()?.text()?;
+    assert_eq!(content, "Test Hello Yes");
+
+    let start = Instant::now();
+    while child.try_wait()?.is_none() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "timeout waiting for shutdown"
+        );
+        sleep(Duration::from_millis(100));
+    }
+
+    Ok(())
+}
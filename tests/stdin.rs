@@ -0,0 +1,45 @@
+mod fixtures;
+
+use assert_cmd::prelude::*;
+use fixtures::{port, Error};
+use pretty_assertions::assert_eq;
+use reqwest::header;
+use rstest::rstest;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+#[rstest]
+fn stdin_is_served_as_a_file(port: u16) -> Result<(), Error> {
+    let mut child = Command::cargo_bin("miniserve")?
+        .arg("-p")
+        .arg(port.to_string())
+        .arg("--from-stdin")
+        .arg("--filename")
+        .arg("output.log")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    let mut stdin = child
+        .stdin
+        .take()
+        .expect("Couldn't open the standard input");
+    stdin.write_all(b"line 1\nline 2\n")?;
+    drop(stdin);
+
+    sleep(Duration::from_secs(1));
+
+    let resp = reqwest::blocking::get(format!("http://localhost:{}", port))?.error_for_status()?;
+    assert_eq!(resp.headers()[header::CONTENT_LENGTH], "14");
+    let disposition = resp.headers()[header::CONTENT_DISPOSITION]
+        .to_str()?
+        .to_string();
+    assert!(disposition.contains("output.log"));
+    assert_eq!(resp.text()?, "line 1\nline 2\n");
+
+    child.kill()?;
+
+    Ok(())
+}
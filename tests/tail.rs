@@ -0,0 +1,52 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+
+#[rstest]
+fn last_lines_are_sent(#[with(&["--enable-tail"])] server: TestServer) -> Result<(), Error> {
+    std::fs::write(server.path().join("app.log"), "a\nb\nc\n")?;
+
+    let tail = reqwest::blocking::get(server.url().join("app.log?tail=2")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(tail, "b\nc\n");
+
+    Ok(())
+}
+
+#[rstest]
+fn appended_lines_are_followed(
+    #[with(&["--enable-tail"])] server: TestServer,
+) -> Result<(), Error> {
+    let path = server.path().join("app.log");
+    std::fs::write(&path, "a\nb\n")?;
+
+    let mut resp = reqwest::blocking::get(server.url().join("app.log?tail=1&follow=1")?)?
+        .error_for_status()?;
+    let mut buf = [0; 64];
+    let len = resp.read(&mut buf)?;
+    assert_eq!(&buf[..len], b"b\n");
+
+    OpenOptions::new()
+        .append(true)
+        .open(&path)?
+        .write_all(b"c\n")?;
+    let len = resp.read(&mut buf)?;
+    assert_eq!(&buf[..len], b"c\n");
+
+    Ok(())
+}
+
+#[rstest]
+fn tail_is_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let content = reqwest::blocking::get(server.url().join("test.txt?tail=1")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "Test Hello Yes");
+
+    Ok(())
+}
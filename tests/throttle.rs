@@ -0,0 +1,81 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use std::time::{Duration, Instant};
+
+#[rstest]
+fn downloads_are_throttled(
+    #[with(&["--throttle", "100KBps"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("large.bin"), vec![0; 200_000])?;
+
+    let start = Instant::now();
+    let body = reqwest::blocking::get(server.url().join("large.bin")?)?
+        .error_for_status()?
+        .bytes()?;
+
+    assert_eq!(body.len(), 200_000);
+    assert!(start.elapsed() >= Duration::from_secs(1));
+
+    Ok(())
+}
+
+#[rstest]
+fn uploads_are_throttled(
+    #[with(&["-u", "--throttle-upload", "100KBps"])] server: TestServer,
+) -> Result<(), Error> {
+    let start = Instant::now();
+    reqwest::blocking::Client::new()
+        .put(server.url().join("large.bin")?)
+        .body(vec![0; 200_000])
+        .send()?
+        .error_for_status()?;
+
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert_eq!(
+        std::fs::metadata(server.path().join("large.bin"))?.len(),
+        200_000
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn connections_are_limited(
+    #[with(&["--max-connections", "1"])] server: TestServer,
+) -> Result<(), Error> {
+    // The connection which checked that the server listens has to be closed first
+    std::thread::sleep(Duration::from_millis(100));
+    let open = std::net::TcpStream::connect(("localhost", server.port()))?;
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(reqwest::blocking::get(server.url()).is_err());
+
+    drop(open);
+    std::thread::sleep(Duration::from_millis(100));
+    reqwest::blocking::get(server.url())?.error_for_status()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn total_rate_is_shared(
+    #[with(&["--throttle-total", "100KBps"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("large.bin"), vec![0; 100_000])?;
+
+    let start = Instant::now();
+    let downloads = (0..2)
+        .map(|_| {
+            let url = server.url().join("large.bin").unwrap();
+            std::thread::spawn(move || reqwest::blocking::get(url)?.bytes())
+        })
+        .collect::<Vec<_>>();
+    for download in downloads {
+        assert_eq!(download.join().unwrap()?.len(), 100_000);
+    }
+
+    assert!(start.elapsed() >= Duration::from_secs(1));
+
+    Ok(())
+}
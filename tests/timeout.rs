@@ -0,0 +1,58 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::{Body, Client};
+use reqwest::StatusCode;
+use rstest::rstest;
+use std::io::Read;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Body sending one byte, and then nothing for a few seconds
+struct SlowBody {
+    sent: bool,
+}
+
+impl Read for SlowBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.sent {
+            sleep(Duration::from_secs(3));
+            return Ok(0);
+        }
+        self.sent = true;
+        buf[0] = b'a';
+        Ok(1)
+    }
+}
+
+#[rstest]
+fn slow_requests_time_out(
+    #[with(&["-u", "--request-timeout", "1s"])] server: TestServer,
+) -> Result<(), Error> {
+    let response = Client::new()
+        .put(server.url().join("slow.txt")?)
+        .body(Body::new(SlowBody { sent: false }))
+        .send();
+
+    // The server may close the connection before the client reads the response
+    if let Ok(response) = response {
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+    assert!(!server.path().join("slow.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn requests_are_answered_within_timeouts(
+    #[with(&["--request-timeout", "10s", "--client-timeout", "1", "--keep-alive", "0"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url().join("test.txt")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(body, "Test Hello Yes");
+
+    Ok(())
+}
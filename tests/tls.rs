@@ -4,6 +4,7 @@ use assert_cmd::Command;
 use fixtures::{server, Error, TestServer, FILES};
 use predicates::str::contains;
 use reqwest::blocking::ClientBuilder;
+use reqwest::{Identity, StatusCode};
 use rstest::rstest;
 use select::{document::Document, node::Node};
 
@@ -28,11 +29,65 @@ fn tls_works(
     Ok(())
 }
 
+/// Can start the server with a generated self-signed certificate.
+#[rstest]
+fn tls_self_signed_works(#[with(&["--tls-self-signed"])] server: TestServer) -> Result<(), Error> {
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .build()?;
+    let body = client.get(server.url()).send()?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    for &file in FILES {
+        assert!(parsed.find(|x: &Node| x.text() == file).next().is_some());
+    }
+
+    Ok(())
+}
+
+/// Plain HTTP is redirected to HTTPS, which sends the HSTS header.
+#[rstest]
+fn plain_http_is_redirected_to_https() -> Result<(), Error> {
+    let http_port = fixtures::port().to_string();
+    let server = server(&[
+        "--tls-cert",
+        "tests/data/cert.pem",
+        "--tls-key",
+        "tests/data/key.pem",
+        "--http-port",
+        &http_port,
+        "--redirect-to-https",
+        "--hsts",
+        "60",
+    ]);
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let res = client
+        .get(format!("http://localhost:{}/dir/?q=1", http_port))
+        .send()?;
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        res.headers().get("location").unwrap(),
+        format!("https://localhost:{}/dir/?q=1", server.port()).as_str()
+    );
+    assert!(res.headers().get("strict-transport-security").is_none());
+
+    let res = client.get(server.url()).send()?.error_for_status()?;
+    assert_eq!(
+        res.headers().get("strict-transport-security").unwrap(),
+        "max-age=60"
+    );
+
+    Ok(())
+}
+
 /// Wrong path for cert throws error.
 #[rstest]
 fn wrong_path_cert() -> Result<(), Error> {
     Command::cargo_bin("miniserve")?
-        .args(&["--tls-cert", "wrong", "--tls-key", "tests/data/key.pem"])
+        .args(["--tls-cert", "wrong", "--tls-key", "tests/data/key.pem"])
         .assert()
         .failure()
         .stderr(contains("Error: Couldn't access TLS certificate \"wrong\""));
@@ -44,10 +99,98 @@ fn wrong_path_cert() -> Result<(), Error> {
 #[rstest]
 fn wrong_path_key() -> Result<(), Error> {
     Command::cargo_bin("miniserve")?
-        .args(&["--tls-cert", "tests/data/cert.pem", "--tls-key", "wrong"])
+        .args(["--tls-cert", "tests/data/cert.pem", "--tls-key", "wrong"])
         .assert()
         .failure()
         .stderr(contains("Error: Couldn't access TLS key \"wrong\""));
 
     Ok(())
 }
+
+/// Can restrict the TLS versions and cipher suites.
+#[rstest]
+fn tls_protocols_can_be_restricted(
+    #[with(&[
+        "--tls-cert", "tests/data/cert.pem",
+        "--tls-key", "tests/data/key.pem",
+        "--tls-min-version", "1.3",
+        "--tls-ciphers", "TLS13_AES_256_GCM_SHA384,TLS13_CHACHA20_POLY1305_SHA256"
+    ])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .build()?;
+    client.get(server.url()).send()?.error_for_status()?;
+
+    Ok(())
+}
+
+/// Unknown cipher suites throw errors.
+#[rstest]
+fn unknown_tls_cipher() -> Result<(), Error> {
+    Command::cargo_bin("miniserve")?
+        .args([
+            "--tls-cert",
+            "tests/data/cert.pem",
+            "--tls-key",
+            "tests/data/key.pem",
+            "--tls-ciphers",
+            "TLS_RSA_WITH_RC4_128_MD5",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Error: Unsupported TLS cipher suite TLS_RSA_WITH_RC4_128_MD5",
+        ));
+
+    Ok(())
+}
+
+/// Clients must present a certificate signed by the client CA.
+#[rstest]
+fn tls_client_certificate_required(
+    #[with(&[
+        "--tls-cert", "tests/data/cert.pem",
+        "--tls-key", "tests/data/key.pem",
+        "--tls-client-ca", "tests/data/client_ca.pem"
+    ])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .build()?;
+    assert!(client.get(server.url()).send().is_err());
+
+    let identity = Identity::from_pem(&std::fs::read("tests/data/client.pem")?)?;
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .identity(identity)
+        .build()?;
+    client.get(server.url()).send()?.error_for_status()?;
+
+    Ok(())
+}
+
+/// The common name of the client certificate can be used as username.
+#[rstest]
+fn tls_client_certificate_authenticates(
+    #[with(&[
+        "--tls-cert", "tests/data/cert.pem",
+        "--tls-key", "tests/data/key.pem",
+        "--tls-client-ca", "tests/data/client_ca.pem",
+        "--tls-client-cn-auth",
+        "--auth", "testuser:testpassword"
+    ])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let identity = Identity::from_pem(&std::fs::read("tests/data/client.pem")?)?;
+    let client = ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .identity(identity)
+        .build()?;
+    let status = client.get(server.url()).send()?.status();
+    assert_eq!(status, StatusCode::OK);
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::header;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Class;
+
+#[rstest]
+fn torrent_has_web_seed(#[with(&["--enable-torrent"])] server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(server.url().join("test.txt?download=torrent")?)?
+        .error_for_status()?;
+    assert_eq!(
+        resp.headers()[header::CONTENT_TYPE],
+        "application/x-bittorrent"
+    );
+    assert!(resp.headers()[header::CONTENT_DISPOSITION]
+        .to_str()?
+        .contains("test.txt.torrent"));
+
+    let torrent = String::from_utf8_lossy(&resp.bytes()?).to_string();
+    assert!(torrent.contains("6:lengthi14e4:name8:test.txt"));
+    let url = server.url().join("test.txt")?.to_string();
+    assert!(torrent.ends_with(&format!("8:url-listl{}:{}ee", url.len(), url)));
+
+    Ok(())
+}
+
+#[rstest]
+fn torrents_are_linked_in_listing(
+    #[with(&["--enable-torrent"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed
+        .find(Class("torrent"))
+        .any(|link| link.attr("href") == Some("/test.txt?download=torrent")));
+
+    Ok(())
+}
+
+#[rstest]
+fn torrents_are_disabled_by_default(server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url().join("test.txt?download=torrent")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(body, "Test Hello Yes");
+
+    Ok(())
+}
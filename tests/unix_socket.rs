@@ -0,0 +1,61 @@
+#![cfg(unix)]
+
+mod fixtures;
+
+use assert_cmd::prelude::*;
+use fixtures::{port, tmpdir, Error};
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+#[test]
+fn serves_on_unix_socket() -> Result<(), Error> {
+    let port = port();
+    let tmpdir = tmpdir();
+    let socket_dir = assert_fs::TempDir::new()?;
+    let socket_path = socket_dir.path().join("miniserve.sock");
+    let mut child = Command::cargo_bin("miniserve")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .arg("--listen-unix")
+        .arg(&socket_path)
+        .arg("--listen-unix-mode")
+        .arg("660")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let start = Instant::now();
+    while !socket_path.exists() {
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "timeout waiting for socket {}",
+            socket_path.display()
+        );
+        sleep(Duration::from_millis(100));
+    }
+
+    let mut stream = UnixStream::connect(&socket_path)?;
+    stream.write_all(b"GET /test.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+
+    let mode = std::fs::metadata(&socket_path)?.permissions().mode();
+    let tcp_reachable = port_check::is_port_reachable(format!("localhost:{}", port));
+
+    child.kill()?;
+    child.wait()?;
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.ends_with("Test Hello Yes"));
+    assert_eq!(mode & 0o777, 0o660);
+
+    // TCP is only used when interfaces are given explicitly
+    assert!(!tcp_reachable);
+
+    Ok(())
+}
@@ -1,10 +1,11 @@
 mod fixtures;
 
-use fixtures::{server, Error, TestServer};
+use fixtures::{server, server_no_stderr, Error, TestServer};
 use reqwest::blocking::{multipart, Client};
+use reqwest::StatusCode;
 use rstest::rstest;
 use select::document::Document;
-use select::predicate::{Attr, Text};
+use select::predicate::{Attr, Class, Text};
 
 #[rstest]
 fn uploading_files_works(#[with(&["-u"])] server: TestServer) -> Result<(), Error> {
@@ -78,3 +79,754 @@ fn uploading_files_is_prevented(server: TestServer) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[rstest]
+fn resumable_uploads_work(#[with(&["-u"])] server: TestServer) -> Result<(), Error> {
+    let test_file_name = "resumed upload.txt";
+    let upload_url = server
+        .url()
+        .join("/upload/resumable?path=/&name=resumed%20upload.txt")?;
+    let client = Client::new();
+
+    // Nothing was received yet
+    let response = client.get(upload_url.clone()).send()?.error_for_status()?;
+    assert_eq!(response.headers()["Upload-Offset"], "0");
+
+    let response = client
+        .patch(upload_url.clone())
+        .header("Upload-Offset", "0")
+        .header("Upload-Length", "11")
+        .body("hello ")
+        .send()?
+        .error_for_status()?;
+    assert_eq!(response.headers()["Upload-Offset"], "6");
+
+    // A chunk which doesn't continue the upload is refused
+    let response = client
+        .patch(upload_url.clone())
+        .header("Upload-Offset", "3")
+        .header("Upload-Length", "11")
+        .body("lo world")
+        .send()?;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(response.headers()["Upload-Offset"], "6");
+
+    // The upload is not listed before it is complete
+    let body = reqwest::blocking::get(server.url())?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).all(|x| x.text() != test_file_name));
+
+    client
+        .patch(upload_url)
+        .header("Upload-Offset", "6")
+        .header("Upload-Length", "11")
+        .body("world")
+        .send()?
+        .error_for_status()?;
+
+    let content = reqwest::blocking::get(server.url().join("/resumed%20upload.txt")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "hello world");
+
+    Ok(())
+}
+
+#[rstest]
+fn raw_put_uploads_work(#[with(&["-u"])] server: TestServer) -> Result<(), Error> {
+    std::fs::create_dir(server.path().join("dir"))?;
+    let client = Client::new();
+
+    let response = client
+        .put(server.url().join("/dir/raw%20upload.txt")?)
+        .body("uploaded with PUT")
+        .send()?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let content = reqwest::blocking::get(server.url().join("/dir/raw%20upload.txt")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "uploaded with PUT");
+
+    // Directories are not created, and the file name is required
+    for url in &["/missing/raw.txt", "/dir/"] {
+        let response = client.put(server.url().join(url)?).body("test").send()?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn raw_put_uploads_are_prevented(server: TestServer) -> Result<(), Error> {
+    let response = Client::new()
+        .put(server.url().join("/raw.txt")?)
+        .body("this should not be uploaded")
+        .send()?;
+    assert!(response.error_for_status().is_err());
+    assert!(!server.path().join("raw.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn creating_new_files_works(#[with(&["-u"])] server: TestServer) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let new_file_action = parsed
+        .find(Class("new-file"))
+        .next()
+        .expect("Couldn't find the new file form")
+        .attr("action")
+        .expect("New file form doesn't have action attribute");
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let response = client
+        .post(server.url().join(new_file_action)?)
+        .form(&[("name", "empty.txt")])
+        .send()?;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let response = client
+        .post(server.url().join(new_file_action)?)
+        .form(&[("name", "notes.md"), ("content", "# Notes\n")])
+        .send()?;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("empty.txt"))?,
+        ""
+    );
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("notes.md"))?,
+        "# Notes\n"
+    );
+
+    // Existing files are never replaced
+    let response = client
+        .post(server.url().join(new_file_action)?)
+        .form(&[("name", "test.txt")])
+        .send()?;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("test.txt"))?,
+        "Test Hello Yes"
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn uploading_folders_keeps_structure(#[with(&["-u"])] server: TestServer) -> Result<(), Error> {
+    // Browsers send the path of files inside of an uploaded folder as their name
+    let part = multipart::Part::text("uploaded with its folder")
+        .file_name("folder/sub/multipart.txt")
+        .mime_str("text/plain")?;
+    let form = multipart::Form::new()
+        .percent_encode_noop()
+        .part("file_to_upload", part);
+    Client::new()
+        .post(server.url().join("/upload?path=/")?)
+        .multipart(form)
+        .send()?
+        .error_for_status()?;
+
+    Client::new()
+        .patch(
+            server
+                .url()
+                .join("/upload/resumable?path=/&name=folder%2Fsub%2Fresumable.txt")?,
+        )
+        .header("Upload-Offset", "0")
+        .header("Upload-Length", "5")
+        .body("hello")
+        .send()?
+        .error_for_status()?;
+
+    for (url, content) in &[
+        ("/folder/sub/multipart.txt", "uploaded with its folder"),
+        ("/folder/sub/resumable.txt", "hello"),
+    ] {
+        let body = reqwest::blocking::get(server.url().join(url)?)?
+            .error_for_status()?
+            .text()?;
+        assert_eq!(&body, content);
+    }
+
+    Ok(())
+}
+
+#[rstest(
+    file_name,
+    case("../escaped.txt"),
+    case("folder/../../escaped.txt"),
+    case("/escaped.txt")
+)]
+fn uploaded_folders_cannot_escape_upload_directory(file_name: &str) -> Result<(), Error> {
+    let server = server_no_stderr(&["-u"]);
+    std::fs::create_dir(server.path().join("dir"))?;
+    let part = multipart::Part::text("this should not be uploaded")
+        .file_name(file_name.to_string())
+        .mime_str("text/plain")?;
+    let form = multipart::Form::new()
+        .percent_encode_noop()
+        .part("file_to_upload", part);
+
+    let status = Client::new()
+        .post(server.url().join("/upload?path=/dir")?)
+        .multipart(form)
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(!server.path().join("escaped.txt").exists());
+    assert!(!server.path().parent().unwrap().join("escaped.txt").exists());
+
+    Ok(())
+}
+
+#[rstest(
+    args,
+    case(&["-u", "--max-upload-size", "10"]),
+    case(&["-u", "--upload-quota", "10"])
+)]
+fn uploading_too_large_files_is_prevented(args: &[&str]) -> Result<(), Error> {
+    let server = server_no_stderr(args);
+    let test_file_name = "too large.txt";
+
+    let form = multipart::Form::new();
+    let part = multipart::Part::text("this is larger than ten bytes")
+        .file_name(test_file_name)
+        .mime_str("text/plain")?;
+    let form = form.part("file_to_upload", part);
+
+    let status = Client::new()
+        .post(server.url().join("/upload?path=/")?)
+        .multipart(form)
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    // The incomplete file must not be kept
+    let body = reqwest::blocking::get(server.url())?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Text).all(|x| x.text() != test_file_name));
+
+    Ok(())
+}
+
+/// Body sending `content` one byte every 100ms
+struct SlowBody(&'static [u8]);
+
+impl std::io::Read for SlowBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.split_first() {
+            Some((byte, rest)) if !buf.is_empty() => {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                buf[0] = *byte;
+                self.0 = rest;
+                Ok(1)
+            }
+            _ => Ok(0),
+        }
+    }
+}
+
+#[rstest]
+fn concurrent_chunks_of_resumable_uploads_are_refused(
+    #[with(&["-u"])] server: TestServer,
+) -> Result<(), Error> {
+    let upload_url = server
+        .url()
+        .join("/upload/resumable?path=/&name=concurrent.txt")?;
+
+    let slow_url = upload_url.clone();
+    let slow_chunk = std::thread::spawn(move || {
+        Client::new()
+            .patch(slow_url)
+            .header("Upload-Offset", "0")
+            .header("Upload-Length", "11")
+            .body(reqwest::blocking::Body::sized(SlowBody(b"hello "), 6))
+            .send()
+    });
+    std::thread::sleep(std::time::Duration::from_millis(300));
+
+    // The same chunk, e.g. retried while the first attempt is still being received
+    let response = Client::new()
+        .patch(upload_url.clone())
+        .header("Upload-Offset", "0")
+        .header("Upload-Length", "11")
+        .body("hello ")
+        .send()?;
+    assert_eq!(response.status(), StatusCode::LOCKED);
+
+    let response = slow_chunk.join().unwrap()?.error_for_status()?;
+    assert_eq!(response.headers()["Upload-Offset"], "6");
+    Client::new()
+        .patch(upload_url)
+        .header("Upload-Offset", "6")
+        .header("Upload-Length", "11")
+        .body("world")
+        .send()?
+        .error_for_status()?;
+
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("concurrent.txt"))?,
+        "hello world"
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn uploaded_archives_are_extracted(
+    #[with(&["-u", "--upload-auto-extract"])] server: TestServer,
+) -> Result<(), Error> {
+    let archive = std::fs::read("tests/data/extract.zip")?;
+    let part = multipart::Part::bytes(archive)
+        .file_name("extract.zip")
+        .mime_str("application/zip")?;
+    let form = multipart::Form::new().part("file_to_upload", part);
+
+    Client::new()
+        .post(server.url().join("/upload?path=/")?)
+        .multipart(form)
+        .send()?
+        .error_for_status()?;
+
+    let content = reqwest::blocking::get(server.url().join("/extracted/hello.txt")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(content, "hello from the archive");
+
+    // The archive itself is not kept
+    assert!(!server.path().join("extract.zip").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn uploaded_archives_follow_the_rules_of_subdirectories(
+    #[with(&["-u", "--upload-auto-extract", "--dir-config"])] server: TestServer,
+) -> Result<(), Error> {
+    std::fs::write(
+        server.path().join("dira/.miniserve.toml"),
+        "uploads = false",
+    )?;
+
+    for entry in &["dira/extracted.txt", "dirb/.miniserve.toml"] {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, entry, std::io::empty())?;
+        let part = multipart::Part::bytes(builder.into_inner()?)
+            .file_name("nested.tar")
+            .mime_str("application/x-tar")?;
+        let form = multipart::Form::new().part("file_to_upload", part);
+
+        let status = Client::new()
+            .post(server.url().join("/upload?path=/")?)
+            .multipart(form)
+            .send()?
+            .status();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    assert!(!server.path().join("dira/extracted.txt").exists());
+    assert!(!server.path().join("dirb/.miniserve.toml").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn uploaded_archives_cannot_escape_upload_directory() -> Result<(), Error> {
+    let server = server_no_stderr(&["-u", "--upload-auto-extract"]);
+    let archive = std::fs::read("tests/data/traversal.zip")?;
+    let part = multipart::Part::bytes(archive)
+        .file_name("traversal.zip")
+        .mime_str("application/zip")?;
+    let form = multipart::Form::new().part("file_to_upload", part);
+
+    let status = Client::new()
+        .post(server.url().join("/upload?path=/")?)
+        .multipart(form)
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let escaped_path = server
+        .path()
+        .parent()
+        .unwrap()
+        .join("miniserve-escaped-file.txt");
+    assert!(!escaped_path.exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn uploads_notify_webhook() -> Result<(), Error> {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let webhook_url = format!("http://{}/hook", listener.local_addr()?);
+    let server = server_no_stderr(&["-u", "--on-change-webhook", &webhook_url]);
+
+    let part = multipart::Part::text("this should be uploaded")
+        .file_name("webhook test.txt")
+        .mime_str("text/plain")?;
+    let form = multipart::Form::new().part("file_to_upload", part);
+    Client::new()
+        .post(server.url().join("/upload?path=/")?)
+        .multipart(form)
+        .send()?
+        .error_for_status()?;
+
+    let (mut stream, _) = listener.accept()?;
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+    let mut request = Vec::new();
+    let mut buffer = [0; 4096];
+    while !String::from_utf8_lossy(&request).ends_with('}') {
+        let len = stream.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..len]);
+    }
+    stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")?;
+
+    let request = String::from_utf8_lossy(&request);
+    assert!(request.starts_with("POST /hook "));
+    assert!(request.contains(r#""action":"upload""#));
+    assert!(request.contains(r#""path":"/webhook test.txt""#));
+    assert!(request.contains(r#""size":23"#));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[rstest(
+    file_name,
+    expected_status,
+    case("accepted.txt", StatusCode::SEE_OTHER),
+    case("rejected.txt", StatusCode::UNPROCESSABLE_ENTITY)
+)]
+fn upload_hook_can_reject_uploads(
+    file_name: &str,
+    expected_status: StatusCode,
+) -> Result<(), Error> {
+    let server = server_no_stderr(&[
+        "-u",
+        "--upload-hook",
+        r#"test "$MINISERVE_UPLOAD_NAME" != rejected.txt && test -f"#,
+        "--upload-hook-error",
+        "Rejected by the test hook",
+    ]);
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let part = multipart::Part::text("this is a test")
+        .file_name(file_name.to_string())
+        .mime_str("text/plain")?;
+    let form = multipart::Form::new().part("file_to_upload", part);
+
+    let response = client
+        .post(server.url().join("/upload?path=/")?)
+        .multipart(form)
+        .send()?;
+    assert_eq!(response.status(), expected_status);
+
+    let accepted = expected_status == StatusCode::SEE_OTHER;
+    if !accepted {
+        assert!(response.text()?.contains("Rejected by the test hook"));
+    }
+    assert_eq!(server.path().join(file_name).exists(), accepted);
+
+    Ok(())
+}
+
+#[rstest(
+    policy,
+    expected_status,
+    expected_content,
+    renamed,
+    case("error", StatusCode::CONFLICT, "Test Hello Yes", false),
+    case("overwrite", StatusCode::SEE_OTHER, "uploaded", false),
+    case("rename", StatusCode::SEE_OTHER, "Test Hello Yes", true),
+    case("skip", StatusCode::SEE_OTHER, "Test Hello Yes", false)
+)]
+fn uploading_duplicate_files_follows_policy(
+    policy: &str,
+    expected_status: StatusCode,
+    expected_content: &str,
+    renamed: bool,
+) -> Result<(), Error> {
+    let server = server_no_stderr(&["-u", "--on-duplicate-files", policy]);
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let part = multipart::Part::text("uploaded")
+        .file_name("test.txt")
+        .mime_str("text/plain")?;
+    let form = multipart::Form::new().part("file_to_upload", part);
+
+    let status = client
+        .post(server.url().join("/upload?path=/")?)
+        .multipart(form)
+        .send()?
+        .status();
+    assert_eq!(status, expected_status);
+
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("test.txt"))?,
+        expected_content
+    );
+    assert_eq!(server.path().join("test (1).txt").exists(), renamed);
+
+    Ok(())
+}
+
+#[rstest]
+fn overwrite_files_flag_overwrites_existing_files(
+    #[with(&["-u", "-o"])] server: TestServer,
+) -> Result<(), Error> {
+    Client::new()
+        .put(server.url().join("test.txt")?)
+        .body("uploaded")
+        .send()?
+        .error_for_status()?;
+
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("test.txt"))?,
+        "uploaded"
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn overwritten_files_are_kept_as_versions(
+    #[with(&["-u", "--on-duplicate-files", "overwrite", "--keep-versions", "2"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+    for content in &["first", "second", "third"] {
+        client
+            .put(server.url().join("test.txt")?)
+            .body(*content)
+            .send()?
+            .error_for_status()?;
+    }
+
+    let read = |name: &str| std::fs::read_to_string(server.path().join(name));
+    assert_eq!(read("test.txt")?, "third");
+    assert!(!server.path().join("test.txt.~1~").exists());
+    assert_eq!(read("test.txt.~2~")?, "first");
+    assert_eq!(read("test.txt.~3~")?, "second");
+
+    Ok(())
+}
+
+#[rstest(
+    header,
+    value,
+    expected,
+    case(
+        "X-Checksum-SHA256",
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+        StatusCode::CREATED
+    ),
+    case(
+        "Content-Digest",
+        "sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:",
+        StatusCode::CREATED
+    ),
+    case(
+        "X-Checksum-SHA256",
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+        StatusCode::BAD_REQUEST
+    ),
+    case("X-Checksum-SHA256", "invalid", StatusCode::BAD_REQUEST)
+)]
+fn uploads_are_checked_against_their_checksum(
+    header: &str,
+    value: &str,
+    expected: StatusCode,
+) -> Result<(), Error> {
+    let server = server_no_stderr(&["-u"]);
+    let status = Client::new()
+        .put(server.url().join("checked.txt")?)
+        .header(header, value)
+        .body("hello")
+        .send()?
+        .status();
+    assert_eq!(status, expected);
+    assert_eq!(
+        server.path().join("checked.txt").exists(),
+        expected.is_success()
+    );
+    assert!(!server.path().join(".checked.txt.part").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn multipart_uploads_are_checked_against_their_checksum(
+    #[with(&["-u"])] server: TestServer,
+) -> Result<(), Error> {
+    let upload_url = server.url().join("/upload?path=/")?;
+    let upload = |checksum: &str, file_name: &str| {
+        let part = multipart::Part::text("hello").file_name(file_name.to_string());
+        let form = multipart::Form::new()
+            .text("checksum_sha256", checksum.to_string())
+            .part("file_to_upload", part);
+        Client::new()
+            .post(upload_url.clone())
+            .multipart(form)
+            .send()
+    };
+
+    upload(
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+        "valid.txt",
+    )?
+    .error_for_status()?;
+    assert!(server.path().join("valid.txt").exists());
+
+    let response = upload(
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+        "corrupted.txt",
+    )?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(!server.path().join("corrupted.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn identical_uploads_are_deduplicated(
+    #[with(&["-u", "--upload-dedup"])] server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+    let response = client
+        .put(server.url().join("test.txt")?)
+        .body("Test Hello Yes")
+        .send()?
+        .error_for_status()?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Upload-Result"], "duplicate");
+
+    // Other content is still refused
+    let response = client
+        .put(server.url().join("test.txt")?)
+        .body("other content")
+        .send()?;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("test.txt"))?,
+        "Test Hello Yes"
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[rstest]
+fn identical_uploads_are_hard_linked(
+    #[with(&["-u", "--upload-dedup", "--dedup-hard-links"])] server: TestServer,
+) -> Result<(), Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::write(server.path().join("original.txt"), "unique content")?;
+    Client::new()
+        .put(server.url().join("copy.txt")?)
+        .body("unique content")
+        .send()?
+        .error_for_status()?;
+
+    let original = std::fs::metadata(server.path().join("original.txt"))?;
+    let copy = std::fs::metadata(server.path().join("copy.txt"))?;
+    assert_eq!(copy.ino(), original.ino());
+    assert_eq!(original.nlink(), 2);
+
+    Ok(())
+}
+
+#[rstest]
+fn user_quotas_are_enforced(
+    #[with(&["-u", "--auth", "joe:123", "--auth", "ann:456", "--quota", "joe=20"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+    client
+        .put(server.url().join("first.txt")?)
+        .basic_auth("joe", Some("123"))
+        .body("fifteen bytes..")
+        .send()?
+        .error_for_status()?;
+
+    let body = client
+        .get(server.url())
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let usage = parsed.find(Class("storage-usage")).next().unwrap().text();
+    assert_eq!(usage, "Storage used: 15 B of 20 B");
+
+    let status = client
+        .put(server.url().join("second.txt")?)
+        .basic_auth("joe", Some("123"))
+        .body("ten bytes.")
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    assert!(!server.path().join("second.txt").exists());
+
+    // Other users don't share the quota
+    client
+        .put(server.url().join("second.txt")?)
+        .basic_auth("ann", Some("456"))
+        .body("ten bytes.")
+        .send()?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn overwritten_files_leave_user_quotas(
+    #[with(&["-u", "--on-duplicate-files", "overwrite", "--auth", "joe:123", "--quota", "joe=20"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+    // Without giving back the size of the replaced file, the third upload would exceed the quota
+    for content in &["version1", "version2", "version3"] {
+        client
+            .put(server.url().join("notes.txt")?)
+            .basic_auth("joe", Some("123"))
+            .body(content.to_string())
+            .send()?
+            .error_for_status()?;
+    }
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("notes.txt"))?,
+        "version3"
+    );
+
+    let body = client
+        .get(server.url())
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let usage = parsed.find(Class("storage-usage")).next().unwrap().text();
+    assert_eq!(usage, "Storage used: 8 B of 20 B");
+
+    Ok(())
+}
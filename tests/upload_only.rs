@@ -0,0 +1,78 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::{multipart, Client};
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Attr, Name, Text};
+
+#[rstest]
+fn only_the_upload_form_is_shown(
+    #[with(&["-u", "--upload-only"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = reqwest::blocking::get(server.url())?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Attr("id", "file_submit")).next().is_some());
+    assert!(parsed.find(Name("table")).next().is_none());
+    assert!(parsed.find(Text).all(|x| x.text() != "test.txt"));
+
+    for path in &["test.txt", "dira/", "dira/test.txt"] {
+        let status = reqwest::blocking::get(server.url().join(path)?)?.status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    let listing = reqwest::blocking::get(server.url().join("?format=json")?)?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(listing, "[]");
+
+    Ok(())
+}
+
+#[rstest]
+fn uploads_of_existing_files_are_renamed(
+    #[with(&["-u", "--upload-only"])] server: TestServer,
+) -> Result<(), Error> {
+    let form = multipart::Form::new().part(
+        "file_to_upload",
+        multipart::Part::text("this should be uploaded")
+            .file_name("test.txt")
+            .mime_str("text/plain")?,
+    );
+
+    Client::new()
+        .post(server.url().join("/upload?path=/")?)
+        .multipart(form)
+        .send()?
+        .error_for_status()?;
+
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("test.txt"))?,
+        "Test Hello Yes"
+    );
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("test (1).txt"))?,
+        "this should be uploaded"
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn contents_of_the_root_are_hidden(
+    #[with(&["-u", "--upload-only", "-r", "-z", "--enable-manifest"])] server: TestServer,
+) -> Result<(), Error> {
+    for query in &[
+        "?download=tar",
+        "?download=zip",
+        "?download=m3u",
+        "?manifest=sha256",
+    ] {
+        let status = reqwest::blocking::get(server.url().join(query)?)?.status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use rstest::rstest;
+use std::thread::sleep;
+use std::time::Duration;
+
+#[rstest]
+fn expired_uploads_are_deleted(
+    #[with(&["-u", "--upload-ttl", "1s", "--hidden"])] server: TestServer,
+) -> Result<(), Error> {
+    Client::new()
+        .put(server.url().join("uploaded.txt")?)
+        .body("temporary")
+        .send()?
+        .error_for_status()?;
+    assert!(server.path().join("uploaded.txt").exists());
+
+    let status = reqwest::blocking::get(server.url().join(".miniserve-uploads.json")?)?.status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    sleep(Duration::from_secs(3));
+    assert!(!server.path().join("uploaded.txt").exists());
+    assert!(server.path().join("test.txt").exists());
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+mod fixtures;
+
+use fixtures::{server, Error, TestServer};
+use pretty_assertions::assert_eq;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::Text;
+
+#[rstest]
+fn users_are_served_their_own_directory(
+    #[with(&["-u", "--auth", "joe:123", "--auth", "ann:456", "--user-dirs"])] server: TestServer,
+) -> Result<(), Error> {
+    let client = Client::new();
+    let body = client
+        .get(server.url())
+        .basic_auth("joe", Some("123"))
+        .send()?
+        .error_for_status()?;
+    assert!(server.path().join("joe").is_dir());
+    let parsed = Document::from_read(body)?;
+    assert!(!parsed.find(Text).any(|x| x.text() == "test.txt"));
+
+    client
+        .put(server.url().join("notes.txt")?)
+        .basic_auth("joe", Some("123"))
+        .body("from joe")
+        .send()?
+        .error_for_status()?;
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("joe/notes.txt"))?,
+        "from joe"
+    );
+
+    let status = client
+        .get(server.url().join("notes.txt")?)
+        .basic_auth("ann", Some("456"))
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let status = client
+        .get(server.url().join("../test.txt")?)
+        .basic_auth("ann", Some("456"))
+        .send()?
+        .status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn user_dirs_require_credentials(
+    #[with(&["--auth", "joe:123", "--user-dirs"])] server: TestServer,
+) -> Result<(), Error> {
+    let status = reqwest::blocking::get(server.url())?.status();
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // The directories are created at startup, since `Files` can't serve them otherwise
+    assert!(server.path().join("joe").is_dir());
+
+    Ok(())
+}